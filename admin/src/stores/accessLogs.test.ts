@@ -258,4 +258,41 @@ describe('useAccessLogsStore', () => {
       expect(store.error).toBeNull()
     })
   })
+
+  describe('fetchSubscriptionFetchCounts', () => {
+    it('should fetch today\'s counts by default', async () => {
+      const mockResponse = {
+        data: {
+          date: '2024-01-01',
+          users: 2,
+          sample_rate: 0.1,
+          top: [
+            { user_id: 100, user_email: 'test@example.com', fetches: 1440 },
+            { user_id: 101, user_email: null, fetches: 24 }
+          ]
+        }
+      }
+
+      vi.mocked(api.get).mockResolvedValue(mockResponse)
+
+      const store = useAccessLogsStore()
+      const result = await store.fetchSubscriptionFetchCounts()
+
+      expect(api.get).toHaveBeenCalledWith('/admin/access-logs/fetch-counts?limit=20')
+      expect(store.fetchCounts).toEqual(mockResponse.data)
+      expect(store.fetchCountsLoading).toBe(false)
+      expect(result).toEqual(mockResponse.data)
+    })
+
+    it('should pass the selected date', async () => {
+      vi.mocked(api.get).mockResolvedValue({
+        data: { date: '2024-01-02', users: 0, sample_rate: 0.1, top: [] }
+      })
+
+      const store = useAccessLogsStore()
+      await store.fetchSubscriptionFetchCounts('2024-01-02', 50)
+
+      expect(api.get).toHaveBeenCalledWith('/admin/access-logs/fetch-counts?date=2024-01-02&limit=50')
+    })
+  })
 })
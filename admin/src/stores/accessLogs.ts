@@ -30,6 +30,19 @@ export interface AccessLogListResponse {
   total_pages: number
 }
 
+export interface SubscriptionFetchCount {
+  user_id: number
+  user_email: string | null
+  fetches: number
+}
+
+export interface SubscriptionFetchCountsResponse {
+  date: string
+  users: number
+  sample_rate: number
+  top: SubscriptionFetchCount[]
+}
+
 export const useAccessLogsStore = defineStore('accessLogs', () => {
   const logs = ref<AccessLog[]>([])
   const total = ref(0)
@@ -38,6 +51,8 @@ export const useAccessLogsStore = defineStore('accessLogs', () => {
   const totalPages = ref(1)
   const loading = ref(false)
   const error = ref<string | null>(null)
+  const fetchCounts = ref<SubscriptionFetchCountsResponse | null>(null)
+  const fetchCountsLoading = ref(false)
 
   const fetchAccessLogs = async (filters: AccessLogFilters = {}): Promise<AccessLogListResponse> => {
     loading.value = true
@@ -87,6 +102,30 @@ export const useAccessLogsStore = defineStore('accessLogs', () => {
     }
   }
 
+  const fetchSubscriptionFetchCounts = async (date?: string, limit = 20): Promise<SubscriptionFetchCountsResponse> => {
+    fetchCountsLoading.value = true
+
+    try {
+      const params = new URLSearchParams()
+      if (date) {
+        params.append('date', date)
+      }
+      params.append('limit', limit.toString())
+
+      const response = await api.get<SubscriptionFetchCountsResponse>(
+        `/admin/access-logs/fetch-counts?${params.toString()}`
+      )
+      fetchCounts.value = response.data
+
+      return response.data
+    } catch (e: any) {
+      error.value = e.response?.data?.error?.message || 'Failed to fetch subscription fetch counts'
+      throw e
+    } finally {
+      fetchCountsLoading.value = false
+    }
+  }
+
   return {
     logs,
     total,
@@ -95,6 +134,9 @@ export const useAccessLogsStore = defineStore('accessLogs', () => {
     totalPages,
     loading,
     error,
-    fetchAccessLogs
+    fetchCounts,
+    fetchCountsLoading,
+    fetchAccessLogs,
+    fetchSubscriptionFetchCounts
   }
 })
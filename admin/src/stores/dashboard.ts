@@ -9,6 +9,38 @@ export interface DashboardStats {
   total_revenue: number
 }
 
+export interface DashboardOrder {
+  id: number
+  order_no: string
+  user_id: number
+  amount: number
+  status: string
+  created_at: string
+}
+
+export interface StaleNode {
+  id: number
+  name: string
+  status: string
+  last_heartbeat: string | null
+}
+
+export interface OpenAlert {
+  id: number
+  node_id: number
+  alert_type: string
+  message: string
+  created_at: string
+}
+
+export interface DashboardSummary {
+  overview: DashboardStats
+  revenue_24h: { revenue: number; order_count: number }
+  recent_orders: DashboardOrder[]
+  stale_nodes: StaleNode[]
+  open_alerts: OpenAlert[]
+}
+
 export const useDashboardStore = defineStore('dashboard', () => {
   const stats = ref<DashboardStats>({
     total_users: 0,
@@ -16,6 +48,10 @@ export const useDashboardStore = defineStore('dashboard', () => {
     total_traffic: 0,
     total_revenue: 0
   })
+  const revenue24h = ref({ revenue: 0, order_count: 0 })
+  const recentOrders = ref<DashboardOrder[]>([])
+  const staleNodes = ref<StaleNode[]>([])
+  const openAlerts = ref<OpenAlert[]>([])
   const loading = ref(false)
   const error = ref<string | null>(null)
 
@@ -24,8 +60,12 @@ export const useDashboardStore = defineStore('dashboard', () => {
     error.value = null
     
     try {
-      const response = await api.get<DashboardStats>('/admin/stats/overview')
-      stats.value = response.data
+      const response = await api.get<DashboardSummary>('/admin/dashboard')
+      stats.value = response.data.overview
+      revenue24h.value = response.data.revenue_24h
+      recentOrders.value = response.data.recent_orders
+      staleNodes.value = response.data.stale_nodes
+      openAlerts.value = response.data.open_alerts
     } catch (e: any) {
       error.value = e.response?.data?.error?.message || '获取统计数据失败'
     } finally {
@@ -35,6 +75,10 @@ export const useDashboardStore = defineStore('dashboard', () => {
 
   return {
     stats,
+    revenue24h,
+    recentOrders,
+    staleNodes,
+    openAlerts,
     loading,
     error,
     fetchStats
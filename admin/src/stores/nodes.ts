@@ -18,10 +18,30 @@ export interface Node {
   updated_at: string
   include_in_clash: boolean
   sort_order: number
+  traffic_budget: number
+  traffic_budget_action: string
+  tags: string[]
+  config_version: number
+  applied_config_version: number | null
   secret?: string
   config?: any
 }
 
+export interface DiagnosticCheck {
+  name: string
+  status: 'pass' | 'warn' | 'fail'
+  detail: string
+}
+
+export interface NodeDiagnostics {
+  id: number
+  node_id: number
+  agent_version: string | null
+  passed: boolean
+  checks: DiagnosticCheck[]
+  created_at: string
+}
+
 export interface CreateNodeRequest {
   name: string
   host: string
@@ -32,6 +52,9 @@ export interface CreateNodeRequest {
   max_users?: number
   include_in_clash?: boolean
   sort_order?: number
+  traffic_budget?: number
+  traffic_budget_action?: string
+  tags?: string[]
 }
 
 export const useNodesStore = defineStore('nodes', () => {
@@ -101,6 +124,18 @@ export const useNodesStore = defineStore('nodes', () => {
     }
   }
 
+  const fetchDiagnostics = async (id: number) => {
+    error.value = null
+
+    try {
+      const response = await api.get<NodeDiagnostics[]>(`/admin/nodes/${id}/diagnostics`)
+      return response.data
+    } catch (e: any) {
+      error.value = e.response?.data?.error?.message || '获取诊断结果失败'
+      return null
+    }
+  }
+
   return {
     nodes,
     loading,
@@ -108,6 +143,7 @@ export const useNodesStore = defineStore('nodes', () => {
     fetchNodes,
     createNode,
     updateNode,
-    deleteNode
+    deleteNode,
+    fetchDiagnostics
   }
 })
@@ -14,6 +14,41 @@ export interface User {
   updated_at: string
 }
 
+export interface UserConnections {
+  current: number
+  limit: number
+  by_node: { node_id: number; connections: number }[]
+}
+
+export interface UserNodeTrafficSummary {
+  node_id: number
+  node_name: string
+  upload: number
+  download: number
+  total: number
+  last_used_at: string
+}
+
+export interface UserNodeDailyTraffic {
+  date: string
+  node_id: number
+  node_name: string
+  upload: number
+  download: number
+}
+
+export interface UserTrafficReport {
+  user_id: number
+  email: string
+  start_date: string
+  end_date: string
+  total_upload: number
+  total_download: number
+  last_used_at: string | null
+  top_nodes: UserNodeTrafficSummary[]
+  daily: UserNodeDailyTraffic[]
+}
+
 export interface UsersResponse {
   users: User[]
   total: number
@@ -45,6 +80,29 @@ export const useUsersStore = defineStore('users', () => {
     }
   }
 
+  const fetchUserConnections = async (id: number): Promise<UserConnections | null> => {
+    try {
+      const response = await api.get<{ connections: UserConnections }>(`/admin/users/${id}`)
+      return response.data.connections
+    } catch (e: any) {
+      console.error('Failed to fetch user connections:', e)
+      return null
+    }
+  }
+
+  const fetchUserTrafficReport = async (
+    id: number,
+    range?: { start_date?: string; end_date?: string }
+  ): Promise<UserTrafficReport | null> => {
+    try {
+      const response = await api.get<UserTrafficReport>(`/admin/users/${id}/traffic`, { params: range })
+      return response.data
+    } catch (e: any) {
+      console.error('Failed to fetch user traffic report:', e)
+      return null
+    }
+  }
+
   const updateUserStatus = async (id: number, status: string) => {
     loading.value = true
     error.value = null
@@ -61,6 +119,21 @@ export const useUsersStore = defineStore('users', () => {
     }
   }
 
+  const revokeUserTokens = async (id: number) => {
+    loading.value = true
+    error.value = null
+    
+    try {
+      await api.post(`/admin/users/${id}/revoke-tokens`)
+      return true
+    } catch (e: any) {
+      error.value = e.response?.data?.error?.message || '强制下线失败'
+      return false
+    } finally {
+      loading.value = false
+    }
+  }
+
   const updateUserBalance = async (id: number, amount: number) => {
     loading.value = true
     error.value = null
@@ -99,7 +172,10 @@ export const useUsersStore = defineStore('users', () => {
     loading,
     error,
     fetchUsers,
+    fetchUserConnections,
+    fetchUserTrafficReport,
     updateUserStatus,
+    revokeUserTokens,
     updateUserBalance,
     updateUserTraffic
   }
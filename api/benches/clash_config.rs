@@ -0,0 +1,96 @@
+//! Clash config generation at scale: hundreds of nodes and thousands of rules
+//!
+//! Run with `cargo bench -p api --bench clash_config`.
+
+use api::clash;
+use api::models::{ClashProxyGroup, ClashRule, Node};
+use chrono::Utc;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+fn nodes(count: usize) -> Vec<Node> {
+    (0..count)
+        .map(|i| Node {
+            id: i as i64,
+            name: format!("Node {}", i),
+            host: format!("node{}.example.com", i),
+            config: serde_json::json!({ "method": "aes-256-gcm", "password": "password" }),
+            sort_order: i as i32,
+            ..Node::test_fixture()
+        })
+        .collect()
+}
+
+fn groups(nodes: &[Node]) -> Vec<ClashProxyGroup> {
+    let names: Vec<String> = nodes.iter().map(|n| n.name.clone()).collect();
+    ["Proxy", "Auto"]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| ClashProxyGroup {
+            id: i as i64,
+            name: name.to_string(),
+            group_type: "select".to_string(),
+            proxies: names.clone(),
+            url: None,
+            interval: None,
+            tolerance: None,
+            is_active: true,
+            sort_order: i as i32,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        })
+        .collect()
+}
+
+fn rules(count: usize) -> Vec<ClashRule> {
+    (0..count)
+        .map(|i| ClashRule {
+            id: i as i64,
+            rule_type: "DOMAIN-SUFFIX".to_string(),
+            rule_value: Some(format!("site{}.example.com", i)),
+            proxy_group: "Proxy".to_string(),
+            no_resolve: false,
+            is_active: true,
+            sort_order: i as i32,
+            description: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        })
+        .collect()
+}
+
+fn bench_clash_config(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clash_config");
+
+    for (node_count, rule_count) in [(100, 1_000), (500, 5_000)] {
+        let nodes = nodes(node_count);
+        let groups = groups(&nodes);
+        let rules = rules(rule_count);
+        let config = clash::build_clash_config_from_nodes_and_db(&nodes, &groups, &rules);
+        let id = format!("{}n_{}r", node_count, rule_count);
+
+        if let Ok(yaml) = config.to_yaml() {
+            println!("{}: {} bytes", id, yaml.len());
+        }
+
+        group.bench_with_input(BenchmarkId::new("serde_yaml_to_string", &id), &config, |b, config| {
+            b.iter(|| serde_yaml::to_string(black_box(config)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("to_yaml_reused_buffer", &id), &config, |b, config| {
+            b.iter(|| black_box(config).to_yaml().unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("write_yaml_sink", &id), &config, |b, config| {
+            b.iter(|| black_box(config).write_yaml(&mut std::io::sink()).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("build_and_render", &id), |b| {
+            b.iter(|| {
+                let config = clash::build_clash_config_from_nodes_and_db(&nodes, &groups, &rules);
+                clash::render_with_update_interval(&config, 24).unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_clash_config);
+criterion_main!(benches);
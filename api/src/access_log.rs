@@ -0,0 +1,249 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::cache::{spawn_periodic_job, RedisCache};
+use crate::config::AccessLogConfig;
+use crate::db;
+use crate::db_error;
+
+/// Longest User-Agent stored with an access log (characters)
+pub const MAX_USER_AGENT_CHARS: usize = 512;
+/// Width of `clash_access_logs.ip_address`
+pub const MAX_IP_ADDRESS_CHARS: usize = 45;
+/// Queued entries written per batch once the database is back
+const FLUSH_BATCH_SIZE: usize = 200;
+
+/// One subscription fetch, as written to `clash_access_logs`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessLogEntry {
+    pub user_id: i64,
+    pub token: String,
+    pub ip_address: String,
+    pub user_agent: Option<String>,
+    pub status: String,
+    /// Client country, when a GeoIP lookup was made
+    pub country: Option<String>,
+    /// When the fetch happened, kept for entries written after an outage
+    pub accessed_at: DateTime<Utc>,
+}
+
+impl AccessLogEntry {
+    pub fn new(user_id: i64, token: &str, ip_address: &str, user_agent: Option<&str>, status: &str) -> Self {
+        Self {
+            user_id,
+            token: token.to_string(),
+            ip_address: truncate_chars(ip_address, MAX_IP_ADDRESS_CHARS).to_string(),
+            user_agent: user_agent.map(|ua| truncate_chars(ua, MAX_USER_AGENT_CHARS).to_string()),
+            status: status.to_string(),
+            country: None,
+            accessed_at: Utc::now(),
+        }
+    }
+
+    pub fn with_country(mut self, country: Option<&str>) -> Self {
+        self.country = country.map(str::to_string);
+        self
+    }
+}
+
+/// Whether a fetch served the config (`not_modified` counts: the client has it, and
+/// `stale` is the outage backup)
+pub fn is_success(status: &str) -> bool {
+    matches!(status, "success" | "not_modified" | "stale")
+}
+
+/// Whether a fetch is written to Postgres
+///
+/// Failures and the first success in a user's window always are; other successes
+/// when `roll` (uniform in [0, 1)) falls under the sample rate.
+pub fn should_record(status: &str, first_success: bool, sample_rate: f64, roll: f64) -> bool {
+    !is_success(status) || first_success || roll < sample_rate
+}
+
+/// Day bucket for per-user fetch counts (UTC)
+pub fn fetch_day(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d").to_string()
+}
+
+/// The first `max` characters of `value`
+pub fn truncate_chars(value: &str, max: usize) -> &str {
+    match value.char_indices().nth(max) {
+        Some((end, _)) => &value[..end],
+        None => value,
+    }
+}
+
+/// Count a subscription fetch and write it to the access log if sampling keeps it
+///
+/// Redis errors fail open: the fetch is logged rather than silently dropped. While the
+/// database is unreachable the entry is queued in Redis for `start_access_log_flush_task`.
+/// Returns whether the entry was written or queued.
+pub async fn record_access(
+    pool: &PgPool,
+    cache: &RedisCache,
+    config: &AccessLogConfig,
+    entry: &AccessLogEntry,
+) -> Result<bool> {
+    let retention_secs = config.fetch_count_retention_days * 86_400;
+    if let Err(e) = cache
+        .record_subscription_fetch(&fetch_day(Utc::now()), entry.user_id, retention_secs)
+        .await
+    {
+        tracing::warn!("Failed to count subscription fetch: {}", e);
+    }
+
+    let first_success = if is_success(&entry.status) {
+        cache
+            .claim_access_log_success(entry.user_id, config.first_success_window_secs)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to check access log window: {}", e);
+                true
+            })
+    } else {
+        false
+    };
+
+    let roll = rand::random::<f64>();
+    if !should_record(&entry.status, first_success, config.sample_rate, roll) {
+        tracing::debug!(user_id = entry.user_id, status = %entry.status, "Subscription fetch sampled out");
+        return Ok(false);
+    }
+
+    if let Err(e) = write_entry(pool, entry).await {
+        if !db_error::is_unavailable(&e) {
+            return Err(e);
+        }
+        tracing::warn!(user_id = entry.user_id, "Database unavailable, queueing access log: {}", e);
+        cache.queue_access_log(entry, config.max_queued).await?;
+    }
+
+    Ok(true)
+}
+
+async fn write_entry(pool: &PgPool, entry: &AccessLogEntry) -> Result<()> {
+    db::create_access_log(
+        pool,
+        entry.user_id,
+        &entry.token,
+        &entry.ip_address,
+        entry.user_agent.as_deref(),
+        &entry.status,
+        entry.country.as_deref(),
+        entry.accessed_at,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Write access logs queued during a database outage, oldest first
+///
+/// Stops at the first entry the database still can't take, putting it and the rest
+/// of the batch back. Entries it rejects for other reasons (e.g. a deleted user) are
+/// dropped. Returns the number written.
+pub async fn flush_queued(pool: &PgPool, cache: &RedisCache) -> Result<usize> {
+    let mut written = 0;
+    loop {
+        let batch = cache.take_queued_access_logs(FLUSH_BATCH_SIZE).await?;
+        if batch.is_empty() {
+            return Ok(written);
+        }
+
+        for (i, entry) in batch.iter().enumerate() {
+            match write_entry(pool, entry).await {
+                Ok(()) => written += 1,
+                Err(e) if db_error::is_unavailable(&e) => {
+                    cache.requeue_access_logs(&batch[i..]).await?;
+                    return Ok(written);
+                }
+                Err(e) => tracing::warn!(user_id = entry.user_id, "Dropping queued access log: {}", e),
+            }
+        }
+    }
+}
+
+/// Periodically write access logs queued during database outages
+pub fn start_access_log_flush_task(db_pool: PgPool, cache: RedisCache) -> tokio::task::JoinHandle<()> {
+    spawn_periodic_job(
+        cache.clone(),
+        "access_log_flush",
+        std::time::Duration::from_secs(30),
+        move || {
+            let db_pool = db_pool.clone();
+            let cache = cache.clone();
+            async move {
+                match flush_queued(&db_pool, &cache).await {
+                    Ok(0) => {}
+                    Ok(count) => tracing::info!("Wrote {} queued access logs", count),
+                    Err(e) => tracing::error!("Failed to write queued access logs: {}", e),
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failures_are_always_recorded() {
+        for status in [
+            "failed",
+            "disabled",
+            "quota_exceeded",
+            "expired",
+            "geo_blocked",
+            "geo_challenged",
+            "key_missing",
+            "key_invalid",
+        ] {
+            assert!(should_record(status, false, 0.0, 0.99));
+        }
+    }
+
+    #[test]
+    fn test_stale_fetches_are_sampled_as_successes() {
+        assert!(is_success("stale"));
+        assert!(!should_record("stale", false, 0.0, 0.5));
+        assert!(should_record("stale", true, 0.0, 0.5));
+    }
+
+    #[test]
+    fn test_entry_round_trips_through_the_queue() {
+        let entry = AccessLogEntry::new(7, "token", "10.0.0.1", Some("clash.meta"), "stale").with_country(Some("JP"));
+        let json = serde_json::to_string(&entry).unwrap();
+        assert_eq!(serde_json::from_str::<AccessLogEntry>(&json).unwrap(), entry);
+    }
+
+    #[test]
+    fn test_successes_are_sampled_after_the_first() {
+        assert!(should_record("success", true, 0.0, 0.99));
+        assert!(should_record("not_modified", true, 0.0, 0.99));
+        assert!(!should_record("success", false, 0.0, 0.0));
+        assert!(!should_record("not_modified", false, 0.1, 0.5));
+        assert!(should_record("success", false, 0.1, 0.05));
+        assert!(should_record("success", false, 1.0, 0.999));
+    }
+
+    #[test]
+    fn test_entry_is_size_bounded() {
+        let long_ua = "界".repeat(MAX_USER_AGENT_CHARS + 10);
+        let entry = AccessLogEntry::new(1, "token", &"1".repeat(60), Some(&long_ua), "success");
+
+        assert_eq!(entry.ip_address.len(), MAX_IP_ADDRESS_CHARS);
+        assert_eq!(entry.user_agent.unwrap().chars().count(), MAX_USER_AGENT_CHARS);
+
+        let entry = AccessLogEntry::new(1, "token", "10.0.0.1", Some("clash.meta"), "failed");
+        assert_eq!(entry.ip_address, "10.0.0.1");
+        assert_eq!(entry.user_agent.as_deref(), Some("clash.meta"));
+    }
+
+    #[test]
+    fn test_fetch_day() {
+        let at = DateTime::parse_from_rfc3339("2026-03-01T23:59:59+00:00").unwrap().with_timezone(&Utc);
+        assert_eq!(fetch_day(at), "2026-03-01");
+    }
+}
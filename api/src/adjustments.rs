@@ -0,0 +1,118 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::amounts::Coins;
+use crate::models::BalanceAdjustment;
+
+/// Width of `balance_adjustments.reason`
+pub const MAX_REASON_LEN: usize = 200;
+/// Width of `balance_adjustments.review_note`
+pub const MAX_NOTE_LEN: usize = 500;
+/// Reason recorded when the admin gives none
+pub const DEFAULT_REASON: &str = "Admin adjustment";
+
+crate::status::status_enum! {
+    /// Review state stored in `balance_adjustments.status`
+    AdjustmentStatus {
+        Pending => "pending",
+        /// Coins added or taken from the user
+        Approved => "approved",
+        Rejected => "rejected",
+    }
+}
+
+/// Whether an adjustment waits for a second admin's approval (threshold 0 disables approvals)
+pub fn requires_approval(amount: Coins, threshold: i64) -> bool {
+    threshold > 0 && amount.get().unsigned_abs() >= threshold as u64
+}
+
+/// Balance after adding `amount`; the error is the message returned to the admin
+///
+/// The balance may not go negative, nor below the coins reserved by pending orders.
+pub fn adjusted_balance(balance: Coins, reserved: Coins, amount: Coins) -> Result<Coins, &'static str> {
+    let new_balance = balance
+        .checked_add(amount)
+        .filter(|balance| !balance.is_negative())
+        .ok_or("Balance cannot be negative")?;
+
+    if new_balance < reserved {
+        return Err("Balance cannot be lower than coins reserved by pending orders");
+    }
+
+    Ok(new_balance)
+}
+
+/// An adjustment was requested or reviewed, published on `adjustments:events`
+///
+/// Admin notifiers pick up `pending` events to ask for a second approval.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdjustmentEvent {
+    pub adjustment_id: i64,
+    pub user_id: i64,
+    pub tenant_id: i64,
+    pub amount: Coins,
+    pub status: AdjustmentStatus,
+    pub requested_by: Option<i64>,
+    pub reviewed_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AdjustmentEvent {
+    pub fn new(adjustment: &BalanceAdjustment, tenant_id: i64) -> Self {
+        Self {
+            adjustment_id: adjustment.id,
+            user_id: adjustment.user_id,
+            tenant_id,
+            amount: adjustment.amount,
+            status: adjustment.status,
+            requested_by: adjustment.requested_by,
+            reviewed_by: adjustment.reviewed_by,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// A requested or reviewed adjustment and the outbox events written with it
+#[derive(Debug, Clone)]
+pub struct AdjustmentUpdate {
+    pub adjustment: BalanceAdjustment,
+    /// Outbox events written with the change (see `outbox::deliver_now`)
+    pub outbox_ids: Vec<i64>,
+}
+
+/// Result of approving a pending adjustment
+#[derive(Debug, Clone)]
+pub enum Approval {
+    /// The balance was changed and the adjustment marked approved
+    Applied(AdjustmentUpdate),
+    /// The adjustment doesn't exist or was already reviewed
+    NotPending,
+    /// The user's balance no longer allows the change; the message from `adjusted_balance`
+    Refused(&'static str),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requires_approval() {
+        assert!(requires_approval(Coins::new(50_000), 50_000));
+        assert!(requires_approval(Coins::new(-50_000), 50_000));
+        assert!(!requires_approval(Coins::new(49_999), 50_000));
+        assert!(!requires_approval(Coins::new(i64::MIN), 0));
+    }
+
+    #[test]
+    fn test_adjusted_balance() {
+        let (balance, reserved) = (Coins::new(1000), Coins::new(300));
+        assert_eq!(adjusted_balance(balance, reserved, Coins::new(500)), Ok(Coins::new(1500)));
+        assert_eq!(adjusted_balance(balance, reserved, Coins::new(-700)), Ok(Coins::new(300)));
+        assert_eq!(
+            adjusted_balance(balance, reserved, Coins::new(-800)),
+            Err("Balance cannot be lower than coins reserved by pending orders")
+        );
+        assert_eq!(adjusted_balance(balance, reserved, Coins::new(-1001)), Err("Balance cannot be negative"));
+        assert_eq!(adjusted_balance(balance, reserved, Coins::new(i64::MAX)), Err("Balance cannot be negative"));
+    }
+}
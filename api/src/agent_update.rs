@@ -0,0 +1,189 @@
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use crate::config::AgentReleaseConfig;
+use crate::crypto;
+use crate::models::Node;
+
+/// Width of `nodes.agent_version`
+pub const MAX_VERSION_LEN: usize = 32;
+
+/// The version an agent reported, if it is usable; blank and oversized values are ignored
+pub fn reported_version(version: Option<&str>) -> Option<&str> {
+    version
+        .map(str::trim)
+        .filter(|v| !v.is_empty() && v.len() <= MAX_VERSION_LEN)
+}
+
+/// Update offered to an agent by `GET /api/node/agent-version`
+///
+/// `signature` is the hex HMAC-SHA256 of [`manifest_message`] keyed with the node secret
+/// the request was authenticated with, so the agent can check the manifest came from
+/// the API before downloading; the binary itself is checked against `sha256`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UpdateManifest {
+    pub version: String,
+    pub url: String,
+    /// Hex SHA-256 of the binary at `url`
+    pub sha256: String,
+    /// Unix time after which the agent must not act on the manifest
+    pub expires_at: i64,
+    pub signature: String,
+}
+
+/// The signed part of a manifest, one field per line
+pub fn manifest_message(version: &str, url: &str, sha256: &str, expires_at: i64) -> String {
+    format!("{}\n{}\n{}\n{}", version, url, sha256, expires_at)
+}
+
+/// The update for an agent running `running` on `arch`, signed with `node_secret`
+///
+/// None when no release is configured, the release is built for another architecture,
+/// or the agent is already up to date. Agents that don't report a version are offered
+/// the release.
+pub fn manifest_for(
+    release: &AgentReleaseConfig,
+    running: Option<&str>,
+    arch: Option<&str>,
+    node_secret: &str,
+    now: i64,
+) -> Option<UpdateManifest> {
+    let (version, url, sha256) = release.offered()?;
+    if arch.is_some_and(|arch| arch != release.arch) {
+        return None;
+    }
+    if running.is_some_and(|running| compare_versions(running, version) != Ordering::Less) {
+        return None;
+    }
+
+    let expires_at = now + release.manifest_ttl_secs;
+    let signature = crypto::hmac_sha256_hex(
+        node_secret,
+        manifest_message(version, url, sha256, expires_at).as_bytes(),
+    );
+
+    Some(UpdateManifest {
+        version: version.to_string(),
+        url: url.to_string(),
+        sha256: sha256.to_string(),
+        expires_at,
+        signature,
+    })
+}
+
+/// Compare dotted versions numerically, so `1.10.0` is newer than `1.9.3`
+///
+/// A leading `v` and any pre-release or build suffix (`-rc1`, `+abc`) are ignored,
+/// missing components count as 0.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn parts(version: &str) -> Vec<u64> {
+        let version = version.trim().trim_start_matches('v');
+        let core = version.split(['-', '+']).next().unwrap_or_default();
+        core.split('.').map(|part| part.parse().unwrap_or(0)).collect()
+    }
+
+    let (a, b) = (parts(a), parts(b));
+    let len = a.len().max(b.len());
+    let at = |parts: &[u64], i: usize| parts.get(i).copied().unwrap_or(0);
+    (0..len)
+        .map(|i| at(&a, i).cmp(&at(&b, i)))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Agent version of one node, for `GET /api/admin/nodes/agent-versions`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeAgentVersion {
+    pub node_id: i64,
+    pub name: String,
+    /// None until the agent reports its version in a heartbeat
+    pub agent_version: Option<String>,
+    /// Older than the configured release (unknown versions count as outdated)
+    pub outdated: bool,
+}
+
+/// Which agent versions the nodes run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VersionSkew {
+    pub latest_version: Option<String>,
+    /// Node count per reported version (`unknown` for nodes that never reported one)
+    pub versions: BTreeMap<String, i64>,
+    pub outdated: i64,
+    pub nodes: Vec<NodeAgentVersion>,
+}
+
+impl VersionSkew {
+    pub fn new(nodes: &[Node], latest_version: Option<&str>) -> Self {
+        let nodes: Vec<NodeAgentVersion> = nodes
+            .iter()
+            .map(|node| NodeAgentVersion {
+                node_id: node.id,
+                name: node.name.clone(),
+                agent_version: node.agent_version.clone(),
+                outdated: latest_version.is_some_and(|latest| match &node.agent_version {
+                    Some(running) => compare_versions(running, latest) == Ordering::Less,
+                    None => true,
+                }),
+            })
+            .collect();
+
+        let mut versions = BTreeMap::new();
+        for node in &nodes {
+            let version = node.agent_version.clone().unwrap_or_else(|| "unknown".to_string());
+            *versions.entry(version).or_insert(0) += 1;
+        }
+
+        Self {
+            latest_version: latest_version.map(str::to_string),
+            versions,
+            outdated: nodes.iter().filter(|node| node.outdated).count() as i64,
+            nodes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release() -> AgentReleaseConfig {
+        AgentReleaseConfig {
+            version: Some("1.4.0".to_string()),
+            download_url: Some("https://releases.example.com/node-agent-1.4.0".to_string()),
+            sha256: Some("ab".repeat(32)),
+            ..AgentReleaseConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("1.10.0", "1.9.3"), Ordering::Greater);
+        assert_eq!(compare_versions("v1.2", "1.2.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.2.0-rc1", "1.2.0"), Ordering::Equal);
+        assert_eq!(compare_versions("0.1.0", "1.0.0"), Ordering::Less);
+
+        assert_eq!(reported_version(Some(" 1.2.0 ")), Some("1.2.0"));
+        assert_eq!(reported_version(Some("")), None);
+        assert_eq!(reported_version(Some(&"9".repeat(33))), None);
+    }
+
+    #[test]
+    fn test_manifest_for() {
+        let release = release();
+
+        let manifest = manifest_for(&release, Some("1.3.9"), Some("x86_64"), "node-secret", 1_000).unwrap();
+        assert_eq!(manifest.version, "1.4.0");
+        assert_eq!(manifest.expires_at, 1_000 + release.manifest_ttl_secs);
+        let message = manifest_message(&manifest.version, &manifest.url, &manifest.sha256, manifest.expires_at);
+        assert_eq!(manifest.signature, crypto::hmac_sha256_hex("node-secret", message.as_bytes()));
+
+        // Up to date, other architecture, or no release configured
+        assert_eq!(manifest_for(&release, Some("1.4.0"), None, "node-secret", 1_000), None);
+        assert_eq!(manifest_for(&release, Some("1.3.9"), Some("aarch64"), "node-secret", 1_000), None);
+        assert_eq!(manifest_for(&AgentReleaseConfig::default(), None, None, "node-secret", 1_000), None);
+
+        // Agents too old to report a version are offered the release
+        assert!(manifest_for(&release, None, None, "node-secret", 1_000).is_some());
+    }
+}
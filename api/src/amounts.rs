@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Define an i64-backed amount type with checked arithmetic only
+///
+/// There are deliberately no `Add`/`Sub` impls: every balance change goes through
+/// `checked_*`, so overflow and unintended negative values surface as `None`.
+macro_rules! amount_type {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(
+            Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+            Serialize, Deserialize, sqlx::Type,
+        )]
+        #[serde(transparent)]
+        #[sqlx(transparent)]
+        pub struct $name(i64);
+
+        impl $name {
+            pub const ZERO: Self = Self(0);
+
+            pub const fn new(value: i64) -> Self {
+                Self(value)
+            }
+
+            /// Raw value, for JSON responses and arithmetic outside accounting
+            pub const fn get(self) -> i64 {
+                self.0
+            }
+
+            pub const fn is_negative(self) -> bool {
+                self.0 < 0
+            }
+
+            pub const fn is_positive(self) -> bool {
+                self.0 > 0
+            }
+
+            pub fn checked_add(self, other: Self) -> Option<Self> {
+                self.0.checked_add(other.0).map(Self)
+            }
+
+            pub fn checked_sub(self, other: Self) -> Option<Self> {
+                self.0.checked_sub(other.0).map(Self)
+            }
+
+            /// Subtract, returning None on overflow or if the result would be negative
+            pub fn checked_debit(self, other: Self) -> Option<Self> {
+                self.checked_sub(other).filter(|v| !v.is_negative())
+            }
+
+            pub fn checked_neg(self) -> Option<Self> {
+                self.0.checked_neg().map(Self)
+            }
+
+            /// Difference clamped at zero (e.g. remaining quota)
+            pub fn saturating_sub(self, other: Self) -> Self {
+                Self(self.0.saturating_sub(other.0).max(0))
+            }
+        }
+
+        impl From<i64> for $name {
+            fn from(value: i64) -> Self {
+                Self(value)
+            }
+        }
+
+        impl From<$name> for i64 {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+    };
+}
+
+amount_type!(
+    /// An amount of virtual coins (balances, prices, order totals, transactions)
+    Coins
+);
+
+amount_type!(
+    /// An amount of traffic in bytes (quotas and usage)
+    TrafficBytes
+);
+
+impl Coins {
+    /// Share of the amount at `rate` (e.g. 0.10 for a 10% rebate), rounded down
+    pub fn percent(self, rate: f64) -> Self {
+        Self((self.0 as f64 * rate) as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let balance = Coins::new(500);
+
+        assert_eq!(balance.checked_add(Coins::new(200)), Some(Coins::new(700)));
+        assert_eq!(balance.checked_sub(Coins::new(700)), Some(Coins::new(-200)));
+        assert_eq!(balance.checked_debit(Coins::new(500)), Some(Coins::ZERO));
+        assert_eq!(balance.checked_debit(Coins::new(501)), None);
+        assert_eq!(Coins::new(i64::MAX).checked_add(Coins::new(1)), None);
+        assert_eq!(Coins::new(i64::MIN).checked_neg(), None);
+    }
+
+    #[test]
+    fn test_saturating_sub_and_percent() {
+        assert_eq!(TrafficBytes::new(100).saturating_sub(TrafficBytes::new(30)), TrafficBytes::new(70));
+        assert_eq!(TrafficBytes::new(100).saturating_sub(TrafficBytes::new(130)), TrafficBytes::ZERO);
+        assert_eq!(Coins::new(999).percent(0.10), Coins::new(99));
+    }
+
+    #[test]
+    fn test_serde_is_transparent() {
+        assert_eq!(serde_json::to_string(&Coins::new(42)).unwrap(), "42");
+        let bytes: TrafficBytes = serde_json::from_str("1073741824").unwrap();
+        assert_eq!(bytes.get(), 1073741824);
+    }
+}
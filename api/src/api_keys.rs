@@ -0,0 +1,154 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
+use sqlx::PgPool;
+
+use crate::crypto;
+use crate::db;
+use crate::models::ApiKey;
+
+crate::status::status_enum! {
+    /// Permission granted to an API key, stored in `api_keys.scopes`
+    ApiKeyScope {
+        /// Read platform statistics
+        StatsRead => "stats:read",
+        /// Submit node traffic reports
+        TrafficWrite => "traffic:write",
+        /// Create user accounts
+        UsersProvision => "users:provision",
+        /// Read node secrets, e.g. to set up node agents
+        NodeSecretsRead => "node-secrets:read",
+    }
+}
+
+impl PgHasArrayType for ApiKeyScope {
+    fn array_type_info() -> PgTypeInfo {
+        <&str as PgHasArrayType>::array_type_info()
+    }
+
+    fn array_compatible(ty: &PgTypeInfo) -> bool {
+        <&str as PgHasArrayType>::array_compatible(ty)
+    }
+}
+
+/// Marks a bearer token as an API key rather than a JWT
+pub const KEY_PREFIX: &str = "nsk_";
+/// Leading characters of a key kept in the clear to tell keys apart in listings
+pub const DISPLAY_PREFIX_LEN: usize = 12;
+/// Width of `api_keys.name`
+pub const MAX_NAME_CHARS: usize = 100;
+/// Longest lifetime a key can be created with (a key may also never expire)
+pub const MAX_EXPIRES_IN_DAYS: i64 = 3650;
+/// `last_used_at` is written at most this often per key
+pub const LAST_USED_PRECISION_SECS: i64 = 60;
+
+/// Whether a bearer token is an API key
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(KEY_PREFIX)
+}
+
+/// The part of a key stored in the clear, e.g. `nsk_Ab3dE6gH`
+pub fn display_prefix(key: &str) -> String {
+    key.chars().take(DISPLAY_PREFIX_LEN).collect()
+}
+
+impl ApiKey {
+    pub fn has_scope(&self, scope: ApiKeyScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+
+    /// Whether a use at `now` should be written to `last_used_at`
+    fn needs_touch(&self, now: DateTime<Utc>) -> bool {
+        !self
+            .last_used_at
+            .is_some_and(|at| now - at < Duration::seconds(LAST_USED_PRECISION_SECS))
+    }
+}
+
+/// The unexpired key matching `key`, recording its use
+///
+/// Keys are looked up by hash; recording the use is best effort and never fails the request.
+pub async fn verify(pool: &PgPool, key: &str, client_ip: Option<&str>) -> Result<Option<ApiKey>> {
+    if !is_api_key(key) {
+        return Ok(None);
+    }
+
+    let now = Utc::now();
+    let Some(api_key) = db::get_api_key_by_hash(pool, &crypto::sha256_hex(key.as_bytes()))
+        .await?
+        .filter(|api_key| !api_key.is_expired(now))
+    else {
+        return Ok(None);
+    };
+
+    if api_key.needs_touch(now) {
+        if let Err(e) = db::touch_api_key(pool, api_key.id, client_ip).await {
+            tracing::warn!("Failed to record use of API key {}: {}", api_key.id, e);
+        }
+    }
+
+    Ok(Some(api_key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_key(scopes: Vec<ApiKeyScope>) -> ApiKey {
+        ApiKey {
+            id: 1,
+            name: "billing".to_string(),
+            key_prefix: "nsk_Ab3dE6gH".to_string(),
+            scopes,
+            expires_at: None,
+            last_used_at: None,
+            last_used_ip: None,
+            created_by: Some(1),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_key_format() {
+        let key = crate::utils::generate_api_key();
+        assert!(is_api_key(&key));
+        assert_eq!(key.len(), KEY_PREFIX.len() + 40);
+        assert_eq!(display_prefix(&key).len(), DISPLAY_PREFIX_LEN);
+        assert!(key.starts_with(&display_prefix(&key)));
+
+        assert!(!is_api_key("eyJhbGciOiJIUzI1NiJ9.e30.sig"));
+    }
+
+    #[test]
+    fn test_scopes_and_expiry() {
+        let now = Utc::now();
+        let mut key = api_key(vec![ApiKeyScope::StatsRead]);
+        assert!(key.has_scope(ApiKeyScope::StatsRead));
+        assert!(!key.has_scope(ApiKeyScope::UsersProvision));
+        assert!(!key.is_expired(now));
+
+        key.expires_at = Some(now - Duration::seconds(1));
+        assert!(key.is_expired(now));
+
+        assert_eq!(ApiKeyScope::parse("traffic:write"), Some(ApiKeyScope::TrafficWrite));
+        assert_eq!(ApiKeyScope::parse("node-secrets:read"), Some(ApiKeyScope::NodeSecretsRead));
+        assert_eq!(ApiKeyScope::parse("admin"), None);
+    }
+
+    #[test]
+    fn test_last_used_is_throttled() {
+        let now = Utc::now();
+        let mut key = api_key(vec![]);
+        assert!(key.needs_touch(now));
+
+        key.last_used_at = Some(now - Duration::seconds(10));
+        assert!(!key.needs_touch(now));
+
+        key.last_used_at = Some(now - Duration::seconds(LAST_USED_PRECISION_SECS));
+        assert!(key.needs_touch(now));
+    }
+}
@@ -1,7 +1,5 @@
-use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
-    Argon2,
-};
+use api::config::Argon2Config;
+use api::utils::hash_password;
 use std::env;
 
 fn main() {
@@ -13,13 +11,9 @@ fn main() {
         "admin123"
     };
     
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
-    
-    let password_hash = argon2
-        .hash_password(password.as_bytes(), &salt)
-        .expect("Failed to hash password")
-        .to_string();
+    // Same ARGON2_* settings as the API, so the hash is not re-hashed on first login
+    let argon2 = Argon2Config::from_env().expect("Invalid ARGON2_* settings");
+    let password_hash = hash_password(password, &argon2).expect("Failed to hash password");
     
     println!("Password: {}", password);
     println!("Hash: {}", password_hash);
@@ -0,0 +1,57 @@
+//! Check every user's coin balance against the sum of their coin transactions.
+//!
+//! Usage:
+//!   DATABASE_URL=... cargo run --bin reconcile_ledger -- [--fix] [--tenant <id>]
+//!
+//! With --fix, each drift is corrected by an 'adjustment' transaction (balances are
+//! left as they are). Exits with status 1 while unresolved discrepancies remain.
+
+use anyhow::{Context, Result};
+use std::env;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv::dotenv().ok();
+
+    let mut fix = false;
+    let mut tenant_id = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fix" => fix = true,
+            "--tenant" => {
+                let id = args.next().context("--tenant needs a tenant id")?;
+                tenant_id = Some(id.parse::<i64>().context("--tenant must be a valid number")?);
+            }
+            other => anyhow::bail!("Unknown argument: {}", other),
+        }
+    }
+
+    let database_url = env::var("DATABASE_URL").context("DATABASE_URL must be set")?;
+    let pool = api::db::create_pool(&database_url, &api::config::DbPoolConfig::from_env()?).await?;
+    let report = api::ledger::reconcile(&pool, tenant_id, fix).await?;
+
+    for drift in &report.discrepancies {
+        println!(
+            "user {} ({}, tenant {}): balance {}, transactions {}, drift {}{}",
+            drift.user_id,
+            drift.email,
+            drift.tenant_id,
+            drift.coin_balance,
+            drift.ledger_balance,
+            drift.drift,
+            if report.fixed.contains(&drift.user_id) { " [fixed]" } else { "" }
+        );
+    }
+    println!(
+        "{} discrepancies, {} fixed",
+        report.discrepancies.len(),
+        report.fixed.len()
+    );
+
+    if report.unresolved().next().is_some() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+//! Check users' traffic totals against their packages.
+//!
+//! Usage:
+//!   DATABASE_URL=... cargo run --bin reconcile_traffic -- [--fix]
+//!
+//! `users.traffic_quota` and `users.traffic_used` are the totals over the user's
+//! packages. Data written before they were kept in step may disagree; --fix
+//! recomputes the totals from the packages (the packages are what quota checks use).
+
+use anyhow::{Context, Result};
+use std::env;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv::dotenv().ok();
+
+    let mut fix = false;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--fix" => fix = true,
+            other => anyhow::bail!("Unknown argument: {}", other),
+        }
+    }
+
+    let database_url = env::var("DATABASE_URL").context("DATABASE_URL must be set")?;
+    let pool = api::db::create_pool(&database_url, &api::config::DbPoolConfig::from_env()?).await?;
+    let drifts = api::traffic::reconcile_traffic_totals(&pool, fix).await?;
+
+    for drift in &drifts {
+        println!(
+            "user {} ({}): quota {} / used {}, packages {} / {}",
+            drift.user_id,
+            drift.email,
+            drift.traffic_quota,
+            drift.traffic_used,
+            drift.package_quota,
+            drift.package_used
+        );
+    }
+    println!(
+        "{} users out of step{}",
+        drifts.len(),
+        if fix { ", all synced" } else { "" }
+    );
+
+    Ok(())
+}
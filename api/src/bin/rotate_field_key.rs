@@ -0,0 +1,168 @@
+//! Re-encrypt sensitive node fields with a new key.
+//!
+//! Usage:
+//!   DATABASE_URL=... FIELD_ENCRYPTION_KEY=<new key> \
+//!   FIELD_ENCRYPTION_OLD_KEY=<old key, omit if data is still plaintext> \
+//!   cargo run --bin rotate_field_key
+
+use anyhow::{bail, Context, Result};
+use api::crypto::{self, FieldCipher};
+use std::env;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv::dotenv().ok();
+
+    let database_url = env::var("DATABASE_URL").context("DATABASE_URL must be set")?;
+    let new_cipher = FieldCipher::from_base64_key(
+        &env::var("FIELD_ENCRYPTION_KEY").context("FIELD_ENCRYPTION_KEY must be set")?,
+    )?;
+    let old_cipher = match env::var("FIELD_ENCRYPTION_OLD_KEY") {
+        Ok(key) if !key.is_empty() => Some(FieldCipher::from_base64_key(&key)?),
+        _ => None,
+    };
+
+    let pool = api::db::create_pool(&database_url, &api::config::DbPoolConfig::from_env()?).await?;
+    let mut tx = pool.begin().await?;
+
+    let rows = sqlx::query_as::<_, (i64, String, Option<String>, serde_json::Value)>(
+        r#"
+        SELECT id, secret, previous_secret, config FROM nodes ORDER BY id FOR UPDATE
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut updated = 0;
+    for (id, secret, previous_secret, config) in &rows {
+        let fields = NodeFields {
+            secret: secret.clone(),
+            previous_secret: previous_secret.clone(),
+            config: config.clone(),
+        };
+        let resealed = reseal_node(&new_cipher, old_cipher.as_ref(), &fields)
+            .with_context(|| format!("Failed to re-encrypt node {}", id))?;
+        if resealed == fields {
+            continue;
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE nodes SET secret = $2, previous_secret = $3, config = $4, updated_at = NOW() WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(resealed.secret)
+        .bind(resealed.previous_secret)
+        .bind(resealed.config)
+        .execute(&mut *tx)
+        .await?;
+        updated += 1;
+    }
+
+    tx.commit().await?;
+    println!("Re-encrypted {} nodes, {} already used the new key", updated, rows.len() - updated);
+
+    Ok(())
+}
+
+/// The encrypted columns of a node
+#[derive(Debug, Clone, PartialEq)]
+struct NodeFields {
+    secret: String,
+    /// Still accepted until `previous_secret_expires_at` after a secret rotation
+    previous_secret: Option<String>,
+    config: serde_json::Value,
+}
+
+/// Encrypt all of a node's encrypted columns with the new key
+fn reseal_node(new: &FieldCipher, old: Option<&FieldCipher>, fields: &NodeFields) -> Result<NodeFields> {
+    Ok(NodeFields {
+        secret: reseal(new, old, &fields.secret).context("secret")?,
+        previous_secret: fields
+            .previous_secret
+            .as_deref()
+            .map(|previous| reseal(new, old, previous))
+            .transpose()
+            .context("previous_secret")?,
+        config: crypto::map_sensitive_values(&fields.config, &|v| reseal(new, old, v)).context("config")?,
+    })
+}
+
+/// Encrypt one value with the new key
+///
+/// A value that already decrypts with the new key (a rerun, or a row written after the
+/// new key was installed) is kept as is; anything else is decrypted with the old key, or
+/// taken as plaintext when there is none.
+fn reseal(new: &FieldCipher, old: Option<&FieldCipher>, value: &str) -> Result<String> {
+    if new.can_decrypt(value) {
+        return Ok(value.to_string());
+    }
+
+    let plaintext = match old {
+        Some(old) => old.decrypt(value)?,
+        // Encrypting it again would lose it for good
+        None if crypto::is_encrypted(value) => {
+            bail!("value is encrypted with another key, set FIELD_ENCRYPTION_OLD_KEY to that key")
+        }
+        None => value.to_string(),
+    };
+    new.encrypt(&plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    use serde_json::json;
+
+    fn cipher(byte: u8) -> FieldCipher {
+        FieldCipher::from_base64_key(&STANDARD.encode([byte; 32])).unwrap()
+    }
+
+    #[test]
+    fn test_node_mid_overlap_is_resealed() {
+        let (old, new) = (cipher(1), cipher(2));
+        // The secret was rotated recently, so the old one is still accepted
+        let fields = NodeFields {
+            secret: old.encrypt("current").unwrap(),
+            previous_secret: Some(old.encrypt("previous").unwrap()),
+            config: old.encrypt_config(&json!({ "password": "pw", "uuid": "abc" })).unwrap(),
+        };
+
+        let resealed = reseal_node(&new, Some(&old), &fields).unwrap();
+        assert_eq!(new.decrypt(&resealed.secret).unwrap(), "current");
+        assert_eq!(new.decrypt(resealed.previous_secret.as_deref().unwrap()).unwrap(), "previous");
+        assert_eq!(new.decrypt_config(&resealed.config).unwrap(), json!({ "password": "pw", "uuid": "abc" }));
+        assert!(!old.can_decrypt(resealed.previous_secret.as_deref().unwrap()));
+
+        // A rerun leaves the row as it is
+        assert_eq!(reseal_node(&new, Some(&old), &resealed).unwrap(), resealed);
+    }
+
+    #[test]
+    fn test_node_without_previous_secret() {
+        let new = cipher(2);
+        let fields = NodeFields {
+            secret: "plaintext".to_string(),
+            previous_secret: None,
+            config: json!({}),
+        };
+
+        let resealed = reseal_node(&new, None, &fields).unwrap();
+        assert_eq!(resealed.previous_secret, None);
+        assert_eq!(new.decrypt(&resealed.secret).unwrap(), "plaintext");
+    }
+
+    #[test]
+    fn test_unknown_key_is_refused() {
+        let fields = NodeFields {
+            secret: cipher(2).encrypt("current").unwrap(),
+            previous_secret: Some(cipher(3).encrypt("previous").unwrap()),
+            config: json!({}),
+        };
+
+        // Only the previous secret is under a key the CLI was not given
+        assert!(reseal_node(&cipher(2), None, &fields).is_err());
+    }
+}
@@ -0,0 +1,49 @@
+//! Populate a development or demo database with sample data.
+//!
+//! Usage:
+//!   DATABASE_URL=... cargo run --bin seed
+//!
+//! Optional: SEED_ADMIN_EMAIL, SEED_ADMIN_PASSWORD, SEED_USER_COUNT, SEED_USER_PASSWORD,
+//! SEED_TRAFFIC_DAYS, FIELD_ENCRYPTION_KEY (set it if the API encrypts node secrets),
+//! ARGON2_MEMORY_KIB, ARGON2_ITERATIONS, ARGON2_PARALLELISM.
+
+use anyhow::{Context, Result};
+use api::seed::{seed_demo_data, SeedOptions};
+use std::env;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv::dotenv().ok();
+
+    let database_url = env::var("DATABASE_URL").context("DATABASE_URL must be set")?;
+
+    // Seeded node secrets must be sealed with the same key the API reads them with
+    if let Ok(key) = env::var("FIELD_ENCRYPTION_KEY") {
+        if !key.is_empty() {
+            api::crypto::install_field_cipher(api::crypto::FieldCipher::from_base64_key(&key)?)?;
+        }
+    }
+
+    let defaults = SeedOptions::default();
+    let options = SeedOptions {
+        admin_email: env::var("SEED_ADMIN_EMAIL").unwrap_or(defaults.admin_email),
+        admin_password: env::var("SEED_ADMIN_PASSWORD").unwrap_or(defaults.admin_password),
+        user_count: env::var("SEED_USER_COUNT")
+            .map(|v| v.parse().context("SEED_USER_COUNT must be a valid number"))
+            .unwrap_or(Ok(defaults.user_count))?,
+        user_password: env::var("SEED_USER_PASSWORD").unwrap_or(defaults.user_password),
+        traffic_days: env::var("SEED_TRAFFIC_DAYS")
+            .map(|v| v.parse().context("SEED_TRAFFIC_DAYS must be a valid number"))
+            .unwrap_or(Ok(defaults.traffic_days))?,
+        argon2: api::config::Argon2Config::from_env()?,
+    };
+
+    let pool = api::db::create_pool(&database_url, &api::config::DbPoolConfig::from_env()?).await?;
+    let summary = seed_demo_data(&pool, &options).await?;
+
+    println!("Seeded {}", summary);
+    println!("Demo admin: {} / {}", options.admin_email, options.admin_password);
+    println!("Demo users: demo1..{}@example.com / {}", options.user_count, options.user_password);
+
+    Ok(())
+}
@@ -1,9 +1,372 @@
 use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use redis::aio::ConnectionManager;
 use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::access_log::AccessLogEntry;
+use crate::compression::Encoding;
+use crate::config::CacheTtlConfig;
+use crate::models::{Node, SubscriptionGeoRestriction, UserNodeSeen};
+use crate::oauth::PendingLogin;
+use crate::status::UserPackageStatus;
+use crate::status_page::StatusPage;
+
+/// Release a lock only if it is still held by the given owner
+const RELEASE_LOCK_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+else
+    return 0
+end
+"#;
+
+/// Extend a lock's lease only if it is still held by the given owner
+const RENEW_LOCK_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("EXPIRE", KEYS[1], ARGV[2])
+else
+    return 0
+end
+"#;
+
+static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+
+/// Unique identifier of this API process, used as the lock owner
+pub fn instance_id() -> &'static str {
+    INSTANCE_ID.get_or_init(|| {
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "api".to_string());
+        format!("{}-{}", host, uuid::Uuid::new_v4())
+    })
+}
+
+/// Index set of every token with a cached subscription config
+const SUBSCRIPTION_INDEX_ALL: &str = "subscription:index:all";
+/// Index set of every cached subscription config fingerprint
+const SUBSCRIPTION_BLOB_INDEX: &str = "subscription:blobs";
+
+/// Key of a config's compressed copy, outside `subscription:blob:*` so stats count configs only
+fn subscription_variant_key(fingerprint: &str, encoding: Encoding) -> String {
+    format!("subscription:encoded:{}:{}", encoding.as_str(), fingerprint)
+}
+
+/// Access log entries waiting for the database, oldest at the tail
+const ACCESS_LOG_QUEUE: &str = "access_log:pending";
+
+/// Key of a token's outage backup, kept for `CacheTtlConfig::subscription_stale_secs`
+fn stale_subscription_key(token: &str) -> String {
+    format!("subscription:stale:{}", token)
+}
+
+/// A token's last generated config, served only while the database is unreachable
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaleSubscription {
+    pub user_id: i64,
+    pub fingerprint: String,
+    pub config: String,
+    pub cached_at: DateTime<Utc>,
+    /// `subscriptions.access_key_hash` when the backup was kept; backups from before
+    /// access keys existed have none
+    #[serde(default)]
+    pub access_key_hash: Option<String>,
+}
+
+/// A cached subscription config with the user it belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedSubscription {
+    pub user_id: i64,
+    pub config: String,
+    /// Fingerprint of the config's inputs, which its compressed copies are keyed by
+    pub fingerprint: String,
+    /// The subscription's access key hash, so protected tokens are checked without a query
+    pub access_key_hash: Option<String>,
+    /// The owner's GeoIP override, so restricted fetches are refused without a query
+    pub geo_restriction: Option<SubscriptionGeoRestriction>,
+}
+
+/// What a token's cache entry holds: its owner, the fingerprint of its shared config,
+/// its access key hash and the owner's GeoIP override
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SubscriptionRef {
+    user_id: i64,
+    fingerprint: String,
+    access_key_hash: Option<String>,
+    geo_restriction: Option<SubscriptionGeoRestriction>,
+}
+
+impl SubscriptionRef {
+    /// Cache value: the owner's id on the first line, then the fingerprint, the access
+    /// key hash and the GeoIP override as JSON, the last two empty when there is none
+    fn encode(
+        user_id: i64,
+        fingerprint: &str,
+        access_key_hash: Option<&str>,
+        geo_restriction: Option<&SubscriptionGeoRestriction>,
+    ) -> String {
+        let geo_restriction = geo_restriction
+            .and_then(|restriction| serde_json::to_string(restriction).ok())
+            .unwrap_or_default();
+        format!("{}\n{}\n{}\n{}", user_id, fingerprint, access_key_hash.unwrap_or_default(), geo_restriction)
+    }
+
+    fn decode(value: &str) -> Option<Self> {
+        let mut lines = value.splitn(4, '\n');
+        let user_id = lines.next()?;
+        let fingerprint = lines.next()?;
+        // Entries from before configs were shared hold the config itself
+        if fingerprint.len() != 64 || !fingerprint.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        // Entries from before the GeoIP override was cached have fewer lines
+        let access_key_hash = lines.next()?;
+        let geo_restriction = match lines.next()? {
+            "" => None,
+            json => Some(serde_json::from_str(json).ok()?),
+        };
+        Some(Self {
+            user_id: user_id.parse().ok()?,
+            fingerprint: fingerprint.to_string(),
+            access_key_hash: (!access_key_hash.is_empty()).then(|| access_key_hash.to_string()),
+            geo_restriction,
+        })
+    }
+}
+
+/// Which cached subscription configs to purge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "scope", content = "id", rename_all = "snake_case")]
+pub enum PurgeScope {
+    /// Every cached subscription config
+    AllSubscriptions,
+    /// Configs that embed the given node
+    Node(i64),
+    /// Configs belonging to the given user
+    User(i64),
+}
+
+impl PurgeScope {
+    /// Parse `all-subscriptions`, `node:{id}` or `user:{id}`
+    pub fn parse(scope: &str) -> Option<Self> {
+        match scope.trim().split_once(':') {
+            None if scope.trim() == "all-subscriptions" => Some(PurgeScope::AllSubscriptions),
+            Some(("node", id)) => id.parse().ok().map(PurgeScope::Node),
+            Some(("user", id)) => id.parse().ok().map(PurgeScope::User),
+            _ => None,
+        }
+    }
+
+    /// The scope in the form `parse` accepts
+    pub fn label(&self) -> String {
+        match self {
+            PurgeScope::AllSubscriptions => "all-subscriptions".to_string(),
+            PurgeScope::Node(node_id) => format!("node:{}", node_id),
+            PurgeScope::User(user_id) => format!("user:{}", user_id),
+        }
+    }
+}
+
+/// What `DELETE /api/admin/cache/:scope` drops
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheScope {
+    /// Every cached user package
+    UserPackages,
+    /// One user's cached package
+    UserPackage(i64),
+    /// Every cached node list
+    NodeGroups,
+    /// The cached node lists of one tenant
+    TenantNodeGroups(i64),
+    /// Cached subscription configs
+    Subscriptions(PurgeScope),
+}
+
+impl CacheScope {
+    /// Parse `user-packages`, `user-package:{id}`, `node-groups`, `node-groups:{tenant_id}`
+    /// or a subscription scope (`all-subscriptions`, `node:{id}`, `user:{id}`)
+    pub fn parse(scope: &str) -> Option<Self> {
+        match scope.trim() {
+            "user-packages" => Some(CacheScope::UserPackages),
+            "node-groups" => Some(CacheScope::NodeGroups),
+            scope => match scope.split_once(':') {
+                Some(("user-package", id)) => id.parse().ok().map(CacheScope::UserPackage),
+                Some(("node-groups", id)) => id.parse().ok().map(CacheScope::TenantNodeGroups),
+                _ => PurgeScope::parse(scope).map(CacheScope::Subscriptions),
+            },
+        }
+    }
+}
+
+/// Caches whose lookups and invalidations are counted for `get_cache_stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    UserPackages,
+    Nodes,
+    Subscriptions,
+    /// Generated configs shared by subscriptions with the same inputs
+    SubscriptionBlobs,
+}
+
+impl CacheKind {
+    pub const ALL: [CacheKind; 4] = [
+        CacheKind::UserPackages,
+        CacheKind::Nodes,
+        CacheKind::Subscriptions,
+        CacheKind::SubscriptionBlobs,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CacheKind::UserPackages => "user_packages",
+            CacheKind::Nodes => "nodes",
+            CacheKind::Subscriptions => "subscriptions",
+            CacheKind::SubscriptionBlobs => "subscription_blobs",
+        }
+    }
+
+    /// Hash of the cache's `hits`, `misses` and `invalidations` counters
+    fn stats_key(self) -> String {
+        format!("cache:stats:{}", self.name())
+    }
+
+    /// List of the cache's latest invalidations, newest first
+    fn invalidations_key(self) -> String {
+        format!("cache:invalidations:{}", self.name())
+    }
+}
+
+/// Invalidations kept per cache for `get_cache_stats`
+const RECENT_INVALIDATIONS: isize = 20;
+
+/// One invalidation of a cache
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheInvalidation {
+    /// What was dropped: `user:{id}`, `tenant:{id}`, a purge scope, or `all`
+    pub target: String,
+    pub at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Counters and contents of one cache, shared by all API instances
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub cache: &'static str,
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+    /// Hits over lookups, 0 before the first lookup
+    pub hit_rate: f64,
+    /// Entries cached right now
+    pub keys: u64,
+    /// Newest first
+    pub recent_invalidations: Vec<CacheInvalidation>,
+}
+
+impl CacheStats {
+    fn from_fields(
+        kind: CacheKind,
+        fields: &HashMap<String, String>,
+        keys: u64,
+        recent_invalidations: Vec<CacheInvalidation>,
+    ) -> Self {
+        let count = |name: &str| fields.get(name).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+        let (hits, misses) = (count("hits"), count("misses"));
+
+        Self {
+            cache: kind.name(),
+            hits,
+            misses,
+            invalidations: count("invalidations"),
+            hit_rate: hit_rate(hits, misses),
+            keys,
+            recent_invalidations,
+        }
+    }
+}
+
+fn hit_rate(hits: u64, misses: u64) -> f64 {
+    let lookups = hits + misses;
+    if lookups == 0 {
+        0.0
+    } else {
+        hits as f64 / lookups as f64
+    }
+}
+
+/// Add commands counting an invalidation and recording it as recent to a pipeline
+fn track_invalidation(pipe: &mut redis::Pipeline, kind: CacheKind, target: &str) {
+    let entry = CacheInvalidation {
+        target: target.to_string(),
+        at: chrono::Utc::now(),
+    };
+    let entry = serde_json::to_string(&entry).unwrap_or_default();
+    pipe.hincr(kind.stats_key(), "invalidations", 1).ignore();
+    pipe.lpush(kind.invalidations_key(), entry).ignore();
+    pipe.ltrim(kind.invalidations_key(), 0, RECENT_INVALIDATIONS - 1).ignore();
+}
+
+/// Index set of every node group hash cached so far
+const NODE_GROUP_INDEX_ALL: &str = "nodes:groups:all";
+
+/// A set of nodes users are entitled to, identifying one cached node list
+///
+/// Today every user of a tenant sees the tenant's online nodes, so a group is just
+/// the tenant. Package-to-node entitlements would add their scope here; the hash
+/// keeps cache keys short whatever the group holds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeGroup {
+    pub tenant_id: i64,
+}
+
+impl NodeGroup {
+    pub fn for_tenant(tenant_id: i64) -> Self {
+        Self { tenant_id }
+    }
+
+    /// Stable key of the group: the first 16 hex digits of the SHA-256 of its JSON form
+    pub fn hash(&self) -> String {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        crate::crypto::sha256_hex(json.as_bytes())[..16].to_string()
+    }
+
+    /// Human-readable name shown in cache statistics
+    pub fn label(&self) -> String {
+        format!("tenant:{}", self.tenant_id)
+    }
+}
+
+/// Cache effectiveness of one node group
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeGroupStats {
+    pub hash: String,
+    pub label: String,
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+    /// Hits over lookups, 0 before the first lookup
+    pub hit_rate: f64,
+    /// Whether the group's node list is cached right now
+    pub cached: bool,
+}
 
-use crate::models::Node;
+impl NodeGroupStats {
+    fn from_fields(hash: String, fields: &HashMap<String, String>, cached: bool) -> Self {
+        let count = |name: &str| fields.get(name).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+        let (hits, misses) = (count("hits"), count("misses"));
+
+        Self {
+            label: fields.get("label").cloned().unwrap_or_else(|| hash.clone()),
+            hash,
+            hits,
+            misses,
+            invalidations: count("invalidations"),
+            hit_rate: hit_rate(hits, misses),
+            cached,
+        }
+    }
+}
 
 /// User package cache data
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,193 +374,1467 @@ pub struct UserPackageCache {
     pub traffic_quota: i64,
     pub traffic_used: i64,
     pub expires_at: String,
-    pub status: String,
+    pub status: UserPackageStatus,
+}
+
+/// Redis cache utility functions
+#[derive(Clone)]
+pub struct RedisCache {
+    conn: ConnectionManager,
+    ttls: CacheTtlConfig,
 }
 
-/// Redis cache utility functions
-#[derive(Clone)]
-pub struct RedisCache {
-    conn: ConnectionManager,
-}
+impl RedisCache {
+    /// Create a new RedisCache instance with the default TTLs
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self::with_ttls(conn, CacheTtlConfig::default())
+    }
+
+    /// Create a new RedisCache instance with configured TTLs
+    pub fn with_ttls(conn: ConnectionManager, ttls: CacheTtlConfig) -> Self {
+        Self { conn, ttls }
+    }
+
+    /// Get the Redis connection manager
+    pub fn connection(&self) -> ConnectionManager {
+        self.conn.clone()
+    }
+
+    // ========================================================================
+    // User Package Cache Operations
+    // ========================================================================
+
+    /// Cache user package data
+    /// TTL: `CacheTtlConfig::user_package_secs` (default 5 minutes)
+    pub async fn cache_user_package(
+        &self,
+        user_id: i64,
+        package_data: &UserPackageCache,
+    ) -> Result<()> {
+        let key = format!("user:package:{}", user_id);
+        let json = serde_json::to_string(package_data)
+            .context("Failed to serialize user package data")?;
+
+        let mut conn = self.conn.clone();
+        conn.set_ex(&key, json, self.ttls.user_package_secs)
+            .await
+            .context("Failed to cache user package data")?;
+
+        Ok(())
+    }
+
+    /// Get cached user package data
+    pub async fn get_user_package(&self, user_id: i64) -> Result<Option<UserPackageCache>> {
+        let key = format!("user:package:{}", user_id);
+        let mut conn = self.conn.clone();
+
+        let json: Option<String> = conn
+            .get(&key)
+            .await
+            .context("Failed to get user package from cache")?;
+        self.record_lookup(CacheKind::UserPackages, json.is_some()).await?;
+
+        match json {
+            Some(data) => {
+                let package = serde_json::from_str(&data)
+                    .context("Failed to deserialize user package data")?;
+                Ok(Some(package))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Invalidate user package cache
+    pub async fn invalidate_user_package(&self, user_id: i64) -> Result<()> {
+        let key = format!("user:package:{}", user_id);
+        let mut conn = self.conn.clone();
+
+        let mut pipe = redis::pipe();
+        pipe.del(&key).ignore();
+        track_invalidation(&mut pipe, CacheKind::UserPackages, &format!("user:{}", user_id));
+        pipe.query_async::<_, ()>(&mut conn)
+            .await
+            .context("Failed to invalidate user package cache")?;
+
+        Ok(())
+    }
+
+    /// Invalidate the cached packages of several users in one round trip
+    ///
+    /// Recorded as a single invalidation with `target` (e.g. `traffic`), so a traffic
+    /// batch doesn't push every other entry out of the recent invalidations.
+    pub async fn invalidate_user_packages(&self, user_ids: &[i64], target: &str) -> Result<()> {
+        if user_ids.is_empty() {
+            return Ok(());
+        }
+
+        let keys: Vec<String> = user_ids.iter().map(|id| format!("user:package:{}", id)).collect();
+        let mut conn = self.conn.clone();
+
+        let mut pipe = redis::pipe();
+        pipe.del(keys).ignore();
+        track_invalidation(&mut pipe, CacheKind::UserPackages, target);
+        pipe.query_async::<_, ()>(&mut conn)
+            .await
+            .context("Failed to invalidate user package cache")?;
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // Token Version Cache Operations
+    // ========================================================================
+
+    /// Cache a user's current token version
+    /// TTL: `CacheTtlConfig::user_package_secs` (default 5 minutes)
+    pub async fn cache_token_version(&self, user_id: i64, version: i32) -> Result<()> {
+        let key = format!("user:token_version:{}", user_id);
+        let mut conn = self.conn.clone();
+
+        conn.set_ex(&key, version, self.ttls.user_package_secs)
+            .await
+            .context("Failed to cache token version")?;
+
+        Ok(())
+    }
+
+    /// Get a user's cached token version
+    pub async fn get_token_version(&self, user_id: i64) -> Result<Option<i32>> {
+        let key = format!("user:token_version:{}", user_id);
+        let mut conn = self.conn.clone();
+
+        let version: Option<i32> = conn
+            .get(&key)
+            .await
+            .context("Failed to get token version from cache")?;
+
+        Ok(version)
+    }
+
+    /// Invalidate a user's cached token version (call after bumping it)
+    pub async fn invalidate_token_version(&self, user_id: i64) -> Result<()> {
+        let key = format!("user:token_version:{}", user_id);
+        let mut conn = self.conn.clone();
+
+        conn.del(&key)
+            .await
+            .context("Failed to invalidate token version cache")?;
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // Registration Rate Limiting
+    // ========================================================================
+
+    /// Count a registration attempt from `ip`; the window starts with the first attempt
+    ///
+    /// The count is taken before the account is created, so concurrent sign-ups from
+    /// one address can't all pass the cap together.
+    pub async fn record_registration(&self, ip: &str, window_secs: u64) -> Result<u32> {
+        let key = format!("register:ip:{}", ip);
+        let mut conn = self.conn.clone();
+
+        let (count, ttl): (u32, i64) = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .ttl(&key)
+            .query_async(&mut conn)
+            .await
+            .context("Failed to record registration")?;
+        if ttl < 0 {
+            conn.expire(&key, window_secs as i64)
+                .await
+                .context("Failed to set registration window")?;
+        }
+
+        Ok(count)
+    }
+
+    // ========================================================================
+    // Subscription Fetch Limiting
+    // ========================================================================
+
+    /// Count a subscription fetch from `ip`; returns the count in the current window
+    /// and the seconds left in it (the window starts with the first fetch)
+    pub async fn record_subscription_request(&self, ip: &str, window_secs: u64) -> Result<(u32, u64)> {
+        let key = format!("sub:ip:requests:{}", ip);
+        let mut conn = self.conn.clone();
+
+        let (count, ttl): (u32, i64) = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .ttl(&key)
+            .query_async(&mut conn)
+            .await
+            .context("Failed to record subscription request")?;
+        if ttl < 0 {
+            conn.expire(&key, window_secs as i64)
+                .await
+                .context("Failed to set subscription request window")?;
+            return Ok((count, window_secs));
+        }
+
+        Ok((count, ttl as u64))
+    }
+
+    /// Claim the right to record `ip` as a subscription flood offender; false if it
+    /// was recorded within `ttl_secs`
+    pub async fn claim_subscription_flood_report(&self, ip: &str, ttl_secs: u64) -> Result<bool> {
+        let key = format!("sub:ip:flood_reported:{}", ip);
+        let mut conn = self.conn.clone();
+
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl_secs.max(1))
+            .query_async(&mut conn)
+            .await
+            .context("Failed to claim subscription flood report")?;
+
+        Ok(claimed.is_some())
+    }
+
+    // ========================================================================
+    // OAuth Login State
+    // ========================================================================
+
+    /// Remember an authorization request until its callback arrives
+    pub async fn store_oauth_state(&self, state: &str, pending: &PendingLogin, ttl_secs: u64) -> Result<()> {
+        let payload = serde_json::to_string(pending).context("Failed to serialize OAuth state")?;
+        let mut conn = self.conn.clone();
+
+        conn.set_ex::<_, _, ()>(format!("oauth:state:{}", state), payload, ttl_secs)
+            .await
+            .context("Failed to store OAuth state")?;
+
+        Ok(())
+    }
+
+    /// Consume an authorization request; None if unknown, expired or already used
+    pub async fn take_oauth_state(&self, state: &str) -> Result<Option<PendingLogin>> {
+        let mut conn = self.conn.clone();
+
+        let payload: Option<String> = redis::cmd("GETDEL")
+            .arg(format!("oauth:state:{}", state))
+            .query_async(&mut conn)
+            .await
+            .context("Failed to read OAuth state")?;
+
+        Ok(payload.and_then(|p| serde_json::from_str(&p).ok()))
+    }
+
+    // ========================================================================
+    // Node Agent Request Protection
+    // ========================================================================
+
+    /// Claim a node request nonce; false if it was already used within `ttl_secs`
+    pub async fn claim_node_nonce(&self, node_id: i64, nonce: &str, ttl_secs: u64) -> Result<bool> {
+        let key = format!("node:nonce:{}:{}", node_id, nonce);
+        let mut conn = self.conn.clone();
+
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl_secs.max(1))
+            .query_async(&mut conn)
+            .await
+            .context("Failed to claim node request nonce")?;
+
+        Ok(claimed.is_some())
+    }
+
+    /// Count a failed node authentication; the window starts with the first failure
+    pub async fn record_node_auth_failure(&self, node_id: i64, window_secs: u64) -> Result<u32> {
+        let key = format!("node:auth_failures:{}", node_id);
+        let mut conn = self.conn.clone();
+
+        let count: u32 = conn
+            .incr(&key, 1)
+            .await
+            .context("Failed to record node authentication failure")?;
+        if count == 1 {
+            conn.expire(&key, window_secs as i64)
+                .await
+                .context("Failed to set node authentication failure window")?;
+        }
+
+        Ok(count)
+    }
+
+    /// Reset a node's failure count (when an admin lifts a suspension)
+    pub async fn clear_node_auth_failures(&self, node_id: i64) -> Result<()> {
+        let key = format!("node:auth_failures:{}", node_id);
+        let mut conn = self.conn.clone();
+
+        conn.del::<_, ()>(&key)
+            .await
+            .context("Failed to clear node authentication failures")?;
+
+        Ok(())
+    }
+
+    /// Count a failed login for an email; the window starts with the first failure
+    pub async fn record_login_failure(&self, email: &str, window_secs: u64) -> Result<u32> {
+        let key = format!("security:login_failures:{}", email.to_lowercase());
+        let mut conn = self.conn.clone();
+
+        let count: u32 = conn
+            .incr(&key, 1)
+            .await
+            .context("Failed to record login failure")?;
+        if count == 1 {
+            conn.expire(&key, window_secs as i64)
+                .await
+                .context("Failed to set login failure window")?;
+        }
+
+        Ok(count)
+    }
+
+    /// Reset an email's failed login count after a successful login
+    pub async fn clear_login_failures(&self, email: &str) -> Result<()> {
+        let key = format!("security:login_failures:{}", email.to_lowercase());
+        let mut conn = self.conn.clone();
+
+        conn.del::<_, ()>(&key)
+            .await
+            .context("Failed to clear login failures")?;
+
+        Ok(())
+    }
+
+    /// Claim the right to send an alert; false if one went out under `key` within `ttl_secs`
+    pub async fn claim_security_alert(&self, key: &str, ttl_secs: u64) -> Result<bool> {
+        let key = format!("security:alert_throttle:{}", key);
+        let mut conn = self.conn.clone();
+
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl_secs.max(1))
+            .query_async(&mut conn)
+            .await
+            .context("Failed to claim security alert slot")?;
+
+        Ok(claimed.is_some())
+    }
+
+    // ========================================================================
+    // Node List Cache Operations
+    // ========================================================================
+
+    /// Cache the node list of an entitlement group
+    /// TTL: `CacheTtlConfig::active_nodes_secs` (default 1 minute)
+    ///
+    /// The group key holds only node ids; each node is stored once under
+    /// `nodes:node:{id}` and shared by every group that contains it.
+    pub async fn cache_node_group(&self, group: &NodeGroup, nodes: &[Node]) -> Result<()> {
+        let hash = group.hash();
+        let ttl = self.ttls.active_nodes_secs;
+        let ids: Vec<i64> = nodes.iter().map(|node| node.id).collect();
+        let ids_json = serde_json::to_string(&ids).context("Failed to serialize node group")?;
+
+        let mut pipe = redis::pipe();
+        for node in nodes {
+            let json = serde_json::to_string(node).context("Failed to serialize node")?;
+            pipe.set_ex(format!("nodes:node:{}", node.id), json, ttl).ignore();
+        }
+        pipe.set_ex(format!("nodes:group:{}", hash), ids_json, ttl).ignore();
+        pipe.sadd(format!("nodes:groups:tenant:{}", group.tenant_id), &hash).ignore();
+        pipe.sadd(NODE_GROUP_INDEX_ALL, &hash).ignore();
+        pipe.hset(format!("nodes:group_stats:{}", hash), "label", group.label()).ignore();
+
+        let mut conn = self.conn.clone();
+        pipe.query_async::<_, ()>(&mut conn)
+            .await
+            .context("Failed to cache node group")?;
+
+        Ok(())
+    }
+
+    /// Get the cached node list of an entitlement group
+    ///
+    /// A group whose id list or any node entry has expired counts as a miss.
+    /// Hits and misses are counted for `get_node_group_stats`.
+    pub async fn get_node_group(&self, group: &NodeGroup) -> Result<Option<Vec<Node>>> {
+        let hash = group.hash();
+        let mut conn = self.conn.clone();
+
+        let ids_json: Option<String> = conn
+            .get(format!("nodes:group:{}", hash))
+            .await
+            .context("Failed to get node group from cache")?;
+
+        let nodes = match ids_json {
+            Some(data) => {
+                let ids: Vec<i64> = serde_json::from_str(&data).context("Failed to deserialize node group")?;
+                self.get_cached_nodes(&ids).await?
+            }
+            None => None,
+        };
+
+        let field = if nodes.is_some() { "hits" } else { "misses" };
+        let _: i64 = conn
+            .hincr(format!("nodes:group_stats:{}", hash), field, 1)
+            .await
+            .context("Failed to record node group cache stats")?;
+        self.record_lookup(CacheKind::Nodes, nodes.is_some()).await?;
+
+        Ok(nodes)
+    }
+
+    /// Read node entries in order; `None` if any of them has expired
+    async fn get_cached_nodes(&self, ids: &[i64]) -> Result<Option<Vec<Node>>> {
+        if ids.is_empty() {
+            return Ok(Some(Vec::new()));
+        }
+        let keys: Vec<String> = ids.iter().map(|id| format!("nodes:node:{}", id)).collect();
+        let mut conn = self.conn.clone();
+
+        let values: Vec<Option<String>> = redis::cmd("MGET")
+            .arg(&keys)
+            .query_async(&mut conn)
+            .await
+            .context("Failed to get nodes from cache")?;
+
+        values
+            .into_iter()
+            .map(|value| {
+                value
+                    .map(|data| serde_json::from_str(&data).context("Failed to deserialize node"))
+                    .transpose()
+            })
+            .collect::<Result<Option<Vec<Node>>>>()
+    }
+
+    /// Invalidate every cached node group of a tenant
+    pub async fn invalidate_node_groups(&self, tenant_id: i64) -> Result<()> {
+        self.invalidate_indexed_node_groups(
+            &format!("nodes:groups:tenant:{}", tenant_id),
+            &format!("tenant:{}", tenant_id),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Invalidate every cached node group
+    pub async fn invalidate_all_node_groups(&self) -> Result<()> {
+        self.invalidate_indexed_node_groups(NODE_GROUP_INDEX_ALL, "all").await?;
+        Ok(())
+    }
+
+    /// Drop the id lists of the groups in an index set and count the invalidation
+    /// Returns the number of cached id lists removed
+    ///
+    /// Node entries are left to expire: they are only read through a group's id list.
+    async fn invalidate_indexed_node_groups(&self, index_key: &str, target: &str) -> Result<usize> {
+        let mut conn = self.conn.clone();
+
+        let hashes: Vec<String> = conn
+            .smembers(index_key)
+            .await
+            .context("Failed to read node group index")?;
+        if hashes.is_empty() {
+            return Ok(0);
+        }
+
+        let mut pipe = redis::pipe();
+        for hash in &hashes {
+            pipe.del(format!("nodes:group:{}", hash));
+            pipe.hincr(format!("nodes:group_stats:{}", hash), "invalidations", 1).ignore();
+        }
+        track_invalidation(&mut pipe, CacheKind::Nodes, target);
+
+        let deleted: Vec<usize> = pipe
+            .query_async(&mut conn)
+            .await
+            .context("Failed to invalidate node groups")?;
+
+        Ok(deleted.into_iter().sum())
+    }
+
+    /// Hit, miss and invalidation counts of every node group cached so far
+    pub async fn get_node_group_stats(&self) -> Result<Vec<NodeGroupStats>> {
+        let mut conn = self.conn.clone();
+
+        let hashes: Vec<String> = conn
+            .smembers(NODE_GROUP_INDEX_ALL)
+            .await
+            .context("Failed to read node group index")?;
+
+        let mut stats = Vec::with_capacity(hashes.len());
+        for hash in hashes {
+            let fields: HashMap<String, String> = conn
+                .hgetall(format!("nodes:group_stats:{}", hash))
+                .await
+                .context("Failed to get node group cache stats")?;
+            let cached: bool = conn
+                .exists(format!("nodes:group:{}", hash))
+                .await
+                .context("Failed to check node group cache")?;
+            stats.push(NodeGroupStats::from_fields(hash, &fields, cached));
+        }
+
+        stats.sort_by(|a, b| a.label.cmp(&b.label));
+        Ok(stats)
+    }
+
+    // ========================================================================
+    // Public Status Page Cache Operations
+    // ========================================================================
+
+    /// Cache a tenant's rendered public status page
+    /// TTL: `CacheTtlConfig::status_page_secs` (default 30 seconds)
+    pub async fn cache_status_page(&self, tenant_id: i64, page: &StatusPage) -> Result<()> {
+        let key = format!("status:page:{}", tenant_id);
+        let json = serde_json::to_string(page).context("Failed to serialize status page")?;
+
+        let mut conn = self.conn.clone();
+        conn.set_ex(key, json, self.ttls.status_page_secs)
+            .await
+            .context("Failed to cache status page")?;
+
+        Ok(())
+    }
+
+    /// Get a tenant's cached public status page
+    pub async fn get_status_page(&self, tenant_id: i64) -> Result<Option<StatusPage>> {
+        let key = format!("status:page:{}", tenant_id);
+        let mut conn = self.conn.clone();
+
+        let json: Option<String> = conn
+            .get(key)
+            .await
+            .context("Failed to get status page from cache")?;
+
+        json.map(|data| serde_json::from_str(&data).context("Failed to deserialize status page"))
+            .transpose()
+    }
+
+    /// Drop the given tenants' cached status pages so an incident change shows up immediately
+    pub async fn invalidate_status_pages(&self, tenant_ids: &[i64]) -> Result<()> {
+        if tenant_ids.is_empty() {
+            return Ok(());
+        }
+
+        let keys: Vec<String> = tenant_ids.iter().map(|id| format!("status:page:{}", id)).collect();
+        let mut conn = self.conn.clone();
+
+        conn.del(keys)
+            .await
+            .context("Failed to invalidate status page cache")?;
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // Subscription Configuration Cache Operations
+    // ========================================================================
+
+    /// Cache subscription configuration (Clash YAML)
+    /// TTL: `CacheTtlConfig::subscription_config_secs` (default 5 minutes)
+    ///
+    /// `token` is the cache key (the subscription handler suffixes the client flavor).
+    /// The config is stored once under `subscription:blob:{fingerprint}` and shared by
+    /// every token whose config has the same inputs (see `clash::ConfigInputs`); the
+    /// token's entry holds the owner, the fingerprint, the access key hash and the owner's
+    /// GeoIP override, so a cache hit needs no database lookup. Pass the config when it was just generated, or `None` when the
+    /// blob came from `get_subscription_blob`, which only extends its TTL.
+    ///
+    /// Tokens are indexed by owner (`subscription:user:{id}`) and by every node the
+    /// config embeds (`subscription:node:{id}`), so mutations can purge exactly the
+    /// affected configs. Index sets share the config TTL, so stale members age out with it.
+    pub async fn cache_subscription_config(
+        &self,
+        token: &str,
+        fingerprint: &str,
+        config: Option<&str>,
+        user_id: i64,
+        access_key_hash: Option<&str>,
+        geo_restriction: Option<&SubscriptionGeoRestriction>,
+        node_ids: &[i64],
+    ) -> Result<()> {
+        let key = format!("subscription:{}", token);
+        let blob_key = format!("subscription:blob:{}", fingerprint);
+        let mut conn = self.conn.clone();
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        let ttl = self.ttls.subscription_config_secs;
+        // The blob is written or refreshed with the entry, so it outlives every entry naming it
+        match config {
+            Some(config) => pipe.set_ex(&blob_key, config, ttl).ignore(),
+            None => pipe.expire(&blob_key, ttl as i64).ignore(),
+        };
+        pipe.sadd(SUBSCRIPTION_BLOB_INDEX, fingerprint).ignore();
+        pipe.expire(SUBSCRIPTION_BLOB_INDEX, ttl as i64).ignore();
+        pipe.set_ex(&key, SubscriptionRef::encode(user_id, fingerprint, access_key_hash, geo_restriction), ttl).ignore();
+
+        let mut index_keys = vec![
+            SUBSCRIPTION_INDEX_ALL.to_string(),
+            format!("subscription:user:{}", user_id),
+        ];
+        index_keys.extend(node_ids.iter().map(|id| format!("subscription:node:{}", id)));
+        for index_key in &index_keys {
+            pipe.sadd(index_key, token).ignore();
+            pipe.expire(index_key, ttl as i64).ignore();
+        }
+
+        pipe.query_async::<_, ()>(&mut conn)
+            .await
+            .context("Failed to cache subscription config")?;
+
+        Ok(())
+    }
+
+    /// Get cached subscription configuration and its owner
+    ///
+    /// Entries whose shared config has expired, and entries cached before configs were
+    /// shared, count as misses.
+    pub async fn get_subscription_config(&self, token: &str) -> Result<Option<CachedSubscription>> {
+        let key = format!("subscription:{}", token);
+        let mut conn = self.conn.clone();
+
+        let value: Option<String> = conn
+            .get(&key)
+            .await
+            .context("Failed to get subscription config from cache")?;
+
+        let cached = match value.as_deref().and_then(SubscriptionRef::decode) {
+            Some(entry) => {
+                let config: Option<String> = conn
+                    .get(format!("subscription:blob:{}", entry.fingerprint))
+                    .await
+                    .context("Failed to get subscription config from cache")?;
+                config.map(|config| CachedSubscription {
+                    user_id: entry.user_id,
+                    config,
+                    fingerprint: entry.fingerprint,
+                    access_key_hash: entry.access_key_hash,
+                    geo_restriction: entry.geo_restriction,
+                })
+            }
+            None => None,
+        };
+        self.record_lookup(CacheKind::Subscriptions, cached.is_some()).await?;
+        Ok(cached)
+    }
+
+    /// Get a generated config by the fingerprint of its inputs
+    ///
+    /// Checked after a token miss, so users with the same inputs share one generation.
+    pub async fn get_subscription_blob(&self, fingerprint: &str) -> Result<Option<String>> {
+        let mut conn = self.conn.clone();
+
+        let config: Option<String> = conn
+            .get(format!("subscription:blob:{}", fingerprint))
+            .await
+            .context("Failed to get subscription config from cache")?;
+
+        self.record_lookup(CacheKind::SubscriptionBlobs, config.is_some()).await?;
+        Ok(config)
+    }
+
+    /// Cache a compressed copy of a generated config, keyed by its fingerprint and encoding
+    /// TTL: `CacheTtlConfig::subscription_config_secs`
+    ///
+    /// The config's inputs determine its fingerprint, so a copy never goes stale; it is
+    /// simply compressed again once it expires.
+    pub async fn cache_subscription_variant(&self, fingerprint: &str, encoding: Encoding, body: &[u8]) -> Result<()> {
+        let mut conn = self.conn.clone();
+
+        conn.set_ex(subscription_variant_key(fingerprint, encoding), body, self.ttls.subscription_config_secs)
+            .await
+            .context("Failed to cache compressed subscription config")?;
+
+        Ok(())
+    }
+
+    /// Get a compressed copy of a generated config
+    pub async fn get_subscription_variant(&self, fingerprint: &str, encoding: Encoding) -> Result<Option<Vec<u8>>> {
+        let mut conn = self.conn.clone();
+
+        let body: Option<Vec<u8>> = conn
+            .get(subscription_variant_key(fingerprint, encoding))
+            .await
+            .context("Failed to get compressed subscription config from cache")?;
+
+        Ok(body)
+    }
+
+    /// Keep a token's config as its outage backup
+    /// TTL: `CacheTtlConfig::subscription_stale_secs` (default 1 hour; 0 keeps none)
+    ///
+    /// Indexed by owner (`subscription:stale:user:{id}`), so purging a user's configs
+    /// also drops the backups of a disabled account or a reset token.
+    pub async fn cache_stale_subscription(&self, token: &str, stale: &StaleSubscription) -> Result<()> {
+        let ttl = self.ttls.subscription_stale_secs;
+        if ttl == 0 {
+            return Ok(());
+        }
+        let json = serde_json::to_string(stale).context("Failed to serialize stale subscription")?;
+        let index_key = format!("subscription:stale:user:{}", stale.user_id);
+        let mut conn = self.conn.clone();
+
+        redis::pipe()
+            .atomic()
+            .set_ex(stale_subscription_key(token), json, ttl)
+            .ignore()
+            .sadd(&index_key, token)
+            .ignore()
+            .expire(&index_key, ttl as i64)
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await
+            .context("Failed to cache stale subscription")?;
+
+        Ok(())
+    }
+
+    /// Get a token's outage backup, if one was kept within the stale TTL
+    pub async fn get_stale_subscription(&self, token: &str) -> Result<Option<StaleSubscription>> {
+        let mut conn = self.conn.clone();
+
+        let json: Option<String> = conn
+            .get(stale_subscription_key(token))
+            .await
+            .context("Failed to get stale subscription from cache")?;
+
+        match json {
+            Some(data) => Ok(Some(
+                serde_json::from_str(&data).context("Failed to deserialize stale subscription")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Invalidate subscription configuration cache
+    pub async fn invalidate_subscription_config(&self, token: &str) -> Result<()> {
+        let key = format!("subscription:{}", token);
+        let mut conn = self.conn.clone();
+
+        // The token is a credential, so the invalidation log doesn't name it
+        let mut pipe = redis::pipe();
+        pipe.del(&key).ignore();
+        track_invalidation(&mut pipe, CacheKind::Subscriptions, "token");
+        pipe.query_async::<_, ()>(&mut conn)
+            .await
+            .context("Failed to invalidate subscription config cache")?;
+
+        Ok(())
+    }
+
+    /// Purge cached subscription configs matching a scope
+    /// Returns the number of cached configs removed
+    pub async fn purge_subscription_configs(&self, scope: PurgeScope) -> Result<usize> {
+        let index_key = match scope {
+            PurgeScope::AllSubscriptions => SUBSCRIPTION_INDEX_ALL.to_string(),
+            PurgeScope::Node(node_id) => format!("subscription:node:{}", node_id),
+            PurgeScope::User(user_id) => format!("subscription:user:{}", user_id),
+        };
+        let mut conn = self.conn.clone();
+
+        let tokens: Vec<String> = conn
+            .smembers(&index_key)
+            .await
+            .context("Failed to read subscription cache index")?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for token in &tokens {
+            pipe.del(format!("subscription:{}", token));
+        }
+        pipe.del(&index_key).ignore();
+        track_invalidation(&mut pipe, CacheKind::Subscriptions, &scope.label());
+
+        // A user's backups go with their configs; node and full purges keep them, as an
+        // outdated config still beats none during an outage
+        if let PurgeScope::User(user_id) = scope {
+            let stale_index = format!("subscription:stale:user:{}", user_id);
+            let stale_tokens: Vec<String> = conn
+                .smembers(&stale_index)
+                .await
+                .context("Failed to read subscription cache index")?;
+            for token in &stale_tokens {
+                pipe.del(stale_subscription_key(token)).ignore();
+            }
+            pipe.del(&stale_index).ignore();
+        }
+
+        // Shared configs are keyed by their inputs, so a changed node or user gets a new
+        // fingerprint and narrower purges leave them to expire; a full purge drops them too
+        if scope == PurgeScope::AllSubscriptions {
+            let fingerprints: Vec<String> = conn
+                .smembers(SUBSCRIPTION_BLOB_INDEX)
+                .await
+                .context("Failed to read subscription cache index")?;
+            for fingerprint in &fingerprints {
+                pipe.del(format!("subscription:blob:{}", fingerprint)).ignore();
+                for encoding in Encoding::ALL {
+                    pipe.del(subscription_variant_key(fingerprint, encoding)).ignore();
+                }
+            }
+            pipe.del(SUBSCRIPTION_BLOB_INDEX).ignore();
+            track_invalidation(&mut pipe, CacheKind::SubscriptionBlobs, "all");
+        }
+
+        let deleted: Vec<usize> = pipe
+            .query_async(&mut conn)
+            .await
+            .context("Failed to purge subscription configs")?;
+
+        Ok(deleted.into_iter().sum())
+    }
+
+    // ========================================================================
+    // Cache Statistics and Targeted Purges
+    // ========================================================================
+
+    /// Count a hit or miss of one of the counted caches
+    async fn record_lookup(&self, kind: CacheKind, hit: bool) -> Result<()> {
+        let field = if hit { "hits" } else { "misses" };
+        let mut conn = self.conn.clone();
+        let _: i64 = conn
+            .hincr(kind.stats_key(), field, 1)
+            .await
+            .context("Failed to record cache stats")?;
+        Ok(())
+    }
+
+    /// Hit, miss and invalidation counts, entry counts and latest invalidations of
+    /// the user package, node list, subscription config and shared config caches
+    pub async fn get_cache_stats(&self) -> Result<Vec<CacheStats>> {
+        let mut conn = self.conn.clone();
+
+        let mut stats = Vec::with_capacity(CacheKind::ALL.len());
+        for kind in CacheKind::ALL {
+            let fields: HashMap<String, String> = conn
+                .hgetall(kind.stats_key())
+                .await
+                .context("Failed to get cache stats")?;
+            let entries: Vec<String> = conn
+                .lrange(kind.invalidations_key(), 0, RECENT_INVALIDATIONS - 1)
+                .await
+                .context("Failed to get recent cache invalidations")?;
+            let recent = entries
+                .iter()
+                .filter_map(|entry| serde_json::from_str(entry).ok())
+                .collect();
+            let keys = self.count_cached_entries(kind).await?;
+            stats.push(CacheStats::from_fields(kind, &fields, keys, recent));
+        }
+
+        Ok(stats)
+    }
+
+    /// Entries of a cache that are cached right now
+    async fn count_cached_entries(&self, kind: CacheKind) -> Result<u64> {
+        match kind {
+            CacheKind::UserPackages => Ok(self.scan_keys("user:package:*").await?.len() as u64),
+            CacheKind::Nodes => Ok(self.scan_keys("nodes:group:*").await?.len() as u64),
+            // Config keys share their prefix with the index and log keys, so count the
+            // indexed tokens whose config is still there
+            CacheKind::Subscriptions => {
+                let mut conn = self.conn.clone();
+                let tokens: Vec<String> = conn
+                    .smembers(SUBSCRIPTION_INDEX_ALL)
+                    .await
+                    .context("Failed to read subscription cache index")?;
+                if tokens.is_empty() {
+                    return Ok(0);
+                }
+
+                let mut pipe = redis::pipe();
+                for token in &tokens {
+                    pipe.exists(format!("subscription:{}", token));
+                }
+                let cached: Vec<bool> = pipe
+                    .query_async(&mut conn)
+                    .await
+                    .context("Failed to count cached subscription configs")?;
+                Ok(cached.into_iter().filter(|cached| *cached).count() as u64)
+            }
+            CacheKind::SubscriptionBlobs => Ok(self.scan_keys("subscription:blob:*").await?.len() as u64),
+        }
+    }
+
+    /// Every key matching a pattern, read with `SCAN` so Redis is never blocked
+    async fn scan_keys(&self, pattern: &str) -> Result<Vec<String>> {
+        let mut conn = self.conn.clone();
+        let mut iter: redis::AsyncIter<String> = conn
+            .scan_match(pattern)
+            .await
+            .context("Failed to scan cache keys")?;
+
+        let mut keys = Vec::new();
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        Ok(keys)
+    }
+
+    /// Drop the cached entries in a scope and record the invalidation
+    /// Returns the number of cache entries removed
+    pub async fn purge_cache(&self, scope: CacheScope) -> Result<usize> {
+        match scope {
+            CacheScope::UserPackages => {
+                let keys = self.scan_keys("user:package:*").await?;
+                let mut conn = self.conn.clone();
+                let mut deleted = 0;
+                for chunk in keys.chunks(500) {
+                    let removed: usize = conn
+                        .del(chunk)
+                        .await
+                        .context("Failed to purge user package cache")?;
+                    deleted += removed;
+                }
+
+                let mut pipe = redis::pipe();
+                track_invalidation(&mut pipe, CacheKind::UserPackages, "all");
+                pipe.query_async::<_, ()>(&mut conn)
+                    .await
+                    .context("Failed to record cache invalidation")?;
+                Ok(deleted)
+            }
+            CacheScope::UserPackage(user_id) => {
+                let existed = self.exists(&format!("user:package:{}", user_id)).await?;
+                self.invalidate_user_package(user_id).await?;
+                Ok(usize::from(existed))
+            }
+            CacheScope::NodeGroups => self.invalidate_indexed_node_groups(NODE_GROUP_INDEX_ALL, "all").await,
+            CacheScope::TenantNodeGroups(tenant_id) => {
+                self.invalidate_indexed_node_groups(
+                    &format!("nodes:groups:tenant:{}", tenant_id),
+                    &format!("tenant:{}", tenant_id),
+                )
+                .await
+            }
+            CacheScope::Subscriptions(scope) => self.purge_subscription_configs(scope).await,
+        }
+    }
+
+    // ========================================================================
+    // Node Configuration Update Notification (Redis Pub/Sub)
+    // ========================================================================
+
+    /// Publish node configuration update notification
+    pub async fn publish_node_config_update(&self, node_id: i64) -> Result<()> {
+        // Each agent subscribes to its own channel
+        let channel = format!("node:config:update:{}", node_id);
+        let message = serde_json::json!({
+            "node_id": node_id,
+            "action": "reload",
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        });
+
+        let mut conn = self.conn.clone();
+        let receivers: i64 = conn
+            .publish(&channel, message.to_string())
+            .await
+            .context("Failed to publish node config update")?;
+        // Pub/sub is fire-and-forget; an agent that wasn't listening notices from its next heartbeat
+        if receivers == 0 {
+            tracing::debug!("No agent subscribed to {}, node {} will catch up on its next heartbeat", channel, node_id);
+        }
+
+        Ok(())
+    }
+
+    /// Publish an admin alert notification (e.g. node threshold breach)
+    pub async fn publish_admin_alert<T: Serialize>(&self, alert: &T) -> Result<()> {
+        let payload = serde_json::to_string(alert)
+            .context("Failed to serialize admin alert")?;
+
+        let mut conn = self.conn.clone();
+        conn.publish("admin:alerts", payload)
+            .await
+            .context("Failed to publish admin alert")?;
+
+        Ok(())
+    }
+
+    /// Publish an order status change for webhook and notification consumers
+    pub async fn publish_order_event<T: Serialize>(&self, event: &T) -> Result<()> {
+        let payload = serde_json::to_string(event)
+            .context("Failed to serialize order event")?;
+
+        let mut conn = self.conn.clone();
+        conn.publish("orders:events", payload)
+            .await
+            .context("Failed to publish order event")?;
+
+        Ok(())
+    }
+
+    /// Publish a top-up request submission or review for notification consumers
+    pub async fn publish_topup_event<T: Serialize>(&self, event: &T) -> Result<()> {
+        let payload = serde_json::to_string(event)
+            .context("Failed to serialize top-up event")?;
+
+        let mut conn = self.conn.clone();
+        conn.publish("topups:events", payload)
+            .await
+            .context("Failed to publish top-up event")?;
+
+        Ok(())
+    }
+
+    /// Publish a balance adjustment request or review for notification consumers
+    pub async fn publish_adjustment_event<T: Serialize>(&self, event: &T) -> Result<()> {
+        let payload = serde_json::to_string(event)
+            .context("Failed to serialize balance adjustment event")?;
+
+        let mut conn = self.conn.clone();
+        conn.publish("adjustments:events", payload)
+            .await
+            .context("Failed to publish balance adjustment event")?;
+
+        Ok(())
+    }
+
+    /// Publish a node maintenance window change for notification consumers
+    pub async fn publish_maintenance_event<T: Serialize>(&self, event: &T) -> Result<()> {
+        let payload = serde_json::to_string(event)
+            .context("Failed to serialize maintenance event")?;
+
+        let mut conn = self.conn.clone();
+        conn.publish("nodes:maintenance", payload)
+            .await
+            .context("Failed to publish maintenance event")?;
+
+        Ok(())
+    }
+
+    /// Publish a credential rotation for notification consumers
+    pub async fn publish_credential_rotation<T: Serialize>(&self, event: &T) -> Result<()> {
+        let payload = serde_json::to_string(event)
+            .context("Failed to serialize credential rotation event")?;
+
+        let mut conn = self.conn.clone();
+        conn.publish("credentials:rotated", payload)
+            .await
+            .context("Failed to publish credential rotation event")?;
+
+        Ok(())
+    }
+
+    /// Publish a security event for notification consumers
+    pub async fn publish_security_event<T: Serialize>(&self, event: &T) -> Result<()> {
+        let payload = serde_json::to_string(event)
+            .context("Failed to serialize security event")?;
 
-impl RedisCache {
-    /// Create a new RedisCache instance
-    pub fn new(conn: ConnectionManager) -> Self {
-        Self { conn }
+        let mut conn = self.conn.clone();
+        conn.publish("security:events", payload)
+            .await
+            .context("Failed to publish security event")?;
+
+        Ok(())
     }
 
-    /// Get the Redis connection manager
-    pub fn connection(&self) -> ConnectionManager {
-        self.conn.clone()
+    /// Publish an upgrade suggestion for notification consumers
+    pub async fn publish_upgrade_suggestion<T: Serialize>(&self, event: &T) -> Result<()> {
+        let payload = serde_json::to_string(event)
+            .context("Failed to serialize upgrade suggestion")?;
+
+        let mut conn = self.conn.clone();
+        conn.publish("users:suggestions", payload)
+            .await
+            .context("Failed to publish upgrade suggestion")?;
+
+        Ok(())
     }
 
     // ========================================================================
-    // User Package Cache Operations
+    // Per-User Connection Tracking (concurrent device limits)
     // ========================================================================
 
-    /// Cache user package data
-    /// TTL: 300 seconds (5 minutes)
-    pub async fn cache_user_package(
+    /// Replace a node's per-user connection counts from its latest heartbeat
+    ///
+    /// Keeps two hashes in sync: `connections:node:{node_id}` (user -> count) and
+    /// `connections:user:{user_id}` (node -> count). Users missing from the new report
+    /// are removed from the node. Both expire after `ttl_seconds` so a node that stops
+    /// reporting drops out of the totals.
+    pub async fn record_node_connections(
         &self,
-        user_id: i64,
-        package_data: &UserPackageCache,
+        node_id: i64,
+        counts: &HashMap<i64, i32>,
+        ttl_seconds: u64,
     ) -> Result<()> {
-        let key = format!("user:package:{}", user_id);
-        let json = serde_json::to_string(package_data)
-            .context("Failed to serialize user package data")?;
-
+        let node_key = format!("connections:node:{}", node_id);
         let mut conn = self.conn.clone();
-        conn.set_ex(&key, json, 300)
+
+        let previous: Vec<i64> = conn
+            .hkeys(&node_key)
             .await
-            .context("Failed to cache user package data")?;
+            .context("Failed to read node connections")?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+
+        for user_id in previous.iter().filter(|id| !counts.contains_key(id)) {
+            pipe.hdel(format!("connections:user:{}", user_id), node_id).ignore();
+        }
+
+        pipe.del(&node_key).ignore();
+        for (user_id, count) in counts.iter().filter(|(_, count)| **count > 0) {
+            let user_key = format!("connections:user:{}", user_id);
+            pipe.hset(&node_key, user_id, count).ignore();
+            pipe.hset(&user_key, node_id, count).ignore();
+            pipe.expire(&user_key, ttl_seconds as i64).ignore();
+        }
+        pipe.expire(&node_key, ttl_seconds as i64).ignore();
+
+        pipe.query_async::<_, ()>(&mut conn)
+            .await
+            .context("Failed to record node connections")?;
 
         Ok(())
     }
 
-    /// Get cached user package data
-    pub async fn get_user_package(&self, user_id: i64) -> Result<Option<UserPackageCache>> {
-        let key = format!("user:package:{}", user_id);
+    /// Get a user's current connection count per node
+    ///
+    /// Entries from nodes whose report has expired are skipped.
+    pub async fn get_user_connections(&self, user_id: i64) -> Result<HashMap<i64, i32>> {
+        let user_key = format!("connections:user:{}", user_id);
         let mut conn = self.conn.clone();
 
-        let json: Option<String> = conn
-            .get(&key)
+        let by_node: HashMap<i64, i32> = conn
+            .hgetall(&user_key)
             .await
-            .context("Failed to get user package from cache")?;
-
-        match json {
-            Some(data) => {
-                let package = serde_json::from_str(&data)
-                    .context("Failed to deserialize user package data")?;
-                Ok(Some(package))
+            .context("Failed to get user connections")?;
+
+        let mut live = HashMap::with_capacity(by_node.len());
+        for (node_id, count) in by_node {
+            let reporting: bool = conn
+                .exists(format!("connections:node:{}", node_id))
+                .await
+                .context("Failed to check node connections")?;
+            if reporting {
+                live.insert(node_id, count);
             }
-            None => Ok(None),
         }
+
+        Ok(live)
     }
 
-    /// Invalidate user package cache
-    pub async fn invalidate_user_package(&self, user_id: i64) -> Result<()> {
-        let key = format!("user:package:{}", user_id);
+    /// Record when users were last online on a node
+    ///
+    /// `sessions:user:{user_id}` maps node -> [`UserNodeSeen`] (JSON). Unlike the
+    /// connection counts it outlives the node's report, so it still says where the
+    /// user was last seen after they go offline; it expires `ttl_seconds` after the
+    /// user was last seen anywhere.
+    pub async fn record_user_seen(
+        &self,
+        node_id: i64,
+        seen: &HashMap<i64, UserNodeSeen>,
+        ttl_seconds: u64,
+    ) -> Result<()> {
+        if seen.is_empty() {
+            return Ok(());
+        }
+
         let mut conn = self.conn.clone();
+        let mut pipe = redis::pipe();
+        for (user_id, entry) in seen {
+            let key = format!("sessions:user:{}", user_id);
+            let json = serde_json::to_string(entry).context("Failed to serialize user session")?;
+            pipe.hset(&key, node_id, json).ignore();
+            pipe.expire(&key, ttl_seconds as i64).ignore();
+        }
 
-        conn.del(&key)
+        pipe.query_async::<_, ()>(&mut conn)
             .await
-            .context("Failed to invalidate user package cache")?;
+            .context("Failed to record user sessions")?;
 
         Ok(())
     }
 
+    /// Get when a user was last online on each node
+    pub async fn get_user_seen(&self, user_id: i64) -> Result<HashMap<i64, UserNodeSeen>> {
+        let key = format!("sessions:user:{}", user_id);
+        let mut conn = self.conn.clone();
+
+        let by_node: HashMap<i64, String> = conn
+            .hgetall(&key)
+            .await
+            .context("Failed to get user sessions")?;
+
+        // Entries that fail to parse are skipped rather than failing the lookup
+        Ok(by_node
+            .into_iter()
+            .filter_map(|(node_id, json)| Some((node_id, serde_json::from_str(&json).ok()?)))
+            .collect())
+    }
+
     // ========================================================================
-    // Node List Cache Operations
+    // Subscription Fetch Counts (access-log sampling)
     // ========================================================================
 
-    /// Cache active nodes list
-    /// TTL: 60 seconds (1 minute)
-    pub async fn cache_active_nodes(&self, nodes: &[Node]) -> Result<()> {
-        let key = "nodes:active";
-        let json = serde_json::to_string(nodes)
-            .context("Failed to serialize nodes list")?;
-
+    /// Count a subscription fetch in the day's per-user tally
+    ///
+    /// `subscription:fetches:{YYYY-MM-DD}` is a sorted set (user -> fetches) so the
+    /// heaviest fetchers come straight out of `ZREVRANGE`. Returns the user's new count.
+    pub async fn record_subscription_fetch(&self, day: &str, user_id: i64, retention_secs: u64) -> Result<i64> {
+        let key = format!("subscription:fetches:{}", day);
         let mut conn = self.conn.clone();
-        conn.set_ex(key, json, 60)
+
+        let (count, _): (f64, ()) = redis::pipe()
+            .zincr(&key, user_id, 1)
+            .expire(&key, retention_secs as i64)
+            .query_async(&mut conn)
             .await
-            .context("Failed to cache active nodes")?;
+            .context("Failed to record subscription fetch")?;
 
-        Ok(())
+        Ok(count as i64)
     }
 
-    /// Get cached active nodes list
-    pub async fn get_active_nodes(&self) -> Result<Option<Vec<Node>>> {
-        let key = "nodes:active";
+    /// Users with the most subscription fetches on a day, as (user_id, fetches)
+    pub async fn get_top_subscription_fetches(&self, day: &str, limit: usize) -> Result<Vec<(i64, i64)>> {
+        let key = format!("subscription:fetches:{}", day);
         let mut conn = self.conn.clone();
 
-        let json: Option<String> = conn
-            .get(key)
+        let top: Vec<(i64, f64)> = conn
+            .zrevrange_withscores(&key, 0, limit.max(1) as isize - 1)
             .await
-            .context("Failed to get active nodes from cache")?;
+            .context("Failed to get subscription fetch counts")?;
 
-        match json {
-            Some(data) => {
-                let nodes = serde_json::from_str(&data)
-                    .context("Failed to deserialize nodes list")?;
-                Ok(Some(nodes))
-            }
-            None => Ok(None),
-        }
+        Ok(top.into_iter().map(|(user_id, count)| (user_id, count as i64)).collect())
     }
 
-    /// Invalidate active nodes cache
-    pub async fn invalidate_active_nodes(&self) -> Result<()> {
-        let key = "nodes:active";
+    /// Number of users that fetched their subscription on a day
+    pub async fn count_subscription_fetchers(&self, day: &str) -> Result<u64> {
+        let key = format!("subscription:fetches:{}", day);
         let mut conn = self.conn.clone();
 
-        conn.del(key)
+        conn.zcard(&key)
+            .await
+            .context("Failed to count subscription fetchers")
+    }
+
+    /// Queue an access log entry that could not be written, dropping the oldest beyond `max_queued`
+    pub async fn queue_access_log(&self, entry: &AccessLogEntry, max_queued: usize) -> Result<()> {
+        let json = serde_json::to_string(entry).context("Failed to serialize access log entry")?;
+        let mut conn = self.conn.clone();
+
+        redis::pipe()
+            .lpush(ACCESS_LOG_QUEUE, json)
+            .ignore()
+            .ltrim(ACCESS_LOG_QUEUE, 0, max_queued.max(1) as isize - 1)
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
             .await
-            .context("Failed to invalidate active nodes cache")?;
+            .context("Failed to queue access log entry")?;
 
         Ok(())
     }
 
-    // ========================================================================
-    // Subscription Configuration Cache Operations
-    // ========================================================================
+    /// Take up to `count` queued access log entries, oldest first
+    ///
+    /// Entries that no longer parse are dropped.
+    pub async fn take_queued_access_logs(&self, count: usize) -> Result<Vec<AccessLogEntry>> {
+        let Some(count) = std::num::NonZeroUsize::new(count) else {
+            return Ok(Vec::new());
+        };
+        let mut conn = self.conn.clone();
 
-    /// Cache subscription configuration (Clash YAML)
-    /// TTL: 300 seconds (5 minutes)
-    pub async fn cache_subscription_config(&self, token: &str, config: &str) -> Result<()> {
-        let key = format!("subscription:{}", token);
+        let entries: Vec<String> = conn
+            .rpop(ACCESS_LOG_QUEUE, Some(count))
+            .await
+            .context("Failed to take queued access logs")?;
+
+        Ok(entries
+            .iter()
+            .filter_map(|entry| serde_json::from_str(entry).ok())
+            .collect())
+    }
+
+    /// Put entries taken with `take_queued_access_logs` back, to be taken first next time
+    pub async fn requeue_access_logs(&self, entries: &[AccessLogEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        // Pushed newest first, so the oldest ends up back at the tail
+        let json = entries
+            .iter()
+            .rev()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to serialize access log entry")?;
         let mut conn = self.conn.clone();
 
-        conn.set_ex(&key, config, 300)
+        let _: i64 = conn
+            .rpush(ACCESS_LOG_QUEUE, json)
             .await
-            .context("Failed to cache subscription config")?;
+            .context("Failed to requeue access logs")?;
 
         Ok(())
     }
 
-    /// Get cached subscription configuration
-    pub async fn get_subscription_config(&self, token: &str) -> Result<Option<String>> {
-        let key = format!("subscription:{}", token);
+    /// Claim a user's logged success for the current window; false if one was already logged
+    pub async fn claim_access_log_success(&self, user_id: i64, window_secs: u64) -> Result<bool> {
+        let key = format!("subscription:logged_success:{}", user_id);
         let mut conn = self.conn.clone();
 
-        let config: Option<String> = conn
-            .get(&key)
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(window_secs.max(1))
+            .query_async(&mut conn)
             .await
-            .context("Failed to get subscription config from cache")?;
+            .context("Failed to claim access log slot")?;
 
-        Ok(config)
+        Ok(claimed.is_some())
     }
 
-    /// Invalidate subscription configuration cache
-    pub async fn invalidate_subscription_config(&self, token: &str) -> Result<()> {
-        let key = format!("subscription:{}", token);
+    /// Claim the right to notify a user about an upgrade suggestion; false if they were notified recently
+    pub async fn claim_upgrade_suggestion_notice(&self, user_id: i64, ttl_secs: u64) -> Result<bool> {
+        let key = format!("suggestions:upgrade:notified:{}", user_id);
         let mut conn = self.conn.clone();
 
-        conn.del(&key)
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl_secs.max(1))
+            .query_async(&mut conn)
             .await
-            .context("Failed to invalidate subscription config cache")?;
+            .context("Failed to claim upgrade suggestion notice")?;
 
-        Ok(())
+        Ok(claimed.is_some())
     }
 
     // ========================================================================
-    // Node Configuration Update Notification (Redis Pub/Sub)
+    // Distributed Locks (leader election for background jobs)
     // ========================================================================
 
-    /// Publish node configuration update notification
-    pub async fn publish_node_config_update(&self, node_id: i64) -> Result<()> {
-        let channel = "node:config:update";
-        let message = serde_json::json!({
-            "node_id": node_id,
-            "action": "reload",
-            "timestamp": chrono::Utc::now().to_rfc3339(),
-        });
+    /// Try to acquire a named lock for `ttl_seconds`
+    /// Returns false if another owner currently holds the lock
+    pub async fn try_acquire_lock(&self, name: &str, owner: &str, ttl_seconds: u64) -> Result<bool> {
+        let key = format!("lock:{}", name);
+        let mut conn = self.conn.clone();
+
+        let result: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(owner)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl_seconds)
+            .query_async(&mut conn)
+            .await
+            .context("Failed to acquire lock")?;
+
+        Ok(result.is_some())
+    }
 
+    /// Extend the lease of a lock held by `owner`
+    /// Returns false if the lock expired or was taken over by another owner
+    pub async fn renew_lock(&self, name: &str, owner: &str, ttl_seconds: u64) -> Result<bool> {
+        let key = format!("lock:{}", name);
         let mut conn = self.conn.clone();
-        conn.publish(channel, message.to_string())
+
+        let renewed: i32 = redis::Script::new(RENEW_LOCK_SCRIPT)
+            .key(&key)
+            .arg(owner)
+            .arg(ttl_seconds)
+            .invoke_async(&mut conn)
             .await
-            .context("Failed to publish node config update")?;
+            .context("Failed to renew lock")?;
 
-        Ok(())
+        Ok(renewed == 1)
+    }
+
+    /// Release a lock held by `owner`
+    pub async fn release_lock(&self, name: &str, owner: &str) -> Result<bool> {
+        let key = format!("lock:{}", name);
+        let mut conn = self.conn.clone();
+
+        let released: i32 = redis::Script::new(RELEASE_LOCK_SCRIPT)
+            .key(&key)
+            .arg(owner)
+            .invoke_async(&mut conn)
+            .await
+            .context("Failed to release lock")?;
+
+        Ok(released == 1)
+    }
+
+    /// Acquire the named lock, or extend it if this instance already holds it
+    pub async fn acquire_or_renew_lock(&self, name: &str, owner: &str, ttl_seconds: u64) -> Result<bool> {
+        if self.renew_lock(name, owner, ttl_seconds).await? {
+            return Ok(true);
+        }
+
+        self.try_acquire_lock(name, owner, ttl_seconds).await
+    }
+
+    /// Run a job only if this instance wins the named lock, releasing it afterwards
+    ///
+    /// Returns `None` when another instance holds the lock.
+    pub async fn run_exclusive<F, Fut, T>(
+        &self,
+        job_name: &str,
+        lease_seconds: u64,
+        job: F,
+    ) -> Result<Option<T>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let owner = instance_id();
+
+        if !self.try_acquire_lock(job_name, owner, lease_seconds).await? {
+            tracing::debug!("Job {} is running on another instance, skipping", job_name);
+            return Ok(None);
+        }
+
+        let output = self.drive_with_lease(job_name, owner, lease_seconds, job()).await;
+
+        if let Err(e) = self.release_lock(job_name, owner).await {
+            tracing::warn!("Failed to release lock for job {}: {}", job_name, e);
+        }
+
+        Ok(Some(output))
+    }
+
+    /// Poll a future while renewing the lock lease every third of `lease_seconds`
+    async fn drive_with_lease<Fut, T>(
+        &self,
+        name: &str,
+        owner: &str,
+        lease_seconds: u64,
+        fut: Fut,
+    ) -> T
+    where
+        Fut: Future<Output = T>,
+    {
+        let mut renew_ticker =
+            tokio::time::interval(Duration::from_secs((lease_seconds / 3).max(1)));
+        renew_ticker.tick().await;
+
+        tokio::pin!(fut);
+
+        loop {
+            tokio::select! {
+                output = &mut fut => return output,
+                _ = renew_ticker.tick() => {
+                    match self.renew_lock(name, owner, lease_seconds).await {
+                        Ok(true) => {}
+                        Ok(false) => tracing::warn!("Lost lock {} while running", name),
+                        Err(e) => tracing::warn!("Failed to renew lock {}: {}", name, e),
+                    }
+                }
+            }
+        }
     }
 
     // ========================================================================
@@ -257,6 +1894,45 @@ impl RedisCache {
     }
 }
 
+/// Spawn a periodic background job that runs on exactly one API replica
+///
+/// Each replica ticks every `period`, but only the current lease holder runs the job.
+/// The holder keeps its lease across ticks (lease = 2 x period + 30s), so leadership is
+/// stable; if the holder dies, another replica takes over once the lease expires.
+pub fn spawn_periodic_job<F, Fut>(
+    cache: RedisCache,
+    job_name: &'static str,
+    period: Duration,
+    mut job: F,
+) -> tokio::task::JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let lease_seconds = period.as_secs() * 2 + 30;
+
+    tokio::spawn(async move {
+        let owner = instance_id();
+        let mut ticker = tokio::time::interval(period);
+
+        loop {
+            ticker.tick().await;
+
+            match cache.acquire_or_renew_lock(job_name, owner, lease_seconds).await {
+                Ok(true) => {
+                    cache.drive_with_lease(job_name, owner, lease_seconds, job()).await;
+                }
+                Ok(false) => {
+                    tracing::debug!("Job {} is led by another instance", job_name);
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to check leadership for job {}: {}", job_name, e);
+                }
+            }
+        }
+    })
+}
+
 /// Create a Redis connection manager
 pub async fn create_redis_connection(redis_url: &str) -> Result<ConnectionManager> {
     let client = redis::Client::open(redis_url)
@@ -372,7 +2048,7 @@ mod tests {
             traffic_quota: 10737418240,
             traffic_used: 1073741824,
             expires_at: chrono::Utc::now().to_rfc3339(),
-            status: "active".to_string(),
+            status: UserPackageStatus::Active,
         };
 
         // Cache the data
@@ -412,7 +2088,7 @@ mod tests {
             traffic_quota: 5368709120,
             traffic_used: 0,
             expires_at: chrono::Utc::now().to_rfc3339(),
-            status: "active".to_string(),
+            status: UserPackageStatus::Active,
         };
 
         cache.cache_user_package(user_id, &package_data).await.unwrap();
@@ -435,36 +2111,30 @@ mod tests {
 
     #[tokio::test]
     #[ignore] // Requires Redis to be running
-    async fn test_active_nodes_cache_hit() {
+    async fn test_node_group_cache_hit() {
         let cache = create_test_redis().await.unwrap();
 
         let nodes = vec![
             Node {
-                id: 1,
                 name: "Test Node 1".to_string(),
                 host: "node1.example.com".to_string(),
-                port: 443,
                 protocol: "vless".to_string(),
                 secret: "secret1".to_string(),
                 config: serde_json::json!({"test": "config1"}),
-                status: "online".to_string(),
-                max_users: 1000,
                 current_users: 50,
-                total_upload: 0,
-                total_download: 0,
                 last_heartbeat: Some(chrono::Utc::now()),
-                created_at: chrono::Utc::now(),
-                updated_at: chrono::Utc::now(),
                 include_in_clash: false,
-                sort_order: 0,
+                ..Node::test_fixture()
             },
         ];
 
+        let group = NodeGroup::for_tenant(1);
+
         // Cache the nodes
-        cache.cache_active_nodes(&nodes).await.unwrap();
+        cache.cache_node_group(&group, &nodes).await.unwrap();
 
         // Retrieve from cache (should hit)
-        let cached = cache.get_active_nodes().await.unwrap();
+        let cached = cache.get_node_group(&group).await.unwrap();
 
         assert!(cached.is_some());
         let cached = cached.unwrap();
@@ -472,25 +2142,25 @@ mod tests {
         assert_eq!(cached[0].name, "Test Node 1");
 
         // Cleanup
-        cache.invalidate_active_nodes().await.unwrap();
+        cache.invalidate_node_groups(1).await.unwrap();
     }
 
     #[tokio::test]
     #[ignore] // Requires Redis to be running
-    async fn test_active_nodes_cache_miss() {
+    async fn test_node_group_cache_miss() {
         let cache = create_test_redis().await.unwrap();
 
         // Ensure cache is empty
-        let _ = cache.invalidate_active_nodes().await;
+        let _ = cache.invalidate_node_groups(2).await;
 
         // Try to get non-existent cache entry
-        let cached = cache.get_active_nodes().await.unwrap();
+        let cached = cache.get_node_group(&NodeGroup::for_tenant(2)).await.unwrap();
         assert!(cached.is_none());
     }
 
     #[tokio::test]
     #[ignore] // Requires Redis to be running
-    async fn test_active_nodes_cache_invalidation() {
+    async fn test_node_group_cache_invalidation() {
         let cache = create_test_redis().await.unwrap();
 
         let nodes = vec![
@@ -498,35 +2168,39 @@ mod tests {
                 id: 3,
                 name: "Test Node 3".to_string(),
                 host: "node3.example.com".to_string(),
-                port: 443,
                 protocol: "trojan".to_string(),
                 secret: "secret3".to_string(),
                 config: serde_json::json!({"test": "config3"}),
-                status: "online".to_string(),
-                max_users: 1000,
                 current_users: 100,
-                total_upload: 0,
-                total_download: 0,
                 last_heartbeat: Some(chrono::Utc::now()),
-                created_at: chrono::Utc::now(),
-                updated_at: chrono::Utc::now(),
                 include_in_clash: false,
-                sort_order: 0,
+                ..Node::test_fixture()
             },
         ];
 
-        cache.cache_active_nodes(&nodes).await.unwrap();
+        let group = NodeGroup::for_tenant(1);
+        let other = NodeGroup::for_tenant(3);
+        cache.cache_node_group(&group, &nodes).await.unwrap();
+        cache.cache_node_group(&other, &[]).await.unwrap();
 
         // Verify it's cached
-        let cached = cache.get_active_nodes().await.unwrap();
+        let cached = cache.get_node_group(&group).await.unwrap();
         assert!(cached.is_some());
 
-        // Invalidate cache
-        cache.invalidate_active_nodes().await.unwrap();
+        // Invalidate the tenant's groups only
+        cache.invalidate_node_groups(1).await.unwrap();
 
-        // Verify it's gone
-        let cached = cache.get_active_nodes().await.unwrap();
+        // Verify it's gone, and the other tenant's group is untouched
+        let cached = cache.get_node_group(&group).await.unwrap();
         assert!(cached.is_none());
+        assert_eq!(cache.get_node_group(&other).await.unwrap().map(|nodes| nodes.len()), Some(0));
+
+        let stats = cache.get_node_group_stats().await.unwrap();
+        let group_stats = stats.iter().find(|s| s.hash == group.hash()).unwrap();
+        assert!(group_stats.hits >= 1 && group_stats.misses >= 1 && group_stats.invalidations >= 1);
+        assert!(!group_stats.cached);
+
+        cache.invalidate_node_groups(3).await.unwrap();
     }
 
     // ========================================================================
@@ -539,20 +2213,296 @@ mod tests {
         let cache = create_test_redis().await.unwrap();
         let token = "test-subscription-token-123";
         let config = "proxies:\n  - name: Test Node\n    type: vless";
+        let fingerprint = crate::crypto::sha256_hex(config.as_bytes());
 
         // Cache the config
-        cache.cache_subscription_config(token, config).await.unwrap();
+        cache
+            .cache_subscription_config(token, &fingerprint, Some(config), 99991, None, None, &[99992])
+            .await
+            .unwrap();
 
         // Retrieve from cache
         let cached = cache.get_subscription_config(token).await.unwrap();
 
-        assert!(cached.is_some());
-        assert_eq!(cached.unwrap(), config);
+        assert_eq!(
+            cached,
+            Some(CachedSubscription {
+                user_id: 99991,
+                config: config.to_string(),
+                fingerprint: fingerprint.clone(),
+                access_key_hash: None,
+                geo_restriction: None,
+            })
+        );
 
         // Cleanup
         cache.invalidate_subscription_config(token).await.unwrap();
     }
 
+    #[tokio::test]
+    #[ignore] // Requires Redis to be running
+    async fn test_purge_subscription_configs_by_scope() {
+        let cache = create_test_redis().await.unwrap();
+        let config = "proxies: []";
+        let fingerprint = crate::crypto::sha256_hex(config.as_bytes());
+
+        cache
+            .cache_subscription_config("purge-token-a", &fingerprint, Some(config), 99991, None, None, &[99993])
+            .await
+            .unwrap();
+        cache
+            .cache_subscription_config("purge-token-b", &fingerprint, Some(config), 99992, None, None, &[99994])
+            .await
+            .unwrap();
+
+        // Purging a node only drops configs that embed it
+        let purged = cache.purge_subscription_configs(PurgeScope::Node(99993)).await.unwrap();
+        assert_eq!(purged, 1);
+        assert!(cache.get_subscription_config("purge-token-a").await.unwrap().is_none());
+        assert!(cache.get_subscription_config("purge-token-b").await.unwrap().is_some());
+
+        let purged = cache.purge_subscription_configs(PurgeScope::User(99992)).await.unwrap();
+        assert_eq!(purged, 1);
+        assert!(cache.get_subscription_config("purge-token-b").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Redis to be running
+    async fn test_subscription_configs_share_blob() {
+        let cache = create_test_redis().await.unwrap();
+        let config = "proxies:\n  - name: Shared Node\n    type: trojan";
+        let fingerprint = crate::crypto::sha256_hex(b"shared-config-inputs");
+
+        // The first token generates the config, the second only references it
+        cache
+            .cache_subscription_config("shared-token-a", &fingerprint, Some(config), 99995, None, None, &[])
+            .await
+            .unwrap();
+        let blob = cache.get_subscription_blob(&fingerprint).await.unwrap();
+        assert_eq!(blob.as_deref(), Some(config));
+        cache
+            .cache_subscription_config("shared-token-b", &fingerprint, None, 99996, None, None, &[])
+            .await
+            .unwrap();
+
+        let cached = cache.get_subscription_config("shared-token-b").await.unwrap().unwrap();
+        assert_eq!((cached.user_id, cached.config.as_str()), (99996, config));
+
+        // Dropping one token keeps the shared config for the other
+        cache.invalidate_subscription_config("shared-token-a").await.unwrap();
+        assert!(cache.get_subscription_config("shared-token-b").await.unwrap().is_some());
+
+        // A full purge drops the shared configs as well
+        cache.purge_subscription_configs(PurgeScope::AllSubscriptions).await.unwrap();
+        assert!(cache.get_subscription_blob(&fingerprint).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Redis to be running
+    async fn test_subscription_variants() {
+        let cache = create_test_redis().await.unwrap();
+        let config = "proxies:\n  - name: Compressed Node\n    type: trojan\n".repeat(50);
+        let fingerprint = crate::crypto::sha256_hex(b"compressed-config-inputs");
+        cache
+            .cache_subscription_config("variant-token", &fingerprint, Some(&config), 99997, None, None, &[])
+            .await
+            .unwrap();
+
+        assert!(cache.get_subscription_variant(&fingerprint, Encoding::Gzip).await.unwrap().is_none());
+        let gzip = Encoding::Gzip.compress(config.as_bytes()).unwrap();
+        cache.cache_subscription_variant(&fingerprint, Encoding::Gzip, &gzip).await.unwrap();
+
+        // Stored as raw bytes, per encoding
+        assert_eq!(cache.get_subscription_variant(&fingerprint, Encoding::Gzip).await.unwrap(), Some(gzip));
+        assert!(cache.get_subscription_variant(&fingerprint, Encoding::Brotli).await.unwrap().is_none());
+        let cached = cache.get_subscription_config("variant-token").await.unwrap().unwrap();
+        assert_eq!(cached.fingerprint, fingerprint);
+
+        // A full purge drops the compressed copies with the configs
+        cache.purge_subscription_configs(PurgeScope::AllSubscriptions).await.unwrap();
+        assert!(cache.get_subscription_variant(&fingerprint, Encoding::Gzip).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Redis to be running
+    async fn test_access_log_queue() {
+        let cache = create_test_redis().await.unwrap();
+        cache.delete(ACCESS_LOG_QUEUE).await.unwrap();
+
+        let entries: Vec<AccessLogEntry> = (1..=4)
+            .map(|user_id| AccessLogEntry::new(user_id, "queued-token", "10.0.0.1", None, "stale"))
+            .collect();
+        for entry in &entries {
+            cache.queue_access_log(entry, 3).await.unwrap();
+        }
+
+        // The oldest entry was dropped; the rest come out oldest first
+        let taken = cache.take_queued_access_logs(2).await.unwrap();
+        assert_eq!(taken, entries[1..3]);
+
+        // Put back, they are taken again before the newer entry
+        cache.requeue_access_logs(&taken).await.unwrap();
+        assert_eq!(cache.take_queued_access_logs(10).await.unwrap(), entries[1..]);
+        assert!(cache.take_queued_access_logs(10).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_subscription_ref_encoding() {
+        let fingerprint = crate::crypto::sha256_hex(b"inputs");
+        let encoded = SubscriptionRef::encode(42, &fingerprint, None, None);
+        assert_eq!(
+            SubscriptionRef::decode(&encoded),
+            Some(SubscriptionRef {
+                user_id: 42,
+                fingerprint: fingerprint.clone(),
+                access_key_hash: None,
+                geo_restriction: None,
+            })
+        );
+
+        let key_hash = crate::crypto::sha256_hex(b"key");
+        let restriction = SubscriptionGeoRestriction {
+            user_id: 42,
+            allowed_countries: None,
+            blocked_countries: Some(vec!["CN".to_string()]),
+            action: Some(crate::geoip::GeoAction::Challenge),
+            updated_by: Some(1),
+            updated_at: Utc::now(),
+        };
+        let encoded = SubscriptionRef::encode(42, &fingerprint, Some(&key_hash), Some(&restriction));
+        let entry = SubscriptionRef::decode(&encoded).unwrap();
+        assert_eq!(entry.access_key_hash, Some(key_hash.clone()));
+        assert_eq!(entry.geo_restriction, Some(restriction));
+
+        // Configs cached before they were shared, before the owner was stored, or before
+        // the GeoIP override was cached, are misses
+        assert_eq!(SubscriptionRef::decode(&format!("42\n{}\n{}", fingerprint, key_hash)), None);
+        assert_eq!(SubscriptionRef::decode("42\nport: 7890\nproxies: []"), None);
+        assert_eq!(SubscriptionRef::decode("port: 7890\nproxies: []"), None);
+        assert_eq!(SubscriptionRef::decode(&fingerprint), None);
+    }
+
+    #[test]
+    fn test_node_group_hash_and_stats() {
+        let group = NodeGroup::for_tenant(1);
+        assert_eq!(group.hash().len(), 16);
+        assert_eq!(group.hash(), NodeGroup::for_tenant(1).hash());
+        assert_ne!(group.hash(), NodeGroup::for_tenant(2).hash());
+
+        let fields = HashMap::from([
+            ("label".to_string(), group.label()),
+            ("hits".to_string(), "3".to_string()),
+            ("misses".to_string(), "1".to_string()),
+        ]);
+        let stats = NodeGroupStats::from_fields(group.hash(), &fields, true);
+        assert_eq!(stats.label, "tenant:1");
+        assert_eq!((stats.hits, stats.misses, stats.invalidations), (3, 1, 0));
+        assert_eq!(stats.hit_rate, 0.75);
+
+        let empty = NodeGroupStats::from_fields(group.hash(), &HashMap::new(), false);
+        assert_eq!(empty.hit_rate, 0.0);
+        assert_eq!(empty.label, group.hash());
+    }
+
+    #[test]
+    fn test_purge_scope_parse() {
+        assert_eq!(PurgeScope::parse("all-subscriptions"), Some(PurgeScope::AllSubscriptions));
+        assert_eq!(PurgeScope::parse("node:12"), Some(PurgeScope::Node(12)));
+        assert_eq!(PurgeScope::parse("user:7"), Some(PurgeScope::User(7)));
+        assert_eq!(PurgeScope::parse("node:abc"), None);
+        assert_eq!(PurgeScope::parse("package:1"), None);
+        assert_eq!(PurgeScope::parse("all"), None);
+
+        for scope in [PurgeScope::AllSubscriptions, PurgeScope::Node(12), PurgeScope::User(7)] {
+            assert_eq!(PurgeScope::parse(&scope.label()), Some(scope));
+        }
+    }
+
+    #[test]
+    fn test_cache_scope_parse() {
+        assert_eq!(CacheScope::parse("user-packages"), Some(CacheScope::UserPackages));
+        assert_eq!(CacheScope::parse("user-package:7"), Some(CacheScope::UserPackage(7)));
+        assert_eq!(CacheScope::parse("node-groups"), Some(CacheScope::NodeGroups));
+        assert_eq!(CacheScope::parse("node-groups:2"), Some(CacheScope::TenantNodeGroups(2)));
+        assert_eq!(
+            CacheScope::parse("node:12"),
+            Some(CacheScope::Subscriptions(PurgeScope::Node(12)))
+        );
+        assert_eq!(
+            CacheScope::parse("all-subscriptions"),
+            Some(CacheScope::Subscriptions(PurgeScope::AllSubscriptions))
+        );
+        assert_eq!(CacheScope::parse("user-package:abc"), None);
+        assert_eq!(CacheScope::parse("everything"), None);
+    }
+
+    #[test]
+    fn test_cache_stats_from_fields() {
+        let fields = HashMap::from([
+            ("hits".to_string(), "9".to_string()),
+            ("misses".to_string(), "1".to_string()),
+            ("invalidations".to_string(), "4".to_string()),
+        ]);
+        let stats = CacheStats::from_fields(CacheKind::Subscriptions, &fields, 3, Vec::new());
+        assert_eq!(stats.cache, "subscriptions");
+        assert_eq!((stats.hits, stats.misses, stats.invalidations, stats.keys), (9, 1, 4, 3));
+        assert_eq!(stats.hit_rate, 0.9);
+
+        let empty = CacheStats::from_fields(CacheKind::Nodes, &HashMap::new(), 0, Vec::new());
+        assert_eq!(empty.hit_rate, 0.0);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Redis to be running
+    async fn test_invalidate_user_packages() {
+        let cache = create_test_redis().await.unwrap();
+        let package = UserPackageCache {
+            traffic_quota: 1000,
+            traffic_used: 0,
+            expires_at: "2030-01-01T00:00:00Z".to_string(),
+            status: UserPackageStatus::Active,
+        };
+        for user_id in [99996, 99997, 99998] {
+            cache.cache_user_package(user_id, &package).await.unwrap();
+        }
+
+        cache.invalidate_user_packages(&[99996, 99997], "traffic").await.unwrap();
+        assert!(cache.get_user_package(99996).await.unwrap().is_none());
+        assert!(cache.get_user_package(99997).await.unwrap().is_none());
+        assert!(cache.get_user_package(99998).await.unwrap().is_some());
+
+        // Nothing to drop is not an error
+        cache.invalidate_user_packages(&[], "traffic").await.unwrap();
+
+        // Cleanup
+        cache.invalidate_user_package(99998).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Redis to be running
+    async fn test_cache_stats_count_lookups_and_invalidations() {
+        let cache = create_test_redis().await.unwrap();
+        let stats = |all: Vec<CacheStats>| all.into_iter().find(|s| s.cache == "user_packages").unwrap();
+        let before = stats(cache.get_cache_stats().await.unwrap());
+
+        let package = UserPackageCache {
+            traffic_quota: 1000,
+            traffic_used: 0,
+            expires_at: "2030-01-01T00:00:00Z".to_string(),
+            status: UserPackageStatus::Active,
+        };
+        cache.cache_user_package(99995, &package).await.unwrap();
+        assert!(cache.get_user_package(99995).await.unwrap().is_some());
+        assert_eq!(cache.purge_cache(CacheScope::UserPackage(99995)).await.unwrap(), 1);
+        assert!(cache.get_user_package(99995).await.unwrap().is_none());
+
+        let after = stats(cache.get_cache_stats().await.unwrap());
+        assert!(after.hits > before.hits);
+        assert!(after.misses > before.misses);
+        assert!(after.invalidations > before.invalidations);
+        assert_eq!(after.recent_invalidations[0].target, "user:99995");
+    }
+
     // ========================================================================
     // Cache Fallback Tests
     // ========================================================================
@@ -567,8 +2517,8 @@ mod tests {
         assert!(user_pkg.is_none());
         
         // Test nodes cache miss
-        let _ = cache.invalidate_active_nodes().await;
-        let nodes = cache.get_active_nodes().await.unwrap();
+        let _ = cache.invalidate_node_groups(88888).await;
+        let nodes = cache.get_node_group(&NodeGroup::for_tenant(88888)).await.unwrap();
         assert!(nodes.is_none());
         
         // Test subscription cache miss
@@ -589,4 +2539,53 @@ mod tests {
         cache.delete("nonexistent_key").await.unwrap();
         cache.delete("nonexistent_key").await.unwrap();
     }
+
+    // ========================================================================
+    // Distributed Lock Tests
+    // ========================================================================
+
+    #[tokio::test]
+    #[ignore] // Requires Redis to be running
+    async fn test_lock_is_exclusive() {
+        let cache = create_test_redis().await.unwrap();
+        let name = "test_lock_exclusive";
+
+        assert!(cache.try_acquire_lock(name, "owner-a", 30).await.unwrap());
+        assert!(!cache.try_acquire_lock(name, "owner-b", 30).await.unwrap());
+
+        // Only the owner can renew or release
+        assert!(!cache.renew_lock(name, "owner-b", 30).await.unwrap());
+        assert!(!cache.release_lock(name, "owner-b").await.unwrap());
+        assert!(cache.renew_lock(name, "owner-a", 30).await.unwrap());
+        assert!(cache.release_lock(name, "owner-a").await.unwrap());
+
+        assert!(cache.try_acquire_lock(name, "owner-b", 30).await.unwrap());
+        cache.release_lock(name, "owner-b").await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Redis to be running
+    async fn test_lock_takeover_after_lease_expiry() {
+        let cache = create_test_redis().await.unwrap();
+        let name = "test_lock_takeover";
+
+        assert!(cache.try_acquire_lock(name, "owner-a", 1).await.unwrap());
+        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+
+        assert!(cache.try_acquire_lock(name, "owner-b", 30).await.unwrap());
+        cache.release_lock(name, "owner-b").await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Redis to be running
+    async fn test_run_exclusive_skips_when_locked() {
+        let cache = create_test_redis().await.unwrap();
+        let name = "test_run_exclusive";
+
+        assert_eq!(cache.run_exclusive(name, 30, || async { 42 }).await.unwrap(), Some(42));
+
+        cache.try_acquire_lock(name, "other-instance", 30).await.unwrap();
+        assert_eq!(cache.run_exclusive(name, 30, || async { 42 }).await.unwrap(), None);
+        cache.release_lock(name, "other-instance").await.unwrap();
+    }
 }
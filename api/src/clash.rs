@@ -1,7 +1,13 @@
 use anyhow::{anyhow, Result};
+use axum::body::{Body, Bytes};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::io::{self, Write};
 use tracing::warn;
 
+use crate::amounts::TrafficBytes;
+use crate::i18n::{self, Lang};
 use crate::models::Node;
 
 /// Clash proxy configuration
@@ -52,6 +58,9 @@ pub enum ClashProxy {
         sni: Option<String>,
         #[serde(rename = "skip-cert-verify")]
         skip_cert_verify: bool,
+        /// Port hopping: extra server ports the client may switch between
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ports: Option<String>,
     },
     #[serde(rename = "vless")]
     VLESS {
@@ -93,10 +102,21 @@ pub struct ProxyGroup {
     #[serde(rename = "type")]
     pub group_type: String,
     pub proxies: Vec<String>,
+    /// Health check URL of automatic groups
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Seconds between health checks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<i32>,
 }
 
 /// Generate Clash configuration from nodes
 pub fn generate_clash_config(nodes: &[Node]) -> Result<String> {
+    build_clash_config(nodes).to_yaml()
+}
+
+/// Build the default Clash configuration (Proxy/Auto groups and basic rules) for nodes
+pub fn build_clash_config(nodes: &[Node]) -> ClashConfig {
     let mut proxies = Vec::new();
     let mut proxy_names = Vec::new();
 
@@ -114,11 +134,15 @@ pub fn generate_clash_config(nodes: &[Node]) -> Result<String> {
             name: "Proxy".to_string(),
             group_type: "select".to_string(),
             proxies: proxy_names.clone(),
+            url: None,
+            interval: None,
         },
         ProxyGroup {
             name: "Auto".to_string(),
             group_type: "url-test".to_string(),
             proxies: proxy_names,
+            url: None,
+            interval: None,
         },
     ];
 
@@ -132,17 +156,13 @@ pub fn generate_clash_config(nodes: &[Node]) -> Result<String> {
         "MATCH,Proxy".to_string(),
     ];
 
-    let config = ClashConfig {
+    let mut config = ClashConfig {
         proxies,
         proxy_groups,
         rules,
     };
-
-    // Serialize to YAML
-    let yaml = serde_yaml::to_string(&config)
-        .map_err(|e| anyhow!("Failed to serialize Clash config: {}", e))?;
-
-    Ok(yaml)
+    config.add_failover_groups(nodes);
+    config
 }
 
 /// Generate Clash configuration from nodes with include_in_clash=true
@@ -166,6 +186,15 @@ pub fn generate_clash_config_from_db(
     db_proxy_groups: &[crate::models::ClashProxyGroup],
     db_rules: &[crate::models::ClashRule],
 ) -> Result<String> {
+    build_clash_config_from_db(db_proxies, db_proxy_groups, db_rules)?.to_yaml()
+}
+
+/// Build Clash configuration from database proxies, groups and rules
+pub fn build_clash_config_from_db(
+    db_proxies: &[crate::models::ClashProxy],
+    db_proxy_groups: &[crate::models::ClashProxyGroup],
+    db_rules: &[crate::models::ClashRule],
+) -> Result<ClashConfig> {
     // Convert database proxies to Clash proxies
     let mut proxies = Vec::new();
     for db_proxy in db_proxies {
@@ -180,36 +209,1045 @@ pub fn generate_clash_config_from_db(
             name: g.name.clone(),
             group_type: g.group_type.clone(),
             proxies: g.proxies.clone(),
+            url: None,
+            interval: None,
         })
         .collect();
 
     // Convert database rules to Clash rules
-    let rules: Vec<String> = db_rules
+    let rules: Vec<String> = db_rules.iter().map(format_rule).collect();
+
+    Ok(ClashConfig {
+        proxies,
+        proxy_groups,
+        rules,
+    })
+}
+
+// ============================================================================
+// Rule Import / Export
+// ============================================================================
+
+/// Supported Clash rule types (mirrors the clash_rules.rule_type CHECK constraint)
+pub const VALID_RULE_TYPES: &[&str] = &[
+    "DOMAIN", "DOMAIN-SUFFIX", "DOMAIN-KEYWORD",
+    "IP-CIDR", "IP-CIDR6", "SRC-IP-CIDR",
+    "GEOIP", "DST-PORT", "SRC-PORT",
+    "PROCESS-NAME", "MATCH",
+];
+
+/// A rule line that could not be parsed during import
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleParseError {
+    pub line: usize,
+    pub content: String,
+    pub reason: String,
+}
+
+/// Format a stored rule as a Clash rule line: `TYPE[,value],group[,no-resolve]`
+pub fn format_rule(rule: &crate::models::ClashRule) -> String {
+    let mut line = rule.rule_type.clone();
+    if let Some(value) = rule.rule_value.as_deref().filter(|v| !v.is_empty()) {
+        line.push_str(&format!(",{}", value));
+    }
+    line.push_str(&format!(",{}", rule.proxy_group));
+    if rule.no_resolve {
+        line.push_str(",no-resolve");
+    }
+    line
+}
+
+/// Parse a single Clash rule line such as `DOMAIN-SUFFIX,google.com,Proxy,no-resolve`
+pub fn parse_rule_line(line: &str) -> std::result::Result<crate::models::ParsedClashRule, String> {
+    let parts: Vec<&str> = line.split(',').map(|p| p.trim()).collect();
+    let rule_type = parts[0].to_uppercase();
+
+    if !VALID_RULE_TYPES.contains(&rule_type.as_str()) {
+        return Err(format!("Unsupported rule type '{}'", parts[0]));
+    }
+
+    let (rule_value, proxy_group, options) = if rule_type == "MATCH" {
+        if parts.len() != 2 {
+            return Err("MATCH rule must be in the form MATCH,group".to_string());
+        }
+        (None, parts[1], &parts[2..])
+    } else {
+        if parts.len() < 3 {
+            return Err(format!("{} rule must be in the form {},value,group", rule_type, rule_type));
+        }
+        (Some(parts[1]), parts[2], &parts[3..])
+    };
+
+    if rule_value.map_or(false, |v| v.is_empty() || v.len() > 255) {
+        return Err("Rule value must be 1-255 characters".to_string());
+    }
+    if proxy_group.is_empty() || proxy_group.len() > 100 {
+        return Err("Proxy group must be 1-100 characters".to_string());
+    }
+
+    let no_resolve = match options {
+        [] => false,
+        ["no-resolve"] => true,
+        _ => return Err(format!("Unsupported rule options '{}'", options.join(","))),
+    };
+
+    Ok(crate::models::ParsedClashRule {
+        rule_type,
+        rule_value: rule_value.map(|v| v.to_string()),
+        proxy_group: proxy_group.to_string(),
+        no_resolve,
+    })
+}
+
+/// Parse a Clash rules block into rules keyed by their 1-based line number
+///
+/// Accepts either a plain list of rule lines or the `rules:` section of a Clash YAML
+/// config (`- DOMAIN,...` items, optionally quoted). Blank lines and comments are skipped.
+pub fn parse_rules_text(
+    text: &str,
+) -> (Vec<(usize, crate::models::ParsedClashRule)>, Vec<RuleParseError>) {
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+
+    for (idx, raw) in text.lines().enumerate() {
+        let mut line = raw.trim();
+        if line.is_empty() || line.starts_with('#') || line == "rules:" {
+            continue;
+        }
+
+        if let Some(item) = line.strip_prefix('-') {
+            line = item.trim();
+        }
+        line = line.trim_matches(|c| c == '"' || c == '\'');
+
+        match parse_rule_line(line) {
+            Ok(rule) => rules.push((idx + 1, rule)),
+            Err(reason) => errors.push(RuleParseError {
+                line: idx + 1,
+                content: raw.trim().to_string(),
+                reason,
+            }),
+        }
+    }
+
+    (rules, errors)
+}
+
+/// Default subscription refresh interval (hours) when no package overrides it
+pub const DEFAULT_UPDATE_INTERVAL_HOURS: i32 = 24;
+
+/// First-line comment carrying the refresh interval inside a generated config
+const UPDATE_INTERVAL_MARKER: &str = "# profile-update-interval: ";
+
+/// Prepend the refresh interval to a generated config as a YAML comment
+/// Clients read the `profile-update-interval` header; the comment keeps the value
+/// with the config so cached copies can restore the header without a DB lookup
+pub fn with_update_interval(config: &str, hours: i32) -> String {
+    format!("{}{}\n{}", UPDATE_INTERVAL_MARKER, hours, config)
+}
+
+/// Read the refresh interval from a config produced by `with_update_interval`
+pub fn parse_update_interval(config: &str) -> Option<i32> {
+    config
+        .lines()
+        .next()?
+        .strip_prefix(UPDATE_INTERVAL_MARKER)?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Generate Clash configuration from Clash-enabled nodes plus database groups and rules
+pub fn generate_clash_config_from_nodes_and_db(
+    nodes: &[Node],
+    db_proxy_groups: &[crate::models::ClashProxyGroup],
+    db_rules: &[crate::models::ClashRule],
+) -> Result<String> {
+    build_clash_config_from_nodes_and_db(nodes, db_proxy_groups, db_rules).to_yaml()
+}
+
+/// Build Clash configuration from Clash-enabled nodes plus database groups and rules
+///
+/// Membership expressions in the groups are expanded against the nodes. Plain members
+/// naming a proxy that is not in this config (an offline node, say) are dropped, and a
+/// group left empty falls back to `DIRECT` so the config still loads.
+pub fn build_clash_config_from_nodes_and_db(
+    nodes: &[Node],
+    db_proxy_groups: &[crate::models::ClashProxyGroup],
+    db_rules: &[crate::models::ClashRule],
+) -> ClashConfig {
+    let (proxy_nodes, proxies): (Vec<&Node>, Vec<ClashProxy>) = nodes
         .iter()
-        .map(|r| {
-            let mut rule = format!("{}", r.rule_type);
-            if let Some(ref value) = r.rule_value {
-                rule.push_str(&format!(",{}", value));
+        .filter_map(|node| node_to_clash_proxy(node).map(|proxy| (node, proxy)))
+        .unzip();
+    let proxy_names: Vec<String> = proxies.iter().map(get_proxy_name).collect();
+    let group_names: Vec<String> = db_proxy_groups.iter().map(|g| g.name.clone()).collect();
+
+    let proxy_groups: Vec<ProxyGroup> = db_proxy_groups
+        .iter()
+        .map(|g| {
+            let mut members = expand_group_members(&g.proxies, &proxy_nodes);
+            members.retain(|m| is_known_target(m, &proxy_names, &group_names));
+            if members.is_empty() {
+                members.push("DIRECT".to_string());
             }
-            rule.push_str(&format!(",{}", r.proxy_group));
-            if r.no_resolve {
-                rule.push_str(",no-resolve");
+
+            ProxyGroup {
+                name: g.name.clone(),
+                group_type: g.group_type.clone(),
+                proxies: members,
+                url: None,
+                interval: None,
             }
-            rule
         })
         .collect();
 
-    let config = ClashConfig {
+    let mut config = ClashConfig {
         proxies,
         proxy_groups,
-        rules,
+        rules: db_rules.iter().map(format_rule).collect(),
     };
+    config.add_failover_groups(nodes);
+    config
+}
+
+// ============================================================================
+// Client Compatibility
+// ============================================================================
+
+/// Clash core family of a subscription client
+///
+/// Legacy Clash (incl. Premium) rejects a whole config that contains a proxy type
+/// it doesn't know, so those clients get a filtered config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientFlavor {
+    /// Original Clash / Clash Premium core
+    Clash,
+    /// Clash.Meta / mihomo core (and other clients that accept the full proxy set)
+    Meta,
+}
+
+/// Client names (from `utils::parse_user_agent`) that run a legacy Clash core
+const LEGACY_CLASH_CLIENTS: &[&str] = &[
+    "Clash",
+    "ClashX",
+    "ClashX Pro",
+    "Clash for Windows",
+    "Clash for Android",
+];
+
+/// Proxy type compatibility matrix: (Clash proxy type, legacy Clash, Clash.Meta)
+pub const PROXY_COMPATIBILITY: &[(&str, bool, bool)] = &[
+    ("ss", true, true),
+    ("vmess", true, true),
+    ("trojan", true, true),
+    ("hysteria2", false, true),
+    ("vless", false, true),
+];
+
+/// Shadowsocks ciphers only Clash.Meta implements (SIP022)
+const META_ONLY_SS_CIPHER_PREFIX: &str = "2022-blake3-";
+
+impl ClientFlavor {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "clash" | "premium" => Some(Self::Clash),
+            "meta" | "clash.meta" | "clash-meta" | "mihomo" => Some(Self::Meta),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Clash => "clash",
+            Self::Meta => "meta",
+        }
+    }
+
+    /// Pick the flavor from an explicit `?flavor=` override, else from the User-Agent
+    /// Unknown clients get the full config, which is what they received before.
+    pub fn detect(flavor_param: Option<&str>, user_agent: Option<&str>) -> Self {
+        if let Some(flavor) = flavor_param.and_then(Self::parse) {
+            return flavor;
+        }
+
+        let client = crate::utils::parse_user_agent(user_agent);
+        if LEGACY_CLASH_CLIENTS.contains(&client.name.as_str()) {
+            Self::Clash
+        } else {
+            Self::Meta
+        }
+    }
+
+    /// Whether a client of this flavor can load the proxy
+    pub fn supports(&self, proxy: &ClashProxy) -> bool {
+        let proxy_type = proxy_type(proxy);
+        let supported = PROXY_COMPATIBILITY
+            .iter()
+            .find(|(t, _, _)| *t == proxy_type)
+            .map_or(false, |(_, clash, meta)| match self {
+                Self::Clash => *clash,
+                Self::Meta => *meta,
+            });
+
+        match (self, proxy) {
+            (Self::Clash, ClashProxy::Shadowsocks { cipher, .. }) => {
+                supported && !cipher.starts_with(META_ONLY_SS_CIPHER_PREFIX)
+            }
+            _ => supported,
+        }
+    }
+}
+
+impl ClashConfig {
+    /// Drop proxies the client flavor cannot load, along with group references to them
+    /// Groups left empty fall back to `DIRECT` so the config still loads.
+    /// Returns the number of proxies removed.
+    pub fn retain_supported(&mut self, flavor: ClientFlavor) -> usize {
+        let (kept, removed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.proxies)
+            .into_iter()
+            .partition(|proxy| flavor.supports(proxy));
+        self.proxies = kept;
+
+        if removed.is_empty() {
+            return 0;
+        }
+
+        let removed_names: Vec<String> = removed.iter().map(get_proxy_name).collect();
+        for group in &mut self.proxy_groups {
+            group.proxies.retain(|name| !removed_names.contains(name));
+            if group.proxies.is_empty() {
+                group.proxies.push("DIRECT".to_string());
+            }
+        }
+
+        removed.len()
+    }
+}
+
+/// Clash `type` value of a proxy
+fn proxy_type(proxy: &ClashProxy) -> &'static str {
+    match proxy {
+        ClashProxy::Shadowsocks { .. } => "ss",
+        ClashProxy::VMess { .. } => "vmess",
+        ClashProxy::Trojan { .. } => "trojan",
+        ClashProxy::Hysteria2 { .. } => "hysteria2",
+        ClashProxy::VLESS { .. } => "vless",
+    }
+}
+
+// ============================================================================
+// Serialization
+// ============================================================================
+
+/// Default config size (bytes) above which generation is logged as oversized
+pub const DEFAULT_CONFIG_SIZE_WARN_BYTES: usize = 2 * 1024 * 1024;
+
+/// Target size of each chunk when streaming a config to the client
+const STREAM_CHUNK_BYTES: usize = 32 * 1024;
+
+/// Buffers larger than this are not kept for reuse, so one huge config doesn't pin memory
+const MAX_RETAINED_BUFFER_BYTES: usize = 8 * 1024 * 1024;
+
+/// Top-level sections in output order
+const SECTION_KEYS: [&str; 3] = ["proxies", "proxy-groups", "rules"];
+
+thread_local! {
+    /// Serialization buffer reused by configs rendered on the same worker thread
+    static YAML_BUFFER: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+}
+
+impl ClashConfig {
+    /// Write the config as YAML one proxy, group and rule at a time
+    ///
+    /// The output is identical to `serde_yaml::to_string(self)`, but no intermediate
+    /// document is built, so memory stays proportional to the largest single entry.
+    pub fn write_yaml<W: Write>(&self, out: &mut W) -> Result<()> {
+        write_section(out, SECTION_KEYS[0], &self.proxies)?;
+        write_section(out, SECTION_KEYS[1], &self.proxy_groups)?;
+        write_section(out, SECTION_KEYS[2], &self.rules)?;
+        Ok(())
+    }
+
+    /// Serialize to a YAML string using this thread's reusable buffer
+    pub fn to_yaml(&self) -> Result<String> {
+        render(|buf| self.write_yaml(buf))
+    }
+
+    /// Serialize lazily into a streaming response body, chunk by chunk as the client reads
+    pub fn into_body(self) -> Body {
+        Body::from_stream(futures::stream::iter(YamlChunks {
+            config: self,
+            section: 0,
+            index: 0,
+            buf: Vec::with_capacity(STREAM_CHUNK_BYTES),
+        }))
+    }
+}
+
+/// Serialize a config with its refresh interval header (see `with_update_interval`)
+/// without copying the YAML a second time
+pub fn render_with_update_interval(config: &ClashConfig, hours: i32) -> Result<String> {
+    render(|buf| {
+        writeln!(buf, "{}{}", UPDATE_INTERVAL_MARKER, hours)?;
+        config.write_yaml(buf)
+    })
+}
+
+/// Log a warning when a generated config is larger than `limit` bytes (0 disables the guard)
+///
+/// Returns whether the config is oversized.
+pub fn warn_if_oversized(size: usize, limit: usize, context: &str) -> bool {
+    let oversized = limit > 0 && size > limit;
+    if oversized {
+        warn!(
+            "Generated Clash config for {} is {} bytes, above the {} byte limit",
+            context, size, limit
+        );
+    }
+    oversized
+}
+
+/// Run `write` against the thread's reusable buffer and return the result as a String
+fn render(write: impl FnOnce(&mut Vec<u8>) -> Result<()>) -> Result<String> {
+    YAML_BUFFER.with(|cell| {
+        let mut buf = cell.borrow_mut();
+        buf.clear();
+        let result = write(&mut *buf).and_then(|()| {
+            String::from_utf8(buf.clone()).map_err(|e| anyhow!("Clash config is not valid UTF-8: {}", e))
+        });
+        if buf.capacity() > MAX_RETAINED_BUFFER_BYTES {
+            *buf = Vec::new();
+        }
+        result
+    })
+}
+
+fn write_section<W: Write, T: Serialize>(out: &mut W, key: &str, items: &[T]) -> Result<()> {
+    write_section_header(out, key, items.is_empty())?;
+    for item in items {
+        write_item(out, item)?;
+    }
+    Ok(())
+}
+
+fn write_section_header<W: Write>(out: &mut W, key: &str, empty: bool) -> Result<()> {
+    if empty {
+        writeln!(out, "{}: []", key)?;
+    } else {
+        writeln!(out, "{}:", key)?;
+    }
+    Ok(())
+}
+
+/// Write one entry as a block sequence item (`- ...`), matching serde_yaml's layout
+/// for a sequence nested in a top-level mapping
+fn write_item<W: Write, T: Serialize>(out: &mut W, item: &T) -> Result<()> {
+    serde_yaml::to_writer(&mut *out, std::slice::from_ref(item))
+        .map_err(|e| anyhow!("Failed to serialize Clash config: {}", e))
+}
+
+/// Iterator producing a config's YAML in chunks of roughly `STREAM_CHUNK_BYTES`
+struct YamlChunks {
+    config: ClashConfig,
+    section: usize,
+    index: usize,
+    buf: Vec<u8>,
+}
+
+impl YamlChunks {
+    /// Write the next section header and/or entry; returns false once everything is written
+    fn write_next(&mut self) -> Result<bool> {
+        let len = match self.section {
+            0 => self.config.proxies.len(),
+            1 => self.config.proxy_groups.len(),
+            2 => self.config.rules.len(),
+            _ => return Ok(false),
+        };
+
+        if self.index == 0 {
+            write_section_header(&mut self.buf, SECTION_KEYS[self.section], len == 0)?;
+        }
+        if self.index < len {
+            match self.section {
+                0 => write_item(&mut self.buf, &self.config.proxies[self.index])?,
+                1 => write_item(&mut self.buf, &self.config.proxy_groups[self.index])?,
+                _ => write_item(&mut self.buf, &self.config.rules[self.index])?,
+            }
+            self.index += 1;
+        }
+        if self.index >= len {
+            self.section += 1;
+            self.index = 0;
+        }
+
+        Ok(true)
+    }
+}
+
+impl Iterator for YamlChunks {
+    type Item = io::Result<Bytes>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buf.len() < STREAM_CHUNK_BYTES {
+            match self.write_next() {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => return Some(Err(io::Error::new(io::ErrorKind::Other, e.to_string()))),
+            }
+        }
+
+        if self.buf.is_empty() {
+            return None;
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(STREAM_CHUNK_BYTES));
+        Some(Ok(Bytes::from(chunk)))
+    }
+}
+
+// ============================================================================
+// Proxy Naming
+// ============================================================================
+
+/// How proxy names are rendered in a user's subscription
+///
+/// Display metadata is read from optional node config keys:
+/// `country` (ISO 3166-1 alpha-2), `region` (display label) and `traffic_ratio` (number).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NamingOptions {
+    pub emoji_flag: bool,
+    pub region_prefix: bool,
+    pub show_traffic_ratio: bool,
+}
+
+impl From<&crate::models::SubscriptionPreferences> for NamingOptions {
+    fn from(prefs: &crate::models::SubscriptionPreferences) -> Self {
+        Self {
+            emoji_flag: prefs.emoji_flag,
+            region_prefix: prefs.region_prefix,
+            show_traffic_ratio: prefs.show_traffic_ratio,
+        }
+    }
+}
+
+impl NamingOptions {
+    /// Whether any option changes the stored node names
+    pub fn is_plain(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Convert an ISO 3166-1 alpha-2 country code to its emoji flag (e.g. "HK" -> 🇭🇰)
+pub fn country_flag(code: &str) -> Option<String> {
+    let code = code.trim();
+    if code.len() != 2 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    code.to_ascii_uppercase()
+        .chars()
+        .map(|c| char::from_u32(0x1F1E6 + (c as u32 - 'A' as u32)))
+        .collect()
+}
+
+/// Render a node's proxy name according to the naming options
+/// e.g. `🇭🇰 HK | Hong Kong 01 [1.5x]`
+pub fn format_proxy_name(node: &Node, options: &NamingOptions) -> String {
+    let country = node.config.get("country").and_then(|v| v.as_str());
+    let mut parts = Vec::new();
+
+    if options.emoji_flag {
+        if let Some(flag) = country.and_then(country_flag) {
+            parts.push(flag);
+        }
+    }
+
+    if options.region_prefix {
+        let region = node
+            .config
+            .get("region")
+            .and_then(|v| v.as_str())
+            .map(|r| r.to_string())
+            .or_else(|| country.map(|c| c.to_ascii_uppercase()));
+        if let Some(region) = region.filter(|r| !r.is_empty()) {
+            parts.push(format!("{} |", region));
+        }
+    }
+
+    parts.push(node.name.clone());
+
+    if options.show_traffic_ratio {
+        if let Some(ratio) = node.config.get("traffic_ratio").and_then(|v| v.as_f64()) {
+            parts.push(format!("[{}x]", ratio));
+        }
+    }
+
+    parts.join(" ")
+}
+
+/// Rename nodes for a subscription, leaving the originals untouched
+pub fn apply_proxy_naming(nodes: &[Node], options: &NamingOptions) -> Vec<Node> {
+    nodes
+        .iter()
+        .map(|node| {
+            let mut node = node.clone();
+            node.name = format_proxy_name(&node, options);
+            node
+        })
+        .collect()
+}
+
+// ============================================================================
+// Subscription Info Entries
+// ============================================================================
+
+/// Placeholder endpoint for informational entries (discard port, never a real proxy)
+const INFO_PROXY_SERVER: &str = "127.0.0.1";
+const INFO_PROXY_PORT: u16 = 9;
+
+/// Human-readable traffic amount, e.g. `38.2GB`
+pub fn format_traffic(bytes: TrafficBytes) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut value = bytes.get().max(0) as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{}B", value as i64)
+    } else {
+        format!("{:.1}{}", value, UNITS[unit])
+    }
+}
+
+/// Names of the informational entries shown at the top of a subscription
+/// e.g. `剩余流量: 38.2GB`, `到期时间: 2025-01-01`
+pub fn info_entry_names(remaining: TrafficBytes, expires_at: DateTime<Utc>, lang: Lang) -> Vec<String> {
+    vec![
+        i18n::render(
+            "subscription.remaining_traffic",
+            lang,
+            &[("traffic", format_traffic(remaining))],
+        ),
+        i18n::render(
+            "subscription.expires_at",
+            lang,
+            &[("date", expires_at.format("%Y-%m-%d").to_string())],
+        ),
+    ]
+}
+
+impl ClashConfig {
+    /// Prepend informational entries as dummy proxies and list them first in `select` groups
+    ///
+    /// The entries point at an unreachable placeholder, so selecting one simply fails;
+    /// automatic groups (url-test, fallback, load-balance) never see them. Shadowsocks is
+    /// used because every client flavor loads it.
+    pub fn prepend_info_proxies(&mut self, names: &[String]) {
+        if names.is_empty() {
+            return;
+        }
+
+        let entries = names.iter().map(|name| ClashProxy::Shadowsocks {
+            name: name.clone(),
+            server: INFO_PROXY_SERVER.to_string(),
+            port: INFO_PROXY_PORT,
+            cipher: "aes-128-gcm".to_string(),
+            password: "info".to_string(),
+            udp: false,
+        });
+        self.proxies.splice(0..0, entries);
+
+        for group in self.proxy_groups.iter_mut().filter(|g| g.group_type == "select") {
+            group.proxies.splice(0..0, names.iter().cloned());
+        }
+    }
+}
+
+// ============================================================================
+// Failover Groups
+// ============================================================================
+
+/// Appended to a primary node's name to name its failover group
+pub const FAILOVER_GROUP_SUFFIX: &str = " (failover)";
+/// Health check URL of generated failover groups
+pub const FAILOVER_TEST_URL: &str = "http://www.gstatic.com/generate_204";
+/// Seconds between health checks of generated failover groups
+pub const FAILOVER_INTERVAL_SECS: i32 = 300;
+
+/// Name of the failover group generated for a primary node
+pub fn failover_group_name(primary: &str) -> String {
+    format!("{}{}", primary, FAILOVER_GROUP_SUFFIX)
+}
+
+impl ClashConfig {
+    /// Add a `fallback` group for every primary node with backups in this config
+    ///
+    /// The group tries the primary first, then its backups in node order. `select` groups
+    /// offer the failover group in place of the primary, so a client on that node switches
+    /// over by itself when it goes down. Nodes missing from the config (offline, say) are
+    /// left out; a primary without a backup left gets no group. Returns the groups added.
+    pub fn add_failover_groups(&mut self, nodes: &[Node]) -> usize {
+        let proxy_names: std::collections::HashSet<String> = self.proxies.iter().map(get_proxy_name).collect();
+        let in_config: Vec<&Node> = nodes.iter().filter(|n| proxy_names.contains(&n.name)).collect();
+
+        let failover: Vec<ProxyGroup> = in_config
+            .iter()
+            .filter(|primary| primary.backup_of.is_none())
+            .filter_map(|primary| {
+                let backups = in_config.iter().copied().filter(|n| n.backup_of == Some(primary.id));
+                let members: Vec<String> = std::iter::once(*primary).chain(backups).map(|n| n.name.clone()).collect();
+
+                (members.len() > 1).then(|| ProxyGroup {
+                    name: failover_group_name(&primary.name),
+                    group_type: "fallback".to_string(),
+                    proxies: members,
+                    url: Some(FAILOVER_TEST_URL.to_string()),
+                    interval: Some(FAILOVER_INTERVAL_SECS),
+                })
+            })
+            .collect();
+
+        for group in self.proxy_groups.iter_mut().filter(|g| g.group_type == "select") {
+            for member in &mut group.proxies {
+                if let Some(replacement) = failover.iter().find(|f| f.proxies[0] == *member) {
+                    *member = replacement.name.clone();
+                }
+            }
+        }
+
+        let added = failover.len();
+        self.proxy_groups.extend(failover);
+        added
+    }
+}
+
+// ============================================================================
+// Dynamic Group Membership
+// ============================================================================
+
+/// Protocols a node may use
+pub const NODE_PROTOCOLS: &[&str] = &["shadowsocks", "vmess", "trojan", "hysteria2", "vless"];
+
+/// Most tags a node may carry
+pub const MAX_NODE_TAGS: usize = 20;
+/// Longest node tag, in characters
+pub const MAX_TAG_CHARS: usize = 32;
+
+/// A proxy group member standing for every node that matches it
+///
+/// Stored in `clash_proxy_groups.proxies` next to plain names and expanded against the
+/// current nodes each time a config is generated, so a new node joins the right groups
+/// without anyone editing them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemberExpression {
+    /// `all`: every node
+    All,
+    /// `tag:HK`: nodes carrying the tag (case-insensitive)
+    Tag(String),
+    /// `protocol:vless`: nodes using the protocol
+    Protocol(String),
+}
+
+impl MemberExpression {
+    /// Parse a group member; None for plain proxy, group and policy names
+    pub fn parse(member: &str) -> Option<Self> {
+        if member == "all" {
+            return Some(MemberExpression::All);
+        }
+        if let Some(tag) = member.strip_prefix("tag:") {
+            return (!tag.is_empty()).then(|| MemberExpression::Tag(tag.to_string()));
+        }
+        if let Some(protocol) = member.strip_prefix("protocol:") {
+            return NODE_PROTOCOLS
+                .contains(&protocol)
+                .then(|| MemberExpression::Protocol(protocol.to_string()));
+        }
+        None
+    }
+
+    pub fn matches(&self, node: &Node) -> bool {
+        match self {
+            MemberExpression::All => true,
+            MemberExpression::Tag(tag) => node.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)),
+            MemberExpression::Protocol(protocol) => node.protocol == *protocol,
+        }
+    }
+}
+
+/// Whether any group has a membership expression, and so needs nodes to be generated
+pub fn uses_member_expressions(groups: &[crate::models::ClashProxyGroup]) -> bool {
+    groups
+        .iter()
+        .any(|g| g.proxies.iter().any(|m| MemberExpression::parse(m).is_some()))
+}
+
+/// Replace the membership expressions among a group's members with the matching node names
+///
+/// Members keep their order, expressions expanding to nodes in the order given; a name
+/// already in the group is not added twice.
+pub fn expand_group_members(members: &[String], nodes: &[&Node]) -> Vec<String> {
+    let mut expanded: Vec<String> = Vec::with_capacity(members.len());
+    let mut seen = std::collections::HashSet::new();
 
-    // Serialize to YAML
-    let yaml = serde_yaml::to_string(&config)
-        .map_err(|e| anyhow!("Failed to serialize Clash config: {}", e))?;
+    for member in members {
+        match MemberExpression::parse(member) {
+            Some(expression) => {
+                for node in nodes.iter().filter(|n| expression.matches(n)) {
+                    if seen.insert(node.name.as_str()) {
+                        expanded.push(node.name.clone());
+                    }
+                }
+            }
+            None => {
+                if seen.insert(member.as_str()) {
+                    expanded.push(member.clone());
+                }
+            }
+        }
+    }
 
-    Ok(yaml)
+    expanded
+}
+
+/// Trimmed node tags without duplicates (compared case-insensitively)
+///
+/// None if there are too many or any is empty or too long.
+pub fn normalize_node_tags(tags: &[String]) -> Option<Vec<String>> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+
+    for tag in tags.iter().map(|t| t.trim()) {
+        if tag.is_empty() || tag.chars().count() > MAX_TAG_CHARS {
+            return None;
+        }
+        if !normalized.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            normalized.push(tag.to_string());
+        }
+    }
+
+    (normalized.len() <= MAX_NODE_TAGS).then_some(normalized)
+}
+
+// ============================================================================
+// Proxy Ordering
+// ============================================================================
+
+/// Width of a load bucket, in percent of `max_users`
+pub const LOAD_BUCKET_PERCENT: i64 = 10;
+/// Width of a latency bucket, in milliseconds
+pub const LATENCY_BUCKET_MS: i64 = 50;
+/// Connectivity tests older than this are ignored when ordering by latency
+pub const LATENCY_MAX_AGE_HOURS: i64 = 24;
+
+/// Order of proxies in generated subscriptions (`PROXY_SORT`)
+///
+/// Dynamic orders compare buckets rather than raw values, so a config only changes,
+/// and its cached copy is only purged, when a node moves to another bucket. Nodes in
+/// the same bucket are ordered by `sort_order`, then id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProxySort {
+    /// As listed, without reordering
+    #[default]
+    Static,
+    /// Least loaded first (`current_users / max_users`)
+    Load,
+    /// Fastest in the last connectivity test first; untested nodes last
+    Latency,
+}
+
+impl ProxySort {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "static" => Some(Self::Static),
+            "load" => Some(Self::Load),
+            "latency" => Some(Self::Latency),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Static => "static",
+            Self::Load => "load",
+            Self::Latency => "latency",
+        }
+    }
+
+    /// Bucket of a node under this order, lower first
+    pub fn bucket(&self, node: &Node, now: DateTime<Utc>) -> i64 {
+        match self {
+            Self::Static => 0,
+            Self::Load => load_bucket(node),
+            Self::Latency => latency_bucket(node, now).unwrap_or(i64::MAX),
+        }
+    }
+}
+
+/// 0 for 0-9% of `max_users` in use, 1 for 10-19%, and so on up to 10 (full)
+///
+/// Nodes without a user limit count as empty.
+pub fn load_bucket(node: &Node) -> i64 {
+    if node.max_users <= 0 {
+        return 0;
+    }
+    let percent = (i64::from(node.current_users.max(0)) * 100 / i64::from(node.max_users)).min(100);
+    percent / LOAD_BUCKET_PERCENT
+}
+
+/// Latency bucket of the node's last connectivity test, None without a recent result
+pub fn latency_bucket(node: &Node, now: DateTime<Utc>) -> Option<i64> {
+    let probed_at = node.probed_at?;
+    if now - probed_at > chrono::Duration::hours(LATENCY_MAX_AGE_HOURS) {
+        return None;
+    }
+    node.probe_latency_ms.map(|ms| i64::from(ms.max(0)) / LATENCY_BUCKET_MS)
+}
+
+/// Reorder a subscription's nodes; the static order leaves them as listed
+pub fn sort_nodes(nodes: &mut [Node], sort: ProxySort, now: DateTime<Utc>) {
+    if sort == ProxySort::Static {
+        return;
+    }
+    nodes.sort_by_cached_key(|node| (sort.bucket(node, now), node.sort_order, node.id));
+}
+
+// ============================================================================
+// Config Fingerprints
+// ============================================================================
+
+/// Version of the generated config layout, part of every config fingerprint
+///
+/// Bump it whenever the same inputs start producing a different config, so configs
+/// cached by an older release are not served.
+pub const TEMPLATE_VERSION: u32 = 2;
+
+/// Everything a subscription config is generated from
+///
+/// Users with the same nodes, naming preferences, package and client flavor have equal
+/// inputs, so their config is generated once and cached under its fingerprint.
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigInputs<'a> {
+    /// Nodes in subscription order, already renamed
+    pub nodes: &'a [Node],
+    pub proxies: Option<&'a [crate::models::ClashProxy]>,
+    pub proxy_groups: Option<&'a [crate::models::ClashProxyGroup]>,
+    pub rules: Option<&'a [crate::models::ClashRule]>,
+    pub flavor: ClientFlavor,
+    /// Informational entry names, empty when they are turned off
+    pub info_entries: &'a [String],
+    pub update_interval_hours: i32,
+}
+
+impl ConfigInputs<'_> {
+    /// Hex SHA-256 of the node set, the rules version and the template version
+    ///
+    /// Nodes contribute only the fields that end up in the config (credentials
+    /// included), so load and heartbeat updates keep the fingerprint stable.
+    pub fn fingerprint(&self) -> String {
+        let nodes: Vec<serde_json::Value> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                serde_json::json!([
+                    node.id,
+                    node.name,
+                    node.host,
+                    node.port,
+                    node.protocol,
+                    merge_node_config(node),
+                    node.tags,
+                    node.backup_of,
+                ])
+            })
+            .collect();
+        let inputs = serde_json::json!({
+            "template_version": TEMPLATE_VERSION,
+            "nodes": nodes,
+            "rules_version": rules_version(self.proxies, self.proxy_groups, self.rules),
+            "flavor": self.flavor.as_str(),
+            "info_entries": self.info_entries,
+            "update_interval_hours": self.update_interval_hours,
+        });
+        crate::crypto::sha256_hex(inputs.to_string().as_bytes())
+    }
+}
+
+/// Hex SHA-256 of the Clash proxies, groups and rules a config is built from
+///
+/// A table that couldn't be read hashes as `null`, matching the node-only fallback config.
+pub fn rules_version(
+    proxies: Option<&[crate::models::ClashProxy]>,
+    proxy_groups: Option<&[crate::models::ClashProxyGroup]>,
+    rules: Option<&[crate::models::ClashRule]>,
+) -> String {
+    let tables = serde_json::json!([proxies, proxy_groups, rules]);
+    crate::crypto::sha256_hex(tables.to_string().as_bytes())
+}
+
+// ============================================================================
+// Reference Validation
+// ============================================================================
+
+/// Built-in Clash policies that can be referenced without being defined
+pub const BUILTIN_POLICIES: &[&str] = &["DIRECT", "REJECT", "REJECT-DROP", "PASS"];
+
+/// A proxy group member or rule target that does not resolve to any proxy, group or policy
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnresolvedReference {
+    pub source_type: &'static str,
+    pub source_id: i64,
+    pub source: String,
+    pub missing: String,
+}
+
+/// Check whether a name resolves to a built-in policy, a proxy, or a proxy group
+pub fn is_known_target(name: &str, proxy_names: &[String], group_names: &[String]) -> bool {
+    BUILTIN_POLICIES.contains(&name)
+        || proxy_names.iter().any(|p| p == name)
+        || group_names.iter().any(|g| g == name)
+}
+
+/// Find every group member and rule target that would be unresolved in the generated config
+pub fn find_unresolved_references(
+    proxy_names: &[String],
+    groups: &[crate::models::ClashProxyGroup],
+    rules: &[crate::models::ClashRule],
+) -> Vec<UnresolvedReference> {
+    let group_names: Vec<String> = groups.iter().map(|g| g.name.clone()).collect();
+    let mut issues = Vec::new();
+
+    for group in groups {
+        for member in &group.proxies {
+            // Expressions resolve to whatever nodes match, possibly none
+            if MemberExpression::parse(member).is_some() {
+                continue;
+            }
+            if member == &group.name || !is_known_target(member, proxy_names, &group_names) {
+                issues.push(UnresolvedReference {
+                    source_type: "proxy_group",
+                    source_id: group.id,
+                    source: group.name.clone(),
+                    missing: member.clone(),
+                });
+            }
+        }
+    }
+
+    for rule in rules {
+        if !is_known_target(&rule.proxy_group, proxy_names, &group_names) {
+            issues.push(UnresolvedReference {
+                source_type: "rule",
+                source_id: rule.id,
+                source: format_rule(rule),
+                missing: rule.proxy_group.clone(),
+            });
+        }
+    }
+
+    issues
 }
 
 /// Convert database ClashProxy to Clash proxy enum
@@ -326,6 +1364,12 @@ fn db_proxy_to_clash_proxy(db_proxy: &crate::models::ClashProxy) -> Result<Clash
                 .and_then(|v| v.as_bool())
                 .unwrap_or(false);
 
+            let ports = db_proxy.config
+                .get("ports")
+                .and_then(|v| v.as_str())
+                .and_then(crate::node_config::parse_port_ranges)
+                .map(|ranges| crate::node_config::format_port_ranges(&ranges));
+
             Ok(ClashProxy::Hysteria2 {
                 name: db_proxy.name.clone(),
                 server: db_proxy.server.clone(),
@@ -335,6 +1379,7 @@ fn db_proxy_to_clash_proxy(db_proxy: &crate::models::ClashProxy) -> Result<Clash
                 obfs_password,
                 sni,
                 skip_cert_verify,
+                ports,
             })
         }
         "vless" => {
@@ -466,7 +1511,12 @@ pub fn merge_node_config(node: &Node) -> serde_json::Value {
 pub fn node_to_clash_proxy(node: &Node) -> Option<ClashProxy> {
     // Check if protocol is supported
     map_node_protocol_to_clash(&node.protocol)?;
-    
+
+    build_node_proxy(node).ok()
+}
+
+/// Convert a Node to a ClashProxy, keeping the reason when the node config is invalid
+pub fn build_node_proxy(node: &Node) -> Result<ClashProxy> {
     // Merge secret and config
     let merged_config = merge_node_config(node);
     
@@ -476,12 +1526,12 @@ pub fn node_to_clash_proxy(node: &Node) -> Option<ClashProxy> {
     
     // Use existing protocol-specific generators
     match node.protocol.as_str() {
-        "shadowsocks" => generate_shadowsocks_proxy(&temp_node).ok(),
-        "vmess" => generate_vmess_proxy(&temp_node).ok(),
-        "trojan" => generate_trojan_proxy(&temp_node).ok(),
-        "hysteria2" => generate_hysteria2_proxy(&temp_node).ok(),
-        "vless" => generate_vless_proxy(&temp_node).ok(),
-        _ => None,
+        "shadowsocks" => generate_shadowsocks_proxy(&temp_node),
+        "vmess" => generate_vmess_proxy(&temp_node),
+        "trojan" => generate_trojan_proxy(&temp_node),
+        "hysteria2" => generate_hysteria2_proxy(&temp_node),
+        "vless" => generate_vless_proxy(&temp_node),
+        _ => Err(anyhow!("Unsupported protocol: {}", node.protocol)),
     }
 }
 
@@ -632,6 +1682,13 @@ fn generate_hysteria2_proxy(node: &Node) -> Result<ClashProxy> {
         .and_then(|v| v.as_bool())
         .unwrap_or(false);
 
+    // Port hopping; ranges that don't parse are left out rather than failing the config
+    let ports = config
+        .get("ports")
+        .and_then(|v| v.as_str())
+        .and_then(crate::node_config::parse_port_ranges)
+        .map(|ranges| crate::node_config::format_port_ranges(&ranges));
+
     Ok(ClashProxy::Hysteria2 {
         name: node.name.clone(),
         server: node.host.clone(),
@@ -641,6 +1698,7 @@ fn generate_hysteria2_proxy(node: &Node) -> Result<ClashProxy> {
         obfs_password,
         sni,
         skip_cert_verify,
+        ports,
     })
 }
 
@@ -717,23 +1775,13 @@ mod tests {
 
     fn create_test_node(protocol: &str, config: JsonValue) -> Node {
         Node {
-            id: 1,
             name: format!("Test {} Node", protocol),
             host: "example.com".to_string(),
-            port: 443,
             protocol: protocol.to_string(),
             secret: "test_secret".to_string(),
             config,
-            status: "online".to_string(),
-            max_users: 1000,
-            current_users: 0,
-            total_upload: 0,
-            total_download: 0,
             last_heartbeat: Some(Utc::now()),
-            created_at: Utc::now(),
-            updated_at: Utc::now(),
-            include_in_clash: true,
-            sort_order: 0,
+            ..Node::test_fixture()
         }
     }
 
@@ -826,7 +1874,7 @@ mod tests {
         let proxy = generate_hysteria2_proxy(&node).unwrap();
         
         match proxy {
-            ClashProxy::Hysteria2 { name, server, port, password, obfs, obfs_password, sni, skip_cert_verify } => {
+            ClashProxy::Hysteria2 { name, server, port, password, obfs, obfs_password, sni, skip_cert_verify, ports } => {
                 assert_eq!(name, "Test hysteria2 Node");
                 assert_eq!(server, "example.com");
                 assert_eq!(port, 443);
@@ -835,11 +1883,32 @@ mod tests {
                 assert_eq!(obfs_password, Some("obfs_pass".to_string()));
                 assert_eq!(sni, Some("example.com".to_string()));
                 assert!(!skip_cert_verify);
+                assert_eq!(ports, None);
             }
             _ => panic!("Expected Hysteria2 proxy"),
         }
     }
 
+    #[test]
+    fn test_generate_hysteria2_port_hopping() {
+        let config = serde_json::json!({
+            "password": "test_password",
+            "ports": "20000-30000, 443"
+        });
+        let node = create_test_node("hysteria2", config);
+        let proxy = generate_hysteria2_proxy(&node).unwrap();
+
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&serde_yaml::to_string(&proxy).unwrap()).unwrap();
+        assert_eq!(yaml["ports"], serde_yaml::Value::from("20000-30000,443"));
+        assert_eq!(yaml["port"], serde_yaml::Value::from(443));
+
+        // Without a range the field is left out entirely
+        let node = create_test_node("hysteria2", serde_json::json!({ "password": "test_password" }));
+        let yaml: serde_yaml::Value =
+            serde_yaml::from_str(&serde_yaml::to_string(&generate_hysteria2_proxy(&node).unwrap()).unwrap()).unwrap();
+        assert!(yaml.get("ports").is_none());
+    }
+
     #[test]
     fn test_generate_vless_proxy() {
         let config = serde_json::json!({
@@ -874,6 +1943,154 @@ mod tests {
         }
     }
 
+    fn proxies_for_flavor_tests() -> Vec<ClashProxy> {
+        vec![
+            ClashProxy::Shadowsocks {
+                name: "SS".to_string(),
+                server: "example.com".to_string(),
+                port: 8388,
+                cipher: "aes-256-gcm".to_string(),
+                password: "pw".to_string(),
+                udp: true,
+            },
+            ClashProxy::Shadowsocks {
+                name: "SS2022".to_string(),
+                server: "example.com".to_string(),
+                port: 8389,
+                cipher: "2022-blake3-aes-256-gcm".to_string(),
+                password: "pw".to_string(),
+                udp: true,
+            },
+            ClashProxy::Trojan {
+                name: "Trojan".to_string(),
+                server: "example.com".to_string(),
+                port: 443,
+                password: "pw".to_string(),
+                udp: true,
+                sni: None,
+                skip_cert_verify: false,
+            },
+            ClashProxy::Hysteria2 {
+                name: "Hy2".to_string(),
+                server: "example.com".to_string(),
+                port: 443,
+                password: "pw".to_string(),
+                obfs: None,
+                obfs_password: None,
+                sni: None,
+                skip_cert_verify: false,
+                ports: None,
+            },
+            ClashProxy::VLESS {
+                name: "Reality".to_string(),
+                server: "example.com".to_string(),
+                port: 443,
+                uuid: "12345678-1234-1234-1234-123456789012".to_string(),
+                flow: Some("xtls-rprx-vision".to_string()),
+                network: "tcp".to_string(),
+                reality_opts: Some(RealityOpts {
+                    public_key: "key".to_string(),
+                    short_id: "".to_string(),
+                }),
+                client_fingerprint: Some("chrome".to_string()),
+            },
+        ]
+    }
+
+    fn config_for_flavor_tests() -> ClashConfig {
+        let proxies = proxies_for_flavor_tests();
+        let names: Vec<String> = proxies.iter().map(get_proxy_name).collect();
+        ClashConfig {
+            proxies,
+            proxy_groups: vec![
+                ProxyGroup {
+                    name: "Proxy".to_string(),
+                    group_type: "select".to_string(),
+                    proxies: names,
+                    url: None,
+                    interval: None,
+                },
+                ProxyGroup {
+                    name: "Fast".to_string(),
+                    group_type: "url-test".to_string(),
+                    proxies: vec!["Hy2".to_string(), "Reality".to_string()],
+                    url: None,
+                    interval: None,
+                },
+            ],
+            rules: vec!["MATCH,Proxy".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_client_flavor_detection() {
+        let cases = [
+            (Some("ClashX/1.95.1"), ClientFlavor::Clash),
+            (Some("ClashX Pro/1.72.0.4"), ClientFlavor::Clash),
+            (Some("ClashForAndroid/2.5.12"), ClientFlavor::Clash),
+            (Some("ClashforWindows/0.20.39"), ClientFlavor::Clash),
+            (Some("clash"), ClientFlavor::Clash),
+            (Some("mihomo/1.18.0"), ClientFlavor::Meta),
+            (Some("clash.meta"), ClientFlavor::Meta),
+            (Some("ClashMetaForAndroid/2.8.9.Meta"), ClientFlavor::Meta),
+            (Some("clash-verge/v1.3.8"), ClientFlavor::Meta),
+            (Some("Stash/2.4.5 Clash/1.9.0"), ClientFlavor::Meta),
+            (Some("Mozilla/5.0"), ClientFlavor::Meta),
+            (None, ClientFlavor::Meta),
+        ];
+
+        for (ua, expected) in cases {
+            assert_eq!(ClientFlavor::detect(None, ua), expected, "UA {:?}", ua);
+        }
+    }
+
+    #[test]
+    fn test_client_flavor_query_override() {
+        assert_eq!(ClientFlavor::detect(Some("meta"), Some("ClashX/1.95.1")), ClientFlavor::Meta);
+        assert_eq!(ClientFlavor::detect(Some("Clash"), Some("mihomo/1.18.0")), ClientFlavor::Clash);
+        // Unknown values are ignored in favour of the User-Agent
+        assert_eq!(ClientFlavor::detect(Some("sing-box"), Some("ClashX/1.95.1")), ClientFlavor::Clash);
+    }
+
+    #[test]
+    fn test_retain_supported_for_legacy_clash() {
+        let mut config = config_for_flavor_tests();
+        let removed = config.retain_supported(ClientFlavor::Clash);
+
+        assert_eq!(removed, 3);
+        let names: Vec<String> = config.proxies.iter().map(get_proxy_name).collect();
+        assert_eq!(names, vec!["SS", "Trojan"]);
+        assert_eq!(config.proxy_groups[0].proxies, vec!["SS", "Trojan"]);
+        // A group whose proxies were all removed still loads
+        assert_eq!(config.proxy_groups[1].proxies, vec!["DIRECT"]);
+
+        let yaml = config.to_yaml().unwrap();
+        assert!(!yaml.contains("hysteria2"));
+        assert!(!yaml.contains("vless"));
+        assert!(!yaml.contains("2022-blake3"));
+    }
+
+    #[test]
+    fn test_retain_supported_for_meta_keeps_everything() {
+        let mut config = config_for_flavor_tests();
+        assert_eq!(config.retain_supported(ClientFlavor::Meta), 0);
+        assert_eq!(config.proxies.len(), 5);
+        assert_eq!(config.proxy_groups[1].proxies, vec!["Hy2", "Reality"]);
+    }
+
+    #[test]
+    fn test_compatibility_matrix_covers_all_proxy_types() {
+        for proxy in proxies_for_flavor_tests() {
+            let proxy_type = proxy_type(&proxy);
+            assert!(
+                PROXY_COMPATIBILITY.iter().any(|(t, _, _)| *t == proxy_type),
+                "{} missing from compatibility matrix",
+                proxy_type
+            );
+            assert!(ClientFlavor::Meta.supports(&proxy));
+        }
+    }
+
     #[test]
     fn test_generate_clash_config() {
         let nodes = vec![
@@ -926,4 +2143,440 @@ mod tests {
             _ => panic!("Expected VLESS proxy"),
         }
     }
+
+    #[test]
+    fn test_write_yaml_matches_serde_yaml() {
+        let nodes = vec![
+            create_test_node("shadowsocks", serde_json::json!({"method": "aes-256-gcm", "password": "pw"})),
+            create_test_node("trojan", serde_json::json!({"password": "pw", "sni": "example.com"})),
+        ];
+        let config = build_clash_config(&nodes);
+        let empty = ClashConfig {
+            proxies: Vec::new(),
+            proxy_groups: Vec::new(),
+            rules: Vec::new(),
+        };
+
+        for config in [&config, &empty] {
+            assert_eq!(config.to_yaml().unwrap(), serde_yaml::to_string(config).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_stream_chunks_match_yaml() {
+        let nodes: Vec<Node> = (0..2000)
+            .map(|_| create_test_node("shadowsocks", serde_json::json!({"method": "aes-256-gcm", "password": "pw"})))
+            .collect();
+        let config = build_clash_config(&nodes);
+        let expected = config.to_yaml().unwrap();
+
+        let chunks: Vec<Bytes> = YamlChunks {
+            config,
+            section: 0,
+            index: 0,
+            buf: Vec::new(),
+        }
+        .collect::<io::Result<_>>()
+        .unwrap();
+
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), expected.into_bytes());
+    }
+
+    #[test]
+    fn test_render_with_update_interval() {
+        let config = build_clash_config(&[]);
+        let rendered = render_with_update_interval(&config, 6).unwrap();
+
+        assert_eq!(parse_update_interval(&rendered), Some(6));
+        assert_eq!(rendered, with_update_interval(&config.to_yaml().unwrap(), 6));
+    }
+
+    #[test]
+    fn test_warn_if_oversized() {
+        assert!(warn_if_oversized(2048, 1024, "test"));
+        assert!(!warn_if_oversized(512, 1024, "test"));
+        assert!(!warn_if_oversized(2048, 0, "test"));
+    }
+
+    #[test]
+    fn test_update_interval_roundtrip() {
+        let config = with_update_interval("proxies: []\n", 12);
+
+        assert!(config.starts_with("# profile-update-interval: 12\n"));
+        assert_eq!(parse_update_interval(&config), Some(12));
+
+        // Comment line must not break YAML parsing
+        let parsed: serde_yaml::Value = serde_yaml::from_str(&config).unwrap();
+        assert!(parsed["proxies"].is_sequence());
+    }
+
+    #[test]
+    fn test_parse_update_interval_missing() {
+        assert_eq!(parse_update_interval("proxies: []\n"), None);
+        assert_eq!(parse_update_interval(""), None);
+    }
+
+    #[test]
+    fn test_parse_rules_text_yaml_block() {
+        let text = "rules:\n  - DOMAIN-SUFFIX,google.com,Proxy\n  - 'IP-CIDR,10.0.0.0/8,DIRECT,no-resolve'\n  # comment\n\n  - MATCH,Proxy\n";
+        let (rules, errors) = parse_rules_text(text);
+
+        assert!(errors.is_empty());
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules[0].0, 2);
+        assert_eq!(rules[0].1.rule_type, "DOMAIN-SUFFIX");
+        assert_eq!(rules[0].1.rule_value.as_deref(), Some("google.com"));
+        assert!(rules[1].1.no_resolve);
+        assert_eq!(rules[2].1.rule_value, None);
+        assert_eq!(rules[2].1.proxy_group, "Proxy");
+    }
+
+    #[test]
+    fn test_parse_rules_text_reports_errors() {
+        let text = "DOMAIN,example.com,Proxy\nUNKNOWN,foo,Proxy\nDOMAIN,example.org\nMATCH\nGEOIP,CN,DIRECT,bogus";
+        let (rules, errors) = parse_rules_text(text);
+
+        assert_eq!(rules.len(), 1);
+        assert_eq!(errors.iter().map(|e| e.line).collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_format_rule_roundtrip() {
+        let now = Utc::now();
+        let rule = crate::models::ClashRule {
+            id: 1,
+            rule_type: "IP-CIDR".to_string(),
+            rule_value: Some("10.0.0.0/8".to_string()),
+            proxy_group: "DIRECT".to_string(),
+            no_resolve: true,
+            is_active: true,
+            sort_order: 0,
+            description: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let line = format_rule(&rule);
+        assert_eq!(line, "IP-CIDR,10.0.0.0/8,DIRECT,no-resolve");
+
+        let parsed = parse_rule_line(&line).unwrap();
+        assert_eq!(parsed.rule_value, rule.rule_value);
+        assert!(parsed.no_resolve);
+
+        // MATCH rules stored with an empty value must not produce an empty field
+        let match_rule = crate::models::ClashRule {
+            rule_type: "MATCH".to_string(),
+            rule_value: Some(String::new()),
+            no_resolve: false,
+            ..rule
+        };
+        assert_eq!(format_rule(&match_rule), "MATCH,DIRECT");
+    }
+
+    #[test]
+    fn test_find_unresolved_references() {
+        let now = Utc::now();
+        let group = |id: i64, name: &str, proxies: &[&str]| crate::models::ClashProxyGroup {
+            id,
+            name: name.to_string(),
+            group_type: "select".to_string(),
+            proxies: proxies.iter().map(|p| p.to_string()).collect(),
+            url: None,
+            interval: None,
+            tolerance: None,
+            is_active: true,
+            sort_order: 0,
+            created_at: now,
+            updated_at: now,
+        };
+        let rule = |id: i64, target: &str| crate::models::ClashRule {
+            id,
+            rule_type: "DOMAIN".to_string(),
+            rule_value: Some("example.com".to_string()),
+            proxy_group: target.to_string(),
+            no_resolve: false,
+            is_active: true,
+            sort_order: 0,
+            description: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let proxies = vec!["HK-01".to_string()];
+        let groups = vec![
+            group(1, "Proxy", &["HK-01", "DIRECT", "Auto"]),
+            group(2, "Auto", &["HK-01", "JP-01", "Auto"]),
+            group(3, "Streaming", &["tag:streaming", "protocol:vless", "all"]),
+        ];
+        let rules = vec![rule(1, "Proxy"), rule(2, "REJECT"), rule(3, "Missing")];
+
+        let issues = find_unresolved_references(&proxies, &groups, &rules);
+        let missing: Vec<(&str, &str)> = issues
+            .iter()
+            .map(|i| (i.source_type, i.missing.as_str()))
+            .collect();
+
+        assert_eq!(
+            missing,
+            vec![("proxy_group", "JP-01"), ("proxy_group", "Auto"), ("rule", "Missing")]
+        );
+    }
+
+    #[test]
+    fn test_member_expressions() {
+        assert_eq!(MemberExpression::parse("all"), Some(MemberExpression::All));
+        assert_eq!(MemberExpression::parse("tag:HK"), Some(MemberExpression::Tag("HK".to_string())));
+        assert_eq!(
+            MemberExpression::parse("protocol:vless"),
+            Some(MemberExpression::Protocol("vless".to_string()))
+        );
+        assert_eq!(MemberExpression::parse("tag:"), None);
+        assert_eq!(MemberExpression::parse("protocol:http"), None);
+        assert_eq!(MemberExpression::parse("HK-01"), None);
+
+        let mut hk = create_test_node("trojan", serde_json::json!({}));
+        hk.name = "HK-01".to_string();
+        hk.tags = vec!["HK".to_string(), "streaming".to_string()];
+        let mut jp = create_test_node("vless", serde_json::json!({}));
+        jp.name = "JP-01".to_string();
+        jp.tags = vec!["JP".to_string()];
+
+        assert!(MemberExpression::parse("tag:hk").unwrap().matches(&hk));
+        assert!(!MemberExpression::parse("tag:HK").unwrap().matches(&jp));
+        assert!(MemberExpression::parse("protocol:vless").unwrap().matches(&jp));
+
+        let members: Vec<String> = ["tag:streaming", "DIRECT", "all", "HK-01"]
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        assert_eq!(
+            expand_group_members(&members, &[&hk, &jp]),
+            vec!["HK-01", "DIRECT", "JP-01"]
+        );
+    }
+
+    #[test]
+    fn test_nodes_and_db_expands_members() {
+        let now = Utc::now();
+        let group = |name: &str, proxies: &[&str]| crate::models::ClashProxyGroup {
+            id: 1,
+            name: name.to_string(),
+            group_type: "select".to_string(),
+            proxies: proxies.iter().map(|p| p.to_string()).collect(),
+            url: None,
+            interval: None,
+            tolerance: None,
+            is_active: true,
+            sort_order: 0,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let mut hk = create_test_node("shadowsocks", serde_json::json!({"method": "aes-256-gcm", "password": "pw"}));
+        hk.name = "HK-01".to_string();
+        hk.tags = vec!["HK".to_string()];
+        let mut us = create_test_node("trojan", serde_json::json!({"password": "pw", "sni": "example.com"}));
+        us.name = "US-01".to_string();
+
+        let groups = vec![
+            group("Hong Kong", &["tag:HK"]),
+            group("Japan", &["tag:JP"]),
+            group("Proxy", &["Hong Kong", "all", "JP-Offline"]),
+        ];
+        let config = build_clash_config_from_nodes_and_db(&[hk, us], &groups, &[]);
+        let members: Vec<&[String]> = config.proxy_groups.iter().map(|g| g.proxies.as_slice()).collect();
+
+        assert_eq!(members[0], ["HK-01"]);
+        // No node carries the tag, so the group would otherwise be empty
+        assert_eq!(members[1], ["DIRECT"]);
+        // Names of proxies that aren't in this config are dropped
+        assert_eq!(members[2], ["Hong Kong", "HK-01", "US-01"]);
+    }
+
+    #[test]
+    fn test_failover_groups() {
+        let node = |id: i64, name: &str, backup_of: Option<i64>| {
+            let mut node = create_test_node("shadowsocks", serde_json::json!({"method": "aes-256-gcm", "password": "pw"}));
+            node.id = id;
+            node.name = name.to_string();
+            node.backup_of = backup_of;
+            node
+        };
+        let nodes = vec![
+            node(1, "HK-01", None),
+            node(2, "HK-02", Some(1)),
+            node(3, "JP-01", None),
+            node(4, "HK-03", Some(1)),
+            // Its primary is offline, so it's just another node
+            node(5, "US-02", Some(9)),
+        ];
+
+        let config = build_clash_config(&nodes);
+        let names: Vec<&str> = config.proxy_groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, ["Proxy", "Auto", "HK-01 (failover)"]);
+
+        let failover = &config.proxy_groups[2];
+        assert_eq!(failover.group_type, "fallback");
+        assert_eq!(failover.proxies, ["HK-01", "HK-02", "HK-03"]);
+        assert_eq!(failover.url.as_deref(), Some(FAILOVER_TEST_URL));
+
+        // Selecting the primary goes through its failover group; automatic groups keep the node
+        assert_eq!(config.proxy_groups[0].proxies, ["HK-01 (failover)", "HK-02", "JP-01", "HK-03", "US-02"]);
+        assert_eq!(config.proxy_groups[1].proxies[0], "HK-01");
+
+        let yaml = config.to_yaml().unwrap();
+        assert!(yaml.contains("interval: 300"));
+    }
+
+    #[test]
+    fn test_normalize_node_tags() {
+        let tags: Vec<String> = [" HK ", "hk", "streaming"].iter().map(|t| t.to_string()).collect();
+        assert_eq!(normalize_node_tags(&tags), Some(vec!["HK".to_string(), "streaming".to_string()]));
+
+        assert_eq!(normalize_node_tags(&[" ".to_string()]), None);
+        assert_eq!(normalize_node_tags(&["x".repeat(MAX_TAG_CHARS + 1)]), None);
+
+        let too_many: Vec<String> = (0..=MAX_NODE_TAGS).map(|i| format!("tag{}", i)).collect();
+        assert_eq!(normalize_node_tags(&too_many), None);
+    }
+
+    #[test]
+    fn test_sort_nodes_by_load() {
+        let node = |id: i64, current_users: i32, max_users: i32| Node {
+            id,
+            current_users,
+            max_users,
+            ..create_test_node("trojan", serde_json::json!({}))
+        };
+        let mut nodes = vec![node(1, 95, 100), node(2, 0, 0), node(3, 52, 100), node(4, 12, 100), node(5, 58, 100)];
+
+        assert_eq!(load_bucket(&nodes[0]), 9);
+        assert_eq!(load_bucket(&node(6, 150, 100)), 10);
+
+        sort_nodes(&mut nodes, ProxySort::Load, Utc::now());
+        let ids: Vec<i64> = nodes.iter().map(|n| n.id).collect();
+        // 52% and 58% share a bucket, so sort_order and then id decide
+        assert_eq!(ids, [2, 4, 3, 5, 1]);
+    }
+
+    #[test]
+    fn test_sort_nodes_by_latency() {
+        let now = Utc::now();
+        let node = |id: i64, latency: Option<i32>, hours_ago: i64| Node {
+            id,
+            probe_latency_ms: latency,
+            probed_at: Some(now - chrono::Duration::hours(hours_ago)),
+            ..create_test_node("trojan", serde_json::json!({}))
+        };
+        let mut nodes = vec![node(1, None, 1), node(2, Some(180), 1), node(3, Some(20), 48), node(4, Some(30), 1)];
+
+        assert_eq!(latency_bucket(&nodes[1], now), Some(3));
+        // Results older than LATENCY_MAX_AGE_HOURS are ignored
+        assert_eq!(latency_bucket(&nodes[2], now), None);
+
+        sort_nodes(&mut nodes, ProxySort::Latency, now);
+        let ids: Vec<i64> = nodes.iter().map(|n| n.id).collect();
+        assert_eq!(ids, [4, 2, 1, 3]);
+
+        let mut unchanged = vec![node(2, Some(180), 1), node(4, Some(30), 1)];
+        sort_nodes(&mut unchanged, ProxySort::Static, now);
+        assert_eq!(unchanged[0].id, 2);
+        assert_eq!(ProxySort::parse(" Latency"), Some(ProxySort::Latency));
+        assert_eq!(ProxySort::parse("random"), None);
+    }
+
+    #[test]
+    fn test_config_fingerprint() {
+        let nodes = vec![
+            create_test_node("trojan", serde_json::json!({})),
+            Node { id: 2, ..create_test_node("vless", serde_json::json!({})) },
+        ];
+        let inputs = ConfigInputs {
+            nodes: &nodes,
+            proxies: None,
+            proxy_groups: None,
+            rules: None,
+            flavor: ClientFlavor::Meta,
+            info_entries: &[],
+            update_interval_hours: DEFAULT_UPDATE_INTERVAL_HOURS,
+        };
+        let fingerprint = inputs.fingerprint();
+        assert_eq!(fingerprint.len(), 64);
+
+        // Load and heartbeats don't change the config
+        let busy: Vec<Node> = nodes.iter().map(|n| Node { current_users: 500, ..n.clone() }).collect();
+        assert_eq!(ConfigInputs { nodes: &busy, ..inputs }.fingerprint(), fingerprint);
+
+        // Credentials, order, flavor and the refresh interval do
+        let rotated: Vec<Node> = nodes.iter().map(|n| Node { secret: "rotated".to_string(), ..n.clone() }).collect();
+        assert_ne!(ConfigInputs { nodes: &rotated, ..inputs }.fingerprint(), fingerprint);
+        let reversed: Vec<Node> = nodes.iter().rev().cloned().collect();
+        assert_ne!(ConfigInputs { nodes: &reversed, ..inputs }.fingerprint(), fingerprint);
+        assert_ne!(ConfigInputs { flavor: ClientFlavor::Clash, ..inputs }.fingerprint(), fingerprint);
+        assert_ne!(ConfigInputs { update_interval_hours: 6, ..inputs }.fingerprint(), fingerprint);
+        assert_ne!(ConfigInputs { rules: Some(&[]), ..inputs }.fingerprint(), fingerprint);
+    }
+
+    #[test]
+    fn test_country_flag() {
+        assert_eq!(country_flag("hk").as_deref(), Some("🇭🇰"));
+        assert_eq!(country_flag("US").as_deref(), Some("🇺🇸"));
+        assert_eq!(country_flag("USA"), None);
+        assert_eq!(country_flag("1A"), None);
+    }
+
+    #[test]
+    fn test_format_proxy_name() {
+        let node = create_test_node(
+            "trojan",
+            serde_json::json!({ "country": "jp", "region": "Tokyo", "traffic_ratio": 1.5 }),
+        );
+
+        assert_eq!(format_proxy_name(&node, &NamingOptions::default()), "Test trojan Node");
+
+        let all = NamingOptions { emoji_flag: true, region_prefix: true, show_traffic_ratio: true };
+        assert_eq!(format_proxy_name(&node, &all), "🇯🇵 Tokyo | Test trojan Node [1.5x]");
+
+        let region_only = NamingOptions { region_prefix: true, ..Default::default() };
+        let no_region = create_test_node("trojan", serde_json::json!({ "country": "sg" }));
+        assert_eq!(format_proxy_name(&no_region, &region_only), "SG | Test trojan Node");
+    }
+
+    #[test]
+    fn test_format_proxy_name_without_metadata() {
+        let node = create_test_node("trojan", serde_json::json!({}));
+        let all = NamingOptions { emoji_flag: true, region_prefix: true, show_traffic_ratio: true };
+
+        assert_eq!(format_proxy_name(&node, &all), "Test trojan Node");
+    }
+
+    #[test]
+    fn test_format_traffic() {
+        assert_eq!(format_traffic(TrafficBytes::new(512)), "512B");
+        assert_eq!(format_traffic(TrafficBytes::new(1536)), "1.5KB");
+        assert_eq!(format_traffic(TrafficBytes::new(41_016_602_419)), "38.2GB");
+        assert_eq!(format_traffic(TrafficBytes::new(-5)), "0B");
+    }
+
+    #[test]
+    fn test_prepend_info_proxies() {
+        let expires_at = chrono::DateTime::parse_from_rfc3339("2025-01-01T08:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let names = info_entry_names(TrafficBytes::new(41_016_602_419), expires_at, Lang::Zh);
+        assert_eq!(names, vec!["剩余流量: 38.2GB", "到期时间: 2025-01-01"]);
+
+        let mut config = config_for_flavor_tests();
+        config.prepend_info_proxies(&names);
+
+        let proxy_names: Vec<String> = config.proxies.iter().map(get_proxy_name).collect();
+        assert_eq!(&proxy_names[..2], &names[..]);
+        assert_eq!(&config.proxy_groups[0].proxies[..2], &names[..]);
+        // Automatic groups never pick an informational entry
+        assert_eq!(config.proxy_groups[1].proxies, vec!["Hy2", "Reality"]);
+        // Legacy clients keep the entries
+        config.retain_supported(ClientFlavor::Clash);
+        assert!(config.proxies.iter().any(|p| get_proxy_name(p) == names[0]));
+    }
 }
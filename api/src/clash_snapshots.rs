@@ -0,0 +1,309 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::db;
+use crate::models::{ClashConfigSnapshot, ClashProxyGroup, ClashRule};
+
+/// A proxy group as stored in a snapshot (no id or timestamps)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupEntry {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub group_type: String,
+    pub proxies: Vec<String>,
+    pub url: Option<String>,
+    pub interval: Option<i32>,
+    pub tolerance: Option<i32>,
+    pub is_active: bool,
+    pub sort_order: i32,
+}
+
+impl From<&ClashProxyGroup> for GroupEntry {
+    fn from(group: &ClashProxyGroup) -> Self {
+        Self {
+            name: group.name.clone(),
+            group_type: group.group_type.clone(),
+            proxies: group.proxies.clone(),
+            url: group.url.clone(),
+            interval: group.interval,
+            tolerance: group.tolerance,
+            is_active: group.is_active,
+            sort_order: group.sort_order,
+        }
+    }
+}
+
+/// A routing rule as stored in a snapshot (no id or timestamps)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleEntry {
+    pub rule_type: String,
+    pub rule_value: Option<String>,
+    pub proxy_group: String,
+    pub no_resolve: bool,
+    pub is_active: bool,
+    pub sort_order: i32,
+    pub description: Option<String>,
+}
+
+impl From<&ClashRule> for RuleEntry {
+    fn from(rule: &ClashRule) -> Self {
+        Self {
+            rule_type: rule.rule_type.clone(),
+            rule_value: rule.rule_value.clone(),
+            proxy_group: rule.proxy_group.clone(),
+            no_resolve: rule.no_resolve,
+            is_active: rule.is_active,
+            sort_order: rule.sort_order,
+            description: rule.description.clone(),
+        }
+    }
+}
+
+/// The proxy group and rule tables at one point in time, inactive rows included
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotContent {
+    pub proxy_groups: Vec<GroupEntry>,
+    pub rules: Vec<RuleEntry>,
+}
+
+impl SnapshotContent {
+    pub fn new(groups: &[ClashProxyGroup], rules: &[ClashRule]) -> Self {
+        Self {
+            proxy_groups: groups.iter().map(GroupEntry::from).collect(),
+            rules: rules.iter().map(RuleEntry::from).collect(),
+        }
+    }
+
+    /// Decode the JSON columns of a stored snapshot
+    pub fn from_snapshot(snapshot: &ClashConfigSnapshot) -> Result<Self> {
+        Ok(Self {
+            proxy_groups: serde_json::from_value(snapshot.proxy_groups.clone())
+                .with_context(|| format!("Invalid proxy groups in Clash snapshot v{}", snapshot.version))?,
+            rules: serde_json::from_value(snapshot.rules.clone())
+                .with_context(|| format!("Invalid rules in Clash snapshot v{}", snapshot.version))?,
+        })
+    }
+}
+
+/// An entry present in both versions but with different settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change<T> {
+    pub before: T,
+    pub after: T,
+}
+
+/// Differences between two snapshots: groups are matched by name, rules by type and value
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SnapshotDiff {
+    pub groups_added: Vec<GroupEntry>,
+    pub groups_removed: Vec<GroupEntry>,
+    pub groups_changed: Vec<Change<GroupEntry>>,
+    pub rules_added: Vec<RuleEntry>,
+    pub rules_removed: Vec<RuleEntry>,
+    pub rules_changed: Vec<Change<RuleEntry>>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.groups_added.is_empty()
+            && self.groups_removed.is_empty()
+            && self.groups_changed.is_empty()
+            && self.rules_added.is_empty()
+            && self.rules_removed.is_empty()
+            && self.rules_changed.is_empty()
+    }
+}
+
+/// Match entries of two lists by key and sort them into added, removed and changed
+///
+/// Entries sharing a key are paired in list order; unpaired ones count as added or removed.
+fn diff_entries<T, K>(from: &[T], to: &[T], key: impl Fn(&T) -> K) -> (Vec<T>, Vec<T>, Vec<Change<T>>)
+where
+    T: Clone + PartialEq,
+    K: Ord,
+{
+    let mut unpaired: BTreeMap<K, VecDeque<usize>> = BTreeMap::new();
+    for (i, entry) in from.iter().enumerate() {
+        unpaired.entry(key(entry)).or_default().push_back(i);
+    }
+
+    let mut paired = vec![false; from.len()];
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    for entry in to {
+        match unpaired.get_mut(&key(entry)).and_then(VecDeque::pop_front) {
+            Some(i) => {
+                paired[i] = true;
+                if from[i] != *entry {
+                    changed.push(Change {
+                        before: from[i].clone(),
+                        after: entry.clone(),
+                    });
+                }
+            }
+            None => added.push(entry.clone()),
+        }
+    }
+
+    let removed = from
+        .iter()
+        .zip(paired)
+        .filter(|(_, paired)| !paired)
+        .map(|(entry, _)| entry.clone())
+        .collect();
+    (added, removed, changed)
+}
+
+/// Compare two snapshots, listing what `to` adds, removes or changes relative to `from`
+pub fn diff(from: &SnapshotContent, to: &SnapshotContent) -> SnapshotDiff {
+    let (groups_added, groups_removed, groups_changed) =
+        diff_entries(&from.proxy_groups, &to.proxy_groups, |g| g.name.clone());
+    let (rules_added, rules_removed, rules_changed) = diff_entries(&from.rules, &to.rules, |r| {
+        (r.rule_type.clone(), r.rule_value.clone().unwrap_or_default())
+    });
+
+    SnapshotDiff {
+        groups_added,
+        groups_removed,
+        groups_changed,
+        rules_added,
+        rules_removed,
+        rules_changed,
+    }
+}
+
+/// The current proxy group and rule tables
+pub async fn current(pool: &PgPool) -> Result<SnapshotContent> {
+    let groups = db::list_clash_proxy_groups(pool, false).await?;
+    let rules = db::list_clash_rules(pool, false).await?;
+    Ok(SnapshotContent::new(&groups, &rules))
+}
+
+/// Snapshot the current tables as a new version
+///
+/// Returns the latest version instead when nothing changed since it was taken, so
+/// repeated generation does not pile up identical versions.
+pub async fn record(pool: &PgPool, author_id: Option<i64>) -> Result<ClashConfigSnapshot> {
+    let content = current(pool).await?;
+    if let Some(latest) = db::get_latest_clash_config_snapshot(pool).await? {
+        if SnapshotContent::from_snapshot(&latest)? == content {
+            return Ok(latest);
+        }
+    }
+
+    db::create_clash_config_snapshot(
+        pool,
+        author_id,
+        &serde_json::to_value(&content.proxy_groups)?,
+        &serde_json::to_value(&content.rules)?,
+    )
+    .await
+}
+
+/// Restore the proxy group and rule tables to a stored version
+///
+/// The current tables are snapshotted first, so the rollback itself can be undone.
+/// Returns `None` if the version does not exist.
+pub async fn rollback(pool: &PgPool, version: i32, author_id: Option<i64>) -> Result<Option<ClashConfigSnapshot>> {
+    let Some(snapshot) = db::get_clash_config_snapshot(pool, version).await? else {
+        return Ok(None);
+    };
+    let content = SnapshotContent::from_snapshot(&snapshot)?;
+
+    record(pool, author_id).await?;
+    db::restore_clash_config(pool, &content).await?;
+
+    Ok(Some(snapshot))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(name: &str, proxies: &[&str]) -> GroupEntry {
+        GroupEntry {
+            name: name.to_string(),
+            group_type: "select".to_string(),
+            proxies: proxies.iter().map(|p| p.to_string()).collect(),
+            url: None,
+            interval: None,
+            tolerance: None,
+            is_active: true,
+            sort_order: 0,
+        }
+    }
+
+    fn rule(rule_type: &str, value: Option<&str>, target: &str) -> RuleEntry {
+        RuleEntry {
+            rule_type: rule_type.to_string(),
+            rule_value: value.map(str::to_string),
+            proxy_group: target.to_string(),
+            no_resolve: false,
+            is_active: true,
+            sort_order: 0,
+            description: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_identical_snapshots_is_empty() {
+        let content = SnapshotContent {
+            proxy_groups: vec![group("Proxy", &["all"])],
+            rules: vec![rule("MATCH", None, "Proxy")],
+        };
+        assert!(diff(&content, &content).is_empty());
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let from = SnapshotContent {
+            proxy_groups: vec![group("Proxy", &["all"]), group("Streaming", &["tag:hk"])],
+            rules: vec![
+                rule("DOMAIN-SUFFIX", Some("google.com"), "Proxy"),
+                rule("GEOIP", Some("CN"), "DIRECT"),
+                rule("MATCH", None, "Proxy"),
+            ],
+        };
+        let to = SnapshotContent {
+            proxy_groups: vec![group("Proxy", &["all", "DIRECT"]), group("Auto", &["all"])],
+            rules: vec![
+                rule("DOMAIN-SUFFIX", Some("google.com"), "Proxy"),
+                rule("GEOIP", Some("CN"), "Proxy"),
+                rule("DOMAIN", Some("example.com"), "DIRECT"),
+            ],
+        };
+
+        let diff = diff(&from, &to);
+        assert_eq!(diff.groups_added, vec![group("Auto", &["all"])]);
+        assert_eq!(diff.groups_removed, vec![group("Streaming", &["tag:hk"])]);
+        assert_eq!(
+            diff.groups_changed,
+            vec![Change {
+                before: group("Proxy", &["all"]),
+                after: group("Proxy", &["all", "DIRECT"]),
+            }]
+        );
+        assert_eq!(diff.rules_added, vec![rule("DOMAIN", Some("example.com"), "DIRECT")]);
+        assert_eq!(diff.rules_removed, vec![rule("MATCH", None, "Proxy")]);
+        assert_eq!(diff.rules_changed.len(), 1);
+        assert_eq!(diff.rules_changed[0].after.proxy_group, "Proxy");
+    }
+
+    #[test]
+    fn test_diff_pairs_duplicate_rules_in_order() {
+        let from = SnapshotContent {
+            proxy_groups: vec![],
+            rules: vec![rule("DOMAIN", Some("a.com"), "Proxy"), rule("DOMAIN", Some("a.com"), "DIRECT")],
+        };
+        let to = SnapshotContent {
+            proxy_groups: vec![],
+            rules: vec![rule("DOMAIN", Some("a.com"), "Proxy")],
+        };
+
+        let diff = diff(&from, &to);
+        assert!(diff.rules_added.is_empty() && diff.rules_changed.is_empty());
+        assert_eq!(diff.rules_removed, vec![rule("DOMAIN", Some("a.com"), "DIRECT")]);
+    }
+}
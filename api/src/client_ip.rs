@@ -0,0 +1,141 @@
+use anyhow::{Context, Result};
+use axum::http::HeaderMap;
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+/// Parse `TRUSTED_PROXIES`: comma-separated networks (`10.0.0.0/8`) or single addresses
+pub fn parse_trusted_proxies(value: &str) -> Result<Vec<IpNet>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<IpNet>()
+                .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+                .with_context(|| format!("'{}' is not an IP address or network", s))
+        })
+        .collect()
+}
+
+fn is_trusted(ip: IpAddr, trusted: &[IpNet]) -> bool {
+    trusted.iter().any(|net| net.contains(&ip))
+}
+
+/// Address of the client behind `peer`, the socket the request arrived on
+///
+/// Forwarding headers are only believed when `peer` is a trusted proxy. The
+/// `X-Forwarded-For` chain is then walked from the right, skipping trusted hops, so a
+/// client can prepend whatever it likes without changing the result. `X-Real-IP` is
+/// used when the proxy sends no chain.
+pub fn resolve(peer: IpAddr, headers: &HeaderMap, trusted: &[IpNet]) -> IpAddr {
+    if !is_trusted(peer, trusted) {
+        return peer;
+    }
+
+    let hops: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    if hops.is_empty() {
+        return headers
+            .get("x-real-ip")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(peer);
+    }
+
+    let mut client = peer;
+    for hop in hops.iter().rev() {
+        // A hop that doesn't parse was written by someone we can't vouch for
+        let Ok(ip) = hop.parse::<IpAddr>() else {
+            return client;
+        };
+        client = ip;
+        if !is_trusted(ip, trusted) {
+            break;
+        }
+    }
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_trusted_proxies() {
+        let trusted = parse_trusted_proxies(" 10.0.0.0/8, 127.0.0.1 ,::1,").unwrap();
+        assert_eq!(trusted.len(), 3);
+        assert!(is_trusted(ip("10.1.2.3"), &trusted));
+        assert!(is_trusted(ip("::1"), &trusted));
+        assert!(!is_trusted(ip("127.0.0.2"), &trusted));
+        assert!(parse_trusted_proxies("").unwrap().is_empty());
+        assert!(parse_trusted_proxies("10.0.0.0/8,proxy").is_err());
+    }
+
+    #[test]
+    fn test_untrusted_peer_ignores_headers() {
+        let forged = headers(&[("x-forwarded-for", "1.2.3.4"), ("x-real-ip", "5.6.7.8")]);
+        assert_eq!(resolve(ip("203.0.113.9"), &forged, &[]), ip("203.0.113.9"));
+
+        let trusted = parse_trusted_proxies("10.0.0.0/8").unwrap();
+        assert_eq!(resolve(ip("203.0.113.9"), &forged, &trusted), ip("203.0.113.9"));
+    }
+
+    #[test]
+    fn test_rightmost_untrusted_hop() {
+        let trusted = parse_trusted_proxies("10.0.0.0/8").unwrap();
+        let peer = ip("10.0.0.1");
+
+        let single = headers(&[("x-forwarded-for", "203.0.113.9")]);
+        assert_eq!(resolve(peer, &single, &trusted), ip("203.0.113.9"));
+
+        // A client-supplied value to the left of the real hop is ignored
+        let spoofed = headers(&[("x-forwarded-for", "1.2.3.4, 203.0.113.9, 10.0.0.2")]);
+        assert_eq!(resolve(peer, &spoofed, &trusted), ip("203.0.113.9"));
+
+        let split = headers(&[("x-forwarded-for", "1.2.3.4"), ("x-forwarded-for", "2001:db8::1")]);
+        assert_eq!(resolve(peer, &split, &trusted), ip("2001:db8::1"));
+
+        let all_trusted = headers(&[("x-forwarded-for", "10.0.0.3, 10.0.0.2")]);
+        assert_eq!(resolve(peer, &all_trusted, &trusted), ip("10.0.0.3"));
+    }
+
+    #[test]
+    fn test_unparseable_hop_stops_the_walk() {
+        let trusted = parse_trusted_proxies("10.0.0.0/8").unwrap();
+        let peer = ip("10.0.0.1");
+
+        let garbage = headers(&[("x-forwarded-for", "1.2.3.4, unknown")]);
+        assert_eq!(resolve(peer, &garbage, &trusted), peer);
+
+        let behind = headers(&[("x-forwarded-for", "unknown, 10.0.0.2")]);
+        assert_eq!(resolve(peer, &behind, &trusted), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn test_x_real_ip_fallback() {
+        let trusted = parse_trusted_proxies("10.0.0.0/8").unwrap();
+        let peer = ip("10.0.0.1");
+
+        assert_eq!(resolve(peer, &headers(&[("x-real-ip", " 203.0.113.9 ")]), &trusted), ip("203.0.113.9"));
+        assert_eq!(resolve(peer, &headers(&[("x-real-ip", "nope")]), &trusted), peer);
+        assert_eq!(resolve(peer, &HeaderMap::new(), &trusted), peer);
+    }
+}
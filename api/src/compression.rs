@@ -0,0 +1,197 @@
+use anyhow::{Context, Result};
+use axum::http::{header, HeaderMap};
+use std::io::Write;
+use tower_http::compression::predicate::{DefaultPredicate, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+
+/// Bodies smaller than this are sent uncompressed
+pub const MIN_COMPRESS_BYTES: u16 = 1024;
+
+/// Brotli quality for subscription configs: compressed once per config and then cached,
+/// so a better ratio is worth more than speed
+const BROTLI_QUALITY: u32 = 9;
+const BROTLI_WINDOW_BITS: u32 = 22;
+
+/// Content codings the API compresses with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// In order of preference when a client accepts several equally
+    pub const ALL: [Encoding; 2] = [Encoding::Brotli, Encoding::Gzip];
+
+    /// `Content-Encoding` token
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    pub fn compress(self, body: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut writer =
+                    brotli::CompressorWriter::new(Vec::new(), 4096, BROTLI_QUALITY, BROTLI_WINDOW_BITS);
+                writer.write_all(body).context("Failed to compress with brotli")?;
+                Ok(writer.into_inner())
+            }
+            Encoding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body).context("Failed to compress with gzip")?;
+                encoder.finish().context("Failed to compress with gzip")
+            }
+        }
+    }
+}
+
+/// Quality the client gave a coding in `Accept-Encoding` (RFC 9110 §12.5.3)
+///
+/// An explicit entry wins over `*`; codings not mentioned at all are not acceptable.
+fn quality(accept_encoding: &str, encoding: Encoding) -> f32 {
+    let mut explicit = None;
+    let mut wildcard = None;
+
+    for entry in accept_encoding.split(',') {
+        let mut params = entry.split(';');
+        let coding = params.next().unwrap_or_default().trim().to_ascii_lowercase();
+        let q = params
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())
+            .unwrap_or(0.0);
+
+        let matches = match encoding {
+            Encoding::Brotli => coding == "br",
+            Encoding::Gzip => coding == "gzip" || coding == "x-gzip",
+        };
+        if matches {
+            explicit = Some(q);
+        } else if coding == "*" {
+            wildcard = Some(q);
+        }
+    }
+
+    explicit.or(wildcard).unwrap_or(0.0)
+}
+
+/// Coding to send a response in, from the request's `Accept-Encoding`
+///
+/// `None` means identity: the header is missing or no supported coding is acceptable.
+pub fn negotiate(headers: &HeaderMap) -> Option<Encoding> {
+    let accept_encoding = headers.get(header::ACCEPT_ENCODING)?.to_str().ok()?;
+
+    // Stable max, so Brotli wins ties
+    Encoding::ALL
+        .into_iter()
+        .map(|encoding| (encoding, quality(accept_encoding, encoding)))
+        .filter(|(_, q)| *q > 0.0)
+        .fold(None, |best: Option<(Encoding, f32)>, (encoding, q)| match best {
+            Some((_, best_q)) if best_q >= q => best,
+            _ => Some((encoding, q)),
+        })
+        .map(|(encoding, _)| encoding)
+}
+
+/// Response compression for the whole router
+///
+/// Subscription configs are compressed by their handler, so each encoding gets its own
+/// ETag and the compressed bytes are cached with the config; the layer leaves responses
+/// that already carry `Content-Encoding` alone and skips bodies under
+/// [`MIN_COMPRESS_BYTES`], so it never touches a subscription response.
+pub fn layer() -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new().compress_when(DefaultPredicate::new().and(SizeAbove::new(MIN_COMPRESS_BYTES)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn accepting(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_ENCODING, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_negotiate_clash_clients() {
+        // Go clients (Clash, Clash.Meta / mihomo) add gzip transparently
+        assert_eq!(negotiate(&accepting("gzip")), Some(Encoding::Gzip));
+        // Apple URLSession clients (Stash, Shadowrocket)
+        assert_eq!(negotiate(&accepting("br, gzip, deflate")), Some(Encoding::Brotli));
+        assert_eq!(negotiate(&accepting("gzip, deflate, br")), Some(Encoding::Brotli));
+        // Clients that don't send the header at all get the plain config
+        assert_eq!(negotiate(&HeaderMap::new()), None);
+        assert_eq!(negotiate(&accepting("identity")), None);
+        assert_eq!(negotiate(&accepting("deflate")), None);
+    }
+
+    #[test]
+    fn test_negotiate_quality_values() {
+        assert_eq!(negotiate(&accepting("gzip;q=1.0, br;q=0.5")), Some(Encoding::Gzip));
+        assert_eq!(negotiate(&accepting("GZIP; q=0.8, br;q=0.9")), Some(Encoding::Brotli));
+        assert_eq!(negotiate(&accepting("gzip;q=0, br;q=0")), None);
+        assert_eq!(negotiate(&accepting("x-gzip")), Some(Encoding::Gzip));
+        assert_eq!(negotiate(&accepting("*")), Some(Encoding::Brotli));
+        assert_eq!(negotiate(&accepting("*;q=0.5, br;q=0")), Some(Encoding::Gzip));
+        // A malformed quality makes that coding unacceptable
+        assert_eq!(negotiate(&accepting("br;q=high, gzip")), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        let body = "proxies:\n  - name: node\n    type: trojan\n".repeat(100);
+
+        let gzip = Encoding::Gzip.compress(body.as_bytes()).unwrap();
+        assert!(gzip.len() < body.len());
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&gzip[..]).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, body);
+
+        let brotli = Encoding::Brotli.compress(body.as_bytes()).unwrap();
+        assert!(brotli.len() < body.len());
+        let mut decoded = String::new();
+        brotli::Decompressor::new(&brotli[..], 4096).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, body);
+    }
+
+    #[tokio::test]
+    async fn test_layer_skips_small_and_encoded_responses() {
+        use axum::body::Body;
+        use axum::http::Request;
+        use axum::{routing::get, Router};
+        use tower::ServiceExt;
+
+        let large = "x".repeat(usize::from(MIN_COMPRESS_BYTES) * 2);
+        let app = Router::new()
+            .route("/small", get(|| async { "small" }))
+            .route("/large", get(move || async move { large }))
+            .route(
+                "/encoded",
+                get(|| async { ([(header::CONTENT_ENCODING, "br")], vec![0u8; 4096]) }),
+            )
+            .layer(layer());
+        let request = |uri: &str| {
+            Request::builder()
+                .uri(uri)
+                .header(header::ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request("/large")).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+
+        let response = app.clone().oneshot(request("/small")).await.unwrap();
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+
+        // Already compressed by the handler: passed through untouched
+        let response = app.oneshot(request("/encoded")).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+        let body = axum::body::to_bytes(response.into_body(), 8192).await.unwrap();
+        assert_eq!(body.len(), 4096);
+    }
+}
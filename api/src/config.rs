@@ -1,6 +1,1231 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use std::env;
 
+use crate::i18n::Lang;
+use crate::password_policy::{CharClass, MAX_PASSWORD_LEN};
+
+/// Database connection pool settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DbPoolConfig {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    /// How long to wait for a free connection before failing (seconds)
+    pub acquire_timeout_secs: u64,
+    /// Server-side statement timeout (milliseconds, 0 = no limit)
+    pub statement_timeout_ms: u64,
+}
+
+impl Default for DbPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout_secs: 30,
+            statement_timeout_ms: 0,
+        }
+    }
+}
+
+impl DbPoolConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        Ok(DbPoolConfig {
+            max_connections: env::var("DB_MAX_CONNECTIONS")
+                .unwrap_or_else(|_| defaults.max_connections.to_string())
+                .parse()
+                .context("DB_MAX_CONNECTIONS must be a valid number")?,
+            min_connections: env::var("DB_MIN_CONNECTIONS")
+                .unwrap_or_else(|_| defaults.min_connections.to_string())
+                .parse()
+                .context("DB_MIN_CONNECTIONS must be a valid number")?,
+            acquire_timeout_secs: env::var("DB_ACQUIRE_TIMEOUT_SECS")
+                .unwrap_or_else(|_| defaults.acquire_timeout_secs.to_string())
+                .parse()
+                .context("DB_ACQUIRE_TIMEOUT_SECS must be a valid number")?,
+            statement_timeout_ms: env::var("DB_STATEMENT_TIMEOUT_MS")
+                .unwrap_or_else(|_| defaults.statement_timeout_ms.to_string())
+                .parse()
+                .context("DB_STATEMENT_TIMEOUT_MS must be a valid number")?,
+        })
+    }
+}
+
+/// Redis cache TTLs (seconds)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheTtlConfig {
+    pub user_package_secs: u64,
+    pub active_nodes_secs: u64,
+    pub subscription_config_secs: u64,
+    /// How long a token's last served config is kept for database outages (0 disables)
+    pub subscription_stale_secs: u64,
+    pub status_page_secs: u64,
+}
+
+impl Default for CacheTtlConfig {
+    fn default() -> Self {
+        Self {
+            user_package_secs: 300,
+            active_nodes_secs: 60,
+            subscription_config_secs: 300,
+            subscription_stale_secs: 3600,
+            status_page_secs: 30,
+        }
+    }
+}
+
+impl CacheTtlConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        Ok(CacheTtlConfig {
+            user_package_secs: env::var("CACHE_TTL_USER_PACKAGE_SECS")
+                .unwrap_or_else(|_| defaults.user_package_secs.to_string())
+                .parse()
+                .context("CACHE_TTL_USER_PACKAGE_SECS must be a valid number")?,
+            active_nodes_secs: env::var("CACHE_TTL_ACTIVE_NODES_SECS")
+                .unwrap_or_else(|_| defaults.active_nodes_secs.to_string())
+                .parse()
+                .context("CACHE_TTL_ACTIVE_NODES_SECS must be a valid number")?,
+            subscription_config_secs: env::var("CACHE_TTL_SUBSCRIPTION_SECS")
+                .unwrap_or_else(|_| defaults.subscription_config_secs.to_string())
+                .parse()
+                .context("CACHE_TTL_SUBSCRIPTION_SECS must be a valid number")?,
+            subscription_stale_secs: env::var("CACHE_TTL_SUBSCRIPTION_STALE_SECS")
+                .unwrap_or_else(|_| defaults.subscription_stale_secs.to_string())
+                .parse()
+                .context("CACHE_TTL_SUBSCRIPTION_STALE_SECS must be a valid number")?,
+            status_page_secs: env::var("CACHE_TTL_STATUS_PAGE_SECS")
+                .unwrap_or_else(|_| defaults.status_page_secs.to_string())
+                .parse()
+                .context("CACHE_TTL_STATUS_PAGE_SECS must be a valid number")?,
+        })
+    }
+}
+
+/// Sign-up restrictions enforced by the register endpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistrationPolicyConfig {
+    /// If non-empty, only these email domains (and their subdomains) may register
+    pub allowed_email_domains: Vec<String>,
+    /// Email domains (and their subdomains) that may never register
+    pub denied_email_domains: Vec<String>,
+    /// Reject addresses from known disposable email providers
+    pub block_disposable_emails: bool,
+    /// Optional file of extra disposable domains (one per line), re-read periodically
+    pub disposable_domains_file: Option<String>,
+    /// How often the disposable domain file is re-read (seconds)
+    pub disposable_domains_refresh_secs: u64,
+    /// Require a valid referral code to register
+    pub invite_only: bool,
+    /// Maximum registration attempts per client IP per window (0 = unlimited)
+    pub max_registrations_per_ip: u32,
+    /// Window for the per-IP registration cap (seconds)
+    pub registration_ip_window_secs: u64,
+}
+
+impl Default for RegistrationPolicyConfig {
+    fn default() -> Self {
+        Self {
+            allowed_email_domains: Vec::new(),
+            denied_email_domains: Vec::new(),
+            block_disposable_emails: true,
+            disposable_domains_file: None,
+            disposable_domains_refresh_secs: 3600,
+            invite_only: false,
+            max_registrations_per_ip: 5,
+            registration_ip_window_secs: 3600,
+        }
+    }
+}
+
+impl RegistrationPolicyConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        Ok(RegistrationPolicyConfig {
+            allowed_email_domains: domain_list("REGISTRATION_ALLOWED_DOMAINS"),
+            denied_email_domains: domain_list("REGISTRATION_DENIED_DOMAINS"),
+            block_disposable_emails: env::var("REGISTRATION_BLOCK_DISPOSABLE")
+                .unwrap_or_else(|_| defaults.block_disposable_emails.to_string())
+                .parse()
+                .context("REGISTRATION_BLOCK_DISPOSABLE must be 'true' or 'false'")?,
+            disposable_domains_file: env::var("DISPOSABLE_EMAIL_DOMAINS_FILE")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            disposable_domains_refresh_secs: env::var("DISPOSABLE_EMAIL_DOMAINS_REFRESH_SECS")
+                .unwrap_or_else(|_| defaults.disposable_domains_refresh_secs.to_string())
+                .parse()
+                .context("DISPOSABLE_EMAIL_DOMAINS_REFRESH_SECS must be a valid number")?,
+            invite_only: env::var("REGISTRATION_INVITE_ONLY")
+                .unwrap_or_else(|_| defaults.invite_only.to_string())
+                .parse()
+                .context("REGISTRATION_INVITE_ONLY must be 'true' or 'false'")?,
+            max_registrations_per_ip: env::var("REGISTRATION_MAX_PER_IP")
+                .unwrap_or_else(|_| defaults.max_registrations_per_ip.to_string())
+                .parse()
+                .context("REGISTRATION_MAX_PER_IP must be a valid number")?,
+            registration_ip_window_secs: env::var("REGISTRATION_IP_WINDOW_SECS")
+                .unwrap_or_else(|_| defaults.registration_ip_window_secs.to_string())
+                .parse()
+                .context("REGISTRATION_IP_WINDOW_SECS must be a valid number")?,
+        })
+    }
+}
+
+/// Rules for passwords chosen at sign-up or set by an admin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicyConfig {
+    /// Shortest password accepted (characters)
+    pub min_length: usize,
+    /// Character classes every password must contain
+    pub required_classes: Vec<CharClass>,
+    /// Reject passwords containing the local part of the account's email
+    pub disallow_email: bool,
+    /// Reject passwords on the common password blacklist
+    pub block_common: bool,
+    /// Optional file of extra common passwords (one per line), read at startup
+    pub blacklist_file: Option<String>,
+}
+
+impl Default for PasswordPolicyConfig {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            required_classes: vec![CharClass::Letter, CharClass::Digit],
+            disallow_email: true,
+            block_common: true,
+            blacklist_file: None,
+        }
+    }
+}
+
+impl PasswordPolicyConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let required_classes = match env::var("PASSWORD_REQUIRED_CLASSES") {
+            Ok(classes) => classes
+                .split(',')
+                .map(str::trim)
+                .filter(|class| !class.is_empty())
+                .map(|class| {
+                    CharClass::parse(class).ok_or_else(|| {
+                        anyhow!(
+                            "PASSWORD_REQUIRED_CLASSES must list letter, lower, upper, digit or symbol, got '{}'",
+                            class
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?,
+            Err(_) => defaults.required_classes,
+        };
+        let config = PasswordPolicyConfig {
+            min_length: env::var("PASSWORD_MIN_LENGTH")
+                .unwrap_or_else(|_| defaults.min_length.to_string())
+                .parse()
+                .context("PASSWORD_MIN_LENGTH must be a valid number")?,
+            required_classes,
+            disallow_email: env::var("PASSWORD_DISALLOW_EMAIL")
+                .unwrap_or_else(|_| defaults.disallow_email.to_string())
+                .parse()
+                .context("PASSWORD_DISALLOW_EMAIL must be 'true' or 'false'")?,
+            block_common: env::var("PASSWORD_BLOCK_COMMON")
+                .unwrap_or_else(|_| defaults.block_common.to_string())
+                .parse()
+                .context("PASSWORD_BLOCK_COMMON must be 'true' or 'false'")?,
+            blacklist_file: env::var("PASSWORD_BLACKLIST_FILE").ok().filter(|s| !s.is_empty()),
+        };
+
+        if config.min_length == 0 || config.min_length > MAX_PASSWORD_LEN {
+            anyhow::bail!("PASSWORD_MIN_LENGTH must be between 1 and {}", MAX_PASSWORD_LEN);
+        }
+
+        Ok(config)
+    }
+}
+
+/// Freshness, replay and lockout rules for node agent requests
+#[derive(Debug, Clone)]
+pub struct NodeAuthConfig {
+    /// Maximum difference between a request's timestamp and server time (seconds)
+    pub max_clock_skew_secs: i64,
+    /// Failed authentications (bad secret or replayed nonce) before a node is suspended
+    pub max_failures: u32,
+    /// Window in which failures are counted (seconds)
+    pub failure_window_secs: u64,
+}
+
+impl Default for NodeAuthConfig {
+    fn default() -> Self {
+        Self {
+            max_clock_skew_secs: 300,
+            max_failures: 10,
+            failure_window_secs: 900,
+        }
+    }
+}
+
+impl NodeAuthConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        Ok(NodeAuthConfig {
+            max_clock_skew_secs: env::var("NODE_AUTH_MAX_CLOCK_SKEW_SECS")
+                .unwrap_or_else(|_| defaults.max_clock_skew_secs.to_string())
+                .parse()
+                .context("NODE_AUTH_MAX_CLOCK_SKEW_SECS must be a valid number")?,
+            max_failures: env::var("NODE_AUTH_MAX_FAILURES")
+                .unwrap_or_else(|_| defaults.max_failures.to_string())
+                .parse()
+                .context("NODE_AUTH_MAX_FAILURES must be a valid number")?,
+            failure_window_secs: env::var("NODE_AUTH_FAILURE_WINDOW_SECS")
+                .unwrap_or_else(|_| defaults.failure_window_secs.to_string())
+                .parse()
+                .context("NODE_AUTH_FAILURE_WINDOW_SECS must be a valid number")?,
+        })
+    }
+}
+
+/// Credential rotation policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CredentialRotationConfig {
+    /// Node secrets older than this are rotated automatically (days, 0 disables)
+    pub node_secret_max_age_days: i64,
+    /// How long a replaced node secret or subscription token keeps working (hours)
+    pub overlap_hours: i64,
+}
+
+impl Default for CredentialRotationConfig {
+    fn default() -> Self {
+        Self {
+            node_secret_max_age_days: 90,
+            overlap_hours: 24,
+        }
+    }
+}
+
+impl CredentialRotationConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let config = CredentialRotationConfig {
+            node_secret_max_age_days: env::var("NODE_SECRET_ROTATION_DAYS")
+                .unwrap_or_else(|_| defaults.node_secret_max_age_days.to_string())
+                .parse()
+                .context("NODE_SECRET_ROTATION_DAYS must be a valid number")?,
+            overlap_hours: env::var("CREDENTIAL_OVERLAP_HOURS")
+                .unwrap_or_else(|_| defaults.overlap_hours.to_string())
+                .parse()
+                .context("CREDENTIAL_OVERLAP_HOURS must be a valid number")?,
+        };
+
+        if config.node_secret_max_age_days < 0 {
+            anyhow::bail!("NODE_SECRET_ROTATION_DAYS cannot be negative");
+        }
+        if config.overlap_hours < 0 {
+            anyhow::bail!("CREDENTIAL_OVERLAP_HOURS cannot be negative");
+        }
+
+        Ok(config)
+    }
+
+    /// Whether node secrets are rotated automatically
+    pub fn node_rotation_enabled(&self) -> bool {
+        self.node_secret_max_age_days > 0
+    }
+}
+
+/// Telegram bot used for security alerts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TelegramAlertConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+}
+
+/// SMTP relay used for security alert emails
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmtpAlertConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+impl SmtpAlertConfig {
+    /// Read `SECURITY_ALERT_SMTP_*`; None when the host is unset
+    fn from_env() -> Result<Option<Self>> {
+        let Some(host) = env::var("SECURITY_ALERT_SMTP_HOST").ok().filter(|s| !s.is_empty()) else {
+            return Ok(None);
+        };
+        let to: Vec<String> = env::var("SECURITY_ALERT_EMAIL_TO")
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if to.is_empty() {
+            anyhow::bail!("SECURITY_ALERT_EMAIL_TO must be set when SECURITY_ALERT_SMTP_HOST is");
+        }
+
+        Ok(Some(SmtpAlertConfig {
+            host,
+            port: env::var("SECURITY_ALERT_SMTP_PORT")
+                .unwrap_or_else(|_| "587".to_string())
+                .parse()
+                .context("SECURITY_ALERT_SMTP_PORT must be a valid port")?,
+            username: env::var("SECURITY_ALERT_SMTP_USERNAME").ok().filter(|s| !s.is_empty()),
+            password: env::var("SECURITY_ALERT_SMTP_PASSWORD").ok().filter(|s| !s.is_empty()),
+            from: env::var("SECURITY_ALERT_EMAIL_FROM")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .context("SECURITY_ALERT_EMAIL_FROM must be set when SECURITY_ALERT_SMTP_HOST is")?,
+            to,
+        }))
+    }
+}
+
+/// Which actions raise security events and where their alerts go
+///
+/// Events are always recorded; alerts for the same event type and subject are sent at
+/// most once per `throttle_secs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityAlertConfig {
+    /// Failed logins for one email within the window that raise an event (0 disables)
+    pub failed_login_threshold: u32,
+    /// Window for counting failed logins (seconds)
+    pub failed_login_window_secs: u64,
+    /// Admin balance adjustments of at least this many coins raise an event (0 disables)
+    pub balance_adjustment_threshold: i64,
+    /// Admin balance adjustments of at least this many coins wait for a second admin's approval (0 disables)
+    pub balance_approval_threshold: i64,
+    /// Users moving at least this much traffic within `heavy_traffic_window_secs` raise an event (bytes, 0 disables)
+    pub heavy_traffic_threshold_bytes: i64,
+    /// Window for measuring heavy traffic (seconds)
+    pub heavy_traffic_window_secs: u64,
+    /// Minimum time between alerts for the same event type and subject (seconds)
+    pub throttle_secs: u64,
+    pub telegram: Option<TelegramAlertConfig>,
+    /// Receives each alert as a JSON POST
+    pub webhook_url: Option<String>,
+    pub email: Option<SmtpAlertConfig>,
+}
+
+impl Default for SecurityAlertConfig {
+    fn default() -> Self {
+        Self {
+            failed_login_threshold: 5,
+            failed_login_window_secs: 900,
+            balance_adjustment_threshold: 10000,
+            balance_approval_threshold: 0,
+            heavy_traffic_threshold_bytes: 200 * 1024 * 1024 * 1024,
+            heavy_traffic_window_secs: 86400,
+            throttle_secs: 600,
+            telegram: None,
+            webhook_url: None,
+            email: None,
+        }
+    }
+}
+
+impl SecurityAlertConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+
+        let telegram = match (
+            env::var("SECURITY_ALERT_TELEGRAM_BOT_TOKEN").ok().filter(|s| !s.is_empty()),
+            env::var("SECURITY_ALERT_TELEGRAM_CHAT_ID").ok().filter(|s| !s.is_empty()),
+        ) {
+            (Some(bot_token), Some(chat_id)) => Some(TelegramAlertConfig { bot_token, chat_id }),
+            (None, None) => None,
+            _ => anyhow::bail!(
+                "SECURITY_ALERT_TELEGRAM_BOT_TOKEN and SECURITY_ALERT_TELEGRAM_CHAT_ID must be set together"
+            ),
+        };
+
+        let config = SecurityAlertConfig {
+            failed_login_threshold: env::var("SECURITY_FAILED_LOGIN_THRESHOLD")
+                .unwrap_or_else(|_| defaults.failed_login_threshold.to_string())
+                .parse()
+                .context("SECURITY_FAILED_LOGIN_THRESHOLD must be a valid number")?,
+            failed_login_window_secs: env::var("SECURITY_FAILED_LOGIN_WINDOW_SECS")
+                .unwrap_or_else(|_| defaults.failed_login_window_secs.to_string())
+                .parse()
+                .context("SECURITY_FAILED_LOGIN_WINDOW_SECS must be a valid number")?,
+            balance_adjustment_threshold: env::var("SECURITY_BALANCE_ALERT_THRESHOLD")
+                .unwrap_or_else(|_| defaults.balance_adjustment_threshold.to_string())
+                .parse()
+                .context("SECURITY_BALANCE_ALERT_THRESHOLD must be a valid number")?,
+            balance_approval_threshold: env::var("BALANCE_APPROVAL_THRESHOLD")
+                .unwrap_or_else(|_| defaults.balance_approval_threshold.to_string())
+                .parse()
+                .context("BALANCE_APPROVAL_THRESHOLD must be a valid number")?,
+            heavy_traffic_threshold_bytes: env::var("SECURITY_HEAVY_TRAFFIC_THRESHOLD_BYTES")
+                .unwrap_or_else(|_| defaults.heavy_traffic_threshold_bytes.to_string())
+                .parse()
+                .context("SECURITY_HEAVY_TRAFFIC_THRESHOLD_BYTES must be a valid number")?,
+            heavy_traffic_window_secs: env::var("SECURITY_HEAVY_TRAFFIC_WINDOW_SECS")
+                .unwrap_or_else(|_| defaults.heavy_traffic_window_secs.to_string())
+                .parse()
+                .context("SECURITY_HEAVY_TRAFFIC_WINDOW_SECS must be a valid number")?,
+            throttle_secs: env::var("SECURITY_ALERT_THROTTLE_SECS")
+                .unwrap_or_else(|_| defaults.throttle_secs.to_string())
+                .parse()
+                .context("SECURITY_ALERT_THROTTLE_SECS must be a valid number")?,
+            telegram,
+            webhook_url: env::var("SECURITY_ALERT_WEBHOOK_URL").ok().filter(|s| !s.is_empty()),
+            email: SmtpAlertConfig::from_env()?,
+        };
+
+        if config.balance_adjustment_threshold < 0 {
+            anyhow::bail!("SECURITY_BALANCE_ALERT_THRESHOLD cannot be negative");
+        }
+        if config.balance_approval_threshold < 0 {
+            anyhow::bail!("BALANCE_APPROVAL_THRESHOLD cannot be negative");
+        }
+        if config.failed_login_window_secs == 0 {
+            anyhow::bail!("SECURITY_FAILED_LOGIN_WINDOW_SECS must be greater than 0");
+        }
+        if config.heavy_traffic_threshold_bytes < 0 {
+            anyhow::bail!("SECURITY_HEAVY_TRAFFIC_THRESHOLD_BYTES cannot be negative");
+        }
+        if config.heavy_traffic_window_secs == 0 {
+            anyhow::bail!("SECURITY_HEAVY_TRAFFIC_WINDOW_SECS must be greater than 0");
+        }
+
+        Ok(config)
+    }
+}
+
+/// Subscription access-log sampling
+///
+/// Failures and each user's first success per window are always written to Postgres;
+/// other successes are written with probability `sample_rate`. Every fetch is still
+/// counted per user and day in Redis.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessLogConfig {
+    /// Share of repeat successful fetches that are written (0.0 - 1.0)
+    pub sample_rate: f64,
+    /// A user's first successful fetch in each window is always written (seconds)
+    pub first_success_window_secs: u64,
+    /// How long daily per-user fetch counts are kept in Redis (days)
+    pub fetch_count_retention_days: u64,
+    /// Entries kept in Redis while the database is unreachable; the oldest are dropped beyond it
+    pub max_queued: usize,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0.1,
+            first_success_window_secs: 3600,
+            fetch_count_retention_days: 7,
+            max_queued: 10_000,
+        }
+    }
+}
+
+impl AccessLogConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let config = AccessLogConfig {
+            sample_rate: env::var("ACCESS_LOG_SAMPLE_RATE")
+                .unwrap_or_else(|_| defaults.sample_rate.to_string())
+                .parse()
+                .context("ACCESS_LOG_SAMPLE_RATE must be a valid number")?,
+            first_success_window_secs: env::var("ACCESS_LOG_FIRST_SUCCESS_WINDOW_SECS")
+                .unwrap_or_else(|_| defaults.first_success_window_secs.to_string())
+                .parse()
+                .context("ACCESS_LOG_FIRST_SUCCESS_WINDOW_SECS must be a valid number")?,
+            fetch_count_retention_days: env::var("ACCESS_LOG_FETCH_COUNT_RETENTION_DAYS")
+                .unwrap_or_else(|_| defaults.fetch_count_retention_days.to_string())
+                .parse()
+                .context("ACCESS_LOG_FETCH_COUNT_RETENTION_DAYS must be a valid number")?,
+            max_queued: env::var("ACCESS_LOG_MAX_QUEUED")
+                .unwrap_or_else(|_| defaults.max_queued.to_string())
+                .parse()
+                .context("ACCESS_LOG_MAX_QUEUED must be a valid number")?,
+        };
+
+        if !(0.0..=1.0).contains(&config.sample_rate) {
+            anyhow::bail!("ACCESS_LOG_SAMPLE_RATE must be between 0 and 1");
+        }
+        if config.first_success_window_secs == 0 {
+            anyhow::bail!("ACCESS_LOG_FIRST_SUCCESS_WINDOW_SECS must be greater than 0");
+        }
+        if config.fetch_count_retention_days == 0 {
+            anyhow::bail!("ACCESS_LOG_FETCH_COUNT_RETENTION_DAYS must be greater than 0");
+        }
+
+        Ok(config)
+    }
+}
+
+/// Per-IP caps on `/sub/:token`, apart from other rate limits
+///
+/// Stops clients that refresh subscriptions in a tight loop. Concurrency is counted
+/// per API instance, request frequency across instances (in Redis).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriptionLimitConfig {
+    /// Fetches one IP may have in progress at once (0 = unlimited)
+    pub max_concurrent_per_ip: u32,
+    /// Fetches one IP may make per window (0 = unlimited)
+    pub max_requests_per_ip: u32,
+    /// Window for the request cap (seconds)
+    pub window_secs: u64,
+}
+
+impl Default for SubscriptionLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_per_ip: 4,
+            max_requests_per_ip: 60,
+            window_secs: 60,
+        }
+    }
+}
+
+impl SubscriptionLimitConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let config = SubscriptionLimitConfig {
+            max_concurrent_per_ip: env::var("SUBSCRIPTION_IP_MAX_CONCURRENT")
+                .unwrap_or_else(|_| defaults.max_concurrent_per_ip.to_string())
+                .parse()
+                .context("SUBSCRIPTION_IP_MAX_CONCURRENT must be a valid number")?,
+            max_requests_per_ip: env::var("SUBSCRIPTION_IP_MAX_REQUESTS")
+                .unwrap_or_else(|_| defaults.max_requests_per_ip.to_string())
+                .parse()
+                .context("SUBSCRIPTION_IP_MAX_REQUESTS must be a valid number")?,
+            window_secs: env::var("SUBSCRIPTION_IP_WINDOW_SECS")
+                .unwrap_or_else(|_| defaults.window_secs.to_string())
+                .parse()
+                .context("SUBSCRIPTION_IP_WINDOW_SECS must be a valid number")?,
+        };
+
+        if config.window_secs == 0 {
+            anyhow::bail!("SUBSCRIPTION_IP_WINDOW_SECS must be greater than 0");
+        }
+
+        Ok(config)
+    }
+}
+
+/// Background regeneration of subscription configs after admin changes
+///
+/// Node and rule changes purge cached configs, so the next fetch of every token pays
+/// for a full regeneration. When enabled, configs recently fetched successfully are
+/// regenerated in the background instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriptionWarmupConfig {
+    /// Most configs regenerated per pass, most recently fetched first (0 disables)
+    pub max_configs: u32,
+    /// Only configs fetched within this many hours are regenerated
+    pub lookback_hours: u32,
+    /// Wait after a purge before regenerating, so a burst of changes triggers one pass (seconds)
+    pub delay_secs: u64,
+}
+
+impl Default for SubscriptionWarmupConfig {
+    fn default() -> Self {
+        Self {
+            max_configs: 0,
+            lookback_hours: 24,
+            delay_secs: 30,
+        }
+    }
+}
+
+impl SubscriptionWarmupConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let config = SubscriptionWarmupConfig {
+            max_configs: env::var("SUBSCRIPTION_WARMUP_MAX_CONFIGS")
+                .unwrap_or_else(|_| defaults.max_configs.to_string())
+                .parse()
+                .context("SUBSCRIPTION_WARMUP_MAX_CONFIGS must be a valid number")?,
+            lookback_hours: env::var("SUBSCRIPTION_WARMUP_LOOKBACK_HOURS")
+                .unwrap_or_else(|_| defaults.lookback_hours.to_string())
+                .parse()
+                .context("SUBSCRIPTION_WARMUP_LOOKBACK_HOURS must be a valid number")?,
+            delay_secs: env::var("SUBSCRIPTION_WARMUP_DELAY_SECS")
+                .unwrap_or_else(|_| defaults.delay_secs.to_string())
+                .parse()
+                .context("SUBSCRIPTION_WARMUP_DELAY_SECS must be a valid number")?,
+        };
+
+        if config.lookback_hours == 0 {
+            anyhow::bail!("SUBSCRIPTION_WARMUP_LOOKBACK_HOURS must be greater than 0");
+        }
+
+        Ok(config)
+    }
+}
+
+/// When users on pace to run out of traffic early are offered a bigger package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpgradeSuggestionConfig {
+    /// Only suggest when traffic is projected to run out within this many days
+    pub horizon_days: i64,
+    /// Only suggest when traffic runs out at least this many days before the package expires
+    pub min_days_early: i64,
+    /// How often users are scanned for notifications (seconds, 0 disables)
+    pub check_interval_secs: u64,
+    /// A notified user is not notified again for this long (hours)
+    pub renotify_hours: u64,
+}
+
+impl Default for UpgradeSuggestionConfig {
+    fn default() -> Self {
+        Self {
+            horizon_days: 14,
+            min_days_early: 3,
+            check_interval_secs: 21600,
+            renotify_hours: 72,
+        }
+    }
+}
+
+impl UpgradeSuggestionConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let config = UpgradeSuggestionConfig {
+            horizon_days: env::var("UPGRADE_SUGGESTION_HORIZON_DAYS")
+                .unwrap_or_else(|_| defaults.horizon_days.to_string())
+                .parse()
+                .context("UPGRADE_SUGGESTION_HORIZON_DAYS must be a valid number")?,
+            min_days_early: env::var("UPGRADE_SUGGESTION_MIN_DAYS_EARLY")
+                .unwrap_or_else(|_| defaults.min_days_early.to_string())
+                .parse()
+                .context("UPGRADE_SUGGESTION_MIN_DAYS_EARLY must be a valid number")?,
+            check_interval_secs: env::var("UPGRADE_SUGGESTION_CHECK_INTERVAL_SECS")
+                .unwrap_or_else(|_| defaults.check_interval_secs.to_string())
+                .parse()
+                .context("UPGRADE_SUGGESTION_CHECK_INTERVAL_SECS must be a valid number")?,
+            renotify_hours: env::var("UPGRADE_SUGGESTION_RENOTIFY_HOURS")
+                .unwrap_or_else(|_| defaults.renotify_hours.to_string())
+                .parse()
+                .context("UPGRADE_SUGGESTION_RENOTIFY_HOURS must be a valid number")?,
+        };
+
+        if config.horizon_days <= 0 {
+            anyhow::bail!("UPGRADE_SUGGESTION_HORIZON_DAYS must be greater than 0");
+        }
+        if config.min_days_early < 0 {
+            anyhow::bail!("UPGRADE_SUGGESTION_MIN_DAYS_EARLY cannot be negative");
+        }
+        if config.renotify_hours == 0 {
+            anyhow::bail!("UPGRADE_SUGGESTION_RENOTIFY_HOURS must be greater than 0");
+        }
+
+        Ok(config)
+    }
+
+    /// Whether users are notified in the background (the endpoint always works)
+    pub fn notifications_enabled(&self) -> bool {
+        self.check_interval_secs > 0
+    }
+}
+
+/// Background check of coin balances against the transaction ledger
+#[derive(Debug, Clone)]
+pub struct LedgerCheckConfig {
+    /// How often balances are checked (seconds, 0 disables)
+    pub check_interval_secs: u64,
+    /// Record adjustments for drift found by the background check, rather than only reporting it
+    pub auto_fix: bool,
+}
+
+impl Default for LedgerCheckConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_secs: 86400,
+            auto_fix: false,
+        }
+    }
+}
+
+impl LedgerCheckConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        Ok(LedgerCheckConfig {
+            check_interval_secs: env::var("LEDGER_CHECK_INTERVAL_SECS")
+                .unwrap_or_else(|_| defaults.check_interval_secs.to_string())
+                .parse()
+                .context("LEDGER_CHECK_INTERVAL_SECS must be a valid number")?,
+            auto_fix: env::var("LEDGER_AUTO_FIX")
+                .unwrap_or_else(|_| defaults.auto_fix.to_string())
+                .parse()
+                .context("LEDGER_AUTO_FIX must be 'true' or 'false'")?,
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.check_interval_secs > 0
+    }
+}
+
+/// Sanity bounds on node traffic reports
+///
+/// Reports outside them are quarantined for admin review instead of being charged,
+/// so a node that misreports after a crash can't wipe out user quotas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrafficGuardConfig {
+    /// Most upload + download one report may carry for a user (bytes, 0 disables);
+    /// agents report every `TRAFFIC_REPORT_INTERVAL` seconds
+    pub max_report_bytes: i64,
+    /// Quarantine every report from nodes in maintenance
+    pub freeze_maintenance_nodes: bool,
+}
+
+impl Default for TrafficGuardConfig {
+    fn default() -> Self {
+        Self {
+            max_report_bytes: 50 * 1024 * 1024 * 1024,
+            freeze_maintenance_nodes: false,
+        }
+    }
+}
+
+impl TrafficGuardConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        Ok(TrafficGuardConfig {
+            max_report_bytes: env::var("TRAFFIC_MAX_REPORT_BYTES")
+                .unwrap_or_else(|_| defaults.max_report_bytes.to_string())
+                .parse()
+                .context("TRAFFIC_MAX_REPORT_BYTES must be a valid number")?,
+            freeze_maintenance_nodes: env::var("TRAFFIC_FREEZE_MAINTENANCE_NODES")
+                .unwrap_or_else(|_| defaults.freeze_maintenance_nodes.to_string())
+                .parse()
+                .context("TRAFFIC_FREEZE_MAINTENANCE_NODES must be 'true' or 'false'")?,
+        })
+    }
+}
+
+/// Built-in TLS termination, for deployments without a reverse proxy
+///
+/// TLS is on when a certificate (`cert_path` + `key_path`) or ACME domains are
+/// configured; it is served on `API_PORT` with HTTP/1.1 and HTTP/2.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// PEM certificate chain
+    pub cert_path: Option<String>,
+    /// PEM private key for `cert_path`
+    pub key_path: Option<String>,
+    /// Domains to obtain Let's Encrypt certificates for (TLS-ALPN-01), instead of certificate files
+    pub acme_domains: Vec<String>,
+    /// Contact email for the ACME account
+    pub acme_email: Option<String>,
+    /// Where the ACME account and certificates are kept between restarts
+    pub acme_cache_dir: String,
+    /// Use the Let's Encrypt staging directory (untrusted certificates, higher rate limits)
+    pub acme_staging: bool,
+    /// Plain HTTP port that redirects to HTTPS (None disables)
+    pub http_redirect_port: Option<u16>,
+    /// Redirect requests a reverse proxy forwards with `X-Forwarded-Proto: http`
+    pub force_https: bool,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            cert_path: None,
+            key_path: None,
+            acme_domains: Vec::new(),
+            acme_email: None,
+            acme_cache_dir: "./acme-cache".to_string(),
+            acme_staging: false,
+            http_redirect_port: None,
+            force_https: false,
+        }
+    }
+}
+
+impl TlsConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let settings = TlsConfig {
+            cert_path: env::var("TLS_CERT_PATH").ok().filter(|s| !s.is_empty()),
+            key_path: env::var("TLS_KEY_PATH").ok().filter(|s| !s.is_empty()),
+            acme_domains: env::var("TLS_ACME_DOMAINS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_ascii_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            acme_email: env::var("TLS_ACME_EMAIL").ok().filter(|s| !s.is_empty()),
+            acme_cache_dir: env::var("TLS_ACME_CACHE_DIR").unwrap_or(defaults.acme_cache_dir),
+            acme_staging: env::var("TLS_ACME_STAGING")
+                .unwrap_or_else(|_| defaults.acme_staging.to_string())
+                .parse()
+                .context("TLS_ACME_STAGING must be 'true' or 'false'")?,
+            http_redirect_port: env::var("TLS_HTTP_REDIRECT_PORT")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse())
+                .transpose()
+                .context("TLS_HTTP_REDIRECT_PORT must be a valid port")?,
+            force_https: env::var("FORCE_HTTPS")
+                .unwrap_or_else(|_| defaults.force_https.to_string())
+                .parse()
+                .context("FORCE_HTTPS must be 'true' or 'false'")?,
+        };
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Reject settings that leave TLS half configured
+    pub fn validate(&self) -> Result<()> {
+        if self.cert_path.is_some() != self.key_path.is_some() {
+            anyhow::bail!("TLS_CERT_PATH and TLS_KEY_PATH must be set together");
+        }
+        if self.cert_path.is_some() && !self.acme_domains.is_empty() {
+            anyhow::bail!("Set either TLS_CERT_PATH/TLS_KEY_PATH or TLS_ACME_DOMAINS, not both");
+        }
+        if self.http_redirect_port.is_some() && !self.enabled() {
+            anyhow::bail!("TLS_HTTP_REDIRECT_PORT needs TLS_CERT_PATH/TLS_KEY_PATH or TLS_ACME_DOMAINS");
+        }
+        Ok(())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.cert_path.is_some() || !self.acme_domains.is_empty()
+    }
+}
+
+/// GeoIP restrictions on subscription fetches (`/sub/:token`)
+///
+/// Lookups need MaxMind databases (GeoLite2 works); admins can override the country
+/// rules per user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeoRestrictionConfig {
+    /// Country database (`GeoLite2-Country.mmdb`)
+    pub country_db_path: Option<String>,
+    /// ASN database (`GeoLite2-ASN.mmdb`)
+    pub asn_db_path: Option<String>,
+    /// Only serve fetches from these countries (empty: any country)
+    pub allowed_countries: Vec<String>,
+    pub blocked_countries: Vec<String>,
+    /// Networks to refuse, e.g. the datacenters scrapers run from
+    pub blocked_asns: Vec<u32>,
+    pub action: crate::geoip::GeoAction,
+}
+
+impl Default for GeoRestrictionConfig {
+    fn default() -> Self {
+        Self {
+            country_db_path: None,
+            asn_db_path: None,
+            allowed_countries: Vec::new(),
+            blocked_countries: Vec::new(),
+            blocked_asns: Vec::new(),
+            action: crate::geoip::GeoAction::Block,
+        }
+    }
+}
+
+impl GeoRestrictionConfig {
+    pub fn from_env() -> Result<Self> {
+        let country_list = |var: &str| -> Result<Vec<String>> {
+            let codes: Vec<String> = env::var(var)
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+            crate::geoip::parse_country_codes(codes.as_slice())
+                .map_err(|code| anyhow!("{} contains an invalid country code '{}'", var, code))
+        };
+
+        let settings = GeoRestrictionConfig {
+            country_db_path: env::var("GEOIP_COUNTRY_DB").ok().filter(|s| !s.is_empty()),
+            asn_db_path: env::var("GEOIP_ASN_DB").ok().filter(|s| !s.is_empty()),
+            allowed_countries: country_list("SUB_GEO_ALLOWED_COUNTRIES")?,
+            blocked_countries: country_list("SUB_GEO_BLOCKED_COUNTRIES")?,
+            blocked_asns: env::var("SUB_GEO_BLOCKED_ASNS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().trim_start_matches("AS"))
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse())
+                .collect::<std::result::Result<_, _>>()
+                .context("SUB_GEO_BLOCKED_ASNS must be a comma-separated list of AS numbers")?,
+            action: crate::geoip::GeoAction::parse(&env::var("SUB_GEO_ACTION").unwrap_or_else(|_| "block".to_string()))
+                .context("SUB_GEO_ACTION must be 'block' or 'challenge'")?,
+        };
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Reject rules that could never match because their database is missing
+    pub fn validate(&self) -> Result<()> {
+        let has_country_rules = !self.allowed_countries.is_empty() || !self.blocked_countries.is_empty();
+        if has_country_rules && self.country_db_path.is_none() {
+            anyhow::bail!("SUB_GEO_ALLOWED_COUNTRIES and SUB_GEO_BLOCKED_COUNTRIES need GEOIP_COUNTRY_DB");
+        }
+        if !self.blocked_asns.is_empty() && self.asn_db_path.is_none() {
+            anyhow::bail!("SUB_GEO_BLOCKED_ASNS needs GEOIP_ASN_DB");
+        }
+        Ok(())
+    }
+
+    /// Whether fetches are looked up at all
+    pub fn enabled(&self) -> bool {
+        self.country_db_path.is_some() || self.asn_db_path.is_some()
+    }
+}
+
+/// Order number generation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OrderNoConfig {
+    pub strategy: crate::order_no::OrderNoStrategy,
+    /// Distinguishes API replicas in Snowflake ids; give each replica its own (0-1023)
+    pub worker_id: u16,
+}
+
+impl OrderNoConfig {
+    pub fn from_env() -> Result<Self> {
+        let settings = OrderNoConfig {
+            strategy: crate::order_no::OrderNoStrategy::parse(
+                &env::var("ORDER_NO_STRATEGY").unwrap_or_else(|_| "random".to_string()),
+            )
+            .context("ORDER_NO_STRATEGY must be 'random', 'snowflake' or 'legacy'")?,
+            worker_id: env::var("ORDER_NO_WORKER_ID")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .context("ORDER_NO_WORKER_ID must be a valid number")?,
+        };
+        if settings.worker_id > crate::order_no::MAX_WORKER_ID {
+            anyhow::bail!("ORDER_NO_WORKER_ID must be at most 1023");
+        }
+        Ok(settings)
+    }
+}
+
+/// Terms of service users are asked to accept
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TermsConfig {
+    /// Current version, e.g. `2026-10`; None when there are no terms
+    pub version: Option<String>,
+    /// Refuse purchases until the user has accepted the current version
+    pub enforce: bool,
+}
+
+impl TermsConfig {
+    pub fn from_env() -> Result<Self> {
+        let settings = TermsConfig {
+            version: env::var("TERMS_VERSION")
+                .ok()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty()),
+            enforce: env::var("TERMS_ENFORCE")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .context("TERMS_ENFORCE must be 'true' or 'false'")?,
+        };
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.version.as_ref().is_some_and(|v| v.chars().count() > MAX_TERMS_VERSION_LEN) {
+            anyhow::bail!("TERMS_VERSION must be at most {} characters", MAX_TERMS_VERSION_LEN);
+        }
+        if self.enforce && self.version.is_none() {
+            anyhow::bail!("TERMS_ENFORCE needs TERMS_VERSION");
+        }
+        Ok(())
+    }
+
+    /// Whether a purchase must wait until the user accepts the current version
+    pub fn blocks_purchase(&self, accepted: bool) -> bool {
+        self.enforce && self.version.is_some() && !accepted
+    }
+}
+
+/// Width of `terms_acceptances.version`
+pub const MAX_TERMS_VERSION_LEN: usize = 50;
+
+/// Node agent release offered to agents for self-update (`/api/node/agent-version`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentReleaseConfig {
+    /// Latest agent version; agents are not offered updates while unset
+    pub version: Option<String>,
+    /// Where agents download the release binary
+    pub download_url: Option<String>,
+    /// Hex SHA-256 of the release binary, checked by the agent before installing it
+    pub sha256: Option<String>,
+    /// Architecture the binary is built for (Rust `std::env::consts::ARCH` names)
+    pub arch: String,
+    /// How long a signed update manifest stays valid (seconds)
+    pub manifest_ttl_secs: i64,
+}
+
+impl Default for AgentReleaseConfig {
+    fn default() -> Self {
+        Self {
+            version: None,
+            download_url: None,
+            sha256: None,
+            arch: "x86_64".to_string(),
+            manifest_ttl_secs: 600,
+        }
+    }
+}
+
+impl AgentReleaseConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let settings = AgentReleaseConfig {
+            version: env::var("AGENT_RELEASE_VERSION").ok().filter(|s| !s.is_empty()),
+            download_url: env::var("AGENT_RELEASE_URL").ok().filter(|s| !s.is_empty()),
+            sha256: env::var("AGENT_RELEASE_SHA256")
+                .ok()
+                .map(|s| s.trim().to_ascii_lowercase())
+                .filter(|s| !s.is_empty()),
+            arch: env::var("AGENT_RELEASE_ARCH").unwrap_or(defaults.arch),
+            manifest_ttl_secs: env::var("AGENT_UPDATE_MANIFEST_TTL_SECS")
+                .unwrap_or_else(|_| defaults.manifest_ttl_secs.to_string())
+                .parse()
+                .context("AGENT_UPDATE_MANIFEST_TTL_SECS must be a valid number")?,
+        };
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// A release needs its download URL and checksum
+    pub fn validate(&self) -> Result<()> {
+        if self.version.is_none() {
+            return Ok(());
+        }
+        if self.download_url.is_none() {
+            anyhow::bail!("AGENT_RELEASE_VERSION needs AGENT_RELEASE_URL");
+        }
+        match &self.sha256 {
+            Some(sha256) if sha256.len() == 64 && sha256.chars().all(|c| c.is_ascii_hexdigit()) => {}
+            _ => anyhow::bail!("AGENT_RELEASE_VERSION needs AGENT_RELEASE_SHA256 (64 hex digits)"),
+        }
+        if self.manifest_ttl_secs <= 0 {
+            anyhow::bail!("AGENT_UPDATE_MANIFEST_TTL_SECS must be positive");
+        }
+        Ok(())
+    }
+
+    /// Version, download URL and checksum of the release, if one is configured
+    pub fn offered(&self) -> Option<(&str, &str, &str)> {
+        Some((
+            self.version.as_deref()?,
+            self.download_url.as_deref()?,
+            self.sha256.as_deref()?,
+        ))
+    }
+}
+
+/// Argon2id cost parameters for new password hashes
+///
+/// Stored hashes carry their own parameters, so changing these never breaks existing
+/// logins; weaker hashes are upgraded the next time the user signs in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Argon2Config {
+    /// Memory cost (KiB)
+    pub memory_kib: u32,
+    /// Number of passes
+    pub iterations: u32,
+    /// Degree of parallelism (lanes)
+    pub parallelism: u32,
+}
+
+impl Default for Argon2Config {
+    /// The argon2 crate's defaults (OWASP minimum: 19 MiB, 2 passes, 1 lane)
+    fn default() -> Self {
+        Self {
+            memory_kib: argon2::Params::DEFAULT_M_COST,
+            iterations: argon2::Params::DEFAULT_T_COST,
+            parallelism: argon2::Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl Argon2Config {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let config = Argon2Config {
+            memory_kib: env::var("ARGON2_MEMORY_KIB")
+                .unwrap_or_else(|_| defaults.memory_kib.to_string())
+                .parse()
+                .context("ARGON2_MEMORY_KIB must be a valid number")?,
+            iterations: env::var("ARGON2_ITERATIONS")
+                .unwrap_or_else(|_| defaults.iterations.to_string())
+                .parse()
+                .context("ARGON2_ITERATIONS must be a valid number")?,
+            parallelism: env::var("ARGON2_PARALLELISM")
+                .unwrap_or_else(|_| defaults.parallelism.to_string())
+                .parse()
+                .context("ARGON2_PARALLELISM must be a valid number")?,
+        };
+        config.params()?;
+
+        Ok(config)
+    }
+
+    /// Validated argon2 parameters
+    pub fn params(&self) -> Result<argon2::Params> {
+        argon2::Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .map_err(|e| anyhow!("Invalid Argon2 parameters: {}", e))
+    }
+}
+
+/// OAuth2 client credentials for one login provider
+#[derive(Debug, Clone)]
+pub struct OAuthClientConfig {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl OAuthClientConfig {
+    /// Read `{prefix}_CLIENT_ID` and `{prefix}_CLIENT_SECRET`; None when the id is unset
+    fn from_env(prefix: &str) -> Result<Option<Self>> {
+        let Some(client_id) = env::var(format!("{}_CLIENT_ID", prefix)).ok().filter(|s| !s.is_empty()) else {
+            return Ok(None);
+        };
+        let client_secret = env::var(format!("{}_CLIENT_SECRET", prefix))
+            .ok()
+            .filter(|s| !s.is_empty())
+            .with_context(|| format!("{}_CLIENT_SECRET must be set when {}_CLIENT_ID is", prefix, prefix))?;
+
+        Ok(Some(OAuthClientConfig { client_id, client_secret }))
+    }
+}
+
+/// Optional OAuth2 login providers
+#[derive(Debug, Clone, Default)]
+pub struct OAuthConfig {
+    pub github: Option<OAuthClientConfig>,
+    pub google: Option<OAuthClientConfig>,
+    /// Public base URL of the API, used to build callback URLs (OAuth is off when unset)
+    pub public_base_url: Option<String>,
+    /// Frontend page that receives `#token=...` after login; JSON is returned when unset
+    pub frontend_callback_url: Option<String>,
+}
+
+impl OAuthConfig {
+    pub fn from_env() -> Result<Self> {
+        Ok(OAuthConfig {
+            github: OAuthClientConfig::from_env("OAUTH_GITHUB")?,
+            google: OAuthClientConfig::from_env("OAUTH_GOOGLE")?,
+            public_base_url: env::var("OAUTH_PUBLIC_BASE_URL").ok().filter(|s| !s.is_empty()),
+            frontend_callback_url: env::var("OAUTH_FRONTEND_CALLBACK_URL").ok().filter(|s| !s.is_empty()),
+        })
+    }
+}
+
+/// Parse a comma-separated list of email domains (lowercased, blanks dropped)
+fn domain_list(var: &str) -> Vec<String> {
+    env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().trim_start_matches('@').to_ascii_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
@@ -10,6 +1235,78 @@ pub struct Config {
     pub host: String,
     pub port: u16,
     pub cors_origins: Vec<String>,
+    /// Reverse proxies whose `X-Forwarded-For` / `X-Real-IP` headers are believed
+    pub trusted_proxies: Vec<ipnet::IpNet>,
+    /// Base64-encoded 32-byte key for encrypting sensitive columns at rest
+    pub field_encryption_key: Option<String>,
+    /// Key for the `X-Config-Signature` subscription header (signing disabled if unset)
+    pub config_signing_key: Option<String>,
+    /// `ed25519` (key is a base64 32-byte seed) or `hmac-sha256` (key is the shared secret)
+    pub config_signing_algorithm: String,
+    /// CPU usage percentage above which a node is considered overloaded
+    pub node_cpu_alert_threshold: f64,
+    /// How long CPU must stay above the threshold before alerting (seconds)
+    pub node_cpu_alert_window_secs: i64,
+    /// How long heartbeat metrics are kept (days)
+    pub node_metrics_retention_days: i64,
+    /// Share of a node's monthly traffic budget (%) at which admins are warned
+    pub node_traffic_budget_alert_percent: f64,
+    /// Language used for errors and notifications when the user has no preference
+    pub default_language: Lang,
+    /// Shared secret used to verify payment gateway callbacks (mixed payments disabled if unset)
+    pub payment_webhook_secret: Option<String>,
+    /// Payment page URL returned with mixed-payment orders (order_no and amount are appended)
+    pub payment_gateway_url: Option<String>,
+    /// How long coins stay reserved for an order awaiting external payment (minutes)
+    pub payment_order_ttl_minutes: i64,
+    /// Treat every purchase as a sandbox purchase (staging); see `payments::is_sandbox`
+    pub sandbox_mode: bool,
+    /// Database connection pool sizing and timeouts
+    pub db_pool: DbPoolConfig,
+    /// Redis cache TTLs
+    pub cache_ttl: CacheTtlConfig,
+    /// Generated Clash configs above this size (bytes) are logged as oversized, 0 disables
+    pub clash_config_warn_bytes: usize,
+    /// Prepend remaining-traffic and expiry entries to generated subscriptions
+    pub subscription_info_entries: bool,
+    /// Order of proxies in generated subscriptions: static, load or latency
+    pub proxy_sort: crate::clash::ProxySort,
+    /// Sign-up restrictions
+    pub registration: RegistrationPolicyConfig,
+    /// Node agent request freshness and lockout
+    pub node_auth: NodeAuthConfig,
+    /// Password hashing cost
+    pub argon2: Argon2Config,
+    /// OAuth login providers
+    pub oauth: OAuthConfig,
+    /// Node secret and subscription token rotation
+    pub credential_rotation: CredentialRotationConfig,
+    /// Security event thresholds and alert channels
+    pub security_alerts: SecurityAlertConfig,
+    /// Subscription access-log sampling and fetch counting
+    pub access_log: AccessLogConfig,
+    /// Burn-rate based package upgrade suggestions
+    pub upgrade_suggestions: UpgradeSuggestionConfig,
+    /// Coin ledger reconciliation
+    pub ledger_check: LedgerCheckConfig,
+    /// Built-in HTTPS
+    pub tls: TlsConfig,
+    /// Bounds on node traffic reports
+    pub traffic_guard: TrafficGuardConfig,
+    /// GeoIP restrictions on subscription fetches
+    pub geo_restriction: GeoRestrictionConfig,
+    /// Per-IP concurrency and frequency caps on subscription fetches
+    pub subscription_limits: SubscriptionLimitConfig,
+    pub subscription_warmup: SubscriptionWarmupConfig,
+    pub password_policy: PasswordPolicyConfig,
+    /// Node agent self-update release
+    pub agent_release: AgentReleaseConfig,
+    /// Time zone admin stats cut days, weeks and months in (IANA name, e.g. `Asia/Shanghai`)
+    pub report_timezone: String,
+    /// How new order numbers are generated
+    pub order_numbers: OrderNoConfig,
+    /// Terms of service version and whether purchases wait for its acceptance
+    pub terms: TermsConfig,
 }
 
 impl Config {
@@ -36,6 +1333,91 @@ impl Config {
                 .split(',')
                 .map(|s| s.trim().to_string())
                 .collect(),
+            trusted_proxies: crate::client_ip::parse_trusted_proxies(
+                &env::var("TRUSTED_PROXIES").unwrap_or_default(),
+            )
+            .context("TRUSTED_PROXIES must be comma-separated IP addresses or networks")?,
+            field_encryption_key: env::var("FIELD_ENCRYPTION_KEY")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            config_signing_key: env::var("CONFIG_SIGNING_KEY")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            config_signing_algorithm: env::var("CONFIG_SIGNING_ALGORITHM")
+                .unwrap_or_else(|_| "ed25519".to_string())
+                .trim()
+                .to_lowercase(),
+            node_cpu_alert_threshold: env::var("NODE_CPU_ALERT_THRESHOLD")
+                .unwrap_or_else(|_| "90".to_string())
+                .parse()
+                .context("NODE_CPU_ALERT_THRESHOLD must be a valid number")?,
+            node_cpu_alert_window_secs: env::var("NODE_CPU_ALERT_WINDOW_SECS")
+                .unwrap_or_else(|_| "300".to_string())
+                .parse()
+                .context("NODE_CPU_ALERT_WINDOW_SECS must be a valid number")?,
+            node_metrics_retention_days: env::var("NODE_METRICS_RETENTION_DAYS")
+                .unwrap_or_else(|_| "7".to_string())
+                .parse()
+                .context("NODE_METRICS_RETENTION_DAYS must be a valid number")?,
+            node_traffic_budget_alert_percent: env::var("NODE_TRAFFIC_BUDGET_ALERT_PERCENT")
+                .unwrap_or_else(|_| "90".to_string())
+                .parse()
+                .context("NODE_TRAFFIC_BUDGET_ALERT_PERCENT must be a valid number")?,
+            default_language: Lang::parse(
+                &env::var("DEFAULT_LANGUAGE").unwrap_or_else(|_| "en".to_string()),
+            )
+            .context("DEFAULT_LANGUAGE must be 'en' or 'zh'")?,
+            payment_webhook_secret: env::var("PAYMENT_WEBHOOK_SECRET")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            payment_gateway_url: env::var("PAYMENT_GATEWAY_URL")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            payment_order_ttl_minutes: env::var("PAYMENT_ORDER_TTL_MINUTES")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .context("PAYMENT_ORDER_TTL_MINUTES must be a valid number")?,
+            sandbox_mode: env::var("SANDBOX_MODE")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .context("SANDBOX_MODE must be 'true' or 'false'")?,
+            db_pool: DbPoolConfig::from_env()?,
+            cache_ttl: CacheTtlConfig::from_env()?,
+            clash_config_warn_bytes: env::var("CLASH_CONFIG_WARN_BYTES")
+                .unwrap_or_else(|_| crate::clash::DEFAULT_CONFIG_SIZE_WARN_BYTES.to_string())
+                .parse()
+                .context("CLASH_CONFIG_WARN_BYTES must be a valid number")?,
+            subscription_info_entries: env::var("SUBSCRIPTION_INFO_ENTRIES")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .context("SUBSCRIPTION_INFO_ENTRIES must be 'true' or 'false'")?,
+            proxy_sort: crate::clash::ProxySort::parse(
+                &env::var("PROXY_SORT").unwrap_or_else(|_| "static".to_string()),
+            )
+            .context("PROXY_SORT must be 'static', 'load' or 'latency'")?,
+            registration: RegistrationPolicyConfig::from_env()?,
+            node_auth: NodeAuthConfig::from_env()?,
+            argon2: Argon2Config::from_env()?,
+            oauth: OAuthConfig::from_env()?,
+            credential_rotation: CredentialRotationConfig::from_env()?,
+            security_alerts: SecurityAlertConfig::from_env()?,
+            access_log: AccessLogConfig::from_env()?,
+            upgrade_suggestions: UpgradeSuggestionConfig::from_env()?,
+            ledger_check: LedgerCheckConfig::from_env()?,
+            tls: TlsConfig::from_env()?,
+            traffic_guard: TrafficGuardConfig::from_env()?,
+            geo_restriction: GeoRestrictionConfig::from_env()?,
+            subscription_limits: SubscriptionLimitConfig::from_env()?,
+            subscription_warmup: SubscriptionWarmupConfig::from_env()?,
+            password_policy: PasswordPolicyConfig::from_env()?,
+            agent_release: AgentReleaseConfig::from_env()?,
+            report_timezone: env::var("REPORT_TIMEZONE")
+                .ok()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| crate::stats::DEFAULT_TIME_ZONE.to_string()),
+            order_numbers: OrderNoConfig::from_env()?,
+            terms: TermsConfig::from_env()?,
         })
     }
 }
@@ -69,6 +1451,16 @@ mod tests {
         assert!(!config.jwt_secret.is_empty());
         assert!(config.jwt_expiration > 0);
         assert!(config.port > 0);
+        assert!(config.db_pool.max_connections > 0);
+        assert!(config.cache_ttl.subscription_config_secs > 0);
+        assert_eq!(config.argon2, Argon2Config::default());
+        assert_eq!(config.credential_rotation, CredentialRotationConfig::default());
+        assert_eq!(config.security_alerts, SecurityAlertConfig::default());
+        assert_eq!(config.access_log, AccessLogConfig::default());
+        assert_eq!(config.upgrade_suggestions, UpgradeSuggestionConfig::default());
+        assert_eq!(config.traffic_guard, TrafficGuardConfig::default());
+        assert_eq!(config.geo_restriction, GeoRestrictionConfig::default());
+        assert_eq!(config.terms, TermsConfig::default());
         
         // Restore original values
         env::remove_var("DATABASE_URL");
@@ -81,6 +1473,79 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_argon2_config_rejects_invalid_params() {
+        assert!(Argon2Config::default().params().is_ok());
+
+        let no_lanes = Argon2Config { parallelism: 0, ..Argon2Config::default() };
+        assert!(no_lanes.params().is_err());
+
+        let no_passes = Argon2Config { iterations: 0, ..Argon2Config::default() };
+        assert!(no_passes.params().is_err());
+    }
+
+    #[test]
+    fn test_tls_config_validation() {
+        assert!(TlsConfig::default().validate().is_ok());
+        assert!(!TlsConfig::default().enabled());
+
+        let files = TlsConfig {
+            cert_path: Some("/etc/ssl/api.pem".to_string()),
+            key_path: Some("/etc/ssl/api.key".to_string()),
+            http_redirect_port: Some(80),
+            ..TlsConfig::default()
+        };
+        assert!(files.validate().is_ok());
+        assert!(files.enabled());
+
+        let no_key = TlsConfig { key_path: None, ..files.clone() };
+        assert!(no_key.validate().is_err());
+
+        let both = TlsConfig { acme_domains: vec!["sub.example.com".to_string()], ..files };
+        assert!(both.validate().is_err());
+
+        let redirect_only = TlsConfig { http_redirect_port: Some(80), ..TlsConfig::default() };
+        assert!(redirect_only.validate().is_err());
+    }
+
+    #[test]
+    fn test_geo_restriction_config_validation() {
+        assert!(GeoRestrictionConfig::default().validate().is_ok());
+        assert!(!GeoRestrictionConfig::default().enabled());
+
+        let countries = GeoRestrictionConfig {
+            allowed_countries: vec!["CN".to_string()],
+            ..GeoRestrictionConfig::default()
+        };
+        assert!(countries.validate().is_err());
+        let with_db = GeoRestrictionConfig {
+            country_db_path: Some("GeoLite2-Country.mmdb".to_string()),
+            ..countries
+        };
+        assert!(with_db.validate().is_ok());
+        assert!(with_db.enabled());
+
+        let asns = GeoRestrictionConfig { blocked_asns: vec![14061], ..with_db };
+        assert!(asns.validate().is_err());
+    }
+
+    #[test]
+    fn test_terms_config_validation() {
+        assert!(TermsConfig::default().validate().is_ok());
+        assert!(!TermsConfig::default().blocks_purchase(false));
+
+        let enforced = TermsConfig { version: Some("2026-10".to_string()), enforce: true };
+        assert!(enforced.validate().is_ok());
+        assert!(enforced.blocks_purchase(false));
+        assert!(!enforced.blocks_purchase(true));
+
+        let informational = TermsConfig { enforce: false, ..enforced.clone() };
+        assert!(!informational.blocks_purchase(false));
+
+        assert!(TermsConfig { version: None, enforce: true }.validate().is_err());
+        assert!(TermsConfig { version: Some("v".repeat(51)), ..enforced }.validate().is_err());
+    }
+
     #[test]
     fn test_config_missing_required_fields() {
         let _lock = TEST_MUTEX.lock().unwrap();
@@ -0,0 +1,435 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::sync::OnceLock;
+
+use crate::models::Node;
+
+/// Prefix marking a value produced by `FieldCipher::encrypt`
+pub const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// Keys inside `nodes.config` whose values are encrypted at rest
+pub const SENSITIVE_CONFIG_KEYS: &[&str] = &[
+    "private_key",
+    "privateKey",
+    "password",
+    "obfs_password",
+    "obfs-password",
+];
+
+const NONCE_LEN: usize = 12;
+
+static FIELD_CIPHER: OnceLock<FieldCipher> = OnceLock::new();
+static CONFIG_SIGNER: OnceLock<ConfigSigner> = OnceLock::new();
+
+/// AES-256-GCM cipher used to encrypt sensitive columns
+pub struct FieldCipher {
+    cipher: Aes256Gcm,
+}
+
+impl FieldCipher {
+    /// Create a cipher from a base64-encoded 32-byte key
+    pub fn from_base64_key(key: &str) -> Result<Self> {
+        let bytes = STANDARD
+            .decode(key.trim())
+            .context("FIELD_ENCRYPTION_KEY must be valid base64")?;
+        let cipher = Aes256Gcm::new_from_slice(&bytes)
+            .map_err(|_| anyhow!("FIELD_ENCRYPTION_KEY must decode to 32 bytes"))?;
+
+        Ok(Self { cipher })
+    }
+
+    /// Encrypt a value, returning `enc:v1:<base64(nonce || ciphertext)>`
+    ///
+    /// Every value is encrypted, including one that already starts with the prefix.
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| anyhow!("Failed to encrypt field"))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+
+        Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(payload)))
+    }
+
+    /// Decrypt a value produced by `encrypt`
+    ///
+    /// Plaintext values (written before encryption was enabled) are returned unchanged.
+    pub fn decrypt(&self, value: &str) -> Result<String> {
+        let Some(encoded) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(value.to_string());
+        };
+
+        let payload = STANDARD
+            .decode(encoded)
+            .context("Encrypted field is not valid base64")?;
+        if payload.len() <= NONCE_LEN {
+            return Err(anyhow!("Encrypted field is truncated"));
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to decrypt field (wrong key?)"))?;
+
+        String::from_utf8(plaintext).context("Decrypted field is not valid UTF-8")
+    }
+
+    /// Check whether a value was encrypted with this key: it carries the prefix and its
+    /// nonce and tag verify
+    pub fn can_decrypt(&self, value: &str) -> bool {
+        is_encrypted(value) && self.decrypt(value).is_ok()
+    }
+
+    /// Encrypt all sensitive string values inside a node config
+    pub fn encrypt_config(&self, config: &serde_json::Value) -> Result<serde_json::Value> {
+        map_sensitive_values(config, &|v| self.encrypt(v))
+    }
+
+    /// Decrypt all sensitive string values inside a node config
+    pub fn decrypt_config(&self, config: &serde_json::Value) -> Result<serde_json::Value> {
+        map_sensitive_values(config, &|v| self.decrypt(v))
+    }
+}
+
+/// Check whether a value carries the encryption prefix
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// Install the process-wide field cipher (called once at startup)
+pub fn install_field_cipher(cipher: FieldCipher) -> Result<()> {
+    FIELD_CIPHER
+        .set(cipher)
+        .map_err(|_| anyhow!("Field cipher already installed"))
+}
+
+/// Get the process-wide field cipher, if encryption is enabled
+pub fn field_cipher() -> Option<&'static FieldCipher> {
+    FIELD_CIPHER.get()
+}
+
+/// Algorithms for `X-Config-Signature`
+pub const SIGNATURE_ALGORITHMS: &[&str] = &["ed25519", "hmac-sha256"];
+
+/// Signs subscription config bodies so clients can detect tampering in transit
+pub enum ConfigSigner {
+    /// Anyone can verify with the published public key
+    Ed25519(ed25519_dalek::SigningKey),
+    /// Clients verify with a secret shared out of band
+    HmacSha256(Vec<u8>),
+}
+
+impl ConfigSigner {
+    /// Create a signer; Ed25519 keys are a base64-encoded 32-byte seed, HMAC keys are used as is
+    pub fn new(algorithm: &str, key: &str) -> Result<Self> {
+        match algorithm {
+            "ed25519" => {
+                let seed: [u8; 32] = STANDARD
+                    .decode(key.trim())
+                    .context("CONFIG_SIGNING_KEY must be valid base64")?
+                    .try_into()
+                    .map_err(|_| anyhow!("CONFIG_SIGNING_KEY must decode to 32 bytes"))?;
+                Ok(ConfigSigner::Ed25519(ed25519_dalek::SigningKey::from_bytes(&seed)))
+            }
+            "hmac-sha256" => Ok(ConfigSigner::HmacSha256(key.as_bytes().to_vec())),
+            other => Err(anyhow!(
+                "Unsupported CONFIG_SIGNING_ALGORITHM {}, expected one of: {}",
+                other,
+                SIGNATURE_ALGORITHMS.join(", ")
+            )),
+        }
+    }
+
+    pub fn algorithm(&self) -> &'static str {
+        match self {
+            ConfigSigner::Ed25519(_) => "ed25519",
+            ConfigSigner::HmacSha256(_) => "hmac-sha256",
+        }
+    }
+
+    /// Base64 signature of `body`
+    pub fn sign(&self, body: &[u8]) -> String {
+        match self {
+            ConfigSigner::Ed25519(key) => {
+                use ed25519_dalek::Signer;
+                STANDARD.encode(key.sign(body).to_bytes())
+            }
+            ConfigSigner::HmacSha256(key) => {
+                use hmac::{Hmac, Mac};
+
+                let mut mac = <Hmac<sha2::Sha256> as Mac>::new_from_slice(key)
+                    .expect("HMAC accepts keys of any length");
+                mac.update(body);
+                STANDARD.encode(mac.finalize().into_bytes())
+            }
+        }
+    }
+
+    /// Base64 public key (Ed25519 only; HMAC keys are never published)
+    pub fn public_key(&self) -> Option<String> {
+        match self {
+            ConfigSigner::Ed25519(key) => Some(STANDARD.encode(key.verifying_key().to_bytes())),
+            ConfigSigner::HmacSha256(_) => None,
+        }
+    }
+}
+
+/// Install the process-wide config signer (called once at startup)
+pub fn install_config_signer(signer: ConfigSigner) -> Result<()> {
+    CONFIG_SIGNER
+        .set(signer)
+        .map_err(|_| anyhow!("Config signer already installed"))
+}
+
+/// Get the process-wide config signer, if signing is enabled
+pub fn config_signer() -> Option<&'static ConfigSigner> {
+    CONFIG_SIGNER.get()
+}
+
+/// Encrypt a node secret for storage (no-op when encryption is disabled)
+pub fn seal_secret(secret: &str) -> Result<String> {
+    match field_cipher() {
+        Some(cipher) => cipher.encrypt(secret),
+        None => Ok(secret.to_string()),
+    }
+}
+
+/// Decrypt a node secret loaded from the database (no-op for plaintext secrets)
+pub fn open_secret(value: &str) -> Result<String> {
+    match field_cipher() {
+        Some(cipher) => cipher.decrypt(value),
+        None if is_encrypted(value) => Err(anyhow!("Encrypted node secret found but FIELD_ENCRYPTION_KEY is not set")),
+        None => Ok(value.to_string()),
+    }
+}
+
+/// Encrypt a node config for storage (no-op when encryption is disabled)
+pub fn seal_config(config: serde_json::Value) -> Result<serde_json::Value> {
+    match field_cipher() {
+        Some(cipher) => cipher.encrypt_config(&config),
+        None => Ok(config),
+    }
+}
+
+/// Decrypt the sensitive fields of a node loaded from the database
+pub fn open_node(mut node: Node) -> Result<Node> {
+    match field_cipher() {
+        Some(cipher) => {
+            node.secret = cipher.decrypt(&node.secret)?;
+            node.config = cipher.decrypt_config(&node.config)?;
+        }
+        None => {
+            if is_encrypted(&node.secret) || contains_encrypted(&node.config) {
+                return Err(anyhow!(
+                    "Node {} has encrypted fields but FIELD_ENCRYPTION_KEY is not set",
+                    node.id
+                ));
+            }
+        }
+    }
+
+    Ok(node)
+}
+
+/// Compute a lowercase hex HMAC-SHA256 of `payload` (used for payment callback signatures)
+pub fn hmac_sha256_hex(secret: &str, payload: &[u8]) -> String {
+    use hmac::{Hmac, Mac};
+
+    let mut mac = <Hmac<sha2::Sha256> as Mac>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(payload);
+
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Compute a lowercase hex SHA-256 digest (used to store single-use tokens)
+pub fn sha256_hex(payload: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    Sha256::digest(payload)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Compare two secrets in constant time
+pub fn secrets_match(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.bytes()
+        .zip(b.bytes())
+        .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+/// Check whether any string inside a JSON value carries the encryption prefix
+pub fn contains_encrypted(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::String(s) => is_encrypted(s),
+        serde_json::Value::Array(items) => items.iter().any(contains_encrypted),
+        serde_json::Value::Object(map) => map.values().any(contains_encrypted),
+        _ => false,
+    }
+}
+
+/// Apply `f` to every sensitive string value inside a node config
+pub fn map_sensitive_values(
+    value: &serde_json::Value,
+    f: &dyn Fn(&str) -> Result<String>,
+) -> Result<serde_json::Value> {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut out = serde_json::Map::with_capacity(map.len());
+            for (key, val) in map {
+                let mapped = match val {
+                    serde_json::Value::String(s) if SENSITIVE_CONFIG_KEYS.contains(&key.as_str()) => {
+                        serde_json::Value::String(f(s)?)
+                    }
+                    other => map_sensitive_values(other, f)?,
+                };
+                out.insert(key.clone(), mapped);
+            }
+            Ok(serde_json::Value::Object(out))
+        }
+        serde_json::Value::Array(items) => Ok(serde_json::Value::Array(
+            items
+                .iter()
+                .map(|item| map_sensitive_values(item, f))
+                .collect::<Result<Vec<_>>>()?,
+        )),
+        other => Ok(other.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn test_cipher() -> FieldCipher {
+        FieldCipher::from_base64_key(&STANDARD.encode([7u8; 32])).unwrap()
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let cipher = test_cipher();
+        let encrypted = cipher.encrypt("super-secret").unwrap();
+
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("super-secret"));
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), "super-secret");
+    }
+
+    #[test]
+    fn test_prefixed_plaintext_is_encrypted() {
+        let cipher = test_cipher();
+        let plaintext = "enc:v1:not-really-encrypted";
+        let encrypted = cipher.encrypt(plaintext).unwrap();
+
+        assert_ne!(encrypted, plaintext);
+        assert!(!cipher.can_decrypt(plaintext));
+        assert!(cipher.can_decrypt(&encrypted));
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn test_decrypt_plaintext_passthrough() {
+        let cipher = test_cipher();
+        assert_eq!(cipher.decrypt("legacy-value").unwrap(), "legacy-value");
+    }
+
+    #[test]
+    fn test_decrypt_with_wrong_key_fails() {
+        let encrypted = test_cipher().encrypt("value").unwrap();
+        let other = FieldCipher::from_base64_key(&STANDARD.encode([9u8; 32])).unwrap();
+
+        assert!(other.decrypt(&encrypted).is_err());
+        assert!(!other.can_decrypt(&encrypted));
+    }
+
+    #[test]
+    fn test_invalid_key_length() {
+        assert!(FieldCipher::from_base64_key(&STANDARD.encode([1u8; 16])).is_err());
+        assert!(FieldCipher::from_base64_key("not base64!").is_err());
+    }
+
+    #[test]
+    fn test_config_only_sensitive_keys_encrypted() {
+        let cipher = test_cipher();
+        let config = json!({
+            "uuid": "abc",
+            "reality_opts": { "public_key": "pub", "private_key": "priv" },
+            "password": "pw"
+        });
+
+        let sealed = cipher.encrypt_config(&config).unwrap();
+        assert_eq!(sealed["uuid"], "abc");
+        assert_eq!(sealed["reality_opts"]["public_key"], "pub");
+        assert!(is_encrypted(sealed["reality_opts"]["private_key"].as_str().unwrap()));
+        assert!(is_encrypted(sealed["password"].as_str().unwrap()));
+
+        let opened = cipher.decrypt_config(&sealed).unwrap();
+        assert_eq!(opened, config);
+    }
+
+    #[test]
+    fn test_hmac_sha256_hex() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hmac_sha256_hex("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_ed25519_config_signature() {
+        // RFC 8032 section 7.1, test 1
+        let seed = "nWGxne/9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A=";
+        let signer = ConfigSigner::new("ed25519", seed).unwrap();
+
+        assert_eq!(signer.algorithm(), "ed25519");
+        assert_eq!(
+            signer.public_key().as_deref(),
+            Some("11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=")
+        );
+        assert_eq!(
+            signer.sign(b""),
+            "5VZDAMNgrHKQhuLMgG6CioSHfx645dl02HPgZSJJAVVfuIIVkKM7rMYeOXAc+bRr0lv18FlbviRlUUFDjnoQCw=="
+        );
+
+        assert!(ConfigSigner::new("ed25519", "c2hvcnQ=").is_err());
+    }
+
+    #[test]
+    fn test_hmac_config_signature() {
+        let signer = ConfigSigner::new("hmac-sha256", "key").unwrap();
+
+        assert_eq!(signer.public_key(), None);
+        assert_eq!(
+            signer.sign(b"The quick brown fox jumps over the lazy dog"),
+            "97yD9DBThCSxMpjmqm+xQ+9NWaFJRhdZl0edvC0aPNg="
+        );
+        assert!(ConfigSigner::new("rsa", "key").is_err());
+    }
+
+    #[test]
+    fn test_secrets_match() {
+        assert!(secrets_match("abc", "abc"));
+        assert!(!secrets_match("abc", "abd"));
+        assert!(!secrets_match("abc", "abcd"));
+    }
+}
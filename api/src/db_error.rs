@@ -0,0 +1,189 @@
+use axum::http::StatusCode;
+
+/// Database errors that deserve a more specific response than a plain 500
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbErrorKind {
+    /// A query expecting a row found none
+    NotFound,
+    /// A unique constraint rejected the write; carries the constraint's name when known
+    UniqueViolation(Option<String>),
+    /// A foreign key rejected the write because the referenced row does not exist
+    ForeignKeyViolation(Option<String>),
+    /// The database could not be reached: connection refused or lost, pool exhausted
+    Unavailable,
+}
+
+impl DbErrorKind {
+    /// Classify a sqlx error; `None` for every other failure
+    pub fn classify(err: &sqlx::Error) -> Option<Self> {
+        match err {
+            sqlx::Error::RowNotFound => Some(DbErrorKind::NotFound),
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed => Some(DbErrorKind::Unavailable),
+            sqlx::Error::Database(e) => {
+                let constraint = e.constraint().map(str::to_string);
+                match e.kind() {
+                    sqlx::error::ErrorKind::UniqueViolation => Some(DbErrorKind::UniqueViolation(constraint)),
+                    sqlx::error::ErrorKind::ForeignKeyViolation => {
+                        Some(DbErrorKind::ForeignKeyViolation(constraint))
+                    }
+                    // Class 08 (connection exception) and 57P01-57P03 (shutdown, cannot connect now)
+                    _ if is_unavailable_code(e.code().as_deref()) => Some(DbErrorKind::Unavailable),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Classify the first sqlx error in an `anyhow` chain (db functions add context on top)
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<sqlx::Error>())
+            .and_then(Self::classify)
+    }
+
+    /// Stable machine-readable code, returned as `error.reason`
+    pub fn code(&self) -> &'static str {
+        match self {
+            DbErrorKind::NotFound => "not_found",
+            DbErrorKind::UniqueViolation(_) => "unique_violation",
+            DbErrorKind::ForeignKeyViolation(_) => "foreign_key_violation",
+            DbErrorKind::Unavailable => "unavailable",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            DbErrorKind::NotFound => StatusCode::NOT_FOUND,
+            DbErrorKind::UniqueViolation(_) => StatusCode::CONFLICT,
+            DbErrorKind::ForeignKeyViolation(_) => StatusCode::BAD_REQUEST,
+            DbErrorKind::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// Generic message, for when the handler has nothing more specific to say
+    pub fn message(&self) -> &'static str {
+        match self {
+            DbErrorKind::NotFound => "Resource not found",
+            DbErrorKind::UniqueViolation(_) => "Resource already exists",
+            DbErrorKind::ForeignKeyViolation(_) => "Referenced resource does not exist",
+            DbErrorKind::Unavailable => "Service temporarily unavailable",
+        }
+    }
+
+    /// Whether this is a unique violation of the named constraint or index
+    pub fn is_unique_violation_of(&self, name: &str) -> bool {
+        matches!(self, DbErrorKind::UniqueViolation(Some(constraint)) if constraint == name)
+    }
+}
+
+/// Whether a SQLSTATE means the server is going away or not accepting connections
+fn is_unavailable_code(code: Option<&str>) -> bool {
+    code.is_some_and(|code| code.starts_with("08") || matches!(code, "57P01" | "57P02" | "57P03"))
+}
+
+/// Whether an `anyhow` error means the database could not be reached
+pub fn is_unavailable(err: &anyhow::Error) -> bool {
+    DbErrorKind::of(err) == Some(DbErrorKind::Unavailable)
+}
+
+/// Unique index on `users(tenant_id, email)`
+pub const USERS_TENANT_EMAIL: &str = "idx_users_tenant_email";
+/// Unique constraint on `tenants.slug`
+pub const TENANTS_SLUG: &str = "tenants_slug_key";
+/// Unique constraint on `users.referral_code`
+pub const USERS_REFERRAL_CODE: &str = "users_referral_code_key";
+/// Unique constraint on `subscriptions.token`
+pub const SUBSCRIPTIONS_TOKEN: &str = "subscriptions_token_key";
+
+/// A write was skipped because its value is already in use somewhere the named unique
+/// index doesn't cover, e.g. a new subscription token equal to another subscription's
+/// `previous_token`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueTaken(pub &'static str);
+
+impl std::fmt::Display for ValueTaken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "value already taken ({})", self.0)
+    }
+}
+
+impl std::error::Error for ValueTaken {}
+
+/// Whether an `anyhow` error is a unique violation of the named constraint or index
+///
+/// A [`ValueTaken`] for the same name counts too.
+pub fn is_unique_violation(err: &anyhow::Error, constraint: &str) -> bool {
+    DbErrorKind::of(err).is_some_and(|kind| kind.is_unique_violation_of(constraint))
+        || err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<ValueTaken>())
+            .is_some_and(|taken| taken.0 == constraint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_classify_row_not_found() {
+        assert_eq!(DbErrorKind::classify(&sqlx::Error::RowNotFound), Some(DbErrorKind::NotFound));
+        assert_eq!(DbErrorKind::NotFound.status(), StatusCode::NOT_FOUND);
+        assert_eq!(DbErrorKind::NotFound.code(), "not_found");
+    }
+
+    #[test]
+    fn test_classify_server_errors() {
+        assert_eq!(DbErrorKind::classify(&sqlx::Error::Protocol("bad".to_string())), None);
+        assert_eq!(DbErrorKind::classify(&sqlx::Error::ColumnNotFound("id".to_string())), None);
+    }
+
+    #[test]
+    fn test_classify_unavailable() {
+        let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        assert_eq!(DbErrorKind::classify(&sqlx::Error::Io(refused)), Some(DbErrorKind::Unavailable));
+        assert_eq!(DbErrorKind::classify(&sqlx::Error::PoolTimedOut), Some(DbErrorKind::Unavailable));
+        assert_eq!(DbErrorKind::classify(&sqlx::Error::PoolClosed), Some(DbErrorKind::Unavailable));
+        assert_eq!(DbErrorKind::Unavailable.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(is_unavailable(&anyhow::Error::from(sqlx::Error::PoolTimedOut).context("Failed to load user")));
+
+        assert!(is_unavailable_code(Some("08006")));
+        assert!(is_unavailable_code(Some("57P01")));
+        assert!(!is_unavailable_code(Some("23505")));
+        assert!(!is_unavailable_code(None));
+    }
+
+    #[test]
+    fn test_of_looks_through_context() {
+        let err = anyhow::Error::from(sqlx::Error::RowNotFound).context("Failed to load order");
+        assert_eq!(DbErrorKind::of(&err), Some(DbErrorKind::NotFound));
+
+        let err: anyhow::Result<()> = Err(sqlx::Error::Protocol("bad".to_string())).context("Failed to load order");
+        assert_eq!(DbErrorKind::of(&err.unwrap_err()), None);
+
+        assert_eq!(DbErrorKind::of(&anyhow::anyhow!("not a database error")), None);
+    }
+
+    #[test]
+    fn test_unique_violation_of() {
+        let kind = DbErrorKind::UniqueViolation(Some(TENANTS_SLUG.to_string()));
+        assert!(kind.is_unique_violation_of(TENANTS_SLUG));
+        assert!(!kind.is_unique_violation_of(USERS_TENANT_EMAIL));
+        assert!(!DbErrorKind::UniqueViolation(None).is_unique_violation_of(TENANTS_SLUG));
+        assert_eq!(kind.status(), StatusCode::CONFLICT);
+        assert_eq!(DbErrorKind::ForeignKeyViolation(None).status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_value_taken_counts_as_unique_violation() {
+        let err = anyhow::Error::from(ValueTaken(SUBSCRIPTIONS_TOKEN)).context("Failed to rotate token");
+        assert!(is_unique_violation(&err, SUBSCRIPTIONS_TOKEN));
+        assert!(!is_unique_violation(&err, USERS_REFERRAL_CODE));
+        assert_eq!(DbErrorKind::of(&err), None);
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::db;
+use crate::models::FeatureFlag;
+
+/// How long flags are cached in process memory
+///
+/// Changes made on this replica apply immediately; other replicas pick them up
+/// within this window.
+pub const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Longest accepted flag key
+pub const MAX_KEY_LEN: usize = 100;
+
+/// Flag rolling out the remaining-traffic and expiry entries at the top of subscriptions
+/// (on for everyone when `SUBSCRIPTION_INFO_ENTRIES` is set)
+pub const SUBSCRIPTION_INFO_ENTRIES: &str = "subscription_info_entries";
+
+type FlagMap = Arc<HashMap<String, FeatureFlag>>;
+
+static FLAG_CACHE: OnceLock<RwLock<Option<(Instant, FlagMap)>>> = OnceLock::new();
+
+fn flag_cache() -> &'static RwLock<Option<(Instant, FlagMap)>> {
+    FLAG_CACHE.get_or_init(|| RwLock::new(None))
+}
+
+/// Whether `key` is a valid flag key: lowercase letters, digits, `_`, `.` and `-`
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= MAX_KEY_LEN
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || matches!(b, b'_' | b'.' | b'-'))
+}
+
+/// Stable rollout bucket (0-99) of a user for a flag
+///
+/// Hashing the key with the user id gives each flag an independent user sample, and
+/// raising the percentage only ever adds users.
+pub fn rollout_bucket(key: &str, user_id: i64) -> u8 {
+    let digest = Sha256::digest(format!("{}:{}", key, user_id).as_bytes());
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(prefix) % 100) as u8
+}
+
+/// Whether a flag is on for a user (None for anonymous requests)
+///
+/// Anonymous requests only see flags rolled out to everyone.
+pub fn evaluate(flag: &FeatureFlag, user_id: Option<i64>) -> bool {
+    if !flag.enabled {
+        return false;
+    }
+
+    match user_id {
+        Some(id) if flag.user_ids.contains(&id) => true,
+        Some(id) => i16::from(rollout_bucket(&flag.key, id)) < flag.rollout_percentage,
+        None => flag.rollout_percentage >= 100,
+    }
+}
+
+/// Drop cached flags so the next evaluation reloads them
+pub fn invalidate_cache() {
+    *flag_cache().write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Replace the cached flags, so tests can evaluate them without a database
+#[cfg(test)]
+pub(crate) fn cache_flags(flags: Vec<FeatureFlag>) {
+    let flags = Arc::new(flags.into_iter().map(|flag| (flag.key.clone(), flag)).collect());
+    *flag_cache().write().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), flags));
+}
+
+async fn load_flags(db_pool: &PgPool) -> Result<FlagMap> {
+    if let Some((loaded_at, flags)) = flag_cache().read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        if loaded_at.elapsed() < CACHE_TTL {
+            return Ok(flags.clone());
+        }
+    }
+
+    let flags: FlagMap = Arc::new(
+        db::list_feature_flags(db_pool)
+            .await?
+            .into_iter()
+            .map(|flag| (flag.key.clone(), flag))
+            .collect(),
+    );
+    *flag_cache().write().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), flags.clone()));
+
+    Ok(flags)
+}
+
+/// Whether the flag `key` is on for a user
+///
+/// Unknown flags are off, and so is every flag when they cannot be loaded.
+pub async fn is_enabled(db_pool: &PgPool, key: &str, user_id: Option<i64>) -> bool {
+    match load_flags(db_pool).await {
+        Ok(flags) => flags.get(key).map_or(false, |flag| evaluate(flag, user_id)),
+        Err(e) => {
+            tracing::warn!("Failed to load feature flags, treating '{}' as off: {}", key, e);
+            false
+        }
+    }
+}
+
+/// Keys of all flags that are on for a user, sorted
+pub async fn enabled_for_user(db_pool: &PgPool, user_id: i64) -> Result<Vec<String>> {
+    let flags = load_flags(db_pool).await?;
+    let mut keys: Vec<String> = flags
+        .values()
+        .filter(|flag| evaluate(flag, Some(user_id)))
+        .map(|flag| flag.key.clone())
+        .collect();
+    keys.sort();
+
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn flag(enabled: bool, rollout_percentage: i16, user_ids: Vec<i64>) -> FeatureFlag {
+        FeatureFlag {
+            key: "new_subscription_format".to_string(),
+            description: None,
+            enabled,
+            rollout_percentage,
+            user_ids,
+            updated_by: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_is_valid_key() {
+        assert!(is_valid_key("new_subscription_format"));
+        assert!(is_valid_key("rbac.v2-beta"));
+        assert!(!is_valid_key(""));
+        assert!(!is_valid_key("Upper"));
+        assert!(!is_valid_key("with space"));
+        assert!(!is_valid_key(&"a".repeat(MAX_KEY_LEN + 1)));
+    }
+
+    #[test]
+    fn test_disabled_flag_is_off_for_everyone() {
+        let flag = flag(false, 100, vec![1]);
+        assert!(!evaluate(&flag, Some(1)));
+        assert!(!evaluate(&flag, Some(2)));
+        assert!(!evaluate(&flag, None));
+    }
+
+    #[test]
+    fn test_listed_users_bypass_rollout() {
+        let flag = flag(true, 0, vec![42]);
+        assert!(evaluate(&flag, Some(42)));
+        assert!(!evaluate(&flag, Some(43)));
+        assert!(!evaluate(&flag, None));
+    }
+
+    #[test]
+    fn test_full_rollout_includes_anonymous() {
+        let flag = flag(true, 100, vec![]);
+        assert!((1..=1000).all(|id| evaluate(&flag, Some(id))));
+        assert!(evaluate(&flag, None));
+    }
+
+    #[test]
+    fn test_partial_rollout_is_stable_and_monotonic() {
+        let quarter = flag(true, 25, vec![]);
+        let half = flag(true, 50, vec![]);
+
+        let in_quarter: Vec<i64> = (1..=10_000).filter(|id| evaluate(&quarter, Some(*id))).collect();
+        // Roughly 25% of users, allowing for hash variance
+        assert!((2_000..3_000).contains(&in_quarter.len()), "got {}", in_quarter.len());
+        // Same users every time, and all of them stay in at a higher percentage
+        assert!(in_quarter.iter().all(|id| evaluate(&quarter, Some(*id))));
+        assert!(in_quarter.iter().all(|id| evaluate(&half, Some(*id))));
+    }
+
+    #[test]
+    fn test_rollout_bucket_depends_on_key() {
+        let differing = (1..=100)
+            .filter(|id| rollout_bucket("flag_a", *id) != rollout_bucket("flag_b", *id))
+            .count();
+        assert!(differing > 50);
+        assert!((1..=1000).all(|id| rollout_bucket("flag_a", id) < 100));
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::{Context, Result};
+use maxminddb::{geoip2, Reader};
+use serde::Serialize;
+use std::net::IpAddr;
+
+use crate::config::GeoRestrictionConfig;
+use crate::models::SubscriptionGeoRestriction;
+
+crate::status::status_enum! {
+    /// What happens to a subscription fetch from a restricted location
+    GeoAction {
+        /// Refused with 403
+        Block => "block",
+        /// Answered with an empty config, so scrapers get nothing but can't tell they were filtered
+        Challenge => "challenge",
+    }
+}
+
+impl GeoAction {
+    /// Status recorded in `clash_access_logs.response_status`
+    pub fn access_status(self) -> &'static str {
+        match self {
+            GeoAction::Block => "geo_blocked",
+            GeoAction::Challenge => "geo_challenged",
+        }
+    }
+}
+
+/// Where a client address is, as far as the GeoIP databases know
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GeoLocation {
+    /// ISO 3166-1 alpha-2 code, uppercase
+    pub country: Option<String>,
+    pub asn: Option<u32>,
+}
+
+/// MaxMind (GeoLite2 / GeoIP2) country and ASN databases, loaded once at startup
+pub struct GeoIpResolver {
+    country: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIpResolver {
+    /// Load the configured databases; `None` when no database is configured
+    pub fn open(settings: &GeoRestrictionConfig) -> Result<Option<Self>> {
+        if !settings.enabled() {
+            return Ok(None);
+        }
+        let open = |path: &String| {
+            Reader::open_readfile(path).with_context(|| format!("Failed to open GeoIP database {}", path))
+        };
+
+        Ok(Some(Self {
+            country: settings.country_db_path.as_ref().map(open).transpose()?,
+            asn: settings.asn_db_path.as_ref().map(open).transpose()?,
+        }))
+    }
+
+    /// Look up an address; addresses missing from a database (private ranges) stay unknown
+    pub fn lookup(&self, ip: IpAddr) -> GeoLocation {
+        let country = self.country.as_ref().and_then(|reader| {
+            reader
+                .lookup::<geoip2::Country>(ip)
+                .ok()
+                .and_then(|record| record.country)
+                .and_then(|country| country.iso_code)
+                .map(str::to_ascii_uppercase)
+        });
+        let asn = self.asn.as_ref().and_then(|reader| {
+            reader
+                .lookup::<geoip2::Asn>(ip)
+                .ok()
+                .and_then(|record| record.autonomous_system_number)
+        });
+
+        GeoLocation { country, asn }
+    }
+}
+
+/// Restrictions applied to one user's subscription fetches
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GeoPolicy {
+    /// Only these countries are served (empty: any country)
+    pub allowed_countries: Vec<String>,
+    pub blocked_countries: Vec<String>,
+    pub blocked_asns: Vec<u32>,
+    pub action: GeoAction,
+}
+
+impl GeoPolicy {
+    /// The global policy, with a user's override in place of the fields it sets
+    ///
+    /// An override with empty country lists exempts the user from the global country
+    /// rules; blocked ASNs always apply.
+    pub fn effective(settings: &GeoRestrictionConfig, user: Option<&SubscriptionGeoRestriction>) -> Self {
+        Self {
+            allowed_countries: user
+                .and_then(|u| u.allowed_countries.clone())
+                .unwrap_or_else(|| settings.allowed_countries.clone()),
+            blocked_countries: user
+                .and_then(|u| u.blocked_countries.clone())
+                .unwrap_or_else(|| settings.blocked_countries.clone()),
+            blocked_asns: settings.blocked_asns.clone(),
+            action: user.and_then(|u| u.action).unwrap_or(settings.action),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allowed_countries.is_empty() && self.blocked_countries.is_empty() && self.blocked_asns.is_empty()
+    }
+
+    /// The action to take for a fetch from `location`, or `None` if it is served
+    ///
+    /// Unknown locations are served: private addresses and gaps in the database
+    /// should not lock users out.
+    pub fn evaluate(&self, location: &GeoLocation) -> Option<GeoAction> {
+        let asn_blocked = location.asn.is_some_and(|asn| self.blocked_asns.contains(&asn));
+        let country_blocked = location.country.as_ref().is_some_and(|country| {
+            self.blocked_countries.contains(country)
+                || (!self.allowed_countries.is_empty() && !self.allowed_countries.contains(country))
+        });
+
+        (asn_blocked || country_blocked).then_some(self.action)
+    }
+}
+
+/// Validate and uppercase ISO 3166-1 alpha-2 country codes
+pub fn parse_country_codes<S: AsRef<str>>(codes: &[S]) -> Result<Vec<String>, String> {
+    codes
+        .iter()
+        .map(|code| {
+            let code = code.as_ref().trim();
+            if code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+                Ok(code.to_ascii_uppercase())
+            } else {
+                Err(code.to_string())
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allowed: &[&str], blocked: &[&str], asns: &[u32]) -> GeoPolicy {
+        GeoPolicy {
+            allowed_countries: allowed.iter().map(|c| c.to_string()).collect(),
+            blocked_countries: blocked.iter().map(|c| c.to_string()).collect(),
+            blocked_asns: asns.to_vec(),
+            action: GeoAction::Block,
+        }
+    }
+
+    fn at(country: Option<&str>, asn: Option<u32>) -> GeoLocation {
+        GeoLocation {
+            country: country.map(str::to_string),
+            asn,
+        }
+    }
+
+    #[test]
+    fn test_evaluate_geo_policy() {
+        let mainland_only = policy(&["CN"], &[], &[14061]);
+        assert_eq!(mainland_only.evaluate(&at(Some("CN"), Some(4134))), None);
+        assert_eq!(mainland_only.evaluate(&at(Some("US"), None)), Some(GeoAction::Block));
+        assert_eq!(mainland_only.evaluate(&at(Some("CN"), Some(14061))), Some(GeoAction::Block));
+        // Unknown locations are served
+        assert_eq!(mainland_only.evaluate(&at(None, None)), None);
+
+        let blocklist = policy(&[], &["US", "SG"], &[]);
+        assert_eq!(blocklist.evaluate(&at(Some("SG"), None)), Some(GeoAction::Block));
+        assert_eq!(blocklist.evaluate(&at(Some("JP"), None)), None);
+
+        assert!(policy(&[], &[], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_effective_policy_applies_user_override() {
+        let settings = GeoRestrictionConfig {
+            country_db_path: Some("GeoLite2-Country.mmdb".to_string()),
+            allowed_countries: vec!["CN".to_string()],
+            blocked_asns: vec![14061],
+            ..GeoRestrictionConfig::default()
+        };
+        assert_eq!(GeoPolicy::effective(&settings, None), policy(&["CN"], &[], &[14061]));
+
+        // Empty lists exempt the user from country rules, but not from blocked ASNs
+        let exempt = SubscriptionGeoRestriction {
+            user_id: 1,
+            allowed_countries: Some(vec![]),
+            blocked_countries: None,
+            action: Some(GeoAction::Challenge),
+            updated_by: None,
+            updated_at: chrono::Utc::now(),
+        };
+        let effective = GeoPolicy::effective(&settings, Some(&exempt));
+        assert_eq!(effective.evaluate(&at(Some("US"), None)), None);
+        assert_eq!(effective.evaluate(&at(Some("US"), Some(14061))), Some(GeoAction::Challenge));
+    }
+
+    #[test]
+    fn test_parse_country_codes() {
+        assert_eq!(parse_country_codes(&["cn", " hk "]), Ok(vec!["CN".to_string(), "HK".to_string()]));
+        assert_eq!(parse_country_codes(&["CHN"]), Err("CHN".to_string()));
+        assert_eq!(parse_country_codes(&["1A"]), Err("1A".to_string()));
+    }
+}
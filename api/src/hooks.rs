@@ -0,0 +1,135 @@
+use crate::clash::{ClashConfig, ClientFlavor};
+use crate::models::{Order, Package};
+
+/// Custom logic around package purchases (e.g. loyalty points)
+///
+/// Implementations needing the database hold their own `PgPool`.
+#[axum::async_trait]
+pub trait PurchaseHook: Send + Sync {
+    /// Called before a coin purchase or mixed-payment order is committed; an `Err`
+    /// rejects the purchase with its message
+    async fn before_purchase(&self, _user_id: i64, _package: &Package) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called once a purchase has completed and committed (for mixed-payment orders,
+    /// when the payment is captured)
+    ///
+    /// The response waits for it, so slow work should be spawned.
+    async fn after_purchase(&self, _order: &Order) {}
+}
+
+/// Custom changes to generated subscription configs
+pub trait ConfigHook: Send + Sync {
+    /// Called on each generated config before it is serialized
+    ///
+    /// Users with the same inputs share one generated config, so the change must not
+    /// depend on who fetches it.
+    fn before_serialize(&self, config: &mut ClashConfig, flavor: ClientFlavor);
+}
+
+/// Hooks registered in `main.rs`, run in registration order
+#[derive(Default)]
+pub struct Hooks {
+    pub purchase: Vec<Box<dyn PurchaseHook>>,
+    pub config: Vec<Box<dyn ConfigHook>>,
+}
+
+impl Hooks {
+    /// Run the purchase hooks' checks; the first rejection wins
+    pub async fn before_purchase(&self, user_id: i64, package: &Package) -> Result<(), String> {
+        for hook in &self.purchase {
+            hook.before_purchase(user_id, package).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn after_purchase(&self, order: &Order) {
+        for hook in &self.purchase {
+            hook.after_purchase(order).await;
+        }
+    }
+
+    pub fn before_serialize(&self, config: &mut ClashConfig, flavor: ClientFlavor) {
+        for hook in &self.config {
+            hook.before_serialize(config, flavor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amounts::{Coins, TrafficBytes};
+    use chrono::Utc;
+
+    struct RejectPackage(&'static str);
+
+    #[axum::async_trait]
+    impl PurchaseHook for RejectPackage {
+        async fn before_purchase(&self, _user_id: i64, package: &Package) -> Result<(), String> {
+            if package.name == self.0 {
+                return Err(format!("{} is not for sale", package.name));
+            }
+            Ok(())
+        }
+    }
+
+    struct AppendRule(&'static str);
+
+    impl ConfigHook for AppendRule {
+        fn before_serialize(&self, config: &mut ClashConfig, _flavor: ClientFlavor) {
+            config.rules.push(self.0.to_string());
+        }
+    }
+
+    fn package(name: &str) -> Package {
+        Package {
+            id: 1,
+            name: name.to_string(),
+            traffic_amount: TrafficBytes::new(1 << 30),
+            price: Coins::new(100),
+            duration_days: 30,
+            description: None,
+            is_active: true,
+            update_interval_hours: 24,
+            max_connections: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            tenant_id: 1,
+            stock: None,
+            max_per_user: 0,
+            exclusive: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_before_purchase() {
+        assert_eq!(Hooks::default().before_purchase(1, &package("Basic")).await, Ok(()));
+
+        let hooks = Hooks {
+            purchase: vec![Box::new(RejectPackage("Basic")), Box::new(RejectPackage("Pro"))],
+            config: vec![],
+        };
+        assert_eq!(hooks.before_purchase(1, &package("Trial")).await, Ok(()));
+        assert_eq!(
+            hooks.before_purchase(1, &package("Pro")).await,
+            Err("Pro is not for sale".to_string())
+        );
+    }
+
+    #[test]
+    fn test_before_serialize() {
+        let hooks = Hooks {
+            purchase: vec![],
+            config: vec![Box::new(AppendRule("DOMAIN,a.example,DIRECT")), Box::new(AppendRule("MATCH,DIRECT"))],
+        };
+        let mut config = ClashConfig {
+            proxies: vec![],
+            proxy_groups: vec![],
+            rules: vec![],
+        };
+        hooks.before_serialize(&mut config, ClientFlavor::Meta);
+        assert_eq!(config.rules, vec!["DOMAIN,a.example,DIRECT", "MATCH,DIRECT"]);
+    }
+}
@@ -0,0 +1,395 @@
+/// Supported response languages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    En,
+    Zh,
+}
+
+impl Lang {
+    /// Language code as stored on the user profile
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::Zh => "zh",
+        }
+    }
+
+    /// Parse a language tag such as `zh`, `zh-CN` or `en-US`
+    pub fn parse(tag: &str) -> Option<Self> {
+        let primary = tag.trim().split(['-', '_']).next()?.to_ascii_lowercase();
+        match primary.as_str() {
+            "en" => Some(Lang::En),
+            "zh" => Some(Lang::Zh),
+            _ => None,
+        }
+    }
+
+    /// Pick the best supported language from an Accept-Language header
+    /// e.g. `zh-CN,zh;q=0.9,en;q=0.8` -> Zh
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        header
+            .split(',')
+            .filter_map(|part| {
+                let mut pieces = part.split(';');
+                let lang = Lang::parse(pieces.next()?)?;
+                let quality = pieces
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                Some((lang, quality))
+            })
+            .filter(|(_, q)| *q > 0.0)
+            .fold(None, |best: Option<(Lang, f32)>, (lang, q)| match best {
+                Some((_, best_q)) if best_q >= q => best,
+                _ => Some((lang, q)),
+            })
+            .map(|(lang, _)| lang)
+    }
+}
+
+/// API error message catalog: (key, en, zh)
+///
+/// The English text matches the message passed to `ApiError`, so existing call sites
+/// are translated without change. Entries ending in ": " match as prefixes and keep
+/// the dynamic suffix (e.g. `Failed to generate config: <details>`).
+const ERROR_CATALOG: &[(&str, &str, &str)] = &[
+    ("auth.missing_header", "Missing or invalid authorization header", "缺少或无效的授权头"),
+    ("auth.missing_header", "Missing authorization header", "缺少授权头"),
+    ("auth.invalid_token", "Invalid or expired token", "令牌无效或已过期"),
+    ("auth.invalid_token", "Invalid token", "令牌无效"),
+    ("auth.token_revoked", "Token has been revoked", "令牌已失效，请重新登录"),
+    ("auth.admin_required", "Admin access required", "需要管理员权限"),
+    ("auth.platform_admin_required", "Platform admin access required", "需要平台管理员权限"),
+    ("auth.invalid_credentials", "Invalid credentials", "邮箱或密码错误"),
+    ("auth.account_disabled", "Account is disabled", "账户已被禁用"),
+    ("auth.access_denied", "Access denied", "拒绝访问"),
+    ("auth.token_required", "Token is required", "缺少令牌"),
+    ("auth.email_exists", "Email already exists", "邮箱已被注册"),
+    ("auth.invalid_referral_code", "Invalid referral code", "邀请码无效"),
+    ("auth.domain_not_allowed", "Registration is not open to this email domain", "该邮箱域名暂未开放注册"),
+    ("auth.domain_denied", "Registration from this email domain is not allowed", "该邮箱域名禁止注册"),
+    ("auth.disposable_email", "Disposable email addresses are not allowed", "不允许使用临时邮箱注册"),
+    ("auth.referral_required", "A referral code is required to register", "注册需要邀请码"),
+    ("auth.weak_password", "Password does not meet the password policy", "密码不符合密码要求"),
+    ("auth.registration_rate_limited", "Too many registrations from this IP address, please try again later", "该 IP 注册次数过多，请稍后再试"),
+    ("auth.invalid_api_key", "Invalid or expired API key", "API 密钥无效或已过期"),
+    ("terms.outdated", "Terms of service version is outdated", "服务条款版本已更新，请重新阅读后接受"),
+    ("terms.none", "There are no terms of service to accept", "当前没有需要接受的服务条款"),
+    ("terms.required", "Please accept the current terms of service before purchasing", "请先接受最新的服务条款再购买"),
+    ("auth.api_key_scope_missing", "API key is missing the required scope: ", "API 密钥缺少所需的权限范围："),
+    ("auth.api_key_no_owner", "API key has no owner to record the reveal under", "API 密钥没有创建者，无法记录本次查看"),
+    ("auth.oauth_provider_disabled", "OAuth provider is not enabled", "未启用该第三方登录方式"),
+    ("auth.oauth_denied", "OAuth login was cancelled or denied", "第三方登录已取消或被拒绝"),
+    ("auth.oauth_invalid_state", "Invalid or expired OAuth state", "第三方登录请求无效或已过期，请重新登录"),
+    ("auth.oauth_missing_code", "Missing authorization code", "缺少授权码"),
+    ("auth.oauth_failed", "OAuth login failed", "第三方登录失败"),
+    ("auth.oauth_unverified_email", "OAuth account has no verified email address", "第三方账户没有已验证的邮箱"),
+    ("auth.oauth_other_tenant", "OAuth account is linked to an account on another site", "该第三方账户已绑定其他站点的账户"),
+    ("node.invalid_credentials", "Invalid node credentials", "节点凭证无效"),
+    ("node.not_found", "Node not found", "节点不存在"),
+    ("node.pending_approval", "Node is pending approval", "节点等待审核"),
+    ("node.invalid_enrollment_token", "Invalid or expired enrollment token", "注册令牌无效或已过期"),
+    ("node.enrollment_token_not_found", "Enrollment token not found or already used", "注册令牌不存在或已被使用"),
+    ("node.enrollment_token_ttl", "expires_in_hours must be between 1 and 720", "expires_in_hours 必须在 1 到 720 之间"),
+    ("node.suspended", "Node is suspended", "节点已被暂停"),
+    ("node.invalid_tags", "Node tags must be 1-32 characters, at most 20 per node", "节点标签长度必须为 1 到 32 个字符，每个节点最多 20 个"),
+    ("node.stale_request", "Request timestamp is outside the allowed window", "请求时间戳超出允许范围"),
+    ("node.replayed_nonce", "Request nonce has already been used", "请求 nonce 已被使用"),
+    ("node.invalid_nonce", "nonce must be 16-64 letters, digits, '-' or '_'", "nonce 必须为 16 到 64 个字母、数字、'-' 或 '_'"),
+    ("node.timestamp_required", "timestamp is required", "缺少 timestamp"),
+    ("node.host_required", "host is required", "缺少主机地址"),
+    ("node.id_required", "node_id is required", "缺少 node_id"),
+    ("node.secret_required", "secret is required", "缺少 secret"),
+    ("node.invalid_port", "Port must be between 1 and 65535", "端口必须在 1 到 65535 之间"),
+    ("node.invalid_config", "Invalid node config", "节点配置无效"),
+    ("node.same_protocol", "Node already uses this protocol", "节点已在使用该协议"),
+    ("field.required", "Field is required", "缺少必填字段"),
+    ("field.not_object", "Must be a JSON object", "必须为 JSON 对象"),
+    ("field.not_string", "Must be a string", "必须为字符串"),
+    ("field.empty", "Must not be empty", "不能为空"),
+    ("field.not_boolean", "Must be a boolean", "必须为布尔值"),
+    ("field.not_uuid", "Must be a UUID", "必须为 UUID"),
+    ("field.not_port_range", "Must be an integer between 0 and 65535", "必须为 0 到 65535 之间的整数"),
+    ("field.not_one_of", "Must be one of: ", "可选值："),
+    ("field.invalid_public_key", "Must be a base64url X25519 public key", "必须为 base64url 编码的 X25519 公钥"),
+    ("field.invalid_short_ids", "Must be an array of hex short IDs (up to 16 characters)", "必须为十六进制 short ID 数组（最多 16 个字符）"),
+    ("field.reality_vless_only", "Only VLESS nodes use Reality", "只有 VLESS 节点可以使用 Reality"),
+    ("field.invalid_dest", "Must be host:port", "必须为 host:port 格式"),
+    ("field.no_server_names", "Must list at least one server name", "至少需要一个服务器名称"),
+    ("user.not_found", "User not found", "用户不存在"),
+    ("traffic.report_range_order", "start_date must not be after end_date", "开始日期不能晚于结束日期"),
+    ("traffic.report_range_too_long", "Date range cannot exceed 366 days", "日期范围不能超过 366 天"),
+    ("order.not_found", "Order not found", "订单不存在"),
+    ("package.not_found", "Package not found", "套餐不存在"),
+    ("package.unavailable", "Package is not available", "套餐不可购买"),
+    ("package.out_of_stock", "Package is out of stock", "套餐已售罄"),
+    ("package.purchase_limit", "Package purchase limit reached", "已达到该套餐的购买次数上限"),
+    ("package.conflict", "Package cannot be held alongside your current package", "该套餐不能与当前生效的套餐同时持有"),
+    ("subscription.not_found", "Subscription not found", "订阅不存在"),
+    ("subscription.region_blocked", "Subscription is not available in your region", "您所在的地区无法使用该订阅"),
+    ("subscription.invalid_country", "Invalid country code: ", "无效的国家/地区代码："),
+    ("subscription.short_link_not_found", "Short link not found", "短链接不存在"),
+    ("subscription.short_link_ttl", "expires_in_hours must be between 1 and 8760", "expires_in_hours 必须在 1 到 8760 之间"),
+    ("referral.not_found", "Referral code not found", "邀请码不存在"),
+    ("balance.insufficient", "Insufficient balance", "余额不足"),
+    ("balance.amount_positive", "Amount must be positive", "金额必须为正数"),
+    ("balance.amount_required", "amount is required", "缺少金额"),
+    ("balance.negative", "Balance cannot be negative", "余额不能为负数"),
+    ("balance.overflow", "Balance overflow", "余额超出上限"),
+    ("balance.below_reserved", "Balance cannot be lower than coins reserved by pending orders", "余额不能低于待支付订单预留的金币"),
+    ("topup.not_found", "Top-up request not found", "充值申请不存在"),
+    ("topup.reviewed", "Top-up request has already been reviewed", "该充值申请已审核"),
+    ("topup.too_many_pending", "Too many pending top-up requests", "待审核的充值申请过多"),
+    ("topup.amount_too_large", "Top-up amount exceeds the maximum of 1000000", "充值金额超过上限 1000000"),
+    ("topup.reference_required", "reference or screenshot_url is required", "请填写 reference 或 screenshot_url"),
+    ("topup.reference_too_long", "reference must be at most 200 characters", "reference 最多 200 个字符"),
+    ("topup.invalid_screenshot_url", "screenshot_url must be an http(s) URL of at most 500 characters", "screenshot_url 必须为不超过 500 个字符的 http(s) 链接"),
+    ("topup.note_too_long", "note must be at most 500 characters", "note 最多 500 个字符"),
+    ("adjustment.not_found", "Balance adjustment not found", "余额调整不存在"),
+    ("adjustment.reviewed", "Balance adjustment has already been reviewed", "该余额调整已审批"),
+    ("adjustment.same_admin", "Balance adjustments must be approved by another admin", "余额调整须由另一名管理员批准"),
+    ("adjustment.invalid_reason", "reason must be 1 to 200 characters", "reason 长度须为 1 到 200 个字符"),
+    ("payment.disabled", "External payments are not enabled", "未启用第三方支付"),
+    ("payment.coins_cover_price", "Coin balance covers the full price, use a coin purchase instead", "金币余额已足够支付全款，请直接使用金币购买"),
+    ("payment.missing_signature", "Missing payment signature", "缺少支付签名"),
+    ("payment.invalid_signature", "Invalid payment signature", "支付签名无效"),
+    ("payment.amount_mismatch", "Paid amount does not match the order", "支付金额与订单不符"),
+    ("payment.invalid_status", "status must be 'paid' or 'failed'", "status 必须为 'paid' 或 'failed'"),
+    ("payment.order_settled", "Order is no longer awaiting payment", "订单已不处于待支付状态"),
+    ("payment.not_sandbox", "Only sandbox orders can be simulated", "只能模拟沙盒订单的支付"),
+    ("traffic.negative", "Traffic values cannot be negative", "流量值不能为负数"),
+    ("traffic.invalid_batch", "reports must contain between 1 and 1000 entries", "reports 须包含 1 到 1000 条记录"),
+    ("traffic.quota_overflow", "Traffic quota overflow", "流量配额超出上限"),
+    ("traffic.no_active_package", "User has no active package to adjust", "用户没有可调整的有效套餐"),
+    ("traffic.adjustment_too_large", "Traffic change exceeds what the current package holds", "流量调整超出当前套餐的可调整范围"),
+    ("traffic.quarantine_not_found", "Quarantined report not found", "隔离的流量上报不存在"),
+    ("traffic.quarantine_reviewed", "Quarantined report has already been reviewed", "该流量上报已审核"),
+    ("node.backup_of_self", "A node cannot be its own backup", "节点不能作为自己的备用节点"),
+    ("node.primary_not_found", "Primary node not found", "主节点不存在"),
+    ("node.backup_tenant", "A backup must belong to the same tenant as its primary", "备用节点必须与主节点属于同一租户"),
+    ("node.primary_is_backup", "Primary node is itself a backup", "主节点本身是备用节点"),
+    ("node.has_backups", "A node with backups cannot become a backup", "已有备用节点的节点不能再作为备用节点"),
+    ("node.budget_negative", "traffic_budget cannot be negative", "traffic_budget 不能为负数"),
+    ("node.invalid_budget_action", "Invalid traffic_budget_action. Must be one of: ", "traffic_budget_action 无效，可选值："),
+    ("node.agent_interval_range", "Agent interval must be between 5 and 3600 seconds: ", "代理间隔必须在 5 到 3600 秒之间："),
+    ("node.too_many_probe_targets", "Too many probe targets (max 10)", "探测目标过多（最多 10 个）"),
+    ("node.invalid_probe_target", "Invalid probe target: ", "探测目标无效："),
+    ("status.required", "status is required", "缺少状态"),
+    ("status.invalid", "Invalid status. Must be one of: ", "status 无效，可选值："),
+    ("alert.invalid_status", "status must be 'active' or 'resolved'", "status 必须为 'active' 或 'resolved'"),
+    ("stats.invalid_granularity", "granularity must be 'day', 'week' or 'month'", "granularity 必须为 'day'、'week' 或 'month'"),
+    ("stats.invalid_start_date", "start_date must be YYYY-MM-DD or RFC 3339", "start_date 必须为 YYYY-MM-DD 或 RFC 3339 格式"),
+    ("stats.invalid_end_date", "end_date must be YYYY-MM-DD or RFC 3339", "end_date 必须为 YYYY-MM-DD 或 RFC 3339 格式"),
+    ("stats.invalid_range", "start_date must be before end_date", "start_date 必须早于 end_date"),
+    ("security.invalid_event_type", "Invalid event_type. Must be one of: ", "event_type 无效，可选值："),
+    ("user.unsupported_language", "Unsupported language", "不支持的语言"),
+    ("user.note_empty", "Note cannot be empty", "备注不能为空"),
+    ("user.note_too_long", "Note must be at most 2000 characters", "备注最多 2000 个字符"),
+    ("user.note_not_found", "Note not found", "备注不存在"),
+    ("user.invalid_tag", "Tag must be 1 to 32 characters", "标签须为 1 到 32 个字符"),
+    ("user.too_many_tags", "A user can have at most 20 tags", "每个用户最多 20 个标签"),
+    ("user.tag_exists", "User already has this tag", "用户已有该标签"),
+    ("user.tag_not_found", "Tag not found", "标签不存在"),
+    ("user.import_empty", "No users found in content", "内容中未找到用户"),
+    ("user.import_too_large", "At most 500 users can be imported at once", "每次最多导入 500 个用户"),
+    ("user.import_invalid_rate", "balance_rate must be a positive number", "balance_rate 必须为正数"),
+    ("user.import_invalid_json", "Invalid JSON export: ", "JSON 导出内容无效："),
+    ("cache.invalid_scope", "scope must be 'all-subscriptions', 'node:{id}' or 'user:{id}'", "scope 必须为 'all-subscriptions'、'node:{id}' 或 'user:{id}'"),
+    ("cache.invalid_purge_scope", "scope must be 'user-packages', 'user-package:{id}', 'node-groups', 'node-groups:{tenant_id}', 'all-subscriptions', 'node:{id}' or 'user:{id}'", "scope 必须为 'user-packages'、'user-package:{id}'、'node-groups'、'node-groups:{tenant_id}'、'all-subscriptions'、'node:{id}' 或 'user:{id}'"),
+    ("job.invalid_kind", "Invalid job kind. Must be one of: ", "任务类型无效，可选值："),
+    ("job.invalid_params", "Invalid job params", "任务参数无效"),
+    ("job.not_found", "Job not found", "任务不存在"),
+    ("job.result_not_found", "Job result not found or expired", "任务结果不存在或已过期"),
+    ("job.invalid_download_link", "Invalid or expired download link", "下载链接无效或已过期"),
+    ("feature.invalid_key", "Flag key must be 1-100 characters of lowercase letters, digits, '_', '.' or '-'", "功能开关名称须为 1 到 100 个小写字母、数字、'_'、'.' 或 '-'"),
+    ("feature.invalid_rollout", "rollout_percentage must be between 0 and 100", "rollout_percentage 必须在 0 到 100 之间"),
+    ("feature.not_found", "Feature flag not found", "功能开关不存在"),
+    ("incident.invalid_title", "Incident title must be 1-200 characters", "公告标题须为 1 到 200 个字符"),
+    ("incident.not_found", "Incident not found", "公告不存在"),
+    ("tenant.not_found", "Tenant not found", "租户不存在"),
+    ("tenant.invalid_slug", "Tenant slug must be 1-50 lowercase letters, digits or dashes", "租户标识须为 1 到 50 个小写字母、数字或 '-'"),
+    ("tenant.invalid_name", "Tenant name must be 1-100 characters", "租户名称须为 1 到 100 个字符"),
+    ("tenant.invalid_domain", "Invalid tenant domain", "租户域名无效"),
+    ("tenant.invalid_settings", "Tenant settings must be a JSON object", "租户设置必须是 JSON 对象"),
+    ("tenant.domain_taken", "Domain is already used by another tenant", "该域名已被其他租户使用"),
+    ("tenant.slug_taken", "Tenant slug already exists", "租户标识已存在"),
+    ("tenant.default_inactive", "The default tenant cannot be deactivated", "默认租户不能停用"),
+    ("api_key.invalid_name", "API key name must be 1-100 characters", "API 密钥名称须为 1 到 100 个字符"),
+    ("api_key.no_scopes", "At least one scope is required", "至少需要一个权限范围"),
+    ("api_key.invalid_expiry", "expires_in_days must be between 1 and 3650", "expires_in_days 必须在 1 到 3650 之间"),
+    ("api_key.not_found", "API key not found", "API 密钥不存在"),
+    ("clash.rule_not_found", "Rule not found", "规则不存在"),
+    ("clash.group_not_found", "Proxy group not found", "代理组不存在"),
+    ("clash.no_rules", "No rules found in content", "内容中未找到规则"),
+    ("clash.sort_order_invalid", "sort_order must be a non-negative integer", "sort_order 必须为非负整数"),
+    ("server.database_error", "Database error occurred", "数据库错误"),
+    ("db.not_found", "Resource not found", "资源不存在"),
+    ("db.already_exists", "Resource already exists", "资源已存在"),
+    ("db.reference_missing", "Referenced resource does not exist", "引用的资源不存在"),
+    ("db.unavailable", "Service temporarily unavailable", "服务暂时不可用"),
+    ("server.error", "Server error", "服务器错误"),
+    ("server.purge_cache_failed", "Failed to purge cache: ", "清除缓存失败："),
+    ("server.generate_config_failed", "Failed to generate config: ", "生成配置失败："),
+    ("clash.unknown_group_members", "Proxy group references unknown proxies or groups: ", "代理组引用了不存在的代理或代理组："),
+    ("clash.unknown_rule_target", "Rule targets unknown proxy group: ", "规则指向不存在的代理组："),
+    ("clash.snapshot_not_found", "Snapshot not found", "快照不存在"),
+    ("clash.snapshot_version_invalid", "Snapshot versions must be integers", "快照版本号必须为整数"),
+    ("clash.snapshot_from_required", "Missing 'from' snapshot version", "缺少 from 快照版本号"),
+];
+
+/// Notification template catalog: (key, en, zh), placeholders written as `{name}`
+const TEMPLATE_CATALOG: &[(&str, &str, &str)] = &[
+    (
+        "alert.high_cpu",
+        "CPU usage above {threshold}% for {minutes} minutes",
+        "CPU 使用率连续 {minutes} 分钟超过 {threshold}%",
+    ),
+    (
+        "alert.connections_exceeded",
+        "Active connections {connections} exceed max users {max_users}",
+        "活跃连接数 {connections} 超过最大用户数 {max_users}",
+    ),
+    (
+        "alert.traffic_budget_warning",
+        "Monthly traffic {used_gb} GB reached {percent}% of the {budget_gb} GB budget",
+        "本月流量 {used_gb} GB 已达到预算 {budget_gb} GB 的 {percent}%",
+    ),
+    (
+        "alert.traffic_budget_exceeded",
+        "Monthly traffic {used_gb} GB used up the {budget_gb} GB budget",
+        "本月流量 {used_gb} GB 已用尽预算 {budget_gb} GB",
+    ),
+    (
+        "alert.auth_lockout",
+        "Suspended after {failures} failed authentications within {minutes} minutes",
+        "{minutes} 分钟内认证失败 {failures} 次，节点已暂停",
+    ),
+    (
+        "security.failed_logins",
+        "{failures} failed logins for {email} within {minutes} minutes",
+        "{email} 在 {minutes} 分钟内登录失败 {failures} 次",
+    ),
+    (
+        "security.balance_adjustment",
+        "Admin {admin_id} adjusted the balance of user {user_id} by {amount} coins",
+        "管理员 {admin_id} 将用户 {user_id} 的余额调整了 {amount} 金币",
+    ),
+    (
+        "security.node_deleted",
+        "Admin {admin_id} deleted node {node_id} ({node_name})",
+        "管理员 {admin_id} 删除了节点 {node_id}（{node_name}）",
+    ),
+    (
+        "security.heavy_traffic",
+        "User {email} used {traffic} within {hours} hours through {nodes} nodes",
+        "用户 {email} 在 {hours} 小时内通过 {nodes} 个节点使用了 {traffic} 流量",
+    ),
+    (
+        "security.subscription_concurrency",
+        "Subscription requests from {ip} exceeded {max} at once",
+        "来自 {ip} 的订阅请求同时超过 {max} 个",
+    ),
+    (
+        "security.subscription_frequency",
+        "Subscription requests from {ip} exceeded {max} within {seconds} seconds",
+        "来自 {ip} 的订阅请求在 {seconds} 秒内超过 {max} 次",
+    ),
+    ("subscription.remaining_traffic", "Remaining: {traffic}", "剩余流量: {traffic}"),
+    ("subscription.expires_at", "Expires: {date}", "到期时间: {date}"),
+];
+
+fn pick(entry: &(&'static str, &'static str, &'static str), lang: Lang) -> &'static str {
+    match lang {
+        Lang::En => entry.1,
+        Lang::Zh => entry.2,
+    }
+}
+
+fn find_error(message: &str) -> Option<(&'static (&'static str, &'static str, &'static str), &str)> {
+    ERROR_CATALOG.iter().find_map(|entry| {
+        if entry.1.ends_with(": ") {
+            message.strip_prefix(entry.1).map(|rest| (entry, rest))
+        } else if message == entry.1 {
+            Some((entry, ""))
+        } else {
+            None
+        }
+    })
+}
+
+/// Look up the catalog key for an English error message
+pub fn error_key(message: &str) -> Option<&'static str> {
+    find_error(message).map(|(entry, _)| entry.0)
+}
+
+/// Translate an English error message, falling back to the original text
+pub fn translate_error(message: &str, lang: Lang) -> String {
+    match find_error(message) {
+        Some((entry, rest)) => format!("{}{}", pick(entry, lang), rest),
+        None => message.to_string(),
+    }
+}
+
+/// Render a notification template, substituting `{name}` placeholders
+/// Unknown keys render as the key itself so missing entries are visible
+pub fn render(key: &str, lang: Lang, args: &[(&str, String)]) -> String {
+    let Some(entry) = TEMPLATE_CATALOG.iter().find(|entry| entry.0 == key) else {
+        return key.to_string();
+    };
+
+    args.iter().fold(pick(entry, lang).to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lang_parse() {
+        assert_eq!(Lang::parse("zh-CN"), Some(Lang::Zh));
+        assert_eq!(Lang::parse("en_US"), Some(Lang::En));
+        assert_eq!(Lang::parse("fr"), None);
+    }
+
+    #[test]
+    fn test_from_accept_language() {
+        assert_eq!(Lang::from_accept_language("zh-CN,zh;q=0.9,en;q=0.8"), Some(Lang::Zh));
+        assert_eq!(Lang::from_accept_language("fr;q=1.0, en;q=0.5, zh;q=0.4"), Some(Lang::En));
+        assert_eq!(Lang::from_accept_language("en;q=0.2, zh;q=0.7"), Some(Lang::Zh));
+        assert_eq!(Lang::from_accept_language("fr, de"), None);
+        assert_eq!(Lang::from_accept_language("zh;q=0"), None);
+    }
+
+    #[test]
+    fn test_translate_error() {
+        assert_eq!(translate_error("User not found", Lang::Zh), "用户不存在");
+        assert_eq!(translate_error("User not found", Lang::En), "User not found");
+        assert_eq!(
+            translate_error("Failed to generate config: bad yaml", Lang::Zh),
+            "生成配置失败：bad yaml"
+        );
+        assert_eq!(translate_error("Something unexpected", Lang::Zh), "Something unexpected");
+        assert_eq!(error_key("Admin access required"), Some("auth.admin_required"));
+    }
+
+    #[test]
+    fn test_catalog_has_no_empty_translations() {
+        for (key, en, zh) in ERROR_CATALOG.iter().chain(TEMPLATE_CATALOG) {
+            assert!(!en.is_empty() && !zh.is_empty(), "empty translation for {}", key);
+        }
+    }
+
+    #[test]
+    fn test_render_template() {
+        let text = render(
+            "alert.high_cpu",
+            Lang::Zh,
+            &[("threshold", "90".to_string()), ("minutes", "5".to_string())],
+        );
+        assert_eq!(text, "CPU 使用率连续 5 分钟超过 90%");
+        assert_eq!(render("missing.key", Lang::En, &[]), "missing.key");
+    }
+}
@@ -0,0 +1,638 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::borrow::Cow;
+
+use crate::crypto;
+use crate::db;
+use crate::models::{AdminJob, ClashAccessLog, CoinTransaction, Order, UserPackage, UserTrafficReportQuery};
+
+/// Job kind: CSV export of raw traffic logs
+pub const JOB_KIND_TRAFFIC_EXPORT: &str = "traffic_export";
+/// Job kind: CSV export of orders
+pub const JOB_KIND_ORDERS_EXPORT: &str = "orders_export";
+/// Job kind: recompute node traffic totals from traffic logs
+pub const JOB_KIND_NODE_TRAFFIC_RECALC: &str = "node_traffic_recalc";
+/// Job kind: JSON archive of one user's personal data (`params.user_id`)
+pub const JOB_KIND_USER_DATA_EXPORT: &str = "user_data_export";
+/// All valid `admin_jobs.kind` values
+pub const JOB_KINDS: [&str; 4] = [
+    JOB_KIND_TRAFFIC_EXPORT,
+    JOB_KIND_ORDERS_EXPORT,
+    JOB_KIND_NODE_TRAFFIC_RECALC,
+    JOB_KIND_USER_DATA_EXPORT,
+];
+
+/// Job statuses while a job is queued or being worked on
+pub const JOB_STATUS_PENDING: &str = "pending";
+pub const JOB_STATUS_RUNNING: &str = "running";
+/// Job status once its result (if any) has been stored
+pub const JOB_STATUS_COMPLETED: &str = "completed";
+
+/// How long a job result stays downloadable
+pub const JOB_RESULT_TTL_HOURS: i64 = 24;
+/// Lifetime of a signed download link
+pub const DOWNLOAD_LINK_TTL_SECS: i64 = 15 * 60;
+/// Running jobs older than this are considered abandoned by a dead worker
+const STALE_JOB_MINUTES: i64 = 30;
+/// How often an idle worker polls for pending jobs
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+/// Rows fetched per export query
+const EXPORT_BATCH_SIZE: i64 = 5000;
+
+/// Parameters shared by the export jobs
+/// Dates are inclusive days in the reporting time zone; the range defaults to the last 30 days.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExportParams {
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    pub user_id: Option<i64>,
+    pub status: Option<String>,
+}
+
+impl ExportParams {
+    pub fn date_range(&self, today: NaiveDate) -> Result<(NaiveDate, NaiveDate), String> {
+        UserTrafficReportQuery {
+            start_date: self.start_date,
+            end_date: self.end_date,
+        }
+        .resolve(today)
+    }
+}
+
+/// Validate a job request before it is queued, returning the API error message on failure
+pub fn validate_job(kind: &str, params: &serde_json::Value) -> Result<(), String> {
+    if !JOB_KINDS.contains(&kind) {
+        return Err(format!("Invalid job kind. Must be one of: {}", JOB_KINDS.join(", ")));
+    }
+
+    if kind == JOB_KIND_NODE_TRAFFIC_RECALC {
+        return Ok(());
+    }
+
+    let params = parse_export_params(params).map_err(|_| "Invalid job params".to_string())?;
+    if kind == JOB_KIND_USER_DATA_EXPORT {
+        return match params.user_id {
+            Some(_) => Ok(()),
+            None => Err("Invalid job params".to_string()),
+        };
+    }
+
+    params.date_range(Utc::now().date_naive())?;
+    Ok(())
+}
+
+fn parse_export_params(params: &serde_json::Value) -> Result<ExportParams> {
+    if params.is_null() {
+        return Ok(ExportParams::default());
+    }
+    Ok(serde_json::from_value(params.clone())?)
+}
+
+// ============================================================================
+// Signed Download Links
+// ============================================================================
+
+/// Which download route a signed link is valid for
+///
+/// The scope is part of the signature, so an admin link cannot be replayed on the
+/// user route or the other way round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadScope {
+    /// `/admin/jobs/:id/download`
+    Admin,
+    /// `/user/export/:id/download`
+    UserExport,
+}
+
+impl DownloadScope {
+    fn payload_prefix(self) -> &'static str {
+        match self {
+            DownloadScope::Admin => "admin-job",
+            DownloadScope::UserExport => "user-export",
+        }
+    }
+
+    fn route(self) -> &'static str {
+        match self {
+            DownloadScope::Admin => "admin/jobs",
+            DownloadScope::UserExport => "user/export",
+        }
+    }
+}
+
+fn download_payload(scope: DownloadScope, job_id: i64, expires: i64) -> String {
+    format!("{}:{}:{}", scope.payload_prefix(), job_id, expires)
+}
+
+/// Signed, time-limited download path for a job result
+pub fn signed_download_path(secret: &str, scope: DownloadScope, job_id: i64, now: DateTime<Utc>) -> String {
+    let expires = now.timestamp() + DOWNLOAD_LINK_TTL_SECS;
+    let signature = crypto::hmac_sha256_hex(secret, download_payload(scope, job_id, expires).as_bytes());
+    format!(
+        "{}/{}/{}/download?expires={}&signature={}",
+        crate::middleware::API_V1_PREFIX,
+        scope.route(),
+        job_id,
+        expires,
+        signature
+    )
+}
+
+/// Check a download link's signature and expiry
+pub fn verify_download(
+    secret: &str,
+    scope: DownloadScope,
+    job_id: i64,
+    expires: i64,
+    signature: &str,
+    now: DateTime<Utc>,
+) -> bool {
+    if expires < now.timestamp() {
+        return false;
+    }
+    let expected = crypto::hmac_sha256_hex(secret, download_payload(scope, job_id, expires).as_bytes());
+    crypto::secrets_match(&expected, &signature.to_ascii_lowercase())
+}
+
+// ============================================================================
+// CSV
+// ============================================================================
+
+/// Quote a CSV field if it contains a delimiter, quote or line break (RFC 4180)
+pub fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Append one CSV line
+fn push_csv_row(out: &mut String, fields: &[&str]) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(&csv_field(field));
+    }
+    out.push_str("\r\n");
+}
+
+/// Progress percentage, kept below 100 until the job is actually stored
+fn progress(done: i64, total: i64) -> i16 {
+    if total <= 0 {
+        return 0;
+    }
+    ((done.min(total) * 99) / total) as i16
+}
+
+// ============================================================================
+// Worker
+// ============================================================================
+
+/// A finished job's downloadable output
+pub struct JobOutput {
+    pub bytes: Vec<u8>,
+    pub filename: String,
+    pub content_type: &'static str,
+}
+
+/// Start the background job worker
+///
+/// Every API replica runs one; jobs are claimed with `SKIP LOCKED`, so each job runs once.
+/// Export date ranges are days in `time_zone` (`REPORT_TIMEZONE`).
+pub fn start_job_worker(db_pool: PgPool, time_zone: String) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            ticker.tick().await;
+
+            match db::cleanup_admin_jobs(&db_pool, STALE_JOB_MINUTES).await {
+                Ok((purged, failed)) if purged > 0 || failed > 0 => tracing::info!(
+                    "Purged {} expired job results, failed {} abandoned jobs",
+                    purged,
+                    failed
+                ),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Failed to clean up admin jobs: {}", e),
+            }
+
+            // Drain the queue before sleeping again
+            loop {
+                match db::claim_next_admin_job(&db_pool).await {
+                    Ok(Some(job)) => process_job(&db_pool, &time_zone, job).await,
+                    Ok(None) => break,
+                    Err(e) => {
+                        tracing::error!("Failed to claim admin job: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    })
+}
+
+async fn process_job(pool: &PgPool, time_zone: &str, job: AdminJob) {
+    tracing::info!("Running admin job {} ({})", job.id, job.kind);
+
+    let outcome = match run_job(pool, time_zone, &job).await {
+        Ok(output) => {
+            let expires_at = Utc::now() + Duration::hours(JOB_RESULT_TTL_HOURS);
+            let result = output
+                .as_ref()
+                .map(|o| (o.bytes.as_slice(), o.filename.as_str(), o.content_type));
+            db::complete_admin_job(pool, job.id, result, expires_at).await
+        }
+        Err(e) => {
+            tracing::error!("Admin job {} failed: {}", job.id, e);
+            db::fail_admin_job(pool, job.id, &e.to_string()).await
+        }
+    };
+
+    if let Err(e) = outcome {
+        tracing::error!("Failed to record result of admin job {}: {}", job.id, e);
+    }
+}
+
+async fn run_job(pool: &PgPool, time_zone: &str, job: &AdminJob) -> Result<Option<JobOutput>> {
+    match job.kind.as_str() {
+        JOB_KIND_TRAFFIC_EXPORT => export_traffic(pool, time_zone, job).await.map(Some),
+        JOB_KIND_ORDERS_EXPORT => export_orders(pool, time_zone, job).await.map(Some),
+        JOB_KIND_NODE_TRAFFIC_RECALC => recalculate_node_traffic(pool, job).await.map(|_| None),
+        JOB_KIND_USER_DATA_EXPORT => export_user_data(pool, time_zone, job).await.map(Some),
+        other => Err(anyhow!("Unknown job kind: {}", other)),
+    }
+}
+
+async fn export_traffic(pool: &PgPool, time_zone: &str, job: &AdminJob) -> Result<JobOutput> {
+    let params = parse_export_params(&job.params)?;
+    let today = db::get_local_date(pool, Utc::now(), time_zone).await?;
+    let (start, end) = params.date_range(today).map_err(|e| anyhow!(e))?;
+
+    let total: i64 = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*) FROM traffic_logs
+        WHERE recorded_at >= ($1::date::timestamp AT TIME ZONE $4)
+          AND recorded_at < (($2::date + 1)::timestamp AT TIME ZONE $4)
+          AND ($3::BIGINT IS NULL OR user_id = $3)
+        "#,
+    )
+    .bind(start)
+    .bind(end)
+    .bind(params.user_id)
+    .bind(time_zone)
+    .fetch_one(pool)
+    .await?;
+
+    let mut csv = String::new();
+    push_csv_row(
+        &mut csv,
+        &["recorded_at", "user_id", "user_email", "node_id", "node_name", "upload", "download"],
+    );
+
+    let mut last_id = 0i64;
+    let mut done = 0i64;
+    loop {
+        let rows = sqlx::query_as::<_, (i64, DateTime<Utc>, i64, String, i64, String, i64, i64)>(
+            r#"
+            SELECT t.id, t.recorded_at, t.user_id, u.email, t.node_id, n.name, t.upload, t.download
+            FROM traffic_logs t
+            JOIN users u ON u.id = t.user_id
+            JOIN nodes n ON n.id = t.node_id
+            WHERE t.id > $1
+              AND t.recorded_at >= ($2::date::timestamp AT TIME ZONE $6)
+              AND t.recorded_at < (($3::date + 1)::timestamp AT TIME ZONE $6)
+              AND ($4::BIGINT IS NULL OR t.user_id = $4)
+            ORDER BY t.id
+            LIMIT $5
+            "#,
+        )
+        .bind(last_id)
+        .bind(start)
+        .bind(end)
+        .bind(params.user_id)
+        .bind(EXPORT_BATCH_SIZE)
+        .bind(time_zone)
+        .fetch_all(pool)
+        .await?;
+
+        let Some(last) = rows.last() else {
+            break;
+        };
+        last_id = last.0;
+        done += rows.len() as i64;
+
+        for (_, recorded_at, user_id, email, node_id, node_name, upload, download) in &rows {
+            push_csv_row(
+                &mut csv,
+                &[
+                    &recorded_at.to_rfc3339(),
+                    &user_id.to_string(),
+                    email,
+                    &node_id.to_string(),
+                    node_name,
+                    &upload.to_string(),
+                    &download.to_string(),
+                ],
+            );
+        }
+
+        db::update_admin_job_progress(pool, job.id, progress(done, total)).await?;
+    }
+
+    Ok(JobOutput {
+        bytes: csv.into_bytes(),
+        filename: format!("traffic_{}_{}.csv", start, end),
+        content_type: "text/csv; charset=utf-8",
+    })
+}
+
+async fn export_orders(pool: &PgPool, time_zone: &str, job: &AdminJob) -> Result<JobOutput> {
+    let params = parse_export_params(&job.params)?;
+    let today = db::get_local_date(pool, Utc::now(), time_zone).await?;
+    let (start, end) = params.date_range(today).map_err(|e| anyhow!(e))?;
+
+    let total: i64 = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*) FROM orders
+        WHERE created_at >= ($1::date::timestamp AT TIME ZONE $5)
+          AND created_at < (($2::date + 1)::timestamp AT TIME ZONE $5)
+          AND ($3::BIGINT IS NULL OR user_id = $3)
+          AND ($4::TEXT IS NULL OR status = $4)
+        "#,
+    )
+    .bind(start)
+    .bind(end)
+    .bind(params.user_id)
+    .bind(params.status.as_deref())
+    .bind(time_zone)
+    .fetch_one(pool)
+    .await?;
+
+    let mut csv = String::new();
+    push_csv_row(
+        &mut csv,
+        &[
+            "order_no",
+            "user_id",
+            "user_email",
+            "package",
+            "amount",
+            "coin_amount",
+            "external_amount",
+            "status",
+            "created_at",
+            "completed_at",
+        ],
+    );
+
+    let mut last_id = 0i64;
+    let mut done = 0i64;
+    loop {
+        let rows = sqlx::query_as::<
+            _,
+            (i64, String, i64, String, String, i64, i64, i64, String, DateTime<Utc>, Option<DateTime<Utc>>),
+        >(
+            r#"
+            SELECT o.id, o.order_no, o.user_id, u.email, p.name, o.amount, o.coin_amount,
+                   o.external_amount, o.status, o.created_at, o.completed_at
+            FROM orders o
+            JOIN users u ON u.id = o.user_id
+            JOIN packages p ON p.id = o.package_id
+            WHERE o.id > $1
+              AND o.created_at >= ($2::date::timestamp AT TIME ZONE $7)
+              AND o.created_at < (($3::date + 1)::timestamp AT TIME ZONE $7)
+              AND ($4::BIGINT IS NULL OR o.user_id = $4)
+              AND ($5::TEXT IS NULL OR o.status = $5)
+            ORDER BY o.id
+            LIMIT $6
+            "#,
+        )
+        .bind(last_id)
+        .bind(start)
+        .bind(end)
+        .bind(params.user_id)
+        .bind(params.status.as_deref())
+        .bind(EXPORT_BATCH_SIZE)
+        .bind(time_zone)
+        .fetch_all(pool)
+        .await?;
+
+        let Some(last) = rows.last() else {
+            break;
+        };
+        last_id = last.0;
+        done += rows.len() as i64;
+
+        for (_, order_no, user_id, email, package, amount, coins, external, status, created_at, completed_at) in &rows {
+            push_csv_row(
+                &mut csv,
+                &[
+                    order_no,
+                    &user_id.to_string(),
+                    email,
+                    package,
+                    &amount.to_string(),
+                    &coins.to_string(),
+                    &external.to_string(),
+                    status,
+                    &created_at.to_rfc3339(),
+                    &completed_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+                ],
+            );
+        }
+
+        db::update_admin_job_progress(pool, job.id, progress(done, total)).await?;
+    }
+
+    Ok(JobOutput {
+        bytes: csv.into_bytes(),
+        filename: format!("orders_{}_{}.csv", start, end),
+        content_type: "text/csv; charset=utf-8",
+    })
+}
+
+/// Recompute each node's lifetime upload/download totals from its traffic logs
+async fn recalculate_node_traffic(pool: &PgPool, job: &AdminJob) -> Result<()> {
+    let node_ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM nodes ORDER BY id")
+        .fetch_all(pool)
+        .await?;
+    let total = node_ids.len() as i64;
+
+    for (i, node_id) in node_ids.into_iter().enumerate() {
+        sqlx::query(
+            r#"
+            UPDATE nodes
+            SET total_upload = t.upload, total_download = t.download, updated_at = NOW()
+            FROM (
+                SELECT COALESCE(SUM(upload), 0)::BIGINT AS upload,
+                       COALESCE(SUM(download), 0)::BIGINT AS download
+                FROM traffic_logs
+                WHERE node_id = $1
+            ) t
+            WHERE nodes.id = $1
+            "#,
+        )
+        .bind(node_id)
+        .execute(pool)
+        .await?;
+
+        db::update_admin_job_progress(pool, job.id, progress(i as i64 + 1, total)).await?;
+    }
+
+    Ok(())
+}
+
+/// Build a JSON archive of everything stored about one user
+///
+/// Covers the profile, subscription, packages, orders, coin transactions, subscription
+/// access history and daily per-node traffic. Secrets (password hash, token version)
+/// are left out by the models' serializers.
+async fn export_user_data(pool: &PgPool, time_zone: &str, job: &AdminJob) -> Result<JobOutput> {
+    let params = parse_export_params(&job.params)?;
+    let user_id = params.user_id.ok_or_else(|| anyhow!("user_id is required"))?;
+    let user = db::get_user_by_id(pool, user_id)
+        .await?
+        .ok_or_else(|| anyhow!("User {} not found", user_id))?;
+    const SECTIONS: i64 = 6;
+
+    let subscription = db::get_subscription_by_user_id(pool, user_id).await?;
+    let preferences = db::get_subscription_preferences(pool, user_id).await?;
+    db::update_admin_job_progress(pool, job.id, progress(1, SECTIONS)).await?;
+
+    let packages = sqlx::query_as::<_, UserPackage>(
+        "SELECT * FROM user_packages WHERE user_id = $1 ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    db::update_admin_job_progress(pool, job.id, progress(2, SECTIONS)).await?;
+
+    let orders = sqlx::query_as::<_, Order>("SELECT * FROM orders WHERE user_id = $1 ORDER BY created_at")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+    db::update_admin_job_progress(pool, job.id, progress(3, SECTIONS)).await?;
+
+    let coin_transactions = sqlx::query_as::<_, CoinTransaction>(
+        "SELECT * FROM coin_transactions WHERE user_id = $1 ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    db::update_admin_job_progress(pool, job.id, progress(4, SECTIONS)).await?;
+
+    let access_logs = sqlx::query_as::<_, ClashAccessLog>(
+        "SELECT * FROM clash_access_logs WHERE user_id = $1 ORDER BY access_timestamp",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    db::update_admin_job_progress(pool, job.id, progress(5, SECTIONS)).await?;
+
+    let today = db::get_local_date(pool, Utc::now(), time_zone).await?;
+    let first_day = db::get_local_date(pool, user.created_at, time_zone).await?;
+    let traffic = db::get_user_node_daily_traffic(pool, user_id, first_day, today, time_zone).await?;
+
+    let archive = serde_json::json!({
+        "exported_at": Utc::now(),
+        "profile": user,
+        "subscription": subscription,
+        "subscription_preferences": preferences,
+        "packages": packages,
+        "orders": orders,
+        "coin_transactions": coin_transactions,
+        "subscription_access_logs": access_logs,
+        "daily_traffic": traffic,
+        "time_zone": time_zone,
+    });
+
+    Ok(JobOutput {
+        bytes: serde_json::to_vec_pretty(&archive)?,
+        filename: format!("user_{}_data_{}.json", user_id, today),
+        content_type: "application/json",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_field_quoting() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("line\nbreak"), "\"line\nbreak\"");
+
+        let mut out = String::new();
+        push_csv_row(&mut out, &["Node, HK", "1"]);
+        assert_eq!(out, "\"Node, HK\",1\r\n");
+    }
+
+    #[test]
+    fn test_signed_download_links() {
+        let now = Utc::now();
+        let path = signed_download_path("secret", DownloadScope::Admin, 42, now);
+        let query = path.split_once('?').unwrap().1;
+        let params: std::collections::HashMap<_, _> = query
+            .split('&')
+            .filter_map(|kv| kv.split_once('='))
+            .collect();
+        let expires: i64 = params["expires"].parse().unwrap();
+        let signature = params["signature"];
+
+        assert!(path.starts_with("/api/v1/admin/jobs/42/download?"));
+        let admin = DownloadScope::Admin;
+        assert!(verify_download("secret", admin, 42, expires, signature, now));
+        assert!(!verify_download("secret", admin, 43, expires, signature, now));
+        assert!(!verify_download("other", admin, 42, expires, signature, now));
+        assert!(!verify_download("secret", admin, 42, expires + 1, signature, now));
+        assert!(!verify_download("secret", DownloadScope::UserExport, 42, expires, signature, now));
+
+        let later = now + Duration::seconds(DOWNLOAD_LINK_TTL_SECS + 1);
+        assert!(!verify_download("secret", admin, 42, expires, signature, later));
+
+        let user_path = signed_download_path("secret", DownloadScope::UserExport, 42, now);
+        assert!(user_path.starts_with("/api/v1/user/export/42/download?"));
+    }
+
+    #[test]
+    fn test_validate_job() {
+        assert!(validate_job(JOB_KIND_NODE_TRAFFIC_RECALC, &serde_json::Value::Null).is_ok());
+        assert!(validate_job(JOB_KIND_TRAFFIC_EXPORT, &serde_json::json!({})).is_ok());
+        assert!(validate_job(
+            JOB_KIND_ORDERS_EXPORT,
+            &serde_json::json!({ "start_date": "2026-01-01", "end_date": "2026-01-31", "status": "completed" })
+        )
+        .is_ok());
+
+        assert!(validate_job(JOB_KIND_USER_DATA_EXPORT, &serde_json::json!({ "user_id": 7 })).is_ok());
+        assert!(validate_job(JOB_KIND_USER_DATA_EXPORT, &serde_json::json!({})).is_err());
+
+        assert!(validate_job("drop_tables", &serde_json::json!({})).is_err());
+        assert_eq!(
+            validate_job(JOB_KIND_TRAFFIC_EXPORT, &serde_json::json!({ "start": "2026-01-01" })).unwrap_err(),
+            "Invalid job params"
+        );
+        assert_eq!(
+            validate_job(
+                JOB_KIND_TRAFFIC_EXPORT,
+                &serde_json::json!({ "start_date": "2026-02-01", "end_date": "2026-01-01" })
+            )
+            .unwrap_err(),
+            "start_date must not be after end_date"
+        );
+    }
+
+    #[test]
+    fn test_progress() {
+        assert_eq!(progress(0, 0), 0);
+        assert_eq!(progress(50, 100), 49);
+        assert_eq!(progress(100, 100), 99);
+        assert_eq!(progress(150, 100), 99);
+    }
+}
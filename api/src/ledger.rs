@@ -0,0 +1,251 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::amounts::Coins;
+use crate::cache::{spawn_periodic_job, RedisCache};
+use crate::config::LedgerCheckConfig;
+use crate::db;
+use crate::models::{CoinBalanceDrift, OrderCoinMismatch};
+
+/// Users whose balance changed this recently are not fixed (seconds)
+///
+/// The admin balance endpoint records its transaction just after updating the
+/// balance, so a drift this fresh may still resolve itself.
+pub const SETTLE_SECONDS: i64 = 60;
+
+/// Outcome of checking coin balances against their transactions
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LedgerReport {
+    pub discrepancies: Vec<CoinBalanceDrift>,
+    /// Users whose drift was corrected with an adjustment transaction
+    pub fixed: Vec<i64>,
+}
+
+impl LedgerReport {
+    /// Discrepancies left after fixing
+    pub fn unresolved(&self) -> impl Iterator<Item = &CoinBalanceDrift> {
+        self.discrepancies.iter().filter(|d| !self.fixed.contains(&d.user_id))
+    }
+
+    /// Net coins by which balances exceed the ledger
+    pub fn total_drift(&self) -> Option<Coins> {
+        self.discrepancies
+            .iter()
+            .try_fold(Coins::ZERO, |total, d| total.checked_add(d.drift))
+    }
+}
+
+/// Compare every user's balance with the sum of their coin transactions
+///
+/// With `fix`, each drift is corrected by an `adjustment` transaction: balances are
+/// never changed, so the ledger keeps a record of the correction.
+pub async fn reconcile(pool: &PgPool, tenant_id: Option<i64>, fix: bool) -> Result<LedgerReport> {
+    let discrepancies = db::list_coin_balance_drift(pool, tenant_id).await?;
+    let mut fixed = Vec::new();
+
+    if fix {
+        for drift in &discrepancies {
+            match db::fix_coin_balance_drift(pool, drift.user_id, SETTLE_SECONDS).await {
+                Ok(Some(_)) => fixed.push(drift.user_id),
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Failed to fix coin ledger of user {}: {}", drift.user_id, e),
+            }
+        }
+    }
+
+    Ok(LedgerReport { discrepancies, fixed })
+}
+
+/// How a completed order's purchase transactions disagree with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderMismatchKind {
+    /// The order charged coins but has no purchase transaction
+    MissingTransaction,
+    /// Its purchase transactions charge a different amount
+    AmountDrift,
+}
+
+impl OrderMismatchKind {
+    pub fn of(mismatch: &OrderCoinMismatch) -> Self {
+        if mismatch.transaction_count == 0 {
+            OrderMismatchKind::MissingTransaction
+        } else {
+            OrderMismatchKind::AmountDrift
+        }
+    }
+}
+
+/// A mismatched order with its kind
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OrderMismatch {
+    #[serde(flatten)]
+    pub order: OrderCoinMismatch,
+    pub kind: OrderMismatchKind,
+}
+
+/// Outcome of reconciling completed orders against their purchase transactions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OrderReconciliation {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Completed orders in the period
+    pub orders_checked: i64,
+    pub mismatches: Vec<OrderMismatch>,
+    /// Orders whose missing transaction was regenerated
+    pub regenerated: Vec<i64>,
+}
+
+impl OrderReconciliation {
+    /// Net coins by which the orders exceed what their transactions charged
+    pub fn total_drift(&self) -> Option<Coins> {
+        self.mismatches.iter().try_fold(Coins::ZERO, |total, m| {
+            total.checked_add(m.order.expected_coins.checked_sub(m.order.charged_coins)?)
+        })
+    }
+}
+
+/// Cross-check completed orders in `[from, to)` against their purchase transactions
+///
+/// With `regenerate`, orders missing their transaction get one recorded; orders with
+/// an amount drift are only reported, as a wrong amount needs a look at the balance.
+pub async fn reconcile_orders(
+    pool: &PgPool,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    tenant_id: Option<i64>,
+    regenerate: bool,
+) -> Result<OrderReconciliation> {
+    let (orders_checked, mismatches) = db::list_order_coin_mismatches(pool, from, to, tenant_id).await?;
+    let mismatches: Vec<OrderMismatch> = mismatches
+        .into_iter()
+        .map(|order| OrderMismatch { kind: OrderMismatchKind::of(&order), order })
+        .collect();
+
+    let mut regenerated = Vec::new();
+    if regenerate {
+        for mismatch in mismatches.iter().filter(|m| m.kind == OrderMismatchKind::MissingTransaction) {
+            match db::regenerate_order_purchase_transaction(pool, mismatch.order.order_id).await {
+                Ok(Some(_)) => regenerated.push(mismatch.order.order_id),
+                Ok(None) => {}
+                Err(e) => tracing::warn!(
+                    "Failed to regenerate purchase transaction of order {}: {}",
+                    mismatch.order.order_no,
+                    e
+                ),
+            }
+        }
+    }
+
+    Ok(OrderReconciliation { from, to, orders_checked, mismatches, regenerated })
+}
+
+/// Background task reporting (and optionally fixing) coin ledger drift
+pub fn start_ledger_check_task(
+    db_pool: PgPool,
+    cache: RedisCache,
+    settings: LedgerCheckConfig,
+) -> Option<tokio::task::JoinHandle<()>> {
+    if !settings.enabled() {
+        return None;
+    }
+
+    let period = std::time::Duration::from_secs(settings.check_interval_secs);
+    Some(spawn_periodic_job(cache, "ledger_check", period, move || {
+        let db_pool = db_pool.clone();
+        let fix = settings.auto_fix;
+        async move {
+            let report = match reconcile(&db_pool, None, fix).await {
+                Ok(report) => report,
+                Err(e) => {
+                    tracing::error!("Coin ledger check failed: {}", e);
+                    return;
+                }
+            };
+
+            for drift in report.unresolved() {
+                tracing::warn!(
+                    "Coin balance of user {} is {} but their transactions sum to {} (drift {})",
+                    drift.user_id,
+                    drift.coin_balance,
+                    drift.ledger_balance,
+                    drift.drift
+                );
+            }
+            if !report.discrepancies.is_empty() {
+                tracing::warn!(
+                    "Coin ledger check found {} discrepancies, fixed {}",
+                    report.discrepancies.len(),
+                    report.fixed.len()
+                );
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drift(user_id: i64, coin_balance: i64, ledger_balance: i64) -> CoinBalanceDrift {
+        CoinBalanceDrift {
+            user_id,
+            tenant_id: 1,
+            email: format!("user{}@example.com", user_id),
+            coin_balance: Coins::new(coin_balance),
+            ledger_balance: Coins::new(ledger_balance),
+            drift: Coins::new(coin_balance - ledger_balance),
+            last_transaction_at: None,
+        }
+    }
+
+    fn order(order_id: i64, expected: i64, charged: i64, transaction_count: i64) -> OrderCoinMismatch {
+        OrderCoinMismatch {
+            order_id,
+            order_no: format!("ORDER-{}", order_id),
+            user_id: 1,
+            tenant_id: 1,
+            email: "user1@example.com".to_string(),
+            completed_at: None,
+            expected_coins: Coins::new(expected),
+            charged_coins: Coins::new(charged),
+            transaction_count,
+        }
+    }
+
+    #[test]
+    fn test_order_reconciliation() {
+        assert_eq!(OrderMismatchKind::of(&order(1, 100, 0, 0)), OrderMismatchKind::MissingTransaction);
+        // A transaction of the wrong amount, or a duplicated one
+        assert_eq!(OrderMismatchKind::of(&order(2, 100, 80, 1)), OrderMismatchKind::AmountDrift);
+        assert_eq!(OrderMismatchKind::of(&order(3, 100, 200, 2)), OrderMismatchKind::AmountDrift);
+
+        let mismatches = [order(1, 100, 0, 0), order(2, 100, 80, 1), order(3, 100, 200, 2)]
+            .into_iter()
+            .map(|order| OrderMismatch { kind: OrderMismatchKind::of(&order), order })
+            .collect();
+        let reconciliation = OrderReconciliation {
+            from: Utc::now(),
+            to: Utc::now(),
+            orders_checked: 10,
+            mismatches,
+            regenerated: vec![],
+        };
+        assert_eq!(reconciliation.total_drift(), Some(Coins::new(20)));
+    }
+
+    #[test]
+    fn test_report() {
+        let report = LedgerReport {
+            discrepancies: vec![drift(1, 100, 40), drift(2, 0, 30), drift(3, 10, 0)],
+            fixed: vec![2],
+        };
+
+        let unresolved: Vec<i64> = report.unresolved().map(|d| d.user_id).collect();
+        assert_eq!(unresolved, vec![1, 3]);
+        assert_eq!(report.total_drift(), Some(Coins::new(40)));
+        assert_eq!(LedgerReport::default().total_drift(), Some(Coins::ZERO));
+    }
+}
@@ -1,11 +1,58 @@
 // Library exports for the VPN Subscription Platform API
 
+pub mod access_log;
+pub mod adjustments;
+pub mod agent_update;
+pub mod amounts;
+pub mod api_keys;
 pub mod cache;
 pub mod clash;
+pub mod clash_snapshots;
+pub mod client_ip;
+pub mod compression;
 pub mod config;
+pub mod crypto;
 pub mod db;
+pub mod db_error;
+pub mod features;
+pub mod geoip;
 pub mod handlers;
+pub mod hooks;
+pub mod i18n;
+pub mod jobs;
+pub mod ledger;
+pub mod locking;
+pub mod maintenance;
 pub mod middleware;
 pub mod models;
+pub mod monitoring;
+pub mod node_auth;
+pub mod node_config;
+pub mod node_probe;
+pub mod oauth;
+pub mod order_no;
+pub mod orders;
+pub mod outbox;
+pub mod password_policy;
+pub mod payments;
+pub mod purchases;
+pub mod registration;
+pub mod rotation;
+pub mod security;
+pub mod seed;
+pub mod sql;
+pub mod stats;
+pub mod status;
+pub mod status_page;
+pub mod store;
+pub mod subscription_key;
+pub mod subscription_limit;
+pub mod subscription_warmup;
+pub mod suggestions;
+pub mod tenancy;
+pub mod tls;
+pub mod topup;
 pub mod traffic;
+pub mod user_import;
+pub mod user_notes;
 pub mod utils;
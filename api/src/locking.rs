@@ -0,0 +1,132 @@
+use anyhow::Result;
+use rand::Rng;
+use sqlx::PgConnection;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::models::User;
+
+/// SQLSTATE of a transaction that lost a serialization conflict
+const SERIALIZATION_FAILURE: &str = "40001";
+/// SQLSTATE of a transaction picked as the victim of a deadlock
+const DEADLOCK_DETECTED: &str = "40P01";
+/// Attempts, including the first, before a contended transaction gives up
+pub const MAX_ATTEMPTS: u32 = 4;
+/// Upper bound of the first retry delay; doubled for every further retry
+const BASE_DELAY_MS: u64 = 20;
+
+/// The order `lock_users` takes row locks in: ascending id, each id once
+pub fn lock_order(user_ids: &[i64]) -> Vec<i64> {
+    let mut ids = user_ids.to_vec();
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// Lock the rows of several users with `SELECT ... FOR UPDATE`, lowest id first
+///
+/// Purchase, payment and rebate transactions lock rows in one order: the order being
+/// settled, then users by ascending id, then packages. Transactions that hold more than
+/// one user row (a buyer and their referrer) must lock them through here; with every
+/// transaction locking in the same order, two of them can wait on each other but never
+/// deadlock. Returns the users in id order; ids without a user are skipped.
+pub async fn lock_users(conn: &mut PgConnection, user_ids: &[i64]) -> Result<Vec<User>> {
+    let mut users = Vec::with_capacity(user_ids.len());
+    for id in lock_order(user_ids) {
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut *conn)
+            .await?;
+        users.extend(user);
+    }
+    Ok(users)
+}
+
+/// Whether the error is Postgres aborting a transaction over a deadlock or a
+/// serialization conflict, which succeeds when the transaction is run again
+pub fn is_contended(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| match cause.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::Database(db_error)) => matches!(
+            db_error.code().as_deref(),
+            Some(SERIALIZATION_FAILURE | DEADLOCK_DETECTED)
+        ),
+        _ => false,
+    })
+}
+
+/// Wait before retry `attempt` (1-based): a random delay in the upper half of
+/// 20, 40, 80, ... ms, so transactions that collided don't collide again in lockstep
+pub fn backoff(attempt: u32) -> Duration {
+    let ceiling = BASE_DELAY_MS << attempt.saturating_sub(1).min(6);
+    Duration::from_millis(rand::thread_rng().gen_range(ceiling / 2..=ceiling))
+}
+
+/// Run a transaction, running it again with backoff if Postgres aborts it as contended
+///
+/// `transaction` must begin and commit its own transaction, so every attempt starts
+/// from scratch. Other errors, and contention on the last attempt, are returned as is.
+pub async fn with_retry<T, F, Fut>(operation: &str, mut transaction: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match transaction().await {
+            Err(e) if attempt < MAX_ATTEMPTS && is_contended(&e) => {
+                let delay = backoff(attempt);
+                tracing::warn!(
+                    "{} aborted by lock contention (attempt {}), retrying in {:?}: {}",
+                    operation,
+                    attempt,
+                    delay,
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_lock_order() {
+        assert_eq!(lock_order(&[42, 7]), vec![7, 42]);
+        assert_eq!(lock_order(&[7, 42]), vec![7, 42]);
+        assert_eq!(lock_order(&[9, 9]), vec![9]);
+        assert!(lock_order(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_backoff_bounds() {
+        for _ in 0..100 {
+            let first = backoff(1).as_millis() as u64;
+            assert!((BASE_DELAY_MS / 2..=BASE_DELAY_MS).contains(&first));
+
+            let third = backoff(3).as_millis() as u64;
+            assert!((BASE_DELAY_MS * 2..=BASE_DELAY_MS * 4).contains(&third));
+        }
+        // The delay stops growing instead of overflowing
+        assert!(backoff(u32::MAX) <= Duration::from_millis(BASE_DELAY_MS << 6));
+    }
+
+    #[tokio::test]
+    async fn test_with_retry_returns_other_errors() {
+        let calls = AtomicU32::new(0);
+        let result: Result<()> = with_retry("test", || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            anyhow::bail!("Insufficient balance")
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert!(!is_contended(&result.unwrap_err()));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,14 +1,60 @@
 use anyhow::Result;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod access_log;
+mod adjustments;
+mod agent_update;
+mod amounts;
+mod api_keys;
 mod config;
+mod crypto;
 mod models;
 mod db;
+mod db_error;
+mod features;
+mod geoip;
 mod cache;
 mod clash;
+mod clash_snapshots;
+mod client_ip;
+mod compression;
 mod handlers;
+mod hooks;
+mod i18n;
+mod jobs;
+mod ledger;
+mod locking;
+mod maintenance;
 mod middleware;
+mod monitoring;
+mod node_auth;
+mod node_config;
+mod node_probe;
+mod oauth;
+mod order_no;
+mod orders;
+mod outbox;
+mod password_policy;
+mod payments;
+mod purchases;
+mod registration;
+mod rotation;
+mod security;
+mod sql;
+mod stats;
+mod status;
+mod status_page;
+mod store;
+mod subscription_key;
+mod subscription_limit;
+mod subscription_warmup;
+mod suggestions;
+mod tenancy;
+mod tls;
+mod topup;
 mod traffic;
+mod user_import;
+mod user_notes;
 mod utils;
 
 #[tokio::main]
@@ -31,24 +77,102 @@ async fn main() -> Result<()> {
     let config = config::Config::from_env()?;
     tracing::info!("Configuration loaded");
 
+    // Extra common passwords for the password policy (in-memory list)
+    password_policy::load_blacklist_file(&config.password_policy)?;
+
+    // Enable at-rest encryption for sensitive node fields
+    if let Some(key) = &config.field_encryption_key {
+        crypto::install_field_cipher(crypto::FieldCipher::from_base64_key(key)?)?;
+        tracing::info!("Field encryption enabled");
+    } else {
+        tracing::warn!("FIELD_ENCRYPTION_KEY not set, node secrets are stored in plaintext");
+    }
+
+    // Sign subscription configs so clients can detect tampering
+    if let Some(key) = &config.config_signing_key {
+        let signer = crypto::ConfigSigner::new(&config.config_signing_algorithm, key)?;
+        tracing::info!("Subscription config signing enabled ({})", signer.algorithm());
+        crypto::install_config_signer(signer)?;
+    }
+
     // Initialize database connection pool
-    let db_pool = db::create_pool(&config.database_url).await?;
+    let db_pool = db::create_pool(&config.database_url, &config.db_pool).await?;
     tracing::info!("Database connection pool created");
 
+    // Fail fast on a reporting time zone Postgres doesn't know
+    stats::validate_time_zone(&db_pool, &config.report_timezone).await?;
+
     // Initialize Redis connection
     let redis_client = redis::Client::open(config.redis_url.as_str())?;
     let redis_conn = redis_client.get_connection_manager().await?;
     tracing::info!("Redis connection established");
 
+    // Start background jobs (each runs on a single replica via Redis leases)
+    let redis_cache = cache::RedisCache::with_ttls(redis_conn.clone(), config.cache_ttl);
+    monitoring::start_metrics_retention_task(
+        db_pool.clone(),
+        redis_cache.clone(),
+        config.node_metrics_retention_days,
+    );
+    payments::start_order_expiry_task(db_pool.clone(), redis_cache.clone());
+    maintenance::start_maintenance_task(db_pool.clone(), redis_cache.clone());
+    purchases::start_scheduled_package_task(db_pool.clone(), redis_cache.clone());
+    rotation::start_credential_rotation_task(
+        db_pool.clone(),
+        redis_cache.clone(),
+        config.credential_rotation.clone(),
+    );
+    suggestions::start_upgrade_suggestion_task(
+        db_pool.clone(),
+        redis_cache.clone(),
+        config.upgrade_suggestions.clone(),
+    );
+    ledger::start_ledger_check_task(
+        db_pool.clone(),
+        redis_cache.clone(),
+        config.ledger_check.clone(),
+    );
+    access_log::start_access_log_flush_task(db_pool.clone(), redis_cache.clone());
+    stats::start_heavy_traffic_check_task(db_pool.clone(), redis_cache.clone(), config.clone());
+    traffic::start_traffic_processor(
+        redis_conn.clone(),
+        db_pool.clone(),
+        monitoring::AlertThresholds::from_config(&config),
+        config.traffic_guard.clone(),
+    );
+    // Admin job worker runs on every replica; jobs are claimed with SKIP LOCKED
+    jobs::start_job_worker(db_pool.clone(), config.report_timezone.clone());
+    // Outbox dispatcher runs on every replica; events are claimed with SKIP LOCKED
+    outbox::start_outbox_dispatcher(db_pool.clone(), redis_cache.clone());
+    // Disposable email list reload runs on every replica (in-memory list)
+    registration::start_disposable_domain_refresh(&config.registration);
+    tracing::info!("Background jobs started");
+
     // Build application router
-    let app = handlers::create_router(db_pool, redis_conn, config.clone());
+    // Plugin hooks: register `hooks::PurchaseHook` and `hooks::ConfigHook` implementations here
+    let hooks = hooks::Hooks {
+        purchase: vec![],
+        config: vec![],
+    };
+
+    let app = handlers::create_router(db_pool, redis_conn, config.clone(), hooks);
 
     // Start server
-    let addr = format!("{}:{}", config.host, config.port);
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    tracing::info!("Server listening on {}", addr);
+    if config.tls.enabled() {
+        if let Some(port) = config.tls.http_redirect_port {
+            tls::spawn_https_redirect(tls::listen_addr(&config.host, port)?, config.port).await?;
+        }
+        let addr = tls::listen_addr(&config.host, config.port)?;
+        tracing::info!("Server listening on {} (HTTPS)", addr);
+
+        tls::serve(app, addr, &config.tls).await?;
+    } else {
+        let addr = format!("{}:{}", config.host, config.port);
+        let listener = tokio::net::TcpListener::bind(&addr).await?;
+        tracing::info!("Server listening on {}", addr);
 
-    axum::serve(listener, app).await?;
+        axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,243 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::cache::{spawn_periodic_job, RedisCache};
+use crate::db;
+use crate::models::{Node, NodeMaintenance};
+use crate::outbox;
+
+/// Longest maintenance window
+pub const MAX_WINDOW_DAYS: i64 = 7;
+/// How far ahead a window may start
+pub const MAX_SCHEDULE_DAYS: i64 = 90;
+/// Width of `node_maintenance.reason`
+pub const MAX_REASON_LEN: usize = 500;
+/// A user predominantly uses the node that carried most of their traffic over this many days
+pub const USAGE_WINDOW_DAYS: i64 = 30;
+
+crate::status::status_enum! {
+    /// State stored in `node_maintenance.status`
+    MaintenanceStatus {
+        /// Waiting for `starts_at`
+        Scheduled => "scheduled",
+        /// Under way: the node is in maintenance and left out of configs
+        Active => "active",
+        Completed => "completed",
+        /// Called off by an admin, before or during the window
+        Cancelled => "cancelled",
+    }
+}
+
+impl MaintenanceStatus {
+    /// Whether the window can still be changed or cancelled
+    pub fn is_open(self) -> bool {
+        matches!(self, MaintenanceStatus::Scheduled | MaintenanceStatus::Active)
+    }
+}
+
+/// A maintenance window was scheduled, started or closed, published on `nodes:maintenance`
+///
+/// Notification consumers tell `user_ids` (the users who predominantly use the node)
+/// about the window.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceEvent {
+    pub maintenance_id: i64,
+    pub node_id: i64,
+    pub node_name: String,
+    pub tenant_id: i64,
+    pub status: MaintenanceStatus,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub reason: Option<String>,
+    pub user_ids: Vec<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl MaintenanceEvent {
+    pub fn new(window: &NodeMaintenance, node: &Node, user_ids: Vec<i64>) -> Self {
+        Self {
+            maintenance_id: window.id,
+            node_id: node.id,
+            node_name: node.name.clone(),
+            tenant_id: node.tenant_id,
+            status: window.status,
+            starts_at: window.starts_at,
+            ends_at: window.ends_at,
+            reason: window.reason.clone(),
+            user_ids,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// A scheduled or changed window and the outbox events written with it
+#[derive(Debug, Clone)]
+pub struct MaintenanceUpdate {
+    pub window: NodeMaintenance,
+    /// Outbox events written with the change (see `outbox::deliver_now`)
+    pub outbox_ids: Vec<i64>,
+}
+
+/// Why a window couldn't be scheduled or changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleConflict {
+    /// Another open window on the node overlaps it
+    Overlaps,
+    /// The window has already ended or was cancelled
+    Closed,
+    /// The window is under way, so its start can no longer move
+    Started,
+}
+
+impl ScheduleConflict {
+    pub fn message(self) -> &'static str {
+        match self {
+            ScheduleConflict::Overlaps => "Maintenance window overlaps another window for this node",
+            ScheduleConflict::Closed => "Maintenance window is already closed",
+            ScheduleConflict::Started => "Maintenance window has already started",
+        }
+    }
+}
+
+/// Check a window an admin asked for; the error is the message returned to them
+pub fn validate_window(
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    reason: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<(), &'static str> {
+    if ends_at <= starts_at {
+        return Err("Maintenance must end after it starts");
+    }
+    if ends_at <= now {
+        return Err("Maintenance must end in the future");
+    }
+    if ends_at - starts_at > Duration::days(MAX_WINDOW_DAYS) {
+        return Err("Maintenance windows may last at most 7 days");
+    }
+    if starts_at > now + Duration::days(MAX_SCHEDULE_DAYS) {
+        return Err("Maintenance can be scheduled at most 90 days ahead");
+    }
+    if reason.is_some_and(|reason| reason.chars().count() > MAX_REASON_LEN) {
+        return Err("Reason must be at most 500 characters");
+    }
+    Ok(())
+}
+
+/// Status a window should have at `now`, going by its times alone
+pub fn phase_at(starts_at: DateTime<Utc>, ends_at: DateTime<Utc>, now: DateTime<Utc>) -> MaintenanceStatus {
+    if now < starts_at {
+        MaintenanceStatus::Scheduled
+    } else if now < ends_at {
+        MaintenanceStatus::Active
+    } else {
+        MaintenanceStatus::Completed
+    }
+}
+
+/// Users whose recent traffic went mostly through the node, to be notified of its windows
+pub async fn affected_users(pool: &PgPool, node_id: i64) -> Result<Vec<i64>> {
+    db::list_node_predominant_users(pool, node_id, Utc::now() - Duration::days(USAGE_WINDOW_DAYS)).await
+}
+
+/// Start or close one open window according to its times
+///
+/// Returns whether the window changed.
+pub async fn advance_window(pool: &PgPool, cache: &RedisCache, window: &NodeMaintenance) -> Result<bool> {
+    let target = phase_at(window.starts_at, window.ends_at, Utc::now());
+    let user_ids = affected_users(pool, window.node_id).await?;
+
+    let update = match (window.status, target) {
+        (MaintenanceStatus::Scheduled, MaintenanceStatus::Active) => {
+            db::start_node_maintenance(pool, window.id, &user_ids).await?
+        }
+        (status, MaintenanceStatus::Completed) if status.is_open() => {
+            db::end_node_maintenance(pool, window.id, MaintenanceStatus::Completed, &user_ids).await?
+        }
+        _ => None,
+    };
+    let Some(update) = update else {
+        return Ok(false);
+    };
+
+    tracing::info!(
+        "Node {} maintenance window {} is now {}",
+        update.window.node_id,
+        update.window.id,
+        update.window.status
+    );
+    outbox::deliver_now(pool, cache, &update.outbox_ids).await;
+
+    Ok(true)
+}
+
+/// Start windows whose time has come and close those that are over
+pub async fn run_maintenance_windows(pool: &PgPool, cache: &RedisCache) -> Result<usize> {
+    let mut changed = 0;
+    for window in db::list_due_node_maintenance(pool, Utc::now()).await? {
+        match advance_window(pool, cache, &window).await {
+            Ok(true) => changed += 1,
+            Ok(false) => {}
+            Err(e) => tracing::error!("Failed to advance maintenance window {}: {}", window.id, e),
+        }
+    }
+    Ok(changed)
+}
+
+/// Background task putting nodes into maintenance for their windows and restoring
+/// them afterwards
+pub fn start_maintenance_task(db_pool: PgPool, cache: RedisCache) -> tokio::task::JoinHandle<()> {
+    let job_cache = cache.clone();
+    spawn_periodic_job(cache, "node_maintenance", std::time::Duration::from_secs(60), move || {
+        let db_pool = db_pool.clone();
+        let cache = job_cache.clone();
+        async move {
+            match run_maintenance_windows(&db_pool, &cache).await {
+                Ok(0) => {}
+                Ok(changed) => tracing::info!("Advanced {} node maintenance windows", changed),
+                Err(e) => tracing::error!("Node maintenance run failed: {}", e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_window() {
+        let now = Utc::now();
+        let hour = Duration::hours(1);
+
+        assert_eq!(validate_window(now + hour, now + hour * 3, Some("Kernel upgrade"), now), Ok(()));
+        // A window may start right away, or have started already
+        assert_eq!(validate_window(now - hour, now + hour, None, now), Ok(()));
+
+        assert!(validate_window(now + hour, now + hour, None, now).is_err());
+        assert!(validate_window(now - hour * 3, now - hour, None, now).is_err());
+        assert!(validate_window(now, now + Duration::days(MAX_WINDOW_DAYS) + hour, None, now).is_err());
+        assert!(validate_window(now + Duration::days(MAX_SCHEDULE_DAYS + 1), now + Duration::days(MAX_SCHEDULE_DAYS + 2), None, now).is_err());
+        assert!(validate_window(now, now + hour, Some(&"x".repeat(MAX_REASON_LEN + 1)), now).is_err());
+    }
+
+    #[test]
+    fn test_phase_at() {
+        let now = Utc::now();
+        let hour = Duration::hours(1);
+
+        assert_eq!(phase_at(now + hour, now + hour * 2, now), MaintenanceStatus::Scheduled);
+        assert_eq!(phase_at(now, now + hour, now), MaintenanceStatus::Active);
+        assert_eq!(phase_at(now - hour, now, now), MaintenanceStatus::Completed);
+    }
+
+    #[test]
+    fn test_is_open() {
+        assert!(MaintenanceStatus::Scheduled.is_open());
+        assert!(MaintenanceStatus::Active.is_open());
+        assert!(!MaintenanceStatus::Completed.is_open());
+        assert!(!MaintenanceStatus::Cancelled.is_open());
+    }
+}
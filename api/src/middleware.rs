@@ -1,22 +1,31 @@
 use axum::{
-    extract::{Request, State},
-    http::{header, StatusCode},
+    async_trait,
+    body::Body,
+    extract::{FromRequestParts, OriginalUri, Request, State},
+    http::{header, request::Parts, HeaderValue, StatusCode, Uri},
     middleware::Next,
-    response::{IntoResponse, Response},
+    response::{IntoResponse, Redirect, Response},
     Json,
 };
 use redis::aio::ConnectionManager;
 use serde_json::json;
+use sqlx::PgPool;
 use std::sync::Arc;
 
+use crate::db;
+use crate::i18n::{self, Lang};
 use crate::utils::{verify_token, Claims};
 
+/// Largest error body the localization middleware will buffer
+const MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
 /// Extension type to store authenticated user claims
 #[derive(Clone)]
 pub struct AuthUser {
     pub user_id: i64,
     pub email: String,
     pub is_admin: bool,
+    pub tenant_id: i64,
 }
 
 impl From<Claims> for AuthUser {
@@ -25,6 +34,7 @@ impl From<Claims> for AuthUser {
             user_id: claims.sub,
             email: claims.email,
             is_admin: claims.is_admin,
+            tenant_id: claims.tenant_id,
         }
     }
 }
@@ -90,6 +100,88 @@ pub async fn admin_middleware(
     Ok(next.run(request).await)
 }
 
+/// Error localization middleware - translates `error.message` in JSON error responses
+///
+/// Language is taken from the authenticated user's profile, then Accept-Language,
+/// then the configured default. English responses pass through untouched.
+pub async fn localize_errors_middleware(
+    State((db_pool, jwt_secret, default_language)): State<(PgPool, String, Lang)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let user_id = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .and_then(|token| verify_token(token, &jwt_secret).ok())
+        .map(|claims| claims.sub);
+    let accept_language = request
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|h| h.to_str().ok())
+        .and_then(Lang::from_accept_language);
+
+    let response = next.run(request).await;
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .map_or(false, |ct| ct.starts_with("application/json"));
+    if !(response.status().is_client_error() || response.status().is_server_error()) || !is_json {
+        return response;
+    }
+
+    // Only look up the profile once we know there is an error to translate
+    let profile_language = match user_id {
+        Some(id) => match db::get_user_by_id(&db_pool, id).await {
+            Ok(user) => user.and_then(|u| u.language).and_then(|l| Lang::parse(&l)),
+            Err(e) => {
+                tracing::warn!("Failed to load language preference for user {}: {}", id, e);
+                None
+            }
+        },
+        None => None,
+    };
+    let lang = profile_language.or(accept_language).unwrap_or(default_language);
+    if lang == Lang::En {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_ERROR_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("Failed to buffer error response for localization: {}", e);
+            return parts.status.into_response();
+        }
+    };
+
+    let localized = serde_json::from_slice::<serde_json::Value>(&bytes)
+        .ok()
+        .and_then(|mut value| {
+            let message = value.pointer("/error/message")?.as_str()?.to_string();
+            value["error"]["message"] = json!(i18n::translate_error(&message, lang));
+            if let Some(fields) = value.pointer_mut("/error/fields").and_then(|f| f.as_array_mut()) {
+                for field in fields {
+                    if let Some(text) = field.get("message").and_then(|m| m.as_str()).map(str::to_string) {
+                        field["message"] = json!(i18n::translate_error(&text, lang));
+                    }
+                }
+            }
+            serde_json::to_vec(&value).ok()
+        });
+
+    match localized {
+        Some(body) => {
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(body))
+        }
+        None => Response::from_parts(parts, Body::from(bytes)),
+    }
+}
+
 /// Authentication errors
 #[derive(Debug)]
 pub enum AuthError {
@@ -201,6 +293,112 @@ pub async fn rate_limit_middleware(
     }
 }
 
+/// Prefix of the current API version
+pub const API_V1_PREFIX: &str = "/api/v1";
+
+/// Prefix of the legacy unversioned routes
+pub const LEGACY_API_PREFIX: &str = "/api";
+
+/// When the unversioned routes were deprecated (RFC 9745 `@` + Unix seconds, 2026-11-01)
+pub const LEGACY_API_DEPRECATED_AT: &str = "@1793491200";
+
+/// When the unversioned routes will be removed (RFC 8594 HTTP-date)
+pub const LEGACY_API_SUNSET: &str = "Sat, 01 May 2027 00:00:00 GMT";
+
+/// API version a request was routed through
+///
+/// Set as a request extension by the versioned routers; handlers that need to keep
+/// legacy response shapes can take it as an extractor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ApiVersion {
+    /// Unversioned `/api/...` aliases
+    #[default]
+    Legacy,
+    /// `/api/v1/...`
+    V1,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ApiVersion {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<ApiVersion>().copied().unwrap_or_default())
+    }
+}
+
+/// Map a legacy `/api/...` path to its `/api/v1/...` successor
+pub fn successor_path(path: &str) -> Option<String> {
+    let rest = path.strip_prefix(LEGACY_API_PREFIX)?;
+    if rest.starts_with('/') && !rest.starts_with("/v1/") {
+        Some(format!("{}{}", API_V1_PREFIX, rest))
+    } else {
+        None
+    }
+}
+
+/// Deprecation middleware for the legacy unversioned routes
+///
+/// Adds `Deprecation`, `Sunset` and a `successor-version` Link to every response so
+/// clients can find and migrate to the `/api/v1` path before the aliases are removed.
+pub async fn deprecation_headers_middleware(request: Request, next: Next) -> Response {
+    // Nested routers see the path with the prefix stripped
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|uri| uri.path().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert("Deprecation", HeaderValue::from_static(LEGACY_API_DEPRECATED_AT));
+    headers.insert("Sunset", HeaderValue::from_static(LEGACY_API_SUNSET));
+    if let Some(link) = successor_path(&path)
+        .and_then(|p| HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", p)).ok())
+    {
+        headers.insert(header::LINK, link);
+    }
+
+    response
+}
+
+/// HTTPS URL for a request made over plain HTTP
+///
+/// `host` comes from [`crate::tenancy::request_host`] (no port); the port is only
+/// written out when it isn't 443.
+pub fn https_location(host: &str, uri: &Uri, https_port: u16) -> String {
+    let host = if host.contains(':') { format!("[{}]", host) } else { host.to_string() };
+    let port = if https_port == 443 { String::new() } else { format!(":{}", https_port) };
+    let path = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+    format!("https://{}{}{}", host, port, path)
+}
+
+/// Permanent (308, method-preserving) redirect of a request to HTTPS
+pub fn redirect_to_https(request: &Request, https_port: u16) -> Response {
+    let Some(host) = crate::tenancy::request_host(request.headers()) else {
+        return (StatusCode::BAD_REQUEST, "Missing Host header").into_response();
+    };
+    Redirect::permanent(&https_location(&host, request.uri(), https_port)).into_response()
+}
+
+/// Redirect requests a reverse proxy received over plain HTTP (`FORCE_HTTPS`)
+///
+/// Only `X-Forwarded-Proto: http` is redirected, so direct requests and the proxy's
+/// own `/health` checks are served as before.
+pub async fn https_redirect_middleware(request: Request, next: Next) -> Response {
+    let forwarded_http = request
+        .headers()
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("http"));
+
+    if forwarded_http && request.uri().path() != "/health" {
+        return redirect_to_https(&request, 443);
+    }
+    next.run(request).await
+}
+
 /// Rate limiting errors
 #[derive(Debug)]
 pub enum RateLimitError {
@@ -240,6 +438,9 @@ mod tests {
             is_admin: true,
             exp: 1234567890,
             iat: 1234567800,
+            ver: 0,
+            tenant_id: 1,
+            platform_admin: false,
         };
 
         let auth_user = AuthUser::from(claims);
@@ -261,6 +462,88 @@ mod tests {
         assert_eq!(forbidden_response.status(), StatusCode::FORBIDDEN);
     }
 
+    #[test]
+    fn test_successor_path() {
+        assert_eq!(successor_path("/api/auth/login"), Some("/api/v1/auth/login".to_string()));
+        assert_eq!(successor_path("/api/admin/nodes/3"), Some("/api/v1/admin/nodes/3".to_string()));
+        assert_eq!(successor_path("/api/v1/auth/login"), None);
+        assert_eq!(successor_path("/apix/auth"), None);
+        assert_eq!(successor_path("/sub/token"), None);
+    }
+
+    #[tokio::test]
+    async fn test_versioned_and_legacy_routes() {
+        use axum::{routing::get, Extension, Router};
+        use tower::ServiceExt;
+
+        async fn version(version: ApiVersion) -> String {
+            format!("{:?}", version)
+        }
+
+        let routes = Router::new().route("/auth/version", get(version));
+        let app = Router::new()
+            .nest(API_V1_PREFIX, routes.clone().layer(Extension(ApiVersion::V1)))
+            .nest(
+                LEGACY_API_PREFIX,
+                routes.layer(axum::middleware::from_fn(deprecation_headers_middleware)),
+            );
+
+        let request = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(request("/api/v1/auth/version")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get("Deprecation").is_none());
+        let body = axum::body::to_bytes(response.into_body(), 1024).await.unwrap();
+        assert_eq!(&body[..], b"V1");
+
+        let response = app.oneshot(request("/api/auth/version")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["Deprecation"], LEGACY_API_DEPRECATED_AT);
+        assert_eq!(response.headers()["Sunset"], LEGACY_API_SUNSET);
+        assert_eq!(
+            response.headers()[header::LINK],
+            "</api/v1/auth/version>; rel=\"successor-version\""
+        );
+        let body = axum::body::to_bytes(response.into_body(), 1024).await.unwrap();
+        assert_eq!(&body[..], b"Legacy");
+    }
+
+    #[test]
+    fn test_https_location() {
+        let uri: Uri = "/sub/abc?target=clash".parse().unwrap();
+
+        assert_eq!(https_location("sub.example.com", &uri, 443), "https://sub.example.com/sub/abc?target=clash");
+        assert_eq!(https_location("sub.example.com", &uri, 8443), "https://sub.example.com:8443/sub/abc?target=clash");
+        assert_eq!(https_location("::1", &"/".parse().unwrap(), 443), "https://[::1]/");
+    }
+
+    #[tokio::test]
+    async fn test_https_redirect_middleware() {
+        use axum::{routing::get, Router};
+        use tower::ServiceExt;
+
+        let app = Router::new()
+            .route("/health", get(|| async { "OK" }))
+            .route("/api/v1/packages", get(|| async { "packages" }))
+            .layer(axum::middleware::from_fn(https_redirect_middleware));
+        let request = |uri: &str, proto: Option<&str>| {
+            let mut builder = Request::builder().uri(uri).header(header::HOST, "sub.example.com:80");
+            if let Some(proto) = proto {
+                builder = builder.header("x-forwarded-proto", proto);
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+
+        let response = app.clone().oneshot(request("/api/v1/packages", Some("http"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(response.headers()[header::LOCATION], "https://sub.example.com/api/v1/packages");
+
+        for (uri, proto) in [("/api/v1/packages", Some("https")), ("/api/v1/packages", None), ("/health", Some("http"))] {
+            let response = app.clone().oneshot(request(uri, proto)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{} {:?}", uri, proto);
+        }
+    }
+
     #[test]
     fn test_rate_limit_error_response() {
         let error = RateLimitError::TooManyRequests;
@@ -1,7 +1,19 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
+use crate::adjustments::AdjustmentStatus;
+use crate::amounts::{Coins, TrafficBytes};
+use crate::api_keys::ApiKeyScope;
+use crate::geoip::GeoAction;
+use crate::maintenance::MaintenanceStatus;
+use crate::orders::OrderStatus;
+use crate::purchases::Ineligibility;
+use crate::status::{NodeStatus, ReportedNodeStatus, UserPackageStatus, UserStatus};
+use crate::status_page::IncidentSeverity;
+use crate::topup::TopupStatus;
+use crate::traffic::{QuarantineReason, QuarantineStatus};
+
 /// User model representing a platform user
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct User {
@@ -9,15 +21,28 @@ pub struct User {
     pub email: String,
     #[serde(skip_serializing)]
     pub password_hash: String,
-    pub coin_balance: i64,
-    pub traffic_quota: i64,
-    pub traffic_used: i64,
+    pub coin_balance: Coins,
+    pub traffic_quota: TrafficBytes,
+    pub traffic_used: TrafficBytes,
     pub referral_code: Option<String>,
     pub referred_by: Option<i64>,
-    pub status: String,
+    pub status: UserStatus,
     pub is_admin: bool,
+    pub language: Option<String>,
+    pub reserved_coins: Coins,
+    #[serde(skip_serializing)]
+    pub token_version: i32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Tenant the account belongs to (see `tenancy`)
+    pub tenant_id: i64,
+    /// Admin of every tenant, including global settings; only meaningful with `is_admin`
+    pub is_platform_admin: bool,
+    /// Source panel and id of an imported user, e.g. `v2board:42`
+    #[serde(skip_serializing)]
+    pub import_ref: Option<String>,
+    /// Test account whose purchases are sandboxed (see `payments::is_sandbox`)
+    pub is_sandbox: bool,
 }
 
 /// Package model representing a traffic package
@@ -25,13 +50,23 @@ pub struct User {
 pub struct Package {
     pub id: i64,
     pub name: String,
-    pub traffic_amount: i64,
-    pub price: i64,
+    pub traffic_amount: TrafficBytes,
+    pub price: Coins,
     pub duration_days: i32,
     pub description: Option<String>,
     pub is_active: bool,
+    pub update_interval_hours: i32,
+    /// Max concurrent connections per user across all nodes (0 = unlimited)
+    pub max_connections: i32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub tenant_id: i64,
+    /// Units left to sell (None = unlimited)
+    pub stock: Option<i32>,
+    /// Times one user may buy the package (0 = unlimited)
+    pub max_per_user: i32,
+    /// Can't be held alongside any other active package
+    pub exclusive: bool,
 }
 
 /// Order model representing a purchase order
@@ -41,10 +76,62 @@ pub struct Order {
     pub order_no: String,
     pub user_id: i64,
     pub package_id: i64,
-    pub amount: i64,
-    pub status: String,
+    pub amount: Coins,
+    pub status: OrderStatus,
     pub created_at: DateTime<Utc>,
     pub completed_at: Option<DateTime<Utc>>,
+    pub coin_amount: Coins,
+    pub external_amount: Coins,
+    pub payment_reference: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Optimistic lock, bumped on every status change
+    pub version: i32,
+    /// Placed in the sandbox: no real coins moved, excluded from revenue stats
+    pub sandbox: bool,
+    /// When the package bought starts, if scheduled; applied when the order completes
+    pub package_activates_at: Option<DateTime<Utc>>,
+    /// Took a unit of the package's stock, returned if the order fails or expires
+    pub took_stock: bool,
+}
+
+/// OAuthIdentity model linking a login provider account to a user
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct OAuthIdentity {
+    pub id: i64,
+    pub user_id: i64,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_login_at: DateTime<Utc>,
+}
+
+/// Query for `GET /api/auth/oauth/:provider/authorize`
+#[derive(Debug, Deserialize)]
+pub struct OAuthAuthorizeQuery {
+    /// Applied if the login creates a new account
+    pub referral_code: Option<String>,
+}
+
+/// Query the provider sends to `GET /api/auth/oauth/:provider/callback`
+#[derive(Debug, Deserialize)]
+pub struct OAuthCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    /// Set instead of `code` when the user denied access
+    pub error: Option<String>,
+}
+
+/// OrderEvent model recording one order status change
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct OrderEvent {
+    pub id: i64,
+    pub order_id: i64,
+    pub order_no: String,
+    pub user_id: i64,
+    pub from_status: OrderStatus,
+    pub to_status: OrderStatus,
+    pub created_at: DateTime<Utc>,
 }
 
 /// UserPackage model representing a user's purchased package
@@ -53,12 +140,15 @@ pub struct UserPackage {
     pub id: i64,
     pub user_id: i64,
     pub package_id: i64,
-    pub order_id: i64,
-    pub traffic_quota: i64,
-    pub traffic_used: i64,
+    /// None for packages imported from another panel
+    pub order_id: Option<i64>,
+    pub traffic_quota: TrafficBytes,
+    pub traffic_used: TrafficBytes,
     pub expires_at: DateTime<Utc>,
-    pub status: String,
+    pub status: UserPackageStatus,
     pub created_at: DateTime<Utc>,
+    /// When a scheduled package starts; None for packages that started when granted
+    pub activates_at: Option<DateTime<Utc>>,
 }
 
 /// Node model representing a VPN server node
@@ -72,7 +162,7 @@ pub struct Node {
     #[serde(skip_serializing)]
     pub secret: String,
     pub config: serde_json::Value,
-    pub status: String,
+    pub status: NodeStatus,
     pub max_users: i32,
     pub current_users: i32,
     pub total_upload: i64,
@@ -83,6 +173,69 @@ pub struct Node {
     // New fields for Clash integration
     pub include_in_clash: bool,
     pub sort_order: i32,
+    /// Monthly traffic budget, 0 = unlimited
+    pub traffic_budget: TrafficBytes,
+    /// What happens once the budget is used up: alert, maintenance or exclude_from_clash
+    pub traffic_budget_action: String,
+    /// False for self-registered nodes until an admin approves them
+    pub approved: bool,
+    /// Capabilities reported by the agent at enrollment (OS, arch, protocols, ...)
+    pub capabilities: serde_json::Value,
+    /// Only users of this tenant get the node in their subscription
+    pub tenant_id: i64,
+    /// Labels matched by `tag:` proxy group members, e.g. `HK`, `streaming`
+    pub tags: Vec<String>,
+    /// TCP connect time of the last connectivity test, None if it failed or never ran
+    pub probe_latency_ms: Option<i32>,
+    /// When the last connectivity test ran
+    pub probed_at: Option<DateTime<Utc>>,
+    /// Primary node this node stands in for; generated configs put them in a `fallback` group
+    pub backup_of: Option<i64>,
+    /// Agent version reported in the last heartbeat (None for agents that don't report it)
+    pub agent_version: Option<String>,
+    /// Incremented whenever the node's settings change (see migration 051)
+    pub config_version: i64,
+    /// `config_version` the agent reported applying in its last heartbeat (None for agents that don't report it)
+    pub applied_config_version: Option<i64>,
+}
+
+impl Node {
+    /// An approved, online Shadowsocks node of the default tenant, for tests and benchmarks
+    /// to override the fields they care about
+    #[doc(hidden)]
+    pub fn test_fixture() -> Self {
+        Node {
+            id: 1,
+            name: "test-node".to_string(),
+            host: "node.example.com".to_string(),
+            port: 443,
+            protocol: "shadowsocks".to_string(),
+            secret: "secret".to_string(),
+            config: serde_json::json!({}),
+            status: NodeStatus::Online,
+            max_users: 1000,
+            current_users: 0,
+            total_upload: 0,
+            total_download: 0,
+            last_heartbeat: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            include_in_clash: true,
+            sort_order: 0,
+            traffic_budget: TrafficBytes::ZERO,
+            traffic_budget_action: "alert".to_string(),
+            approved: true,
+            capabilities: serde_json::json!({}),
+            tenant_id: crate::tenancy::DEFAULT_TENANT_ID,
+            tags: Vec::new(),
+            probe_latency_ms: None,
+            probed_at: None,
+            backup_of: None,
+            agent_version: None,
+            config_version: 1,
+            applied_config_version: None,
+        }
+    }
 }
 
 /// TrafficLog model representing traffic usage records
@@ -104,6 +257,38 @@ pub struct Subscription {
     pub token: String,
     pub created_at: DateTime<Utc>,
     pub last_accessed: Option<DateTime<Utc>>,
+    pub token_rotated_at: Option<DateTime<Utc>>,
+    /// Until when the token replaced by the last rotation keeps working
+    pub previous_token_expires_at: Option<DateTime<Utc>>,
+    /// SHA-256 of the access key fetches must present; `None` when not protected
+    #[serde(skip_serializing)]
+    pub access_key_hash: Option<String>,
+}
+
+/// Short code that serves the subscription of `subscription_id` at `/s/:code`
+///
+/// Expires and rotates independently of the subscription token it points to.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SubscriptionShortLink {
+    pub id: i64,
+    pub subscription_id: i64,
+    pub code: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub last_accessed: Option<DateTime<Utc>>,
+}
+
+/// Admin override of the global GeoIP restrictions for one user's subscription
+///
+/// `None` fields fall back to the global setting; empty lists lift the country rules.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct SubscriptionGeoRestriction {
+    pub user_id: i64,
+    pub allowed_countries: Option<Vec<String>>,
+    pub blocked_countries: Option<Vec<String>>,
+    pub action: Option<GeoAction>,
+    pub updated_by: Option<i64>,
+    pub updated_at: DateTime<Utc>,
 }
 
 /// CoinTransaction model representing coin balance changes
@@ -111,12 +296,202 @@ pub struct Subscription {
 pub struct CoinTransaction {
     pub id: i64,
     pub user_id: i64,
-    pub amount: i64,
+    pub amount: Coins,
     #[serde(rename = "type")]
     #[sqlx(rename = "type")]
     pub transaction_type: String,
     pub description: Option<String>,
     pub created_at: DateTime<Utc>,
+    /// Order a purchase transaction pays for
+    pub order_id: Option<i64>,
+}
+
+/// A referral rebate paid for one referee
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ReferralCommission {
+    #[serde(skip)]
+    pub referee_id: i64,
+    pub amount: Coins,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A user the referrer brought in, with the commissions earned from them
+#[derive(Debug, Clone, Serialize)]
+pub struct ReferralRecord {
+    /// Masked before being returned to the referrer
+    pub email: String,
+    pub registered_at: DateTime<Utc>,
+    /// Whether the referee has completed a (non-sandbox) order
+    pub purchased: bool,
+    pub total_commission: Coins,
+    /// Oldest first
+    pub commissions: Vec<ReferralCommission>,
+}
+
+/// Paginated referral records, most recent referees first
+#[derive(Debug, Serialize)]
+pub struct ReferralRecordListResponse {
+    pub records: Vec<ReferralRecord>,
+    pub total: i64,
+    pub page: i64,
+    pub page_size: i64,
+    pub total_pages: i64,
+}
+
+/// A user whose coin balance doesn't match the sum of their coin transactions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct CoinBalanceDrift {
+    pub user_id: i64,
+    pub tenant_id: i64,
+    pub email: String,
+    pub coin_balance: Coins,
+    /// Sum of the user's coin transactions
+    pub ledger_balance: Coins,
+    /// `coin_balance - ledger_balance`
+    pub drift: Coins,
+    pub last_transaction_at: Option<DateTime<Utc>>,
+}
+
+/// A completed order whose coin purchase transactions don't add up to the coins it charged
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct OrderCoinMismatch {
+    pub order_id: i64,
+    pub order_no: String,
+    pub user_id: i64,
+    pub tenant_id: i64,
+    pub email: String,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Coins the order should have charged (the whole price for coin purchases)
+    pub expected_coins: Coins,
+    /// Coins charged by the order's purchase transactions
+    pub charged_coins: Coins,
+    pub transaction_count: i64,
+}
+
+/// A user whose traffic totals don't match the sums over their packages
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct UserTrafficDrift {
+    pub user_id: i64,
+    pub email: String,
+    pub traffic_quota: TrafficBytes,
+    pub traffic_used: TrafficBytes,
+    /// Sum of the user's package quotas
+    pub package_quota: TrafficBytes,
+    /// Sum of the user's package usage
+    pub package_used: TrafficBytes,
+}
+
+/// A node traffic report held back from quotas for admin review (see `traffic::screen_report`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct QuarantinedTrafficReport {
+    pub id: i64,
+    pub node_id: i64,
+    pub user_id: i64,
+    pub upload: i64,
+    pub download: i64,
+    pub reason: QuarantineReason,
+    pub status: QuarantineStatus,
+    /// When the node sent the report
+    pub reported_at: DateTime<Utc>,
+    pub reviewed_by: Option<i64>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A user's request to have coins credited after paying outside the platform
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TopupRequest {
+    pub id: i64,
+    pub user_id: i64,
+    pub amount: Coins,
+    /// Payment reference given by the user (transfer number, payer name, ...)
+    pub reference: Option<String>,
+    pub screenshot_url: Option<String>,
+    pub status: TopupStatus,
+    /// Reviewer's note, shown to the user
+    pub admin_note: Option<String>,
+    pub reviewed_by: Option<i64>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    /// The recharge transaction written on approval
+    pub coin_transaction_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A manual balance change large enough to need a second admin's approval
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct BalanceAdjustment {
+    pub id: i64,
+    pub user_id: i64,
+    /// Positive adds coins, negative takes them
+    pub amount: Coins,
+    pub reason: String,
+    pub status: AdjustmentStatus,
+    /// Admin who asked for the change
+    pub requested_by: Option<i64>,
+    /// Admin who approved or rejected it
+    pub reviewed_by: Option<i64>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    pub review_note: Option<String>,
+    /// The transaction written on approval
+    pub coin_transaction_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A terms of service version a user accepted, kept for compliance
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TermsAcceptance {
+    pub id: i64,
+    pub user_id: i64,
+    pub version: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub accepted_at: DateTime<Utc>,
+}
+
+/// Request body for accepting the terms of service
+#[derive(Debug, Deserialize)]
+pub struct AcceptTermsRequest {
+    /// Must be the current version, so users accept the text they were shown
+    pub version: String,
+}
+
+/// An admin's note on a user account, with the author's email for display
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UserNote {
+    pub id: i64,
+    pub user_id: i64,
+    pub author_id: Option<i64>,
+    /// None once the author's account is deleted
+    pub author_email: Option<String>,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A tag an admin put on a user account (e.g. "VIP", "chargeback risk")
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UserTag {
+    pub id: i64,
+    pub user_id: i64,
+    pub tag: String,
+    pub author_id: Option<i64>,
+    pub author_email: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A tag in use, with how many users carry it
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UserTagCount {
+    pub tag: String,
+    pub users: i64,
+}
+
+/// Filters for the admin user list
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserListFilter {
+    /// Users carrying this tag (case-insensitive)
+    pub tag: Option<String>,
+    /// Users with a note containing this text (case-insensitive)
+    pub note: Option<String>,
 }
 
 /// AdminLog model representing admin operations
@@ -131,74 +506,638 @@ pub struct AdminLog {
     pub created_at: DateTime<Utc>,
 }
 
+/// NodeMetric model representing one heartbeat's resource sample
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NodeMetric {
+    pub id: i64,
+    pub node_id: i64,
+    pub cpu_usage: Option<f64>,
+    pub memory_usage: Option<f64>,
+    pub active_connections: Option<i32>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// NodeAlert model representing a breached node health threshold
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NodeAlert {
+    pub id: i64,
+    pub node_id: i64,
+    pub alert_type: String,
+    pub message: String,
+    pub value: Option<f64>,
+    pub threshold: Option<f64>,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+/// NodeDiagnostics model holding one `node-agent --diagnose` report
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NodeDiagnostics {
+    pub id: i64,
+    pub node_id: i64,
+    pub agent_version: Option<String>,
+    /// Whether no check failed
+    pub passed: bool,
+    /// `DiagnosticCheck` list as reported by the agent
+    pub checks: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// NodeMaintenance model for a scheduled maintenance window
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NodeMaintenance {
+    pub id: i64,
+    pub node_id: i64,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub reason: Option<String>,
+    pub status: MaintenanceStatus,
+    /// Node status put back when the window ends, None if the node was already held
+    pub restore_status: Option<NodeStatus>,
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// SecurityEvent model recording a security-relevant action
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SecurityEvent {
+    pub id: i64,
+    pub event_type: String,
+    pub severity: String,
+    pub actor_id: Option<i64>,
+    pub target_type: Option<String>,
+    pub target_id: Option<i64>,
+    pub ip_address: Option<String>,
+    pub message: String,
+    pub details: serde_json::Value,
+    pub alerted: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An IP recorded for going over the per-IP subscription fetch caps
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SubscriptionOffender {
+    pub ip_address: String,
+    /// Windows in which the IP was refused
+    pub events: i64,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    /// Caps it went over (`concurrency`, `frequency`)
+    pub limits: Vec<String>,
+}
+
+/// Revenue aggregates over a report range
+#[derive(Debug, Clone, FromRow)]
+pub struct RevenueTotals {
+    pub gross_revenue: Coins,
+    pub refunds: Coins,
+    pub order_count: i64,
+    /// Distinct users with a sale in the range
+    pub buyers: i64,
+    /// Buyers whose first ever purchase is in the range
+    pub new_buyers: i64,
+    /// Non-admin users registered by the end of the range
+    pub users: i64,
+}
+
+/// One period of the revenue time series
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct RevenueBucket {
+    /// Start of the period
+    pub period: DateTime<Utc>,
+    pub gross_revenue: Coins,
+    pub refunds: Coins,
+    pub net_revenue: Coins,
+    pub order_count: i64,
+    pub buyers: i64,
+    pub new_buyers: i64,
+    pub returning_buyers: i64,
+}
+
+/// Revenue of one package over a report range
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct PackageRevenue {
+    pub package_id: i64,
+    pub package_name: String,
+    pub order_count: i64,
+    pub gross_revenue: Coins,
+    pub refunds: Coins,
+    pub net_revenue: Coins,
+}
+
+/// Revenue totals with per-user averages
+#[derive(Debug, Clone, Serialize)]
+pub struct RevenueSummary {
+    pub gross_revenue: Coins,
+    pub refunds: Coins,
+    pub net_revenue: Coins,
+    pub order_count: i64,
+    pub buyers: i64,
+    pub new_buyers: i64,
+    pub returning_buyers: i64,
+    pub users: i64,
+    /// Net revenue per registered user
+    pub arpu: f64,
+    /// Net revenue per paying user
+    pub arppu: f64,
+}
+
+/// A user's traffic over a report window, with their current package
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TopTrafficUser {
+    pub user_id: i64,
+    pub tenant_id: i64,
+    pub email: String,
+    pub upload: TrafficBytes,
+    pub download: TrafficBytes,
+    pub total: TrafficBytes,
+    /// Distinct nodes the traffic went through
+    pub node_ids: Vec<i64>,
+    /// Active package that expires last, if any
+    pub package_id: Option<i64>,
+    pub package_name: Option<String>,
+    /// Sum of the active packages' quotas
+    pub quota: TrafficBytes,
+    /// Sum of the active packages' usage
+    pub used: TrafficBytes,
+}
+
+/// Revenue report for `GET /api/admin/stats/revenue`
+#[derive(Debug, Clone, Serialize)]
+pub struct RevenueReport {
+    pub granularity: String,
+    /// Time zone periods are cut in (`REPORT_TIMEZONE`)
+    pub time_zone: String,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub summary: RevenueSummary,
+    pub series: Vec<RevenueBucket>,
+    pub by_package: Vec<PackageRevenue>,
+}
+
+/// SubscriptionPreferences model controlling proxy naming in a user's subscription
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SubscriptionPreferences {
+    pub user_id: i64,
+    pub emoji_flag: bool,
+    pub region_prefix: bool,
+    pub show_traffic_ratio: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
 // DTO (Data Transfer Object) models for API requests/responses
 
-/// Request body for user registration
+/// Request body for user registration
+#[derive(Debug, Deserialize)]
+pub struct RegisterRequest {
+    pub email: String,
+    pub password: String,
+    pub referral_code: Option<String>,
+    /// Terms version the user agreed to on the sign-up form
+    pub terms_version: Option<String>,
+}
+
+/// Request body for user login
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    pub email: String,
+    pub password: String,
+}
+
+/// Request body for updating the preferred language (null clears it)
+#[derive(Debug, Deserialize)]
+pub struct UpdateLanguageRequest {
+    pub language: Option<String>,
+}
+
+/// Request body for updating subscription naming preferences (omitted fields are unchanged)
+#[derive(Debug, Deserialize)]
+pub struct UpdateSubscriptionPreferencesRequest {
+    pub emoji_flag: Option<bool>,
+    pub region_prefix: Option<bool>,
+    pub show_traffic_ratio: Option<bool>,
+}
+
+/// Request body for turning subscription access key protection on or off
+///
+/// Enabling without a `key` generates one.
+#[derive(Debug, Deserialize)]
+pub struct UpdateSubscriptionProtectionRequest {
+    pub enabled: bool,
+    pub key: Option<String>,
+}
+
+/// Request body for setting a user's GeoIP restrictions (omitted fields use the global setting)
+#[derive(Debug, Deserialize)]
+pub struct UpdateGeoRestrictionRequest {
+    pub allowed_countries: Option<Vec<String>>,
+    pub blocked_countries: Option<Vec<String>>,
+    pub action: Option<GeoAction>,
+}
+
+/// Request body for submitting a top-up request
+#[derive(Debug, Deserialize)]
+pub struct CreateTopupRequest {
+    pub amount: Coins,
+    pub reference: Option<String>,
+    pub screenshot_url: Option<String>,
+}
+
+/// Request body for adding a note to a user account
+#[derive(Debug, Deserialize)]
+pub struct CreateUserNoteRequest {
+    pub body: String,
+}
+
+/// Request body for tagging a user account
+#[derive(Debug, Deserialize)]
+pub struct AddUserTagRequest {
+    pub tag: String,
+}
+
+/// Request body for approving or rejecting a top-up request
+#[derive(Debug, Default, Deserialize)]
+pub struct ReviewTopupRequest {
+    pub note: Option<String>,
+}
+
+/// Request body for approving or rejecting a balance adjustment
+#[derive(Debug, Default, Deserialize)]
+pub struct ReviewAdjustmentRequest {
+    pub note: Option<String>,
+}
+
+/// Request body for issuing a subscription short link
+#[derive(Debug, Default, Deserialize)]
+pub struct CreateShortLinkRequest {
+    /// Link lifetime in hours (max 8760); omitted means the link never expires
+    pub expires_in_hours: Option<i64>,
+}
+
+/// Longest lifetime a subscription short link may be given
+pub const SHORT_LINK_MAX_HOURS: i64 = 24 * 365;
+
+/// Response body for authentication (login/register)
+#[derive(Debug, Serialize)]
+pub struct AuthResponse {
+    pub token: String,
+    pub user: UserResponse,
+    pub terms: TermsStatus,
+}
+
+/// Where a user stands with the current terms of service
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TermsStatus {
+    /// Current version (`TERMS_VERSION`), None when there are no terms to accept
+    pub version: Option<String>,
+    /// Whether the user accepted the current version; always true without terms
+    pub accepted: bool,
+}
+
+/// User response (without sensitive data)
+#[derive(Debug, Serialize)]
+pub struct UserResponse {
+    pub id: i64,
+    pub email: String,
+    pub coin_balance: Coins,
+    pub traffic_quota: TrafficBytes,
+    pub traffic_used: TrafficBytes,
+    pub referral_code: Option<String>,
+    pub status: UserStatus,
+    pub is_admin: bool,
+    pub language: Option<String>,
+    pub is_sandbox: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<User> for UserResponse {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            email: user.email,
+            coin_balance: user.coin_balance,
+            traffic_quota: user.traffic_quota,
+            traffic_used: user.traffic_used,
+            referral_code: user.referral_code,
+            status: user.status,
+            is_admin: user.is_admin,
+            language: user.language,
+            is_sandbox: user.is_sandbox,
+            created_at: user.created_at,
+        }
+    }
+}
+
+/// Request body for a purchase split between coins and an external payment
+#[derive(Debug, Deserialize)]
+pub struct MixedPurchaseRequest {
+    /// Coins to apply; defaults to the whole available balance (capped at the price)
+    pub coin_amount: Option<Coins>,
+    /// When the package starts: `now` (default), `on_expiry` or an RFC 3339 time
+    pub activates_at: Option<String>,
+}
+
+/// Query parameters for `POST /api/packages/:id/purchase`
+#[derive(Debug, Deserialize)]
+pub struct PurchaseOptions {
+    /// When the package starts: `now` (default), `on_expiry` or an RFC 3339 time
+    pub activates_at: Option<String>,
+}
+
+/// Payment gateway callback body (signed with PAYMENT_WEBHOOK_SECRET)
+#[derive(Debug, Deserialize)]
+pub struct PaymentCallbackRequest {
+    pub order_no: String,
+    pub payment_reference: String,
+    pub amount: Coins,
+    /// "paid" or "failed"
+    pub status: String,
+}
+
+/// Request body for `POST /api/admin/payments/simulate`
+#[derive(Debug, Deserialize)]
+pub struct SimulatePaymentRequest {
+    pub order_no: String,
+    /// "paid" (default) or "failed"
+    pub status: Option<String>,
+    /// Defaults to a generated `SANDBOX-...` reference
+    pub payment_reference: Option<String>,
+}
+
+/// Request body for package purchase
+#[derive(Debug, Deserialize)]
+pub struct PurchasePackageRequest {
+    pub package_id: i64,
+}
+
+/// Response for GET /api/packages/:id/eligibility
+#[derive(Debug, Serialize)]
+pub struct PackageEligibilityResponse {
+    pub package_id: i64,
+    /// Whether a coin purchase would go through
+    pub eligible: bool,
+    pub reasons: Vec<Ineligibility>,
+    pub price: Coins,
+    pub available_balance: Coins,
+    /// Coins missing for a coin purchase
+    pub shortfall: Coins,
+    /// Units left (None = unlimited)
+    pub stock: Option<i32>,
+    /// Purchases left for this user (None = unlimited)
+    pub remaining_purchases: Option<i64>,
+}
+
+/// Request body for creating a node
+#[derive(Debug, Deserialize)]
+pub struct CreateNodeRequest {
+    pub name: String,
+    pub host: String,
+    pub port: i32,
+    pub protocol: String,
+    pub config: serde_json::Value,
+    /// Owning tenant (platform admins only; defaults to the admin's tenant)
+    pub tenant_id: Option<i64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Default lifetime of a node enrollment token
+pub const ENROLLMENT_TOKEN_DEFAULT_HOURS: i64 = 24;
+/// Longest lifetime an enrollment token may be given
+pub const ENROLLMENT_TOKEN_MAX_HOURS: i64 = 24 * 30;
+
+/// NodeEnrollmentToken model (the token itself is only returned once, at creation)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NodeEnrollmentToken {
+    pub id: i64,
+    pub description: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    /// Node created with this token
+    pub node_id: Option<i64>,
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    /// Tenant the enrolled node is assigned to
+    pub tenant_id: i64,
+}
+
+/// Request body for creating a node enrollment token
+#[derive(Debug, Deserialize)]
+pub struct CreateEnrollmentTokenRequest {
+    pub description: Option<String>,
+    /// Token lifetime in hours (default 24, max 720)
+    pub expires_in_hours: Option<i64>,
+    /// Tenant for the enrolled node (platform admins only; defaults to the admin's tenant)
+    pub tenant_id: Option<i64>,
+}
+
+/// ApiKey model: a scoped credential for server-to-server integrations (see `api_keys`)
+///
+/// The key itself is only returned once, at creation; only its hash is stored.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    /// Leading characters of the key, to tell keys apart
+    pub key_prefix: String,
+    pub scopes: Vec<ApiKeyScope>,
+    /// None: the key never expires
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub last_used_ip: Option<String>,
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Request body for `POST /api/admin/api-keys`
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<ApiKeyScope>,
+    /// Key lifetime in days (default: never expires)
+    pub expires_in_days: Option<i64>,
+}
+
+/// Request body for `POST /api/admin/users` (admins and `users:provision` API keys)
+#[derive(Debug, Deserialize)]
+pub struct ProvisionUserRequest {
+    pub email: String,
+    pub password: String,
+    /// Tenant for the account (defaults to the default tenant)
+    pub tenant_id: Option<i64>,
+    /// Create a test account whose purchases are sandboxed
+    #[serde(default)]
+    pub sandbox: bool,
+}
+
+/// Request body for `POST /api/admin/users/import`
+#[derive(Debug, Deserialize)]
+pub struct ImportUsersRequest {
+    pub source: crate::user_import::ImportSource,
+    pub format: crate::user_import::ImportFormat,
+    /// The export: CSV with a header row, or a JSON array of objects
+    pub content: String,
+    /// Package the imported users' remaining traffic is attached to
+    pub package_id: i64,
+    /// Tenant to import into (defaults to the admin's own)
+    pub tenant_id: Option<i64>,
+    /// Coins per yuan of SSPanel/V2Board balance (defaults to 1)
+    pub balance_rate: Option<f64>,
+    pub dry_run: Option<bool>,
+}
+
+/// One node traffic report submitted by an integration
+#[derive(Debug, Deserialize)]
+pub struct TrafficReportEntry {
+    pub node_id: i64,
+    pub user_id: i64,
+    pub upload: i64,
+    pub download: i64,
+}
+
+/// Request body for `POST /api/admin/traffic/reports`
+#[derive(Debug, Deserialize)]
+pub struct SubmitTrafficReportsRequest {
+    pub reports: Vec<TrafficReportEntry>,
+}
+
+/// FeatureFlag model for gradual rollouts (see `features`)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct FeatureFlag {
+    pub key: String,
+    pub description: Option<String>,
+    /// Master switch; a disabled flag is off for everyone
+    pub enabled: bool,
+    /// Share of users (0-100) the flag is on for
+    pub rollout_percentage: i16,
+    /// Users the flag is always on for while enabled
+    pub user_ids: Vec<i64>,
+    pub updated_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Request body for `PUT /api/admin/feature-flags/:key`
+#[derive(Debug, Deserialize)]
+pub struct UpsertFeatureFlagRequest {
+    pub description: Option<String>,
+    pub enabled: bool,
+    #[serde(default)]
+    pub rollout_percentage: i16,
+    #[serde(default)]
+    pub user_ids: Vec<i64>,
+}
+
+/// Incident notice on the public status page (see `status_page`)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct StatusIncident {
+    pub id: i64,
+    pub title: String,
+    pub message: String,
+    pub severity: IncidentSeverity,
+    pub started_at: DateTime<Utc>,
+    /// `None` while the incident is ongoing
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Request body for `POST /api/admin/incidents`
 #[derive(Debug, Deserialize)]
-pub struct RegisterRequest {
-    pub email: String,
-    pub password: String,
-    pub referral_code: Option<String>,
+pub struct CreateStatusIncidentRequest {
+    pub title: String,
+    #[serde(default)]
+    pub message: String,
+    pub severity: IncidentSeverity,
+    /// Defaults to now
+    pub started_at: Option<DateTime<Utc>>,
 }
 
-/// Request body for user login
+/// Request body for `PUT /api/admin/incidents/:id`; omitted fields are left unchanged
 #[derive(Debug, Deserialize)]
-pub struct LoginRequest {
-    pub email: String,
-    pub password: String,
+pub struct UpdateStatusIncidentRequest {
+    pub title: Option<String>,
+    pub message: Option<String>,
+    pub severity: Option<IncidentSeverity>,
+    /// `true` marks the incident resolved now, `false` reopens it
+    pub resolved: Option<bool>,
 }
 
-/// Response body for authentication (login/register)
-#[derive(Debug, Serialize)]
-pub struct AuthResponse {
-    pub token: String,
-    pub user: UserResponse,
+/// Tenant model: one branded panel of a shared deployment (see `tenancy`)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Tenant {
+    pub id: i64,
+    pub slug: String,
+    pub name: String,
+    /// Panel hosts, matched against the request's Host header
+    pub domains: Vec<String>,
+    /// Host used in subscription links (defaults to the request's host)
+    pub subscription_domain: Option<String>,
+    /// Branding shown to visitors: site name, logo, colors, support contact, ...
+    pub settings: serde_json::Value,
+    /// Inactive tenants' domains fall back to the default tenant
+    pub is_active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
-/// User response (without sensitive data)
+/// Response for `GET /api/tenant`: what the frontend needs to brand itself
 #[derive(Debug, Serialize)]
-pub struct UserResponse {
-    pub id: i64,
-    pub email: String,
-    pub coin_balance: i64,
-    pub traffic_quota: i64,
-    pub traffic_used: i64,
-    pub referral_code: Option<String>,
-    pub status: String,
-    pub is_admin: bool,
-    pub created_at: DateTime<Utc>,
+pub struct TenantBranding {
+    pub slug: String,
+    pub name: String,
+    pub settings: serde_json::Value,
 }
 
-impl From<User> for UserResponse {
-    fn from(user: User) -> Self {
+impl From<Tenant> for TenantBranding {
+    fn from(tenant: Tenant) -> Self {
         Self {
-            id: user.id,
-            email: user.email,
-            coin_balance: user.coin_balance,
-            traffic_quota: user.traffic_quota,
-            traffic_used: user.traffic_used,
-            referral_code: user.referral_code,
-            status: user.status,
-            is_admin: user.is_admin,
-            created_at: user.created_at,
+            slug: tenant.slug,
+            name: tenant.name,
+            settings: tenant.settings,
         }
     }
 }
 
-/// Request body for package purchase
+/// Request body for `POST /api/admin/tenants`
 #[derive(Debug, Deserialize)]
-pub struct PurchasePackageRequest {
-    pub package_id: i64,
+pub struct CreateTenantRequest {
+    pub slug: String,
+    pub name: String,
+    #[serde(default)]
+    pub domains: Vec<String>,
+    pub subscription_domain: Option<String>,
+    pub settings: Option<serde_json::Value>,
 }
 
-/// Request body for creating a node
+/// Request body for `PUT /api/admin/tenants/:id`; omitted fields are left unchanged
 #[derive(Debug, Deserialize)]
-pub struct CreateNodeRequest {
-    pub name: String,
+pub struct UpdateTenantRequest {
+    pub name: Option<String>,
+    pub domains: Option<Vec<String>>,
+    pub subscription_domain: Option<String>,
+    pub settings: Option<serde_json::Value>,
+    pub is_active: Option<bool>,
+}
+
+/// Request body for `POST /api/node/register`, sent by a fresh node agent
+#[derive(Debug, Deserialize)]
+pub struct NodeRegisterRequest {
+    pub token: String,
+    /// Display name (defaults to the host)
+    pub name: Option<String>,
     pub host: String,
     pub port: i32,
     pub protocol: String,
-    pub config: serde_json::Value,
+    /// Protocol config; admins can complete it before approving
+    #[serde(default)]
+    pub config: Option<serde_json::Value>,
+    /// Detected capabilities (OS, arch, supported protocols, ...)
+    #[serde(default)]
+    pub capabilities: Option<serde_json::Value>,
 }
 
 /// Request body for updating a node
@@ -213,6 +1152,149 @@ pub struct UpdateNodeRequest {
     // New fields for Clash integration
     pub include_in_clash: Option<bool>,
     pub sort_order: Option<i32>,
+    pub traffic_budget: Option<TrafficBytes>,
+    pub traffic_budget_action: Option<String>,
+    /// Replaces the node's tags
+    pub tags: Option<Vec<String>>,
+}
+
+/// Request body for making a node a backup of another (`null` makes it a standalone node)
+#[derive(Debug, Deserialize)]
+pub struct SetNodeBackupRequest {
+    pub backup_of: Option<i64>,
+}
+
+/// Request body for scheduling or rescheduling a node maintenance window
+#[derive(Debug, Deserialize)]
+pub struct NodeMaintenanceRequest {
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub reason: Option<String>,
+}
+
+/// Request body for moving a node to another protocol
+#[derive(Debug, Deserialize)]
+pub struct MigrateNodeProtocolRequest {
+    pub protocol: String,
+    /// Switch a VLESS node to Reality with freshly generated keys
+    #[serde(default)]
+    pub reality: Option<RealityTarget>,
+}
+
+/// The site a Reality node impersonates
+#[derive(Debug, Clone, Deserialize)]
+pub struct RealityTarget {
+    /// `host:port` the node forwards unauthenticated handshakes to
+    pub dest: String,
+    /// SNI values clients may present
+    pub server_names: Vec<String>,
+}
+
+/// Query for node create/update: `validate_only=true` checks the request without saving
+#[derive(Debug, Default, Deserialize)]
+pub struct NodeWriteQuery {
+    #[serde(default)]
+    pub validate_only: bool,
+}
+
+/// Result of a `validate_only` node request that passed validation
+#[derive(Debug, Serialize)]
+pub struct NodeValidationResponse {
+    pub valid: bool,
+}
+
+/// How clients can verify the `X-Config-Signature` header on subscription configs
+#[derive(Debug, Serialize)]
+pub struct ConfigSignatureInfo {
+    pub enabled: bool,
+    pub algorithm: Option<String>,
+    pub header: String,
+    pub encoding: String,
+    /// Base64 Ed25519 public key; HMAC keys are shared out of band
+    pub public_key: Option<String>,
+}
+
+/// A validation problem with one request field, e.g. `config.uuid`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Node agent settings that can change at runtime
+///
+/// Served in the `agent` object of `/api/v1/node/config`; missing fields fall back
+/// to the agent defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeAgentSettings {
+    pub traffic_report_interval: u64,
+    pub heartbeat_interval: u64,
+    pub user_sync_interval: u64,
+    /// `host:port` targets the agent TCP-probes on every heartbeat
+    pub probe_targets: Vec<String>,
+}
+
+impl Default for NodeAgentSettings {
+    fn default() -> Self {
+        Self {
+            traffic_report_interval: 30,
+            heartbeat_interval: 60,
+            user_sync_interval: 60,
+            probe_targets: Vec::new(),
+        }
+    }
+}
+
+/// Bounds for agent intervals in seconds
+pub const AGENT_INTERVAL_RANGE: (u64, u64) = (5, 3600);
+/// Most probe targets a node agent will check
+pub const MAX_AGENT_PROBE_TARGETS: usize = 10;
+
+impl NodeAgentSettings {
+    /// Validate intervals and probe targets, returning the API error message on failure
+    pub fn validate(&self) -> Result<(), String> {
+        let (min, max) = AGENT_INTERVAL_RANGE;
+        for (name, value) in [
+            ("traffic_report_interval", self.traffic_report_interval),
+            ("heartbeat_interval", self.heartbeat_interval),
+            ("user_sync_interval", self.user_sync_interval),
+        ] {
+            if value < min || value > max {
+                return Err(format!("Agent interval must be between 5 and 3600 seconds: {}", name));
+            }
+        }
+
+        if self.probe_targets.len() > MAX_AGENT_PROBE_TARGETS {
+            return Err("Too many probe targets (max 10)".to_string());
+        }
+        for target in &self.probe_targets {
+            let valid = target
+                .rsplit_once(':')
+                .map_or(false, |(host, port)| !host.is_empty() && port.parse::<u16>().map_or(false, |p| p > 0));
+            if !valid {
+                return Err(format!("Invalid probe target: {}", target));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Request body for purging cached subscription configs
+/// scope: `all-subscriptions`, `node:{id}` or `user:{id}`
+#[derive(Debug, Deserialize)]
+pub struct CachePurgeRequest {
+    pub scope: String,
 }
 
 /// Request body for node heartbeat
@@ -220,10 +1302,139 @@ pub struct UpdateNodeRequest {
 pub struct HeartbeatRequest {
     pub node_id: i64,
     pub secret: String,
-    pub status: String,
+    /// Unix time the agent sent the heartbeat, checked against `NODE_AUTH_MAX_CLOCK_SKEW_SECS`
+    pub timestamp: i64,
+    /// Single-use nonce, rejected if replayed
+    pub nonce: String,
+    pub status: ReportedNodeStatus,
     pub cpu_usage: Option<f64>,
     pub memory_usage: Option<f64>,
     pub active_connections: Option<i32>,
+    /// Per-user connection counts (older agents omit this)
+    #[serde(default)]
+    pub user_connections: Vec<UserConnectionReport>,
+    /// Version of the running agent binary (older agents omit this)
+    #[serde(default)]
+    pub agent_version: Option<String>,
+    /// `config_hash` of the config the agent last applied (older agents omit this)
+    #[serde(default)]
+    pub config_hash: Option<String>,
+    /// `config_version` of the config the agent last applied (older agents omit this)
+    #[serde(default)]
+    pub config_version: Option<i64>,
+}
+
+/// Connection count for one user on the reporting node
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserConnectionReport {
+    pub email: String,
+    pub connections: i32,
+    /// Unix time the node last saw a new connection from the user (older agents omit this)
+    #[serde(default)]
+    pub last_handshake: Option<i64>,
+}
+
+/// Most checks accepted in one diagnostics report
+pub const MAX_DIAGNOSTIC_CHECKS: usize = 32;
+/// Longest check name in a diagnostics report
+pub const MAX_DIAGNOSTIC_NAME_LEN: usize = 64;
+/// Check details are truncated to this many characters
+pub const MAX_DIAGNOSTIC_DETAIL_LEN: usize = 1000;
+/// Diagnostics reports kept per node
+pub const NODE_DIAGNOSTICS_KEPT: i64 = 20;
+
+/// Request body for a node agent diagnostics report (`node-agent --diagnose`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct NodeDiagnosticsRequest {
+    pub node_id: i64,
+    pub secret: String,
+    pub timestamp: i64,
+    pub nonce: String,
+    #[serde(default)]
+    pub agent_version: Option<String>,
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+impl NodeDiagnosticsRequest {
+    /// Validate the check list, returning the API error message on failure
+    pub fn validate(&self) -> Result<(), String> {
+        if self.checks.is_empty() {
+            return Err("At least one check is required".to_string());
+        }
+        if self.checks.len() > MAX_DIAGNOSTIC_CHECKS {
+            return Err("Too many checks (max 32)".to_string());
+        }
+        for check in &self.checks {
+            if check.name.is_empty() || check.name.chars().count() > MAX_DIAGNOSTIC_NAME_LEN {
+                return Err("Check names must be 1 to 64 characters".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether no check failed
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.status != DiagnosticStatus::Fail)
+    }
+}
+
+/// One check of a node diagnostics report
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiagnosticCheck {
+    pub name: String,
+    pub status: DiagnosticStatus,
+    #[serde(default)]
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// When a user was last online on a node, kept after their sessions end
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserNodeSeen {
+    pub last_seen: DateTime<Utc>,
+    pub last_handshake: Option<DateTime<Utc>>,
+}
+
+/// A user's sessions on one node
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserNodeSession {
+    pub node_id: i64,
+    /// None once the node has been deleted
+    pub node_name: Option<String>,
+    pub online: bool,
+    pub connections: i32,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub last_handshake: Option<DateTime<Utc>>,
+}
+
+/// A user's online status across nodes, as of the latest heartbeats
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserDevices {
+    pub online: bool,
+    /// Connections across all nodes
+    pub connections: i32,
+    /// Concurrent connection limit of the user's packages (0 = unlimited)
+    pub limit: i32,
+    pub last_seen_node_id: Option<i64>,
+    pub last_seen_at: Option<DateTime<Utc>>,
+    /// Online nodes first, then by most recently seen
+    pub sessions: Vec<UserNodeSession>,
+}
+
+/// Instruction for a node to drop a user's sessions that exceed the package limit
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionDropInstruction {
+    pub user_id: i64,
+    pub email: String,
+    pub connections: i32,
+    pub limit: i32,
 }
 
 /// Request body for traffic reporting
@@ -299,6 +1510,28 @@ pub struct ClashRule {
     pub updated_at: DateTime<Utc>,
 }
 
+/// Versioned copy of the Clash proxy group and rule tables (see `clash_snapshots`)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ClashConfigSnapshot {
+    pub id: i64,
+    pub version: i32,
+    pub author_id: Option<i64>,
+    pub proxy_groups: serde_json::Value,
+    pub rules: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Clash config snapshot as listed, without its contents
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ClashConfigSnapshotSummary {
+    pub version: i32,
+    pub author_id: Option<i64>,
+    pub author_email: Option<String>,
+    pub group_count: i32,
+    pub rule_count: i32,
+    pub created_at: DateTime<Utc>,
+}
+
 /// Request body for creating/updating a Clash proxy
 #[derive(Debug, Deserialize)]
 pub struct ClashProxyRequest {
@@ -338,6 +1571,28 @@ pub struct ClashRuleRequest {
     pub description: Option<String>,
 }
 
+/// Request body for bulk importing Clash rules from a YAML/text block
+#[derive(Debug, Deserialize)]
+pub struct ClashRuleImportRequest {
+    pub content: String,
+    pub dry_run: Option<bool>,
+}
+
+/// Request body for reordering Clash rules; lists every rule ID in evaluation order
+#[derive(Debug, Deserialize)]
+pub struct ClashRuleReorderRequest {
+    pub rule_ids: Vec<i64>,
+}
+
+/// A Clash rule parsed from a rule line, not yet persisted
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParsedClashRule {
+    pub rule_type: String,
+    pub rule_value: Option<String>,
+    pub proxy_group: String,
+    pub no_resolve: bool,
+}
+
 // ============================================================================
 // Clash Access Logs Models
 // ============================================================================
@@ -353,6 +1608,8 @@ pub struct ClashAccessLog {
     pub user_agent: Option<String>,
     pub response_status: String,
     pub created_at: DateTime<Utc>,
+    /// Client country, recorded with GeoIP restriction decisions
+    pub country: Option<String>,
 }
 
 /// Request body for querying access logs (admin)
@@ -377,6 +1634,38 @@ pub struct AccessLogResponse {
     pub ip_address: String,
     pub user_agent: Option<String>,
     pub response_status: String,
+    pub country: Option<String>,
+}
+
+/// Query parameters for a user's own subscription access logs
+#[derive(Debug, Deserialize)]
+pub struct UserAccessLogQuery {
+    pub limit: Option<i64>,
+}
+
+/// A subscription fetch as shown to its owner, with the client parsed from the User-Agent
+#[derive(Debug, Serialize)]
+pub struct UserAccessLogEntry {
+    pub access_timestamp: DateTime<Utc>,
+    pub ip_address: String,
+    pub user_agent: Option<String>,
+    pub client_name: String,
+    pub client_version: Option<String>,
+    pub response_status: String,
+}
+
+impl From<ClashAccessLog> for UserAccessLogEntry {
+    fn from(log: ClashAccessLog) -> Self {
+        let client = crate::utils::parse_user_agent(log.user_agent.as_deref());
+        Self {
+            access_timestamp: log.access_timestamp,
+            ip_address: log.ip_address,
+            user_agent: log.user_agent,
+            client_name: client.name,
+            client_version: client.version,
+            response_status: log.response_status,
+        }
+    }
 }
 
 /// Paginated response for access logs
@@ -389,26 +1678,209 @@ pub struct AccessLogListResponse {
     pub total_pages: i64,
 }
 
+/// Query parameters for per-user subscription fetch counts (admin)
+#[derive(Debug, Deserialize)]
+pub struct FetchCountQuery {
+    /// UTC day, defaults to today
+    pub date: Option<NaiveDate>,
+    pub limit: Option<usize>,
+}
+
+/// A user's subscription fetches on one day, counted in Redis before sampling
+#[derive(Debug, Serialize)]
+pub struct SubscriptionFetchCount {
+    pub user_id: i64,
+    pub user_email: Option<String>,
+    pub fetches: i64,
+}
+
+/// Heaviest subscription fetchers for a day
+#[derive(Debug, Serialize)]
+pub struct SubscriptionFetchCountsResponse {
+    pub date: NaiveDate,
+    /// Users that fetched at least once that day
+    pub users: u64,
+    /// Share of repeat successes written to the access log
+    pub sample_rate: f64,
+    pub top: Vec<SubscriptionFetchCount>,
+}
+
+/// AdminJob model representing a queued background task (result bytes not included)
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct AdminJob {
+    pub id: i64,
+    pub kind: String,
+    pub params: serde_json::Value,
+    pub status: String,
+    pub progress: i16,
+    pub error: Option<String>,
+    pub result_filename: Option<String>,
+    pub result_expires_at: Option<DateTime<Utc>>,
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// OutboxRecord model: one row of `outbox_events`, a side effect awaiting delivery (see `outbox`)
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct OutboxRecord {
+    pub id: i64,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub available_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Request body for enqueueing a background job (admin)
+#[derive(Debug, Deserialize)]
+pub struct CreateJobRequest {
+    pub kind: String,
+    #[serde(default)]
+    pub params: serde_json::Value,
+}
+
+/// Job status as returned to admins, with a signed download link once the result is ready
+#[derive(Debug, Serialize)]
+pub struct AdminJobResponse {
+    #[serde(flatten)]
+    pub job: AdminJob,
+    pub download_url: Option<String>,
+}
+
+/// Signed download link query parameters
+#[derive(Debug, Deserialize)]
+pub struct JobDownloadQuery {
+    pub expires: i64,
+    pub signature: String,
+}
+
+/// Default length of a user traffic report when no range is given
+pub const USER_TRAFFIC_REPORT_DEFAULT_DAYS: i64 = 30;
+/// Longest range a user traffic report may cover
+pub const USER_TRAFFIC_REPORT_MAX_DAYS: i64 = 366;
+/// Number of nodes listed in a report's `top_nodes`
+pub const USER_TRAFFIC_REPORT_TOP_NODES: i64 = 10;
+
+/// Query parameters for a user's per-node traffic report (admin)
+/// Dates are inclusive calendar days in the reporting time zone.
+#[derive(Debug, Default, Deserialize)]
+pub struct UserTrafficReportQuery {
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+}
+
+impl UserTrafficReportQuery {
+    /// Resolve the inclusive date range, defaulting to the last 30 days up to `today`
+    pub fn resolve(&self, today: NaiveDate) -> Result<(NaiveDate, NaiveDate), String> {
+        let end = self.end_date.unwrap_or(today);
+        let start = self
+            .start_date
+            .unwrap_or(end - Duration::days(USER_TRAFFIC_REPORT_DEFAULT_DAYS - 1));
+
+        if start > end {
+            return Err("start_date must not be after end_date".to_string());
+        }
+        if (end - start).num_days() + 1 > USER_TRAFFIC_REPORT_MAX_DAYS {
+            return Err("Date range cannot exceed 366 days".to_string());
+        }
+
+        Ok((start, end))
+    }
+}
+
+/// A user's traffic on one node on one day
+#[derive(Debug, Serialize, FromRow)]
+pub struct UserNodeDailyTraffic {
+    pub date: NaiveDate,
+    pub node_id: i64,
+    pub node_name: String,
+    pub upload: TrafficBytes,
+    pub download: TrafficBytes,
+}
+
+/// A user's traffic on one node over the report range
+#[derive(Debug, Serialize, FromRow)]
+pub struct UserNodeTrafficSummary {
+    pub node_id: i64,
+    pub node_name: String,
+    pub upload: TrafficBytes,
+    pub download: TrafficBytes,
+    pub total: TrafficBytes,
+    pub last_used_at: DateTime<Utc>,
+}
+
+/// Per-user traffic report for support investigations (admin)
+#[derive(Debug, Serialize)]
+pub struct UserTrafficReport {
+    pub user_id: i64,
+    pub email: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    /// Reporting time zone the days are cut in
+    pub time_zone: String,
+    pub total_upload: TrafficBytes,
+    pub total_download: TrafficBytes,
+    /// Most recent traffic on any node, regardless of the range
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub top_nodes: Vec<UserNodeTrafficSummary>,
+    pub daily: Vec<UserNodeDailyTraffic>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use proptest::prelude::*;
 
+    #[test]
+    fn test_user_traffic_report_range() {
+        let today = NaiveDate::from_ymd_opt(2026, 3, 31).unwrap();
+        let date = |m, d| NaiveDate::from_ymd_opt(2026, m, d).unwrap();
+
+        let (start, end) = UserTrafficReportQuery::default().resolve(today).unwrap();
+        assert_eq!((start, end), (date(3, 2), today));
+
+        let query = UserTrafficReportQuery { start_date: Some(date(1, 1)), end_date: Some(date(1, 31)) };
+        assert_eq!(query.resolve(today).unwrap(), (date(1, 1), date(1, 31)));
+
+        let query = UserTrafficReportQuery { start_date: Some(date(2, 1)), end_date: Some(date(1, 1)) };
+        assert!(query.resolve(today).is_err());
+
+        let query = UserTrafficReportQuery { start_date: Some(date(1, 1)), end_date: None };
+        assert_eq!(query.resolve(today).unwrap(), (date(1, 1), today));
+
+        let query = UserTrafficReportQuery {
+            start_date: Some(NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()),
+            end_date: None,
+        };
+        assert_eq!(query.resolve(today).unwrap_err(), "Date range cannot exceed 366 days");
+    }
+
     #[test]
     fn test_user_response_from_user() {
         let user = User {
             id: 1,
             email: "test@example.com".to_string(),
             password_hash: "hashed_password".to_string(),
-            coin_balance: 1000,
-            traffic_quota: 10737418240,
-            traffic_used: 1073741824,
+            coin_balance: Coins::new(1000),
+            traffic_quota: TrafficBytes::new(10737418240),
+            traffic_used: TrafficBytes::new(1073741824),
             referral_code: Some("ABC123".to_string()),
             referred_by: None,
-            status: "active".to_string(),
+            status: UserStatus::Active,
             is_admin: false,
+            language: None,
+            reserved_coins: Coins::ZERO,
+            token_version: 0,
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            tenant_id: 1,
+            is_platform_admin: false,
+            import_ref: None,
+            is_sandbox: false,
         };
 
         let response: UserResponse = user.clone().into();
@@ -423,21 +1895,87 @@ mod tests {
         assert_eq!(response.is_admin, user.is_admin);
     }
 
+    #[test]
+    fn test_node_agent_settings_validate() {
+        let settings: NodeAgentSettings =
+            serde_json::from_str(r#"{"heartbeat_interval": 30, "probe_targets": ["1.1.1.1:443"]}"#).unwrap();
+        assert_eq!(settings.traffic_report_interval, 30);
+        assert!(settings.validate().is_ok());
+
+        let too_fast = NodeAgentSettings { heartbeat_interval: 1, ..settings.clone() };
+        assert_eq!(
+            too_fast.validate().unwrap_err(),
+            "Agent interval must be between 5 and 3600 seconds: heartbeat_interval"
+        );
+
+        for target in ["example.com", ":443", "example.com:0", "example.com:http"] {
+            let bad = NodeAgentSettings { probe_targets: vec![target.to_string()], ..settings.clone() };
+            assert!(bad.validate().is_err(), "{} should be rejected", target);
+        }
+
+        let too_many = NodeAgentSettings {
+            probe_targets: vec!["1.1.1.1:443".to_string(); MAX_AGENT_PROBE_TARGETS + 1],
+            ..settings
+        };
+        assert!(too_many.validate().is_err());
+    }
+
+    #[test]
+    fn test_node_diagnostics_request_validate() {
+        let request: NodeDiagnosticsRequest = serde_json::from_str(
+            r#"{"node_id": 1, "secret": "s", "timestamp": 0, "nonce": "n", "checks": [
+                {"name": "clock_skew", "status": "warn", "detail": "40s ahead"},
+                {"name": "core_binary", "status": "pass"}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(request.validate().is_ok());
+        assert!(request.passed());
+
+        let mut failed = request.clone();
+        failed.checks[1].status = DiagnosticStatus::Fail;
+        assert!(!failed.passed());
+
+        let unnamed = NodeDiagnosticsRequest {
+            checks: vec![DiagnosticCheck { name: String::new(), ..request.checks[0].clone() }],
+            ..request.clone()
+        };
+        assert!(unnamed.validate().is_err());
+
+        let too_many = NodeDiagnosticsRequest {
+            checks: vec![request.checks[0].clone(); MAX_DIAGNOSTIC_CHECKS + 1],
+            ..request.clone()
+        };
+        assert!(too_many.validate().is_err());
+
+        let empty = NodeDiagnosticsRequest { checks: Vec::new(), ..request };
+        assert!(empty.validate().is_err());
+
+        assert!(serde_json::from_str::<DiagnosticCheck>(r#"{"name": "x", "status": "broken"}"#).is_err());
+    }
+
     #[test]
     fn test_user_serialization_skips_password() {
         let user = User {
             id: 1,
             email: "test@example.com".to_string(),
             password_hash: "hashed_password".to_string(),
-            coin_balance: 1000,
-            traffic_quota: 10737418240,
-            traffic_used: 1073741824,
+            coin_balance: Coins::new(1000),
+            traffic_quota: TrafficBytes::new(10737418240),
+            traffic_used: TrafficBytes::new(1073741824),
             referral_code: Some("ABC123".to_string()),
             referred_by: None,
-            status: "active".to_string(),
+            status: UserStatus::Active,
             is_admin: false,
+            language: None,
+            reserved_coins: Coins::ZERO,
+            token_version: 0,
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            tenant_id: 1,
+            is_platform_admin: false,
+            import_ref: None,
+            is_sandbox: false,
         };
 
         let json = serde_json::to_string(&user).unwrap();
@@ -448,23 +1986,17 @@ mod tests {
     #[test]
     fn test_node_serialization_skips_secret() {
         let node = Node {
-            id: 1,
             name: "Test Node".to_string(),
             host: "example.com".to_string(),
-            port: 443,
             protocol: "vless".to_string(),
             secret: "secret_key".to_string(),
             config: serde_json::json!({"key": "value"}),
-            status: "online".to_string(),
-            max_users: 1000,
             current_users: 50,
             total_upload: 1073741824,
             total_download: 2147483648,
             last_heartbeat: Some(Utc::now()),
-            created_at: Utc::now(),
-            updated_at: Utc::now(),
             include_in_clash: false,
-            sort_order: 0,
+            ..Node::test_fixture()
         };
 
         let json = serde_json::to_string(&node).unwrap();
@@ -516,23 +2048,16 @@ mod tests {
             };
 
             let node = Node {
-                id: 1,
                 name: format!("Test {} Node", protocol),
                 host: "example.com".to_string(),
                 port,
                 protocol: protocol.to_string(),
                 secret: "test_secret".to_string(),
                 config: config.clone(),
-                status: "online".to_string(),
                 max_users,
-                current_users: 0,
-                total_upload: 0,
-                total_download: 0,
                 last_heartbeat: Some(Utc::now()),
-                created_at: Utc::now(),
-                updated_at: Utc::now(),
                 include_in_clash: false,
-                sort_order: 0,
+                ..Node::test_fixture()
             };
 
             // Serialize to JSON (simulating database storage)
@@ -645,18 +2170,14 @@ mod tests {
                 host: "example.com".to_string(),
                 port,
                 protocol: "vless".to_string(),
-                secret: "secret".to_string(),
                 config: config.clone(),
-                status: "online".to_string(),
                 max_users,
                 current_users,
                 total_upload,
                 total_download,
                 last_heartbeat: Some(Utc::now()),
-                created_at: Utc::now(),
-                updated_at: Utc::now(),
                 include_in_clash: false,
-                sort_order: 0,
+                ..Node::test_fixture()
             };
 
             // Serialize the entire node
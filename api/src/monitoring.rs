@@ -0,0 +1,576 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+use crate::amounts::TrafficBytes;
+use crate::cache::{spawn_periodic_job, PurgeScope, RedisCache};
+use crate::config::Config;
+use crate::db;
+use crate::i18n::{self, Lang};
+use crate::models::{
+    HeartbeatRequest, Node, NodeMetric, SessionDropInstruction, UserConnectionReport, UserDevices, UserNodeSeen,
+    UserNodeSession,
+};
+use crate::outbox::{self, OutboxEvent};
+use crate::status::NodeStatus;
+
+/// Alert type: CPU usage stayed above the threshold for the whole window
+pub const ALERT_HIGH_CPU: &str = "high_cpu";
+/// Alert type: active connections exceeded the node's max_users
+pub const ALERT_CONNECTIONS_EXCEEDED: &str = "connections_exceeded";
+/// Alert type: monthly traffic reached the warning share of the node's budget
+pub const ALERT_TRAFFIC_BUDGET_WARNING: &str = "traffic_budget_warning";
+/// Alert type: monthly traffic used up the node's budget
+pub const ALERT_TRAFFIC_BUDGET_EXCEEDED: &str = "traffic_budget_exceeded";
+
+/// Budget action: only alert admins
+pub const BUDGET_ACTION_ALERT: &str = "alert";
+/// Budget action: put the node into maintenance (removed from configs, kept by heartbeats)
+pub const BUDGET_ACTION_MAINTENANCE: &str = "maintenance";
+/// Budget action: stop including the node in Clash configs
+pub const BUDGET_ACTION_EXCLUDE_FROM_CLASH: &str = "exclude_from_clash";
+/// All valid `nodes.traffic_budget_action` values
+pub const BUDGET_ACTIONS: [&str; 3] = [
+    BUDGET_ACTION_ALERT,
+    BUDGET_ACTION_MAINTENANCE,
+    BUDGET_ACTION_EXCLUDE_FROM_CLASH,
+];
+
+const GB: f64 = (1024 * 1024 * 1024) as f64;
+
+/// A node without a heartbeat for this long is considered stale (3 default heartbeats)
+pub const STALE_HEARTBEAT_SECS: i64 = 180;
+
+/// How long a node's per-user connection report stays valid (3 default heartbeats)
+pub const CONNECTIONS_TTL_SECS: u64 = 180;
+
+/// How long to remember where a user was last online (30 days)
+pub const SESSIONS_TTL_SECS: u64 = 30 * 24 * 3600;
+
+/// Node health thresholds evaluated on every heartbeat
+#[derive(Debug, Clone, Copy)]
+pub struct AlertThresholds {
+    pub cpu_percent: f64,
+    pub cpu_window: Duration,
+    /// Share of the monthly traffic budget (%) that triggers a warning
+    pub traffic_budget_percent: f64,
+    /// Language used for alert messages
+    pub language: Lang,
+}
+
+impl AlertThresholds {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            cpu_percent: config.node_cpu_alert_threshold,
+            cpu_window: Duration::seconds(config.node_cpu_alert_window_secs),
+            traffic_budget_percent: config.node_traffic_budget_alert_percent,
+            language: config.default_language,
+        }
+    }
+}
+
+/// Check whether CPU usage has been above `threshold` for the entire `window`
+///
+/// `samples` must be ordered oldest first. The window counts as covered only if a
+/// sample exists at or before its start; every sample from that one onwards must breach.
+pub fn cpu_sustained_above(
+    samples: &[NodeMetric],
+    threshold: f64,
+    window: Duration,
+    now: DateTime<Utc>,
+) -> bool {
+    let window_start = now - window;
+
+    let Some(first) = samples.iter().rposition(|m| m.recorded_at <= window_start) else {
+        return false;
+    };
+
+    samples[first..]
+        .iter()
+        .all(|m| m.cpu_usage.map_or(false, |cpu| cpu > threshold))
+}
+
+/// Check whether reported connections exceed the node's capacity
+pub fn connections_exceeded(active_connections: Option<i32>, max_users: i32) -> bool {
+    active_connections.map_or(false, |c| c > max_users)
+}
+
+/// Share of the monthly budget used, in percent (None when the budget is unlimited)
+pub fn budget_usage_percent(used: TrafficBytes, budget: TrafficBytes) -> Option<f64> {
+    budget
+        .is_positive()
+        .then(|| used.get() as f64 / budget.get() as f64 * 100.0)
+}
+
+/// Persist a heartbeat's metrics and open/resolve threshold alerts
+pub async fn record_heartbeat_metrics(
+    pool: &PgPool,
+    cache: &RedisCache,
+    node: &Node,
+    heartbeat: &HeartbeatRequest,
+    thresholds: AlertThresholds,
+) -> Result<()> {
+    db::create_node_metric(
+        pool,
+        node.id,
+        heartbeat.cpu_usage,
+        heartbeat.memory_usage,
+        heartbeat.active_connections,
+    )
+    .await?;
+
+    let now = Utc::now();
+
+    // Look back twice the window so the sample covering the window start is included
+    let samples = db::list_node_metrics_since(pool, node.id, now - thresholds.cpu_window * 2).await?;
+    let high_cpu = cpu_sustained_above(&samples, thresholds.cpu_percent, thresholds.cpu_window, now);
+    update_alert(
+        pool,
+        cache,
+        node,
+        ALERT_HIGH_CPU,
+        high_cpu,
+        i18n::render(
+            "alert.high_cpu",
+            thresholds.language,
+            &[
+                ("threshold", format!("{:.0}", thresholds.cpu_percent)),
+                ("minutes", thresholds.cpu_window.num_minutes().to_string()),
+            ],
+        ),
+        heartbeat.cpu_usage,
+        Some(thresholds.cpu_percent),
+    )
+    .await?;
+
+    let overloaded = connections_exceeded(heartbeat.active_connections, node.max_users);
+    update_alert(
+        pool,
+        cache,
+        node,
+        ALERT_CONNECTIONS_EXCEEDED,
+        overloaded,
+        i18n::render(
+            "alert.connections_exceeded",
+            thresholds.language,
+            &[
+                ("connections", heartbeat.active_connections.unwrap_or(0).to_string()),
+                ("max_users", node.max_users.to_string()),
+            ],
+        ),
+        heartbeat.active_connections.map(f64::from),
+        Some(f64::from(node.max_users)),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Evaluate a node's monthly traffic against its budget after new traffic was recorded
+///
+/// Opens a warning at `traffic_budget_percent` and an exceeded alert at 100%; both resolve
+/// once usage drops below (e.g. in a new month). When the budget is used up the node's
+/// `traffic_budget_action` is applied once; re-enabling the node is left to admins.
+pub async fn check_traffic_budget(
+    pool: &PgPool,
+    cache: &RedisCache,
+    node: &Node,
+    month_used: TrafficBytes,
+    thresholds: AlertThresholds,
+) -> Result<()> {
+    let percent = budget_usage_percent(month_used, node.traffic_budget);
+    let params = [
+        ("used_gb", format!("{:.2}", month_used.get() as f64 / GB)),
+        ("budget_gb", format!("{:.2}", node.traffic_budget.get() as f64 / GB)),
+        ("percent", format!("{:.0}", thresholds.traffic_budget_percent)),
+    ];
+
+    update_alert(
+        pool,
+        cache,
+        node,
+        ALERT_TRAFFIC_BUDGET_WARNING,
+        percent.map_or(false, |p| p >= thresholds.traffic_budget_percent),
+        i18n::render("alert.traffic_budget_warning", thresholds.language, &params),
+        percent,
+        Some(thresholds.traffic_budget_percent),
+    )
+    .await?;
+
+    let exceeded = percent.map_or(false, |p| p >= 100.0);
+    update_alert(
+        pool,
+        cache,
+        node,
+        ALERT_TRAFFIC_BUDGET_EXCEEDED,
+        exceeded,
+        i18n::render("alert.traffic_budget_exceeded", thresholds.language, &params),
+        percent,
+        Some(100.0),
+    )
+    .await?;
+
+    if exceeded {
+        apply_budget_action(pool, cache, node).await?;
+    }
+
+    Ok(())
+}
+
+/// Take a node out of rotation according to its `traffic_budget_action`
+async fn apply_budget_action(pool: &PgPool, cache: &RedisCache, node: &Node) -> Result<()> {
+    let (status, include_in_clash) = match node.traffic_budget_action.as_str() {
+        BUDGET_ACTION_MAINTENANCE if node.status != NodeStatus::Maintenance => (Some(NodeStatus::Maintenance), None),
+        BUDGET_ACTION_EXCLUDE_FROM_CLASH if node.include_in_clash => (None, Some(false)),
+        _ => return Ok(()),
+    };
+
+    let mut tx = pool.begin().await?;
+    db::update_node(&mut *tx, node.id, None, None, None, None, None, status, include_in_clash, None, None).await?;
+    let outbox_ids = outbox::enqueue(
+        &mut *tx,
+        &[
+            OutboxEvent::InvalidateNodeGroups {
+                tenant_id: node.tenant_id,
+            },
+            OutboxEvent::PurgeSubscriptions(PurgeScope::Node(node.id)),
+        ],
+    )
+    .await?;
+    tx.commit().await?;
+    tracing::warn!(
+        "Node {} ({}) used up its monthly traffic budget, applied action: {}",
+        node.id,
+        node.name,
+        node.traffic_budget_action
+    );
+
+    outbox::deliver_now(pool, cache, &outbox_ids).await;
+
+    Ok(())
+}
+
+/// Open an alert when the condition is breached, resolve it when it clears
+#[allow(clippy::too_many_arguments)]
+async fn update_alert(
+    pool: &PgPool,
+    cache: &RedisCache,
+    node: &Node,
+    alert_type: &str,
+    breached: bool,
+    message: String,
+    value: Option<f64>,
+    threshold: Option<f64>,
+) -> Result<()> {
+    if breached {
+        if let Some(alert) =
+            db::open_node_alert(pool, node.id, alert_type, &message, value, threshold).await?
+        {
+            tracing::warn!("Node {} ({}) alert: {}", node.id, node.name, message);
+            if let Err(e) = cache.publish_admin_alert(&alert).await {
+                tracing::warn!("Failed to publish node alert: {}", e);
+            }
+        }
+    } else if let Some(alert) = db::resolve_node_alert(pool, node.id, alert_type).await? {
+        tracing::info!("Node {} ({}) alert resolved: {}", node.id, node.name, alert.alert_type);
+        if let Err(e) = cache.publish_admin_alert(&alert).await {
+            tracing::warn!("Failed to publish node alert resolution: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Pick the nodes that must drop a user's sessions to bring them back under `limit`
+///
+/// Nodes are released newest first (highest id) until the remaining total fits, so
+/// every node evaluating the same counts reaches the same decision. A limit of 0
+/// means unlimited.
+pub fn nodes_to_disconnect(by_node: &HashMap<i64, i32>, limit: i32) -> Vec<i64> {
+    let mut total: i32 = by_node.values().sum();
+    if limit <= 0 || total <= limit {
+        return Vec::new();
+    }
+
+    let mut nodes: Vec<(i64, i32)> = by_node.iter().map(|(id, count)| (*id, *count)).collect();
+    nodes.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let mut selected = Vec::new();
+    for (node_id, count) in nodes {
+        if total <= limit {
+            break;
+        }
+        total -= count;
+        selected.push(node_id);
+    }
+
+    selected
+}
+
+/// Record a node's per-user connection counts and decide which sessions it must drop
+///
+/// Counts are aggregated across nodes in Redis; users over their package's
+/// `max_connections` get a drop instruction on the nodes picked by [`nodes_to_disconnect`].
+pub async fn enforce_connection_limits(
+    pool: &PgPool,
+    cache: &RedisCache,
+    node_id: i64,
+    reports: &[UserConnectionReport],
+) -> Result<Vec<SessionDropInstruction>> {
+    let emails: Vec<String> = reports.iter().map(|r| r.email.clone()).collect();
+    let users = if emails.is_empty() {
+        Vec::new()
+    } else {
+        db::get_connection_limits_by_emails(pool, &emails).await?
+    };
+
+    let counts: HashMap<i64, i32> = users
+        .iter()
+        .filter_map(|(user_id, email, _)| {
+            reports
+                .iter()
+                .find(|r| &r.email == email)
+                .map(|r| (*user_id, r.connections))
+        })
+        .collect();
+
+    // Always record, so users who disconnected are cleared from this node
+    cache.record_node_connections(node_id, &counts, CONNECTIONS_TTL_SECS).await?;
+
+    // Remember where online users were seen, for the devices view
+    let now = Utc::now();
+    let seen: HashMap<i64, UserNodeSeen> = users
+        .iter()
+        .filter_map(|(user_id, email, _)| {
+            let report = reports.iter().find(|r| &r.email == email && r.connections > 0)?;
+            let seen = UserNodeSeen {
+                last_seen: now,
+                last_handshake: report
+                    .last_handshake
+                    .and_then(|secs| Utc.timestamp_opt(secs, 0).single()),
+            };
+            Some((*user_id, seen))
+        })
+        .collect();
+    if let Err(e) = cache.record_user_seen(node_id, &seen, SESSIONS_TTL_SECS).await {
+        tracing::warn!("Failed to record user sessions for node {}: {}", node_id, e);
+    }
+
+    let mut drops = Vec::new();
+    for (user_id, email, limit) in users {
+        if limit <= 0 || !counts.contains_key(&user_id) {
+            continue;
+        }
+
+        let by_node = cache.get_user_connections(user_id).await?;
+        if nodes_to_disconnect(&by_node, limit).contains(&node_id) {
+            drops.push(SessionDropInstruction {
+                user_id,
+                email,
+                connections: by_node.values().sum(),
+                limit,
+            });
+        }
+    }
+
+    Ok(drops)
+}
+
+/// Combine a user's live connections and last-seen records into per-node sessions
+///
+/// `live` holds the current connection counts by node and `seen` when the user was
+/// last online on each node; a node in either one gets a session.
+pub fn build_user_devices(
+    live: &HashMap<i64, i32>,
+    seen: &HashMap<i64, UserNodeSeen>,
+    node_names: &HashMap<i64, String>,
+    limit: i32,
+) -> UserDevices {
+    let mut node_ids: Vec<i64> = live.keys().chain(seen.keys()).copied().collect();
+    node_ids.sort_unstable();
+    node_ids.dedup();
+
+    let mut sessions: Vec<UserNodeSession> = node_ids
+        .into_iter()
+        .map(|node_id| {
+            let connections = live.get(&node_id).copied().unwrap_or(0);
+            let seen = seen.get(&node_id);
+            UserNodeSession {
+                node_id,
+                node_name: node_names.get(&node_id).cloned(),
+                online: connections > 0,
+                connections,
+                last_seen: seen.map(|s| s.last_seen),
+                last_handshake: seen.and_then(|s| s.last_handshake),
+            }
+        })
+        .collect();
+    sessions.sort_by(|a, b| b.online.cmp(&a.online).then(b.last_seen.cmp(&a.last_seen)));
+
+    let last_seen = sessions.iter().filter_map(|s| Some((s.last_seen?, s.node_id))).max();
+
+    UserDevices {
+        online: sessions.iter().any(|s| s.online),
+        connections: sessions.iter().map(|s| s.connections).sum(),
+        limit,
+        last_seen_node_id: last_seen.map(|(_, node_id)| node_id),
+        last_seen_at: last_seen.map(|(at, _)| at),
+        sessions,
+    }
+}
+
+/// Get a user's online status across nodes and where they were last seen
+pub async fn user_devices(pool: &PgPool, cache: &RedisCache, user_id: i64) -> Result<UserDevices> {
+    let live = cache.get_user_connections(user_id).await?;
+    let seen = cache.get_user_seen(user_id).await?;
+    let limit = db::get_user_connection_limit(pool, user_id).await?;
+
+    let node_ids: Vec<i64> = live.keys().chain(seen.keys()).copied().collect();
+    let node_names = if node_ids.is_empty() {
+        HashMap::new()
+    } else {
+        db::get_node_names(pool, &node_ids).await?
+    };
+
+    Ok(build_user_devices(&live, &seen, &node_names, limit))
+}
+
+/// Background task pruning node metrics older than the retention period
+pub fn start_metrics_retention_task(
+    db_pool: PgPool,
+    cache: RedisCache,
+    retention_days: i64,
+) -> tokio::task::JoinHandle<()> {
+    spawn_periodic_job(
+        cache,
+        "node_metrics_retention",
+        std::time::Duration::from_secs(3600),
+        move || {
+            let db_pool = db_pool.clone();
+            async move {
+                let cutoff = Utc::now() - Duration::days(retention_days);
+                match db::delete_node_metrics_before(&db_pool, cutoff).await {
+                    Ok(count) => tracing::debug!("Pruned {} node metric samples", count),
+                    Err(e) => tracing::error!("Failed to prune node metrics: {}", e),
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(minutes_ago: i64, cpu: f64, now: DateTime<Utc>) -> NodeMetric {
+        NodeMetric {
+            id: 0,
+            node_id: 1,
+            cpu_usage: Some(cpu),
+            memory_usage: None,
+            active_connections: None,
+            recorded_at: now - Duration::minutes(minutes_ago),
+        }
+    }
+
+    #[test]
+    fn test_cpu_sustained_above_full_window() {
+        let now = Utc::now();
+        let samples: Vec<NodeMetric> = (0..=6).rev().map(|m| sample(m, 95.0, now)).collect();
+
+        assert!(cpu_sustained_above(&samples, 90.0, Duration::minutes(5), now));
+    }
+
+    #[test]
+    fn test_cpu_sustained_above_requires_coverage() {
+        let now = Utc::now();
+        // Only 3 minutes of history: not enough to prove 5 minutes of high CPU
+        let samples: Vec<NodeMetric> = (0..=3).rev().map(|m| sample(m, 95.0, now)).collect();
+
+        assert!(!cpu_sustained_above(&samples, 90.0, Duration::minutes(5), now));
+        assert!(!cpu_sustained_above(&[], 90.0, Duration::minutes(5), now));
+    }
+
+    #[test]
+    fn test_cpu_sustained_above_single_dip() {
+        let now = Utc::now();
+        let mut samples: Vec<NodeMetric> = (0..=6).rev().map(|m| sample(m, 95.0, now)).collect();
+        samples[4].cpu_usage = Some(50.0);
+
+        assert!(!cpu_sustained_above(&samples, 90.0, Duration::minutes(5), now));
+    }
+
+    #[test]
+    fn test_budget_usage_percent() {
+        let bytes = TrafficBytes::new;
+
+        assert_eq!(budget_usage_percent(bytes(50), bytes(200)), Some(25.0));
+        assert_eq!(budget_usage_percent(bytes(300), bytes(200)), Some(150.0));
+        assert_eq!(budget_usage_percent(bytes(300), TrafficBytes::ZERO), None);
+    }
+
+    #[test]
+    fn test_connections_exceeded() {
+        assert!(connections_exceeded(Some(1001), 1000));
+        assert!(!connections_exceeded(Some(1000), 1000));
+        assert!(!connections_exceeded(None, 1000));
+    }
+
+    #[test]
+    fn test_nodes_to_disconnect_within_limit() {
+        let by_node = HashMap::from([(1, 2), (2, 1)]);
+
+        assert!(nodes_to_disconnect(&by_node, 3).is_empty());
+        assert!(nodes_to_disconnect(&by_node, 0).is_empty());
+        assert!(nodes_to_disconnect(&HashMap::new(), 1).is_empty());
+    }
+
+    #[test]
+    fn test_nodes_to_disconnect_newest_first() {
+        let by_node = HashMap::from([(1, 2), (2, 1), (3, 2)]);
+
+        // 5 connections, limit 3: dropping node 3 is enough
+        assert_eq!(nodes_to_disconnect(&by_node, 3), vec![3]);
+        // limit 2: nodes 3 and 2 must both drop
+        assert_eq!(nodes_to_disconnect(&by_node, 2), vec![3, 2]);
+        // limit 1: even the oldest node is over the limit on its own
+        assert_eq!(nodes_to_disconnect(&by_node, 1), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_build_user_devices() {
+        let now = Utc::now();
+        let seen_at = |minutes_ago: i64| UserNodeSeen {
+            last_seen: now - Duration::minutes(minutes_ago),
+            last_handshake: Some(now - Duration::minutes(minutes_ago + 10)),
+        };
+        let live = HashMap::from([(2, 1)]);
+        let seen = HashMap::from([(1, seen_at(30)), (2, seen_at(1)), (3, seen_at(5))]);
+        let names = HashMap::from([(1, "hk-1".to_string()), (2, "jp-1".to_string())]);
+
+        let devices = build_user_devices(&live, &seen, &names, 2);
+        assert!(devices.online);
+        assert_eq!(devices.connections, 1);
+        assert_eq!(devices.limit, 2);
+        assert_eq!(devices.last_seen_node_id, Some(2));
+        assert_eq!(devices.last_seen_at, Some(now - Duration::minutes(1)));
+
+        let order: Vec<(i64, bool)> = devices.sessions.iter().map(|s| (s.node_id, s.online)).collect();
+        assert_eq!(order, vec![(2, true), (3, false), (1, false)]);
+        assert_eq!(devices.sessions[0].node_name.as_deref(), Some("jp-1"));
+        // Node 3 has been deleted since
+        assert_eq!(devices.sessions[1].node_name, None);
+        assert_eq!(devices.sessions[2].last_handshake, Some(now - Duration::minutes(40)));
+    }
+
+    #[test]
+    fn test_build_user_devices_offline() {
+        let devices = build_user_devices(&HashMap::new(), &HashMap::new(), &HashMap::new(), 0);
+
+        assert!(!devices.online);
+        assert_eq!(devices.connections, 0);
+        assert_eq!(devices.last_seen_node_id, None);
+        assert!(devices.sessions.is_empty());
+    }
+}
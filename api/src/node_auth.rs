@@ -0,0 +1,190 @@
+use anyhow::Result;
+use sqlx::PgPool;
+
+use crate::cache::{PurgeScope, RedisCache};
+use crate::config::Config;
+use crate::db;
+use crate::i18n;
+
+/// Alert type: a node was suspended after repeated authentication failures
+pub const ALERT_AUTH_LOCKOUT: &str = "auth_lockout";
+
+const NONCE_MIN_LEN: usize = 16;
+const NONCE_MAX_LEN: usize = 64;
+
+/// Why a node agent request was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeAuthRejection {
+    /// Nonce missing or malformed
+    InvalidNonce,
+    /// Timestamp outside the clock skew window
+    StaleRequest,
+    /// Unknown node or wrong secret
+    InvalidCredentials,
+    /// Nonce already used
+    Replayed,
+    /// Node is locked out
+    Suspended,
+}
+
+impl NodeAuthRejection {
+    /// API error message (see `i18n::ERROR_CATALOG`)
+    pub fn message(self) -> &'static str {
+        match self {
+            NodeAuthRejection::InvalidNonce => "nonce must be 16-64 letters, digits, '-' or '_'",
+            NodeAuthRejection::StaleRequest => "Request timestamp is outside the allowed window",
+            NodeAuthRejection::InvalidCredentials => "Invalid node credentials",
+            NodeAuthRejection::Replayed => "Request nonce has already been used",
+            NodeAuthRejection::Suspended => "Node is suspended",
+        }
+    }
+
+    /// Whether the rejection counts towards the lockout threshold
+    ///
+    /// Clock drift is not counted so a node with a bad clock is not suspended.
+    pub fn counts_as_failure(self) -> bool {
+        matches!(self, NodeAuthRejection::InvalidCredentials | NodeAuthRejection::Replayed)
+    }
+}
+
+/// Whether a nonce is well formed
+pub fn is_valid_nonce(nonce: &str) -> bool {
+    (NONCE_MIN_LEN..=NONCE_MAX_LEN).contains(&nonce.len())
+        && nonce
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Whether a request timestamp is within `max_skew_secs` of `now` (either direction)
+pub fn is_fresh(timestamp: i64, now: i64, max_skew_secs: i64) -> bool {
+    now.checked_sub(timestamp)
+        .map_or(false, |age| age.abs() <= max_skew_secs)
+}
+
+/// How long a nonce must be remembered
+///
+/// A timestamp accepted at the start of the window stays acceptable for twice the
+/// skew, so a replay is caught for as long as it could pass the freshness check.
+pub fn nonce_ttl_secs(max_skew_secs: i64) -> u64 {
+    (max_skew_secs.max(1) as u64) * 2
+}
+
+/// Whether a failure count reaches the lockout threshold (0 disables lockout)
+pub fn should_lock_out(failures: u32, max_failures: u32) -> bool {
+    max_failures > 0 && failures >= max_failures
+}
+
+/// Count a failed authentication and suspend the node once the threshold is reached
+///
+/// Admins are notified through a node alert when the node is suspended.
+pub async fn record_failure(
+    pool: &PgPool,
+    cache: &RedisCache,
+    config: &Config,
+    node_id: i64,
+    rejection: NodeAuthRejection,
+) -> Result<()> {
+    if !rejection.counts_as_failure() {
+        return Ok(());
+    }
+
+    let policy = &config.node_auth;
+    let failures = cache
+        .record_node_auth_failure(node_id, policy.failure_window_secs)
+        .await?;
+    tracing::warn!(
+        "Node {} authentication failed ({}), {} failures in window",
+        node_id,
+        rejection.message(),
+        failures
+    );
+
+    if !should_lock_out(failures, policy.max_failures) {
+        return Ok(());
+    }
+
+    let Some(node) = db::suspend_node(pool, node_id).await? else {
+        return Ok(());
+    };
+    tracing::warn!("Node {} ({}) suspended after {} authentication failures", node.id, node.name, failures);
+
+    let message = i18n::render(
+        "alert.auth_lockout",
+        config.default_language,
+        &[
+            ("failures", failures.to_string()),
+            ("minutes", (policy.failure_window_secs / 60).to_string()),
+        ],
+    );
+    if let Some(alert) = db::open_node_alert(
+        pool,
+        node.id,
+        ALERT_AUTH_LOCKOUT,
+        &message,
+        Some(failures as f64),
+        Some(policy.max_failures as f64),
+    )
+    .await?
+    {
+        if let Err(e) = cache.publish_admin_alert(&alert).await {
+            tracing::warn!("Failed to publish node alert: {}", e);
+        }
+    }
+
+    if let Err(e) = cache.invalidate_node_groups(node.tenant_id).await {
+        tracing::warn!("Failed to invalidate node group cache: {}", e);
+    }
+    if let Err(e) = cache.purge_subscription_configs(PurgeScope::Node(node.id)).await {
+        tracing::warn!("Failed to purge subscription cache for node {}: {}", node.id, e);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_nonce() {
+        assert!(is_valid_nonce("0123456789abcdef"));
+        assert!(is_valid_nonce("18c2f0a9b3e4d5f6-1a2b-3"));
+        assert!(!is_valid_nonce("short"));
+        assert!(!is_valid_nonce(&"a".repeat(NONCE_MAX_LEN + 1)));
+        assert!(!is_valid_nonce("0123456789abcdef/../"));
+    }
+
+    #[test]
+    fn test_is_fresh_allows_skew_both_ways() {
+        let now = 1_700_000_000;
+        assert!(is_fresh(now, now, 300));
+        assert!(is_fresh(now - 300, now, 300));
+        assert!(is_fresh(now + 300, now, 300));
+        assert!(!is_fresh(now - 301, now, 300));
+        assert!(!is_fresh(now + 301, now, 300));
+        assert!(!is_fresh(i64::MIN, now, 300));
+    }
+
+    #[test]
+    fn test_nonce_ttl_covers_window() {
+        assert_eq!(nonce_ttl_secs(300), 600);
+        assert_eq!(nonce_ttl_secs(0), 2);
+    }
+
+    #[test]
+    fn test_lockout_threshold() {
+        assert!(!should_lock_out(9, 10));
+        assert!(should_lock_out(10, 10));
+        assert!(should_lock_out(11, 10));
+        assert!(!should_lock_out(100, 0));
+    }
+
+    #[test]
+    fn test_only_credential_failures_count() {
+        assert!(NodeAuthRejection::InvalidCredentials.counts_as_failure());
+        assert!(NodeAuthRejection::Replayed.counts_as_failure());
+        assert!(!NodeAuthRejection::StaleRequest.counts_as_failure());
+        assert!(!NodeAuthRejection::InvalidNonce.counts_as_failure());
+        assert!(!NodeAuthRejection::Suspended.counts_as_failure());
+    }
+}
@@ -0,0 +1,597 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::models::{FieldError, RealityTarget};
+
+/// Protocols a node can serve
+pub const PROTOCOLS: &[&str] = &["shadowsocks", "vmess", "trojan", "hysteria2", "vless"];
+
+/// Ciphers accepted for Shadowsocks nodes (AEAD and Shadowsocks 2022)
+pub const SHADOWSOCKS_METHODS: &[&str] = &[
+    "aes-128-gcm",
+    "aes-192-gcm",
+    "aes-256-gcm",
+    "chacha20-ietf-poly1305",
+    "xchacha20-ietf-poly1305",
+    "2022-blake3-aes-128-gcm",
+    "2022-blake3-aes-256-gcm",
+    "2022-blake3-chacha20-poly1305",
+];
+
+/// VMess `security` values
+pub const VMESS_SECURITIES: &[&str] = &["auto", "none", "zero", "aes-128-gcm", "chacha20-poly1305"];
+
+/// Transport `network` values for VMess and VLESS
+pub const NETWORKS: &[&str] = &["tcp", "ws", "http", "h2", "grpc"];
+
+/// VLESS `flow` values
+pub const VLESS_FLOWS: &[&str] = &["xtls-rprx-vision"];
+
+/// Hysteria2 `obfs` values
+pub const HYSTERIA2_OBFS: &[&str] = &["salamander"];
+
+/// Longest Reality short ID, in hex characters
+const REALITY_SHORT_ID_MAX_LEN: usize = 16;
+
+/// Config fields each protocol reads; any other key is metadata (country, traffic ratio, ...)
+const PROTOCOL_FIELDS: &[(&str, &[&str])] = &[
+    ("shadowsocks", &["method", "password"]),
+    ("vmess", &["uuid", "alter_id", "security", "network"]),
+    ("trojan", &["password", "sni", "skip_cert_verify"]),
+    ("hysteria2", &["password", "obfs", "obfs_password", "sni", "skip_cert_verify", "ports"]),
+    ("vless", &["uuid", "flow", "network", "client_fingerprint", "reality"]),
+];
+
+/// Cipher given to nodes migrated to Shadowsocks; it accepts any password, so the node
+/// secret keeps working as one
+const DEFAULT_SHADOWSOCKS_METHOD: &str = "aes-256-gcm";
+
+/// Check a node config against what the Clash generator for `protocol` needs
+///
+/// `secret` is the node secret that fills in a missing `password`/`uuid` (see
+/// `clash::merge_node_config`); pass `None` when no node exists yet. Returns every
+/// problem found, keyed by the path of the offending field. Unknown protocols are left
+/// to the caller, and unknown keys are allowed so admins can keep metadata in the config.
+pub fn validate(protocol: &str, config: &Value, secret: Option<&str>) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    let Some(fields) = config.as_object() else {
+        errors.push(FieldError::new("config", "Must be a JSON object"));
+        return errors;
+    };
+    let mut check = Checker {
+        fields,
+        prefix: "config",
+        errors: &mut errors,
+    };
+
+    match protocol {
+        "shadowsocks" => {
+            check.one_of("method", SHADOWSOCKS_METHODS, true);
+            check.non_empty_string("password", false);
+        }
+        "vmess" => {
+            check.uuid("uuid", secret);
+            check.u16("alter_id");
+            check.one_of("security", VMESS_SECURITIES, false);
+            check.one_of("network", NETWORKS, false);
+        }
+        "trojan" => {
+            check.non_empty_string("password", false);
+            check.non_empty_string("sni", false);
+            check.boolean("skip_cert_verify");
+        }
+        "hysteria2" => {
+            check.non_empty_string("password", false);
+            check.one_of("obfs", HYSTERIA2_OBFS, false);
+            let obfs_required = fields.get("obfs").map_or(false, |v| !v.is_null());
+            check.non_empty_string("obfs_password", obfs_required);
+            check.non_empty_string("sni", false);
+            check.boolean("skip_cert_verify");
+            check.port_ranges("ports");
+        }
+        "vless" => {
+            check.uuid("uuid", secret);
+            check.one_of("flow", VLESS_FLOWS, false);
+            check.one_of("network", NETWORKS, false);
+            check.non_empty_string("client_fingerprint", false);
+            validate_reality(fields.get("reality"), &mut errors);
+        }
+        _ => {}
+    }
+
+    errors
+}
+
+/// A node config rewritten for another protocol, and what changed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProtocolMigration {
+    pub config: Value,
+    /// Fields of the old protocol the new one reads too
+    pub kept: Vec<String>,
+    /// Fields of the old protocol the new one doesn't use
+    pub dropped: Vec<String>,
+    /// Fields filled with fresh values (UUID, Reality keys, ...)
+    pub generated: Vec<String>,
+}
+
+/// Rewrite a `from` node config for `to`
+///
+/// Fields both protocols read and metadata are kept, the old protocol's other fields
+/// are dropped, and fields the new protocol needs are generated where possible: a UUID
+/// for VMess/VLESS when the node secret can't stand in for one, a Shadowsocks cipher,
+/// and for VLESS a fresh Reality key pair and short ID when `reality` is given. The
+/// result must pass `validate`; otherwise every problem is returned.
+pub fn migrate(
+    from: &str,
+    to: &str,
+    config: &Value,
+    secret: &str,
+    reality: Option<&RealityTarget>,
+) -> Result<ProtocolMigration, Vec<FieldError>> {
+    let Some(fields) = config.as_object() else {
+        return Err(vec![FieldError::new("config", "Must be a JSON object")]);
+    };
+    let (old_fields, new_fields) = (protocol_fields(from), protocol_fields(to));
+
+    let mut fields = fields.clone();
+    let (mut kept, mut dropped, mut generated) = (Vec::new(), Vec::new(), Vec::new());
+    for &field in old_fields {
+        if fields.get(field).map_or(true, Value::is_null) {
+            continue;
+        }
+        if new_fields.contains(&field) {
+            kept.push(field.to_string());
+        } else {
+            fields.remove(field);
+            dropped.push(field.to_string());
+        }
+    }
+
+    let mut generate = |fields: &mut serde_json::Map<String, Value>, field: &str, value: Value| {
+        fields.insert(field.to_string(), value);
+        generated.push(field.to_string());
+    };
+    let missing = |fields: &serde_json::Map<String, Value>, field: &str| fields.get(field).map_or(true, Value::is_null);
+
+    if matches!(to, "vmess" | "vless") && missing(&fields, "uuid") && !is_uuid(secret) {
+        generate(&mut fields, "uuid", json!(uuid::Uuid::new_v4().to_string()));
+    }
+    if to == "shadowsocks" && missing(&fields, "method") {
+        generate(&mut fields, "method", json!(DEFAULT_SHADOWSOCKS_METHOD));
+    }
+    if let Some(reality) = reality {
+        if to != "vless" {
+            return Err(vec![FieldError::new("reality", "Only VLESS nodes use Reality")]);
+        }
+        let errors = validate_reality_target(reality);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let (private_key, public_key) = generate_reality_keys();
+        let short_id: String = rand::random::<[u8; 8]>().iter().map(|b| format!("{:02x}", b)).collect();
+        generate(
+            &mut fields,
+            "reality",
+            json!({
+                "dest": reality.dest.trim(),
+                "serverNames": reality.server_names.iter().map(|name| name.trim()).collect::<Vec<_>>(),
+                "privateKey": private_key,
+                "publicKey": public_key,
+                "shortIds": [short_id],
+            }),
+        );
+        // Reality with XTLS Vision only runs over raw TCP
+        if fields.get("network").and_then(Value::as_str).is_some_and(|network| network != "tcp") {
+            generate(&mut fields, "network", json!("tcp"));
+        }
+        if missing(&fields, "flow") {
+            generate(&mut fields, "flow", json!("xtls-rprx-vision"));
+        }
+    }
+
+    let config = Value::Object(fields);
+    let errors = validate(to, &config, Some(secret));
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(ProtocolMigration {
+        config,
+        kept,
+        dropped,
+        generated,
+    })
+}
+
+fn protocol_fields(protocol: &str) -> &'static [&'static str] {
+    PROTOCOL_FIELDS
+        .iter()
+        .find(|(name, _)| *name == protocol)
+        .map_or(&[], |(_, fields)| fields)
+}
+
+fn validate_reality_target(reality: &RealityTarget) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let dest = reality.dest.trim();
+    let valid_dest = dest
+        .rsplit_once(':')
+        .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok_and(|port| port > 0));
+    if !valid_dest {
+        errors.push(FieldError::new("reality.dest", "Must be host:port"));
+    }
+    if reality.server_names.is_empty() || reality.server_names.iter().any(|name| name.trim().is_empty()) {
+        errors.push(FieldError::new("reality.server_names", "Must list at least one server name"));
+    }
+    errors
+}
+
+/// A fresh Reality (X25519) key pair as unpadded base64url: (private key, public key)
+///
+/// The private key is the scalar of a random Ed25519 key and the public key its
+/// Montgomery form, which is exactly the X25519 key pair of that scalar.
+pub fn generate_reality_keys() -> (String, String) {
+    let signing_key = ed25519_dalek::SigningKey::from_bytes(&rand::random());
+    let mut private_key = signing_key.to_scalar_bytes();
+    // Stored clamped, the form Xray itself generates
+    private_key[0] &= 248;
+    private_key[31] &= 127;
+    private_key[31] |= 64;
+    let public_key = signing_key.verifying_key().to_montgomery().to_bytes();
+    (URL_SAFE_NO_PAD.encode(private_key), URL_SAFE_NO_PAD.encode(public_key))
+}
+
+fn validate_reality(reality: Option<&Value>, errors: &mut Vec<FieldError>) {
+    let Some(reality) = reality.filter(|v| !v.is_null()) else {
+        return;
+    };
+    let Some(fields) = reality.as_object() else {
+        errors.push(FieldError::new("config.reality", "Must be a JSON object"));
+        return;
+    };
+
+    // The generator accepts both spellings and prefers the camelCase one
+    let spelling = |camel: &'static str, snake: &'static str| {
+        if !fields.contains_key(camel) && fields.contains_key(snake) {
+            snake
+        } else {
+            camel
+        }
+    };
+    let key_field = spelling("publicKey", "public_key");
+    let short_ids_field = spelling("shortIds", "short_ids");
+
+    match fields.get(key_field) {
+        None | Some(Value::Null) => {
+            errors.push(FieldError::new(format!("config.reality.{}", key_field), "Field is required"))
+        }
+        Some(Value::String(key)) if is_reality_public_key(key) => {}
+        Some(_) => errors.push(FieldError::new(
+            format!("config.reality.{}", key_field),
+            "Must be a base64url X25519 public key",
+        )),
+    }
+
+    match fields.get(short_ids_field) {
+        None | Some(Value::Null) => {}
+        Some(Value::Array(ids)) if ids.iter().all(|id| id.as_str().map_or(false, is_reality_short_id)) => {}
+        Some(_) => errors.push(FieldError::new(
+            format!("config.reality.{}", short_ids_field),
+            "Must be an array of hex short IDs (up to 16 characters)",
+        )),
+    }
+}
+
+/// Field checks against one JSON object; missing and `null` fields count as absent
+struct Checker<'a> {
+    fields: &'a serde_json::Map<String, Value>,
+    prefix: &'static str,
+    errors: &'a mut Vec<FieldError>,
+}
+
+impl<'a> Checker<'a> {
+    fn fail(&mut self, field: &str, message: impl Into<String>) {
+        self.errors
+            .push(FieldError::new(format!("{}.{}", self.prefix, field), message));
+    }
+
+    fn get(&self, field: &str) -> Option<&'a Value> {
+        self.fields.get(field).filter(|v| !v.is_null())
+    }
+
+    fn non_empty_string(&mut self, field: &str, required: bool) {
+        match self.get(field) {
+            None if required => self.fail(field, "Field is required"),
+            None => {}
+            Some(Value::String(s)) if !s.trim().is_empty() => {}
+            Some(Value::String(_)) => self.fail(field, "Must not be empty"),
+            Some(_) => self.fail(field, "Must be a string"),
+        }
+    }
+
+    fn one_of(&mut self, field: &str, allowed: &[&str], required: bool) {
+        match self.get(field) {
+            None if required => self.fail(field, "Field is required"),
+            None => {}
+            Some(Value::String(s)) if allowed.contains(&s.as_str()) => {}
+            Some(_) => self.fail(field, format!("Must be one of: {}", allowed.join(", "))),
+        }
+    }
+
+    fn boolean(&mut self, field: &str) {
+        if self.get(field).map_or(false, |v| !v.is_boolean()) {
+            self.fail(field, "Must be a boolean");
+        }
+    }
+
+    fn u16(&mut self, field: &str) {
+        let valid = self
+            .get(field)
+            .map_or(true, |v| v.as_u64().map_or(false, |n| n <= u16::MAX as u64));
+        if !valid {
+            self.fail(field, "Must be an integer between 0 and 65535");
+        }
+    }
+
+    /// Extra ports for Hysteria2 port hopping, e.g. `20000-30000` or `443,20000-30000`
+    fn port_ranges(&mut self, field: &str) {
+        let valid = self
+            .get(field)
+            .map_or(true, |v| v.as_str().is_some_and(|s| parse_port_ranges(s).is_some()));
+        if !valid {
+            self.fail(field, "Must be ports or port ranges, e.g. 20000-30000");
+        }
+    }
+
+    /// A UUID, which may come from the node secret when the field is absent
+    fn uuid(&mut self, field: &str, secret: Option<&str>) {
+        match self.get(field) {
+            None if secret.map_or(false, is_uuid) => {}
+            None => self.fail(field, "Field is required"),
+            Some(Value::String(s)) if is_uuid(s) => {}
+            Some(_) => self.fail(field, "Must be a UUID"),
+        }
+    }
+}
+
+/// Parse a comma-separated list of ports and inclusive ranges (`443,20000-30000`)
+///
+/// `None` unless every entry is a port from 1 to 65535 or a range whose start is not
+/// above its end.
+pub fn parse_port_ranges(s: &str) -> Option<Vec<(u16, u16)>> {
+    s.split(',')
+        .map(|entry| {
+            let entry = entry.trim();
+            let (start, end) = entry.split_once('-').unwrap_or((entry, entry));
+            let (start, end) = (start.trim().parse::<u16>().ok()?, end.trim().parse::<u16>().ok()?);
+            (start > 0 && start <= end).then_some((start, end))
+        })
+        .collect()
+}
+
+/// Render parsed port ranges the way Clash Meta's `ports` takes them (`443,20000-30000`)
+pub fn format_port_ranges(ranges: &[(u16, u16)]) -> String {
+    ranges
+        .iter()
+        .map(|&(start, end)| if start == end { start.to_string() } else { format!("{}-{}", start, end) })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Whether `s` is a hyphenated UUID (8-4-4-4-12 hex digits)
+pub fn is_uuid(s: &str) -> bool {
+    let groups: Vec<&str> = s.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(group, len)| group.len() == len && group.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// Whether `s` looks like an X25519 public key: 32 bytes as unpadded base64url
+fn is_reality_public_key(s: &str) -> bool {
+    s.len() == 43
+        && s
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Whether `s` is a Reality short ID: an even number of hex digits, at most 16
+fn is_reality_short_id(s: &str) -> bool {
+    s.len() <= REALITY_SHORT_ID_MAX_LEN && s.len() % 2 == 0 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: &str = "b831381d-6324-4d53-ad4f-8cda48b30811";
+    const PUBLIC_KEY: &str = "Z84J2IelR9ch3k8VtlVhhs5ycBUlXA7wHBWcBrjqnAw";
+
+    fn fields(errors: &[FieldError]) -> Vec<&str> {
+        errors.iter().map(|e| e.field.as_str()).collect()
+    }
+
+    #[test]
+    fn test_valid_configs_pass() {
+        let cases = [
+            ("shadowsocks", json!({ "method": "aes-256-gcm", "country": "HK" })),
+            ("vmess", json!({ "uuid": UUID, "alter_id": 0, "security": "auto", "network": "ws" })),
+            ("trojan", json!({ "sni": "example.com", "skip_cert_verify": false })),
+            ("hysteria2", json!({ "obfs": "salamander", "obfs_password": "secret" })),
+            (
+                "vless",
+                json!({
+                    "uuid": UUID,
+                    "flow": "xtls-rprx-vision",
+                    "reality": { "publicKey": PUBLIC_KEY, "shortIds": ["", "6ba85179e30d4fc2"] }
+                }),
+            ),
+        ];
+
+        for (protocol, config) in cases {
+            assert_eq!(validate(protocol, &config, None), vec![], "{} should be valid", protocol);
+        }
+    }
+
+    #[test]
+    fn test_config_must_be_object() {
+        let errors = validate("trojan", &json!(["password"]), None);
+        assert_eq!(errors, vec![FieldError::new("config", "Must be a JSON object")]);
+    }
+
+    #[test]
+    fn test_vmess_requires_uuid() {
+        let errors = validate("vmess", &json!({}), None);
+        assert_eq!(errors, vec![FieldError::new("config.uuid", "Field is required")]);
+
+        // Generated node secrets are not UUIDs, so they cannot stand in for one
+        let errors = validate("vmess", &json!({}), Some("aB3dE5gH7jK9mN1pQ3sT5vW7yZ9bC1dE"));
+        assert_eq!(fields(&errors), vec!["config.uuid"]);
+
+        assert!(validate("vmess", &json!({}), Some(UUID)).is_empty());
+
+        let errors = validate("vless", &json!({ "uuid": "not-a-uuid" }), None);
+        assert_eq!(errors, vec![FieldError::new("config.uuid", "Must be a UUID")]);
+    }
+
+    #[test]
+    fn test_shadowsocks_method() {
+        let errors = validate("shadowsocks", &json!({ "password": "pw" }), None);
+        assert_eq!(fields(&errors), vec!["config.method"]);
+
+        let errors = validate("shadowsocks", &json!({ "method": "rc4-md5", "password": "" }), None);
+        assert_eq!(fields(&errors), vec!["config.method", "config.password"]);
+        assert!(errors[0].message.starts_with("Must be one of: aes-128-gcm"));
+    }
+
+    #[test]
+    fn test_value_types_are_checked() {
+        let errors = validate(
+            "vmess",
+            &json!({ "uuid": UUID, "alter_id": 70000, "security": 1, "network": "quic" }),
+            None,
+        );
+        assert_eq!(fields(&errors), vec!["config.alter_id", "config.security", "config.network"]);
+
+        let errors = validate("trojan", &json!({ "password": 123, "skip_cert_verify": "yes" }), None);
+        assert_eq!(fields(&errors), vec!["config.password", "config.skip_cert_verify"]);
+    }
+
+    #[test]
+    fn test_hysteria2_obfs_needs_password() {
+        let errors = validate("hysteria2", &json!({ "obfs": "salamander" }), None);
+        assert_eq!(errors, vec![FieldError::new("config.obfs_password", "Field is required")]);
+    }
+
+    #[test]
+    fn test_hysteria2_port_range() {
+        let config = json!({ "ports": "20000-30000" });
+        assert!(validate("hysteria2", &config, None).is_empty());
+        assert!(validate("hysteria2", &json!({ "ports": "443, 8443,20000-30000" }), None).is_empty());
+
+        for invalid in [json!("30000-20000"), json!("0-100"), json!("20000-70000"), json!(""), json!("a-b"), json!(20000)] {
+            let errors = validate("hysteria2", &json!({ "ports": invalid }), None);
+            assert_eq!(fields(&errors), vec!["config.ports"], "{}", invalid);
+        }
+
+        assert_eq!(parse_port_ranges("443,20000-30000"), Some(vec![(443, 443), (20000, 30000)]));
+        assert_eq!(parse_port_ranges("20000-"), None);
+        assert_eq!(format_port_ranges(&[(443, 443), (20000, 30000)]), "443,20000-30000");
+    }
+
+    #[test]
+    fn test_reality_settings() {
+        let errors = validate("vless", &json!({ "uuid": UUID, "reality": {} }), None);
+        assert_eq!(fields(&errors), vec!["config.reality.publicKey"]);
+
+        let errors = validate(
+            "vless",
+            &json!({ "uuid": UUID, "reality": { "public_key": "short", "short_ids": ["xyz"] } }),
+            None,
+        );
+        assert_eq!(fields(&errors), vec!["config.reality.public_key", "config.reality.short_ids"]);
+    }
+
+    #[test]
+    fn test_migrate_vmess_to_trojan() {
+        let config = json!({ "uuid": UUID, "alter_id": 0, "network": "ws", "country": "JP" });
+        let migration = migrate("vmess", "trojan", &config, "node-secret", None).unwrap();
+
+        // The node secret stands in for the trojan password; metadata stays
+        assert_eq!(migration.config, json!({ "country": "JP" }));
+        assert_eq!(migration.dropped, vec!["uuid", "alter_id", "network"]);
+        assert!(migration.kept.is_empty() && migration.generated.is_empty());
+    }
+
+    #[test]
+    fn test_migrate_vmess_to_vless_with_reality() {
+        let config = json!({ "uuid": UUID, "security": "auto", "network": "ws" });
+        let reality = RealityTarget {
+            dest: "www.microsoft.com:443".to_string(),
+            server_names: vec!["www.microsoft.com".to_string()],
+        };
+        let migration = migrate("vmess", "vless", &config, "node-secret", Some(&reality)).unwrap();
+
+        assert_eq!(migration.kept, vec!["uuid", "network"]);
+        assert_eq!(migration.dropped, vec!["security"]);
+        assert_eq!(migration.generated, vec!["reality", "network", "flow"]);
+        assert_eq!(migration.config["uuid"], UUID);
+        assert_eq!(migration.config["network"], "tcp");
+        assert_eq!(migration.config["reality"]["dest"], "www.microsoft.com:443");
+        let short_id = migration.config["reality"]["shortIds"][0].as_str().unwrap();
+        assert!(is_reality_short_id(short_id) && short_id.len() == 16);
+    }
+
+    #[test]
+    fn test_migrate_generates_missing_fields() {
+        // A generated node secret is not a UUID, so VLESS gets its own
+        let migration = migrate("trojan", "vless", &json!({ "sni": "a.example.com" }), "node-secret", None).unwrap();
+        assert_eq!(migration.dropped, vec!["sni"]);
+        assert_eq!(migration.generated, vec!["uuid"]);
+        assert!(is_uuid(migration.config["uuid"].as_str().unwrap()));
+
+        let migration = migrate("trojan", "vless", &json!({}), UUID, None).unwrap();
+        assert!(migration.generated.is_empty());
+
+        let migration = migrate("hysteria2", "shadowsocks", &json!({ "password": "pw" }), "node-secret", None).unwrap();
+        assert_eq!(migration.kept, vec!["password"]);
+        assert_eq!(migration.config, json!({ "password": "pw", "method": DEFAULT_SHADOWSOCKS_METHOD }));
+    }
+
+    #[test]
+    fn test_migrate_rejects_bad_reality() {
+        let reality = RealityTarget {
+            dest: "www.microsoft.com".to_string(),
+            server_names: vec![],
+        };
+        let errors = migrate("vmess", "vless", &json!({}), UUID, Some(&reality)).unwrap_err();
+        assert_eq!(fields(&errors), vec!["reality.dest", "reality.server_names"]);
+
+        let errors = migrate("vless", "trojan", &json!({}), UUID, Some(&reality)).unwrap_err();
+        assert_eq!(fields(&errors), vec!["reality"]);
+    }
+
+    #[test]
+    fn test_generate_reality_keys() {
+        let (private_key, public_key) = generate_reality_keys();
+        assert!(is_reality_public_key(&public_key));
+        assert_ne!(generate_reality_keys().1, public_key);
+
+        // X25519 private keys are clamped
+        let private_key = URL_SAFE_NO_PAD.decode(private_key).unwrap();
+        assert_eq!(private_key.len(), 32);
+        assert_eq!(private_key[0] & 7, 0);
+        assert_eq!(private_key[31] & 0xc0, 0x40);
+    }
+
+    #[test]
+    fn test_is_uuid() {
+        assert!(is_uuid(UUID));
+        assert!(is_uuid(&UUID.to_uppercase()));
+        assert!(!is_uuid("b831381d63244d53ad4f8cda48b30811"));
+        assert!(!is_uuid("b831381d-6324-4d53-ad4f-8cda48b3081g"));
+    }
+}
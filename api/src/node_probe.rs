@@ -0,0 +1,477 @@
+use rand::RngCore;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::clash::{self, ClashProxy};
+use crate::models::Node;
+
+/// Time allowed for each network step (connect, TLS handshake)
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Fields hidden from the proxy preview
+const REDACTED_FIELDS: &[&str] = &["password", "uuid", "obfs-password"];
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Failed,
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub status: CheckStatus,
+    pub latency_ms: Option<u64>,
+    pub detail: Option<String>,
+}
+
+impl CheckResult {
+    fn ok(started: Option<Instant>, detail: impl Into<String>) -> Self {
+        CheckResult {
+            status: CheckStatus::Ok,
+            latency_ms: started.map(|s| s.elapsed().as_millis() as u64),
+            detail: Some(detail.into()),
+        }
+    }
+
+    fn failed(detail: impl Into<String>) -> Self {
+        CheckResult {
+            status: CheckStatus::Failed,
+            latency_ms: None,
+            detail: Some(detail.into()),
+        }
+    }
+
+    fn skipped(detail: impl Into<String>) -> Self {
+        CheckResult {
+            status: CheckStatus::Skipped,
+            latency_ms: None,
+            detail: Some(detail.into()),
+        }
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.status == CheckStatus::Failed
+    }
+}
+
+/// Result of `POST /api/admin/nodes/:id/test`
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeTestReport {
+    pub node_id: i64,
+    /// True when no check failed
+    pub passed: bool,
+    /// Node config produces a valid Clash proxy
+    pub config: CheckResult,
+    /// TCP connect from the API server
+    pub tcp: CheckResult,
+    /// TLS handshake, for protocols served over TLS
+    pub tls: CheckResult,
+    /// Clash proxy users would receive, with credentials redacted
+    pub proxy: Option<serde_json::Value>,
+}
+
+/// How clients reach a node, as served by the node agent's Xray inbounds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport {
+    Tcp,
+    Tls { sni: String },
+    /// QUIC based (Hysteria2); not checked from the API server
+    Udp,
+}
+
+/// Transport used by a node's protocol
+pub fn transport(node: &Node) -> Transport {
+    match node.protocol.as_str() {
+        "trojan" => Transport::Tls {
+            sni: node
+                .config
+                .get("sni")
+                .and_then(|v| v.as_str())
+                .unwrap_or(&node.host)
+                .to_string(),
+        },
+        // VLESS inbounds are served with Reality, which answers like its camouflage site
+        "vless" => Transport::Tls {
+            sni: node
+                .config
+                .get("reality")
+                .and_then(|r| r.get("serverNames").or_else(|| r.get("server_names")))
+                .and_then(|v| v.as_array())
+                .and_then(|arr| arr.first())
+                .and_then(|v| v.as_str())
+                .unwrap_or(&node.host)
+                .to_string(),
+        },
+        "hysteria2" => Transport::Udp,
+        _ => Transport::Tcp,
+    }
+}
+
+/// Validate the node config by generating its Clash proxy
+pub fn check_config(node: &Node) -> (CheckResult, Option<ClashProxy>) {
+    if node.host.trim().is_empty() {
+        return (CheckResult::failed("Node host is empty"), None);
+    }
+    if !(1..=65535).contains(&node.port) {
+        return (CheckResult::failed("Port must be between 1 and 65535"), None);
+    }
+    if clash::map_node_protocol_to_clash(&node.protocol).is_none() {
+        return (
+            CheckResult::failed(format!("Unsupported protocol: {}", node.protocol)),
+            None,
+        );
+    }
+
+    match clash::build_node_proxy(node) {
+        Ok(proxy) => (CheckResult::ok(None, "Clash proxy generated"), Some(proxy)),
+        Err(e) => (CheckResult::failed(e.to_string()), None),
+    }
+}
+
+/// Serialize a proxy for display, hiding credentials
+pub fn redacted_preview(proxy: &ClashProxy) -> Option<serde_json::Value> {
+    let mut value = serde_json::to_value(proxy).ok()?;
+    if let Some(fields) = value.as_object_mut() {
+        for field in REDACTED_FIELDS {
+            if let Some(v) = fields.get_mut(*field) {
+                if !v.is_null() {
+                    *v = serde_json::json!("***");
+                }
+            }
+        }
+    }
+    Some(value)
+}
+
+/// Open a TCP connection to `host:port`
+pub async fn connect(host: &str, port: u16) -> Result<TcpStream, String> {
+    match tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect((host, port))).await {
+        Ok(Ok(stream)) => Ok(stream),
+        Ok(Err(e)) => Err(format!("Connection to {}:{} failed: {}", host, port, e)),
+        Err(_) => Err(format!(
+            "Connection to {}:{} timed out after {}s",
+            host,
+            port,
+            PROBE_TIMEOUT.as_secs()
+        )),
+    }
+}
+
+/// Build a TLS ClientHello offering TLS 1.3 and 1.2 for `sni`
+///
+/// Only used to see whether the server answers with a ServerHello; the handshake is
+/// never completed, so the key share is random bytes.
+pub fn client_hello(sni: &str) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    let mut random = [0u8; 32];
+    let mut session_id = [0u8; 32];
+    let mut key_share = [0u8; 32];
+    rng.fill_bytes(&mut random);
+    rng.fill_bytes(&mut session_id);
+    rng.fill_bytes(&mut key_share);
+
+    let mut extensions = Vec::new();
+
+    // server_name
+    let name = sni.as_bytes();
+    let mut server_name = Vec::new();
+    push_u16(&mut server_name, name.len() as u16 + 3);
+    server_name.push(0); // host_name
+    push_u16(&mut server_name, name.len() as u16);
+    server_name.extend_from_slice(name);
+    push_extension(&mut extensions, 0x0000, &server_name);
+
+    // supported_groups: x25519, secp256r1, secp384r1
+    push_extension(&mut extensions, 0x000a, &[0x00, 0x06, 0x00, 0x1d, 0x00, 0x17, 0x00, 0x18]);
+    // ec_point_formats: uncompressed
+    push_extension(&mut extensions, 0x000b, &[0x01, 0x00]);
+    // signature_algorithms
+    push_extension(
+        &mut extensions,
+        0x000d,
+        &[
+            0x00, 0x10, 0x04, 0x03, 0x08, 0x04, 0x04, 0x01, 0x05, 0x03, 0x08, 0x05, 0x05, 0x01, 0x08, 0x06,
+            0x06, 0x01,
+        ],
+    );
+    // supported_versions: TLS 1.3, TLS 1.2
+    push_extension(&mut extensions, 0x002b, &[0x04, 0x03, 0x04, 0x03, 0x03]);
+    // key_share: x25519
+    let mut shares = Vec::new();
+    push_u16(&mut shares, 36);
+    push_u16(&mut shares, 0x001d);
+    push_u16(&mut shares, 32);
+    shares.extend_from_slice(&key_share);
+    push_extension(&mut extensions, 0x0033, &shares);
+
+    let cipher_suites: &[u16] = &[
+        0x1301, 0x1302, 0x1303, // TLS 1.3
+        0xc02b, 0xc02f, 0xc02c, 0xc030, 0xcca9, 0xcca8, // ECDHE AEAD
+    ];
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&[0x03, 0x03]); // legacy_version: TLS 1.2
+    body.extend_from_slice(&random);
+    body.push(session_id.len() as u8);
+    body.extend_from_slice(&session_id);
+    push_u16(&mut body, cipher_suites.len() as u16 * 2);
+    for suite in cipher_suites {
+        push_u16(&mut body, *suite);
+    }
+    body.extend_from_slice(&[0x01, 0x00]); // compression: null
+    push_u16(&mut body, extensions.len() as u16);
+    body.extend_from_slice(&extensions);
+
+    let mut handshake = vec![0x01]; // client_hello
+    handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    handshake.extend_from_slice(&body);
+
+    let mut record = vec![0x16, 0x03, 0x01]; // handshake record, TLS 1.0 for compatibility
+    push_u16(&mut record, handshake.len() as u16);
+    record.extend_from_slice(&handshake);
+    record
+}
+
+fn push_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn push_extension(buf: &mut Vec<u8>, kind: u16, data: &[u8]) {
+    push_u16(buf, kind);
+    push_u16(buf, data.len() as u16);
+    buf.extend_from_slice(data);
+}
+
+/// Interpret the first bytes a server sent back after our ClientHello
+pub fn classify_tls_response(head: &[u8]) -> Result<&'static str, String> {
+    match head {
+        [0x16, 0x03, _, _, _, 0x02, ..] => Ok("ServerHello received"),
+        [0x15, 0x03, _, _, _, _, description, ..] => Err(format!("Server sent TLS alert {}", description)),
+        [] => Err("Server closed the connection without a TLS response".to_string()),
+        _ => Err("Server did not answer with TLS".to_string()),
+    }
+}
+
+/// Send a ClientHello over `stream` and wait for the server's first TLS record
+pub async fn check_tls(stream: &mut TcpStream, sni: &str) -> CheckResult {
+    let started = Instant::now();
+    let exchange = async {
+        stream.write_all(&client_hello(sni)).await?;
+
+        // Record header (5 bytes) plus the handshake type or alert level/description
+        let mut head = [0u8; 7];
+        let mut read = 0;
+        while read < head.len() {
+            match stream.read(&mut head[read..]).await? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        Ok::<_, std::io::Error>(head[..read].to_vec())
+    };
+
+    match tokio::time::timeout(PROBE_TIMEOUT, exchange).await {
+        Ok(Ok(head)) => match classify_tls_response(&head) {
+            Ok(detail) => CheckResult::ok(Some(started), format!("{} (SNI {})", detail, sni)),
+            Err(detail) => CheckResult::failed(format!("{} (SNI {})", detail, sni)),
+        },
+        Ok(Err(e)) => CheckResult::failed(format!("TLS handshake failed: {}", e)),
+        Err(_) => CheckResult::failed(format!(
+            "TLS handshake timed out after {}s",
+            PROBE_TIMEOUT.as_secs()
+        )),
+    }
+}
+
+/// Check a node's config and reachability from the API server
+pub async fn test_node(node: &Node) -> NodeTestReport {
+    let (config, proxy) = check_config(node);
+    let proxy = proxy.as_ref().and_then(redacted_preview);
+
+    let (tcp, tls) = match (transport(node), u16::try_from(node.port)) {
+        (Transport::Udp, _) => (
+            CheckResult::skipped("UDP protocol, not checked from the API server"),
+            CheckResult::skipped("Not applicable"),
+        ),
+        (_, Err(_)) | (_, Ok(0)) => (
+            CheckResult::skipped("Invalid port"),
+            CheckResult::skipped("Invalid port"),
+        ),
+        (transport, Ok(port)) => {
+            let started = Instant::now();
+            match connect(&node.host, port).await {
+                Ok(mut stream) => {
+                    let peer = stream
+                        .peer_addr()
+                        .map(|addr| addr.to_string())
+                        .unwrap_or_else(|_| format!("{}:{}", node.host, port));
+                    let tcp = CheckResult::ok(Some(started), format!("Connected to {}", peer));
+                    let tls = match transport {
+                        Transport::Tls { sni } => check_tls(&mut stream, &sni).await,
+                        _ => CheckResult::skipped("Protocol is not served over TLS"),
+                    };
+                    (tcp, tls)
+                }
+                Err(e) => (
+                    CheckResult::failed(e),
+                    CheckResult::skipped("TCP connection failed"),
+                ),
+            }
+        }
+    };
+
+    NodeTestReport {
+        node_id: node.id,
+        passed: !(config.is_failed() || tcp.is_failed() || tls.is_failed()),
+        config,
+        tcp,
+        tls,
+        proxy,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::NodeStatus;
+    use tokio::net::TcpListener;
+
+    fn node(protocol: &str, host: &str, port: i32, config: serde_json::Value) -> Node {
+        Node {
+            id: 7,
+            name: "Probe".to_string(),
+            host: host.to_string(),
+            port,
+            protocol: protocol.to_string(),
+            config,
+            status: NodeStatus::Offline,
+            max_users: 100,
+            approved: false,
+            ..Node::test_fixture()
+        }
+    }
+
+    #[test]
+    fn test_transport_by_protocol() {
+        let trojan = node("trojan", "example.com", 443, serde_json::json!({ "sni": "cdn.example.com" }));
+        assert_eq!(transport(&trojan), Transport::Tls { sni: "cdn.example.com".to_string() });
+
+        let vless = node(
+            "vless",
+            "example.com",
+            443,
+            serde_json::json!({ "reality": { "serverNames": ["www.microsoft.com"] } }),
+        );
+        assert_eq!(transport(&vless), Transport::Tls { sni: "www.microsoft.com".to_string() });
+
+        assert_eq!(transport(&node("vless", "example.com", 443, serde_json::json!({}))), Transport::Tls { sni: "example.com".to_string() });
+        assert_eq!(transport(&node("hysteria2", "example.com", 443, serde_json::json!({}))), Transport::Udp);
+        assert_eq!(transport(&node("shadowsocks", "example.com", 8388, serde_json::json!({}))), Transport::Tcp);
+    }
+
+    #[test]
+    fn test_check_config_reports_generator_error() {
+        let (result, proxy) = check_config(&node("shadowsocks", "example.com", 8388, serde_json::json!({})));
+        assert!(result.is_failed());
+        assert!(result.detail.unwrap().contains("method"));
+        assert!(proxy.is_none());
+
+        let (result, _) = check_config(&node("shadowsocks", "example.com", 70000, serde_json::json!({})));
+        assert!(result.is_failed());
+    }
+
+    #[test]
+    fn test_check_config_preview_is_redacted() {
+        let (result, proxy) = check_config(&node(
+            "shadowsocks",
+            "example.com",
+            8388,
+            serde_json::json!({ "method": "aes-256-gcm" }),
+        ));
+        assert_eq!(result.status, CheckStatus::Ok);
+
+        let preview = redacted_preview(&proxy.unwrap()).unwrap();
+        assert_eq!(preview["type"], "ss");
+        assert_eq!(preview["password"], "***");
+        assert_eq!(preview["cipher"], "aes-256-gcm");
+    }
+
+    #[test]
+    fn test_client_hello_lengths_are_consistent() {
+        let hello = client_hello("example.com");
+        assert_eq!(&hello[..3], &[0x16, 0x03, 0x01]);
+        let record_len = u16::from_be_bytes([hello[3], hello[4]]) as usize;
+        assert_eq!(hello.len(), 5 + record_len);
+
+        assert_eq!(hello[5], 0x01);
+        let handshake_len = u32::from_be_bytes([0, hello[6], hello[7], hello[8]]) as usize;
+        assert_eq!(record_len, 4 + handshake_len);
+
+        let sni = b"example.com";
+        assert!(hello.windows(sni.len()).any(|w| w == sni));
+    }
+
+    #[test]
+    fn test_classify_tls_response() {
+        assert!(classify_tls_response(&[0x16, 0x03, 0x03, 0x00, 0x7a, 0x02, 0x00]).is_ok());
+        assert!(classify_tls_response(&[0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 0x28])
+            .unwrap_err()
+            .contains("alert 40"));
+        assert!(classify_tls_response(b"HTTP/1.1").is_err());
+        assert!(classify_tls_response(&[]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_node_reachable_over_tls() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 512];
+            let _ = socket.read(&mut buf).await;
+            let _ = socket.write_all(&[0x16, 0x03, 0x03, 0x00, 0x7a, 0x02, 0x00]).await;
+        });
+
+        let report = test_node(&node(
+            "trojan",
+            "127.0.0.1",
+            port as i32,
+            serde_json::json!({ "sni": "example.com" }),
+        ))
+        .await;
+
+        assert_eq!(report.tcp.status, CheckStatus::Ok);
+        assert_eq!(report.tls.status, CheckStatus::Ok);
+        assert_eq!(report.config.status, CheckStatus::Ok);
+        assert!(report.passed);
+        assert_eq!(report.proxy.unwrap()["password"], "***");
+    }
+
+    #[tokio::test]
+    async fn test_node_unreachable() {
+        // Bind then drop to get a port nothing listens on
+        let port = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let report = test_node(&node(
+            "shadowsocks",
+            "127.0.0.1",
+            port as i32,
+            serde_json::json!({ "method": "aes-256-gcm" }),
+        ))
+        .await;
+
+        assert_eq!(report.tcp.status, CheckStatus::Failed);
+        assert_eq!(report.tls.status, CheckStatus::Skipped);
+        assert!(!report.passed);
+    }
+}
@@ -0,0 +1,308 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config::{OAuthClientConfig, OAuthConfig};
+
+/// How long an authorization request may take before its `state` expires (seconds)
+pub const STATE_TTL_SECS: u64 = 600;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+/// GitHub's API rejects requests without a User-Agent
+const USER_AGENT: &str = "vpn-platform-api";
+
+static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+fn http_client() -> &'static reqwest::Client {
+    HTTP_CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .user_agent(USER_AGENT)
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// Supported OAuth2 login providers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OAuthProvider {
+    GitHub,
+    Google,
+}
+
+impl OAuthProvider {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "github" => Some(OAuthProvider::GitHub),
+            "google" => Some(OAuthProvider::Google),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OAuthProvider::GitHub => "github",
+            OAuthProvider::Google => "google",
+        }
+    }
+
+    fn authorize_endpoint(self) -> &'static str {
+        match self {
+            OAuthProvider::GitHub => "https://github.com/login/oauth/authorize",
+            OAuthProvider::Google => "https://accounts.google.com/o/oauth2/v2/auth",
+        }
+    }
+
+    fn token_endpoint(self) -> &'static str {
+        match self {
+            OAuthProvider::GitHub => "https://github.com/login/oauth/access_token",
+            OAuthProvider::Google => "https://oauth2.googleapis.com/token",
+        }
+    }
+
+    fn scope(self) -> &'static str {
+        match self {
+            OAuthProvider::GitHub => "read:user user:email",
+            OAuthProvider::Google => "openid email",
+        }
+    }
+
+    /// Client credentials, if this provider is configured
+    pub fn client(self, config: &OAuthConfig) -> Option<&OAuthClientConfig> {
+        match self {
+            OAuthProvider::GitHub => config.github.as_ref(),
+            OAuthProvider::Google => config.google.as_ref(),
+        }
+    }
+}
+
+/// An authorization request waiting for its callback, stored under its `state`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingLogin {
+    pub provider: String,
+    /// Applied if the login creates a new account
+    pub referral_code: Option<String>,
+    /// Tenant whose site started the login (the callback host is shared)
+    #[serde(default = "default_tenant_id")]
+    pub tenant_id: i64,
+}
+
+fn default_tenant_id() -> i64 {
+    crate::tenancy::DEFAULT_TENANT_ID
+}
+
+/// Identity reported by a provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderProfile {
+    /// Stable account id at the provider
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+}
+
+impl ProviderProfile {
+    /// Email address usable for account linking (only if the provider verified it)
+    pub fn verified_email(&self) -> Option<&str> {
+        self.email.as_deref().filter(|_| self.email_verified)
+    }
+}
+
+/// Callback URL registered with the provider
+pub fn callback_url(public_base_url: &str, provider: OAuthProvider) -> String {
+    format!(
+        "{}{}/auth/oauth/{}/callback",
+        public_base_url.trim_end_matches('/'),
+        crate::middleware::API_V1_PREFIX,
+        provider.as_str()
+    )
+}
+
+/// Provider login page for an authorization request
+pub fn authorize_url(provider: OAuthProvider, client_id: &str, redirect_uri: &str, state: &str) -> Result<String> {
+    let url = reqwest::Url::parse_with_params(
+        provider.authorize_endpoint(),
+        &[
+            ("client_id", client_id),
+            ("redirect_uri", redirect_uri),
+            ("response_type", "code"),
+            ("scope", provider.scope()),
+            ("state", state),
+        ],
+    )?;
+
+    Ok(url.into())
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+/// Exchange an authorization code for an access token
+pub async fn exchange_code(
+    provider: OAuthProvider,
+    client: &OAuthClientConfig,
+    code: &str,
+    redirect_uri: &str,
+) -> Result<String> {
+    let response: TokenResponse = http_client()
+        .post(provider.token_endpoint())
+        .header(reqwest::header::ACCEPT, "application/json")
+        .form(&[
+            ("client_id", client.client_id.as_str()),
+            ("client_secret", client.client_secret.as_str()),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("grant_type", "authorization_code"),
+        ])
+        .send()
+        .await
+        .with_context(|| format!("{} token request failed", provider.as_str()))?
+        .json()
+        .await
+        .with_context(|| format!("Invalid {} token response", provider.as_str()))?;
+
+    // GitHub reports errors with a 200 and an `error` field
+    match response.access_token {
+        Some(token) if response.error.is_none() => Ok(token),
+        _ => Err(anyhow!(
+            "{} rejected the authorization code: {}",
+            provider.as_str(),
+            response
+                .error_description
+                .or(response.error)
+                .unwrap_or_else(|| "no access token".to_string())
+        )),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubUser {
+    id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubEmail {
+    email: String,
+    primary: bool,
+    verified: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GoogleUserInfo {
+    sub: String,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+}
+
+fn github_profile(user: GithubUser, emails: &[GithubEmail]) -> ProviderProfile {
+    let primary = emails.iter().find(|e| e.primary);
+    ProviderProfile {
+        subject: user.id.to_string(),
+        email: primary.map(|e| e.email.trim().to_string()),
+        email_verified: primary.map_or(false, |e| e.verified),
+    }
+}
+
+fn google_profile(info: GoogleUserInfo) -> ProviderProfile {
+    ProviderProfile {
+        subject: info.sub,
+        email: info.email.map(|e| e.trim().to_string()),
+        email_verified: info.email_verified,
+    }
+}
+
+async fn get_json<T: serde::de::DeserializeOwned>(url: &str, access_token: &str) -> Result<T> {
+    http_client()
+        .get(url)
+        .bearer_auth(access_token)
+        .header(reqwest::header::ACCEPT, "application/json")
+        .send()
+        .await
+        .with_context(|| format!("Request to {} failed", url))?
+        .error_for_status()?
+        .json()
+        .await
+        .with_context(|| format!("Invalid response from {}", url))
+}
+
+/// Fetch the account id and email behind an access token
+pub async fn fetch_profile(provider: OAuthProvider, access_token: &str) -> Result<ProviderProfile> {
+    match provider {
+        OAuthProvider::GitHub => {
+            let user: GithubUser = get_json("https://api.github.com/user", access_token).await?;
+            let emails: Vec<GithubEmail> = get_json("https://api.github.com/user/emails", access_token).await?;
+            Ok(github_profile(user, &emails))
+        }
+        OAuthProvider::Google => {
+            let info: GoogleUserInfo =
+                get_json("https://openidconnect.googleapis.com/v1/userinfo", access_token).await?;
+            Ok(google_profile(info))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_names() {
+        assert_eq!(OAuthProvider::parse("github"), Some(OAuthProvider::GitHub));
+        assert_eq!(OAuthProvider::parse("google"), Some(OAuthProvider::Google));
+        assert_eq!(OAuthProvider::parse("GitHub"), None);
+        assert_eq!(OAuthProvider::GitHub.as_str(), "github");
+    }
+
+    #[test]
+    fn test_callback_url() {
+        assert_eq!(
+            callback_url("https://api.example.com/", OAuthProvider::Google),
+            "https://api.example.com/api/v1/auth/oauth/google/callback"
+        );
+    }
+
+    #[test]
+    fn test_authorize_url_encodes_params() {
+        let url = authorize_url(
+            OAuthProvider::GitHub,
+            "client-1",
+            "https://api.example.com/api/v1/auth/oauth/github/callback",
+            "abc123",
+        )
+        .unwrap();
+
+        assert!(url.starts_with("https://github.com/login/oauth/authorize?client_id=client-1&"));
+        assert!(url.contains("redirect_uri=https%3A%2F%2Fapi.example.com%2Fapi%2Fv1%2Fauth%2Foauth%2Fgithub%2Fcallback"));
+        assert!(url.contains("scope=read%3Auser+user%3Aemail"));
+        assert!(url.contains("state=abc123"));
+    }
+
+    #[test]
+    fn test_github_profile_uses_primary_email() {
+        let emails = vec![
+            GithubEmail { email: "old@example.com".to_string(), primary: false, verified: true },
+            GithubEmail { email: "me@example.com".to_string(), primary: true, verified: true },
+        ];
+        let profile = github_profile(GithubUser { id: 42 }, &emails);
+
+        assert_eq!(profile.subject, "42");
+        assert_eq!(profile.verified_email(), Some("me@example.com"));
+    }
+
+    #[test]
+    fn test_unverified_email_is_not_linkable() {
+        let emails = vec![GithubEmail { email: "me@example.com".to_string(), primary: true, verified: false }];
+        assert_eq!(github_profile(GithubUser { id: 1 }, &emails).verified_email(), None);
+        assert_eq!(github_profile(GithubUser { id: 1 }, &[]).verified_email(), None);
+
+        let info: GoogleUserInfo = serde_json::from_str(r#"{"sub": "1089", "email": "me@example.com"}"#).unwrap();
+        let profile = google_profile(info);
+        assert_eq!(profile.subject, "1089");
+        assert_eq!(profile.verified_email(), None);
+    }
+}
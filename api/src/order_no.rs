@@ -0,0 +1,227 @@
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::config::OrderNoConfig;
+
+/// Prefix of every order number
+pub const PREFIX: &str = "ORD-";
+/// Numbers drawn for one order before giving up on finding a free one
+pub const MAX_ATTEMPTS: u32 = 5;
+/// Random characters in a `random` order number, before the check character
+pub const RANDOM_LEN: usize = 12;
+/// Largest worker id a Snowflake order number can carry (10 bits)
+pub const MAX_WORKER_ID: u16 = 1023;
+
+/// Crockford base32: no I, L, O or U, so numbers read out over the phone survive
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+/// Start of Snowflake time (2024-01-01T00:00:00Z, Unix milliseconds)
+const SNOWFLAKE_EPOCH_MS: u64 = 1_704_067_200_000;
+const SEQUENCE_BITS: u32 = 12;
+const WORKER_BITS: u32 = 10;
+
+/// How new order numbers are generated (`ORDER_NO_STRATEGY`)
+///
+/// Existing orders keep their numbers whatever the strategy, and lookups accept every
+/// format, so the strategy can be changed at any time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OrderNoStrategy {
+    /// `ORD-` and 12 random Crockford base32 characters plus a check character
+    #[default]
+    Random,
+    /// `ORD-` and a decimal Snowflake id: milliseconds, worker id and a sequence
+    Snowflake,
+    /// `ORD-{user_id}-{millis}`, as issued before order numbers were configurable
+    Legacy,
+}
+
+impl OrderNoStrategy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "random" => Some(Self::Random),
+            "snowflake" => Some(Self::Snowflake),
+            "legacy" => Some(Self::Legacy),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Random => "random",
+            Self::Snowflake => "snowflake",
+            Self::Legacy => "legacy",
+        }
+    }
+}
+
+/// A new order number for an order of `user_id`
+pub fn generate(config: &OrderNoConfig, user_id: i64, now: DateTime<Utc>) -> String {
+    match config.strategy {
+        OrderNoStrategy::Random => random(&mut rand::thread_rng()),
+        OrderNoStrategy::Snowflake => format!("{}{}", PREFIX, snowflake(config.worker_id, now)),
+        OrderNoStrategy::Legacy => format!("{}{}-{}", PREFIX, user_id, now.timestamp_millis()),
+    }
+}
+
+fn random(rng: &mut impl Rng) -> String {
+    let mut body: String = (0..RANDOM_LEN)
+        .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+        .collect();
+    body.push(check_char(&body));
+    format!("{}{}", PREFIX, body)
+}
+
+/// Luhn mod 32 check character over Crockford base32 characters
+///
+/// Catches every single-character typo and every swap of adjacent characters except
+/// `0` with `Z`.
+fn check_char(body: &str) -> char {
+    let n = ALPHABET.len();
+    let mut factor = 2;
+    let mut sum = 0;
+    for c in body.bytes().rev() {
+        let value = ALPHABET.iter().position(|&a| a == c).unwrap_or(0);
+        let addend = factor * value;
+        sum += addend / n + addend % n;
+        factor = if factor == 2 { 1 } else { 2 };
+    }
+    ALPHABET[(n - sum % n) % n] as char
+}
+
+/// Last issued Snowflake time and sequence, packed as `millis << SEQUENCE_BITS | sequence`
+static SNOWFLAKE_STATE: AtomicU64 = AtomicU64::new(0);
+
+/// Next Snowflake id of this process, strictly increasing
+///
+/// When more than 4096 ids are drawn within a millisecond, or the clock steps back,
+/// ids borrow from the following milliseconds instead of repeating.
+fn snowflake(worker_id: u16, now: DateTime<Utc>) -> u64 {
+    let now_ms = (now.timestamp_millis().max(0) as u64).saturating_sub(SNOWFLAKE_EPOCH_MS);
+    let floor = now_ms << SEQUENCE_BITS;
+    let previous = SNOWFLAKE_STATE
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| Some((last + 1).max(floor)))
+        .unwrap_or_default();
+    let state = (previous + 1).max(floor);
+
+    let millis = state >> SEQUENCE_BITS;
+    let sequence = state & ((1 << SEQUENCE_BITS) - 1);
+    let worker = u64::from(worker_id.min(MAX_WORKER_ID));
+    (millis << (WORKER_BITS + SEQUENCE_BITS)) | (worker << SEQUENCE_BITS) | sequence
+}
+
+/// The random part of a `random` order number, or None for other formats
+fn random_body(order_no: &str) -> Option<&str> {
+    let body = order_no.strip_prefix(PREFIX)?;
+    // Digits only is a Snowflake id
+    let is_random = body.len() == RANDOM_LEN + 1
+        && body.bytes().all(|c| c.is_ascii_alphanumeric())
+        && !body.bytes().all(|c| c.is_ascii_digit());
+    is_random.then_some(body)
+}
+
+/// Canonical form of an order number typed in by a person
+///
+/// `random` numbers are upper-cased and read the Crockford way (`I`/`L` as `1`, `O` as
+/// `0`); legacy and Snowflake numbers are only trimmed, since they are looked up as issued.
+pub fn normalize(order_no: &str) -> String {
+    let order_no = order_no.trim();
+    let upper = order_no.to_ascii_uppercase();
+    match random_body(&upper) {
+        Some(body) => {
+            let body: String = body
+                .chars()
+                .map(|c| match c {
+                    'I' | 'L' => '1',
+                    'O' => '0',
+                    c => c,
+                })
+                .collect();
+            format!("{}{}", PREFIX, body)
+        }
+        None => order_no.to_string(),
+    }
+}
+
+/// Whether a normalized order number is in the `random` format with a wrong check character
+///
+/// Such a number was mistyped and cannot exist, so lookups can answer without a query.
+pub fn is_mistyped(order_no: &str) -> bool {
+    random_body(order_no).is_some_and(|body| {
+        let (chars, check) = body.split_at(RANDOM_LEN);
+        !chars.bytes().all(|c| ALPHABET.contains(&c)) || check.chars().next() != Some(check_char(chars))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strategy_parse() {
+        assert_eq!(OrderNoStrategy::parse(" Snowflake "), Some(OrderNoStrategy::Snowflake));
+        assert_eq!(OrderNoStrategy::parse("uuid"), None);
+        assert_eq!(OrderNoStrategy::default().as_str(), "random");
+    }
+
+    #[test]
+    fn test_random_order_no() {
+        let config = OrderNoConfig::default();
+        let order_no = generate(&config, 42, Utc::now());
+        assert_eq!(order_no.len(), PREFIX.len() + RANDOM_LEN + 1);
+        assert!(!order_no.contains("42-"));
+        assert!(!is_mistyped(&order_no));
+        assert_ne!(order_no, generate(&config, 42, Utc::now()));
+    }
+
+    #[test]
+    fn test_check_char_catches_typos() {
+        let order_no = random(&mut rand::thread_rng());
+        let body = order_no.strip_prefix(PREFIX).unwrap().as_bytes().to_vec();
+
+        for i in 0..RANDOM_LEN {
+            let mut typo = body.clone();
+            typo[i] = if typo[i] == b'7' { b'8' } else { b'7' };
+            assert!(is_mistyped(&format!("{}{}", PREFIX, String::from_utf8(typo).unwrap())));
+
+            let pair = [body[i], body[i + 1]];
+            if pair[0] != pair[1] && pair != [b'0', b'Z'] && pair != [b'Z', b'0'] {
+                let mut swapped = body.clone();
+                swapped.swap(i, i + 1);
+                assert!(is_mistyped(&format!("{}{}", PREFIX, String::from_utf8(swapped).unwrap())));
+            }
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let order_no = random(&mut rand::thread_rng());
+        assert_eq!(normalize(&format!(" {} ", order_no.to_lowercase())), order_no);
+        assert_eq!(normalize("ord-a0000000000oI"), "ORD-A000000000001");
+
+        // Older formats are looked up as issued
+        assert_eq!(normalize("ORD-17-1700000000000"), "ORD-17-1700000000000");
+        assert_eq!(normalize(" ORDER123456 "), "ORDER123456");
+        assert!(!is_mistyped("ORD-17-1700000000000"));
+        assert!(!is_mistyped("ORD-481516234200000001"));
+        assert!(!is_mistyped("ORDER123456"));
+    }
+
+    #[test]
+    fn test_snowflake_is_increasing() {
+        let config = OrderNoConfig { strategy: OrderNoStrategy::Snowflake, worker_id: 7 };
+        let now = Utc::now();
+        let ids: Vec<u64> = (0..5000)
+            .map(|_| generate(&config, 1, now).strip_prefix(PREFIX).unwrap().parse().unwrap())
+            .collect();
+
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(ids.iter().all(|id| (id >> SEQUENCE_BITS) & u64::from(MAX_WORKER_ID) == 7));
+    }
+
+    #[test]
+    fn test_legacy_order_no() {
+        let config = OrderNoConfig { strategy: OrderNoStrategy::Legacy, worker_id: 0 };
+        let now = chrono::TimeZone::timestamp_millis_opt(&Utc, 1_700_000_000_000).unwrap();
+        assert_eq!(generate(&config, 17, now), "ORD-17-1700000000000");
+    }
+}
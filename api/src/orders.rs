@@ -0,0 +1,152 @@
+use std::fmt;
+
+use crate::models::{Order, OrderEvent};
+
+crate::status::status_enum! {
+    /// Status stored in `orders.status`
+    OrderStatus {
+        /// Coin-only purchase being processed
+        Pending => "pending",
+        /// Waiting for the payment gateway; coins are reserved
+        AwaitingPayment => "awaiting_payment",
+        Completed => "completed",
+        /// Payment failed or was declined by the gateway
+        Failed => "failed",
+        /// Payment window passed
+        Expired => "expired",
+        Cancelled => "cancelled",
+        Refunded => "refunded",
+        /// Paid after it expired, when the balance or stock no longer allowed completing it;
+        /// the payment has to be refunded by an admin
+        PaidAfterExpiry => "paid_after_expiry",
+    }
+}
+
+impl OrderStatus {
+    /// Whether an order in this status may move to `to`
+    pub fn can_transition_to(self, to: OrderStatus) -> bool {
+        use OrderStatus::*;
+
+        matches!(
+            (self, to),
+            (Pending, Completed)
+                | (Pending, Cancelled)
+                | (AwaitingPayment, Completed)
+                | (AwaitingPayment, Failed)
+                | (AwaitingPayment, Expired)
+                | (AwaitingPayment, Cancelled)
+                | (Expired, Completed)
+                | (Expired, PaidAfterExpiry)
+                | (Completed, Refunded)
+        )
+    }
+
+    /// Whether no further transition is possible
+    pub fn is_final(self) -> bool {
+        Self::ALL.into_iter().all(|to| !self.can_transition_to(to))
+    }
+}
+
+/// Why `db::transition_order` refused a status change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransitionError {
+    /// The target status is not reachable from the current one
+    Illegal { from: OrderStatus, to: OrderStatus },
+    /// The order was changed by someone else since it was read
+    Conflict { order_id: i64 },
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransitionError::Illegal { from, to } => {
+                write!(f, "Order cannot move from {} to {}", from, to)
+            }
+            TransitionError::Conflict { order_id } => {
+                write!(f, "Order {} was modified concurrently", order_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TransitionError {}
+
+/// Check a status change against the state machine
+pub fn check_transition(from: OrderStatus, to: OrderStatus) -> Result<(), TransitionError> {
+    if from.can_transition_to(to) {
+        Ok(())
+    } else {
+        Err(TransitionError::Illegal { from, to })
+    }
+}
+
+/// An applied status change: the updated order and the event recorded for it
+#[derive(Debug, Clone)]
+pub struct OrderTransition {
+    pub order: Order,
+    pub event: OrderEvent,
+    /// Outbox events written with the change (see `outbox::deliver_now`)
+    pub outbox_ids: Vec<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_round_trip() {
+        for status in OrderStatus::ALL {
+            assert_eq!(OrderStatus::parse(status.as_str()), Some(status));
+        }
+        assert_eq!(OrderStatus::parse("paid"), None);
+    }
+
+    #[test]
+    fn test_legal_transitions() {
+        use OrderStatus::*;
+
+        assert!(Pending.can_transition_to(Completed));
+        assert!(Pending.can_transition_to(Cancelled));
+        assert!(AwaitingPayment.can_transition_to(Completed));
+        assert!(AwaitingPayment.can_transition_to(Failed));
+        assert!(AwaitingPayment.can_transition_to(Expired));
+        assert!(Completed.can_transition_to(Refunded));
+        // A payment that arrives late still completes the order, or is kept on record
+        assert!(Expired.can_transition_to(Completed));
+        assert!(Expired.can_transition_to(PaidAfterExpiry));
+
+        assert!(!Pending.can_transition_to(Refunded));
+        assert!(!Completed.can_transition_to(Pending));
+        assert!(!Completed.can_transition_to(Cancelled));
+        assert!(!Expired.can_transition_to(Refunded));
+        assert!(!PaidAfterExpiry.can_transition_to(Completed));
+        assert!(!Failed.can_transition_to(Completed));
+        assert!(!Refunded.can_transition_to(Completed));
+        assert!(OrderStatus::ALL.into_iter().all(|s| !s.can_transition_to(s)));
+    }
+
+    #[test]
+    fn test_final_statuses() {
+        use OrderStatus::*;
+
+        for status in [Failed, Cancelled, Refunded, PaidAfterExpiry] {
+            assert!(status.is_final(), "{} should be final", status);
+        }
+        for status in [Pending, AwaitingPayment, Completed, Expired] {
+            assert!(!status.is_final(), "{} should not be final", status);
+        }
+    }
+
+    #[test]
+    fn test_check_transition() {
+        assert!(check_transition(OrderStatus::AwaitingPayment, OrderStatus::Completed).is_ok());
+        assert_eq!(
+            check_transition(OrderStatus::Completed, OrderStatus::Completed),
+            Err(TransitionError::Illegal {
+                from: OrderStatus::Completed,
+                to: OrderStatus::Completed,
+            })
+        );
+        assert!(check_transition(OrderStatus::Refunded, OrderStatus::Cancelled).is_err());
+    }
+}
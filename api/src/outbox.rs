@@ -0,0 +1,280 @@
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use std::time::Instant;
+
+use crate::adjustments::AdjustmentEvent;
+use crate::cache::{PurgeScope, RedisCache};
+use crate::db;
+use crate::maintenance::MaintenanceEvent;
+use crate::models::{OrderEvent, OutboxRecord};
+use crate::topup::TopupEvent;
+
+/// How often an idle dispatcher polls for due events
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+/// Events claimed per query
+const BATCH_SIZE: i64 = 100;
+/// How long a claimed event stays hidden from other dispatchers
+const CLAIM_LEASE_SECS: i64 = 60;
+/// Longest wait between delivery attempts
+const MAX_RETRY_DELAY_SECS: i64 = 3600;
+/// How long delivered events are kept for debugging
+const DELIVERED_RETENTION_HOURS: i64 = 24;
+/// How often delivered events are pruned
+const CLEANUP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+/// A side effect of a state change, delivered to Redis once the change commits
+///
+/// Write it with `enqueue` inside the transaction making the change: a crash after
+/// commit delays the side effect but cannot lose it. Delivery is at least once, so
+/// every event must be safe to repeat.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "payload", rename_all = "snake_case")]
+pub enum OutboxEvent {
+    /// Tell a node's agent to fetch its config now
+    NodeConfigUpdate { node_id: i64 },
+    /// Drop every cached node group
+    InvalidateActiveNodes,
+    /// Drop a tenant's cached node groups
+    InvalidateNodeGroups { tenant_id: i64 },
+    /// Drop cached subscription configs
+    PurgeSubscriptions(PurgeScope),
+    /// Drop a user's cached package data
+    InvalidateUserPackage { user_id: i64 },
+    /// Publish an order status change on `orders:events`
+    OrderEvent(OrderEvent),
+    /// Publish a top-up request submission or review on `topups:events`
+    TopupEvent(TopupEvent),
+    /// Publish a balance adjustment request or review on `adjustments:events`
+    AdjustmentEvent(AdjustmentEvent),
+    /// Publish a node maintenance window change on `nodes:maintenance`
+    MaintenanceEvent(MaintenanceEvent),
+}
+
+impl OutboxEvent {
+    /// Split into the `kind` and `payload` columns
+    fn to_columns(&self) -> Result<(String, serde_json::Value)> {
+        let mut value = serde_json::to_value(self).context("Failed to serialize outbox event")?;
+        let kind = value["kind"].as_str().unwrap_or_default().to_string();
+        let payload = value.get_mut("payload").map(serde_json::Value::take).unwrap_or_default();
+        Ok((kind, payload))
+    }
+
+    fn from_record(record: &OutboxRecord) -> Result<Self> {
+        serde_json::from_value(serde_json::json!({ "kind": record.kind, "payload": record.payload }))
+            .with_context(|| format!("Unknown outbox event {}", record.kind))
+    }
+}
+
+/// Record events on the caller's connection, returning their ids for `deliver_now`
+pub async fn enqueue(conn: &mut PgConnection, events: &[OutboxEvent]) -> Result<Vec<i64>> {
+    let mut ids = Vec::with_capacity(events.len());
+    for event in events {
+        let (kind, payload) = event.to_columns()?;
+        ids.push(db::insert_outbox_event(&mut *conn, &kind, &payload).await?);
+    }
+    Ok(ids)
+}
+
+/// Apply one event to Redis
+pub async fn deliver(cache: &RedisCache, event: &OutboxEvent) -> Result<()> {
+    match event {
+        OutboxEvent::NodeConfigUpdate { node_id } => cache.publish_node_config_update(*node_id).await,
+        OutboxEvent::InvalidateActiveNodes => cache.invalidate_all_node_groups().await,
+        OutboxEvent::InvalidateNodeGroups { tenant_id } => cache.invalidate_node_groups(*tenant_id).await,
+        OutboxEvent::PurgeSubscriptions(scope) => cache.purge_subscription_configs(*scope).await.map(|_| ()),
+        OutboxEvent::InvalidateUserPackage { user_id } => cache.invalidate_user_package(*user_id).await,
+        OutboxEvent::OrderEvent(event) => cache.publish_order_event(event).await,
+        OutboxEvent::TopupEvent(event) => cache.publish_topup_event(event).await,
+        OutboxEvent::AdjustmentEvent(event) => cache.publish_adjustment_event(event).await,
+        OutboxEvent::MaintenanceEvent(event) => cache.publish_maintenance_event(event).await,
+    }
+}
+
+/// Wait before retrying an event that failed `attempts` times: 2, 4, 8, ... seconds, capped at an hour
+pub fn retry_delay(attempts: i32) -> Duration {
+    let secs = 2i64.saturating_pow(attempts.clamp(1, 62) as u32);
+    Duration::seconds(secs.min(MAX_RETRY_DELAY_SECS))
+}
+
+/// Claim and deliver one batch of due events (only `ids`, if given)
+///
+/// Returns how many events were claimed; failed ones are rescheduled with backoff.
+pub async fn dispatch(pool: &PgPool, cache: &RedisCache, ids: Option<&[i64]>) -> Result<usize> {
+    let records = db::claim_outbox_events(pool, ids, BATCH_SIZE, CLAIM_LEASE_SECS).await?;
+
+    for record in &records {
+        let delivered = match OutboxEvent::from_record(record) {
+            Ok(event) => deliver(cache, &event).await,
+            Err(e) => Err(e),
+        };
+
+        match delivered {
+            Ok(()) => db::mark_outbox_event_delivered(pool, record.id).await?,
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to deliver outbox event {} ({}, attempt {}): {}",
+                    record.id,
+                    record.kind,
+                    record.attempts,
+                    e
+                );
+                let retry_at = Utc::now() + retry_delay(record.attempts);
+                db::record_outbox_event_failure(pool, record.id, &e.to_string(), retry_at).await?;
+            }
+        }
+    }
+
+    Ok(records.len())
+}
+
+/// Deliver events right after the transaction that wrote them commits
+///
+/// Saves waiting for the dispatcher's next poll; anything not delivered here is
+/// retried by the dispatcher.
+pub async fn deliver_now(pool: &PgPool, cache: &RedisCache, ids: &[i64]) {
+    if ids.is_empty() {
+        return;
+    }
+    if let Err(e) = dispatch(pool, cache, Some(ids)).await {
+        tracing::warn!("Failed to deliver outbox events {:?}: {}", ids, e);
+    }
+}
+
+/// Start the outbox dispatcher
+///
+/// Every API replica runs one; events are claimed with `SKIP LOCKED`, so each is
+/// delivered by one replica at a time.
+pub fn start_outbox_dispatcher(db_pool: PgPool, cache: RedisCache) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        let mut last_cleanup: Option<Instant> = None;
+
+        loop {
+            ticker.tick().await;
+
+            if !last_cleanup.is_some_and(|at| at.elapsed() < CLEANUP_INTERVAL) {
+                last_cleanup = Some(Instant::now());
+                let cutoff = Utc::now() - Duration::hours(DELIVERED_RETENTION_HOURS);
+                match db::delete_delivered_outbox_events(&db_pool, cutoff).await {
+                    Ok(0) => {}
+                    Ok(deleted) => tracing::debug!("Pruned {} delivered outbox events", deleted),
+                    Err(e) => tracing::warn!("Failed to prune outbox events: {}", e),
+                }
+            }
+
+            // Drain the backlog before sleeping again
+            loop {
+                match dispatch(&db_pool, &cache, None).await {
+                    Ok(claimed) if claimed as i64 == BATCH_SIZE => continue,
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::error!("Failed to dispatch outbox events: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adjustments::AdjustmentStatus;
+    use crate::amounts::Coins;
+    use crate::maintenance::MaintenanceStatus;
+    use crate::orders::OrderStatus;
+    use crate::topup::TopupStatus;
+
+    fn record(event: &OutboxEvent) -> OutboxRecord {
+        let (kind, payload) = event.to_columns().unwrap();
+        OutboxRecord {
+            id: 1,
+            kind,
+            payload,
+            attempts: 0,
+            last_error: None,
+            available_at: Utc::now(),
+            delivered_at: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_event_round_trip() {
+        let events = vec![
+            OutboxEvent::NodeConfigUpdate { node_id: 7 },
+            OutboxEvent::InvalidateActiveNodes,
+            OutboxEvent::InvalidateNodeGroups { tenant_id: 1 },
+            OutboxEvent::PurgeSubscriptions(PurgeScope::AllSubscriptions),
+            OutboxEvent::PurgeSubscriptions(PurgeScope::User(3)),
+            OutboxEvent::InvalidateUserPackage { user_id: 3 },
+            OutboxEvent::OrderEvent(OrderEvent {
+                id: 1,
+                order_id: 2,
+                order_no: "ORD1".to_string(),
+                user_id: 3,
+                from_status: OrderStatus::AwaitingPayment,
+                to_status: OrderStatus::Completed,
+                created_at: Utc::now(),
+            }),
+            OutboxEvent::TopupEvent(TopupEvent {
+                request_id: 4,
+                user_id: 3,
+                tenant_id: 1,
+                amount: Coins::new(500),
+                status: TopupStatus::Approved,
+                admin_note: None,
+                created_at: Utc::now(),
+            }),
+            OutboxEvent::AdjustmentEvent(AdjustmentEvent {
+                adjustment_id: 5,
+                user_id: 3,
+                tenant_id: 1,
+                amount: Coins::new(-20_000),
+                status: AdjustmentStatus::Pending,
+                requested_by: Some(1),
+                reviewed_by: None,
+                created_at: Utc::now(),
+            }),
+            OutboxEvent::MaintenanceEvent(MaintenanceEvent {
+                maintenance_id: 6,
+                node_id: 7,
+                node_name: "HK-1".to_string(),
+                tenant_id: 1,
+                status: MaintenanceStatus::Scheduled,
+                starts_at: Utc::now(),
+                ends_at: Utc::now() + Duration::hours(2),
+                reason: Some("Kernel upgrade".to_string()),
+                user_ids: vec![3],
+                created_at: Utc::now(),
+            }),
+        ];
+
+        for event in &events {
+            let parsed = OutboxEvent::from_record(&record(event)).unwrap();
+            assert_eq!(serde_json::to_value(&parsed).unwrap(), serde_json::to_value(event).unwrap());
+        }
+
+        let stored = record(&OutboxEvent::NodeConfigUpdate { node_id: 7 });
+        assert_eq!(stored.kind, "node_config_update");
+        assert_eq!(stored.payload, serde_json::json!({ "node_id": 7 }));
+    }
+
+    #[test]
+    fn test_unknown_event_is_rejected() {
+        let mut stored = record(&OutboxEvent::InvalidateActiveNodes);
+        stored.kind = "removed_event".to_string();
+        assert!(OutboxEvent::from_record(&stored).is_err());
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off() {
+        assert_eq!(retry_delay(1).num_seconds(), 2);
+        assert_eq!(retry_delay(3).num_seconds(), 8);
+        assert_eq!(retry_delay(20).num_seconds(), MAX_RETRY_DELAY_SECS);
+        assert_eq!(retry_delay(i32::MAX).num_seconds(), MAX_RETRY_DELAY_SECS);
+    }
+}
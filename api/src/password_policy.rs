@@ -0,0 +1,306 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::sync::{OnceLock, RwLock};
+
+use crate::config::PasswordPolicyConfig;
+use crate::models::FieldError;
+
+/// Longest password accepted whatever the policy, so hashing cost stays bounded
+pub const MAX_PASSWORD_LEN: usize = 128;
+
+/// Shortest email local part checked against passwords; shorter ones (`a@…`) would
+/// reject half of all passwords
+const MIN_EMAIL_PART_LEN: usize = 3;
+
+/// The 10,000 most common passwords, rejected when `PASSWORD_BLOCK_COMMON` is on
+///
+/// Site-specific additions can be supplied through `PASSWORD_BLACKLIST_FILE`.
+const BUNDLED_COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+static BUNDLED: OnceLock<HashSet<String>> = OnceLock::new();
+
+/// Passwords loaded from `PASSWORD_BLACKLIST_FILE` (bundled list excluded)
+static EXTRA_COMMON_PASSWORDS: OnceLock<RwLock<HashSet<String>>> = OnceLock::new();
+
+fn bundled_common_passwords() -> &'static HashSet<String> {
+    BUNDLED.get_or_init(|| parse_password_list(BUNDLED_COMMON_PASSWORDS))
+}
+
+fn extra_common_passwords() -> &'static RwLock<HashSet<String>> {
+    EXTRA_COMMON_PASSWORDS.get_or_init(|| RwLock::new(HashSet::new()))
+}
+
+/// A kind of character a policy can require
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharClass {
+    /// Any letter, in any script
+    Letter,
+    Lowercase,
+    Uppercase,
+    Digit,
+    /// Anything but letters and digits
+    Symbol,
+}
+
+impl CharClass {
+    /// Parse a `PASSWORD_REQUIRED_CLASSES` entry
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "letter" => Some(CharClass::Letter),
+            "lower" | "lowercase" => Some(CharClass::Lowercase),
+            "upper" | "uppercase" => Some(CharClass::Uppercase),
+            "digit" | "number" => Some(CharClass::Digit),
+            "symbol" | "special" => Some(CharClass::Symbol),
+            _ => None,
+        }
+    }
+
+    fn matches(self, c: char) -> bool {
+        match self {
+            CharClass::Letter => c.is_alphabetic(),
+            CharClass::Lowercase => c.is_lowercase(),
+            CharClass::Uppercase => c.is_uppercase(),
+            CharClass::Digit => c.is_numeric(),
+            CharClass::Symbol => !c.is_alphanumeric(),
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            CharClass::Letter => "a letter",
+            CharClass::Lowercase => "a lowercase letter",
+            CharClass::Uppercase => "an uppercase letter",
+            CharClass::Digit => "a digit",
+            CharClass::Symbol => "a symbol",
+        }
+    }
+}
+
+/// One way a password breaks the policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordViolation {
+    /// Shorter than the policy's minimum length
+    TooShort(usize),
+    TooLong,
+    MissingClass(CharClass),
+    /// Contains the local part of the account's email
+    ContainsEmail,
+    /// On the common password blacklist
+    Common,
+}
+
+impl PasswordViolation {
+    pub fn message(self) -> String {
+        match self {
+            PasswordViolation::TooShort(min) => format!("Must be at least {} characters long", min),
+            PasswordViolation::TooLong => format!("Must be at most {} characters long", MAX_PASSWORD_LEN),
+            PasswordViolation::MissingClass(class) => format!("Must contain {}", class.description()),
+            PasswordViolation::ContainsEmail => "Must not contain your email address".to_string(),
+            PasswordViolation::Common => "Is too common, choose a less predictable password".to_string(),
+        }
+    }
+}
+
+/// Whether `password` (any case) is on the common password blacklist
+pub fn is_common_password(password: &str) -> bool {
+    let password = password.to_lowercase();
+    if bundled_common_passwords().contains(&password) {
+        return true;
+    }
+    extra_common_passwords()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .contains(&password)
+}
+
+/// Every way `password` breaks `policy`; `email` is the account's address, when known
+pub fn check(policy: &PasswordPolicyConfig, password: &str, email: Option<&str>) -> Vec<PasswordViolation> {
+    let mut violations = Vec::new();
+
+    let len = password.chars().count();
+    if len < policy.min_length {
+        violations.push(PasswordViolation::TooShort(policy.min_length));
+    }
+    if len > MAX_PASSWORD_LEN {
+        violations.push(PasswordViolation::TooLong);
+    }
+
+    for &class in &policy.required_classes {
+        if !password.chars().any(|c| class.matches(c)) {
+            violations.push(PasswordViolation::MissingClass(class));
+        }
+    }
+
+    if policy.disallow_email {
+        let local_part = email
+            .and_then(|email| email.rsplit_once('@'))
+            .map(|(local, _)| local.trim().to_lowercase())
+            .filter(|local| local.chars().count() >= MIN_EMAIL_PART_LEN);
+        if local_part.is_some_and(|local| password.to_lowercase().contains(&local)) {
+            violations.push(PasswordViolation::ContainsEmail);
+        }
+    }
+
+    if policy.block_common && is_common_password(password) {
+        violations.push(PasswordViolation::Common);
+    }
+
+    violations
+}
+
+/// Check a password submitted in `field`, one field error per violation
+pub fn validate(
+    policy: &PasswordPolicyConfig,
+    field: &str,
+    password: &str,
+    email: Option<&str>,
+) -> Result<(), Vec<FieldError>> {
+    let violations = check(policy, password, email);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations
+            .into_iter()
+            .map(|violation| FieldError::new(field, violation.message()))
+            .collect())
+    }
+}
+
+/// Parse a password list: one password per line, blank lines ignored
+///
+/// There are no comments, since `#` is a common password character.
+pub fn parse_password_list(content: &str) -> HashSet<String> {
+    content
+        .lines()
+        .map(|line| line.trim().to_lowercase())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Load extra common passwords from `PASSWORD_BLACKLIST_FILE`, if set
+///
+/// Runs once at startup on every replica, since the list lives in process memory.
+pub fn load_blacklist_file(policy: &PasswordPolicyConfig) -> Result<()> {
+    let Some(path) = &policy.blacklist_file else {
+        return Ok(());
+    };
+
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read PASSWORD_BLACKLIST_FILE {}", path))?;
+    let passwords = parse_password_list(&content);
+    tracing::info!("Loaded {} common passwords from {}", passwords.len(), path);
+    *extra_common_passwords().write().unwrap_or_else(|e| e.into_inner()) = passwords;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> PasswordPolicyConfig {
+        PasswordPolicyConfig::default()
+    }
+
+    #[test]
+    fn test_default_policy_valid() {
+        assert!(check(&policy(), "Tiger2Lamp", None).is_empty());
+        assert!(check(&policy(), "abcdefgh1", None).is_empty());
+        assert!(check(&policy(), "correct horse 9", Some("alice@example.com")).is_empty());
+    }
+
+    #[test]
+    fn test_default_policy_length() {
+        assert_eq!(check(&policy(), "zq7w1", None), vec![PasswordViolation::TooShort(8)]);
+        assert_eq!(check(&policy(), "abc123", None), vec![PasswordViolation::TooShort(8), PasswordViolation::Common]);
+
+        let long_password = format!("{}1", "a".repeat(130));
+        assert_eq!(check(&policy(), &long_password, None), vec![PasswordViolation::TooLong]);
+
+        // Length is counted in characters, not bytes
+        assert!(check(&policy(), "密码密码密码密1", None).is_empty());
+    }
+
+    #[test]
+    fn test_default_policy_classes() {
+        assert_eq!(
+            check(&policy(), "90817263", None),
+            vec![PasswordViolation::MissingClass(CharClass::Letter)]
+        );
+        assert_eq!(
+            check(&policy(), "abcdefgh", None),
+            vec![PasswordViolation::MissingClass(CharClass::Digit)]
+        );
+
+        let strict = PasswordPolicyConfig {
+            required_classes: vec![CharClass::Lowercase, CharClass::Uppercase, CharClass::Digit, CharClass::Symbol],
+            ..policy()
+        };
+        assert!(check(&strict, "Tr0ub4dor&3", None).is_empty());
+        assert_eq!(
+            check(&strict, "tr0ub4dor3x", None),
+            vec![
+                PasswordViolation::MissingClass(CharClass::Uppercase),
+                PasswordViolation::MissingClass(CharClass::Symbol),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_email_local_part() {
+        let email = Some("Alice.Wong@example.com");
+        assert_eq!(check(&policy(), "alice.wong42", email), vec![PasswordViolation::ContainsEmail]);
+        assert_eq!(check(&policy(), "xALICE.WONGx9", email), vec![PasswordViolation::ContainsEmail]);
+        assert!(check(&policy(), "alice.wong42", None).is_empty());
+
+        // Very short local parts are not checked
+        assert!(check(&policy(), "ab-cd-9876", Some("ab@example.com")).is_empty());
+
+        let lenient = PasswordPolicyConfig {
+            disallow_email: false,
+            ..policy()
+        };
+        assert!(check(&lenient, "alice.wong42", email).is_empty());
+    }
+
+    #[test]
+    fn test_common_passwords() {
+        assert!(is_common_password("password123"));
+        assert!(is_common_password("PassWord123"));
+        assert!(is_common_password("qwerty123"));
+        assert!(!is_common_password("Tr0ub4dor&3"));
+        assert_eq!(check(&policy(), "password123", None), vec![PasswordViolation::Common]);
+        assert_eq!(check(&policy(), "P@ssw0rd", None), vec![PasswordViolation::Common]);
+
+        let lenient = PasswordPolicyConfig {
+            block_common: false,
+            ..policy()
+        };
+        assert!(check(&lenient, "password123", None).is_empty());
+    }
+
+    #[test]
+    fn test_validate_field_errors() {
+        assert_eq!(validate(&policy(), "password", "Tiger2Lamp", None), Ok(()));
+        assert_eq!(
+            validate(&policy(), "password", "password", None),
+            Err(vec![
+                FieldError::new("password", "Must contain a digit"),
+                FieldError::new("password", "Is too common, choose a less predictable password"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_password_list() {
+        let passwords = parse_password_list("  Hunter2 \n\n#hashtag\nletmein\n");
+        assert_eq!(passwords.len(), 3);
+        assert!(passwords.contains("hunter2"));
+        assert!(passwords.contains("#hashtag"));
+    }
+
+    #[test]
+    fn test_bundled_list_size() {
+        assert_eq!(bundled_common_passwords().len(), 10_000);
+    }
+}
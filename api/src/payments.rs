@@ -0,0 +1,124 @@
+use sqlx::PgPool;
+
+use crate::amounts::Coins;
+use crate::cache::{spawn_periodic_job, RedisCache};
+use crate::crypto;
+use crate::db;
+use crate::models::User;
+
+/// Header carrying the hex HMAC-SHA256 of the raw callback body
+pub const SIGNATURE_HEADER: &str = "x-payment-signature";
+
+/// Callback status reported by the gateway for a successful payment
+pub const STATUS_PAID: &str = "paid";
+/// Callback status reported by the gateway for a failed or cancelled payment
+pub const STATUS_FAILED: &str = "failed";
+
+/// Prefix of the payment reference recorded for simulated sandbox payments
+pub const SANDBOX_REFERENCE_PREFIX: &str = "SANDBOX-";
+
+/// Whether a user's purchases go to the sandbox: no real coins are moved and the
+/// orders are left out of revenue stats
+pub fn is_sandbox(sandbox_mode: bool, user: &User) -> bool {
+    sandbox_mode || user.is_sandbox
+}
+
+/// Payment reference for a simulated payment when the admin doesn't give one
+pub fn sandbox_reference(order_no: &str) -> String {
+    format!("{}{}", SANDBOX_REFERENCE_PREFIX, order_no)
+}
+
+/// Verify a callback signature against the shared webhook secret
+pub fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let expected = crypto::hmac_sha256_hex(secret, body);
+    crypto::secrets_match(&expected, &signature.trim().to_ascii_lowercase())
+}
+
+/// Split a price between coins and the external payment
+///
+/// `requested` is the number of coins the user wants to apply (defaults to everything
+/// available); the result is always within `0..=price` and `0..=available`.
+/// Coins and the gateway currency are assumed to share the same unit.
+pub fn split_payment(price: Coins, available_coins: Coins, requested: Option<Coins>) -> (Coins, Coins) {
+    let coins = requested
+        .unwrap_or(available_coins)
+        .clamp(Coins::ZERO, available_coins.max(Coins::ZERO))
+        .min(price);
+    (coins, price.saturating_sub(coins))
+}
+
+/// Build the gateway payment URL for an order
+pub fn payment_url(gateway_url: &str, order_no: &str, amount: Coins) -> String {
+    let separator = if gateway_url.contains('?') { '&' } else { '?' };
+    format!("{}{}order_no={}&amount={}", gateway_url, separator, order_no, amount)
+}
+
+/// Background task expiring unpaid mixed-payment orders and releasing their coins
+///
+/// Order events for expired orders are published by the outbox dispatcher.
+pub fn start_order_expiry_task(db_pool: PgPool, cache: RedisCache) -> tokio::task::JoinHandle<()> {
+    spawn_periodic_job(
+        cache,
+        "order_expiry",
+        std::time::Duration::from_secs(60),
+        move || {
+            let db_pool = db_pool.clone();
+            async move {
+                match db::expire_reserved_orders(&db_pool).await {
+                    Ok(expired) if !expired.is_empty() => {
+                        tracing::info!("Expired {} unpaid orders and released reserved coins", expired.len());
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::error!("Failed to expire unpaid orders: {}", e),
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_payment() {
+        let split = |price, available, requested: Option<i64>| {
+            let (coins, external) = split_payment(
+                Coins::new(price),
+                Coins::new(available),
+                requested.map(Coins::new),
+            );
+            (coins.get(), external.get())
+        };
+
+        assert_eq!(split(500, 200, None), (200, 300));
+        assert_eq!(split(500, 200, Some(50)), (50, 450));
+        assert_eq!(split(500, 200, Some(300)), (200, 300));
+        assert_eq!(split(500, 900, None), (500, 0));
+        assert_eq!(split(500, 200, Some(-10)), (0, 500));
+        assert_eq!(split(500, -5, None), (0, 500));
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let body = br#"{"order_no":"ORD-1-1","status":"paid"}"#;
+        let signature = crypto::hmac_sha256_hex("secret", body);
+
+        assert!(verify_signature("secret", body, &signature));
+        assert!(verify_signature("secret", body, &signature.to_uppercase()));
+        assert!(!verify_signature("other", body, &signature));
+        assert!(!verify_signature("secret", b"tampered", &signature));
+    }
+
+    #[test]
+    fn test_payment_url() {
+        assert_eq!(
+            payment_url("https://pay.example.com/checkout", "ORD-1-2", Coins::new(300)),
+            "https://pay.example.com/checkout?order_no=ORD-1-2&amount=300"
+        );
+        assert_eq!(
+            payment_url("https://pay.example.com/checkout?merchant=7", "ORD-1-2", Coins::new(300)),
+            "https://pay.example.com/checkout?merchant=7&order_no=ORD-1-2&amount=300"
+        );
+    }
+}
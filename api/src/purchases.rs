@@ -0,0 +1,301 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+
+use crate::amounts::Coins;
+use crate::cache::{spawn_periodic_job, RedisCache};
+use crate::db;
+use crate::models::{Package, User};
+use crate::status::UserStatus;
+
+/// Share of a referred user's first purchase credited to their referrer
+pub const REFERRAL_REBATE_PERCENTAGE: f64 = 0.10;
+
+/// Why a user can't buy a package right now
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ineligibility {
+    /// The package has been deactivated
+    PackageUnavailable,
+    AccountDisabled,
+    /// Spendable coins don't cover the price; only coin purchases need them to
+    InsufficientBalance,
+    OutOfStock,
+    /// The user already bought the package `max_per_user` times
+    PurchaseLimitReached,
+    /// The package is exclusive and the user has a package running when it would start,
+    /// or the other way round
+    ConflictsWithCurrentPackage,
+}
+
+impl Ineligibility {
+    /// API error message (see `i18n::ERROR_CATALOG`)
+    pub fn message(self) -> &'static str {
+        match self {
+            Ineligibility::PackageUnavailable => "Package is not available",
+            Ineligibility::AccountDisabled => "Account is disabled",
+            Ineligibility::InsufficientBalance => "Insufficient balance",
+            Ineligibility::OutOfStock => "Package is out of stock",
+            Ineligibility::PurchaseLimitReached => "Package purchase limit reached",
+            Ineligibility::ConflictsWithCurrentPackage => "Package cannot be held alongside your current package",
+        }
+    }
+}
+
+/// What deciding a purchase needs to know about the buyer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Buyer {
+    pub disabled: bool,
+    /// Balance less the coins reserved by orders awaiting payment
+    pub available_coins: Coins,
+    /// The user's pending, awaiting payment and completed orders for the package
+    pub purchases: i64,
+    /// Whether one of the user's active or scheduled packages conflicts with the package
+    pub conflicting_package: bool,
+}
+
+impl Buyer {
+    /// Load the user's standing towards `package`, bought to start at `starts_at`, on the
+    /// caller's connection
+    ///
+    /// Purchases call this in their transaction with the user's row locked, so a
+    /// concurrent purchase by the same user is counted.
+    pub async fn load(
+        conn: &mut PgConnection,
+        user: &User,
+        package: &Package,
+        starts_at: DateTime<Utc>,
+    ) -> Result<Self> {
+        let (purchases, conflicting_package) =
+            db::get_package_purchase_standing(conn, user.id, package.id, package.exclusive, starts_at).await?;
+
+        Ok(Buyer {
+            disabled: user.status == UserStatus::Disabled,
+            available_coins: user.coin_balance.saturating_sub(user.reserved_coins),
+            purchases,
+            conflicting_package,
+        })
+    }
+
+    /// Purchases left before `max_per_user` (None = unlimited)
+    pub fn remaining_purchases(&self, package: &Package) -> Option<i64> {
+        (package.max_per_user > 0).then(|| (package.max_per_user as i64 - self.purchases).max(0))
+    }
+}
+
+/// Longest a package purchase may be scheduled ahead
+pub const MAX_SCHEDULE_DAYS: i64 = 366;
+
+/// When a purchased package starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activation {
+    /// As soon as the purchase completes
+    Now,
+    /// When the last of the user's active and scheduled packages expires
+    OnExpiry,
+    At(DateTime<Utc>),
+}
+
+impl Activation {
+    /// Parse `activates_at`: omitted or `now`, `on_expiry`, or an RFC 3339 time
+    pub fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value.map(str::trim) {
+            None | Some("") | Some("now") => Ok(Activation::Now),
+            Some("on_expiry") => Ok(Activation::OnExpiry),
+            Some(value) => DateTime::parse_from_rfc3339(value)
+                .map(|at| Activation::At(at.with_timezone(&Utc)))
+                .map_err(|_| "activates_at must be 'now', 'on_expiry' or an RFC 3339 time".to_string()),
+        }
+    }
+
+    /// Start time of the package, or None to start it right away
+    ///
+    /// `last_expiry` is when the user's last active or scheduled package expires; with
+    /// none, `OnExpiry` starts now. A chosen time must be in the future, and no start may
+    /// be more than `MAX_SCHEDULE_DAYS` away.
+    pub fn resolve(self, last_expiry: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, String> {
+        let starts_at = match self {
+            Activation::Now => None,
+            Activation::OnExpiry => last_expiry.filter(|at| *at > now),
+            Activation::At(at) if at <= now => return Err("activates_at must be in the future".to_string()),
+            Activation::At(at) => Some(at),
+        };
+        if starts_at.is_some_and(|at| at > now + chrono::Duration::days(MAX_SCHEDULE_DAYS)) {
+            return Err(format!(
+                "Packages cannot be scheduled more than {} days ahead",
+                MAX_SCHEDULE_DAYS
+            ));
+        }
+        Ok(starts_at)
+    }
+}
+
+/// Every reason the buyer can't buy the package, empty if they can
+pub fn check(package: &Package, buyer: &Buyer) -> Vec<Ineligibility> {
+    let mut reasons = Vec::new();
+
+    if !package.is_active {
+        reasons.push(Ineligibility::PackageUnavailable);
+    }
+    if buyer.disabled {
+        reasons.push(Ineligibility::AccountDisabled);
+    }
+    if buyer.available_coins < package.price {
+        reasons.push(Ineligibility::InsufficientBalance);
+    }
+    if package.stock == Some(0) {
+        reasons.push(Ineligibility::OutOfStock);
+    }
+    if buyer.remaining_purchases(package) == Some(0) {
+        reasons.push(Ineligibility::PurchaseLimitReached);
+    }
+    if buyer.conflicting_package {
+        reasons.push(Ineligibility::ConflictsWithCurrentPackage);
+    }
+
+    reasons
+}
+
+/// Background task starting scheduled packages once their start time has come
+///
+/// Runs every minute, so a package may start up to a minute late.
+pub fn start_scheduled_package_task(db_pool: PgPool, cache: RedisCache) -> tokio::task::JoinHandle<()> {
+    spawn_periodic_job(
+        cache.clone(),
+        "scheduled_packages",
+        std::time::Duration::from_secs(60),
+        move || {
+            let db_pool = db_pool.clone();
+            let cache = cache.clone();
+            async move {
+                match db::activate_scheduled_user_packages(&db_pool).await {
+                    Ok((activated, outbox_ids)) => {
+                        if !activated.is_empty() {
+                            tracing::info!("Activated {} scheduled packages", activated.len());
+                        }
+                        crate::outbox::deliver_now(&db_pool, &cache, &outbox_ids).await;
+                    }
+                    Err(e) => tracing::error!("Failed to activate scheduled packages: {}", e),
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amounts::TrafficBytes;
+    use chrono::Utc;
+
+    fn package() -> Package {
+        Package {
+            id: 1,
+            name: "Basic".to_string(),
+            traffic_amount: TrafficBytes::new(1 << 30),
+            price: Coins::new(100),
+            duration_days: 30,
+            description: None,
+            is_active: true,
+            update_interval_hours: 24,
+            max_connections: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            tenant_id: 1,
+            stock: None,
+            max_per_user: 0,
+            exclusive: false,
+        }
+    }
+
+    fn buyer(available_coins: i64) -> Buyer {
+        Buyer {
+            disabled: false,
+            available_coins: Coins::new(available_coins),
+            purchases: 0,
+            conflicting_package: false,
+        }
+    }
+
+    #[test]
+    fn test_eligible() {
+        assert!(check(&package(), &buyer(100)).is_empty());
+        assert_eq!(buyer(100).remaining_purchases(&package()), None);
+    }
+
+    #[test]
+    fn test_reports_every_reason() {
+        let package = Package {
+            is_active: false,
+            stock: Some(0),
+            max_per_user: 2,
+            ..package()
+        };
+        let buyer = Buyer {
+            disabled: true,
+            purchases: 3,
+            conflicting_package: true,
+            ..buyer(99)
+        };
+
+        assert_eq!(
+            check(&package, &buyer),
+            vec![
+                Ineligibility::PackageUnavailable,
+                Ineligibility::AccountDisabled,
+                Ineligibility::InsufficientBalance,
+                Ineligibility::OutOfStock,
+                Ineligibility::PurchaseLimitReached,
+                Ineligibility::ConflictsWithCurrentPackage,
+            ]
+        );
+        assert_eq!(buyer.remaining_purchases(&package), Some(0));
+        assert_eq!(
+            serde_json::to_value(Ineligibility::OutOfStock).unwrap(),
+            serde_json::json!("out_of_stock")
+        );
+    }
+
+    #[test]
+    fn test_purchase_limit() {
+        let package = Package { max_per_user: 2, ..package() };
+        let mut buyer = buyer(100);
+
+        buyer.purchases = 1;
+        assert_eq!(buyer.remaining_purchases(&package), Some(1));
+        assert!(check(&package, &buyer).is_empty());
+
+        buyer.purchases = 2;
+        assert_eq!(check(&package, &buyer), vec![Ineligibility::PurchaseLimitReached]);
+    }
+
+    #[test]
+    fn test_parse_activation() {
+        assert_eq!(Activation::parse(None), Ok(Activation::Now));
+        assert_eq!(Activation::parse(Some("now")), Ok(Activation::Now));
+        assert_eq!(Activation::parse(Some("on_expiry")), Ok(Activation::OnExpiry));
+        assert_eq!(
+            Activation::parse(Some("2030-01-02T03:04:05+08:00")),
+            Ok(Activation::At("2030-01-01T19:04:05Z".parse().unwrap()))
+        );
+        assert!(Activation::parse(Some("next week")).is_err());
+    }
+
+    #[test]
+    fn test_resolve_activation() {
+        let now = Utc::now();
+        let expiry = now + chrono::Duration::days(10);
+
+        assert_eq!(Activation::Now.resolve(Some(expiry), now), Ok(None));
+        assert_eq!(Activation::OnExpiry.resolve(Some(expiry), now), Ok(Some(expiry)));
+        // Nothing running: starts right away
+        assert_eq!(Activation::OnExpiry.resolve(None, now), Ok(None));
+        assert_eq!(Activation::At(expiry).resolve(None, now), Ok(Some(expiry)));
+        assert!(Activation::At(now - chrono::Duration::minutes(1)).resolve(None, now).is_err());
+
+        let far = now + chrono::Duration::days(MAX_SCHEDULE_DAYS + 1);
+        assert!(Activation::At(far).resolve(None, now).is_err());
+        assert!(Activation::OnExpiry.resolve(Some(far), now).is_err());
+    }
+}
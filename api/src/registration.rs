@@ -0,0 +1,268 @@
+use std::collections::HashSet;
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+
+use crate::config::RegistrationPolicyConfig;
+
+/// Well-known disposable email providers, always blocked when disposable blocking is on
+///
+/// Extra domains can be supplied through `DISPOSABLE_EMAIL_DOMAINS_FILE`.
+pub const BUNDLED_DISPOSABLE_DOMAINS: &[&str] = &[
+    "10minutemail.com",
+    "10minutemail.net",
+    "20minutemail.com",
+    "33mail.com",
+    "burnermail.io",
+    "discard.email",
+    "dispostable.com",
+    "emailondeck.com",
+    "fakeinbox.com",
+    "getairmail.com",
+    "getnada.com",
+    "guerrillamail.biz",
+    "guerrillamail.com",
+    "guerrillamail.de",
+    "guerrillamail.info",
+    "guerrillamail.net",
+    "guerrillamail.org",
+    "guerrillamailblock.com",
+    "harakirimail.com",
+    "incognitomail.org",
+    "maildrop.cc",
+    "mailcatch.com",
+    "mailinator.com",
+    "mailinator.net",
+    "mailnesia.com",
+    "mintemail.com",
+    "mohmal.com",
+    "mytemp.email",
+    "sharklasers.com",
+    "spambox.us",
+    "spamgourmet.com",
+    "temp-mail.io",
+    "temp-mail.org",
+    "tempail.com",
+    "tempmail.com",
+    "tempmail.dev",
+    "tempmailo.com",
+    "tempr.email",
+    "throwawaymail.com",
+    "trashmail.com",
+    "trashmail.de",
+    "yopmail.com",
+    "yopmail.fr",
+    "yopmail.net",
+];
+
+/// Disposable domains loaded from `DISPOSABLE_EMAIL_DOMAINS_FILE` (bundled list excluded)
+static EXTRA_DISPOSABLE_DOMAINS: OnceLock<RwLock<HashSet<String>>> = OnceLock::new();
+
+fn extra_disposable_domains() -> &'static RwLock<HashSet<String>> {
+    EXTRA_DISPOSABLE_DOMAINS.get_or_init(|| RwLock::new(HashSet::new()))
+}
+
+/// Why a sign-up was refused by the email domain policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DomainRejection {
+    NotAllowed,
+    Denied,
+    Disposable,
+}
+
+impl DomainRejection {
+    /// API error message (see `i18n::ERROR_CATALOG`)
+    pub fn message(self) -> &'static str {
+        match self {
+            DomainRejection::NotAllowed => "Registration is not open to this email domain",
+            DomainRejection::Denied => "Registration from this email domain is not allowed",
+            DomainRejection::Disposable => "Disposable email addresses are not allowed",
+        }
+    }
+}
+
+/// Lowercased domain part of an email address
+pub fn email_domain(email: &str) -> Option<String> {
+    email
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.trim().trim_end_matches('.').to_ascii_lowercase())
+        .filter(|domain| !domain.is_empty())
+}
+
+/// Whether `domain` is `listed` or one of its subdomains
+fn domain_matches(domain: &str, listed: &str) -> bool {
+    domain == listed
+        || domain
+            .strip_suffix(listed)
+            .map_or(false, |prefix| prefix.ends_with('.'))
+}
+
+/// Whether `domain` (or a parent domain) is a known disposable provider
+pub fn is_disposable_domain(domain: &str) -> bool {
+    if BUNDLED_DISPOSABLE_DOMAINS
+        .iter()
+        .any(|listed| domain_matches(domain, listed))
+    {
+        return true;
+    }
+
+    let extra = extra_disposable_domains()
+        .read()
+        .unwrap_or_else(|e| e.into_inner());
+    // Check the domain and each parent domain
+    let mut candidate = domain;
+    loop {
+        if extra.contains(candidate) {
+            return true;
+        }
+        match candidate.split_once('.') {
+            Some((_, parent)) if parent.contains('.') => candidate = parent,
+            _ => return false,
+        }
+    }
+}
+
+/// Check an email address against the allow, deny and disposable lists
+pub fn check_email_domain(policy: &RegistrationPolicyConfig, email: &str) -> Result<(), DomainRejection> {
+    let domain = email_domain(email).ok_or(DomainRejection::NotAllowed)?;
+
+    if policy
+        .denied_email_domains
+        .iter()
+        .any(|listed| domain_matches(&domain, listed))
+    {
+        return Err(DomainRejection::Denied);
+    }
+
+    // An explicit allowlist is trusted over the disposable list
+    if !policy.allowed_email_domains.is_empty() {
+        return if policy
+            .allowed_email_domains
+            .iter()
+            .any(|listed| domain_matches(&domain, listed))
+        {
+            Ok(())
+        } else {
+            Err(DomainRejection::NotAllowed)
+        };
+    }
+
+    if policy.block_disposable_emails && is_disposable_domain(&domain) {
+        return Err(DomainRejection::Disposable);
+    }
+
+    Ok(())
+}
+
+/// Parse a disposable domain list: one domain per line, `#` comments and blank lines ignored
+pub fn parse_domain_list(content: &str) -> HashSet<String> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .map(|line| line.trim_start_matches('@').trim_end_matches('.').to_ascii_lowercase())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Periodically reload extra disposable domains from `DISPOSABLE_EMAIL_DOMAINS_FILE`
+///
+/// Runs on every replica since the list lives in process memory. A failed read keeps
+/// the previously loaded list.
+pub fn start_disposable_domain_refresh(policy: &RegistrationPolicyConfig) -> Option<tokio::task::JoinHandle<()>> {
+    let path = policy.disposable_domains_file.clone()?;
+    let period = Duration::from_secs(policy.disposable_domains_refresh_secs.max(60));
+
+    Some(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+
+            match tokio::fs::read_to_string(&path).await {
+                Ok(content) => {
+                    let domains = parse_domain_list(&content);
+                    tracing::debug!("Loaded {} disposable email domains from {}", domains.len(), path);
+                    *extra_disposable_domains()
+                        .write()
+                        .unwrap_or_else(|e| e.into_inner()) = domains;
+                }
+                Err(e) => tracing::warn!("Failed to read disposable email domains from {}: {}", path, e),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RegistrationPolicyConfig {
+        RegistrationPolicyConfig::default()
+    }
+
+    #[test]
+    fn test_email_domain() {
+        assert_eq!(email_domain("User@Example.COM"), Some("example.com".to_string()));
+        assert_eq!(email_domain("a@b@mail.example.org."), Some("mail.example.org".to_string()));
+        assert_eq!(email_domain("no-at-sign"), None);
+        assert_eq!(email_domain("user@"), None);
+    }
+
+    #[test]
+    fn test_domain_matches_subdomains_only_on_label_boundary() {
+        assert!(domain_matches("example.com", "example.com"));
+        assert!(domain_matches("mail.example.com", "example.com"));
+        assert!(!domain_matches("badexample.com", "example.com"));
+        assert!(!domain_matches("example.com.evil", "example.com"));
+    }
+
+    #[test]
+    fn test_disposable_domains_blocked_by_default() {
+        assert_eq!(
+            check_email_domain(&policy(), "user@mailinator.com"),
+            Err(DomainRejection::Disposable)
+        );
+        assert_eq!(
+            check_email_domain(&policy(), "user@inbox.yopmail.com"),
+            Err(DomainRejection::Disposable)
+        );
+        assert!(check_email_domain(&policy(), "user@gmail.com").is_ok());
+
+        let mut lenient = policy();
+        lenient.block_disposable_emails = false;
+        assert!(check_email_domain(&lenient, "user@mailinator.com").is_ok());
+    }
+
+    #[test]
+    fn test_allow_and_deny_lists() {
+        let mut policy = policy();
+        policy.denied_email_domains = vec!["spam.example".to_string()];
+        assert_eq!(
+            check_email_domain(&policy, "user@sub.spam.example"),
+            Err(DomainRejection::Denied)
+        );
+        assert!(check_email_domain(&policy, "user@example.com").is_ok());
+
+        policy.allowed_email_domains = vec!["corp.example".to_string(), "mailinator.com".to_string()];
+        assert!(check_email_domain(&policy, "user@corp.example").is_ok());
+        assert!(check_email_domain(&policy, "user@mailinator.com").is_ok());
+        assert_eq!(
+            check_email_domain(&policy, "user@gmail.com"),
+            Err(DomainRejection::NotAllowed)
+        );
+
+        // Deny wins over allow
+        policy.allowed_email_domains.push("spam.example".to_string());
+        assert_eq!(
+            check_email_domain(&policy, "user@spam.example"),
+            Err(DomainRejection::Denied)
+        );
+    }
+
+    #[test]
+    fn test_parse_domain_list() {
+        let domains = parse_domain_list("# header\nFoo.example\n\n@bar.example # trailing\n  baz.example.  \n");
+        assert_eq!(domains.len(), 3);
+        assert!(domains.contains("foo.example"));
+        assert!(domains.contains("bar.example"));
+        assert!(domains.contains("baz.example"));
+    }
+}
@@ -0,0 +1,221 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::cache::{spawn_periodic_job, PurgeScope, RedisCache};
+use crate::config::CredentialRotationConfig;
+use crate::db;
+use crate::models::{Node, Subscription};
+
+/// Most nodes rotated per run, so a large backlog is spread over several runs
+const ROTATION_BATCH_SIZE: i64 = 50;
+
+/// Published on `credentials:rotated` for notification consumers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RotationEvent {
+    NodeSecret {
+        node_id: i64,
+        previous_valid_until: Option<DateTime<Utc>>,
+    },
+    SubscriptionToken {
+        user_id: i64,
+        previous_valid_until: Option<DateTime<Utc>>,
+    },
+}
+
+/// Whether the node secret doubles as the proxy password/UUID in subscriptions
+///
+/// Mirrors `clash::merge_node_config`. Rotating such a secret changes every user's
+/// proxy credential, so automatic rotation skips these nodes.
+pub fn secret_is_proxy_credential(node: &Node) -> bool {
+    let field = match node.protocol.as_str() {
+        "shadowsocks" | "trojan" | "hysteria2" => "password",
+        "vmess" | "vless" => "uuid",
+        _ => return false,
+    };
+    node.config.get(field).is_none()
+}
+
+async fn publish(cache: &RedisCache, event: &RotationEvent) {
+    if let Err(e) = cache.publish_credential_rotation(event).await {
+        tracing::warn!("Failed to publish credential rotation event: {}", e);
+    }
+}
+
+/// Give a node a new secret; the agent picks it up on its next authenticated request
+///
+/// Returns the node with its new secret, or `None` if the node does not exist.
+pub async fn rotate_node_secret(
+    pool: &PgPool,
+    cache: &RedisCache,
+    policy: &CredentialRotationConfig,
+    node_id: i64,
+) -> Result<Option<Node>> {
+    let secret = crate::utils::generate_node_secret();
+    let Some(node) = db::rotate_node_secret(pool, node_id, &secret, policy.overlap_hours).await? else {
+        return Ok(None);
+    };
+
+    // Prompt the agent to fetch its config now, which hands it the new secret
+    if let Err(e) = cache.publish_node_config_update(node.id).await {
+        tracing::warn!("Failed to publish node config update: {}", e);
+    }
+    if secret_is_proxy_credential(&node) {
+        if let Err(e) = cache.purge_subscription_configs(PurgeScope::Node(node.id)).await {
+            tracing::warn!("Failed to purge subscription cache for node {}: {}", node.id, e);
+        }
+    }
+
+    let previous_valid_until = db::get_node_previous_secret_expiry(pool, node.id).await?;
+    publish(
+        cache,
+        &RotationEvent::NodeSecret {
+            node_id: node.id,
+            previous_valid_until,
+        },
+    )
+    .await;
+
+    Ok(Some(node))
+}
+
+/// Give a user a new subscription token; the old one keeps working during the overlap
+///
+/// Returns `None` if the user has no subscription yet.
+pub async fn rotate_subscription_token(
+    pool: &PgPool,
+    cache: &RedisCache,
+    policy: &CredentialRotationConfig,
+    user_id: i64,
+) -> Result<Option<Subscription>> {
+    let overlap_hours = policy.overlap_hours;
+    let rotated = db::with_unique_value(
+        crate::db_error::SUBSCRIPTIONS_TOKEN,
+        crate::utils::generate_subscription_token,
+        move |token| async move { db::rotate_subscription_token(pool, user_id, &token, overlap_hours).await },
+    )
+    .await?;
+    let Some(subscription) = rotated else {
+        return Ok(None);
+    };
+
+    if let Err(e) = cache.purge_subscription_configs(PurgeScope::User(user_id)).await {
+        tracing::warn!("Failed to purge subscription cache for user {}: {}", user_id, e);
+    }
+
+    publish(
+        cache,
+        &RotationEvent::SubscriptionToken {
+            user_id,
+            previous_valid_until: subscription.previous_token_expires_at,
+        },
+    )
+    .await;
+
+    Ok(Some(subscription))
+}
+
+/// Rotate node secrets past their maximum age and retire credentials whose overlap ended
+pub async fn run_rotation(pool: &PgPool, cache: &RedisCache, policy: &CredentialRotationConfig) -> Result<()> {
+    if policy.node_rotation_enabled() {
+        let due = db::list_nodes_due_for_secret_rotation(pool, policy.node_secret_max_age_days, ROTATION_BATCH_SIZE).await?;
+        for node in due {
+            if secret_is_proxy_credential(&node) {
+                tracing::warn!(
+                    "Node {} ({}) uses its secret as the proxy credential, set an explicit one in its config to enable rotation",
+                    node.id,
+                    node.name
+                );
+                continue;
+            }
+            match rotate_node_secret(pool, cache, policy, node.id).await {
+                Ok(_) => tracing::info!("Rotated secret of node {} ({})", node.id, node.name),
+                Err(e) => tracing::error!("Failed to rotate secret of node {}: {}", node.id, e),
+            }
+        }
+    }
+
+    let cleared = db::clear_expired_node_secrets(pool).await?;
+    if cleared > 0 {
+        tracing::info!("Retired {} previous node secrets", cleared);
+    }
+
+    // Configs cached under a retired token would otherwise be served until they expire
+    for user_id in db::clear_expired_subscription_tokens(pool).await? {
+        if let Err(e) = cache.purge_subscription_configs(PurgeScope::User(user_id)).await {
+            tracing::warn!("Failed to purge subscription cache for user {}: {}", user_id, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Background task applying the credential rotation policy
+pub fn start_credential_rotation_task(
+    db_pool: PgPool,
+    cache: RedisCache,
+    policy: CredentialRotationConfig,
+) -> tokio::task::JoinHandle<()> {
+    let job_cache = cache.clone();
+    spawn_periodic_job(
+        cache,
+        "credential_rotation",
+        std::time::Duration::from_secs(3600),
+        move || {
+            let db_pool = db_pool.clone();
+            let cache = job_cache.clone();
+            let policy = policy.clone();
+            async move {
+                if let Err(e) = run_rotation(&db_pool, &cache, &policy).await {
+                    tracing::error!("Credential rotation failed: {}", e);
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn node(protocol: &str, config: serde_json::Value) -> Node {
+        Node {
+            name: "edge-1".to_string(),
+            host: "edge-1.example.com".to_string(),
+            protocol: protocol.to_string(),
+            config,
+            max_users: 100,
+            ..Node::test_fixture()
+        }
+    }
+
+    #[test]
+    fn test_secret_is_proxy_credential() {
+        assert!(secret_is_proxy_credential(&node("shadowsocks", json!({ "method": "aes-256-gcm" }))));
+        assert!(!secret_is_proxy_credential(&node(
+            "shadowsocks",
+            json!({ "method": "aes-256-gcm", "password": "pw" })
+        )));
+        assert!(secret_is_proxy_credential(&node("trojan", json!({}))));
+        assert!(!secret_is_proxy_credential(&node(
+            "vless",
+            json!({ "uuid": "b831381d-6324-4d53-ad4f-8cda48b30811" })
+        )));
+        assert!(!secret_is_proxy_credential(&node("wireguard", json!({}))));
+    }
+
+    #[test]
+    fn test_rotation_event_payload() {
+        let event = RotationEvent::SubscriptionToken {
+            user_id: 7,
+            previous_valid_until: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({ "kind": "subscription_token", "user_id": 7, "previous_valid_until": null })
+        );
+    }
+}
@@ -0,0 +1,501 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::amounts::{Coins, TrafficBytes};
+use crate::cache::RedisCache;
+use crate::config::{Config, SecurityAlertConfig, SmtpAlertConfig, TelegramAlertConfig};
+use crate::db;
+use crate::i18n::{self, Lang};
+use crate::models::SecurityEvent;
+use crate::subscription_limit::SubscriptionLimit;
+
+/// Event type: repeated failed logins for one email
+pub const EVENT_FAILED_LOGINS: &str = "failed_logins";
+/// Event type: an admin adjusted a balance by at least the threshold
+pub const EVENT_BALANCE_ADJUSTMENT: &str = "balance_adjustment";
+/// Event type: an admin deleted a node
+pub const EVENT_NODE_DELETED: &str = "node_deleted";
+/// Event type: a user moved at least the threshold of traffic within the window
+pub const EVENT_HEAVY_TRAFFIC: &str = "heavy_traffic";
+/// Event type: an IP went over the per-IP caps on subscription fetches
+pub const EVENT_SUBSCRIPTION_FLOOD: &str = "subscription_flood";
+
+/// Event types accepted by `GET /api/admin/security/events`
+pub const EVENT_TYPES: &[&str] = &[
+    EVENT_FAILED_LOGINS,
+    EVENT_BALANCE_ADJUSTMENT,
+    EVENT_NODE_DELETED,
+    EVENT_HEAVY_TRAFFIC,
+    EVENT_SUBSCRIPTION_FLOOD,
+];
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+fn http_client() -> &'static reqwest::Client {
+    HTTP_CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// A security-relevant action worth recording and alerting on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecurityEventKind {
+    FailedLogins {
+        email: String,
+        /// Account behind the email, if there is one
+        user_id: Option<i64>,
+        failures: u32,
+        window_secs: u64,
+    },
+    BalanceAdjustment {
+        admin_id: i64,
+        user_id: i64,
+        amount: Coins,
+    },
+    NodeDeleted {
+        admin_id: i64,
+        node_id: i64,
+        node_name: String,
+    },
+    HeavyTraffic {
+        user_id: i64,
+        email: String,
+        traffic: TrafficBytes,
+        /// Distinct nodes the traffic went through
+        nodes: usize,
+        window_secs: u64,
+    },
+    /// The offending IP is the event's `ip_address`
+    SubscriptionFlood {
+        ip: String,
+        limit: SubscriptionLimit,
+        /// The cap that was exceeded
+        max: u32,
+        window_secs: u64,
+    },
+}
+
+impl SecurityEventKind {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            SecurityEventKind::FailedLogins { .. } => EVENT_FAILED_LOGINS,
+            SecurityEventKind::BalanceAdjustment { .. } => EVENT_BALANCE_ADJUSTMENT,
+            SecurityEventKind::NodeDeleted { .. } => EVENT_NODE_DELETED,
+            SecurityEventKind::HeavyTraffic { .. } => EVENT_HEAVY_TRAFFIC,
+            SecurityEventKind::SubscriptionFlood { .. } => EVENT_SUBSCRIPTION_FLOOD,
+        }
+    }
+
+    pub fn severity(&self) -> &'static str {
+        match self {
+            SecurityEventKind::NodeDeleted { .. } => "critical",
+            _ => "warning",
+        }
+    }
+
+    /// Admin who performed the action
+    pub fn actor_id(&self) -> Option<i64> {
+        match self {
+            SecurityEventKind::FailedLogins { .. }
+            | SecurityEventKind::HeavyTraffic { .. }
+            | SecurityEventKind::SubscriptionFlood { .. } => None,
+            SecurityEventKind::BalanceAdjustment { admin_id, .. } | SecurityEventKind::NodeDeleted { admin_id, .. } => {
+                Some(*admin_id)
+            }
+        }
+    }
+
+    /// Object the event is about, as (type, id)
+    pub fn target(&self) -> Option<(&'static str, i64)> {
+        match self {
+            SecurityEventKind::FailedLogins { user_id, .. } => user_id.map(|id| ("user", id)),
+            SecurityEventKind::BalanceAdjustment { user_id, .. } => Some(("user", *user_id)),
+            SecurityEventKind::NodeDeleted { node_id, .. } => Some(("node", *node_id)),
+            SecurityEventKind::HeavyTraffic { user_id, .. } => Some(("user", *user_id)),
+            SecurityEventKind::SubscriptionFlood { .. } => None,
+        }
+    }
+
+    pub fn details(&self) -> Value {
+        match self {
+            SecurityEventKind::FailedLogins {
+                email,
+                failures,
+                window_secs,
+                ..
+            } => json!({ "email": email, "failures": failures, "window_secs": window_secs }),
+            SecurityEventKind::BalanceAdjustment { amount, .. } => json!({ "amount": amount }),
+            SecurityEventKind::NodeDeleted { node_name, .. } => json!({ "node_name": node_name }),
+            SecurityEventKind::HeavyTraffic {
+                email,
+                traffic,
+                nodes,
+                window_secs,
+                ..
+            } => json!({ "email": email, "traffic": traffic, "nodes": nodes, "window_secs": window_secs }),
+            SecurityEventKind::SubscriptionFlood {
+                limit,
+                max,
+                window_secs,
+                ..
+            } => json!({ "limit": limit.as_str(), "max": max, "window_secs": window_secs }),
+        }
+    }
+
+    /// Alert text (see `i18n::TEMPLATE_CATALOG`)
+    pub fn message(&self, lang: Lang) -> String {
+        match self {
+            SecurityEventKind::FailedLogins {
+                email,
+                failures,
+                window_secs,
+                ..
+            } => i18n::render(
+                "security.failed_logins",
+                lang,
+                &[
+                    ("email", email.clone()),
+                    ("failures", failures.to_string()),
+                    ("minutes", (window_secs / 60).max(1).to_string()),
+                ],
+            ),
+            SecurityEventKind::BalanceAdjustment {
+                admin_id,
+                user_id,
+                amount,
+            } => i18n::render(
+                "security.balance_adjustment",
+                lang,
+                &[
+                    ("admin_id", admin_id.to_string()),
+                    ("user_id", user_id.to_string()),
+                    ("amount", amount.to_string()),
+                ],
+            ),
+            SecurityEventKind::NodeDeleted {
+                admin_id,
+                node_id,
+                node_name,
+            } => i18n::render(
+                "security.node_deleted",
+                lang,
+                &[
+                    ("admin_id", admin_id.to_string()),
+                    ("node_id", node_id.to_string()),
+                    ("node_name", node_name.clone()),
+                ],
+            ),
+            SecurityEventKind::HeavyTraffic {
+                email,
+                traffic,
+                nodes,
+                window_secs,
+                ..
+            } => i18n::render(
+                "security.heavy_traffic",
+                lang,
+                &[
+                    ("email", email.clone()),
+                    ("traffic", crate::clash::format_traffic(*traffic)),
+                    ("hours", (window_secs / 3600).max(1).to_string()),
+                    ("nodes", nodes.to_string()),
+                ],
+            ),
+            SecurityEventKind::SubscriptionFlood {
+                ip,
+                limit: SubscriptionLimit::Concurrency,
+                max,
+                ..
+            } => i18n::render(
+                "security.subscription_concurrency",
+                lang,
+                &[("ip", ip.clone()), ("max", max.to_string())],
+            ),
+            SecurityEventKind::SubscriptionFlood {
+                ip,
+                limit: SubscriptionLimit::Frequency,
+                max,
+                window_secs,
+            } => i18n::render(
+                "security.subscription_frequency",
+                lang,
+                &[
+                    ("ip", ip.clone()),
+                    ("max", max.to_string()),
+                    ("seconds", window_secs.to_string()),
+                ],
+            ),
+        }
+    }
+
+    /// Alerts sharing a key are throttled together
+    pub fn throttle_key(&self) -> String {
+        match self {
+            SecurityEventKind::FailedLogins { email, .. } => {
+                format!("{}:{}", EVENT_FAILED_LOGINS, email.to_lowercase())
+            }
+            SecurityEventKind::BalanceAdjustment { admin_id, .. } | SecurityEventKind::NodeDeleted { admin_id, .. } => {
+                format!("{}:{}", self.event_type(), admin_id)
+            }
+            SecurityEventKind::HeavyTraffic { user_id, .. } => format!("{}:{}", EVENT_HEAVY_TRAFFIC, user_id),
+            SecurityEventKind::SubscriptionFlood { ip, .. } => format!("{}:{}", EVENT_SUBSCRIPTION_FLOOD, ip),
+        }
+    }
+}
+
+/// Whether a balance adjustment is large enough to raise an event (threshold 0 disables)
+pub fn is_large_adjustment(amount: Coins, threshold: i64) -> bool {
+    threshold > 0 && amount.get().unsigned_abs() >= threshold as u64
+}
+
+/// Whether a user's traffic over the window reaches the heavy-traffic threshold (0 disables)
+pub fn is_heavy_traffic(traffic: TrafficBytes, threshold_bytes: i64) -> bool {
+    threshold_bytes > 0 && traffic.get() >= threshold_bytes
+}
+
+/// Whether a failed login count has just reached the threshold (0 disables)
+///
+/// Only the failure that crosses the threshold raises an event, so one burst records one event.
+pub fn reached_failed_login_threshold(failures: u32, threshold: u32) -> bool {
+    threshold > 0 && failures == threshold
+}
+
+/// Record a security event and alert on it unless an alert for the same subject went out recently
+pub async fn record_event(
+    pool: &PgPool,
+    cache: &RedisCache,
+    config: &Config,
+    kind: SecurityEventKind,
+    ip_address: Option<&str>,
+) -> Result<SecurityEvent> {
+    let alerts = &config.security_alerts;
+
+    // Fail open: a Redis outage should not silence alerts
+    let alert = match cache.claim_security_alert(&kind.throttle_key(), alerts.throttle_secs).await {
+        Ok(claimed) => claimed,
+        Err(e) => {
+            tracing::warn!("Failed to check security alert throttle: {}", e);
+            true
+        }
+    };
+
+    let message = kind.message(config.default_language);
+    let event = db::create_security_event(
+        pool,
+        kind.event_type(),
+        kind.severity(),
+        kind.actor_id(),
+        kind.target(),
+        ip_address,
+        &message,
+        &kind.details(),
+        alert,
+    )
+    .await?;
+    tracing::warn!("Security event {}: {}", event.event_type, event.message);
+
+    if alert {
+        if let Err(e) = cache.publish_security_event(&event).await {
+            tracing::warn!("Failed to publish security event: {}", e);
+        }
+
+        // Delivery can take seconds per channel, keep it off the request path
+        let alerts = alerts.clone();
+        let event = event.clone();
+        tokio::spawn(async move { deliver(&alerts, &event).await });
+    }
+
+    Ok(event)
+}
+
+/// Send an alert to every configured channel, logging failures
+pub async fn deliver(alerts: &SecurityAlertConfig, event: &SecurityEvent) {
+    let text = format!("[{}] {}", event.severity.to_uppercase(), event.message);
+
+    if let Some(telegram) = &alerts.telegram {
+        if let Err(e) = send_telegram(telegram, &text).await {
+            tracing::error!("Failed to send security alert to Telegram: {}", e);
+        }
+    }
+    if let Some(url) = &alerts.webhook_url {
+        if let Err(e) = send_webhook(url, event).await {
+            tracing::error!("Failed to send security alert to webhook: {}", e);
+        }
+    }
+    if let Some(email) = &alerts.email {
+        if let Err(e) = send_email(email, &format!("Security alert: {}", event.event_type), &text).await {
+            tracing::error!("Failed to send security alert email: {}", e);
+        }
+    }
+}
+
+async fn send_telegram(telegram: &TelegramAlertConfig, text: &str) -> Result<()> {
+    http_client()
+        .post(format!("https://api.telegram.org/bot{}/sendMessage", telegram.bot_token))
+        .json(&json!({ "chat_id": telegram.chat_id, "text": text }))
+        .send()
+        .await
+        .context("Telegram request failed")?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+async fn send_webhook(url: &str, event: &SecurityEvent) -> Result<()> {
+    http_client()
+        .post(url)
+        .json(event)
+        .send()
+        .await
+        .context("Webhook request failed")?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+async fn send_email(smtp: &SmtpAlertConfig, subject: &str, body: &str) -> Result<()> {
+    use lettre::transport::smtp::authentication::Credentials;
+    use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+    let mut builder = Message::builder()
+        .from(smtp.from.parse().context("Invalid SECURITY_ALERT_EMAIL_FROM")?)
+        .subject(subject);
+    for to in &smtp.to {
+        builder = builder.to(to.parse().context("Invalid SECURITY_ALERT_EMAIL_TO")?);
+    }
+    let message = builder.body(body.to_string())?;
+
+    let mut transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host)?.port(smtp.port);
+    if let (Some(username), Some(password)) = (&smtp.username, &smtp.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    transport.build().send(message).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_large_adjustment_threshold() {
+        assert!(is_large_adjustment(Coins::new(10000), 10000));
+        assert!(is_large_adjustment(Coins::new(-10000), 10000));
+        assert!(!is_large_adjustment(Coins::new(9999), 10000));
+        assert!(!is_large_adjustment(Coins::new(1_000_000), 0));
+        assert!(is_large_adjustment(Coins::new(i64::MIN), 1));
+    }
+
+    #[test]
+    fn test_failed_login_threshold_fires_once() {
+        assert!(!reached_failed_login_threshold(4, 5));
+        assert!(reached_failed_login_threshold(5, 5));
+        assert!(!reached_failed_login_threshold(6, 5));
+        assert!(!reached_failed_login_threshold(5, 0));
+    }
+
+    #[test]
+    fn test_event_fields() {
+        let kind = SecurityEventKind::NodeDeleted {
+            admin_id: 1,
+            node_id: 9,
+            node_name: "edge-9".to_string(),
+        };
+        assert_eq!(kind.event_type(), EVENT_NODE_DELETED);
+        assert_eq!(kind.severity(), "critical");
+        assert_eq!(kind.actor_id(), Some(1));
+        assert_eq!(kind.target(), Some(("node", 9)));
+        assert_eq!(kind.details(), json!({ "node_name": "edge-9" }));
+
+        let kind = SecurityEventKind::FailedLogins {
+            email: "Me@Example.com".to_string(),
+            user_id: None,
+            failures: 5,
+            window_secs: 900,
+        };
+        assert_eq!(kind.actor_id(), None);
+        assert_eq!(kind.target(), None);
+        assert_eq!(kind.throttle_key(), "failed_logins:me@example.com");
+        assert_eq!(
+            kind.message(Lang::En),
+            "5 failed logins for Me@Example.com within 15 minutes"
+        );
+    }
+
+    #[test]
+    fn test_heavy_traffic_event() {
+        assert!(is_heavy_traffic(TrafficBytes::new(100), 100));
+        assert!(!is_heavy_traffic(TrafficBytes::new(99), 100));
+        assert!(!is_heavy_traffic(TrafficBytes::new(i64::MAX), 0));
+
+        let kind = SecurityEventKind::HeavyTraffic {
+            user_id: 7,
+            email: "heavy@example.com".to_string(),
+            traffic: TrafficBytes::new(300 * 1024 * 1024 * 1024),
+            nodes: 4,
+            window_secs: 86400,
+        };
+        assert_eq!(kind.event_type(), EVENT_HEAVY_TRAFFIC);
+        assert_eq!(kind.actor_id(), None);
+        assert_eq!(kind.target(), Some(("user", 7)));
+        assert_eq!(kind.throttle_key(), "heavy_traffic:7");
+        assert_eq!(
+            kind.message(Lang::En),
+            "User heavy@example.com used 300.0GB within 24 hours through 4 nodes"
+        );
+    }
+
+    #[test]
+    fn test_subscription_flood_event() {
+        let kind = SecurityEventKind::SubscriptionFlood {
+            ip: "203.0.113.9".to_string(),
+            limit: SubscriptionLimit::Frequency,
+            max: 60,
+            window_secs: 60,
+        };
+        assert_eq!(kind.event_type(), EVENT_SUBSCRIPTION_FLOOD);
+        assert_eq!(kind.target(), None);
+        assert_eq!(kind.throttle_key(), "subscription_flood:203.0.113.9");
+        assert_eq!(kind.details()["limit"], "frequency");
+        assert_eq!(
+            kind.message(Lang::En),
+            "Subscription requests from 203.0.113.9 exceeded 60 within 60 seconds"
+        );
+
+        let kind = SecurityEventKind::SubscriptionFlood {
+            ip: "203.0.113.9".to_string(),
+            limit: SubscriptionLimit::Concurrency,
+            max: 4,
+            window_secs: 60,
+        };
+        assert_eq!(kind.message(Lang::Zh), "来自 203.0.113.9 的订阅请求同时超过 4 个");
+    }
+
+    #[test]
+    fn test_admin_events_throttle_per_admin() {
+        let delete = |node_id| SecurityEventKind::NodeDeleted {
+            admin_id: 3,
+            node_id,
+            node_name: String::new(),
+        };
+        assert_eq!(delete(1).throttle_key(), delete(2).throttle_key());
+        assert_ne!(
+            delete(1).throttle_key(),
+            SecurityEventKind::BalanceAdjustment {
+                admin_id: 3,
+                user_id: 1,
+                amount: Coins::new(1),
+            }
+            .throttle_key()
+        );
+    }
+}
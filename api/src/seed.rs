@@ -0,0 +1,389 @@
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
+use rand::Rng;
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::clash;
+use crate::config::{Argon2Config, OrderNoConfig};
+use crate::db;
+use crate::models::{Package, User};
+use crate::order_no;
+use crate::orders::OrderStatus;
+use crate::status::NodeStatus;
+use crate::tenancy::DEFAULT_TENANT_ID;
+use crate::utils::{generate_node_secret, hash_password};
+
+const GB: i64 = 1024 * 1024 * 1024;
+
+/// Packages created when the database has none: (name, traffic bytes, price, days, description)
+const DEMO_PACKAGES: &[(&str, i64, i64, i32, &str)] = &[
+    ("体验套餐", 10 * GB, 100, 30, "10GB 流量，有效期 30 天"),
+    ("标准套餐", 50 * GB, 500, 30, "50GB 流量，有效期 30 天"),
+    ("高级套餐", 100 * GB, 900, 30, "100GB 流量，有效期 30 天"),
+];
+
+/// Extra Clash rules appended on top of the migration defaults
+const DEMO_RULES: &str = "\
+DOMAIN-SUFFIX,t.me,Telegram
+DOMAIN-SUFFIX,youtube.com,Youtube
+DOMAIN-SUFFIX,googlevideo.com,Youtube
+DOMAIN-SUFFIX,netflix.com,Netflix
+DOMAIN-SUFFIX,openai.com,ChatGPT及其他AI
+DOMAIN-SUFFIX,bilibili.com,哔哩哔哩
+IP-CIDR,91.108.4.0/22,Telegram,no-resolve
+";
+
+/// Options for `seed_demo_data`
+#[derive(Debug, Clone)]
+pub struct SeedOptions {
+    pub admin_email: String,
+    pub admin_password: String,
+    pub user_count: usize,
+    pub user_password: String,
+    /// Days of traffic history generated per demo user
+    pub traffic_days: i64,
+    /// Cost used to hash the demo passwords
+    pub argon2: Argon2Config,
+}
+
+impl Default for SeedOptions {
+    fn default() -> Self {
+        Self {
+            admin_email: "demo-admin@example.com".to_string(),
+            admin_password: "demo-admin-123".to_string(),
+            user_count: 5,
+            user_password: "demo-user-123".to_string(),
+            traffic_days: 14,
+            argon2: Argon2Config::default(),
+        }
+    }
+}
+
+/// Counts of records created by a seed run (existing records are skipped)
+#[derive(Debug, Default)]
+pub struct SeedSummary {
+    pub packages: usize,
+    pub nodes: usize,
+    pub users: usize,
+    pub traffic_logs: usize,
+    pub clash_rules: usize,
+}
+
+impl std::fmt::Display for SeedSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} packages, {} nodes, {} users, {} traffic logs, {} clash rules",
+            self.packages, self.nodes, self.users, self.traffic_logs, self.clash_rules
+        )
+    }
+}
+
+/// Demo nodes covering every supported protocol, with configs the Clash generator accepts
+pub fn demo_nodes() -> Vec<(&'static str, &'static str, i32, &'static str, serde_json::Value)> {
+    vec![
+        (
+            "Hong Kong 01",
+            "hk1.demo.example.com",
+            8388,
+            "shadowsocks",
+            json!({ "method": "aes-256-gcm", "country": "HK", "region": "香港" }),
+        ),
+        (
+            "Japan 01",
+            "jp1.demo.example.com",
+            443,
+            "vmess",
+            json!({ "alter_id": 0, "security": "auto", "network": "tcp", "country": "JP", "region": "日本" }),
+        ),
+        (
+            "Singapore 01",
+            "sg1.demo.example.com",
+            443,
+            "trojan",
+            json!({ "sni": "sg1.demo.example.com", "skip_cert_verify": false, "country": "SG", "region": "新加坡" }),
+        ),
+        (
+            "United States 01",
+            "us1.demo.example.com",
+            443,
+            "hysteria2",
+            json!({ "sni": "us1.demo.example.com", "skip_cert_verify": false, "country": "US", "region": "美国", "traffic_ratio": 0.5 }),
+        ),
+        (
+            "Taiwan 01",
+            "tw1.demo.example.com",
+            443,
+            "vless",
+            json!({
+                "flow": "xtls-rprx-vision",
+                "network": "tcp",
+                "client_fingerprint": "chrome",
+                "reality": {
+                    "public_key": "demo-public-key-not-for-production",
+                    "short_ids": ["0123456789abcdef"]
+                },
+                "country": "TW",
+                "region": "台湾",
+                "traffic_ratio": 1.5
+            }),
+        ),
+    ]
+}
+
+/// Node secret for a demo protocol: VMess/VLESS use the secret as the client UUID
+pub fn demo_secret(protocol: &str) -> String {
+    match protocol {
+        "vmess" | "vless" => uuid::Uuid::new_v4().to_string(),
+        _ => generate_node_secret(),
+    }
+}
+
+/// Populate packages, demo nodes, a demo admin, demo users with packages and traffic
+/// history, and extra Clash rules. Safe to run repeatedly: existing records are kept.
+pub async fn seed_demo_data(pool: &PgPool, options: &SeedOptions) -> Result<SeedSummary> {
+    let mut summary = SeedSummary::default();
+
+    let packages = seed_packages(pool, &mut summary).await?;
+    let node_ids = seed_nodes(pool, &mut summary).await?;
+    seed_admin(pool, options, &mut summary).await?;
+
+    for i in 1..=options.user_count {
+        let email = format!("demo{}@example.com", i);
+        if db::get_user_by_email(pool, DEFAULT_TENANT_ID, &email).await?.is_some() {
+            continue;
+        }
+
+        let user = db::create_user_with_referral_code(
+            pool,
+            DEFAULT_TENANT_ID,
+            &email,
+            &hash_password(&options.user_password, &options.argon2)?,
+            None,
+        )
+        .await?;
+        summary.users += 1;
+
+        let package = &packages[(i - 1) % packages.len()];
+        seed_user_package(pool, &user, package).await?;
+        summary.traffic_logs += seed_traffic_history(pool, &user, &node_ids, options.traffic_days).await?;
+        db::create_subscription_with_new_token(pool, user.id).await?;
+    }
+
+    summary.clash_rules = seed_clash_rules(pool).await?;
+
+    Ok(summary)
+}
+
+async fn seed_packages(pool: &PgPool, summary: &mut SeedSummary) -> Result<Vec<Package>> {
+    let existing = db::list_all_packages(pool).await?;
+    if !existing.is_empty() {
+        return Ok(existing);
+    }
+
+    let mut packages = Vec::with_capacity(DEMO_PACKAGES.len());
+    for (name, traffic, price, days, description) in DEMO_PACKAGES {
+        packages.push(
+            db::create_package(pool, DEFAULT_TENANT_ID, name, *traffic, *price, *days, Some(*description), None, None)
+                .await?,
+        );
+        summary.packages += 1;
+    }
+
+    Ok(packages)
+}
+
+async fn seed_nodes(pool: &PgPool, summary: &mut SeedSummary) -> Result<Vec<i64>> {
+    let existing = db::list_all_nodes(pool, None).await?;
+    let mut node_ids = Vec::new();
+
+    for (sort_order, (name, host, port, protocol, config)) in demo_nodes().into_iter().enumerate() {
+        if let Some(node) = existing.iter().find(|n| n.name == name) {
+            node_ids.push(node.id);
+            continue;
+        }
+
+        let secret = demo_secret(protocol);
+        let node = db::create_node(pool, DEFAULT_TENANT_ID, name, host, port, protocol, &secret, config, &[]).await?;
+        db::update_node(
+            &mut *pool.acquire().await?,
+            node.id,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(NodeStatus::Online),
+            Some(true),
+            Some(sort_order as i32),
+            None,
+        )
+        .await?;
+        node_ids.push(node.id);
+        summary.nodes += 1;
+    }
+
+    Ok(node_ids)
+}
+
+async fn seed_admin(pool: &PgPool, options: &SeedOptions, summary: &mut SeedSummary) -> Result<()> {
+    if db::get_user_by_email(pool, DEFAULT_TENANT_ID, &options.admin_email).await?.is_some() {
+        return Ok(());
+    }
+
+    let admin = db::create_user_with_referral_code(
+        pool,
+        DEFAULT_TENANT_ID,
+        &options.admin_email,
+        &hash_password(&options.admin_password, &options.argon2)?,
+        None,
+    )
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE users SET is_admin = true, is_platform_admin = true, updated_at = NOW() WHERE id = $1
+        "#,
+    )
+    .bind(admin.id)
+    .execute(pool)
+    .await?;
+    summary.users += 1;
+
+    Ok(())
+}
+
+/// Give a demo user coins and a completed purchase of `package`
+async fn seed_user_package(pool: &PgPool, user: &User, package: &Package) -> Result<()> {
+    let recharge = package.price.checked_add(package.price).context("Package price overflow")?;
+    let purchase = package.price.checked_neg().context("Package price overflow")?;
+    let balance = recharge.checked_debit(package.price).context("Package price overflow")?;
+    db::create_coin_transaction(pool, user.id, recharge, "recharge", Some("Demo recharge")).await?;
+    db::create_coin_transaction(pool, user.id, purchase, "purchase", Some(&package.name)).await?;
+    db::update_user_coin_balance(pool, user.id, balance).await?;
+
+    let order_no = order_no::generate(&OrderNoConfig::default(), user.id, Utc::now());
+    let order = db::create_order(pool, &order_no, user.id, package.id, package.price).await?;
+    db::transition_order(&mut *pool.acquire().await?, &order, OrderStatus::Completed, None).await?;
+
+    db::create_user_package(
+        pool,
+        user.id,
+        package.id,
+        order.id,
+        package.traffic_amount,
+        Utc::now() + Duration::days(package.duration_days as i64),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Insert one traffic log per node per day, backdated over `days`, and update usage totals
+async fn seed_traffic_history(pool: &PgPool, user: &User, node_ids: &[i64], days: i64) -> Result<usize> {
+    if node_ids.is_empty() {
+        return Ok(0);
+    }
+
+    // Generate samples up front: ThreadRng is not Send and must not live across awaits
+    let samples: Vec<(i64, i64, i64, i64)> = {
+        let mut rng = rand::thread_rng();
+        (1..=days)
+            .flat_map(|day| node_ids.iter().map(move |node_id| (day, *node_id)))
+            .map(|(day, node_id)| {
+                (day, node_id, rng.gen_range(5..50) * 1024 * 1024, rng.gen_range(50..500) * 1024 * 1024)
+            })
+            .collect()
+    };
+
+    let mut total = 0i64;
+    for (day, node_id, upload, download) in &samples {
+        sqlx::query(
+            r#"
+            INSERT INTO traffic_logs (user_id, node_id, upload, download, recorded_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(user.id)
+        .bind(node_id)
+        .bind(upload)
+        .bind(download)
+        .bind(Utc::now() - Duration::days(*day))
+        .execute(pool)
+        .await?;
+        total += upload + download;
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE user_packages SET traffic_used = LEAST(traffic_quota, $2) WHERE user_id = $1
+        "#,
+    )
+    .bind(user.id)
+    .bind(total)
+    .execute(pool)
+    .await?;
+    db::sync_user_traffic_totals(pool, user.id).await?;
+
+    Ok(samples.len())
+}
+
+/// Append demo rules that do not exist yet (matched by type and value)
+async fn seed_clash_rules(pool: &PgPool) -> Result<usize> {
+    let (parsed, errors) = clash::parse_rules_text(DEMO_RULES);
+    if let Some(error) = errors.first() {
+        anyhow::bail!("Invalid demo rule on line {}: {}", error.line, error.reason);
+    }
+
+    let existing = db::list_clash_rules(pool, false).await?;
+    let new_rules: Vec<_> = parsed
+        .into_iter()
+        .map(|(_, rule)| rule)
+        .filter(|rule| {
+            !existing
+                .iter()
+                .any(|r| r.rule_type == rule.rule_type && r.rule_value == rule.rule_value)
+        })
+        .collect();
+
+    if new_rules.is_empty() {
+        return Ok(0);
+    }
+
+    let created = db::bulk_create_clash_rules(pool, &new_rules)
+        .await
+        .context("Failed to insert demo Clash rules")?;
+
+    Ok(created.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Node;
+
+    #[test]
+    fn test_demo_nodes_generate_clash_proxies() {
+        for (name, host, port, protocol, config) in demo_nodes() {
+            let node = Node {
+                name: name.to_string(),
+                host: host.to_string(),
+                port,
+                protocol: protocol.to_string(),
+                secret: demo_secret(protocol),
+                config,
+                ..Node::test_fixture()
+            };
+
+            assert!(clash::node_to_clash_proxy(&node).is_some(), "demo node {} is invalid", name);
+        }
+    }
+
+    #[test]
+    fn test_demo_rules_parse() {
+        let (parsed, errors) = clash::parse_rules_text(DEMO_RULES);
+        assert!(errors.is_empty());
+        assert_eq!(parsed.len(), DEMO_RULES.lines().count());
+    }
+}
@@ -0,0 +1,136 @@
+use sqlx::{Encode, Postgres, QueryBuilder, Type};
+
+/// Builder for `UPDATE ... SET` statements where every column is optional
+///
+/// Each placeholder is numbered when its value is bound, so the SQL text and the bind
+/// order cannot drift apart. Table and column names are `'static` so they can only come
+/// from code, never from request data.
+pub struct UpdateBuilder<'args> {
+    builder: QueryBuilder<'args, Postgres>,
+    columns: Vec<&'static str>,
+}
+
+impl<'args> UpdateBuilder<'args> {
+    /// Start `UPDATE {table} SET updated_at = NOW()`
+    pub fn new(table: &'static str) -> Self {
+        let mut builder = QueryBuilder::new("UPDATE ");
+        builder.push(table).push(" SET updated_at = NOW()");
+
+        UpdateBuilder {
+            builder,
+            columns: Vec::new(),
+        }
+    }
+
+    /// Set `column` to `value` if a value was given
+    ///
+    /// Use `Option<Option<T>>` for nullable columns: `Some(None)` sets NULL.
+    pub fn set<T>(&mut self, column: &'static str, value: Option<T>) -> &mut Self
+    where
+        T: 'args + Encode<'args, Postgres> + Send + Type<Postgres>,
+    {
+        if let Some(value) = value {
+            self.builder.push(", ").push(column).push(" = ").push_bind(value);
+            self.columns.push(column);
+        }
+        self
+    }
+
+    /// Columns set so far, in bind order
+    pub fn columns(&self) -> &[&'static str] {
+        &self.columns
+    }
+
+    /// Whether no column was set (only `updated_at` would change)
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Finish with `WHERE id = $n RETURNING *`
+    pub fn where_id(mut self, id: i64) -> QueryBuilder<'args, Postgres> {
+        self.builder.push(" WHERE id = ").push_bind(id).push(" RETURNING *");
+        self.builder
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// The SQL `UpdateBuilder` must produce for `columns` set in order on `table`
+    pub(crate) fn expected_update_sql(table: &str, columns: &[&str]) -> String {
+        let mut sql = format!("UPDATE {} SET updated_at = NOW()", table);
+        for (i, column) in columns.iter().enumerate() {
+            sql.push_str(&format!(", {} = ${}", column, i + 1));
+        }
+        sql.push_str(&format!(" WHERE id = ${} RETURNING *", columns.len() + 1));
+        sql
+    }
+
+    /// Columns whose bit is set in `mask`, in order
+    pub(crate) fn masked<'a>(columns: &[&'a str], mask: u32) -> Vec<&'a str> {
+        columns
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| mask & (1 << i) != 0)
+            .map(|(_, column)| *column)
+            .collect()
+    }
+
+    /// `Some(value)` if bit `bit` of `mask` is set
+    pub(crate) fn pick<T>(mask: u32, bit: usize, value: T) -> Option<T> {
+        (mask & (1 << bit) != 0).then_some(value)
+    }
+
+    #[test]
+    fn test_empty_update_only_touches_updated_at() {
+        let update = UpdateBuilder::new("packages");
+        assert!(update.is_empty());
+        assert_eq!(
+            update.where_id(7).sql(),
+            "UPDATE packages SET updated_at = NOW() WHERE id = $1 RETURNING *"
+        );
+    }
+
+    #[test]
+    fn test_none_values_are_skipped() {
+        let mut update = UpdateBuilder::new("nodes");
+        update
+            .set("name", Some("edge-1"))
+            .set("port", None::<i32>)
+            .set("status", Some("online"));
+
+        assert_eq!(update.columns(), &["name", "status"]);
+        assert_eq!(
+            update.where_id(3).sql(),
+            "UPDATE nodes SET updated_at = NOW(), name = $1, status = $2 WHERE id = $3 RETURNING *"
+        );
+    }
+
+    #[test]
+    fn test_nullable_columns_bind_null() {
+        let mut update = UpdateBuilder::new("clash_rules");
+        update.set("description", Some(None::<&str>));
+
+        assert_eq!(update.columns(), &["description"]);
+        assert_eq!(update.where_id(1).sql(), expected_update_sql("clash_rules", &["description"]));
+    }
+
+    #[test]
+    fn test_placeholders_follow_bind_order_for_every_combination() {
+        const COLUMNS: [&str; 4] = ["a", "b", "c", "d"];
+
+        for mask in 0..(1u32 << COLUMNS.len()) {
+            let mut update = UpdateBuilder::new("t");
+            update
+                .set("a", pick(mask, 0, 1i64))
+                .set("b", pick(mask, 1, "two"))
+                .set("c", pick(mask, 2, true))
+                .set("d", pick(mask, 3, 4i32));
+
+            let expected = masked(&COLUMNS, mask);
+            assert_eq!(update.columns(), expected.as_slice());
+            assert_eq!(update.where_id(9).sql(), expected_update_sql("t", &expected));
+        }
+    }
+}
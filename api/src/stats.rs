@@ -0,0 +1,356 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::amounts::TrafficBytes;
+use crate::cache::{spawn_periodic_job, RedisCache};
+use crate::config::Config;
+use crate::db;
+use crate::models::{RevenueReport, RevenueSummary, TopTrafficUser};
+use crate::security::{self, SecurityEventKind};
+
+/// Period covered by the revenue report when no range is given (days)
+pub const DEFAULT_RANGE_DAYS: i64 = 30;
+/// Reporting time zone when `REPORT_TIMEZONE` is unset
+pub const DEFAULT_TIME_ZONE: &str = "UTC";
+/// Window of the top-traffic report when none is given
+pub const DEFAULT_TRAFFIC_WINDOW: &str = "24h";
+/// Longest window the top-traffic report accepts (days)
+pub const MAX_TRAFFIC_WINDOW_DAYS: i64 = 90;
+/// How often users are checked for heavy traffic (seconds)
+pub const HEAVY_TRAFFIC_CHECK_INTERVAL_SECS: u64 = 3600;
+
+/// Bucket size for time series in admin reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    Day,
+    Week,
+    Month,
+}
+
+impl Granularity {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "day" => Some(Granularity::Day),
+            "week" => Some(Granularity::Week),
+            "month" => Some(Granularity::Month),
+            _ => None,
+        }
+    }
+
+    /// Also the `date_trunc` field name
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Granularity::Day => "day",
+            Granularity::Week => "week",
+            Granularity::Month => "month",
+        }
+    }
+}
+
+/// Fail if Postgres does not know the reporting time zone (`REPORT_TIMEZONE`)
+pub async fn validate_time_zone(pool: &PgPool, time_zone: &str) -> Result<()> {
+    db::get_local_date(pool, Utc::now(), time_zone)
+        .await
+        .with_context(|| format!("Unknown time zone '{}'", time_zone))?;
+    Ok(())
+}
+
+/// A range bound as given by the caller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    /// `YYYY-MM-DD`, a day in the reporting time zone
+    Date(NaiveDate),
+    /// RFC 3339, an exact instant
+    Instant(DateTime<Utc>),
+}
+
+/// Parse a range bound: `YYYY-MM-DD` or RFC 3339
+pub fn parse_bound(value: &str) -> Option<Bound> {
+    let value = value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(Bound::Date(date));
+    }
+
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|t| Bound::Instant(t.with_timezone(&Utc)))
+}
+
+/// The instant a bound stands for; a bare end date is inclusive, so it becomes the start of the following day
+///
+/// Dates are converted by Postgres in `time_zone`, so days keep their local midnight across
+/// a DST change.
+pub async fn resolve_bound(pool: &PgPool, bound: Bound, is_end: bool, time_zone: &str) -> Result<DateTime<Utc>> {
+    match bound {
+        Bound::Instant(instant) => Ok(instant),
+        Bound::Date(date) => {
+            let date = if is_end {
+                date.succ_opt().context("Date out of range")?
+            } else {
+                date
+            };
+            db::get_day_start(pool, date, time_zone).await
+        }
+    }
+}
+
+/// Report range `[from, to)` from resolved bounds, defaulting to the last `DEFAULT_RANGE_DAYS` days
+pub fn report_range(
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), &'static str> {
+    let to = to.unwrap_or(now);
+    let from = from.unwrap_or(to - Duration::days(DEFAULT_RANGE_DAYS));
+
+    if from >= to {
+        return Err("start_date must be before end_date");
+    }
+
+    Ok((from, to))
+}
+
+/// Revenue per head, rounded to two decimals (0 when there is nobody to divide by)
+pub fn per_head(revenue: i64, heads: i64) -> f64 {
+    if heads <= 0 {
+        return 0.0;
+    }
+    (revenue as f64 / heads as f64 * 100.0).round() / 100.0
+}
+
+/// Revenue over `[from, to)`: totals, a time series and a per-package breakdown
+///
+/// Sales count when an order completes; refunds are netted out when they happen, so a
+/// refund can reduce a later period than the sale it reverses. ARPU divides net revenue
+/// by all non-admin users registered by the end of the range, ARPPU by paying users.
+/// Periods start at midnight in `time_zone`.
+pub async fn revenue_report(
+    pool: &PgPool,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    granularity: Granularity,
+    time_zone: &str,
+) -> Result<RevenueReport> {
+    let totals = db::get_revenue_totals(pool, from, to).await?;
+    let series = db::get_revenue_series(pool, granularity.as_str(), from, to, time_zone).await?;
+    let by_package = db::get_revenue_by_package(pool, from, to).await?;
+
+    // Negative when refunds outweigh sales in the range
+    let net_revenue = totals.gross_revenue.checked_sub(totals.refunds).unwrap_or_default();
+    let summary = RevenueSummary {
+        gross_revenue: totals.gross_revenue,
+        refunds: totals.refunds,
+        net_revenue,
+        order_count: totals.order_count,
+        buyers: totals.buyers,
+        new_buyers: totals.new_buyers,
+        returning_buyers: totals.buyers - totals.new_buyers,
+        users: totals.users,
+        arpu: per_head(net_revenue.get(), totals.users),
+        arppu: per_head(net_revenue.get(), totals.buyers),
+    };
+
+    Ok(RevenueReport {
+        granularity: granularity.as_str().to_string(),
+        time_zone: time_zone.to_string(),
+        from,
+        to,
+        summary,
+        series,
+        by_package,
+    })
+}
+
+/// Parse a report window such as `30m`, `24h` or `7d`, up to `MAX_TRAFFIC_WINDOW_DAYS`
+pub fn parse_window(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let unit = value.chars().last()?;
+    let amount: i64 = value[..value.len() - unit.len_utf8()].parse().ok()?;
+    if amount <= 0 {
+        return None;
+    }
+
+    let window = match unit {
+        'm' => Duration::try_minutes(amount)?,
+        'h' => Duration::try_hours(amount)?,
+        'd' => Duration::try_days(amount)?,
+        _ => return None,
+    };
+    (window <= Duration::days(MAX_TRAFFIC_WINDOW_DAYS)).then_some(window)
+}
+
+/// Share of the quota used, in percent rounded to one decimal (None without an active package)
+pub fn quota_used_percent(used: TrafficBytes, quota: TrafficBytes) -> Option<f64> {
+    if !quota.is_positive() {
+        return None;
+    }
+    Some((used.get() as f64 / quota.get() as f64 * 1000.0).round() / 10.0)
+}
+
+/// One row of the top-traffic report
+#[derive(Debug, Clone, Serialize)]
+pub struct TopTrafficEntry {
+    #[serde(flatten)]
+    pub user: TopTrafficUser,
+    /// Share of the active packages' quota used so far
+    pub quota_used_percent: Option<f64>,
+    pub node_count: usize,
+    /// Traffic in the window reached `SECURITY_HEAVY_TRAFFIC_THRESHOLD_BYTES`
+    pub flagged: bool,
+}
+
+impl TopTrafficEntry {
+    pub fn new(user: TopTrafficUser, threshold_bytes: i64) -> Self {
+        Self {
+            quota_used_percent: quota_used_percent(user.used, user.quota),
+            node_count: user.node_ids.len(),
+            flagged: security::is_heavy_traffic(user.total, threshold_bytes),
+            user,
+        }
+    }
+}
+
+/// Users ranked by traffic since `since`, flagged against the heavy-traffic threshold
+pub async fn top_traffic_report(
+    pool: &PgPool,
+    since: DateTime<Utc>,
+    limit: i64,
+    threshold_bytes: i64,
+) -> Result<Vec<TopTrafficEntry>> {
+    let users = db::get_top_traffic_users(pool, since, limit, 0).await?;
+    Ok(users
+        .into_iter()
+        .map(|user| TopTrafficEntry::new(user, threshold_bytes))
+        .collect())
+}
+
+/// Record a security event for every user over the heavy-traffic threshold
+///
+/// A user is flagged at most once per window: users with a heavy-traffic event
+/// inside the current window are skipped. Returns the number of events recorded.
+pub async fn flag_heavy_traffic(pool: &PgPool, cache: &RedisCache, config: &Config) -> Result<usize> {
+    let alerts = &config.security_alerts;
+    if alerts.heavy_traffic_threshold_bytes == 0 {
+        return Ok(0);
+    }
+
+    let window_secs = alerts.heavy_traffic_window_secs;
+    let since = Utc::now() - Duration::seconds(window_secs as i64);
+    let heavy = db::get_top_traffic_users(pool, since, i64::MAX, alerts.heavy_traffic_threshold_bytes).await?;
+    let already_flagged =
+        db::list_security_event_targets_since(pool, security::EVENT_HEAVY_TRAFFIC, "user", since).await?;
+
+    let mut recorded = 0;
+    for user in heavy.into_iter().filter(|u| !already_flagged.contains(&u.user_id)) {
+        let kind = SecurityEventKind::HeavyTraffic {
+            user_id: user.user_id,
+            email: user.email,
+            traffic: user.total,
+            nodes: user.node_ids.len(),
+            window_secs,
+        };
+        match security::record_event(pool, cache, config, kind, None).await {
+            Ok(_) => recorded += 1,
+            Err(e) => tracing::warn!("Failed to record heavy traffic of user {}: {}", user.user_id, e),
+        }
+    }
+
+    Ok(recorded)
+}
+
+/// Background task flagging heavy-traffic users (see `flag_heavy_traffic`)
+pub fn start_heavy_traffic_check_task(
+    db_pool: PgPool,
+    cache: RedisCache,
+    config: Config,
+) -> Option<tokio::task::JoinHandle<()>> {
+    if config.security_alerts.heavy_traffic_threshold_bytes == 0 {
+        return None;
+    }
+
+    let config = std::sync::Arc::new(config);
+    let period = std::time::Duration::from_secs(HEAVY_TRAFFIC_CHECK_INTERVAL_SECS);
+    Some(spawn_periodic_job(cache.clone(), "heavy_traffic_check", period, move || {
+        let db_pool = db_pool.clone();
+        let cache = cache.clone();
+        let config = config.clone();
+        async move {
+            match flag_heavy_traffic(&db_pool, &cache, &config).await {
+                Ok(0) => {}
+                Ok(count) => tracing::warn!("Flagged {} users for heavy traffic", count),
+                Err(e) => tracing::error!("Heavy traffic check failed: {}", e),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_granularity_parse() {
+        assert_eq!(Granularity::parse("week"), Some(Granularity::Week));
+        assert_eq!(Granularity::parse("Month"), None);
+        assert_eq!(Granularity::Month.as_str(), "month");
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_parse_bound() {
+        assert_eq!(parse_bound("2026-03-01"), Some(Bound::Date(date("2026-03-01"))));
+        assert_eq!(
+            parse_bound("2026-03-31T08:00:00+08:00"),
+            Some(Bound::Instant(utc("2026-03-31T00:00:00Z")))
+        );
+        assert_eq!(parse_bound("31/03/2026"), None);
+    }
+
+    #[test]
+    fn test_report_range() {
+        let now = utc("2026-03-31T12:00:00Z");
+        assert_eq!(report_range(None, None, now), Ok((now - Duration::days(30), now)));
+        let (from, to) = (utc("2026-03-01T00:00:00Z"), utc("2026-03-02T00:00:00Z"));
+        assert_eq!(report_range(Some(from), Some(to), now), Ok((from, to)));
+        assert_eq!(report_range(None, Some(to), now), Ok((to - Duration::days(30), to)));
+        assert!(report_range(Some(to), Some(from), now).is_err());
+        assert!(report_range(Some(now), None, now).is_err());
+    }
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("24h"), Some(Duration::hours(24)));
+        assert_eq!(parse_window("30m"), Some(Duration::minutes(30)));
+        assert_eq!(parse_window("7d"), Some(Duration::days(7)));
+        assert_eq!(parse_window("90d"), Some(Duration::days(90)));
+        assert_eq!(parse_window("91d"), None);
+        assert_eq!(parse_window("0h"), None);
+        assert_eq!(parse_window("-1h"), None);
+        assert_eq!(parse_window("24"), None);
+        assert_eq!(parse_window("h"), None);
+        assert_eq!(parse_window(""), None);
+    }
+
+    #[test]
+    fn test_quota_used_percent() {
+        assert_eq!(quota_used_percent(TrafficBytes::new(1), TrafficBytes::new(3)), Some(33.3));
+        assert_eq!(quota_used_percent(TrafficBytes::new(150), TrafficBytes::new(100)), Some(150.0));
+        assert_eq!(quota_used_percent(TrafficBytes::new(10), TrafficBytes::ZERO), None);
+    }
+
+    #[test]
+    fn test_per_head() {
+        assert_eq!(per_head(1000, 3), 333.33);
+        assert_eq!(per_head(1000, 0), 0.0);
+        assert_eq!(per_head(-50, 2), -25.0);
+    }
+}
@@ -0,0 +1,173 @@
+/// Define a status enum stored as text in a CHECK-constrained column
+///
+/// Each variant maps to one column value, used both in SQL and in JSON. Decoding a
+/// value outside the set is an error rather than a silent fallback, so a row the code
+/// does not understand fails loudly where it is read.
+macro_rules! status_enum {
+    (
+        $(#[$meta:meta])*
+        $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident => $value:literal),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ::serde::Serialize, ::serde::Deserialize)]
+        pub enum $name {
+            $($(#[$variant_meta])* #[serde(rename = $value)] $variant),+
+        }
+
+        impl $name {
+            pub const ALL: [$name; <[&str]>::len(&[$($value),+])] = [$($name::$variant),+];
+
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $($name::$variant => $value),+
+                }
+            }
+
+            pub fn parse(value: &str) -> Option<Self> {
+                Self::ALL.into_iter().find(|s| s.as_str() == value)
+            }
+
+            /// Accepted values, comma separated, for error messages
+            pub fn values() -> String {
+                Self::ALL.map(Self::as_str).join(", ")
+            }
+        }
+
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl ::sqlx::Type<::sqlx::Postgres> for $name {
+            fn type_info() -> ::sqlx::postgres::PgTypeInfo {
+                <str as ::sqlx::Type<::sqlx::Postgres>>::type_info()
+            }
+
+            fn compatible(ty: &::sqlx::postgres::PgTypeInfo) -> bool {
+                <str as ::sqlx::Type<::sqlx::Postgres>>::compatible(ty)
+            }
+        }
+
+        impl ::sqlx::Encode<'_, ::sqlx::Postgres> for $name {
+            fn encode_by_ref(&self, buf: &mut ::sqlx::postgres::PgArgumentBuffer) -> ::sqlx::encode::IsNull {
+                <&str as ::sqlx::Encode<::sqlx::Postgres>>::encode(self.as_str(), buf)
+            }
+        }
+
+        impl<'r> ::sqlx::Decode<'r, ::sqlx::Postgres> for $name {
+            fn decode(value: ::sqlx::postgres::PgValueRef<'r>) -> Result<Self, ::sqlx::error::BoxDynError> {
+                let value = <&str as ::sqlx::Decode<::sqlx::Postgres>>::decode(value)?;
+                Self::parse(value)
+                    .ok_or_else(|| format!("unknown {} '{}'", stringify!($name), value).into())
+            }
+        }
+    };
+}
+
+pub(crate) use status_enum;
+
+status_enum! {
+    /// Status stored in `users.status`
+    UserStatus {
+        Active => "active",
+        /// Blocked from logging in and from fetching subscriptions
+        Disabled => "disabled",
+    }
+}
+
+status_enum! {
+    /// Status stored in `nodes.status`
+    NodeStatus {
+        Online => "online",
+        Offline => "offline",
+        /// Set by an admin (or a traffic budget action); heartbeats leave it alone
+        Maintenance => "maintenance",
+        /// Locked out after repeated authentication failures until an admin lifts it
+        Suspended => "suspended",
+    }
+}
+
+impl NodeStatus {
+    /// Whether only an admin can move the node out of this status
+    pub fn is_held(self) -> bool {
+        matches!(self, NodeStatus::Maintenance | NodeStatus::Suspended)
+    }
+
+    /// Status after a heartbeat reporting `reported`: held statuses stay put
+    pub fn after_heartbeat(self, reported: ReportedNodeStatus) -> NodeStatus {
+        if self.is_held() {
+            self
+        } else {
+            reported.into()
+        }
+    }
+}
+
+status_enum! {
+    /// Status a node agent may report in its heartbeat
+    ///
+    /// A separate type so an agent cannot put its node into maintenance or lift a suspension.
+    ReportedNodeStatus {
+        Online => "online",
+        Offline => "offline",
+    }
+}
+
+impl From<ReportedNodeStatus> for NodeStatus {
+    fn from(status: ReportedNodeStatus) -> Self {
+        match status {
+            ReportedNodeStatus::Online => NodeStatus::Online,
+            ReportedNodeStatus::Offline => NodeStatus::Offline,
+        }
+    }
+}
+
+status_enum! {
+    /// Status stored in `user_packages.status`
+    UserPackageStatus {
+        Active => "active",
+        Expired => "expired",
+        /// Traffic quota used up
+        Exhausted => "exhausted",
+        /// Bought to start later, activated at `activates_at`
+        Scheduled => "scheduled",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for status in NodeStatus::ALL {
+            assert_eq!(NodeStatus::parse(status.as_str()), Some(status));
+            assert_eq!(
+                serde_json::to_string(&status).unwrap(),
+                format!("\"{}\"", status.as_str())
+            );
+        }
+        assert_eq!(UserStatus::parse("banned"), None);
+        assert_eq!(UserPackageStatus::values(), "active, expired, exhausted, scheduled");
+    }
+
+    #[test]
+    fn test_serde_rejects_unknown_values() {
+        assert_eq!(serde_json::from_str::<UserStatus>("\"disabled\"").unwrap(), UserStatus::Disabled);
+        assert!(serde_json::from_str::<UserStatus>("\"Disabled\"").is_err());
+        assert!(serde_json::from_str::<ReportedNodeStatus>("\"suspended\"").is_err());
+    }
+
+    #[test]
+    fn test_heartbeat_keeps_held_statuses() {
+        use NodeStatus::*;
+
+        assert_eq!(Offline.after_heartbeat(ReportedNodeStatus::Online), Online);
+        assert_eq!(Online.after_heartbeat(ReportedNodeStatus::Offline), Offline);
+        assert_eq!(Maintenance.after_heartbeat(ReportedNodeStatus::Online), Maintenance);
+        assert_eq!(Suspended.after_heartbeat(ReportedNodeStatus::Online), Suspended);
+    }
+}
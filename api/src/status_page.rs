@@ -0,0 +1,289 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{Node, StatusIncident};
+use crate::status::NodeStatus;
+
+/// How long a resolved incident stays on the public page
+pub const RESOLVED_INCIDENT_VISIBLE_DAYS: i64 = 3;
+/// Width of `status_incidents.title`
+pub const MAX_INCIDENT_TITLE_CHARS: usize = 200;
+
+crate::status::status_enum! {
+    /// Impact of a status page incident, stored in `status_incidents.severity`
+    IncidentSeverity {
+        /// A notice, e.g. planned maintenance; does not change the overall status
+        Info => "info",
+        /// Some nodes or features affected
+        Minor => "minor",
+        /// Most users affected
+        Major => "major",
+    }
+}
+
+/// Node availability as shown publicly; suspended nodes simply show as offline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Availability {
+    Online,
+    Maintenance,
+    Offline,
+}
+
+/// Coarse share of a node's user slots in use, so exact counts are not exposed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBucket {
+    /// Under 50%
+    Low,
+    /// 50-80%
+    Medium,
+    /// 80-100%
+    High,
+    Full,
+}
+
+/// Platform-wide status shown at the top of the page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverallStatus {
+    Operational,
+    Degraded,
+    MajorOutage,
+}
+
+/// One node on the public status page: no id, name or host
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusPageNode {
+    /// Display label from the node config, falling back to the country code
+    pub region: Option<String>,
+    /// ISO 3166-1 alpha-2 code from the node config
+    pub country: Option<String>,
+    pub availability: Availability,
+    /// Only reported for online nodes
+    pub load: Option<LoadBucket>,
+}
+
+/// Incident notice as shown publicly (without who posted it)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusPageIncident {
+    pub title: String,
+    pub message: String,
+    pub severity: IncidentSeverity,
+    pub started_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+/// Response for `GET /api/status`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusPage {
+    pub status: OverallStatus,
+    pub nodes_online: usize,
+    pub nodes_total: usize,
+    pub nodes: Vec<StatusPageNode>,
+    /// Open incidents first, then recently resolved ones
+    pub incidents: Vec<StatusPageIncident>,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// Bucket `current_users` out of `max_users` (a missing cap counts as low)
+pub fn load_bucket(current_users: i32, max_users: i32) -> LoadBucket {
+    if max_users <= 0 {
+        return LoadBucket::Low;
+    }
+
+    let ratio = current_users.max(0) as f64 / max_users as f64;
+    if ratio >= 1.0 {
+        LoadBucket::Full
+    } else if ratio >= 0.8 {
+        LoadBucket::High
+    } else if ratio >= 0.5 {
+        LoadBucket::Medium
+    } else {
+        LoadBucket::Low
+    }
+}
+
+pub fn availability(status: NodeStatus) -> Availability {
+    match status {
+        NodeStatus::Online => Availability::Online,
+        NodeStatus::Maintenance => Availability::Maintenance,
+        NodeStatus::Offline | NodeStatus::Suspended => Availability::Offline,
+    }
+}
+
+fn node_entry(node: &Node) -> StatusPageNode {
+    let config_str = |key: &str| {
+        node.config
+            .get(key)
+            .and_then(|v| v.as_str())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+    let country = config_str("country").map(|c| c.to_ascii_uppercase());
+    let availability = availability(node.status);
+
+    StatusPageNode {
+        region: config_str("region").or_else(|| country.clone()),
+        country,
+        availability,
+        load: (availability == Availability::Online).then(|| load_bucket(node.current_users, node.max_users)),
+    }
+}
+
+/// Worst of what open incidents declare and what the nodes show
+pub fn overall_status(nodes: &[StatusPageNode], incidents: &[StatusIncident]) -> OverallStatus {
+    let open = || incidents.iter().filter(|i| i.resolved_at.is_none());
+    let online = nodes.iter().filter(|n| n.availability == Availability::Online).count();
+
+    if open().any(|i| i.severity == IncidentSeverity::Major) || (!nodes.is_empty() && online == 0) {
+        OverallStatus::MajorOutage
+    } else if open().any(|i| i.severity == IncidentSeverity::Minor) || online < nodes.len() {
+        OverallStatus::Degraded
+    } else {
+        OverallStatus::Operational
+    }
+}
+
+/// Build the public status page from the subscription nodes and recent incidents
+///
+/// Unapproved nodes are left out: they are not in anyone's subscription.
+pub fn build(nodes: &[Node], incidents: &[StatusIncident], now: DateTime<Utc>) -> StatusPage {
+    let mut entries: Vec<StatusPageNode> = nodes.iter().filter(|n| n.approved).map(node_entry).collect();
+    // Grouped by region; the admin sort order would hint at which node is which
+    entries.sort_by(|a, b| a.region.cmp(&b.region).then(a.availability.cmp(&b.availability)));
+
+    let mut public_incidents: Vec<&StatusIncident> = incidents.iter().collect();
+    public_incidents.sort_by_key(|i| (i.resolved_at.is_some(), std::cmp::Reverse(i.started_at)));
+
+    StatusPage {
+        status: overall_status(&entries, incidents),
+        nodes_online: entries.iter().filter(|n| n.availability == Availability::Online).count(),
+        nodes_total: entries.len(),
+        nodes: entries,
+        incidents: public_incidents
+            .into_iter()
+            .map(|i| StatusPageIncident {
+                title: i.title.clone(),
+                message: i.message.clone(),
+                severity: i.severity,
+                started_at: i.started_at,
+                resolved_at: i.resolved_at,
+            })
+            .collect(),
+        generated_at: now,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn node(status: NodeStatus, config: serde_json::Value, current_users: i32) -> Node {
+        Node {
+            name: "HK 01".to_string(),
+            host: "hk1.example.com".to_string(),
+            protocol: "trojan".to_string(),
+            config,
+            status,
+            max_users: 100,
+            current_users,
+            ..Node::test_fixture()
+        }
+    }
+
+    fn incident(severity: IncidentSeverity, resolved: bool) -> StatusIncident {
+        StatusIncident {
+            id: 1,
+            title: "Degraded performance".to_string(),
+            message: String::new(),
+            severity,
+            started_at: Utc::now(),
+            resolved_at: resolved.then(Utc::now),
+            created_by: Some(1),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_load_bucket() {
+        assert_eq!(load_bucket(0, 100), LoadBucket::Low);
+        assert_eq!(load_bucket(49, 100), LoadBucket::Low);
+        assert_eq!(load_bucket(50, 100), LoadBucket::Medium);
+        assert_eq!(load_bucket(80, 100), LoadBucket::High);
+        assert_eq!(load_bucket(100, 100), LoadBucket::Full);
+        assert_eq!(load_bucket(120, 100), LoadBucket::Full);
+        assert_eq!(load_bucket(5, 0), LoadBucket::Low);
+    }
+
+    #[test]
+    fn test_nodes_are_anonymized() {
+        let nodes = vec![
+            node(NodeStatus::Online, json!({ "country": "jp", "region": "日本", "password": "pw" }), 85),
+            node(NodeStatus::Suspended, json!({ "country": "HK" }), 10),
+            node(NodeStatus::Maintenance, json!({}), 0),
+        ];
+        let page = build(&nodes, &[], Utc::now());
+
+        let body = serde_json::to_string(&page).unwrap();
+        assert!(!body.contains("hk1.example.com"));
+        assert!(!body.contains("HK 01"));
+        assert!(!body.contains("pw"));
+
+        assert_eq!(page.nodes_total, 3);
+        assert_eq!(page.nodes_online, 1);
+        assert_eq!(page.nodes[0].region, None);
+        assert_eq!(page.nodes[0].availability, Availability::Maintenance);
+        assert_eq!(page.nodes[1].region.as_deref(), Some("HK"));
+        assert_eq!(page.nodes[1].availability, Availability::Offline);
+        assert_eq!(page.nodes[1].load, None);
+        assert_eq!(page.nodes[2].country.as_deref(), Some("JP"));
+        assert_eq!(page.nodes[2].load, Some(LoadBucket::High));
+    }
+
+    #[test]
+    fn test_unapproved_nodes_are_hidden() {
+        let mut pending = node(NodeStatus::Offline, json!({}), 0);
+        pending.approved = false;
+        let page = build(&[node(NodeStatus::Online, json!({}), 0), pending], &[], Utc::now());
+
+        assert_eq!(page.nodes_total, 1);
+        assert_eq!(page.status, OverallStatus::Operational);
+    }
+
+    #[test]
+    fn test_overall_status() {
+        let online = build(&[node(NodeStatus::Online, json!({}), 0)], &[], Utc::now()).nodes;
+        let mixed = build(
+            &[node(NodeStatus::Online, json!({}), 0), node(NodeStatus::Offline, json!({}), 0)],
+            &[],
+            Utc::now(),
+        )
+        .nodes;
+        let down = build(&[node(NodeStatus::Offline, json!({}), 0)], &[], Utc::now()).nodes;
+
+        assert_eq!(overall_status(&online, &[]), OverallStatus::Operational);
+        assert_eq!(overall_status(&[], &[]), OverallStatus::Operational);
+        assert_eq!(overall_status(&mixed, &[]), OverallStatus::Degraded);
+        assert_eq!(overall_status(&down, &[]), OverallStatus::MajorOutage);
+
+        assert_eq!(overall_status(&online, &[incident(IncidentSeverity::Info, false)]), OverallStatus::Operational);
+        assert_eq!(overall_status(&online, &[incident(IncidentSeverity::Minor, false)]), OverallStatus::Degraded);
+        assert_eq!(overall_status(&online, &[incident(IncidentSeverity::Major, false)]), OverallStatus::MajorOutage);
+        assert_eq!(overall_status(&online, &[incident(IncidentSeverity::Major, true)]), OverallStatus::Operational);
+    }
+
+    #[test]
+    fn test_open_incidents_come_first() {
+        let mut resolved = incident(IncidentSeverity::Major, true);
+        resolved.title = "Resolved".to_string();
+        let open = incident(IncidentSeverity::Minor, false);
+
+        let page = build(&[], &[resolved, open], Utc::now());
+        assert_eq!(page.incidents[0].severity, IncidentSeverity::Minor);
+        assert_eq!(page.incidents[1].title, "Resolved");
+    }
+}
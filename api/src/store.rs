@@ -0,0 +1,432 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::db;
+use crate::models::{
+    Node, Order, Package, Subscription, SubscriptionGeoRestriction, SubscriptionPreferences, User, UserListFilter,
+};
+
+/// Account lookups handlers make outside transactions
+#[axum::async_trait]
+pub trait UserStore: Send + Sync {
+    async fn get_user_by_id(&self, user_id: i64) -> Result<Option<User>>;
+
+    /// A tenant's user by email
+    async fn get_user_by_email(&self, tenant_id: i64, email: &str) -> Result<Option<User>>;
+
+    /// The user's current token version; tokens carrying another one are revoked
+    async fn get_user_token_version(&self, user_id: i64) -> Result<Option<i32>>;
+
+    /// A page of the admin user list, newest first, optionally of one tenant only
+    async fn list_users(
+        &self,
+        tenant_id: Option<i64>,
+        filter: &UserListFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<User>>;
+
+    async fn count_users(&self, tenant_id: Option<i64>, filter: &UserListFilter) -> Result<i64>;
+}
+
+/// Node lookups handlers make outside transactions
+#[axum::async_trait]
+pub trait NodeStore: Send + Sync {
+    /// The node with its config and secret opened
+    async fn get_node_by_id(&self, node_id: i64) -> Result<Option<Node>>;
+
+    /// Until when the node's previous secret is still accepted, if it was rotated recently
+    async fn get_node_previous_secret_expiry(&self, node_id: i64) -> Result<Option<DateTime<Utc>>>;
+
+    /// Every node, newest first, optionally of one tenant only
+    async fn list_all_nodes(&self, tenant_id: Option<i64>) -> Result<Vec<Node>>;
+}
+
+/// Order lookups handlers make outside transactions
+#[axum::async_trait]
+pub trait OrderStore: Send + Sync {
+    async fn get_order_by_id(&self, order_id: i64) -> Result<Option<Order>>;
+
+    async fn get_order_by_order_no(&self, order_no: &str) -> Result<Option<Order>>;
+
+    /// A page of the user's orders, newest first
+    async fn list_orders_by_user(&self, user_id: i64, limit: i64, offset: i64) -> Result<Vec<Order>>;
+
+    /// A page of all orders, newest first, optionally of one tenant's users only
+    async fn list_all_orders(&self, tenant_id: Option<i64>, limit: i64, offset: i64) -> Result<Vec<Order>>;
+}
+
+/// Package lookups handlers make outside transactions
+#[axum::async_trait]
+pub trait PackageStore: Send + Sync {
+    async fn get_package_by_id(&self, package_id: i64) -> Result<Option<Package>>;
+
+    /// A tenant's packages on sale, cheapest first
+    async fn list_active_packages(&self, tenant_id: i64) -> Result<Vec<Package>>;
+}
+
+/// Subscription lookups handlers make outside transactions
+#[axum::async_trait]
+pub trait SubscriptionStore: Send + Sync {
+    /// The subscription a token belongs to, including a rotated token still in its overlap window
+    async fn get_subscription_by_token(&self, token: &str) -> Result<Option<Subscription>>;
+
+    async fn get_subscription_by_user_id(&self, user_id: i64) -> Result<Option<Subscription>>;
+
+    /// The user's override of the global GeoIP restrictions
+    async fn get_subscription_geo_restriction(&self, user_id: i64) -> Result<Option<SubscriptionGeoRestriction>>;
+
+    /// How the user's proxies are named
+    async fn get_subscription_preferences(&self, user_id: i64) -> Result<Option<SubscriptionPreferences>>;
+}
+
+/// Everything handlers read through `AppState::store`
+///
+/// Covers the user, node, order, package and subscription reads of the purchase,
+/// subscription and admin list paths, including token revocation and the tenant scope
+/// checks, so those handlers' logic can be unit tested against `MemoryStore`. Writes,
+/// and reads that must see a transaction's own changes, still go through `db` with
+/// `state.db_pool` or the transaction.
+pub trait Store: UserStore + NodeStore + OrderStore + PackageStore + SubscriptionStore {}
+
+impl<T: UserStore + NodeStore + OrderStore + PackageStore + SubscriptionStore> Store for T {}
+
+/// The Postgres store used in production; a thin layer over the `db` functions
+#[derive(Clone)]
+pub struct PgStore {
+    pool: PgPool,
+}
+
+impl PgStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[axum::async_trait]
+impl UserStore for PgStore {
+    async fn get_user_by_id(&self, user_id: i64) -> Result<Option<User>> {
+        db::get_user_by_id(&self.pool, user_id).await
+    }
+
+    async fn get_user_by_email(&self, tenant_id: i64, email: &str) -> Result<Option<User>> {
+        db::get_user_by_email(&self.pool, tenant_id, email).await
+    }
+
+    async fn get_user_token_version(&self, user_id: i64) -> Result<Option<i32>> {
+        db::get_user_token_version(&self.pool, user_id).await
+    }
+
+    async fn list_users(
+        &self,
+        tenant_id: Option<i64>,
+        filter: &UserListFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<User>> {
+        db::list_users(&self.pool, tenant_id, filter, limit, offset).await
+    }
+
+    async fn count_users(&self, tenant_id: Option<i64>, filter: &UserListFilter) -> Result<i64> {
+        db::count_users(&self.pool, tenant_id, filter).await
+    }
+}
+
+#[axum::async_trait]
+impl NodeStore for PgStore {
+    async fn get_node_by_id(&self, node_id: i64) -> Result<Option<Node>> {
+        db::get_node_by_id(&self.pool, node_id).await
+    }
+
+    async fn get_node_previous_secret_expiry(&self, node_id: i64) -> Result<Option<DateTime<Utc>>> {
+        db::get_node_previous_secret_expiry(&self.pool, node_id).await
+    }
+
+    async fn list_all_nodes(&self, tenant_id: Option<i64>) -> Result<Vec<Node>> {
+        db::list_all_nodes(&self.pool, tenant_id).await
+    }
+}
+
+#[axum::async_trait]
+impl OrderStore for PgStore {
+    async fn get_order_by_id(&self, order_id: i64) -> Result<Option<Order>> {
+        db::get_order_by_id(&self.pool, order_id).await
+    }
+
+    async fn get_order_by_order_no(&self, order_no: &str) -> Result<Option<Order>> {
+        db::get_order_by_order_no(&self.pool, order_no).await
+    }
+
+    async fn list_orders_by_user(&self, user_id: i64, limit: i64, offset: i64) -> Result<Vec<Order>> {
+        db::list_orders_by_user(&self.pool, user_id, limit, offset).await
+    }
+
+    async fn list_all_orders(&self, tenant_id: Option<i64>, limit: i64, offset: i64) -> Result<Vec<Order>> {
+        db::list_all_orders(&self.pool, tenant_id, limit, offset).await
+    }
+}
+
+#[axum::async_trait]
+impl PackageStore for PgStore {
+    async fn get_package_by_id(&self, package_id: i64) -> Result<Option<Package>> {
+        db::get_package_by_id(&self.pool, package_id).await
+    }
+
+    async fn list_active_packages(&self, tenant_id: i64) -> Result<Vec<Package>> {
+        db::list_active_packages(&self.pool, tenant_id).await
+    }
+}
+
+#[axum::async_trait]
+impl SubscriptionStore for PgStore {
+    async fn get_subscription_by_token(&self, token: &str) -> Result<Option<Subscription>> {
+        db::get_subscription_by_token(&self.pool, token).await
+    }
+
+    async fn get_subscription_by_user_id(&self, user_id: i64) -> Result<Option<Subscription>> {
+        db::get_subscription_by_user_id(&self.pool, user_id).await
+    }
+
+    async fn get_subscription_geo_restriction(&self, user_id: i64) -> Result<Option<SubscriptionGeoRestriction>> {
+        db::get_subscription_geo_restriction(&self.pool, user_id).await
+    }
+
+    async fn get_subscription_preferences(&self, user_id: i64) -> Result<Option<SubscriptionPreferences>> {
+        db::get_subscription_preferences(&self.pool, user_id).await
+    }
+}
+
+/// In-memory store for handler unit tests
+#[cfg(test)]
+pub mod memory {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::RwLock;
+
+    /// Records keyed by id (subscription records by owner); tests fill it with `insert_*`
+    ///
+    /// Secret rotations, rotated subscription tokens, user tags and user notes are never
+    /// present, so a tag or note filter on the user list matches nothing.
+    #[derive(Default)]
+    pub struct MemoryStore {
+        users: RwLock<HashMap<i64, User>>,
+        nodes: RwLock<HashMap<i64, Node>>,
+        orders: RwLock<HashMap<i64, Order>>,
+        packages: RwLock<HashMap<i64, Package>>,
+        subscriptions: RwLock<HashMap<i64, Subscription>>,
+        geo_restrictions: RwLock<HashMap<i64, SubscriptionGeoRestriction>>,
+        preferences: RwLock<HashMap<i64, SubscriptionPreferences>>,
+    }
+
+    impl MemoryStore {
+        pub fn insert_user(&self, user: User) {
+            self.users.write().unwrap().insert(user.id, user);
+        }
+
+        pub fn insert_node(&self, node: Node) {
+            self.nodes.write().unwrap().insert(node.id, node);
+        }
+
+        pub fn insert_order(&self, order: Order) {
+            self.orders.write().unwrap().insert(order.id, order);
+        }
+
+        pub fn insert_package(&self, package: Package) {
+            self.packages.write().unwrap().insert(package.id, package);
+        }
+
+        pub fn insert_subscription(&self, subscription: Subscription) {
+            self.subscriptions.write().unwrap().insert(subscription.user_id, subscription);
+        }
+
+        pub fn insert_geo_restriction(&self, restriction: SubscriptionGeoRestriction) {
+            self.geo_restrictions.write().unwrap().insert(restriction.user_id, restriction);
+        }
+
+        pub fn insert_preferences(&self, preferences: SubscriptionPreferences) {
+            self.preferences.write().unwrap().insert(preferences.user_id, preferences);
+        }
+
+        fn user_tenant(&self, user_id: i64) -> Option<i64> {
+            self.users.read().unwrap().get(&user_id).map(|user| user.tenant_id)
+        }
+    }
+
+    /// `LIMIT`/`OFFSET` over records already in order
+    fn page<T>(records: Vec<T>, limit: i64, offset: i64) -> Vec<T> {
+        records.into_iter().skip(offset.max(0) as usize).take(limit.max(0) as usize).collect()
+    }
+
+    fn in_scope(tenant_id: Option<i64>, record_tenant: i64) -> bool {
+        tenant_id.map_or(true, |tenant_id| tenant_id == record_tenant)
+    }
+
+    /// Users the admin list shows; nobody carries tags or notes here
+    fn listed_users(store: &MemoryStore, tenant_id: Option<i64>, filter: &UserListFilter) -> Vec<User> {
+        if filter.tag.is_some() || filter.note.is_some() {
+            return Vec::new();
+        }
+        let mut users: Vec<User> = store
+            .users
+            .read()
+            .unwrap()
+            .values()
+            .filter(|user| in_scope(tenant_id, user.tenant_id))
+            .cloned()
+            .collect();
+        users.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+        users
+    }
+
+    fn newest_orders_first(orders: &mut [Order]) {
+        orders.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+    }
+
+    #[axum::async_trait]
+    impl UserStore for MemoryStore {
+        async fn get_user_by_id(&self, user_id: i64) -> Result<Option<User>> {
+            Ok(self.users.read().unwrap().get(&user_id).cloned())
+        }
+
+        async fn get_user_by_email(&self, tenant_id: i64, email: &str) -> Result<Option<User>> {
+            Ok(self
+                .users
+                .read()
+                .unwrap()
+                .values()
+                .find(|user| user.tenant_id == tenant_id && user.email == email)
+                .cloned())
+        }
+
+        async fn get_user_token_version(&self, user_id: i64) -> Result<Option<i32>> {
+            Ok(self.users.read().unwrap().get(&user_id).map(|user| user.token_version))
+        }
+
+        async fn list_users(
+            &self,
+            tenant_id: Option<i64>,
+            filter: &UserListFilter,
+            limit: i64,
+            offset: i64,
+        ) -> Result<Vec<User>> {
+            Ok(page(listed_users(self, tenant_id, filter), limit, offset))
+        }
+
+        async fn count_users(&self, tenant_id: Option<i64>, filter: &UserListFilter) -> Result<i64> {
+            Ok(listed_users(self, tenant_id, filter).len() as i64)
+        }
+    }
+
+    #[axum::async_trait]
+    impl NodeStore for MemoryStore {
+        async fn get_node_by_id(&self, node_id: i64) -> Result<Option<Node>> {
+            Ok(self.nodes.read().unwrap().get(&node_id).cloned())
+        }
+
+        async fn get_node_previous_secret_expiry(&self, _node_id: i64) -> Result<Option<DateTime<Utc>>> {
+            Ok(None)
+        }
+
+        async fn list_all_nodes(&self, tenant_id: Option<i64>) -> Result<Vec<Node>> {
+            let mut nodes: Vec<Node> = self
+                .nodes
+                .read()
+                .unwrap()
+                .values()
+                .filter(|node| in_scope(tenant_id, node.tenant_id))
+                .cloned()
+                .collect();
+            nodes.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+            Ok(nodes)
+        }
+    }
+
+    #[axum::async_trait]
+    impl OrderStore for MemoryStore {
+        async fn get_order_by_id(&self, order_id: i64) -> Result<Option<Order>> {
+            Ok(self.orders.read().unwrap().get(&order_id).cloned())
+        }
+
+        async fn get_order_by_order_no(&self, order_no: &str) -> Result<Option<Order>> {
+            Ok(self
+                .orders
+                .read()
+                .unwrap()
+                .values()
+                .find(|order| order.order_no == order_no)
+                .cloned())
+        }
+
+        async fn list_orders_by_user(&self, user_id: i64, limit: i64, offset: i64) -> Result<Vec<Order>> {
+            let mut orders: Vec<Order> = self
+                .orders
+                .read()
+                .unwrap()
+                .values()
+                .filter(|order| order.user_id == user_id)
+                .cloned()
+                .collect();
+            newest_orders_first(&mut orders);
+            Ok(page(orders, limit, offset))
+        }
+
+        async fn list_all_orders(&self, tenant_id: Option<i64>, limit: i64, offset: i64) -> Result<Vec<Order>> {
+            let mut orders: Vec<Order> = self
+                .orders
+                .read()
+                .unwrap()
+                .values()
+                .filter(|order| tenant_id.is_none() || self.user_tenant(order.user_id) == tenant_id)
+                .cloned()
+                .collect();
+            newest_orders_first(&mut orders);
+            Ok(page(orders, limit, offset))
+        }
+    }
+
+    #[axum::async_trait]
+    impl PackageStore for MemoryStore {
+        async fn get_package_by_id(&self, package_id: i64) -> Result<Option<Package>> {
+            Ok(self.packages.read().unwrap().get(&package_id).cloned())
+        }
+
+        async fn list_active_packages(&self, tenant_id: i64) -> Result<Vec<Package>> {
+            let mut packages: Vec<Package> = self
+                .packages
+                .read()
+                .unwrap()
+                .values()
+                .filter(|package| package.is_active && package.tenant_id == tenant_id)
+                .cloned()
+                .collect();
+            packages.sort_by_key(|package| package.price);
+            Ok(packages)
+        }
+    }
+
+    #[axum::async_trait]
+    impl SubscriptionStore for MemoryStore {
+        async fn get_subscription_by_token(&self, token: &str) -> Result<Option<Subscription>> {
+            Ok(self
+                .subscriptions
+                .read()
+                .unwrap()
+                .values()
+                .find(|subscription| subscription.token == token)
+                .cloned())
+        }
+
+        async fn get_subscription_by_user_id(&self, user_id: i64) -> Result<Option<Subscription>> {
+            Ok(self.subscriptions.read().unwrap().get(&user_id).cloned())
+        }
+
+        async fn get_subscription_geo_restriction(&self, user_id: i64) -> Result<Option<SubscriptionGeoRestriction>> {
+            Ok(self.geo_restrictions.read().unwrap().get(&user_id).cloned())
+        }
+
+        async fn get_subscription_preferences(&self, user_id: i64) -> Result<Option<SubscriptionPreferences>> {
+            Ok(self.preferences.read().unwrap().get(&user_id).cloned())
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use axum::http::HeaderMap;
+
+/// Shortest access key a user may choose
+pub const MIN_KEY_LEN: usize = 8;
+/// Longest access key a user may choose
+pub const MAX_KEY_LEN: usize = 128;
+
+/// Why a fetch of a protected subscription was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyRejection {
+    /// Neither `?key=` nor an `Authorization` header was sent
+    Missing,
+    /// A key was sent but does not match
+    Invalid,
+}
+
+impl KeyRejection {
+    /// Status recorded in `clash_access_logs.response_status`
+    pub fn access_status(self) -> &'static str {
+        match self {
+            KeyRejection::Missing => "key_missing",
+            KeyRejection::Invalid => "key_invalid",
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            KeyRejection::Missing => "Subscription key required",
+            KeyRejection::Invalid => "Invalid subscription key",
+        }
+    }
+}
+
+/// Stored form of an access key (`subscriptions.access_key_hash`)
+pub fn hash_key(key: &str) -> String {
+    crate::crypto::sha256_hex(key.as_bytes())
+}
+
+/// Check a user-chosen access key: URL-unreserved characters only, so it can be put in
+/// a subscription URL as is
+pub fn validate_key(key: &str) -> Result<(), String> {
+    if key.len() < MIN_KEY_LEN || key.len() > MAX_KEY_LEN {
+        return Err(format!(
+            "Subscription key must be {} to {} characters",
+            MIN_KEY_LEN, MAX_KEY_LEN
+        ));
+    }
+    if !key.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~')) {
+        return Err("Subscription key may only contain letters, digits, '-', '.', '_' and '~'".to_string());
+    }
+    Ok(())
+}
+
+/// Key sent with a fetch: the `key` query parameter, else the `Authorization` header
+///
+/// The header may carry the key bare or as a `Bearer` credential.
+pub fn presented_key<'a>(query: Option<&'a str>, headers: &'a HeaderMap) -> Option<&'a str> {
+    query.filter(|key| !key.is_empty()).or_else(|| {
+        headers
+            .get(axum::http::header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.strip_prefix("Bearer ").unwrap_or(v).trim())
+            .filter(|key| !key.is_empty())
+    })
+}
+
+/// Check a fetch against a subscription's access key; `None` when it may be served
+///
+/// Unprotected subscriptions (`expected_hash` is `None`) ignore any key sent.
+pub fn check(expected_hash: Option<&str>, presented: Option<&str>) -> Option<KeyRejection> {
+    let expected_hash = expected_hash?;
+    match presented {
+        None => Some(KeyRejection::Missing),
+        Some(key) if crate::crypto::secrets_match(&hash_key(key), expected_hash) => None,
+        Some(_) => Some(KeyRejection::Invalid),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let hash = hash_key("correct-horse");
+        assert_eq!(check(None, None), None);
+        assert_eq!(check(None, Some("anything")), None);
+        assert_eq!(check(Some(&hash), Some("correct-horse")), None);
+        assert_eq!(check(Some(&hash), None), Some(KeyRejection::Missing));
+        assert_eq!(check(Some(&hash), Some("battery-staple")), Some(KeyRejection::Invalid));
+        assert_eq!(KeyRejection::Invalid.access_status(), "key_invalid");
+    }
+
+    #[test]
+    fn test_presented_key() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(presented_key(None, &headers), None);
+        assert_eq!(presented_key(Some(""), &headers), None);
+
+        headers.insert(axum::http::header::AUTHORIZATION, "Bearer from-header".parse().unwrap());
+        assert_eq!(presented_key(None, &headers), Some("from-header"));
+        // The query parameter wins
+        assert_eq!(presented_key(Some("from-query"), &headers), Some("from-query"));
+
+        headers.insert(axum::http::header::AUTHORIZATION, "bare-key".parse().unwrap());
+        assert_eq!(presented_key(None, &headers), Some("bare-key"));
+    }
+
+    #[test]
+    fn test_validate_key() {
+        assert!(validate_key("s3cret-key").is_ok());
+        assert!(validate_key("short").is_err());
+        assert!(validate_key(&"k".repeat(MAX_KEY_LEN + 1)).is_err());
+        assert!(validate_key("has a space").is_err());
+        assert!(validate_key("a&b=c#d?e").is_err());
+        assert!(validate_key("密钥密钥密钥密钥").is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+/// Subscription fetches in progress per client IP on this instance
+static IN_FLIGHT: OnceLock<Mutex<HashMap<String, u32>>> = OnceLock::new();
+
+fn in_flight() -> &'static Mutex<HashMap<String, u32>> {
+    IN_FLIGHT.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Which per-IP cap a subscription fetch hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionLimit {
+    /// Too many fetches in progress at once
+    Concurrency,
+    /// Too many fetches within the window
+    Frequency,
+}
+
+impl SubscriptionLimit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SubscriptionLimit::Concurrency => "concurrency",
+            SubscriptionLimit::Frequency => "frequency",
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            SubscriptionLimit::Concurrency => "Too many concurrent subscription requests",
+            SubscriptionLimit::Frequency => "Too many subscription requests",
+        }
+    }
+}
+
+/// A subscription fetch counted against its IP's concurrency cap until dropped
+#[derive(Debug)]
+pub struct InFlight {
+    /// None when concurrency isn't limited
+    ip: Option<String>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let Some(ip) = self.ip.take() else {
+            return;
+        };
+        let mut counts = in_flight().lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = counts.get_mut(&ip) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&ip);
+            }
+        }
+    }
+}
+
+/// Start a fetch from `ip`; None if it already has `max` fetches in progress (0 = unlimited)
+pub fn enter(ip: &str, max: u32) -> Option<InFlight> {
+    if max == 0 {
+        return Some(InFlight { ip: None });
+    }
+
+    let mut counts = in_flight().lock().unwrap_or_else(|e| e.into_inner());
+    let count = counts.entry(ip.to_string()).or_insert(0);
+    if *count >= max {
+        return None;
+    }
+    *count += 1;
+
+    Some(InFlight { ip: Some(ip.to_string()) })
+}
+
+/// Whether the `count`th fetch in a window goes over `max` (0 = unlimited)
+pub fn exceeds_frequency(count: u32, max: u32) -> bool {
+    max > 0 && count > max
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enter() {
+        let ip = "198.51.100.1";
+        let first = enter(ip, 2).expect("first fetch refused");
+        let second = enter(ip, 2).expect("second fetch refused");
+        assert!(enter(ip, 2).is_none());
+
+        // Another IP has its own count
+        assert!(enter("198.51.100.2", 2).is_some());
+
+        drop(first);
+        let third = enter(ip, 2).expect("fetch refused after one finished");
+        drop((second, third));
+        assert!(!in_flight().lock().unwrap().contains_key(ip));
+
+        // Unlimited fetches are not tracked
+        let untracked: Vec<InFlight> = (0..10).map(|_| enter(ip, 0).unwrap()).collect();
+        assert_eq!(untracked.len(), 10);
+        assert!(!in_flight().lock().unwrap().contains_key(ip));
+    }
+
+    #[test]
+    fn test_exceeds_frequency() {
+        assert!(!exceeds_frequency(60, 60));
+        assert!(exceeds_frequency(61, 60));
+        assert!(!exceeds_frequency(1000, 0));
+    }
+}
@@ -0,0 +1,67 @@
+use crate::clash::ClientFlavor;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set while a warm-up pass is waiting to start on this instance
+static PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Claim the next warm-up pass; false if one is already waiting, so a burst of purges
+/// triggers a single pass
+pub fn schedule() -> bool {
+    !PENDING.swap(true, Ordering::AcqRel)
+}
+
+/// Mark the waiting pass as started; purges from here on schedule another one, so
+/// changes made while it runs are not missed
+pub fn start() {
+    PENDING.store(false, Ordering::Release);
+}
+
+/// Configs to regenerate for recently active clients: one per token and client
+/// flavor, in the order given
+///
+/// The flavor is detected from the user agent alone; fetches that forced another one
+/// with `?flavor=` are regenerated on their next fetch as before.
+pub fn targets(clients: Vec<(String, Option<String>)>) -> Vec<(String, ClientFlavor)> {
+    let mut seen = HashSet::new();
+    clients
+        .into_iter()
+        .map(|(token, user_agent)| {
+            let flavor = ClientFlavor::detect(None, user_agent.as_deref());
+            (token, flavor)
+        })
+        .filter(|(token, flavor)| seen.insert((token.clone(), flavor.as_str())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule() {
+        assert!(schedule());
+        assert!(!schedule());
+        start();
+        assert!(schedule());
+        start();
+    }
+
+    #[test]
+    fn test_targets() {
+        let clients = vec![
+            ("a".to_string(), Some("mihomo/1.18.0".to_string())),
+            ("a".to_string(), Some("clash-verge/v1.3.8".to_string())),
+            ("b".to_string(), None),
+            ("a".to_string(), Some("ClashX/1.95.1".to_string())),
+        ];
+        assert_eq!(
+            targets(clients),
+            vec![
+                ("a".to_string(), ClientFlavor::Meta),
+                ("b".to_string(), ClientFlavor::Meta),
+                ("a".to_string(), ClientFlavor::Clash),
+            ]
+        );
+    }
+}
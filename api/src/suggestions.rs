@@ -0,0 +1,303 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::amounts::{Coins, TrafficBytes};
+use crate::cache::{spawn_periodic_job, RedisCache};
+use crate::config::UpgradeSuggestionConfig;
+use crate::db;
+use crate::models::Package;
+use crate::traffic::{self, PackageQuota, BURN_RATE_WINDOW_DAYS};
+
+/// Most packages offered in one suggestion
+pub const MAX_CANDIDATES: usize = 3;
+
+/// A package offered to a user who is running out of traffic
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PackageCandidate {
+    pub package_id: i64,
+    pub name: String,
+    pub traffic_amount: TrafficBytes,
+    pub price: Coins,
+    pub duration_days: i32,
+    /// Whether the package's traffic covers the projected shortfall
+    pub covers_shortfall: bool,
+}
+
+/// Offer of a bigger package to a user on pace to run out before their package expires
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UpgradeSuggestion {
+    pub remaining: TrafficBytes,
+    /// Average daily usage over the last `BURN_RATE_WINDOW_DAYS`
+    pub daily_usage: TrafficBytes,
+    pub projected_exhaustion_at: DateTime<Utc>,
+    /// Latest expiry among the active packages
+    pub expires_at: DateTime<Utc>,
+    /// Extra traffic needed to last until `expires_at` at the current pace
+    pub shortfall: TrafficBytes,
+    pub candidates: Vec<PackageCandidate>,
+}
+
+/// Published on `users:suggestions` for notification consumers
+#[derive(Debug, Clone, Serialize)]
+pub struct UpgradeSuggestionEvent<'a> {
+    pub user_id: i64,
+    #[serde(flatten)]
+    pub suggestion: &'a UpgradeSuggestion,
+}
+
+/// Suggest an upgrade if the user's recent burn rate runs out their traffic early
+///
+/// `None` unless traffic is projected to run out within `horizon_days` and at least
+/// `min_days_early` days before the active packages expire.
+pub fn suggest_upgrade(
+    quota: &PackageQuota,
+    used_in_window: TrafficBytes,
+    window_days: i64,
+    packages: &[Package],
+    settings: &UpgradeSuggestionConfig,
+    now: DateTime<Utc>,
+) -> Option<UpgradeSuggestion> {
+    let expires_at = quota.expires_at?;
+    let projected = traffic::project_exhaustion(quota.remaining, used_in_window, window_days, now)?;
+
+    if projected > now + chrono::Duration::days(settings.horizon_days)
+        || expires_at - projected < chrono::Duration::days(settings.min_days_early)
+    {
+        return None;
+    }
+
+    // Traffic needed until expiry at the same pace
+    let needed = used_in_window.get() as i128 * (expires_at - now).num_seconds() as i128
+        / (window_days as i128 * 86_400);
+    let shortfall = TrafficBytes::new((needed - quota.remaining.get() as i128).clamp(0, i64::MAX as i128) as i64);
+
+    Some(UpgradeSuggestion {
+        remaining: quota.remaining,
+        daily_usage: TrafficBytes::new(used_in_window.get() / window_days),
+        projected_exhaustion_at: projected,
+        expires_at,
+        shortfall,
+        candidates: pick_candidates(packages, shortfall),
+    })
+}
+
+/// The cheapest packages covering `shortfall`, or the largest ones if none does
+fn pick_candidates(packages: &[Package], shortfall: TrafficBytes) -> Vec<PackageCandidate> {
+    let mut offered: Vec<&Package> = packages
+        .iter()
+        .filter(|p| p.is_active && p.traffic_amount.is_positive())
+        .collect();
+
+    if offered.iter().any(|p| p.traffic_amount >= shortfall) {
+        offered.retain(|p| p.traffic_amount >= shortfall);
+        offered.sort_by_key(|p| (p.price, std::cmp::Reverse(p.traffic_amount)));
+    } else {
+        offered.sort_by_key(|p| (std::cmp::Reverse(p.traffic_amount), p.price));
+    }
+
+    offered
+        .into_iter()
+        .take(MAX_CANDIDATES)
+        .map(|p| PackageCandidate {
+            package_id: p.id,
+            name: p.name.clone(),
+            traffic_amount: p.traffic_amount,
+            price: p.price,
+            duration_days: p.duration_days,
+            covers_shortfall: p.traffic_amount >= shortfall,
+        })
+        .collect()
+}
+
+/// A user's current upgrade suggestion, from their packages and recent traffic logs
+pub async fn upgrade_suggestion(
+    pool: &PgPool,
+    user_id: i64,
+    packages: &[Package],
+    settings: &UpgradeSuggestionConfig,
+) -> Result<Option<UpgradeSuggestion>> {
+    let now = Utc::now();
+    let quota = traffic::remaining_quota(pool, user_id).await?;
+    if quota.active_packages == 0 {
+        return Ok(None);
+    }
+    let used = db::get_user_traffic_since(pool, user_id, now - chrono::Duration::days(BURN_RATE_WINDOW_DAYS)).await?;
+
+    Ok(suggest_upgrade(&quota, used, BURN_RATE_WINDOW_DAYS, packages, settings, now))
+}
+
+/// Publish suggestions for users who have not been notified recently
+pub async fn notify_upgrade_suggestions(
+    pool: &PgPool,
+    cache: &RedisCache,
+    settings: &UpgradeSuggestionConfig,
+) -> Result<usize> {
+    let since = Utc::now() - chrono::Duration::days(BURN_RATE_WINDOW_DAYS);
+    let mut notified = 0;
+    // Users come ordered by tenant, so each tenant's packages are loaded once
+    let mut packages = Vec::new();
+    let mut packages_tenant = None;
+
+    for (user_id, tenant_id) in db::list_users_with_recent_traffic(pool, since).await? {
+        if packages_tenant != Some(tenant_id) {
+            packages = db::list_active_packages(pool, tenant_id).await?;
+            packages_tenant = Some(tenant_id);
+        }
+
+        let suggestion = match upgrade_suggestion(pool, user_id, &packages, settings).await {
+            Ok(Some(suggestion)) => suggestion,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!("Failed to compute upgrade suggestion for user {}: {}", user_id, e);
+                continue;
+            }
+        };
+
+        match cache.claim_upgrade_suggestion_notice(user_id, settings.renotify_hours * 3600).await {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                // Skip rather than risk notifying the same user on every run
+                tracing::warn!("Failed to check upgrade suggestion notice for user {}: {}", user_id, e);
+                continue;
+            }
+        }
+
+        let event = UpgradeSuggestionEvent {
+            user_id,
+            suggestion: &suggestion,
+        };
+        if let Err(e) = cache.publish_upgrade_suggestion(&event).await {
+            tracing::warn!("Failed to publish upgrade suggestion for user {}: {}", user_id, e);
+            continue;
+        }
+        notified += 1;
+    }
+
+    Ok(notified)
+}
+
+/// Background task notifying users on pace to run out of traffic early
+pub fn start_upgrade_suggestion_task(
+    db_pool: PgPool,
+    cache: RedisCache,
+    settings: UpgradeSuggestionConfig,
+) -> Option<tokio::task::JoinHandle<()>> {
+    if !settings.notifications_enabled() {
+        return None;
+    }
+
+    let job_cache = cache.clone();
+    let period = std::time::Duration::from_secs(settings.check_interval_secs);
+    Some(spawn_periodic_job(cache, "upgrade_suggestions", period, move || {
+        let db_pool = db_pool.clone();
+        let cache = job_cache.clone();
+        let settings = settings.clone();
+        async move {
+            match notify_upgrade_suggestions(&db_pool, &cache, &settings).await {
+                Ok(0) => {}
+                Ok(notified) => tracing::info!("Sent {} upgrade suggestions", notified),
+                Err(e) => tracing::error!("Upgrade suggestion run failed: {}", e),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GB: i64 = 1024 * 1024 * 1024;
+
+    fn package(id: i64, traffic_gb: i64, price: i64) -> Package {
+        Package {
+            id,
+            name: format!("{} GB", traffic_gb),
+            traffic_amount: TrafficBytes::new(traffic_gb * GB),
+            price: Coins::new(price),
+            duration_days: 30,
+            description: None,
+            is_active: true,
+            update_interval_hours: 24,
+            max_connections: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            tenant_id: 1,
+            stock: None,
+            max_per_user: 0,
+            exclusive: false,
+        }
+    }
+
+    fn quota(remaining_gb: i64, expires_in_days: i64, now: DateTime<Utc>) -> PackageQuota {
+        PackageQuota {
+            quota: TrafficBytes::new(100 * GB),
+            used: TrafficBytes::new((100 - remaining_gb) * GB),
+            remaining: TrafficBytes::new(remaining_gb * GB),
+            active_packages: 1,
+            expires_at: Some(now + chrono::Duration::days(expires_in_days)),
+        }
+    }
+
+    #[test]
+    fn test_suggests_when_running_out_early() {
+        let now = Utc::now();
+        let packages = vec![package(1, 50, 1000), package(2, 200, 3000), package(3, 100, 2000)];
+
+        // 70 GB/week = 10 GB/day, 20 GB left: out in 2 days, package runs 20 more
+        let suggestion = suggest_upgrade(
+            &quota(20, 20, now),
+            TrafficBytes::new(70 * GB),
+            7,
+            &packages,
+            &UpgradeSuggestionConfig::default(),
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(suggestion.daily_usage.get() / GB, 10);
+        assert!((suggestion.projected_exhaustion_at - (now + chrono::Duration::days(2))).num_seconds().abs() <= 1);
+        assert_eq!(suggestion.shortfall.get() / GB, 180);
+        let ids: Vec<i64> = suggestion.candidates.iter().map(|c| c.package_id).collect();
+        assert_eq!(ids, vec![2]);
+        assert!(suggestion.candidates[0].covers_shortfall);
+    }
+
+    #[test]
+    fn test_offers_largest_packages_when_none_covers() {
+        let now = Utc::now();
+        let packages = vec![package(1, 10, 100), package(2, 30, 300), package(3, 20, 200), package(4, 5, 50)];
+
+        let suggestion = suggest_upgrade(
+            &quota(5, 30, now),
+            TrafficBytes::new(70 * GB),
+            7,
+            &packages,
+            &UpgradeSuggestionConfig::default(),
+            now,
+        )
+        .unwrap();
+
+        let ids: Vec<i64> = suggestion.candidates.iter().map(|c| c.package_id).collect();
+        assert_eq!(ids, vec![2, 3, 1]);
+        assert!(suggestion.candidates.iter().all(|c| !c.covers_shortfall));
+    }
+
+    #[test]
+    fn test_no_suggestion_when_on_track() {
+        let now = Utc::now();
+        let settings = UpgradeSuggestionConfig::default();
+        let packages = vec![package(1, 50, 1000)];
+
+        // Lasts until expiry
+        assert!(suggest_upgrade(&quota(50, 3, now), TrafficBytes::new(70 * GB), 7, &packages, &settings, now).is_none());
+        // Runs out, but only a day before expiry
+        assert!(suggest_upgrade(&quota(20, 3, now), TrafficBytes::new(70 * GB), 7, &packages, &settings, now).is_none());
+        // Runs out early, but beyond the horizon
+        assert!(suggest_upgrade(&quota(90, 60, now), TrafficBytes::new(35 * GB), 7, &packages, &settings, now).is_none());
+        // No recent usage, nothing to project
+        assert!(suggest_upgrade(&quota(20, 20, now), TrafficBytes::ZERO, 7, &packages, &settings, now).is_none());
+    }
+}
@@ -0,0 +1,240 @@
+use anyhow::{anyhow, Result};
+use axum::http::HeaderMap;
+use sqlx::PgPool;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::db;
+use crate::models::Tenant;
+use crate::utils::Claims;
+
+/// Tenant that owns data from before tenants existed and serves unknown hosts
+pub const DEFAULT_TENANT_ID: i64 = 1;
+
+/// How long tenants are cached in process memory
+///
+/// Changes made on this replica apply immediately; other replicas pick them up
+/// within this window.
+pub const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Width of `tenants.slug`
+pub const MAX_SLUG_LEN: usize = 50;
+/// Width of `tenants.name`
+pub const MAX_NAME_CHARS: usize = 100;
+
+type TenantList = Arc<Vec<Tenant>>;
+
+static TENANT_CACHE: OnceLock<RwLock<Option<(Instant, TenantList)>>> = OnceLock::new();
+
+fn tenant_cache() -> &'static RwLock<Option<(Instant, TenantList)>> {
+    TENANT_CACHE.get_or_init(|| RwLock::new(None))
+}
+
+/// Whether `slug` is a valid tenant slug: lowercase letters, digits and `-`
+pub fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug.len() <= MAX_SLUG_LEN
+        && !slug.starts_with('-')
+        && slug.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+}
+
+/// Lowercased host without port or trailing dot, e.g. `Panel.Example.com:443` -> `panel.example.com`
+pub fn normalize_host(host: &str) -> Option<String> {
+    let host = host.trim();
+    let host = match host.strip_prefix('[') {
+        // IPv6 literal, possibly with a port after the bracket
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+    (!host.is_empty()).then_some(host)
+}
+
+/// Whether `domain` is usable as a tenant domain: a bare host name, no scheme, port or path
+pub fn is_valid_domain(domain: &str) -> bool {
+    normalize_host(domain).as_deref() == Some(domain)
+        && domain.len() <= 253
+        && domain
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || matches!(b, b'.' | b'-'))
+}
+
+/// Host the request was made to, preferring `X-Forwarded-Host` set by the reverse proxy
+pub fn request_host(headers: &HeaderMap) -> Option<String> {
+    ["x-forwarded-host", "host"].into_iter().find_map(|name| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .and_then(normalize_host)
+    })
+}
+
+/// The active tenant serving `host`, if any
+pub fn find_by_host<'a>(tenants: &'a [Tenant], host: &str) -> Option<&'a Tenant> {
+    tenants
+        .iter()
+        .find(|t| t.is_active && t.domains.iter().any(|d| d.eq_ignore_ascii_case(host)))
+}
+
+/// Whether an admin may manage data belonging to `tenant_id`
+pub fn can_manage(claims: &Claims, tenant_id: i64) -> bool {
+    claims.is_admin && (claims.platform_admin || claims.tenant_id == tenant_id)
+}
+
+/// Tenant an admin's listings are limited to (`None`: platform admins see every tenant)
+pub fn admin_scope(claims: &Claims) -> Option<i64> {
+    (!claims.platform_admin).then_some(claims.tenant_id)
+}
+
+/// Base URL for a tenant's subscription links
+///
+/// Uses the tenant's subscription domain when set, else `default_base_url`.
+pub fn subscription_base_url(tenant: &Tenant, default_base_url: &str) -> String {
+    match tenant.subscription_domain.as_deref().filter(|d| !d.is_empty()) {
+        Some(domain) => format!("https://{}", domain),
+        None => default_base_url.trim_end_matches('/').to_string(),
+    }
+}
+
+/// Drop cached tenants so the next lookup reloads them
+pub fn invalidate_cache() {
+    *tenant_cache().write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Every tenant, active or not, served from the in-process cache
+pub async fn all(db_pool: &PgPool) -> Result<TenantList> {
+    if let Some((loaded_at, tenants)) = tenant_cache().read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        if loaded_at.elapsed() < CACHE_TTL {
+            return Ok(tenants.clone());
+        }
+    }
+
+    let tenants: TenantList = Arc::new(db::list_tenants(db_pool).await?);
+    *tenant_cache().write().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), tenants.clone()));
+
+    Ok(tenants)
+}
+
+/// Tenant by id, served from the in-process cache
+pub async fn get(db_pool: &PgPool, tenant_id: i64) -> Result<Option<Tenant>> {
+    let tenants = all(db_pool).await?;
+    Ok(tenants.iter().find(|t| t.id == tenant_id).cloned())
+}
+
+/// Tenant serving a request, by its Host header, falling back to the default tenant
+pub async fn resolve(db_pool: &PgPool, headers: &HeaderMap) -> Result<Tenant> {
+    let tenants = all(db_pool).await?;
+
+    request_host(headers)
+        .and_then(|host| find_by_host(&tenants, &host))
+        .or_else(|| tenants.iter().find(|t| t.id == DEFAULT_TENANT_ID))
+        .cloned()
+        .ok_or_else(|| anyhow!("Default tenant is missing"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use chrono::Utc;
+
+    fn tenant(id: i64, domains: &[&str], is_active: bool) -> Tenant {
+        Tenant {
+            id,
+            slug: format!("tenant-{}", id),
+            name: format!("Tenant {}", id),
+            domains: domains.iter().map(|d| d.to_string()).collect(),
+            subscription_domain: None,
+            settings: serde_json::json!({}),
+            is_active,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn claims(tenant_id: i64, is_admin: bool, platform_admin: bool) -> Claims {
+        Claims {
+            sub: 1,
+            email: "admin@example.com".to_string(),
+            is_admin,
+            exp: 0,
+            iat: 0,
+            ver: 0,
+            tenant_id,
+            platform_admin,
+        }
+    }
+
+    #[test]
+    fn test_normalize_host() {
+        assert_eq!(normalize_host("Panel.Example.com:8443").as_deref(), Some("panel.example.com"));
+        assert_eq!(normalize_host("panel.example.com.").as_deref(), Some("panel.example.com"));
+        assert_eq!(normalize_host("[::1]:8080").as_deref(), Some("::1"));
+        assert_eq!(normalize_host(" "), None);
+        assert_eq!(normalize_host(":443"), None);
+    }
+
+    #[test]
+    fn test_request_host_prefers_forwarded_host() {
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("api.internal:8080"));
+        assert_eq!(request_host(&headers).as_deref(), Some("api.internal"));
+
+        headers.insert("x-forwarded-host", HeaderValue::from_static("Brand.Example.com, proxy.local"));
+        assert_eq!(request_host(&headers).as_deref(), Some("brand.example.com"));
+
+        assert_eq!(request_host(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_find_by_host() {
+        let tenants = vec![
+            tenant(1, &[], true),
+            tenant(2, &["a.example.com", "panel.a.example.com"], true),
+            tenant(3, &["b.example.com"], false),
+        ];
+
+        assert_eq!(find_by_host(&tenants, "panel.a.example.com").map(|t| t.id), Some(2));
+        assert_eq!(find_by_host(&tenants, "b.example.com").map(|t| t.id), None);
+        assert_eq!(find_by_host(&tenants, "unknown.example.com").map(|t| t.id), None);
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(is_valid_slug("brand-2"));
+        assert!(!is_valid_slug("Brand"));
+        assert!(!is_valid_slug("-brand"));
+        assert!(!is_valid_slug(&"a".repeat(MAX_SLUG_LEN + 1)));
+
+        assert!(is_valid_domain("panel.example.com"));
+        assert!(!is_valid_domain("https://panel.example.com"));
+        assert!(!is_valid_domain("panel.example.com:8443"));
+        assert!(!is_valid_domain("Panel.example.com"));
+        assert!(!is_valid_domain("panel.example.com/sub"));
+    }
+
+    #[test]
+    fn test_admin_permissions() {
+        let tenant_admin = claims(2, true, false);
+        assert!(can_manage(&tenant_admin, 2));
+        assert!(!can_manage(&tenant_admin, 1));
+        assert_eq!(admin_scope(&tenant_admin), Some(2));
+
+        let platform_admin = claims(1, true, true);
+        assert!(can_manage(&platform_admin, 2));
+        assert_eq!(admin_scope(&platform_admin), None);
+
+        assert!(!can_manage(&claims(2, false, false), 2));
+    }
+
+    #[test]
+    fn test_subscription_base_url() {
+        let mut t = tenant(2, &["a.example.com"], true);
+        assert_eq!(subscription_base_url(&t, "http://localhost:8080/"), "http://localhost:8080");
+
+        t.subscription_domain = Some("sub.a.example.com".to_string());
+        assert_eq!(subscription_base_url(&t, "http://localhost:8080"), "https://sub.a.example.com");
+    }
+}
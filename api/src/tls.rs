@@ -0,0 +1,86 @@
+use anyhow::{Context, Result};
+use axum::extract::Request;
+use axum::Router;
+use futures::StreamExt;
+use rustls_acme::caches::DirCache;
+use rustls_acme::AcmeConfig;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+
+use crate::config::TlsConfig;
+use crate::middleware::redirect_to_https;
+
+/// ALPN protocols offered to clients, most preferred first
+const ALPN_PROTOCOLS: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+/// Resolve `API_HOST` and a port to the address to listen on
+pub fn listen_addr(host: &str, port: u16) -> Result<SocketAddr> {
+    (host, port)
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {}:{}", host, port))?
+        .next()
+        .with_context(|| format!("{}:{} did not resolve to an address", host, port))
+}
+
+/// Serve the router over HTTPS on `addr`, with HTTP/1.1 and HTTP/2
+///
+/// Certificates come from the configured PEM files, or from Let's Encrypt for the
+/// ACME domains (renewed in the background, cached in `acme_cache_dir`).
+pub async fn serve(app: Router, addr: SocketAddr, settings: &TlsConfig) -> Result<()> {
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+
+    if let (Some(cert_path), Some(key_path)) = (&settings.cert_path, &settings.key_path) {
+        let rustls_config = axum_server::tls_rustls::RustlsConfig::from_pem_file(cert_path, key_path)
+            .await
+            .with_context(|| format!("Failed to load TLS certificate {} / key {}", cert_path, key_path))?;
+        tracing::info!("Serving HTTPS with certificate {}", cert_path);
+
+        axum_server::bind_rustls(addr, rustls_config).serve(service).await?;
+        return Ok(());
+    }
+
+    let mut state = AcmeConfig::new(settings.acme_domains.clone())
+        .contact(settings.acme_email.iter().map(|email| format!("mailto:{}", email)))
+        .cache(DirCache::new(settings.acme_cache_dir.clone()))
+        .directory_lets_encrypt(!settings.acme_staging)
+        .state();
+
+    let mut rustls_config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_cert_resolver(state.resolver());
+    rustls_config.alpn_protocols = ALPN_PROTOCOLS.iter().map(|p| p.to_vec()).collect();
+    let acceptor = state.axum_acceptor(Arc::new(rustls_config));
+
+    // Drives certificate orders and renewals; must keep being polled
+    tokio::spawn(async move {
+        while let Some(event) = state.next().await {
+            match event {
+                Ok(event) => tracing::info!("ACME: {:?}", event),
+                Err(e) => tracing::error!("ACME error: {}", e),
+            }
+        }
+    });
+    tracing::info!(
+        "Serving HTTPS with Let's Encrypt{} certificates for {}",
+        if settings.acme_staging { " (staging)" } else { "" },
+        settings.acme_domains.join(", ")
+    );
+
+    axum_server::bind(addr).acceptor(acceptor).serve(service).await?;
+    Ok(())
+}
+
+/// Listen for plain HTTP on `addr`, redirecting every request to HTTPS on `https_port`
+pub async fn spawn_https_redirect(addr: SocketAddr, https_port: u16) -> Result<tokio::task::JoinHandle<()>> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind HTTP redirect listener on {}", addr))?;
+    let app = Router::new().fallback(move |request: Request| async move { redirect_to_https(&request, https_port) });
+    tracing::info!("Redirecting HTTP on {} to HTTPS", addr);
+
+    Ok(tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!("HTTP redirect listener failed: {}", e);
+        }
+    }))
+}
@@ -0,0 +1,142 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::amounts::Coins;
+use crate::models::{CreateTopupRequest, TopupRequest};
+
+/// Largest amount a single top-up request may ask for
+pub const MAX_AMOUNT: Coins = Coins::new(1_000_000);
+/// Pending requests a user may have at once
+pub const MAX_PENDING_PER_USER: i64 = 3;
+/// Width of `topup_requests.reference`
+pub const MAX_REFERENCE_LEN: usize = 200;
+/// Width of `topup_requests.screenshot_url`
+pub const MAX_SCREENSHOT_URL_LEN: usize = 500;
+/// Width of `topup_requests.admin_note`
+pub const MAX_NOTE_LEN: usize = 500;
+
+crate::status::status_enum! {
+    /// Review state stored in `topup_requests.status`
+    TopupStatus {
+        Pending => "pending",
+        /// Coins credited to the user
+        Approved => "approved",
+        Rejected => "rejected",
+    }
+}
+
+/// A top-up request was submitted or reviewed, published on `topups:events`
+///
+/// Admin notifiers pick up `pending` events, user notifiers the reviewed ones.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopupEvent {
+    pub request_id: i64,
+    pub user_id: i64,
+    pub tenant_id: i64,
+    pub amount: Coins,
+    pub status: TopupStatus,
+    pub admin_note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TopupEvent {
+    pub fn new(request: &TopupRequest, tenant_id: i64) -> Self {
+        Self {
+            request_id: request.id,
+            user_id: request.user_id,
+            tenant_id,
+            amount: request.amount,
+            status: request.status,
+            admin_note: request.admin_note.clone(),
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// A submitted or reviewed request and the outbox events written with it
+#[derive(Debug, Clone)]
+pub struct TopupUpdate {
+    pub request: TopupRequest,
+    /// Outbox events written with the change (see `outbox::deliver_now`)
+    pub outbox_ids: Vec<i64>,
+}
+
+/// A top-up request with blank fields dropped, ready to store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewTopup {
+    pub amount: Coins,
+    pub reference: Option<String>,
+    pub screenshot_url: Option<String>,
+}
+
+/// Check a submitted request; the error is the message returned to the user
+///
+/// A request needs a payment reference, a screenshot link or both, so admins have
+/// something to match against their payment records.
+pub fn validate_request(request: &CreateTopupRequest) -> Result<NewTopup, &'static str> {
+    if !request.amount.is_positive() {
+        return Err("Amount must be positive");
+    }
+    if request.amount > MAX_AMOUNT {
+        return Err("Top-up amount exceeds the maximum of 1000000");
+    }
+
+    let trimmed = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+    let reference = trimmed(&request.reference);
+    let screenshot_url = trimmed(&request.screenshot_url);
+
+    if reference.is_none() && screenshot_url.is_none() {
+        return Err("reference or screenshot_url is required");
+    }
+    if reference.as_ref().is_some_and(|r| r.chars().count() > MAX_REFERENCE_LEN) {
+        return Err("reference must be at most 200 characters");
+    }
+    if let Some(url) = &screenshot_url {
+        let valid = url.len() <= MAX_SCREENSHOT_URL_LEN
+            && reqwest::Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https"));
+        if !valid {
+            return Err("screenshot_url must be an http(s) URL of at most 500 characters");
+        }
+    }
+
+    Ok(NewTopup {
+        amount: request.amount,
+        reference,
+        screenshot_url,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(amount: i64, reference: Option<&str>, screenshot_url: Option<&str>) -> CreateTopupRequest {
+        CreateTopupRequest {
+            amount: Coins::new(amount),
+            reference: reference.map(str::to_string),
+            screenshot_url: screenshot_url.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_validate_topup_request() {
+        let valid = validate_request(&request(500, Some(" TX-1 "), Some(""))).unwrap();
+        assert_eq!(valid.reference.as_deref(), Some("TX-1"));
+        assert_eq!(valid.screenshot_url, None);
+
+        let screenshot = validate_request(&request(500, None, Some("https://img.example.com/a.png"))).unwrap();
+        assert_eq!(screenshot.screenshot_url.as_deref(), Some("https://img.example.com/a.png"));
+
+        assert!(validate_request(&request(0, Some("TX-1"), None)).is_err());
+        assert!(validate_request(&request(1_000_001, Some("TX-1"), None)).is_err());
+        assert!(validate_request(&request(500, Some("  "), None)).is_err());
+        assert!(validate_request(&request(500, None, Some("javascript:alert(1)"))).is_err());
+        assert!(validate_request(&request(500, Some(&"x".repeat(201)), None)).is_err());
+    }
+}
@@ -0,0 +1,448 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::amounts::{Coins, TrafficBytes};
+
+/// Most rows accepted by one import; larger exports are imported in batches
+pub const MAX_IMPORT_ROWS: usize = 500;
+
+/// Panel an export comes from, which decides its column names and units
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportSource {
+    /// SSPanel `user` table: `money` in yuan, `class_expire` as `YYYY-MM-DD HH:MM:SS`
+    Sspanel,
+    /// V2Board `v2_user` table: `balance` in cents, `expired_at` as a Unix timestamp
+    V2board,
+    /// This platform's own column names, amounts already in coins and bytes
+    Generic,
+}
+
+impl ImportSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ImportSource::Sspanel => "sspanel",
+            ImportSource::V2board => "v2board",
+            ImportSource::Generic => "generic",
+        }
+    }
+
+    /// `users.import_ref` of the user with `source_id` in this panel
+    pub fn import_ref(self, source_id: &str) -> String {
+        format!("{}:{}", self.as_str(), source_id)
+    }
+}
+
+/// Encoding of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    /// Comma separated, header row first
+    Csv,
+    /// An array of objects
+    Json,
+}
+
+/// A user read from an export, converted to this platform's units
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedUser {
+    /// The user's id in the source panel
+    pub source_id: Option<String>,
+    pub email: String,
+    /// Only Argon2 hashes are kept; other algorithms can't be verified here
+    pub password_hash: Option<String>,
+    pub balance: Coins,
+    pub traffic_total: TrafficBytes,
+    pub traffic_used: TrafficBytes,
+    /// None if the source has no expiry for the user
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Source panel id of the user who referred this one
+    pub referrer_source_id: Option<String>,
+    pub disabled: bool,
+}
+
+/// Outcome of one row, as listed in the import report
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowStatus {
+    /// Passed validation in a dry run
+    Valid,
+    Imported,
+    Failed,
+}
+
+/// One row of the import report
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportRowReport {
+    /// 1-based position among the records (the CSV header is not counted)
+    pub row: usize,
+    pub source_id: Option<String>,
+    pub email: Option<String>,
+    pub status: RowStatus,
+    pub user_id: Option<i64>,
+    pub error: Option<String>,
+    /// Problems that did not stop the row, e.g. an unknown referrer
+    pub warnings: Vec<String>,
+    /// Generated when the source password hash could not be kept; shown only here
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temporary_password: Option<String>,
+}
+
+impl ImportRowReport {
+    pub fn new(row: usize) -> Self {
+        ImportRowReport {
+            row,
+            source_id: None,
+            email: None,
+            status: RowStatus::Valid,
+            user_id: None,
+            error: None,
+            warnings: Vec::new(),
+            temporary_password: None,
+        }
+    }
+
+    pub fn fail(&mut self, error: impl Into<String>) {
+        self.status = RowStatus::Failed;
+        self.error = Some(error.into());
+    }
+}
+
+/// Response for POST /api/admin/users/import
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub total: usize,
+    /// Rows imported, or that would be in a dry run
+    pub succeeded: usize,
+    pub failed: usize,
+    pub rows: Vec<ImportRowReport>,
+}
+
+impl ImportReport {
+    pub fn new(dry_run: bool, rows: Vec<ImportRowReport>) -> Self {
+        let failed = rows.iter().filter(|r| r.status == RowStatus::Failed).count();
+        ImportReport {
+            dry_run,
+            total: rows.len(),
+            succeeded: rows.len() - failed,
+            failed,
+            rows,
+        }
+    }
+}
+
+/// Split an export into records of column name to value
+pub fn parse_records(content: &str, format: ImportFormat) -> Result<Vec<HashMap<String, Value>>, String> {
+    match format {
+        ImportFormat::Json => {
+            serde_json::from_str(content).map_err(|e| format!("Invalid JSON export: {}", e))
+        }
+        ImportFormat::Csv => {
+            let mut lines = parse_csv(content)?.into_iter();
+            let Some(header) = lines.next() else {
+                return Ok(Vec::new());
+            };
+            let header: Vec<String> = header.iter().map(|h| h.trim().to_ascii_lowercase()).collect();
+
+            lines
+                .enumerate()
+                .map(|(idx, fields)| {
+                    if fields.len() != header.len() {
+                        return Err(format!(
+                            "CSV record {} has {} fields, expected {}",
+                            idx + 1,
+                            fields.len(),
+                            header.len()
+                        ));
+                    }
+                    Ok(header.iter().cloned().zip(fields.into_iter().map(Value::String)).collect())
+                })
+                .collect()
+        }
+    }
+}
+
+/// Parse RFC 4180 CSV: quoted fields may contain commas, doubled quotes and line breaks
+///
+/// Blank lines are skipped.
+fn parse_csv(content: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = content.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                if !(record.len() == 1 && record[0].is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err("Unterminated quoted field in CSV export".to_string());
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+/// Typed access to one record's columns; empty strings and nulls count as missing
+struct Record<'a>(&'a HashMap<String, Value>);
+
+impl Record<'_> {
+    fn text(&self, column: &str) -> Option<String> {
+        match self.0.get(column)? {
+            Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+            Value::Number(n) => Some(n.to_string()),
+            Value::Bool(b) => Some(b.to_string()),
+            _ => None,
+        }
+    }
+
+    fn number(&self, column: &str) -> Result<Option<f64>, String> {
+        self.text(column)
+            .map(|s| {
+                s.parse::<f64>()
+                    .ok()
+                    .filter(|n| n.is_finite() && *n >= 0.0)
+                    .ok_or_else(|| format!("{} must be a non-negative number", column))
+            })
+            .transpose()
+    }
+
+    fn integer(&self, column: &str) -> Result<Option<i64>, String> {
+        self.text(column)
+            .map(|s| {
+                s.parse::<i64>()
+                    .ok()
+                    .filter(|n| *n >= 0)
+                    .ok_or_else(|| format!("{} must be a non-negative integer", column))
+            })
+            .transpose()
+    }
+
+    fn flag(&self, column: &str) -> Option<bool> {
+        self.text(column)
+            .map(|s| matches!(s.to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+    }
+
+    /// A Unix timestamp, RFC 3339 time, `YYYY-MM-DD HH:MM:SS` (UTC) or date
+    ///
+    /// Timestamps of 0 mean "never", as both panels store them.
+    fn time(&self, column: &str) -> Result<Option<DateTime<Utc>>, String> {
+        let Some(s) = self.text(column) else {
+            return Ok(None);
+        };
+        if let Ok(secs) = s.parse::<i64>() {
+            return Ok((secs > 0).then(|| Utc.timestamp_opt(secs, 0).single()).flatten());
+        }
+        DateTime::parse_from_rfc3339(&s)
+            .map(|t| t.with_timezone(&Utc))
+            .or_else(|_| NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S").map(|t| t.and_utc()))
+            .or_else(|_| NaiveDate::parse_from_str(&s, "%Y-%m-%d").map(|d| d.and_time(NaiveTime::MIN).and_utc()))
+            .map(Some)
+            .map_err(|_| format!("{} is not a valid time", column))
+    }
+}
+
+/// Coins for a balance in source currency units, rounded to the nearest coin
+fn coins(amount: f64, rate: f64) -> Result<Coins, String> {
+    let coins = (amount * rate).round();
+    if coins > i64::MAX as f64 {
+        return Err("balance is too large".to_string());
+    }
+    Ok(Coins::new(coins as i64))
+}
+
+/// Read one record of a `source` export
+///
+/// `balance_rate` is the number of coins per yuan for SSPanel and V2Board balances;
+/// generic balances are already in coins.
+pub fn map_record(
+    source: ImportSource,
+    record: &HashMap<String, Value>,
+    balance_rate: f64,
+) -> Result<ImportedUser, String> {
+    let record = Record(record);
+    let email = record
+        .text("email")
+        .map(|e| e.to_ascii_lowercase())
+        .ok_or_else(|| "email is required".to_string())?;
+    crate::utils::validate_email(&email).map_err(|e| e.to_string())?;
+
+    let (password_column, referrer_column) = match source {
+        ImportSource::Sspanel => ("pass", "ref_by"),
+        ImportSource::V2board => ("password", "invite_user_id"),
+        ImportSource::Generic => ("password_hash", "referrer_id"),
+    };
+
+    let balance = match source {
+        ImportSource::Sspanel => coins(record.number("money")?.unwrap_or(0.0), balance_rate)?,
+        ImportSource::V2board => coins(record.number("balance")?.unwrap_or(0.0) / 100.0, balance_rate)?,
+        ImportSource::Generic => Coins::new(record.integer("balance")?.unwrap_or(0)),
+    };
+
+    let (traffic_total, traffic_used) = match source {
+        ImportSource::Sspanel | ImportSource::V2board => {
+            let used = record
+                .integer("u")?
+                .unwrap_or(0)
+                .checked_add(record.integer("d")?.unwrap_or(0))
+                .ok_or_else(|| "u + d is too large".to_string())?;
+            (record.integer("transfer_enable")?.unwrap_or(0), used)
+        }
+        ImportSource::Generic => (
+            record.integer("traffic_total")?.unwrap_or(0),
+            record.integer("traffic_used")?.unwrap_or(0),
+        ),
+    };
+
+    let expires_at = match source {
+        ImportSource::Sspanel => match record.time("class_expire")? {
+            Some(at) => Some(at),
+            None => record.time("expire_in")?,
+        },
+        ImportSource::V2board => record.time("expired_at")?,
+        ImportSource::Generic => record.time("expires_at")?,
+    };
+
+    let disabled = match source {
+        ImportSource::Sspanel => record.flag("enable") == Some(false),
+        ImportSource::V2board => record.flag("banned").unwrap_or(false),
+        ImportSource::Generic => record.flag("disabled").unwrap_or(false),
+    };
+
+    Ok(ImportedUser {
+        source_id: record.text("id"),
+        email,
+        password_hash: record.text(password_column).filter(|h| h.starts_with("$argon2")),
+        balance,
+        traffic_total: TrafficBytes::new(traffic_total),
+        traffic_used: TrafficBytes::new(traffic_used),
+        expires_at,
+        // Both panels use 0 for "not referred"
+        referrer_source_id: record.text(referrer_column).filter(|id| id != "0"),
+        disabled,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GB: i64 = 1024 * 1024 * 1024;
+
+    #[test]
+    fn test_parse_csv() {
+        let records = parse_csv("a,b\r\n\"x, \"\"y\"\"\",\"multi\nline\"\n\n1,\n").unwrap();
+        assert_eq!(
+            records,
+            vec![
+                vec!["a".to_string(), "b".to_string()],
+                vec!["x, \"y\"".to_string(), "multi\nline".to_string()],
+                vec!["1".to_string(), String::new()],
+            ]
+        );
+        assert!(parse_csv("a,\"b\n").is_err());
+    }
+
+    #[test]
+    fn test_map_sspanel() {
+        let csv = "id,email,pass,money,transfer_enable,u,d,class_expire,ref_by,enable\n\
+                   7,Alice@Example.com,5f4dcc3b5aa765d61d8327deb882cf99,12.50,10737418240,1073741824,1073741824,2030-01-02 03:04:05,3,0\n";
+        let records = parse_records(csv, ImportFormat::Csv).unwrap();
+        let user = map_record(ImportSource::Sspanel, &records[0], 10.0).unwrap();
+
+        assert_eq!(user.source_id.as_deref(), Some("7"));
+        assert_eq!(user.email, "alice@example.com");
+        // MD5 hashes can't be verified here
+        assert_eq!(user.password_hash, None);
+        assert_eq!(user.balance, Coins::new(125));
+        assert_eq!(user.traffic_total, TrafficBytes::new(10 * GB));
+        assert_eq!(user.traffic_used, TrafficBytes::new(2 * GB));
+        assert_eq!(user.expires_at.unwrap().to_rfc3339(), "2030-01-02T03:04:05+00:00");
+        assert_eq!(user.referrer_source_id.as_deref(), Some("3"));
+        assert!(user.disabled);
+    }
+
+    #[test]
+    fn test_map_v2board() {
+        let json = r#"[{"id": 42, "email": "bob@example.com", "password": "$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA",
+                        "balance": 1999, "transfer_enable": 1073741824, "u": 0, "d": 512,
+                        "expired_at": 0, "invite_user_id": null, "banned": 0}]"#;
+        let records = parse_records(json, ImportFormat::Json).unwrap();
+        let user = map_record(ImportSource::V2board, &records[0], 1.0).unwrap();
+
+        assert_eq!(user.source_id.as_deref(), Some("42"));
+        assert!(user.password_hash.unwrap().starts_with("$argon2id$"));
+        // 19.99 yuan at one coin per yuan
+        assert_eq!(user.balance, Coins::new(20));
+        assert_eq!(user.traffic_used, TrafficBytes::new(512));
+        assert_eq!(user.expires_at, None);
+        assert_eq!(user.referrer_source_id, None);
+        assert!(!user.disabled);
+    }
+
+    #[test]
+    fn test_map_rejects_bad_rows() {
+        let record = |json: Value| -> HashMap<String, Value> { serde_json::from_value(json).unwrap() };
+
+        let missing_email = record(serde_json::json!({ "id": 1 }));
+        assert_eq!(
+            map_record(ImportSource::Generic, &missing_email, 1.0).unwrap_err(),
+            "email is required"
+        );
+
+        let negative = record(serde_json::json!({ "email": "c@example.com", "balance": -5 }));
+        assert_eq!(
+            map_record(ImportSource::Generic, &negative, 1.0).unwrap_err(),
+            "balance must be a non-negative integer"
+        );
+
+        let bad_time = record(serde_json::json!({ "email": "c@example.com", "expires_at": "soon" }));
+        assert_eq!(
+            map_record(ImportSource::Generic, &bad_time, 1.0).unwrap_err(),
+            "expires_at is not a valid time"
+        );
+
+        assert!(parse_records("email,balance\na@example.com\n", ImportFormat::Csv).is_err());
+    }
+
+    #[test]
+    fn test_report_counts() {
+        let mut failed = ImportRowReport::new(2);
+        failed.fail("Email already exists");
+        let report = ImportReport::new(true, vec![ImportRowReport::new(1), failed]);
+
+        assert_eq!((report.total, report.succeeded, report.failed), (2, 1, 1));
+        assert_eq!(ImportSource::V2board.import_ref("42"), "v2board:42");
+    }
+}
@@ -0,0 +1,54 @@
+/// Longest admin note, in characters
+pub const MAX_NOTE_LEN: usize = 2000;
+/// Width of `user_tags.tag`
+pub const MAX_TAG_LEN: usize = 32;
+/// Tags a single user may carry
+pub const MAX_TAGS_PER_USER: i64 = 20;
+
+/// Check a note before storing it; returns the trimmed text or the error message
+pub fn validate_note(body: &str) -> Result<String, &'static str> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err("Note cannot be empty");
+    }
+    if body.chars().count() > MAX_NOTE_LEN {
+        return Err("Note must be at most 2000 characters");
+    }
+    Ok(body.to_string())
+}
+
+/// Check a tag before storing it; returns the trimmed tag or the error message
+///
+/// Inner whitespace is collapsed to single spaces so `chargeback  risk` and
+/// `chargeback risk` are one tag. Case is kept for display; tags are compared
+/// case-insensitively.
+pub fn normalize_tag(tag: &str) -> Result<String, &'static str> {
+    let tag = tag.split_whitespace().collect::<Vec<_>>().join(" ");
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_LEN || tag.chars().any(char::is_control) {
+        return Err("Tag must be 1 to 32 characters");
+    }
+    Ok(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_note() {
+        assert_eq!(validate_note("  Asked for a refund twice \n"), Ok("Asked for a refund twice".to_string()));
+        assert!(validate_note(" \n ").is_err());
+        assert!(validate_note(&"备".repeat(MAX_NOTE_LEN)).is_ok());
+        assert!(validate_note(&"x".repeat(MAX_NOTE_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(normalize_tag(" VIP "), Ok("VIP".to_string()));
+        assert_eq!(normalize_tag("chargeback \t risk"), Ok("chargeback risk".to_string()));
+        assert_eq!(normalize_tag(&"标".repeat(MAX_TAG_LEN)), Ok("标".repeat(MAX_TAG_LEN)));
+        assert!(normalize_tag("").is_err());
+        assert!(normalize_tag(&"x".repeat(MAX_TAG_LEN + 1)).is_err());
+        assert!(normalize_tag("a\u{7}b").is_err());
+    }
+}
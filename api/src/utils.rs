@@ -1,6 +1,6 @@
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
+    Algorithm, Argon2, Params, Version,
 };
 use anyhow::{anyhow, Result};
 use chrono::{Duration, Utc};
@@ -8,6 +8,9 @@ use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation}
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+use crate::config::Argon2Config;
+use crate::models::User;
+
 /// JWT Claims structure
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
@@ -16,12 +19,24 @@ pub struct Claims {
     pub is_admin: bool,  // Admin role
     pub exp: i64,        // Expiration time
     pub iat: i64,        // Issued at
+    #[serde(default)]
+    pub ver: i32,        // User token version at issue time
+    #[serde(default = "default_tenant_id")]
+    pub tenant_id: i64,  // Tenant the user belongs to
+    #[serde(default)]
+    pub platform_admin: bool, // Admin of every tenant
+}
+
+fn default_tenant_id() -> i64 {
+    crate::tenancy::DEFAULT_TENANT_ID
 }
 
-/// Hash a password using Argon2id
-pub fn hash_password(password: &str) -> Result<String> {
+/// Hash a password using Argon2id with the configured cost
+///
+/// The parameters are embedded in the PHC string, so verification never needs them.
+pub fn hash_password(password: &str, config: &Argon2Config) -> Result<String> {
     let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, config.params()?);
     
     let password_hash = argon2
         .hash_password(password.as_bytes(), &salt)
@@ -32,6 +47,8 @@ pub fn hash_password(password: &str) -> Result<String> {
 }
 
 /// Verify a password against a hash using Argon2id
+///
+/// Uses the algorithm, version and cost recorded in the hash itself.
 pub fn verify_password(password: &str, password_hash: &str) -> Result<bool> {
     let parsed_hash = PasswordHash::new(password_hash)
         .map_err(|e| anyhow!("Failed to parse password hash: {}", e))?;
@@ -44,15 +61,42 @@ pub fn verify_password(password: &str, password_hash: &str) -> Result<bool> {
     }
 }
 
+/// Whether a stored hash is weaker than the configured parameters
+///
+/// True for anything other than Argon2id v0x13, or when any cost is below the
+/// configured value. Stronger hashes are left alone so lowering the config does not
+/// churn every password.
+pub fn password_needs_rehash(password_hash: &str, config: &Argon2Config) -> bool {
+    let Ok(parsed_hash) = PasswordHash::new(password_hash) else {
+        return false;
+    };
+
+    if parsed_hash.algorithm != Algorithm::Argon2id.ident()
+        || parsed_hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+
+    match Params::try_from(&parsed_hash) {
+        Ok(params) => {
+            params.m_cost() < config.memory_kib
+                || params.t_cost() < config.iterations
+                || params.p_cost() < config.parallelism
+        }
+        Err(_) => true,
+    }
+}
+
 // ============================================================================
 // JWT Token Functions
 // ============================================================================
 
-/// Generate a JWT token for a user
+/// Generate a JWT token for a user of the default tenant
 pub fn generate_token(
     user_id: i64,
     email: &str,
     is_admin: bool,
+    token_version: i32,
     secret: &str,
     expiration_seconds: i64,
 ) -> Result<String> {
@@ -65,11 +109,37 @@ pub fn generate_token(
         is_admin,
         exp: exp.timestamp(),
         iat: now.timestamp(),
+        ver: token_version,
+        tenant_id: crate::tenancy::DEFAULT_TENANT_ID,
+        platform_admin: false,
     };
     
+    encode_claims(&claims, secret)
+}
+
+/// Generate a JWT token carrying a user's tenant and admin roles
+pub fn generate_user_token(user: &User, secret: &str, expiration_seconds: i64) -> Result<String> {
+    let now = Utc::now();
+    let exp = now + Duration::seconds(expiration_seconds);
+
+    let claims = Claims {
+        sub: user.id,
+        email: user.email.clone(),
+        is_admin: user.is_admin,
+        exp: exp.timestamp(),
+        iat: now.timestamp(),
+        ver: user.token_version,
+        tenant_id: user.tenant_id,
+        platform_admin: user.is_admin && user.is_platform_admin,
+    };
+
+    encode_claims(&claims, secret)
+}
+
+fn encode_claims(claims: &Claims, secret: &str) -> Result<String> {
     let token = encode(
         &Header::default(),
-        &claims,
+        claims,
         &EncodingKey::from_secret(secret.as_bytes()),
     )
     .map_err(|e| anyhow!("Failed to generate token: {}", e))?;
@@ -94,10 +164,11 @@ pub fn generate_refresh_token(
     user_id: i64,
     email: &str,
     is_admin: bool,
+    token_version: i32,
     secret: &str,
 ) -> Result<String> {
     // Refresh tokens expire in 7 days
-    generate_token(user_id, email, is_admin, secret, 7 * 24 * 3600)
+    generate_token(user_id, email, is_admin, token_version, secret, 7 * 24 * 3600)
 }
 
 // ============================================================================
@@ -121,25 +192,21 @@ pub fn validate_email(email: &str) -> Result<()> {
     Ok(())
 }
 
-/// Validate password strength
-/// Requirements: At least 8 characters, contains letters and numbers
-pub fn validate_password(password: &str) -> Result<()> {
-    if password.len() < 8 {
-        return Err(anyhow!("Password must be at least 8 characters long"));
-    }
-    
-    if password.len() > 128 {
-        return Err(anyhow!("Password is too long (max 128 characters)"));
-    }
-    
-    let has_letter = password.chars().any(|c| c.is_alphabetic());
-    let has_number = password.chars().any(|c| c.is_numeric());
-    
-    if !has_letter || !has_number {
-        return Err(anyhow!("Password must contain both letters and numbers"));
+/// Email shown to other users: the first and last characters of the local part, with
+/// the rest hidden (`a***e@example.com`)
+pub fn mask_email(email: &str) -> String {
+    let (local, domain) = email.rsplit_once('@').unwrap_or((email, ""));
+    let mut chars = local.chars();
+    let masked = match (chars.next(), chars.next_back()) {
+        (Some(first), Some(last)) if local.chars().count() > 2 => format!("{}***{}", first, last),
+        (Some(first), _) => format!("{}***", first),
+        (None, _) => "***".to_string(),
+    };
+    if domain.is_empty() {
+        masked
+    } else {
+        format!("{}@{}", masked, domain)
     }
-    
-    Ok(())
 }
 
 /// Generate a random referral code
@@ -170,6 +237,78 @@ pub fn generate_subscription_token() -> String {
         .collect()
 }
 
+/// Generate a subscription access key (24 characters)
+pub fn generate_subscription_key() -> String {
+    use rand::Rng;
+    const CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    let mut rng = rand::thread_rng();
+
+    (0..24)
+        .map(|_| {
+            let idx = rng.gen_range(0..CHARSET.len());
+            CHARSET[idx] as char
+        })
+        .collect()
+}
+
+/// Generate a subscription short link code (8 characters)
+pub fn generate_short_link_code() -> String {
+    use rand::Rng;
+    const CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    let mut rng = rand::thread_rng();
+
+    (0..8)
+        .map(|_| {
+            let idx = rng.gen_range(0..CHARSET.len());
+            CHARSET[idx] as char
+        })
+        .collect()
+}
+
+/// Generate a single-use node enrollment token (`enr_` + 40 characters)
+pub fn generate_enrollment_token() -> String {
+    use rand::Rng;
+    const CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    let mut rng = rand::thread_rng();
+
+    let body: String = (0..40)
+        .map(|_| {
+            let idx = rng.gen_range(0..CHARSET.len());
+            CHARSET[idx] as char
+        })
+        .collect();
+    format!("enr_{}", body)
+}
+
+/// Generate a temporary password for an imported user (16 characters)
+pub fn generate_temporary_password() -> String {
+    use rand::Rng;
+    const CHARSET: &[u8] = b"abcdefghijkmnpqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    let mut rng = rand::thread_rng();
+
+    (0..16)
+        .map(|_| {
+            let idx = rng.gen_range(0..CHARSET.len());
+            CHARSET[idx] as char
+        })
+        .collect()
+}
+
+/// Generate an API key for server-to-server integrations (`nsk_` + 40 characters)
+pub fn generate_api_key() -> String {
+    use rand::Rng;
+    const CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    let mut rng = rand::thread_rng();
+
+    let body: String = (0..40)
+        .map(|_| {
+            let idx = rng.gen_range(0..CHARSET.len());
+            CHARSET[idx] as char
+        })
+        .collect();
+    format!("{}{}", crate::api_keys::KEY_PREFIX, body)
+}
+
 /// Generate a secure node secret (32 characters)
 pub fn generate_node_secret() -> String {
     use rand::Rng;
@@ -255,6 +394,80 @@ pub fn validate_host(host: &str) -> Result<()> {
     Ok(())
 }
 
+// ============================================================================
+// User-Agent Parsing
+// ============================================================================
+
+/// Subscription client identified from a User-Agent header
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ClientInfo {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+/// Known subscription clients: (lowercase UA product, display name)
+/// Ordered so more specific products win (Stash also sends `Clash/x`, ClashX before Clash).
+const KNOWN_CLIENTS: &[(&str, &str)] = &[
+    ("stash", "Stash"),
+    ("shadowrocket", "Shadowrocket"),
+    ("quantumult%20x", "Quantumult X"),
+    ("quantumult x", "Quantumult X"),
+    ("surge", "Surge"),
+    ("loon", "Loon"),
+    ("sing-box", "sing-box"),
+    ("v2rayng", "v2rayNG"),
+    ("v2rayn", "v2rayN"),
+    ("clashx pro", "ClashX Pro"),
+    ("clashx.meta", "ClashX Meta"),
+    ("clashx", "ClashX"),
+    ("clash-verge", "Clash Verge"),
+    ("clash verge", "Clash Verge"),
+    ("clashforwindows", "Clash for Windows"),
+    ("clashmetaforandroid", "Clash Meta for Android"),
+    ("clashforandroid", "Clash for Android"),
+    ("clash.meta", "Clash.Meta"),
+    ("mihomo", "Mihomo"),
+    ("clash", "Clash"),
+];
+
+/// Parse a subscription client's name and version from its User-Agent
+/// e.g. `ClashX/1.95.1` -> ClashX 1.95.1, `clash-verge/v1.3.8` -> Clash Verge 1.3.8
+pub fn parse_user_agent(user_agent: Option<&str>) -> ClientInfo {
+    let Some(ua) = user_agent.map(str::trim).filter(|ua| !ua.is_empty()) else {
+        return ClientInfo { name: "Unknown".to_string(), version: None };
+    };
+    let lower = ua.to_ascii_lowercase();
+
+    for (product, name) in KNOWN_CLIENTS {
+        // Match at a token boundary so e.g. "clash" doesn't match inside "myclash"
+        let found = lower.match_indices(product).find(|(i, _)| {
+            lower[..*i]
+                .chars()
+                .next_back()
+                .map_or(true, |c| !c.is_ascii_alphanumeric())
+        });
+        let Some((start, _)) = found else {
+            continue;
+        };
+
+        let version = ua[start + product.len()..]
+            .strip_prefix('/')
+            .map(|rest| {
+                rest.split(|c: char| c.is_whitespace() || c == ';' || c == ')' || c == ',')
+                    .next()
+                    .unwrap_or("")
+                    .trim_start_matches(['v', 'V'])
+                    .to_string()
+            })
+            .filter(|v| !v.is_empty());
+
+        return ClientInfo { name: name.to_string(), version };
+    }
+
+    let name = if lower.starts_with("mozilla/") { "Browser" } else { "Unknown" };
+    ClientInfo { name: name.to_string(), version: None }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -262,7 +475,7 @@ mod tests {
     #[test]
     fn test_hash_password_creates_valid_hash() {
         let password = "test_password_123";
-        let hash = hash_password(password).unwrap();
+        let hash = hash_password(password, &Argon2Config::default()).unwrap();
         
         // Argon2 hash should start with $argon2
         assert!(hash.starts_with("$argon2"));
@@ -274,7 +487,7 @@ mod tests {
     #[test]
     fn test_verify_password_with_correct_password() {
         let password = "correct_password";
-        let hash = hash_password(password).unwrap();
+        let hash = hash_password(password, &Argon2Config::default()).unwrap();
         
         let result = verify_password(password, &hash).unwrap();
         assert!(result);
@@ -283,7 +496,7 @@ mod tests {
     #[test]
     fn test_verify_password_with_incorrect_password() {
         let password = "correct_password";
-        let hash = hash_password(password).unwrap();
+        let hash = hash_password(password, &Argon2Config::default()).unwrap();
         
         let result = verify_password("wrong_password", &hash).unwrap();
         assert!(!result);
@@ -292,8 +505,8 @@ mod tests {
     #[test]
     fn test_hash_password_generates_different_hashes() {
         let password = "same_password";
-        let hash1 = hash_password(password).unwrap();
-        let hash2 = hash_password(password).unwrap();
+        let hash1 = hash_password(password, &Argon2Config::default()).unwrap();
+        let hash2 = hash_password(password, &Argon2Config::default()).unwrap();
         
         // Same password should generate different hashes due to different salts
         assert_ne!(hash1, hash2);
@@ -306,7 +519,7 @@ mod tests {
     #[test]
     fn test_verify_password_with_empty_password() {
         let password = "";
-        let hash = hash_password(password).unwrap();
+        let hash = hash_password(password, &Argon2Config::default()).unwrap();
         
         assert!(verify_password("", &hash).unwrap());
         assert!(!verify_password("not_empty", &hash).unwrap());
@@ -315,7 +528,7 @@ mod tests {
     #[test]
     fn test_verify_password_with_special_characters() {
         let password = "p@ssw0rd!#$%^&*()";
-        let hash = hash_password(password).unwrap();
+        let hash = hash_password(password, &Argon2Config::default()).unwrap();
         
         assert!(verify_password(password, &hash).unwrap());
         assert!(!verify_password("p@ssw0rd", &hash).unwrap());
@@ -324,7 +537,7 @@ mod tests {
     #[test]
     fn test_verify_password_with_unicode() {
         let password = "密码测试123";
-        let hash = hash_password(password).unwrap();
+        let hash = hash_password(password, &Argon2Config::default()).unwrap();
         
         assert!(verify_password(password, &hash).unwrap());
         assert!(!verify_password("密码测试", &hash).unwrap());
@@ -336,12 +549,52 @@ mod tests {
         assert!(result.is_err());
     }
 
+    fn cheap_argon2() -> Argon2Config {
+        Argon2Config { memory_kib: 1024, iterations: 1, parallelism: 1 }
+    }
+
+    #[test]
+    fn test_hash_password_embeds_configured_params() {
+        let hash = hash_password("password", &cheap_argon2()).unwrap();
+
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+        assert!(verify_password("password", &hash).unwrap());
+    }
+
+    #[test]
+    fn test_password_needs_rehash_when_config_is_stronger() {
+        let weak = hash_password("password", &cheap_argon2()).unwrap();
+
+        assert!(!password_needs_rehash(&weak, &cheap_argon2()));
+        assert!(password_needs_rehash(&weak, &Argon2Config { memory_kib: 2048, ..cheap_argon2() }));
+        assert!(password_needs_rehash(&weak, &Argon2Config { iterations: 2, ..cheap_argon2() }));
+        assert!(password_needs_rehash(&weak, &Argon2Config { parallelism: 2, ..cheap_argon2() }));
+    }
+
+    #[test]
+    fn test_password_needs_rehash_keeps_stronger_hashes() {
+        let strong = hash_password("password", &Argon2Config { iterations: 3, ..cheap_argon2() }).unwrap();
+        assert!(!password_needs_rehash(&strong, &cheap_argon2()));
+    }
+
+    #[test]
+    fn test_password_needs_rehash_for_other_variants() {
+        let salt = SaltString::generate(&mut OsRng);
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, cheap_argon2().params().unwrap())
+            .hash_password(b"password", &salt)
+            .unwrap()
+            .to_string();
+
+        assert!(password_needs_rehash(&argon2i, &cheap_argon2()));
+        assert!(!password_needs_rehash("invalid_hash", &cheap_argon2()));
+    }
+
     // JWT token tests
     
     #[test]
     fn test_generate_token_creates_valid_token() {
         let secret = "test_secret_key";
-        let token = generate_token(1, "test@example.com", false, secret, 3600).unwrap();
+        let token = generate_token(1, "test@example.com", false, 0, secret, 3600).unwrap();
         
         // Token should be a non-empty string
         assert!(!token.is_empty());
@@ -357,7 +610,7 @@ mod tests {
         let email = "user@example.com";
         let is_admin = true;
         
-        let token = generate_token(user_id, email, is_admin, secret, 3600).unwrap();
+        let token = generate_token(user_id, email, is_admin, 0, secret, 3600).unwrap();
         let claims = verify_token(&token, secret).unwrap();
         
         assert_eq!(claims.sub, user_id);
@@ -370,7 +623,7 @@ mod tests {
         let secret = "test_secret_key";
         let wrong_secret = "wrong_secret_key";
         
-        let token = generate_token(1, "test@example.com", false, secret, 3600).unwrap();
+        let token = generate_token(1, "test@example.com", false, 0, secret, 3600).unwrap();
         let result = verify_token(&token, wrong_secret);
         
         assert!(result.is_err());
@@ -384,10 +637,72 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_token_version_claim() {
+        let secret = "test_secret_key";
+        let token = generate_token(1, "test@example.com", false, 3, secret, 3600).unwrap();
+        assert_eq!(verify_token(&token, secret).unwrap().ver, 3);
+
+        // Tokens issued before the version claim existed decode as version 0
+        let legacy = encode(
+            &Header::default(),
+            &serde_json::json!({
+                "sub": 1,
+                "email": "test@example.com",
+                "is_admin": false,
+                "exp": Utc::now().timestamp() + 3600,
+                "iat": Utc::now().timestamp(),
+            }),
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap();
+        let legacy_claims = verify_token(&legacy, secret).unwrap();
+        assert_eq!(legacy_claims.ver, 0);
+        // ... and as non-platform users of the default tenant
+        assert_eq!(legacy_claims.tenant_id, crate::tenancy::DEFAULT_TENANT_ID);
+        assert!(!legacy_claims.platform_admin);
+    }
+
+    #[test]
+    fn test_user_token_carries_tenant() {
+        let secret = "test_secret_key";
+        let mut user = User {
+            id: 7,
+            email: "admin@brand.example.com".to_string(),
+            password_hash: String::new(),
+            coin_balance: Default::default(),
+            traffic_quota: Default::default(),
+            traffic_used: Default::default(),
+            referral_code: None,
+            referred_by: None,
+            status: crate::status::UserStatus::Active,
+            is_admin: true,
+            language: None,
+            reserved_coins: Default::default(),
+            token_version: 2,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            tenant_id: 3,
+            is_platform_admin: false,
+            import_ref: None,
+            is_sandbox: false,
+        };
+
+        let claims = verify_token(&generate_user_token(&user, secret, 3600).unwrap(), secret).unwrap();
+        assert_eq!((claims.sub, claims.tenant_id, claims.ver), (7, 3, 2));
+        assert!(claims.is_admin && !claims.platform_admin);
+
+        // The platform flag alone does not make a regular user an admin
+        user.is_admin = false;
+        user.is_platform_admin = true;
+        let claims = verify_token(&generate_user_token(&user, secret, 3600).unwrap(), secret).unwrap();
+        assert!(!claims.is_admin && !claims.platform_admin);
+    }
+
     #[test]
     fn test_generate_refresh_token() {
         let secret = "test_secret_key";
-        let token = generate_refresh_token(1, "test@example.com", false, secret).unwrap();
+        let token = generate_refresh_token(1, "test@example.com", false, 0, secret).unwrap();
         
         // Verify the token is valid
         let claims = verify_token(&token, secret).unwrap();
@@ -407,7 +722,7 @@ mod tests {
         let email = "admin@example.com";
         let is_admin = true;
         
-        let token = generate_token(user_id, email, is_admin, secret, 3600).unwrap();
+        let token = generate_token(user_id, email, is_admin, 0, secret, 3600).unwrap();
         let claims = verify_token(&token, secret).unwrap();
         
         // Verify all claims
@@ -427,8 +742,8 @@ mod tests {
     fn test_different_users_get_different_tokens() {
         let secret = "test_secret_key";
         
-        let token1 = generate_token(1, "user1@example.com", false, secret, 3600).unwrap();
-        let token2 = generate_token(2, "user2@example.com", false, secret, 3600).unwrap();
+        let token1 = generate_token(1, "user1@example.com", false, 0, secret, 3600).unwrap();
+        let token2 = generate_token(2, "user2@example.com", false, 0, secret, 3600).unwrap();
         
         assert_ne!(token1, token2);
     }
@@ -437,8 +752,8 @@ mod tests {
     fn test_admin_and_regular_user_tokens() {
         let secret = "test_secret_key";
         
-        let admin_token = generate_token(1, "admin@example.com", true, secret, 3600).unwrap();
-        let user_token = generate_token(2, "user@example.com", false, secret, 3600).unwrap();
+        let admin_token = generate_token(1, "admin@example.com", true, 0, secret, 3600).unwrap();
+        let user_token = generate_token(2, "user@example.com", false, 0, secret, 3600).unwrap();
         
         let admin_claims = verify_token(&admin_token, secret).unwrap();
         let user_claims = verify_token(&user_token, secret).unwrap();
@@ -473,34 +788,12 @@ mod tests {
     }
 
     #[test]
-    fn test_validate_password_valid() {
-        assert!(validate_password("password123").is_ok());
-        assert!(validate_password("Test1234").is_ok());
-        assert!(validate_password("abcdefgh1").is_ok());
-        assert!(validate_password("P@ssw0rd!").is_ok());
-    }
-
-    #[test]
-    fn test_validate_password_too_short() {
-        assert!(validate_password("pass1").is_err());
-        assert!(validate_password("abc123").is_err());
-    }
-
-    #[test]
-    fn test_validate_password_no_letters() {
-        assert!(validate_password("12345678").is_err());
-    }
-
-    #[test]
-    fn test_validate_password_no_numbers() {
-        assert!(validate_password("password").is_err());
-        assert!(validate_password("abcdefgh").is_err());
-    }
-
-    #[test]
-    fn test_validate_password_too_long() {
-        let long_password = format!("{}1", "a".repeat(130));
-        assert!(validate_password(&long_password).is_err());
+    fn test_mask_email() {
+        assert_eq!(mask_email("alice@example.com"), "a***e@example.com");
+        assert_eq!(mask_email("bo@example.com"), "b***@example.com");
+        assert_eq!(mask_email("x@example.com"), "x***@example.com");
+        assert_eq!(mask_email("张三丰@example.com"), "张***丰@example.com");
+        assert_eq!(mask_email("not-an-email"), "n***l");
     }
 
     #[test]
@@ -520,6 +813,14 @@ mod tests {
         assert!(code2.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()));
     }
 
+    #[test]
+    fn test_generate_short_link_code() {
+        let code = generate_short_link_code();
+        assert_eq!(code.len(), 8);
+        assert!(code.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(code, generate_short_link_code());
+    }
+
     // XSS Prevention tests
     
     #[test]
@@ -624,7 +925,7 @@ mod tests {
             password in "[A-Za-z0-9!@#$%^&*()_+\\-=\\[\\]{};':\"\\\\|,.<>/?]{8,32}"
         ) {
             // Hash the password
-            let hash = hash_password(&password).unwrap();
+            let hash = hash_password(&password, &Argon2Config::default()).unwrap();
             
             // Property 1: Hash should not equal the original password
             prop_assert_ne!(&hash, &password);
@@ -643,7 +944,7 @@ mod tests {
             prop_assert!(!verify_password(&different_password, &hash).unwrap());
             
             // Property 6: Same password should produce different hashes (due to salt)
-            let hash2 = hash_password(&password).unwrap();
+            let hash2 = hash_password(&password, &Argon2Config::default()).unwrap();
             prop_assert_ne!(&hash, &hash2);
             
             // Property 7: Both hashes should verify with the original password
@@ -659,7 +960,7 @@ mod tests {
         fn test_password_not_in_hash(
             password in "[A-Za-z0-9]{8,16}"
         ) {
-            let hash = hash_password(&password).unwrap();
+            let hash = hash_password(&password, &Argon2Config::default()).unwrap();
             
             // The hash should not contain the original password as a substring
             prop_assert!(!hash.contains(&password));
@@ -675,7 +976,7 @@ mod tests {
             password in prop::string::string_regex(".*").unwrap()
         ) {
             // Should be able to hash any string
-            let hash_result = hash_password(&password);
+            let hash_result = hash_password(&password, &Argon2Config::default());
             prop_assert!(hash_result.is_ok());
             
             if let Ok(hash) = hash_result {
@@ -693,7 +994,7 @@ mod tests {
         fn test_unicode_password_hashing(
             password in "[\\u{4e00}-\\u{9fff}]{4,16}" // Chinese characters
         ) {
-            let hash = hash_password(&password).unwrap();
+            let hash = hash_password(&password, &Argon2Config::default()).unwrap();
             
             // Should verify correctly
             prop_assert!(verify_password(&password, &hash).unwrap());
@@ -713,7 +1014,7 @@ mod tests {
             password in "[A-Za-z0-9!@#$%^&*]{8,20}",
             num_verifications in 1..=10usize
         ) {
-            let hash = hash_password(&password).unwrap();
+            let hash = hash_password(&password, &Argon2Config::default()).unwrap();
             
             // Verify multiple times - should always succeed
             for _ in 0..num_verifications {
@@ -740,7 +1041,7 @@ mod tests {
             let secret = "test_secret_key_for_property_test";
             
             // Property 1: Token generation should succeed for valid inputs
-            let token_result = generate_token(user_id, &email, is_admin, secret, expiration);
+            let token_result = generate_token(user_id, &email, is_admin, 0, secret, expiration);
             prop_assert!(token_result.is_ok());
             
             let token = token_result.unwrap();
@@ -789,7 +1090,7 @@ mod tests {
             let secret = "test_secret_for_roundtrip";
             
             // Generate token
-            let token = generate_token(user_id, &email, is_admin, secret, 3600).unwrap();
+            let token = generate_token(user_id, &email, is_admin, 0, secret, 3600).unwrap();
             
             // Verify and extract claims
             let claims = verify_token(&token, secret).unwrap();
@@ -813,7 +1114,7 @@ mod tests {
         ) {
             let secret = "test_secret";
             
-            let token = generate_token(user_id, &email, is_admin, secret, 3600).unwrap();
+            let token = generate_token(user_id, &email, is_admin, 0, secret, 3600).unwrap();
             let claims = verify_token(&token, secret).unwrap();
             
             // Admin role should be preserved exactly
@@ -834,11 +1135,11 @@ mod tests {
             let secret = "test_secret";
             
             // Generate regular token (1 hour)
-            let regular_token = generate_token(user_id, &email, is_admin, secret, 3600).unwrap();
+            let regular_token = generate_token(user_id, &email, is_admin, 0, secret, 3600).unwrap();
             let regular_claims = verify_token(&regular_token, secret).unwrap();
             
             // Generate refresh token (7 days)
-            let refresh_token = generate_refresh_token(user_id, &email, is_admin, secret).unwrap();
+            let refresh_token = generate_refresh_token(user_id, &email, is_admin, 0, secret).unwrap();
             let refresh_claims = verify_token(&refresh_token, secret).unwrap();
             
             // Refresh token should expire much later than regular token
@@ -850,4 +1151,35 @@ mod tests {
             prop_assert!((refresh_claims.exp - expected_refresh_exp).abs() < 10);
         }
     }
+
+    #[test]
+    fn test_parse_user_agent_known_clients() {
+        let cases = [
+            ("ClashX/1.95.1", "ClashX", Some("1.95.1")),
+            ("ClashX Pro/1.72.0.4", "ClashX Pro", Some("1.72.0.4")),
+            ("clash-verge/v1.3.8", "Clash Verge", Some("1.3.8")),
+            ("ClashForAndroid/2.5.12", "Clash for Android", Some("2.5.12")),
+            ("Stash/2.4.5 Clash/1.9.0", "Stash", Some("2.4.5")),
+            ("Shadowrocket/1980 CFNetwork/1408.0.4 Darwin/22.5.0", "Shadowrocket", Some("1980")),
+            ("mihomo/1.18.0", "Mihomo", Some("1.18.0")),
+            ("clash", "Clash", None),
+        ];
+
+        for (ua, name, version) in cases {
+            let client = parse_user_agent(Some(ua));
+            assert_eq!(client.name, name, "name for {}", ua);
+            assert_eq!(client.version.as_deref(), version, "version for {}", ua);
+        }
+    }
+
+    #[test]
+    fn test_parse_user_agent_fallbacks() {
+        assert_eq!(parse_user_agent(None).name, "Unknown");
+        assert_eq!(parse_user_agent(Some("  ")).name, "Unknown");
+        assert_eq!(parse_user_agent(Some("curl/8.4.0")).name, "Unknown");
+        assert_eq!(
+            parse_user_agent(Some("Mozilla/5.0 (Windows NT 10.0; Win64; x64)")).name,
+            "Browser"
+        );
+    }
 }
@@ -21,6 +21,11 @@ const router = createRouter({
       component: () => import('../views/Login.vue'),
       meta: { requiresGuest: true }
     },
+    {
+      path: '/status',
+      name: 'status',
+      component: () => import('../views/Status.vue')
+    },
     {
       path: '/dashboard',
       name: 'dashboard',
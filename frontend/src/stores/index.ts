@@ -5,6 +5,7 @@ export { usePackageStore } from './package'
 export { useOrderStore } from './order'
 export { useSubscriptionStore } from './subscription'
 export { useReferralStore } from './referral'
+export { useStatusStore } from './status'
 
 
 
@@ -1,13 +1,14 @@
 import { defineStore } from 'pinia'
 import { ref } from 'vue'
 import api from '@/api'
-import type { Package } from '@/types'
+import type { Package, PackageEligibility } from '@/types'
 
 export const usePackageStore = defineStore('package', () => {
   const packages = ref<Package[]>([])
   const loading = ref(false)
   const error = ref<string | null>(null)
   const purchasing = ref(false)
+  const eligibility = ref<Record<number, PackageEligibility>>({})
 
   const fetchPackages = async () => {
     loading.value = true
@@ -16,6 +17,7 @@ export const usePackageStore = defineStore('package', () => {
     try {
       const response = await api.get<Package[]>('/packages')
       packages.value = response.data
+      await fetchEligibility()
     } catch (e: any) {
       error.value = e.response?.data?.error?.message || '获取套餐列表失败'
     } finally {
@@ -23,6 +25,21 @@ export const usePackageStore = defineStore('package', () => {
     }
   }
 
+  // Reasons a purchase would fail, so buttons can be disabled up front
+  const fetchEligibility = async () => {
+    const results = await Promise.all(
+      packages.value.map((pkg) =>
+        api
+          .get<PackageEligibility>(`/packages/${pkg.id}/eligibility`)
+          .then((response) => response.data)
+          .catch(() => null)
+      )
+    )
+    eligibility.value = Object.fromEntries(
+      results.filter((e): e is PackageEligibility => e !== null).map((e) => [e.package_id, e])
+    )
+  }
+
   const purchasePackage = async (packageId: number) => {
     purchasing.value = true
     error.value = null
@@ -43,7 +60,9 @@ export const usePackageStore = defineStore('package', () => {
     loading,
     error,
     purchasing,
+    eligibility,
     fetchPackages,
+    fetchEligibility,
     purchasePackage
   }
 })
@@ -0,0 +1,31 @@
+import { defineStore } from 'pinia'
+import { ref } from 'vue'
+import api from '@/api'
+import type { StatusPage } from '@/types'
+
+export const useStatusStore = defineStore('status', () => {
+  const page = ref<StatusPage | null>(null)
+  const loading = ref(false)
+  const error = ref<string | null>(null)
+
+  const fetchStatus = async () => {
+    loading.value = true
+    error.value = null
+
+    try {
+      const response = await api.get<StatusPage>('/status')
+      page.value = response.data
+    } catch (e: any) {
+      error.value = e.response?.data?.error?.message || '获取服务状态失败'
+    } finally {
+      loading.value = false
+    }
+  }
+
+  return {
+    page,
+    loading,
+    error,
+    fetchStatus
+  }
+})
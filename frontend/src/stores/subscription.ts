@@ -1,11 +1,13 @@
 import { defineStore } from 'pinia'
 import { ref } from 'vue'
 import api from '@/api'
-import type { Subscription, Node } from '@/types'
+import type { Subscription, SubscriptionAccessLog, Node } from '@/types'
 
 export const useSubscriptionStore = defineStore('subscription', () => {
   const subscription = ref<Subscription | null>(null)
   const nodes = ref<Node[]>([])
+  const accessLogs = ref<SubscriptionAccessLog[]>([])
+  const uniqueIps = ref(0)
   const loading = ref(false)
   const error = ref<string | null>(null)
 
@@ -38,12 +40,25 @@ export const useSubscriptionStore = defineStore('subscription', () => {
     }
   }
 
+  const fetchAccessLogs = async (limit = 20) => {
+    try {
+      const response = await api.get('/user/subscription/access-logs', { params: { limit } })
+      accessLogs.value = response.data.logs
+      uniqueIps.value = response.data.unique_ips
+    } catch (e: any) {
+      console.error('Failed to fetch access logs:', e)
+    }
+  }
+
   return {
     subscription,
     nodes,
+    accessLogs,
+    uniqueIps,
     loading,
     error,
     fetchSubscription,
-    fetchNodes
+    fetchNodes,
+    fetchAccessLogs
   }
 })
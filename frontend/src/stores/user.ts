@@ -1,7 +1,7 @@
 import { defineStore } from 'pinia'
 import { ref } from 'vue'
 import api from '@/api'
-import type { TrafficInfo } from '@/types'
+import type { TrafficInfo, UpgradeSuggestion } from '@/types'
 
 export const useUserStore = defineStore('user', () => {
   const balance = ref(0)
@@ -11,6 +11,7 @@ export const useUserStore = defineStore('user', () => {
     traffic_remaining: 0,
     percentage_used: 0
   })
+  const upgradeSuggestion = ref<UpgradeSuggestion | null>(null)
   const loading = ref(false)
 
   const fetchBalance = async () => {
@@ -31,10 +32,19 @@ export const useUserStore = defineStore('user', () => {
     }
   }
 
+  const fetchSuggestions = async () => {
+    try {
+      const response = await api.get('/user/suggestions')
+      upgradeSuggestion.value = response.data.upgrade
+    } catch (e) {
+      console.error('Failed to fetch suggestions:', e)
+    }
+  }
+
   const refresh = async () => {
     loading.value = true
     try {
-      await Promise.all([fetchBalance(), fetchTraffic()])
+      await Promise.all([fetchBalance(), fetchTraffic(), fetchSuggestions()])
     } finally {
       loading.value = false
     }
@@ -43,9 +53,11 @@ export const useUserStore = defineStore('user', () => {
   return {
     balance,
     traffic,
+    upgradeSuggestion,
     loading,
     fetchBalance,
     fetchTraffic,
+    fetchSuggestions,
     refresh
   }
 })
@@ -19,6 +19,28 @@ export interface Package {
   duration_days: number
   description: string | null
   is_active: boolean
+  stock: number | null
+  max_per_user: number
+  exclusive: boolean
+}
+
+export type Ineligibility =
+  | 'package_unavailable'
+  | 'account_disabled'
+  | 'insufficient_balance'
+  | 'out_of_stock'
+  | 'purchase_limit_reached'
+  | 'conflicts_with_current_package'
+
+export interface PackageEligibility {
+  package_id: number
+  eligible: boolean
+  reasons: Ineligibility[]
+  price: number
+  available_balance: number
+  shortfall: number
+  stock: number | null
+  remaining_purchases: number | null
 }
 
 // Order types
@@ -40,6 +62,15 @@ export interface Subscription {
   url: string
 }
 
+export interface SubscriptionAccessLog {
+  access_timestamp: string
+  ip_address: string
+  user_agent: string | null
+  client_name: string
+  client_version: string | null
+  response_status: string
+}
+
 // Referral types
 export interface ReferralStats {
   referral_count: number
@@ -76,6 +107,54 @@ export interface TrafficInfo {
   percentage_used: number
 }
 
+// Upgrade suggestion types
+export interface PackageCandidate {
+  package_id: number
+  name: string
+  traffic_amount: number
+  price: number
+  duration_days: number
+  covers_shortfall: boolean
+}
+
+export interface UpgradeSuggestion {
+  remaining: number
+  daily_usage: number
+  projected_exhaustion_at: string
+  expires_at: string
+  shortfall: number
+  candidates: PackageCandidate[]
+}
+
+// Public status page types
+export type NodeAvailability = 'online' | 'offline' | 'maintenance'
+export type LoadBucket = 'low' | 'medium' | 'high' | 'full'
+export type IncidentSeverity = 'info' | 'minor' | 'major'
+
+export interface StatusPageNode {
+  region: string | null
+  country: string | null
+  availability: NodeAvailability
+  load: LoadBucket | null
+}
+
+export interface StatusPageIncident {
+  title: string
+  message: string
+  severity: IncidentSeverity
+  started_at: string
+  resolved_at: string | null
+}
+
+export interface StatusPage {
+  status: 'operational' | 'degraded' | 'major_outage'
+  nodes_online: number
+  nodes_total: number
+  nodes: StatusPageNode[]
+  incidents: StatusPageIncident[]
+  generated_at: string
+}
+
 // API Response types
 export interface ApiResponse<T> {
   data?: T
@@ -332,6 +332,1059 @@ ALTER TABLE nodes ADD COLUMN sort_order INTEGER DEFAULT 0;
 -- Create index for Clash-related queries
 CREATE INDEX idx_nodes_clash_inclusion ON nodes(include_in_clash, sort_order);
 
+-- ========================================
+-- MIGRATION 006: Package Subscription Update Interval
+-- ========================================
+
+-- How often Clash clients should refresh the subscription (profile-update-interval header)
+ALTER TABLE packages ADD COLUMN IF NOT EXISTS update_interval_hours INT NOT NULL DEFAULT 24 CHECK (update_interval_hours > 0);
+
+COMMENT ON COLUMN packages.update_interval_hours IS '订阅自动更新间隔（小时），通过 profile-update-interval 响应头下发给客户端';
+
+-- ========================================
+-- MIGRATION 007: Node Metrics History and Alerts
+-- ========================================
+
+-- Node metrics table (one row per heartbeat)
+CREATE TABLE node_metrics (
+    id BIGSERIAL PRIMARY KEY,
+    node_id BIGINT NOT NULL REFERENCES nodes(id) ON DELETE CASCADE,
+    cpu_usage DOUBLE PRECISION,
+    memory_usage DOUBLE PRECISION,
+    active_connections INT,
+    recorded_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX idx_node_metrics_node_recorded ON node_metrics(node_id, recorded_at DESC);
+
+-- Node alerts table (threshold breaches, resolved when the condition clears)
+CREATE TABLE node_alerts (
+    id BIGSERIAL PRIMARY KEY,
+    node_id BIGINT NOT NULL REFERENCES nodes(id) ON DELETE CASCADE,
+    alert_type VARCHAR(50) NOT NULL CHECK (alert_type IN ('high_cpu', 'connections_exceeded')),
+    message TEXT NOT NULL,
+    value DOUBLE PRECISION,
+    threshold DOUBLE PRECISION,
+    status VARCHAR(20) NOT NULL DEFAULT 'active' CHECK (status IN ('active', 'resolved')),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_node_alerts_status ON node_alerts(status, created_at DESC);
+-- At most one active alert per node and type
+CREATE UNIQUE INDEX idx_node_alerts_active ON node_alerts(node_id, alert_type) WHERE status = 'active';
+
+COMMENT ON TABLE node_metrics IS '节点心跳指标历史表';
+COMMENT ON TABLE node_alerts IS '节点告警表';
+
+-- ========================================
+-- MIGRATION 008: User Language Preference
+-- ========================================
+
+-- Preferred language for API error messages and notifications (NULL = use Accept-Language)
+ALTER TABLE users ADD COLUMN IF NOT EXISTS language VARCHAR(10) CHECK (language IN ('en', 'zh'));
+
+COMMENT ON COLUMN users.language IS '用户首选语言（en/zh），为空时按 Accept-Language 请求头选择';
+
+-- ========================================
+-- MIGRATION 009: Subscription Naming Preferences
+-- ========================================
+
+-- Per-user options controlling how proxy names are rendered in generated configs
+CREATE TABLE subscription_preferences (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    emoji_flag BOOLEAN NOT NULL DEFAULT FALSE,
+    region_prefix BOOLEAN NOT NULL DEFAULT FALSE,
+    show_traffic_ratio BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+COMMENT ON TABLE subscription_preferences IS '用户订阅节点命名偏好表';
+COMMENT ON COLUMN subscription_preferences.emoji_flag IS '节点名称前添加国旗 emoji（取自节点 config.country）';
+COMMENT ON COLUMN subscription_preferences.region_prefix IS '节点名称前添加地区前缀（取自节点 config.region 或 config.country）';
+COMMENT ON COLUMN subscription_preferences.show_traffic_ratio IS '节点名称后显示流量倍率（取自节点 config.traffic_ratio）';
+
+-- ========================================
+-- MIGRATION 010: Mixed Coin + External Payment Orders
+-- ========================================
+
+-- Coins held by orders awaiting external payment (available balance = coin_balance - reserved_coins)
+ALTER TABLE users ADD COLUMN IF NOT EXISTS reserved_coins BIGINT NOT NULL DEFAULT 0 CHECK (reserved_coins >= 0);
+
+-- Split of the order amount between coins and the payment gateway
+ALTER TABLE orders ADD COLUMN IF NOT EXISTS coin_amount BIGINT NOT NULL DEFAULT 0 CHECK (coin_amount >= 0);
+ALTER TABLE orders ADD COLUMN IF NOT EXISTS external_amount BIGINT NOT NULL DEFAULT 0 CHECK (external_amount >= 0);
+ALTER TABLE orders ADD COLUMN IF NOT EXISTS payment_reference VARCHAR(128);
+ALTER TABLE orders ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ;
+
+-- NOT VALID: on a re-run, existing orders may already use statuses added by later migrations,
+-- which replace this constraint
+ALTER TABLE orders DROP CONSTRAINT IF EXISTS orders_status_check;
+ALTER TABLE orders ADD CONSTRAINT orders_status_check
+    CHECK (status IN ('pending', 'awaiting_payment', 'completed', 'failed', 'expired')) NOT VALID;
+
+CREATE INDEX IF NOT EXISTS idx_orders_awaiting_payment ON orders(expires_at) WHERE status = 'awaiting_payment';
+
+COMMENT ON COLUMN users.reserved_coins IS '待支付订单预留的金币，可用余额 = coin_balance - reserved_coins';
+COMMENT ON COLUMN orders.coin_amount IS '订单中由金币支付的部分';
+COMMENT ON COLUMN orders.external_amount IS '订单中由第三方支付的部分';
+COMMENT ON COLUMN orders.payment_reference IS '第三方支付流水号';
+COMMENT ON COLUMN orders.expires_at IS '待支付订单过期时间，过期后释放预留金币';
+
+-- ========================================
+-- MIGRATION 011: Per-Package Concurrent Device Limit
+-- ========================================
+
+ALTER TABLE packages ADD COLUMN IF NOT EXISTS max_connections INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE packages DROP CONSTRAINT IF EXISTS packages_max_connections_check;
+ALTER TABLE packages ADD CONSTRAINT packages_max_connections_check CHECK (max_connections >= 0);
+
+COMMENT ON COLUMN packages.max_connections IS '每个用户跨所有节点的最大并发连接数，0 表示不限制';
+
+-- ========================================
+-- MIGRATION 012: Node Monthly Traffic Budget
+-- ========================================
+
+-- Monthly traffic budget per node (bytes, 0 = unlimited) and what to do once it is used up
+ALTER TABLE nodes ADD COLUMN IF NOT EXISTS traffic_budget BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE nodes ADD COLUMN IF NOT EXISTS traffic_budget_action VARCHAR(20) NOT NULL DEFAULT 'alert';
+
+ALTER TABLE nodes DROP CONSTRAINT IF EXISTS nodes_traffic_budget_check;
+ALTER TABLE nodes ADD CONSTRAINT nodes_traffic_budget_check CHECK (traffic_budget >= 0);
+ALTER TABLE nodes DROP CONSTRAINT IF EXISTS nodes_traffic_budget_action_check;
+ALTER TABLE nodes ADD CONSTRAINT nodes_traffic_budget_action_check
+    CHECK (traffic_budget_action IN ('alert', 'maintenance', 'exclude_from_clash'));
+
+-- Traffic aggregated per node and calendar month (UTC) by the traffic pipeline
+CREATE TABLE IF NOT EXISTS node_traffic_monthly (
+    node_id BIGINT NOT NULL REFERENCES nodes(id) ON DELETE CASCADE,
+    month DATE NOT NULL,
+    upload BIGINT NOT NULL DEFAULT 0 CHECK (upload >= 0),
+    download BIGINT NOT NULL DEFAULT 0 CHECK (download >= 0),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (node_id, month)
+);
+
+ALTER TABLE node_alerts DROP CONSTRAINT IF EXISTS node_alerts_alert_type_check;
+ALTER TABLE node_alerts ADD CONSTRAINT node_alerts_alert_type_check
+    CHECK (alert_type IN ('high_cpu', 'connections_exceeded', 'traffic_budget_warning', 'traffic_budget_exceeded'));
+
+COMMENT ON COLUMN nodes.traffic_budget IS '节点每月流量预算（字节），0 表示不限制';
+COMMENT ON COLUMN nodes.traffic_budget_action IS '流量预算用尽后的处理方式：alert 仅告警，maintenance 设为维护状态，exclude_from_clash 从订阅中移除';
+COMMENT ON TABLE node_traffic_monthly IS '节点月度流量汇总表';
+
+-- ========================================
+-- MIGRATION 013: User Token Version
+-- ========================================
+
+-- Embedded in issued JWTs; bumping it invalidates every outstanding token of the user
+ALTER TABLE users ADD COLUMN IF NOT EXISTS token_version INTEGER NOT NULL DEFAULT 0;
+
+COMMENT ON COLUMN users.token_version IS '令牌版本号，凭证或状态变更时递增，使已签发的令牌全部失效';
+
+-- ========================================
+-- MIGRATION 014: Node Agent Runtime Settings
+-- ========================================
+
+-- Settings the node agent applies without restart (intervals, probe targets)
+ALTER TABLE nodes ADD COLUMN IF NOT EXISTS agent_settings JSONB NOT NULL DEFAULT '{}'::jsonb;
+
+COMMENT ON COLUMN nodes.agent_settings IS '节点代理运行时配置（上报间隔、心跳间隔、探测目标等），修改后无需重启即可生效';
+
+-- ========================================
+-- MIGRATION 015: User Traffic Report Index
+-- ========================================
+
+-- Per-user traffic reports filter by user and time range
+CREATE INDEX IF NOT EXISTS idx_traffic_logs_user_recorded_at ON traffic_logs(user_id, recorded_at);
+
+-- ========================================
+-- MIGRATION 016: Admin Background Jobs
+-- ========================================
+
+-- Long-running admin tasks (CSV exports, recalculations) processed by API workers
+CREATE TABLE IF NOT EXISTS admin_jobs (
+    id BIGSERIAL PRIMARY KEY,
+    kind VARCHAR(50) NOT NULL,
+    params JSONB NOT NULL DEFAULT '{}'::jsonb,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'running', 'completed', 'failed')),
+    progress SMALLINT NOT NULL DEFAULT 0 CHECK (progress BETWEEN 0 AND 100),
+    error TEXT,
+    result BYTEA,
+    result_filename VARCHAR(255),
+    result_content_type VARCHAR(100),
+    result_expires_at TIMESTAMPTZ,
+    created_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_admin_jobs_pending ON admin_jobs(created_at) WHERE status = 'pending';
+
+COMMENT ON TABLE admin_jobs IS '后台任务表（导出、重算等耗时操作）';
+COMMENT ON COLUMN admin_jobs.result IS '任务结果文件内容，过期后清除';
+
+-- ========================================
+-- MIGRATION 017: Node Agent Self-Registration
+-- ========================================
+
+-- Self-registered nodes wait for admin approval before receiving users
+ALTER TABLE nodes ADD COLUMN IF NOT EXISTS approved BOOLEAN NOT NULL DEFAULT true;
+ALTER TABLE nodes ADD COLUMN IF NOT EXISTS capabilities JSONB NOT NULL DEFAULT '{}'::jsonb;
+
+-- Single-use tokens a fresh agent exchanges for its node record and permanent secret
+CREATE TABLE IF NOT EXISTS node_enrollment_tokens (
+    id BIGSERIAL PRIMARY KEY,
+    token_hash VARCHAR(64) UNIQUE NOT NULL,
+    description VARCHAR(255),
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    node_id BIGINT REFERENCES nodes(id) ON DELETE SET NULL,
+    created_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+COMMENT ON COLUMN nodes.approved IS '节点是否已审核，自注册节点审核通过前不下发用户';
+COMMENT ON COLUMN nodes.capabilities IS '节点代理注册时上报的能力信息（系统、架构、支持的协议等）';
+COMMENT ON TABLE node_enrollment_tokens IS '节点注册令牌表（一次性使用，仅保存哈希）';
+
+-- ========================================
+-- MIGRATION 018: Feature Flags
+-- ========================================
+
+-- Runtime switches for gradual rollouts, evaluated per user by the API
+CREATE TABLE IF NOT EXISTS feature_flags (
+    key VARCHAR(100) PRIMARY KEY,
+    description VARCHAR(255),
+    enabled BOOLEAN NOT NULL DEFAULT false,
+    rollout_percentage SMALLINT NOT NULL DEFAULT 0 CHECK (rollout_percentage BETWEEN 0 AND 100),
+    user_ids BIGINT[] NOT NULL DEFAULT '{}',
+    updated_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+COMMENT ON TABLE feature_flags IS '功能开关表（灰度发布，无需重新部署即可切换）';
+COMMENT ON COLUMN feature_flags.enabled IS '总开关，关闭时对所有用户无效';
+COMMENT ON COLUMN feature_flags.rollout_percentage IS '按用户 ID 哈希分桶开启的用户比例（0-100）';
+COMMENT ON COLUMN feature_flags.user_ids IS '总开关开启时始终启用的用户 ID 列表';
+
+-- ========================================
+-- MIGRATION 019: Node Authentication Lockout
+-- ========================================
+
+-- Nodes are suspended after repeated authentication failures until an admin lifts it
+ALTER TABLE nodes DROP CONSTRAINT IF EXISTS nodes_status_check;
+ALTER TABLE nodes ADD CONSTRAINT nodes_status_check
+    CHECK (status IN ('online', 'offline', 'maintenance', 'suspended'));
+
+ALTER TABLE node_alerts DROP CONSTRAINT IF EXISTS node_alerts_alert_type_check;
+ALTER TABLE node_alerts ADD CONSTRAINT node_alerts_alert_type_check
+    CHECK (alert_type IN ('high_cpu', 'connections_exceeded', 'traffic_budget_warning', 'traffic_budget_exceeded', 'auth_lockout'));
+
+COMMENT ON COLUMN nodes.status IS '节点状态：online 在线，offline 离线，maintenance 维护，suspended 因多次认证失败被暂停';
+
+-- ========================================
+-- MIGRATION 020: Order State Machine
+-- ========================================
+
+ALTER TABLE orders DROP CONSTRAINT IF EXISTS orders_status_check;
+ALTER TABLE orders ADD CONSTRAINT orders_status_check
+    CHECK (status IN ('pending', 'awaiting_payment', 'completed', 'failed', 'expired', 'cancelled', 'refunded'));
+
+-- Bumped on every status change; transitions only apply to the version they read
+ALTER TABLE orders ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
+
+-- One row per status change, written in the same transaction (feed for webhooks/notifications)
+CREATE TABLE order_events (
+    id BIGSERIAL PRIMARY KEY,
+    order_id BIGINT NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    order_no VARCHAR(64) NOT NULL,
+    user_id BIGINT NOT NULL,
+    from_status VARCHAR(20) NOT NULL,
+    to_status VARCHAR(20) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_order_events_order ON order_events(order_id, created_at);
+CREATE INDEX idx_order_events_created_at ON order_events(created_at);
+
+COMMENT ON COLUMN orders.status IS '订单状态：pending 待处理，awaiting_payment 待支付，completed 已完成，failed 支付失败，expired 已过期，cancelled 已取消，refunded 已退款';
+COMMENT ON COLUMN orders.version IS '乐观锁版本号，每次状态变更加一';
+COMMENT ON TABLE order_events IS '订单状态变更事件，供 Webhook 与通知使用';
+
+-- ========================================
+-- MIGRATION 021: OAuth Identities
+-- ========================================
+
+CREATE TABLE oauth_identities (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider VARCHAR(20) NOT NULL CHECK (provider IN ('github', 'google')),
+    subject VARCHAR(255) NOT NULL,
+    email VARCHAR(255),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_login_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (provider, subject)
+);
+
+CREATE INDEX idx_oauth_identities_user ON oauth_identities(user_id);
+
+COMMENT ON TABLE oauth_identities IS '第三方登录身份（GitHub / Google），按已验证邮箱关联到平台账户';
+COMMENT ON COLUMN oauth_identities.subject IS '第三方平台的账户 ID';
+COMMENT ON COLUMN oauth_identities.email IS '关联时第三方平台提供的已验证邮箱';
+
+-- ========================================
+-- MIGRATION 022: Credential Rotation
+-- ========================================
+
+ALTER TABLE nodes ADD COLUMN secret_rotated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE nodes ADD COLUMN previous_secret TEXT;
+ALTER TABLE nodes ADD COLUMN previous_secret_expires_at TIMESTAMPTZ;
+
+ALTER TABLE subscriptions ADD COLUMN token_rotated_at TIMESTAMPTZ;
+ALTER TABLE subscriptions ADD COLUMN previous_token VARCHAR(64);
+ALTER TABLE subscriptions ADD COLUMN previous_token_expires_at TIMESTAMPTZ;
+
+CREATE INDEX idx_nodes_secret_rotated_at ON nodes(secret_rotated_at);
+CREATE UNIQUE INDEX idx_subscriptions_previous_token ON subscriptions(previous_token)
+    WHERE previous_token IS NOT NULL;
+
+COMMENT ON COLUMN nodes.secret_rotated_at IS '节点密钥最近一次轮换时间';
+COMMENT ON COLUMN nodes.previous_secret IS '轮换前的旧密钥，过渡期内仍可用于节点认证';
+COMMENT ON COLUMN nodes.previous_secret_expires_at IS '旧密钥失效时间';
+COMMENT ON COLUMN subscriptions.token_rotated_at IS '订阅令牌最近一次轮换时间';
+COMMENT ON COLUMN subscriptions.previous_token IS '轮换前的旧订阅令牌，过渡期内仍可拉取订阅';
+COMMENT ON COLUMN subscriptions.previous_token_expires_at IS '旧订阅令牌失效时间';
+
+-- ========================================
+-- MIGRATION 023: Security Events
+-- ========================================
+
+CREATE TABLE security_events (
+    id BIGSERIAL PRIMARY KEY,
+    event_type VARCHAR(50) NOT NULL CHECK (event_type IN ('failed_logins', 'balance_adjustment', 'node_deleted')),
+    severity VARCHAR(20) NOT NULL CHECK (severity IN ('warning', 'critical')),
+    actor_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    target_type VARCHAR(50),
+    target_id BIGINT,
+    ip_address VARCHAR(45),
+    message TEXT NOT NULL,
+    details JSONB NOT NULL DEFAULT '{}',
+    alerted BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX idx_security_events_created_at ON security_events(created_at DESC);
+CREATE INDEX idx_security_events_type ON security_events(event_type, created_at DESC);
+
+COMMENT ON TABLE security_events IS '安全事件表（连续登录失败、大额余额调整、删除节点等）';
+COMMENT ON COLUMN security_events.actor_id IS '触发事件的管理员 ID，登录失败事件为空';
+COMMENT ON COLUMN security_events.target_type IS '事件对象类型（user/node）';
+COMMENT ON COLUMN security_events.alerted IS '是否已发送告警（同类事件在节流时间内只告警一次）';
+
+-- ========================================
+-- MIGRATION 024: Not Modified Subscription Access
+-- ========================================
+
+-- Conditional requests answered with 304 are logged separately from full downloads
+ALTER TABLE clash_access_logs DROP CONSTRAINT IF EXISTS clash_access_logs_response_status_check;
+ALTER TABLE clash_access_logs ADD CONSTRAINT clash_access_logs_response_status_check
+    CHECK (response_status IN ('success', 'not_modified', 'failed', 'quota_exceeded', 'expired', 'disabled'));
+
+COMMENT ON COLUMN clash_access_logs.response_status IS '响应状态：success-成功, not_modified-配置未变化（304）, failed-失败, quota_exceeded-流量超限, expired-已过期, disabled-已禁用';
+
+-- ========================================
+-- MIGRATION 025: Order Event Status Constraints
+-- ========================================
+
+-- Event rows are decoded into the same closed set of statuses as orders.status
+ALTER TABLE order_events DROP CONSTRAINT IF EXISTS order_events_from_status_check;
+ALTER TABLE order_events ADD CONSTRAINT order_events_from_status_check
+    CHECK (from_status IN ('pending', 'awaiting_payment', 'completed', 'failed', 'expired', 'cancelled', 'refunded'));
+ALTER TABLE order_events DROP CONSTRAINT IF EXISTS order_events_to_status_check;
+ALTER TABLE order_events ADD CONSTRAINT order_events_to_status_check
+    CHECK (to_status IN ('pending', 'awaiting_payment', 'completed', 'failed', 'expired', 'cancelled', 'refunded'));
+
+-- ========================================
+-- MIGRATION 026: Status Page Incidents
+-- ========================================
+
+CREATE TABLE status_incidents (
+    id BIGSERIAL PRIMARY KEY,
+    title VARCHAR(200) NOT NULL,
+    message TEXT NOT NULL DEFAULT '',
+    severity VARCHAR(20) NOT NULL DEFAULT 'minor' CHECK (severity IN ('info', 'minor', 'major')),
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ,
+    created_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_status_incidents_started_at ON status_incidents(started_at DESC);
+CREATE INDEX idx_status_incidents_open ON status_incidents(resolved_at) WHERE resolved_at IS NULL;
+
+COMMENT ON TABLE status_incidents IS '状态页故障公告表，未登录用户也可在公开状态页查看';
+COMMENT ON COLUMN status_incidents.severity IS '影响程度：info-通知（如计划维护）, minor-部分受影响, major-大面积故障';
+COMMENT ON COLUMN status_incidents.resolved_at IS '恢复时间，为空表示仍在处理中';
+
+-- ========================================
+-- MIGRATION 027: Multi-Tenancy
+-- ========================================
+
+CREATE TABLE tenants (
+    id BIGSERIAL PRIMARY KEY,
+    slug VARCHAR(50) UNIQUE NOT NULL,
+    name VARCHAR(100) NOT NULL,
+    domains TEXT[] NOT NULL DEFAULT '{}',
+    subscription_domain VARCHAR(255),
+    settings JSONB NOT NULL DEFAULT '{}',
+    is_active BOOLEAN NOT NULL DEFAULT true,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Existing data belongs to the default tenant, which also serves unknown hosts
+INSERT INTO tenants (id, slug, name) VALUES (1, 'default', 'Default') ON CONFLICT (id) DO NOTHING;
+SELECT setval('tenants_id_seq', GREATEST((SELECT MAX(id) FROM tenants), 1));
+
+CREATE INDEX idx_tenants_domains ON tenants USING GIN (domains);
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS tenant_id BIGINT NOT NULL DEFAULT 1 REFERENCES tenants(id);
+ALTER TABLE users ADD COLUMN IF NOT EXISTS is_platform_admin BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE packages ADD COLUMN IF NOT EXISTS tenant_id BIGINT NOT NULL DEFAULT 1 REFERENCES tenants(id);
+ALTER TABLE nodes ADD COLUMN IF NOT EXISTS tenant_id BIGINT NOT NULL DEFAULT 1 REFERENCES tenants(id);
+ALTER TABLE node_enrollment_tokens ADD COLUMN IF NOT EXISTS tenant_id BIGINT NOT NULL DEFAULT 1 REFERENCES tenants(id);
+
+-- Admins from before tenants existed keep access to everything
+UPDATE users SET is_platform_admin = true WHERE is_admin = true;
+
+-- The same email may register with several brands
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_email_key;
+DROP INDEX IF EXISTS idx_users_email;
+CREATE UNIQUE INDEX idx_users_tenant_email ON users(tenant_id, email);
+
+CREATE INDEX idx_packages_tenant_id ON packages(tenant_id);
+CREATE INDEX idx_nodes_tenant_id ON nodes(tenant_id);
+
+COMMENT ON TABLE tenants IS '租户表，一套部署可运行多个独立品牌的面板';
+COMMENT ON COLUMN tenants.domains IS '面板域名，按请求的 Host 头匹配租户，未匹配的域名使用默认租户';
+COMMENT ON COLUMN tenants.subscription_domain IS '订阅链接使用的域名，为空时使用请求的域名';
+COMMENT ON COLUMN tenants.settings IS '品牌设置（站点名称、Logo、主题色、客服联系方式等），对访客公开';
+COMMENT ON COLUMN users.tenant_id IS '用户所属租户，管理员只能管理本租户的数据';
+COMMENT ON COLUMN users.is_platform_admin IS '平台管理员，可管理所有租户及全局设置';
+COMMENT ON COLUMN packages.tenant_id IS '套餐所属租户';
+COMMENT ON COLUMN nodes.tenant_id IS '节点所属租户，仅出现在该租户用户的订阅中';
+
+-- ========================================
+-- MIGRATION 028: Transactional Outbox
+-- ========================================
+
+CREATE TABLE outbox_events (
+    id BIGSERIAL PRIMARY KEY,
+    kind VARCHAR(50) NOT NULL,
+    payload JSONB NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    available_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_outbox_events_pending ON outbox_events(available_at) WHERE delivered_at IS NULL;
+CREATE INDEX idx_outbox_events_delivered_at ON outbox_events(delivered_at) WHERE delivered_at IS NOT NULL;
+
+COMMENT ON TABLE outbox_events IS '事务发件箱：与状态变更在同一事务中写入，由后台任务投递到 Redis（缓存失效、节点配置更新、订单事件）';
+COMMENT ON COLUMN outbox_events.available_at IS '下次可投递时间；投递中的事件会被顺延以防重复领取，失败后按退避时间重试';
+COMMENT ON COLUMN outbox_events.delivered_at IS '投递成功时间，为空表示尚未投递';
+
+-- ========================================
+-- MIGRATION 029: API Keys
+-- ========================================
+
+-- Long-lived credentials for server-to-server integrations, used instead of admin JWTs
+CREATE TABLE api_keys (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    key_prefix VARCHAR(20) NOT NULL,
+    key_hash VARCHAR(64) UNIQUE NOT NULL,
+    scopes TEXT[] NOT NULL
+        CHECK (scopes <@ ARRAY['stats:read', 'traffic:write', 'users:provision']::TEXT[]),
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+    last_used_ip VARCHAR(45),
+    created_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+COMMENT ON TABLE api_keys IS 'API 密钥表（供外部系统调用，仅保存哈希）';
+COMMENT ON COLUMN api_keys.key_prefix IS '密钥开头几位明文，用于在列表中辨认密钥';
+COMMENT ON COLUMN api_keys.scopes IS '授权范围：stats:read 读取统计，traffic:write 上报流量，users:provision 创建用户';
+COMMENT ON COLUMN api_keys.last_used_at IS '最近使用时间（按分钟粒度更新）';
+
+-- ========================================
+-- MIGRATION 030: Node Tags
+-- ========================================
+
+-- Labels that let proxy groups pick up nodes by expression (tag:HK, protocol:vless, all)
+ALTER TABLE nodes ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}';
+
+COMMENT ON COLUMN nodes.tags IS '节点标签，代理组成员写 tag:标签 即可自动包含带该标签的节点';
+COMMENT ON COLUMN clash_proxy_groups.proxies IS '成员：代理或代理组名称，或生成配置时按当前节点展开的表达式（all、tag:标签、protocol:协议）';
+
+-- ========================================
+-- MIGRATION 031: Package Purchase Limits
+-- ========================================
+
+-- Stock (NULL = unlimited), per-user purchase limit (0 = unlimited) and exclusivity
+ALTER TABLE packages ADD COLUMN IF NOT EXISTS stock INTEGER CHECK (stock IS NULL OR stock >= 0);
+ALTER TABLE packages ADD COLUMN IF NOT EXISTS max_per_user INTEGER NOT NULL DEFAULT 0 CHECK (max_per_user >= 0);
+ALTER TABLE packages ADD COLUMN IF NOT EXISTS exclusive BOOLEAN NOT NULL DEFAULT false;
+
+-- Counting a user's purchases of a package
+CREATE INDEX IF NOT EXISTS idx_orders_user_package ON orders(user_id, package_id);
+
+COMMENT ON COLUMN packages.stock IS '剩余库存，下单时扣减，待支付订单失败或过期时退回；NULL 表示不限量';
+COMMENT ON COLUMN packages.max_per_user IS '每个用户最多购买次数（待处理、待支付和已完成的订单），0 表示不限制';
+COMMENT ON COLUMN packages.exclusive IS '独占套餐：不能与其他生效中的套餐同时持有';
+
+-- ========================================
+-- MIGRATION 032: User Import
+-- ========================================
+
+-- Imported users keep their id in the source panel, so referrals can be resolved across
+-- batches and a row is not imported twice
+ALTER TABLE users ADD COLUMN IF NOT EXISTS import_ref VARCHAR(100);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_tenant_import_ref ON users(tenant_id, import_ref) WHERE import_ref IS NOT NULL;
+
+-- Imported packages have no order behind them
+ALTER TABLE user_packages ALTER COLUMN order_id DROP NOT NULL;
+
+COMMENT ON COLUMN users.import_ref IS '导入来源及原面板中的用户 ID，如 v2board:42；非导入用户为 NULL';
+COMMENT ON COLUMN user_packages.order_id IS '对应订单；从其他面板导入的套餐为 NULL';
+
+-- ========================================
+-- MIGRATION 033: Node Probe Latency
+-- ========================================
+
+-- Result of the last admin connectivity test, used when subscriptions order proxies by latency
+ALTER TABLE nodes ADD COLUMN IF NOT EXISTS probe_latency_ms INTEGER;
+ALTER TABLE nodes ADD COLUMN IF NOT EXISTS probed_at TIMESTAMPTZ;
+
+COMMENT ON COLUMN nodes.probe_latency_ms IS '最近一次连通性测试的 TCP 连接耗时（毫秒），测试失败为 NULL';
+COMMENT ON COLUMN nodes.probed_at IS '最近一次连通性测试时间';
+
+-- ========================================
+-- MIGRATION 034: Traffic Totals Follow User Packages
+-- ========================================
+
+-- users.traffic_quota / traffic_used are the totals over the user's packages, written
+-- together with user_packages; existing data is reconciled with `reconcile_traffic`
+COMMENT ON COLUMN users.traffic_quota IS '流量总额（该用户全部套餐含已过期套餐的流量之和，随 user_packages 同步更新）';
+COMMENT ON COLUMN users.traffic_used IS '已用流量总额（该用户全部套餐已用流量之和，随 user_packages 同步更新）';
+
+-- ========================================
+-- MIGRATION 035: Traffic Report Quarantine
+-- ========================================
+
+-- Node traffic reports held back from quotas until an admin approves or discards them
+CREATE TABLE IF NOT EXISTS quarantined_traffic_reports (
+    id BIGSERIAL PRIMARY KEY,
+    node_id BIGINT NOT NULL REFERENCES nodes(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    upload BIGINT NOT NULL,
+    download BIGINT NOT NULL,
+    reason VARCHAR(32) NOT NULL CHECK (reason IN ('exceeds_limit', 'negative', 'node_maintenance')),
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'discarded')),
+    reported_at TIMESTAMPTZ NOT NULL,
+    reviewed_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    reviewed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_quarantined_traffic_reports_status ON quarantined_traffic_reports(status, created_at);
+
+COMMENT ON TABLE quarantined_traffic_reports IS '隔离的节点流量上报，管理员批准后才计入用户流量';
+COMMENT ON COLUMN quarantined_traffic_reports.reason IS '隔离原因：exceeds_limit-单次上报超过 TRAFFIC_MAX_REPORT_BYTES, negative-流量为负, node_maintenance-节点维护中';
+COMMENT ON COLUMN quarantined_traffic_reports.status IS '审核状态：pending-待审核, approved-已批准并计入, discarded-已丢弃';
+COMMENT ON COLUMN quarantined_traffic_reports.reported_at IS '节点上报时间';
+
+-- ========================================
+-- MIGRATION 036: Subscription Short Links
+-- ========================================
+
+-- Short codes served at /s/:code; they follow the subscription, so token rotation keeps them working
+CREATE TABLE IF NOT EXISTS subscription_short_links (
+    id BIGSERIAL PRIMARY KEY,
+    subscription_id BIGINT NOT NULL UNIQUE REFERENCES subscriptions(id) ON DELETE CASCADE,
+    code VARCHAR(16) NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_accessed TIMESTAMPTZ
+);
+
+COMMENT ON TABLE subscription_short_links IS '订阅短链接，每个订阅最多一个，重新生成时替换旧短码';
+COMMENT ON COLUMN subscription_short_links.code IS '短码，通过 /s/:code 访问订阅';
+COMMENT ON COLUMN subscription_short_links.expires_at IS '过期时间，NULL 表示永不过期';
+COMMENT ON COLUMN subscription_short_links.last_accessed IS '最近一次通过短链接拉取订阅的时间';
+
+-- ========================================
+-- MIGRATION 037: Clash Config Snapshots
+-- ========================================
+
+-- Copies of the proxy group and rule tables taken on generation, restorable by version
+CREATE TABLE IF NOT EXISTS clash_config_snapshots (
+    id BIGSERIAL PRIMARY KEY,
+    version INT NOT NULL UNIQUE,
+    author_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    proxy_groups JSONB NOT NULL,
+    rules JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+COMMENT ON TABLE clash_config_snapshots IS 'Clash 配置快照，生成配置时保存代理组和规则表，可按版本回滚';
+COMMENT ON COLUMN clash_config_snapshots.version IS '快照版本号，从 1 递增';
+COMMENT ON COLUMN clash_config_snapshots.author_id IS '生成配置或执行回滚的管理员';
+COMMENT ON COLUMN clash_config_snapshots.proxy_groups IS '快照时的全部代理组（含未启用）';
+COMMENT ON COLUMN clash_config_snapshots.rules IS '快照时的全部规则（含未启用）';
+
+-- ========================================
+-- MIGRATION 038: Subscription GeoIP Restrictions
+-- ========================================
+
+-- Per-user overrides of the global GEOIP restrictions (NULL columns use the global setting)
+CREATE TABLE IF NOT EXISTS subscription_geo_restrictions (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    allowed_countries TEXT[],
+    blocked_countries TEXT[],
+    action VARCHAR(16) CHECK (action IN ('block', 'challenge')),
+    updated_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+COMMENT ON TABLE subscription_geo_restrictions IS '用户订阅地区限制（覆盖全局配置，NULL 表示沿用全局配置）';
+COMMENT ON COLUMN subscription_geo_restrictions.allowed_countries IS '只允许这些国家/地区拉取订阅，空数组表示不限制';
+COMMENT ON COLUMN subscription_geo_restrictions.blocked_countries IS '禁止这些国家/地区拉取订阅，空数组表示不限制';
+COMMENT ON COLUMN subscription_geo_restrictions.action IS '受限请求的处理方式：block-返回 403, challenge-返回空配置';
+
+-- Restriction decisions are logged with the client country
+ALTER TABLE clash_access_logs ADD COLUMN IF NOT EXISTS country VARCHAR(2);
+ALTER TABLE clash_access_logs DROP CONSTRAINT IF EXISTS clash_access_logs_response_status_check;
+ALTER TABLE clash_access_logs ADD CONSTRAINT clash_access_logs_response_status_check
+    CHECK (response_status IN ('success', 'not_modified', 'failed', 'quota_exceeded', 'expired', 'disabled', 'geo_blocked', 'geo_challenged'));
+
+COMMENT ON COLUMN clash_access_logs.response_status IS '响应状态：success-成功, not_modified-配置未变化（304）, failed-失败, quota_exceeded-流量超限, expired-已过期, disabled-已禁用, geo_blocked-地区限制拒绝, geo_challenged-地区限制返回空配置';
+COMMENT ON COLUMN clash_access_logs.country IS '客户端所在国家/地区代码（地区限制生效时记录）';
+
+-- ========================================
+-- MIGRATION 039: Top-up Requests
+-- ========================================
+
+-- Coins paid for outside the platform, credited once an admin approves the request
+CREATE TABLE IF NOT EXISTS topup_requests (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    amount BIGINT NOT NULL CHECK (amount > 0),
+    reference VARCHAR(200),
+    screenshot_url VARCHAR(500),
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'rejected')),
+    admin_note VARCHAR(500),
+    reviewed_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    reviewed_at TIMESTAMPTZ,
+    coin_transaction_id BIGINT REFERENCES coin_transactions(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_topup_requests_user ON topup_requests(user_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_topup_requests_status ON topup_requests(status, created_at);
+
+COMMENT ON TABLE topup_requests IS '用户充值申请，管理员人工核对付款后批准入账';
+COMMENT ON COLUMN topup_requests.reference IS '用户填写的付款凭证（转账单号、付款人等）';
+COMMENT ON COLUMN topup_requests.screenshot_url IS '付款截图链接';
+COMMENT ON COLUMN topup_requests.status IS '审核状态：pending-待审核, approved-已批准并入账, rejected-已拒绝';
+COMMENT ON COLUMN topup_requests.admin_note IS '审核备注，用户可见';
+COMMENT ON COLUMN topup_requests.coin_transaction_id IS '批准时写入的充值流水';
+
+-- ========================================
+-- MIGRATION 040: Node Failover
+-- ========================================
+
+-- Backup nodes are grouped with their primary in a Clash fallback group
+ALTER TABLE nodes ADD COLUMN IF NOT EXISTS backup_of BIGINT REFERENCES nodes(id) ON DELETE SET NULL;
+ALTER TABLE nodes DROP CONSTRAINT IF EXISTS nodes_backup_of_check;
+ALTER TABLE nodes ADD CONSTRAINT nodes_backup_of_check CHECK (backup_of <> id);
+
+CREATE INDEX IF NOT EXISTS idx_nodes_backup_of ON nodes(backup_of) WHERE backup_of IS NOT NULL;
+
+COMMENT ON COLUMN nodes.backup_of IS '作为哪个主节点的备用节点，生成配置时与主节点组成 fallback 代理组；NULL 表示普通节点';
+
+-- ========================================
+-- MIGRATION 041: Payment Sandbox
+-- ========================================
+
+-- Sandbox purchases don't touch coin balances and are excluded from revenue stats
+ALTER TABLE users ADD COLUMN IF NOT EXISTS is_sandbox BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE orders ADD COLUMN IF NOT EXISTS sandbox BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_orders_sandbox ON orders(created_at) WHERE sandbox;
+
+COMMENT ON COLUMN users.is_sandbox IS '测试用户：购买不扣减真实金币，订单标记为沙盒订单';
+COMMENT ON COLUMN orders.sandbox IS '沙盒订单：未扣减真实金币，可由管理员模拟支付回调，不计入收入统计';
+
+-- ========================================
+-- MIGRATION 042: Node Agent Versions
+-- ========================================
+
+-- Reported in heartbeats so admins can see which nodes run an outdated agent
+ALTER TABLE nodes ADD COLUMN IF NOT EXISTS agent_version VARCHAR(32);
+
+COMMENT ON COLUMN nodes.agent_version IS '节点代理程序版本，由心跳上报；NULL 表示旧版代理未上报';
+
+-- ========================================
+-- MIGRATION 043: Referral Rebate Claims
+-- ========================================
+
+-- The first purchase claims the referral rebate by setting this under the buyer's row
+-- lock, so concurrent purchases can't both pay it
+ALTER TABLE users ADD COLUMN IF NOT EXISTS referral_rebate_paid_at TIMESTAMPTZ;
+
+-- Referred users who already completed a purchase have had their first purchase
+UPDATE users u SET referral_rebate_paid_at = NOW()
+WHERE u.referred_by IS NOT NULL
+  AND u.referral_rebate_paid_at IS NULL
+  AND EXISTS (
+      SELECT 1 FROM orders o
+      WHERE o.user_id = u.id AND o.status = 'completed' AND NOT o.sandbox
+  );
+
+COMMENT ON COLUMN users.referral_rebate_paid_at IS '首次购买邀请返利的结算时间；NULL 表示尚未结算，返利只发放一次';
+
+-- ========================================
+-- MIGRATION 044: Admin User Notes and Tags
+-- ========================================
+
+CREATE TABLE IF NOT EXISTS user_notes (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    author_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_notes_user ON user_notes(user_id, created_at DESC);
+
+CREATE TABLE IF NOT EXISTS user_tags (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    tag VARCHAR(32) NOT NULL,
+    author_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Tags are matched case-insensitively, so "VIP" and "vip" are the same tag
+CREATE UNIQUE INDEX IF NOT EXISTS idx_user_tags_user_tag ON user_tags(user_id, LOWER(tag));
+CREATE INDEX IF NOT EXISTS idx_user_tags_tag ON user_tags(LOWER(tag));
+
+COMMENT ON TABLE user_notes IS '管理员对用户账户的备注，仅管理员可见';
+COMMENT ON COLUMN user_notes.author_id IS '填写备注的管理员';
+COMMENT ON TABLE user_tags IS '管理员为用户打的标签（如 VIP、拒付风险），可在用户列表中筛选';
+COMMENT ON COLUMN user_tags.tag IS '标签名，同一用户的标签不区分大小写去重';
+COMMENT ON COLUMN user_tags.author_id IS '添加标签的管理员';
+
+-- ========================================
+-- MIGRATION 045: Node Secret Read Scope
+-- ========================================
+
+-- API keys for provisioning tools may read node secrets to set up agents
+ALTER TABLE api_keys DROP CONSTRAINT IF EXISTS api_keys_scopes_check;
+ALTER TABLE api_keys ADD CONSTRAINT api_keys_scopes_check
+    CHECK (scopes <@ ARRAY['stats:read', 'traffic:write', 'users:provision', 'node-secrets:read']::TEXT[]);
+
+COMMENT ON COLUMN api_keys.scopes IS '授权范围：stats:read 读取统计，traffic:write 上报流量，users:provision 创建用户，node-secrets:read 读取节点密钥';
+
+-- ========================================
+-- MIGRATION 046: Balance Adjustment Approvals
+-- ========================================
+
+-- Manual balance changes above BALANCE_APPROVAL_THRESHOLD wait for a second admin
+CREATE TABLE IF NOT EXISTS balance_adjustments (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    amount BIGINT NOT NULL CHECK (amount <> 0),
+    reason VARCHAR(200) NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'rejected')),
+    requested_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    reviewed_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    reviewed_at TIMESTAMPTZ,
+    review_note VARCHAR(500),
+    coin_transaction_id BIGINT REFERENCES coin_transactions(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (reviewed_by IS NULL OR status <> 'approved' OR reviewed_by IS DISTINCT FROM requested_by)
+);
+
+CREATE INDEX IF NOT EXISTS idx_balance_adjustments_status ON balance_adjustments(status, created_at);
+CREATE INDEX IF NOT EXISTS idx_balance_adjustments_user ON balance_adjustments(user_id, created_at DESC);
+
+COMMENT ON TABLE balance_adjustments IS '大额手动余额调整，须由另一名管理员批准后入账';
+COMMENT ON COLUMN balance_adjustments.amount IS '调整金币数，正数增加、负数扣减';
+COMMENT ON COLUMN balance_adjustments.status IS '审核状态：pending-待审批, approved-已批准并入账, rejected-已拒绝';
+COMMENT ON COLUMN balance_adjustments.requested_by IS '发起调整的管理员';
+COMMENT ON COLUMN balance_adjustments.reviewed_by IS '审批的管理员，批准时不能是发起人';
+COMMENT ON COLUMN balance_adjustments.coin_transaction_id IS '批准时写入的金币流水';
+
+-- ========================================
+-- MIGRATION 047: Terms of Service Acceptances
+-- ========================================
+
+-- Every terms version a user accepted, kept for compliance
+CREATE TABLE IF NOT EXISTS terms_acceptances (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    version VARCHAR(50) NOT NULL,
+    ip_address VARCHAR(45),
+    user_agent VARCHAR(512),
+    accepted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, version)
+);
+
+CREATE INDEX IF NOT EXISTS idx_terms_acceptances_user ON terms_acceptances(user_id, accepted_at DESC);
+
+COMMENT ON TABLE terms_acceptances IS '用户接受服务条款的记录，每个版本保留首次接受';
+COMMENT ON COLUMN terms_acceptances.version IS '接受的条款版本（TERMS_VERSION）';
+COMMENT ON COLUMN terms_acceptances.ip_address IS '接受时的客户端 IP';
+COMMENT ON COLUMN terms_acceptances.user_agent IS '接受时的 User-Agent，最多 512 个字符';
+
+-- ========================================
+-- MIGRATION 048: Node Diagnostics
+-- ========================================
+
+-- Results of `node-agent --diagnose` runs, shown on the admin node page
+CREATE TABLE IF NOT EXISTS node_diagnostics (
+    id BIGSERIAL PRIMARY KEY,
+    node_id BIGINT NOT NULL REFERENCES nodes(id) ON DELETE CASCADE,
+    agent_version VARCHAR(50),
+    passed BOOLEAN NOT NULL,
+    checks JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_node_diagnostics_node_created ON node_diagnostics(node_id, created_at DESC);
+
+COMMENT ON TABLE node_diagnostics IS 'Node Agent 诊断模式上报的检查结果，每个节点保留最近若干次';
+COMMENT ON COLUMN node_diagnostics.passed IS '是否没有失败的检查项';
+COMMENT ON COLUMN node_diagnostics.checks IS '检查项列表，每项包含 name、status（pass/warn/fail）和 detail';
+
+-- ========================================
+-- MIGRATION 049: Stale Subscription Access Logs
+-- ========================================
+
+-- Fetches served from the outage backup while the database was unreachable
+ALTER TABLE clash_access_logs DROP CONSTRAINT IF EXISTS clash_access_logs_response_status_check;
+ALTER TABLE clash_access_logs ADD CONSTRAINT clash_access_logs_response_status_check
+    CHECK (response_status IN ('success', 'not_modified', 'stale', 'failed', 'quota_exceeded', 'expired', 'disabled', 'geo_blocked', 'geo_challenged'));
+
+COMMENT ON COLUMN clash_access_logs.response_status IS '响应状态：success-成功, not_modified-配置未变化（304）, stale-数据库不可用时返回的备份配置, failed-失败, quota_exceeded-流量超限, expired-已过期, disabled-已禁用, geo_blocked-地区限制拒绝, geo_challenged-地区限制返回空配置';
+
+-- ========================================
+-- MIGRATION 050: Heavy Traffic Security Events
+-- ========================================
+
+-- Users whose traffic within the window reached SECURITY_HEAVY_TRAFFIC_THRESHOLD_BYTES
+ALTER TABLE security_events DROP CONSTRAINT IF EXISTS security_events_event_type_check;
+ALTER TABLE security_events ADD CONSTRAINT security_events_event_type_check
+    CHECK (event_type IN ('failed_logins', 'balance_adjustment', 'node_deleted', 'heavy_traffic'));
+
+CREATE INDEX IF NOT EXISTS idx_security_events_target ON security_events(event_type, target_type, target_id, created_at DESC);
+
+COMMENT ON TABLE security_events IS '安全事件表（连续登录失败、大额余额调整、删除节点、异常流量等）';
+
+-- ========================================
+-- MIGRATION 051: Node Config Versions
+-- ========================================
+
+-- Bumped on every change to what the agent is configured with; agents echo the
+-- version they applied in heartbeats
+ALTER TABLE nodes ADD COLUMN IF NOT EXISTS config_version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE nodes ADD COLUMN IF NOT EXISTS applied_config_version BIGINT;
+
+CREATE OR REPLACE FUNCTION bump_node_config_version()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF (NEW.name, NEW.host, NEW.port, NEW.protocol, NEW.secret, NEW.config, NEW.max_users, NEW.agent_settings, NEW.approved)
+        IS DISTINCT FROM
+       (OLD.name, OLD.host, OLD.port, OLD.protocol, OLD.secret, OLD.config, OLD.max_users, OLD.agent_settings, OLD.approved)
+    THEN
+        NEW.config_version = OLD.config_version + 1;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS bump_nodes_config_version ON nodes;
+CREATE TRIGGER bump_nodes_config_version BEFORE UPDATE ON nodes
+    FOR EACH ROW EXECUTE FUNCTION bump_node_config_version();
+
+COMMENT ON COLUMN nodes.config_version IS '节点配置版本，名称、地址、端口、协议、密钥、配置、用户上限、运行时配置或审核状态变化时自动加 1';
+COMMENT ON COLUMN nodes.applied_config_version IS '节点代理在最近一次心跳中上报的已应用配置版本；NULL 表示旧版代理未上报';
+
+-- ========================================
+-- MIGRATION 052: Subscription Access Keys
+-- ========================================
+
+-- Optional second factor for subscription fetches: `/sub/:token?key=...`
+ALTER TABLE subscriptions ADD COLUMN IF NOT EXISTS access_key_hash TEXT;
+
+ALTER TABLE clash_access_logs DROP CONSTRAINT IF EXISTS clash_access_logs_response_status_check;
+ALTER TABLE clash_access_logs ADD CONSTRAINT clash_access_logs_response_status_check
+    CHECK (response_status IN ('success', 'not_modified', 'stale', 'failed', 'quota_exceeded', 'expired', 'disabled', 'geo_blocked', 'geo_challenged', 'key_missing', 'key_invalid'));
+
+COMMENT ON COLUMN subscriptions.access_key_hash IS '订阅访问密钥的 SHA-256；设置后获取订阅必须提供 key 参数或 Authorization 头，NULL 表示未启用';
+COMMENT ON COLUMN clash_access_logs.response_status IS '响应状态：success-成功, not_modified-配置未变化（304）, stale-数据库不可用时返回的备份配置, failed-失败, quota_exceeded-流量超限, expired-已过期, disabled-已禁用, geo_blocked-地区限制拒绝, geo_challenged-地区限制返回空配置, key_missing-未提供订阅访问密钥, key_invalid-订阅访问密钥错误';
+
+-- ========================================
+-- MIGRATION 053: Scheduled User Packages
+-- ========================================
+
+-- Packages bought to start later (e.g. when the current one expires) wait as
+-- 'scheduled' until `activates_at`; quota checks and subscriptions only see 'active'
+ALTER TABLE user_packages ADD COLUMN IF NOT EXISTS activates_at TIMESTAMPTZ;
+ALTER TABLE user_packages DROP CONSTRAINT IF EXISTS user_packages_status_check;
+ALTER TABLE user_packages ADD CONSTRAINT user_packages_status_check
+    CHECK (status IN ('scheduled', 'active', 'expired', 'exhausted'));
+CREATE INDEX IF NOT EXISTS idx_user_packages_scheduled ON user_packages(activates_at) WHERE status = 'scheduled';
+
+-- Start requested with a mixed-payment order, applied when the payment completes
+ALTER TABLE orders ADD COLUMN IF NOT EXISTS package_activates_at TIMESTAMPTZ;
+
+COMMENT ON COLUMN user_packages.activates_at IS '套餐生效时间；NULL 表示购买后立即生效，scheduled 状态的套餐到此时间后自动变为 active';
+COMMENT ON COLUMN user_packages.status IS '套餐状态：scheduled-待生效, active-生效中, expired-已过期, exhausted-流量已用完';
+COMMENT ON COLUMN orders.package_activates_at IS '订单所购套餐的生效时间（预约购买）；NULL 表示支付完成后立即生效';
+
+-- ========================================
+-- MIGRATION 054: Order Purchase Transactions
+-- ========================================
+
+-- Purchase transactions name the order they pay for, so completed orders can be
+-- reconciled against the coins actually charged
+ALTER TABLE coin_transactions ADD COLUMN IF NOT EXISTS order_id BIGINT REFERENCES orders(id) ON DELETE SET NULL;
+CREATE INDEX IF NOT EXISTS idx_coin_transactions_order ON coin_transactions(order_id) WHERE order_id IS NOT NULL;
+
+-- Mixed-payment captures put the order number in the description
+UPDATE coin_transactions ct
+SET order_id = o.id
+FROM orders o
+WHERE ct.order_id IS NULL AND ct.type = 'purchase'
+  AND ct.user_id = o.user_id
+  AND ct.description LIKE '%(order ' || o.order_no || ')';
+
+-- Coin purchases wrote the order and its transaction in one transaction (same NOW())
+UPDATE coin_transactions ct
+SET order_id = o.id
+FROM orders o
+WHERE ct.order_id IS NULL AND ct.type = 'purchase'
+  AND ct.user_id = o.user_id
+  AND ct.created_at = o.created_at
+  AND ct.amount = -o.amount
+  AND o.coin_amount = 0 AND o.external_amount = 0;
+
+COMMENT ON COLUMN coin_transactions.order_id IS '购买交易对应的订单；用于订单与金币流水对账，其他类型交易为 NULL';
+
+-- ========================================
+-- MIGRATION 055: Subscription Flood Security Events
+-- ========================================
+
+-- IPs that went over SUBSCRIPTION_IP_MAX_CONCURRENT or SUBSCRIPTION_IP_MAX_REQUESTS on /sub/:token
+ALTER TABLE security_events DROP CONSTRAINT IF EXISTS security_events_event_type_check;
+ALTER TABLE security_events ADD CONSTRAINT security_events_event_type_check
+    CHECK (event_type IN ('failed_logins', 'balance_adjustment', 'node_deleted', 'heavy_traffic', 'subscription_flood'));
+
+CREATE INDEX IF NOT EXISTS idx_security_events_ip ON security_events(event_type, ip_address, created_at DESC);
+
+COMMENT ON TABLE security_events IS '安全事件表（连续登录失败、大额余额调整、删除节点、异常流量、订阅请求过频等）';
+
+-- ========================================
+-- MIGRATION 056: Referral Commission Referees
+-- ========================================
+
+-- Referral rebates name the referred user who earned them, so referrers can see
+-- their commissions per referee
+ALTER TABLE coin_transactions ADD COLUMN IF NOT EXISTS referee_id BIGINT REFERENCES users(id) ON DELETE SET NULL;
+CREATE INDEX IF NOT EXISTS idx_coin_transactions_referee ON coin_transactions(user_id, referee_id) WHERE referee_id IS NOT NULL;
+
+-- Rebates put the referee's ID in the description
+UPDATE coin_transactions ct
+SET referee_id = u.id
+FROM users u
+WHERE ct.referee_id IS NULL AND ct.type = 'referral'
+  AND ct.description = 'Referral rebate from user ' || u.id;
+
+COMMENT ON COLUMN coin_transactions.referee_id IS '推荐返利交易对应的被推荐用户；其他类型交易为 NULL';
+
+-- ========================================
+-- MIGRATION 057: Node Maintenance Windows
+-- ========================================
+
+-- Scheduled maintenance: the node is put in maintenance (and left out of
+-- subscription configs) for the window, then restored
+CREATE TABLE IF NOT EXISTS node_maintenance (
+    id BIGSERIAL PRIMARY KEY,
+    node_id BIGINT NOT NULL REFERENCES nodes(id) ON DELETE CASCADE,
+    starts_at TIMESTAMPTZ NOT NULL,
+    ends_at TIMESTAMPTZ NOT NULL,
+    reason VARCHAR(500),
+    status VARCHAR(20) NOT NULL DEFAULT 'scheduled' CHECK (status IN ('scheduled', 'active', 'completed', 'cancelled')),
+    restore_status VARCHAR(20),
+    created_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (ends_at > starts_at)
+);
+
+CREATE INDEX IF NOT EXISTS idx_node_maintenance_node ON node_maintenance(node_id, starts_at DESC);
+CREATE INDEX IF NOT EXISTS idx_node_maintenance_open ON node_maintenance(status, starts_at) WHERE status IN ('scheduled', 'active');
+
+COMMENT ON TABLE node_maintenance IS '节点维护窗口，窗口内节点自动置为维护状态并从订阅配置中移除，结束后恢复';
+COMMENT ON COLUMN node_maintenance.reason IS '维护原因，会通知主要使用该节点的用户';
+COMMENT ON COLUMN node_maintenance.status IS '窗口状态：scheduled-待开始, active-维护中, completed-已结束, cancelled-已取消';
+COMMENT ON COLUMN node_maintenance.restore_status IS '维护开始前的节点状态，结束时恢复；节点原本已是维护或停用状态时为 NULL';
+
+-- ========================================
+-- MIGRATION 058: Payments After Order Expiry
+-- ========================================
+
+-- A payment that arrives after its order expired completes the order when the balance
+-- and stock still allow it, otherwise the order is kept as paid_after_expiry for a refund
+ALTER TABLE orders DROP CONSTRAINT IF EXISTS orders_status_check;
+ALTER TABLE orders ADD CONSTRAINT orders_status_check
+    CHECK (status IN ('pending', 'awaiting_payment', 'completed', 'failed', 'expired', 'cancelled', 'refunded', 'paid_after_expiry'));
+ALTER TABLE order_events DROP CONSTRAINT IF EXISTS order_events_from_status_check;
+ALTER TABLE order_events ADD CONSTRAINT order_events_from_status_check
+    CHECK (from_status IN ('pending', 'awaiting_payment', 'completed', 'failed', 'expired', 'cancelled', 'refunded', 'paid_after_expiry'));
+ALTER TABLE order_events DROP CONSTRAINT IF EXISTS order_events_to_status_check;
+ALTER TABLE order_events ADD CONSTRAINT order_events_to_status_check
+    CHECK (to_status IN ('pending', 'awaiting_payment', 'completed', 'failed', 'expired', 'cancelled', 'refunded', 'paid_after_expiry'));
+
+CREATE INDEX IF NOT EXISTS idx_orders_paid_after_expiry ON orders(created_at) WHERE status = 'paid_after_expiry';
+
+COMMENT ON COLUMN orders.status IS '订单状态：pending 待处理，awaiting_payment 待支付，completed 已完成，failed 支付失败，expired 已过期，cancelled 已取消，refunded 已退款，paid_after_expiry 过期后才支付且无法完成（需管理员退款）';
+
+-- ========================================
+-- MIGRATION 059: Order Stock Reservations
+-- ========================================
+
+-- Orders only give stock back if they took it; orders awaiting payment before this
+-- migration took a unit whenever their package's stock was limited
+ALTER TABLE orders ADD COLUMN IF NOT EXISTS took_stock BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE orders o SET took_stock = TRUE
+FROM packages p
+WHERE p.id = o.package_id AND o.status = 'awaiting_payment' AND p.stock IS NOT NULL AND NOT o.took_stock;
+
+COMMENT ON COLUMN orders.took_stock IS '下单时是否占用了套餐库存，订单失败或过期时据此退回库存';
+
 -- ========================================
 -- END OF MIGRATIONS
 -- ========================================
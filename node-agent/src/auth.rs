@@ -0,0 +1,51 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static NONCE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Timestamp and single-use nonce sent with every authenticated API request
+///
+/// The API rejects requests whose timestamp is outside its clock skew window and
+/// nonces it has already seen, so the node clock must be kept in sync (NTP).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestStamp {
+    pub timestamp: i64,
+    pub nonce: String,
+}
+
+impl RequestStamp {
+    pub fn now() -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let count = NONCE_COUNTER.fetch_add(1, Ordering::Relaxed);
+
+        RequestStamp {
+            timestamp: now.as_secs() as i64,
+            nonce: format!("{:016x}-{:x}-{:x}", now.as_nanos() as u64, std::process::id(), count),
+        }
+    }
+
+    /// Query string parameters for GET requests
+    pub fn query(&self) -> String {
+        format!("timestamp={}&nonce={}", self.timestamp, self.nonce)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_stamp_nonces_are_unique() {
+        let first = RequestStamp::now();
+        let second = RequestStamp::now();
+
+        assert_ne!(first.nonce, second.nonce);
+        assert!(first.nonce.len() >= 16 && first.nonce.len() <= 64);
+        assert!(first
+            .nonce
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-'));
+        assert!(first.timestamp > 0);
+        assert!(first.query().starts_with(&format!("timestamp={}&nonce=", first.timestamp)));
+    }
+}
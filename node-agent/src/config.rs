@@ -1,25 +1,88 @@
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 use std::env;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::time::{interval, interval_at, Duration, Instant, Interval};
+use tracing::info;
 
+/// Where enrolled credentials are saved when `NODE_CREDENTIALS_FILE` is unset
+pub const DEFAULT_CREDENTIALS_FILE: &str = "/var/lib/node-agent/credentials.json";
+
+/// Where the last good config and user list are kept when `NODE_CACHE_DIR` is unset
+pub const DEFAULT_CACHE_DIR: &str = "/var/lib/node-agent/cache";
+
+/// Shortest interval the API may configure, so a bad value cannot busy-loop the agent
+pub const MIN_INTERVAL_SECS: u64 = 5;
+
+/// Node secret shared by every component, so a rotated secret takes effect everywhere
+#[derive(Debug, Clone, Default)]
+pub struct NodeSecret(Arc<std::sync::RwLock<String>>);
+
+impl NodeSecret {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self(Arc::new(std::sync::RwLock::new(secret.into())))
+    }
+
+    /// Current secret
+    pub fn get(&self) -> String {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Replace the secret for all holders
+    pub fn set(&self, secret: impl Into<String>) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = secret.into();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).is_empty()
+    }
+}
+
+/// Bootstrap configuration from the environment
+///
+/// Only what is needed to reach the API lives here for good; the interval values are
+/// just the initial [`RuntimeSettings`] until the first config fetch replaces them.
 #[derive(Debug, Clone)]
 pub struct Config {
     pub api_url: String,
     pub node_id: String,
-    pub node_secret: String,
+    pub node_secret: NodeSecret,
     pub xray_api_port: u16,
     pub traffic_report_interval: u64,
     pub heartbeat_interval: u64,
+    /// Single-use token to self-register when no credentials exist yet
+    pub enrollment_token: Option<String>,
+    /// Where credentials issued at enrollment are saved and loaded from
+    pub credentials_file: String,
+    /// Where the last good config and user list are kept for starting while the API is down
+    pub cache_dir: String,
+    /// How often to check the API for a newer agent release (seconds, 0 disables self-update)
+    pub update_check_interval: u64,
 }
 
 impl Config {
+    /// Credentials come from `NODE_ID`/`NODE_SECRET`, else from a previous enrollment's
+    /// credentials file; with neither, `ENROLLMENT_TOKEN` must be set so the agent can enroll.
     pub fn from_env() -> Result<Self> {
+        let enrollment_token = env::var("ENROLLMENT_TOKEN").ok().filter(|s| !s.is_empty());
+        let credentials_file = env::var("NODE_CREDENTIALS_FILE")
+            .unwrap_or_else(|_| DEFAULT_CREDENTIALS_FILE.to_string());
+
+        let (node_id, node_secret) = match (env::var("NODE_ID"), env::var("NODE_SECRET")) {
+            (Ok(id), Ok(secret)) => (id, secret),
+            _ => match crate::enroll::Credentials::load(std::path::Path::new(&credentials_file))? {
+                Some(saved) => (saved.node_id, saved.node_secret),
+                None if enrollment_token.is_some() => (String::new(), String::new()),
+                None => anyhow::bail!("NODE_ID and NODE_SECRET must be set, or ENROLLMENT_TOKEN to enroll"),
+            },
+        };
+
         Ok(Config {
             api_url: env::var("API_URL")
                 .context("API_URL must be set")?,
-            node_id: env::var("NODE_ID")
-                .context("NODE_ID must be set")?,
-            node_secret: env::var("NODE_SECRET")
-                .context("NODE_SECRET must be set")?,
+            node_id,
+            node_secret: NodeSecret::new(node_secret),
             xray_api_port: env::var("XRAY_API_PORT")
                 .unwrap_or_else(|_| "10085".to_string())
                 .parse()
@@ -32,8 +95,175 @@ impl Config {
                 .unwrap_or_else(|_| "60".to_string())
                 .parse()
                 .context("HEARTBEAT_INTERVAL must be a valid number")?,
+            enrollment_token,
+            credentials_file,
+            cache_dir: env::var("NODE_CACHE_DIR")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| DEFAULT_CACHE_DIR.to_string()),
+            update_check_interval: env::var("AGENT_UPDATE_CHECK_INTERVAL")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse()
+                .context("AGENT_UPDATE_CHECK_INTERVAL must be a valid number")?,
         })
     }
+
+    /// Whether the agent still has to exchange its enrollment token for credentials
+    pub fn needs_enrollment(&self) -> bool {
+        self.node_id.is_empty() || self.node_secret.is_empty()
+    }
+
+    /// Runtime settings to use until the API provides its own
+    pub fn initial_settings(&self) -> RuntimeSettings {
+        RuntimeSettings {
+            traffic_report_interval: self.traffic_report_interval,
+            heartbeat_interval: self.heartbeat_interval,
+            ..RuntimeSettings::default()
+        }
+        .sanitized()
+    }
+}
+
+/// Agent settings managed by the API (`agent` object of `/api/v1/node/config`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuntimeSettings {
+    pub traffic_report_interval: u64,
+    pub heartbeat_interval: u64,
+    pub user_sync_interval: u64,
+    /// `host:port` targets the health checker probes on every heartbeat
+    pub probe_targets: Vec<String>,
+}
+
+impl Default for RuntimeSettings {
+    fn default() -> Self {
+        Self {
+            traffic_report_interval: 30,
+            heartbeat_interval: 60,
+            user_sync_interval: 60,
+            probe_targets: Vec::new(),
+        }
+    }
+}
+
+impl RuntimeSettings {
+    /// Clamp intervals to `MIN_INTERVAL_SECS` and drop blank probe targets
+    pub fn sanitized(mut self) -> Self {
+        self.traffic_report_interval = self.traffic_report_interval.max(MIN_INTERVAL_SECS);
+        self.heartbeat_interval = self.heartbeat_interval.max(MIN_INTERVAL_SECS);
+        self.user_sync_interval = self.user_sync_interval.max(MIN_INTERVAL_SECS);
+        self.probe_targets.retain(|target| !target.trim().is_empty());
+        self
+    }
+
+    /// Names of the settings that differ from `other`
+    pub fn changed_fields(&self, other: &Self) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.traffic_report_interval != other.traffic_report_interval {
+            changed.push("traffic_report_interval");
+        }
+        if self.heartbeat_interval != other.heartbeat_interval {
+            changed.push("heartbeat_interval");
+        }
+        if self.user_sync_interval != other.user_sync_interval {
+            changed.push("user_sync_interval");
+        }
+        if self.probe_targets != other.probe_targets {
+            changed.push("probe_targets");
+        }
+        changed
+    }
+}
+
+/// Shared handle to the current runtime settings
+///
+/// Cloned into every component; `apply` publishes new settings to all of them.
+#[derive(Debug, Clone)]
+pub struct SettingsHandle {
+    tx: Arc<watch::Sender<RuntimeSettings>>,
+}
+
+impl SettingsHandle {
+    pub fn new(initial: RuntimeSettings) -> Self {
+        let (tx, _) = watch::channel(initial);
+        Self { tx: Arc::new(tx) }
+    }
+
+    /// Snapshot of the current settings
+    pub fn current(&self) -> RuntimeSettings {
+        self.tx.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<RuntimeSettings> {
+        self.tx.subscribe()
+    }
+
+    /// Apply settings received from the API, returning the names of changed fields
+    ///
+    /// Subscribers are only woken when something actually changed.
+    pub fn apply(&self, settings: RuntimeSettings) -> Vec<&'static str> {
+        let settings = settings.sanitized();
+        let mut changed = Vec::new();
+        self.tx.send_if_modified(|current| {
+            changed = current.changed_fields(&settings);
+            if changed.is_empty() {
+                return false;
+            }
+            *current = settings;
+            true
+        });
+        changed
+    }
+
+    /// Interval whose period follows one of the settings
+    pub fn interval(&self, name: &'static str, period: fn(&RuntimeSettings) -> u64) -> ReloadableInterval {
+        let mut rx = self.subscribe();
+        let secs = period(&rx.borrow_and_update());
+        ReloadableInterval {
+            _handle: self.clone(),
+            rx,
+            name,
+            period,
+            secs,
+            ticker: interval(Duration::from_secs(secs)),
+        }
+    }
+}
+
+/// Drop-in replacement for `tokio::time::Interval` that picks up period changes
+pub struct ReloadableInterval {
+    // Keeps the sender alive so `changed()` never resolves spuriously
+    _handle: SettingsHandle,
+    rx: watch::Receiver<RuntimeSettings>,
+    name: &'static str,
+    period: fn(&RuntimeSettings) -> u64,
+    secs: u64,
+    ticker: Interval,
+}
+
+impl ReloadableInterval {
+    /// Current period in seconds
+    pub fn period_secs(&self) -> u64 {
+        self.secs
+    }
+
+    /// Wait for the next tick, restarting the timer if the period changes meanwhile
+    pub async fn tick(&mut self) {
+        loop {
+            tokio::select! {
+                _ = self.ticker.tick() => return,
+                Ok(()) = self.rx.changed() => {
+                    let secs = (self.period)(&self.rx.borrow_and_update());
+                    if secs != self.secs {
+                        info!("{} changed from {}s to {}s", self.name, self.secs, secs);
+                        self.secs = secs;
+                        let period = Duration::from_secs(secs);
+                        self.ticker = interval_at(Instant::now() + period, period);
+                    }
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -59,7 +289,7 @@ mod tests {
         let config = Config::from_env().unwrap();
         assert_eq!(config.api_url, "https://api.example.com");
         assert_eq!(config.node_id, "node-001");
-        assert_eq!(config.node_secret, "secret-key");
+        assert_eq!(config.node_secret.get(), "secret-key");
         assert_eq!(config.xray_api_port, 10085);
         assert_eq!(config.traffic_report_interval, 30);
         assert_eq!(config.heartbeat_interval, 60);
@@ -82,6 +312,62 @@ mod tests {
         assert_eq!(config.heartbeat_interval, 120);
     }
 
+    #[test]
+    fn test_config_requires_credentials_or_enrollment_token() {
+        let _lock = TEST_MUTEX.lock().unwrap();
+
+        let missing = env::temp_dir().join("node-agent-missing-credentials.json");
+        env::set_var("API_URL", "https://api.example.com");
+        env::remove_var("NODE_ID");
+        env::remove_var("NODE_SECRET");
+        env::remove_var("ENROLLMENT_TOKEN");
+        env::set_var("NODE_CREDENTIALS_FILE", &missing);
+
+        assert!(Config::from_env().is_err());
+
+        env::set_var("ENROLLMENT_TOKEN", "enr_test");
+        let config = Config::from_env().unwrap();
+        assert!(config.needs_enrollment());
+        assert_eq!(config.enrollment_token.as_deref(), Some("enr_test"));
+
+        env::remove_var("ENROLLMENT_TOKEN");
+        env::remove_var("NODE_CREDENTIALS_FILE");
+    }
+
+    #[test]
+    fn test_runtime_settings_sanitized_and_diffed() {
+        let settings: RuntimeSettings = serde_json::from_str(
+            r#"{"heartbeat_interval": 1, "probe_targets": ["1.1.1.1:443", " "]}"#,
+        )
+        .unwrap();
+        let settings = settings.sanitized();
+
+        assert_eq!(settings.heartbeat_interval, MIN_INTERVAL_SECS);
+        assert_eq!(settings.traffic_report_interval, 30);
+        assert_eq!(settings.probe_targets, vec!["1.1.1.1:443".to_string()]);
+        assert_eq!(
+            settings.changed_fields(&RuntimeSettings::default()),
+            vec!["heartbeat_interval", "probe_targets"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_settings_handle_applies_only_changes() {
+        let handle = SettingsHandle::new(RuntimeSettings::default());
+        let rx = handle.subscribe();
+
+        assert!(handle.apply(RuntimeSettings::default()).is_empty());
+        assert!(!rx.has_changed().unwrap());
+
+        let changed = handle.apply(RuntimeSettings {
+            user_sync_interval: 120,
+            ..RuntimeSettings::default()
+        });
+        assert_eq!(changed, vec!["user_sync_interval"]);
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(handle.current().user_sync_interval, 120);
+    }
+
     // Feature: vpn-subscription-platform, Property 20: 环境变量配置正确性
     // **Validates: Requirements 13.4**
     // For any configuration parameter (database connection, Redis connection, etc.),
@@ -115,7 +401,7 @@ mod tests {
             // Verify that the loaded config matches the environment variables
             prop_assert_eq!(&config.api_url, &api_url);
             prop_assert_eq!(&config.node_id, &node_id);
-            prop_assert_eq!(&config.node_secret, &node_secret);
+            prop_assert_eq!(&config.node_secret.get(), &node_secret);
             prop_assert_eq!(config.xray_api_port, xray_port);
             prop_assert_eq!(config.traffic_report_interval, traffic_interval);
             prop_assert_eq!(config.heartbeat_interval, heartbeat_interval);
@@ -145,7 +431,7 @@ mod tests {
             // Verify required values are set correctly
             prop_assert_eq!(&config.api_url, &api_url);
             prop_assert_eq!(&config.node_id, &node_id);
-            prop_assert_eq!(&config.node_secret, &node_secret);
+            prop_assert_eq!(&config.node_secret.get(), &node_secret);
             
             // Verify default values are used for optional parameters
             prop_assert_eq!(config.xray_api_port, 10085);
@@ -0,0 +1,402 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::io::ErrorKind;
+use std::net::{TcpListener, UdpSocket};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration};
+
+use crate::auth::RequestStamp;
+use crate::config::Config;
+use crate::snapshot::{describe_age, SnapshotStore};
+use crate::sync::{ConfigSync, NodeConfig};
+
+/// Clock skew the API accepts by default (`NODE_AUTH_MAX_CLOCK_SKEW_SECS`)
+pub const MAX_CLOCK_SKEW_SECS: i64 = 300;
+
+/// Skew worth fixing before the API starts rejecting requests
+pub const WARN_CLOCK_SKEW_SECS: i64 = 30;
+
+/// Proxy core binary looked up on `PATH`
+pub const CORE_BINARY: &str = "xray";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Outcome of a single check; only `Fail` fails the run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl CheckStatus {
+    fn label(self) -> &'static str {
+        match self {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Results of a `--diagnose` run
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+    /// Core config rendered from the node config; printed, never written or applied
+    pub rendered_config: Option<String>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.status != CheckStatus::Fail)
+    }
+
+    pub fn print(&self) {
+        for check in &self.checks {
+            println!("[{}] {}: {}", check.status.label(), check.name, check.detail);
+        }
+        if let Some(rendered) = &self.rendered_config {
+            println!("\n--- Rendered Xray config (not applied) ---\n{}", rendered);
+        }
+        println!("\nDiagnostics {}", if self.passed() { "passed" } else { "failed" });
+    }
+}
+
+/// Body of `POST /api/v1/node/diagnostics`
+#[derive(Debug, Serialize)]
+struct DiagnosticsRequest<'a> {
+    node_id: i64,
+    secret: String,
+    timestamp: i64,
+    nonce: String,
+    agent_version: &'static str,
+    checks: &'a [Check],
+}
+
+/// Run every check, print the results and report them to the API
+///
+/// Returns whether all checks passed, for the process exit code.
+pub async fn run_and_report(config: Arc<Config>) -> bool {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+
+    let report = run(&config, &client).await;
+    report.print();
+
+    if config.needs_enrollment() {
+        println!("Node is not enrolled yet, results were not reported to the API");
+    } else {
+        match submit(&config, &client, &report.checks).await {
+            Ok(()) => println!("Results reported to the API"),
+            Err(e) => println!("Failed to report results to the API: {:#}", e),
+        }
+    }
+
+    report.passed()
+}
+
+/// Run the checks without changing anything on the node
+pub async fn run(config: &Arc<Config>, client: &reqwest::Client) -> Report {
+    let mut report = Report::default();
+
+    let server_time = match fetch_server_time(client, &config.api_url).await {
+        Ok(server_time) => {
+            report.checks.push(Check::new(
+                "api_connectivity",
+                CheckStatus::Pass,
+                format!("{} is reachable", config.api_url),
+            ));
+            server_time
+        }
+        Err(e) => {
+            report.checks.push(Check::new("api_connectivity", CheckStatus::Fail, format!("{:#}", e)));
+            None
+        }
+    };
+    report.checks.push(match server_time {
+        Some(server_time) => clock_skew_check((Utc::now() - server_time).num_seconds()),
+        None => Check::new("clock_skew", CheckStatus::Warn, "API server time unavailable, skew not checked"),
+    });
+
+    report.checks.push(core_binary_check());
+
+    let node_config = load_node_config(config, &mut report).await;
+    if let Some(node_config) = &node_config {
+        match ConfigSync::new(Arc::clone(config), None).generate_xray_config(node_config) {
+            Ok(rendered) => {
+                report.checks.push(Check::new(
+                    "core_config",
+                    CheckStatus::Pass,
+                    format!(
+                        "rendered {} inbound on port {} with {} users",
+                        node_config.protocol,
+                        node_config.port,
+                        node_config.users.len()
+                    ),
+                ));
+                report.rendered_config = Some(rendered);
+            }
+            Err(e) => report.checks.push(Check::new("core_config", CheckStatus::Fail, format!("{:#}", e))),
+        }
+    }
+
+    // Ports held by a running core are expected; anything else would stop it starting
+    let core_running = matches!(
+        timeout(Duration::from_secs(2), TcpStream::connect(("127.0.0.1", config.xray_api_port))).await,
+        Ok(Ok(_))
+    );
+    if let Some(node_config) = &node_config {
+        let udp = node_config.protocol == "hysteria2";
+        report.checks.push(port_check("proxy_port", "0.0.0.0", node_config.port, udp, core_running));
+    }
+    report
+        .checks
+        .push(port_check("core_api_port", "127.0.0.1", config.xray_api_port, false, core_running));
+
+    report
+}
+
+/// Reach the API health endpoint and read the server time from its `Date` header
+async fn fetch_server_time(client: &reqwest::Client, api_url: &str) -> Result<Option<DateTime<Utc>>> {
+    let response = client
+        .get(format!("{}/health", api_url))
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach {}", api_url))?;
+
+    if !response.status().is_success() {
+        anyhow::bail!("{} health check returned {}", api_url, response.status());
+    }
+
+    Ok(response
+        .headers()
+        .get(reqwest::header::DATE)
+        .and_then(|date| date.to_str().ok())
+        .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+        .map(|date| date.with_timezone(&Utc)))
+}
+
+/// Judge the local clock against the API's (`skew_secs` is positive when local is ahead)
+pub fn clock_skew_check(skew_secs: i64) -> Check {
+    let direction = if skew_secs >= 0 { "ahead of" } else { "behind" };
+    let detail = format!("local clock is {}s {} the API", skew_secs.abs(), direction);
+
+    let status = match skew_secs.abs() {
+        s if s > MAX_CLOCK_SKEW_SECS => CheckStatus::Fail,
+        s if s > WARN_CLOCK_SKEW_SECS => CheckStatus::Warn,
+        _ => CheckStatus::Pass,
+    };
+    match status {
+        CheckStatus::Pass => Check::new("clock_skew", status, detail),
+        _ => Check::new(
+            "clock_skew",
+            status,
+            format!("{}; the API rejects requests over {}s off, sync the clock with NTP", detail, MAX_CLOCK_SKEW_SECS),
+        ),
+    }
+}
+
+/// First file called `name` in the directories of a `PATH`-style list
+pub fn find_in_path(name: &str, path: &OsStr) -> Option<PathBuf> {
+    std::env::split_paths(path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+fn core_binary_check() -> Check {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let binary = match find_in_path(CORE_BINARY, &path) {
+        Some(binary) => binary,
+        None => return Check::new("core_binary", CheckStatus::Fail, format!("{} not found on PATH", CORE_BINARY)),
+    };
+
+    match Command::new(&binary).arg("version").output() {
+        Ok(output) if output.status.success() => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let version = stdout.lines().next().unwrap_or_default().trim();
+            Check::new("core_binary", CheckStatus::Pass, format!("{} ({})", binary.display(), version))
+        }
+        Ok(output) => Check::new(
+            "core_binary",
+            CheckStatus::Fail,
+            format!("{} version exited with {}", binary.display(), output.status),
+        ),
+        Err(e) => Check::new(
+            "core_binary",
+            CheckStatus::Fail,
+            format!("{} could not be run: {}", binary.display(), e),
+        ),
+    }
+}
+
+/// Fetch the node config from the API, falling back to the cached one for rendering
+async fn load_node_config(config: &Arc<Config>, report: &mut Report) -> Option<NodeConfig> {
+    let error = if config.needs_enrollment() {
+        "node is not enrolled yet".to_string()
+    } else {
+        match ConfigSync::new(Arc::clone(config), None).fetch_config().await {
+            Ok(node_config) => {
+                report.checks.push(Check::new(
+                    "node_config",
+                    CheckStatus::Pass,
+                    format!("fetched {} config for port {}", node_config.protocol, node_config.port),
+                ));
+                return Some(node_config);
+            }
+            Err(e) => format!("{:#}", e),
+        }
+    };
+
+    match SnapshotStore::new(&config.cache_dir).load_node_config() {
+        Ok(Some(snapshot)) => {
+            report.checks.push(Check::new(
+                "node_config",
+                CheckStatus::Fail,
+                format!(
+                    "{}; rendering the cached config from {} ago instead",
+                    error,
+                    describe_age(snapshot.age(Utc::now()))
+                ),
+            ));
+            Some(snapshot.value)
+        }
+        _ => {
+            report.checks.push(Check::new("node_config", CheckStatus::Fail, error));
+            None
+        }
+    }
+}
+
+/// Check a port can be bound on `host`, as the core will need to
+pub fn port_check(name: &str, host: &str, port: u16, udp: bool, core_running: bool) -> Check {
+    let transport = if udp { "udp" } else { "tcp" };
+    let bound = if udp {
+        UdpSocket::bind((host, port)).map(drop)
+    } else {
+        TcpListener::bind((host, port)).map(drop)
+    };
+
+    match bound {
+        Ok(()) => Check::new(name, CheckStatus::Pass, format!("{}/{} is free", port, transport)),
+        Err(e) if e.kind() == ErrorKind::AddrInUse && core_running => Check::new(
+            name,
+            CheckStatus::Warn,
+            format!("{}/{} is in use, presumably by the running core", port, transport),
+        ),
+        Err(e) if e.kind() == ErrorKind::AddrInUse => Check::new(
+            name,
+            CheckStatus::Fail,
+            format!("{}/{} is in use by another process", port, transport),
+        ),
+        Err(e) => Check::new(name, CheckStatus::Fail, format!("{}/{} cannot be bound: {}", port, transport, e)),
+    }
+}
+
+/// Send the check results to the API for the admin node page
+async fn submit(config: &Config, client: &reqwest::Client, checks: &[Check]) -> Result<()> {
+    let stamp = RequestStamp::now();
+    let request = DiagnosticsRequest {
+        node_id: config.node_id.parse().context("NODE_ID must be numeric")?,
+        secret: config.node_secret.get(),
+        timestamp: stamp.timestamp,
+        nonce: stamp.nonce,
+        agent_version: crate::update::AGENT_VERSION,
+        checks,
+    };
+
+    let response = client
+        .post(format!("{}/api/v1/node/diagnostics", config.api_url))
+        .json(&request)
+        .send()
+        .await
+        .context("Failed to send diagnostics to API")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("Diagnostics report failed: status={}, body={}", status, body);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_skew_check() {
+        let ok = clock_skew_check(-2);
+        assert_eq!(ok.status, CheckStatus::Pass);
+        assert_eq!(ok.detail, "local clock is 2s behind the API");
+
+        assert_eq!(clock_skew_check(WARN_CLOCK_SKEW_SECS + 1).status, CheckStatus::Warn);
+        let off = clock_skew_check(MAX_CLOCK_SKEW_SECS + 1);
+        assert_eq!(off.status, CheckStatus::Fail);
+        assert!(off.detail.starts_with("local clock is 301s ahead of the API"));
+    }
+
+    #[test]
+    fn test_port_check_detects_bound_port() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        assert_eq!(port_check("proxy_port", "127.0.0.1", port, false, false).status, CheckStatus::Fail);
+        assert_eq!(port_check("proxy_port", "127.0.0.1", port, false, true).status, CheckStatus::Warn);
+
+        drop(listener);
+        assert_eq!(port_check("proxy_port", "127.0.0.1", port, false, false).status, CheckStatus::Pass);
+    }
+
+    #[test]
+    fn test_find_in_path() {
+        let dir = std::env::temp_dir().join("node-agent-diagnose-path");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("fake-core"), b"").unwrap();
+
+        let path = std::env::join_paths([PathBuf::from("/nonexistent"), dir.clone()]).unwrap();
+        assert_eq!(find_in_path("fake-core", &path), Some(dir.join("fake-core")));
+        assert_eq!(find_in_path("missing-core", &path), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_report_fails_only_on_failed_checks() {
+        let mut report = Report::default();
+        report.checks.push(Check::new("clock_skew", CheckStatus::Warn, "slightly off"));
+        assert!(report.passed());
+
+        report.checks.push(Check::new("core_binary", CheckStatus::Fail, "missing"));
+        assert!(!report.passed());
+
+        let json = serde_json::to_value(&report.checks[1]).unwrap();
+        assert_eq!(json["status"], "fail");
+    }
+}
@@ -0,0 +1,232 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::config::Config;
+
+/// Protocols this agent can serve (see `ConfigSync::generate_xray_config`)
+pub const SUPPORTED_PROTOCOLS: &[&str] = &["shadowsocks", "vmess", "trojan", "hysteria2", "vless"];
+
+/// Permanent node credentials issued at enrollment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credentials {
+    pub node_id: String,
+    pub node_secret: String,
+}
+
+impl Credentials {
+    /// Load credentials saved by a previous enrollment, if any
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Ok(Some(
+                serde_json::from_str(&content).context("Failed to parse node credentials file")?,
+            )),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read node credentials file"),
+        }
+    }
+
+    /// Persist credentials so later restarts skip enrollment (owner-only on Unix)
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).context("Failed to create credentials directory")?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)
+            .context("Failed to write node credentials file")?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+                .context("Failed to restrict node credentials file permissions")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Body of `POST /api/v1/node/register`
+#[derive(Debug, Clone, Serialize)]
+pub struct RegisterRequest {
+    pub token: String,
+    pub name: Option<String>,
+    pub host: String,
+    pub port: u16,
+    pub protocol: String,
+    pub capabilities: serde_json::Value,
+}
+
+impl RegisterRequest {
+    /// Build the enrollment request from `NODE_HOST`, `NODE_PORT`, `NODE_PROTOCOL` and `NODE_NAME`
+    pub fn from_env(token: &str, config: &Config) -> Result<Self> {
+        let protocol = env::var("NODE_PROTOCOL").unwrap_or_else(|_| "vless".to_string());
+        if !SUPPORTED_PROTOCOLS.contains(&protocol.as_str()) {
+            anyhow::bail!("NODE_PROTOCOL must be one of: {}", SUPPORTED_PROTOCOLS.join(", "));
+        }
+
+        Ok(RegisterRequest {
+            token: token.to_string(),
+            name: env::var("NODE_NAME").ok().filter(|s| !s.is_empty()),
+            host: env::var("NODE_HOST")
+                .context("NODE_HOST must be set to enroll with ENROLLMENT_TOKEN")?,
+            port: env::var("NODE_PORT")
+                .unwrap_or_else(|_| "443".to_string())
+                .parse()
+                .context("NODE_PORT must be a valid number")?,
+            protocol,
+            capabilities: detect_capabilities(config),
+        })
+    }
+}
+
+/// Response of `POST /api/v1/node/register`
+#[derive(Debug, Deserialize)]
+struct RegisterResponse {
+    node_id: i64,
+    secret: String,
+}
+
+/// Capabilities reported to the API at enrollment
+pub fn detect_capabilities(config: &Config) -> serde_json::Value {
+    serde_json::json!({
+        "agent_version": crate::update::AGENT_VERSION,
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "cpus": std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        "protocols": SUPPORTED_PROTOCOLS,
+        "xray_api_port": config.xray_api_port,
+    })
+}
+
+/// Exchange an enrollment token for permanent node credentials
+pub async fn register(client: &reqwest::Client, api_url: &str, request: &RegisterRequest) -> Result<Credentials> {
+    let url = format!("{}/api/v1/node/register", api_url);
+    let response = client
+        .post(&url)
+        .json(request)
+        .send()
+        .await
+        .context("Failed to send enrollment request to API")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("Enrollment failed: status={}, body={}", status, body);
+    }
+
+    let registered: RegisterResponse = response
+        .json()
+        .await
+        .context("Failed to parse enrollment response")?;
+
+    Ok(Credentials {
+        node_id: registered.node_id.to_string(),
+        node_secret: registered.secret,
+    })
+}
+
+/// Enroll with `ENROLLMENT_TOKEN` if the agent has no credentials yet
+///
+/// The issued credentials are saved to `NODE_CREDENTIALS_FILE`; the node stays pending
+/// until an admin approves it, so config fetches fail until then.
+pub async fn ensure_credentials(config: &mut Config) -> Result<()> {
+    if !config.needs_enrollment() {
+        return Ok(());
+    }
+
+    let token = config
+        .enrollment_token
+        .clone()
+        .context("NODE_ID and NODE_SECRET must be set, or ENROLLMENT_TOKEN to enroll")?;
+    let request = RegisterRequest::from_env(&token, config)?;
+
+    info!("Enrolling node {}:{} ({}) with the API", request.host, request.port, request.protocol);
+    let credentials = register(&reqwest::Client::new(), &config.api_url, &request).await?;
+    credentials.save(Path::new(&config.credentials_file))?;
+    info!(
+        "Enrolled as node {}, credentials saved to {}; waiting for admin approval",
+        credentials.node_id, config.credentials_file
+    );
+
+    config.node_id = credentials.node_id;
+    config.node_secret.set(credentials.node_secret);
+    Ok(())
+}
+
+/// Switch to a secret issued by a rotation and save it for restarts
+///
+/// The API accepts the old secret for an overlap window only, so the new one is used
+/// right away even if it cannot be saved.
+pub fn adopt_rotated_secret(config: &Config, secret: &str) -> Result<()> {
+    if config.node_secret.get() == secret {
+        return Ok(());
+    }
+    config.node_secret.set(secret);
+    info!("Node secret rotated by the API");
+
+    if env::var("NODE_SECRET").is_ok() {
+        warn!("NODE_SECRET is set and takes precedence over the credentials file; update it before the old secret expires");
+    }
+    let credentials = Credentials {
+        node_id: config.node_id.clone(),
+        node_secret: secret.to_string(),
+    };
+    credentials.save(Path::new(&config.credentials_file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_credentials_roundtrip() {
+        let path = std::env::temp_dir().join(format!("node-agent-credentials-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(Credentials::load(&path).unwrap(), None);
+
+        let credentials = Credentials {
+            node_id: "42".to_string(),
+            node_secret: "s3cret".to_string(),
+        };
+        credentials.save(&path).unwrap();
+        assert_eq!(Credentials::load(&path).unwrap(), Some(credentials));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_adopt_rotated_secret() {
+        let path = std::env::temp_dir().join(format!("node-agent-rotated-{}.json", std::process::id()));
+        let config = Config {
+            api_url: "http://localhost:8080".to_string(),
+            node_id: "42".to_string(),
+            node_secret: crate::config::NodeSecret::new("old-secret"),
+            xray_api_port: 10085,
+            traffic_report_interval: 30,
+            heartbeat_interval: 60,
+            enrollment_token: None,
+            credentials_file: path.to_string_lossy().into_owned(),
+            cache_dir: crate::config::DEFAULT_CACHE_DIR.to_string(),
+            update_check_interval: 0,
+        };
+        let component_copy = config.clone();
+
+        adopt_rotated_secret(&config, "new-secret").unwrap();
+
+        assert_eq!(component_copy.node_secret.get(), "new-secret");
+        let saved = Credentials::load(&path).unwrap().unwrap();
+        assert_eq!(saved.node_id, "42");
+        assert_eq!(saved.node_secret, "new-secret");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,11 +1,15 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::process::Command;
 use std::sync::Arc;
-use tokio::time::{interval, Duration};
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration};
 use tracing::{error, info, warn};
 
-use crate::config::Config;
+use crate::auth::RequestStamp;
+use crate::config::{Config, SettingsHandle};
+use crate::sync::{AppliedConfig, MAIN_INBOUND_TAG};
 
 /// Health status of the node
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,22 +27,131 @@ pub struct NodeHealth {
 pub struct HeartbeatData {
     pub node_id: String,
     pub secret: String,
+    /// Unix time the heartbeat was sent (see `RequestStamp`)
+    pub timestamp: i64,
+    /// Single-use nonce, rejected by the API if replayed
+    pub nonce: String,
     pub status: String,
     pub cpu_usage: f64,
     pub memory_usage: f64,
     pub active_connections: u32,
+    /// Online connection count per user email
+    #[serde(default)]
+    pub user_connections: Vec<UserConnectionCount>,
+    /// Version of this agent binary, so admins can see version skew
+    #[serde(default)]
+    pub agent_version: String,
+    /// `config_hash` of the applied config, so the API can tell whether it is current
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_hash: Option<String>,
+    /// `config_version` of the applied config, shown to admins as the node's applied version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_version: Option<i64>,
+}
+
+/// Number of live connections for one user on this node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserConnectionCount {
+    pub email: String,
+    pub connections: u32,
+    /// Unix time this node last saw a new connection from the user
+    #[serde(default)]
+    pub last_handshake: Option<i64>,
+}
+
+/// Heartbeat response from API service
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HeartbeatResponse {
+    /// Users over their concurrent device limit whose sessions this node must drop
+    #[serde(default)]
+    pub drop_sessions: Vec<SessionDrop>,
+    /// New node secret, sent while the API still accepts the one it replaced
+    #[serde(default)]
+    pub node_secret: Option<String>,
+    /// Hash of the node's current config, answered when the heartbeat carried one
+    #[serde(default)]
+    pub config_hash: Option<String>,
+}
+
+/// Instruction to drop a user's sessions on this node
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionDrop {
+    pub user_id: i64,
+    pub email: String,
+    pub connections: i32,
+    pub limit: i32,
+}
+
+/// Parse per-user online counts from an Xray stats query response
+///
+/// Online stats are named `user>>>{email}>>>online`; values may be encoded as
+/// numbers or strings.
+pub fn parse_online_stats(stats: &serde_json::Value) -> Vec<UserConnectionCount> {
+    stats["stat"]
+        .as_array()
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|entry| {
+                    let email = entry["name"]
+                        .as_str()?
+                        .strip_prefix("user>>>")?
+                        .strip_suffix(">>>online")?;
+                    let connections = match &entry["value"] {
+                        serde_json::Value::String(v) => v.parse::<u32>().ok()?,
+                        v => v.as_u64()? as u32,
+                    };
+                    (connections > 0).then(|| UserConnectionCount {
+                        email: email.to_string(),
+                        connections,
+                        last_handshake: None,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Tracks when each user last opened a new connection on this node
+///
+/// Xray only reports how many connections a user has, so a user who just came
+/// online, or whose count rose since the previous heartbeat, has just handshaken.
+#[derive(Debug, Default)]
+pub struct HandshakeTracker {
+    /// Connection count and last handshake per email, as of the previous heartbeat
+    previous: HashMap<String, (u32, i64)>,
+}
+
+impl HandshakeTracker {
+    /// Fill in `last_handshake` for the counts observed at Unix time `now`
+    pub fn observe(&mut self, counts: &mut [UserConnectionCount], now: i64) {
+        let mut current = HashMap::with_capacity(counts.len());
+        for count in counts.iter_mut() {
+            let handshake = match self.previous.get(&count.email) {
+                Some(&(connections, at)) if count.connections <= connections => at,
+                _ => now,
+            };
+            count.last_handshake = Some(handshake);
+            current.insert(count.email.clone(), (count.connections, handshake));
+        }
+        self.previous = current;
+    }
 }
 
 /// Health checker that monitors Xray-core and sends heartbeats
 pub struct HealthChecker {
     config: Arc<Config>,
+    settings: SettingsHandle,
+    applied: AppliedConfig,
     http_client: reqwest::Client,
 }
 
 impl HealthChecker {
-    pub fn new(config: Arc<Config>) -> Self {
+    pub fn new(config: Arc<Config>, settings: SettingsHandle, applied: AppliedConfig) -> Self {
         Self {
             config,
+            settings,
+            applied,
             http_client: reqwest::Client::new(),
         }
     }
@@ -46,15 +159,20 @@ impl HealthChecker {
     /// Start the health check and heartbeat loop
     pub async fn start(&self) -> Result<()> {
         let config = Arc::clone(&self.config);
+        let settings = self.settings.clone();
+        let applied = self.applied.clone();
         let http_client = self.http_client.clone();
+        let mut ticker = settings.interval("heartbeat_interval", |s| s.heartbeat_interval);
+        let period = ticker.period_secs();
 
         tokio::spawn(async move {
-            let mut ticker = interval(Duration::from_secs(config.heartbeat_interval));
-
+            let mut handshakes = HandshakeTracker::default();
             loop {
                 ticker.tick().await;
 
-                match Self::check_and_send_heartbeat(&config, &http_client).await {
+                Self::probe_targets(&settings.current().probe_targets).await;
+
+                match Self::check_and_send_heartbeat(&config, &http_client, &applied, &mut handshakes).await {
                     Ok(_) => {
                         info!("Heartbeat sent successfully");
                     }
@@ -65,10 +183,7 @@ impl HealthChecker {
             }
         });
 
-        info!(
-            "Health checker started with interval: {} seconds",
-            self.config.heartbeat_interval
-        );
+        info!("Health checker started with interval: {} seconds", period);
 
         Ok(())
     }
@@ -77,6 +192,8 @@ impl HealthChecker {
     async fn check_and_send_heartbeat(
         config: &Config,
         http_client: &reqwest::Client,
+        applied: &AppliedConfig,
+        handshakes: &mut HandshakeTracker,
     ) -> Result<()> {
         // Check Xray-core status
         let xray_status = Self::check_xray_status(config).await;
@@ -93,11 +210,25 @@ impl HealthChecker {
         let cpu_usage = Self::get_cpu_usage().await.unwrap_or(0.0);
         let memory_usage = Self::get_memory_usage().await.unwrap_or(0.0);
         let active_connections = Self::get_active_connections(config).await.unwrap_or(0);
+        let user_connections = match Self::get_user_connections(config).await {
+            Ok(mut counts) => {
+                handshakes.observe(&mut counts, chrono::Utc::now().timestamp());
+                counts
+            }
+            Err(e) => {
+                // Leave the tracker alone so users don't all look reconnected next time
+                warn!("Failed to collect per-user connections: {}", e);
+                Vec::new()
+            }
+        };
 
         // Prepare heartbeat data
+        let stamp = RequestStamp::now();
         let heartbeat = HeartbeatData {
             node_id: config.node_id.clone(),
-            secret: config.node_secret.clone(),
+            secret: config.node_secret.get(),
+            timestamp: stamp.timestamp,
+            nonce: stamp.nonce,
             status: if xray_status == "running" {
                 "online".to_string()
             } else {
@@ -106,10 +237,14 @@ impl HealthChecker {
             cpu_usage,
             memory_usage,
             active_connections,
+            user_connections,
+            agent_version: crate::update::AGENT_VERSION.to_string(),
+            config_hash: applied.hash(),
+            config_version: applied.version(),
         };
 
         // Send heartbeat to API service
-        let url = format!("{}/api/node/heartbeat", config.api_url);
+        let url = format!("{}/api/v1/node/heartbeat", config.api_url);
 
         let response = http_client
             .post(&url)
@@ -128,9 +263,39 @@ impl HealthChecker {
             );
         }
 
+        let heartbeat_response: HeartbeatResponse = response.json().await.unwrap_or_default();
+        if let Some(secret) = &heartbeat_response.node_secret {
+            if let Err(e) = crate::enroll::adopt_rotated_secret(config, secret) {
+                error!("Failed to save rotated node secret: {}", e);
+            }
+        }
+        if applied.check(heartbeat_response.config_hash.as_deref()) {
+            warn!("API reports a newer configuration than the one applied, requesting a refetch");
+        }
+        for drop in &heartbeat_response.drop_sessions {
+            warn!(
+                "User {} has {} connections (limit {}), dropping sessions on this node",
+                drop.user_id, drop.connections, drop.limit
+            );
+            if let Err(e) = Self::drop_user_sessions(config, &drop.email) {
+                error!("Failed to drop sessions for user {}: {}", drop.user_id, e);
+            }
+        }
+
         Ok(())
     }
 
+    /// TCP-probe the configured targets, warning about unreachable ones
+    async fn probe_targets(targets: &[String]) {
+        for target in targets {
+            match timeout(Duration::from_secs(5), TcpStream::connect(target.as_str())).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => warn!("Probe target {} unreachable: {}", target, e),
+                Err(_) => warn!("Probe target {} timed out", target),
+            }
+        }
+    }
+
     /// Check if Xray-core is running
     async fn check_xray_status(config: &Config) -> String {
         // Try to connect to Xray API
@@ -229,6 +394,50 @@ impl HealthChecker {
             _ => Ok(0),
         }
     }
+
+    /// Get online connection counts per user from Xray stats
+    async fn get_user_connections(config: &Config) -> Result<Vec<UserConnectionCount>> {
+        let api_url = format!("http://127.0.0.1:{}/stats/query", config.xray_api_port);
+
+        let stats: serde_json::Value = reqwest::Client::new()
+            .post(&api_url)
+            .json(&serde_json::json!({
+                "pattern": "user>>>",
+                "reset": false
+            }))
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .context("Failed to query Xray user stats")?
+            .json()
+            .await
+            .context("Failed to parse Xray user stats")?;
+
+        Ok(parse_online_stats(&stats))
+    }
+
+    /// Remove a user from the main inbound, closing their sessions on this node
+    ///
+    /// The user is restored the next time the node configuration is applied.
+    fn drop_user_sessions(config: &Config, email: &str) -> Result<()> {
+        let output = Command::new("xray")
+            .args([
+                "api",
+                "rmu",
+                &format!("--server=127.0.0.1:{}", config.xray_api_port),
+                &format!("-tag={}", MAIN_INBOUND_TAG),
+                email,
+            ])
+            .output()
+            .context("Failed to execute xray api rmu")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("xray api rmu failed: {}", stderr);
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -240,10 +449,20 @@ mod tests {
         let heartbeat = HeartbeatData {
             node_id: "node-001".to_string(),
             secret: "secret-key".to_string(),
+            timestamp: 1_700_000_000,
+            nonce: "0123456789abcdef".to_string(),
             status: "online".to_string(),
             cpu_usage: 45.2,
             memory_usage: 60.5,
             active_connections: 123,
+            user_connections: vec![UserConnectionCount {
+                email: "user@example.com".to_string(),
+                connections: 2,
+                last_handshake: Some(1_699_999_990),
+            }],
+            agent_version: "1.4.0".to_string(),
+            config_hash: Some("abc123".to_string()),
+            config_version: Some(7),
         };
 
         let json = serde_json::to_string(&heartbeat).unwrap();
@@ -251,10 +470,75 @@ mod tests {
 
         assert_eq!(deserialized.node_id, "node-001");
         assert_eq!(deserialized.secret, "secret-key");
+        assert_eq!(deserialized.timestamp, 1_700_000_000);
+        assert_eq!(deserialized.nonce, "0123456789abcdef");
         assert_eq!(deserialized.status, "online");
         assert_eq!(deserialized.cpu_usage, 45.2);
         assert_eq!(deserialized.memory_usage, 60.5);
         assert_eq!(deserialized.active_connections, 123);
+        assert_eq!(deserialized.user_connections, heartbeat.user_connections);
+        assert_eq!(deserialized.agent_version, "1.4.0");
+        assert_eq!(deserialized.config_hash.as_deref(), Some("abc123"));
+        assert_eq!(deserialized.config_version, Some(7));
+    }
+
+    fn count(email: &str, connections: u32) -> UserConnectionCount {
+        UserConnectionCount {
+            email: email.to_string(),
+            connections,
+            last_handshake: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_online_stats() {
+        let stats = serde_json::json!({
+            "stat": [
+                { "name": "user>>>a@example.com>>>online", "value": "3" },
+                { "name": "user>>>b@example.com>>>online", "value": 1 },
+                { "name": "user>>>c@example.com>>>online", "value": 0 },
+                { "name": "user>>>a@example.com>>>traffic>>>uplink", "value": "1024" }
+            ]
+        });
+
+        let counts = parse_online_stats(&stats);
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts[0], count("a@example.com", 3));
+        assert_eq!(counts[1], count("b@example.com", 1));
+        assert!(parse_online_stats(&serde_json::json!({})).is_empty());
+    }
+
+    #[test]
+    fn test_handshake_tracker() {
+        let mut tracker = HandshakeTracker::default();
+        let handshakes = |counts: &[UserConnectionCount]| -> Vec<Option<i64>> {
+            counts.iter().map(|c| c.last_handshake).collect()
+        };
+
+        let mut counts = vec![count("a@example.com", 2), count("b@example.com", 1)];
+        tracker.observe(&mut counts, 100);
+        assert_eq!(handshakes(&counts), vec![Some(100), Some(100)]);
+
+        // a dropped a connection, b opened another
+        let mut counts = vec![count("a@example.com", 1), count("b@example.com", 2)];
+        tracker.observe(&mut counts, 160);
+        assert_eq!(handshakes(&counts), vec![Some(100), Some(160)]);
+
+        // a went offline and came back
+        let mut counts = vec![count("b@example.com", 2)];
+        tracker.observe(&mut counts, 220);
+        let mut counts = vec![count("a@example.com", 1), count("b@example.com", 2)];
+        tracker.observe(&mut counts, 280);
+        assert_eq!(handshakes(&counts), vec![Some(280), Some(160)]);
+    }
+
+    #[test]
+    fn test_heartbeat_response_without_drops() {
+        let response: HeartbeatResponse =
+            serde_json::from_str(r#"{"message":"Heartbeat received","node_id":1}"#).unwrap();
+        assert!(response.drop_sessions.is_empty());
+        assert!(response.node_secret.is_none());
+        assert!(response.config_hash.is_none());
     }
 
     #[test]
@@ -1,5 +1,10 @@
+pub mod auth;
 pub mod config;
+pub mod diagnose;
+pub mod enroll;
 pub mod health;
+pub mod snapshot;
 pub mod sync;
 pub mod traffic;
+pub mod update;
 pub mod users;
@@ -1,10 +1,15 @@
 use anyhow::Result;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+pub mod auth;
 pub mod config;
+pub mod diagnose;
+pub mod enroll;
 pub mod health;
+pub mod snapshot;
 pub mod sync;
 pub mod traffic;
+pub mod update;
 pub mod users;
 
 #[tokio::main]
@@ -21,10 +26,19 @@ async fn main() -> Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    // Dry run: check the node and print the rendered config without applying it
+    if std::env::args().skip(1).any(|arg| arg == "--diagnose") {
+        let config = std::sync::Arc::new(config::Config::from_env()?);
+        let passed = diagnose::run_and_report(config).await;
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
     tracing::info!("Starting VPN Node Agent");
 
     // Load configuration
-    let config = std::sync::Arc::new(config::Config::from_env()?);
+    let mut config = config::Config::from_env()?;
+    enroll::ensure_credentials(&mut config).await?;
+    let config = std::sync::Arc::new(config);
     tracing::info!("Configuration loaded");
     tracing::info!("API URL: {}", config.api_url);
     tracing::info!("Node ID: {}", config.node_id);
@@ -0,0 +1,220 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+use crate::sync::NodeConfig;
+use crate::users::ActiveUser;
+
+const NODE_CONFIG_FILE: &str = "node-config.json";
+const ACTIVE_USERS_FILE: &str = "active-users.json";
+
+/// On-disk form of a snapshot; the payload is kept as text so the checksum covers its exact bytes
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    saved_at: DateTime<Utc>,
+    /// Hex SHA-256 of `payload`
+    checksum: String,
+    payload: String,
+}
+
+/// A value restored from disk, with the time it was fetched from the API
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot<T> {
+    pub saved_at: DateTime<Utc>,
+    pub value: T,
+}
+
+impl<T> Snapshot<T> {
+    /// How long ago the value was fetched
+    pub fn age(&self, now: DateTime<Utc>) -> chrono::Duration {
+        now - self.saved_at
+    }
+}
+
+/// Last good node config and user list, so the agent can start while the API is down
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl SnapshotStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Save a fetched node config; a rotated node secret is left out (it lives in the credentials file)
+    pub fn save_node_config(&self, config: &NodeConfig) -> Result<()> {
+        let mut config = config.clone();
+        config.node_secret = None;
+        self.save(NODE_CONFIG_FILE, &config)
+    }
+
+    pub fn load_node_config(&self) -> Result<Option<Snapshot<NodeConfig>>> {
+        self.load(NODE_CONFIG_FILE)
+    }
+
+    pub fn save_active_users(&self, users: &[ActiveUser]) -> Result<()> {
+        self.save(ACTIVE_USERS_FILE, &users)
+    }
+
+    pub fn load_active_users(&self) -> Result<Option<Snapshot<Vec<ActiveUser>>>> {
+        self.load(ACTIVE_USERS_FILE)
+    }
+
+    /// Write through a temporary file so a crash never leaves a half-written snapshot
+    ///
+    /// Snapshots hold proxy credentials, so they are owner-only on Unix.
+    fn save<T: Serialize>(&self, name: &str, value: &T) -> Result<()> {
+        std::fs::create_dir_all(&self.dir).context("Failed to create snapshot directory")?;
+
+        let payload = serde_json::to_string(value).context("Failed to serialize snapshot")?;
+        let envelope = Envelope {
+            saved_at: Utc::now(),
+            checksum: checksum(&payload),
+            payload,
+        };
+
+        let path = self.dir.join(name);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&envelope)?).context("Failed to write snapshot")?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600))
+                .context("Failed to restrict snapshot permissions")?;
+        }
+
+        std::fs::rename(&tmp, &path).context("Failed to replace snapshot")?;
+        Ok(())
+    }
+
+    /// Load a snapshot; `None` if there is none, an error if it is unreadable or corrupted
+    fn load<T: DeserializeOwned>(&self, name: &str) -> Result<Option<Snapshot<T>>> {
+        let path = self.dir.join(name);
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read snapshot {}", path.display()))
+            }
+        };
+
+        let envelope: Envelope = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse snapshot {}", path.display()))?;
+        if checksum(&envelope.payload) != envelope.checksum {
+            anyhow::bail!("Snapshot {} failed its checksum", path.display());
+        }
+        let value = serde_json::from_str(&envelope.payload)
+            .with_context(|| format!("Failed to parse snapshot {}", path.display()))?;
+
+        Ok(Some(Snapshot {
+            saved_at: envelope.saved_at,
+            value,
+        }))
+    }
+}
+
+fn checksum(payload: &str) -> String {
+    Sha256::digest(payload.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Human-readable staleness for logs, e.g. `2d 3h`, `3h 12m` or `45s`
+pub fn describe_age(age: chrono::Duration) -> String {
+    let secs = age.num_seconds().max(0);
+    let (days, hours, minutes) = (secs / 86_400, secs % 86_400 / 3_600, secs % 3_600 / 60);
+
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str) -> SnapshotStore {
+        let dir = std::env::temp_dir().join(format!("node-agent-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        SnapshotStore::new(dir)
+    }
+
+    fn node_config() -> NodeConfig {
+        serde_json::from_str(
+            r#"{
+                "protocol": "trojan",
+                "port": 443,
+                "users": [{"id": "uuid-1", "email": "user@example.com"}],
+                "trojan_config": {"password": "pw"},
+                "node_secret": "rotated-secret"
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_node_config_roundtrip() {
+        let store = temp_store("snapshot-config");
+        assert!(store.load_node_config().unwrap().is_none());
+
+        store.save_node_config(&node_config()).unwrap();
+        let snapshot = store.load_node_config().unwrap().unwrap();
+
+        assert_eq!(snapshot.value.protocol, "trojan");
+        assert_eq!(snapshot.value.users.len(), 1);
+        assert_eq!(snapshot.value.node_secret, None);
+        assert!(snapshot.age(Utc::now()) < chrono::Duration::minutes(1));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(store.dir.join(NODE_CONFIG_FILE)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        std::fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn test_tampered_snapshot_is_rejected() {
+        let store = temp_store("snapshot-tampered");
+        let users = vec![ActiveUser {
+            user_id: "1".to_string(),
+            email: "user@example.com".to_string(),
+            traffic_quota: 100,
+            traffic_used: 10,
+        }];
+        store.save_active_users(&users).unwrap();
+        assert_eq!(store.load_active_users().unwrap().unwrap().value.len(), 1);
+
+        let path = store.dir.join(ACTIVE_USERS_FILE);
+        let tampered = std::fs::read_to_string(&path).unwrap().replace("user@example.com", "evil@example.com");
+        std::fs::write(&path, tampered).unwrap();
+
+        let err = store.load_active_users().unwrap_err();
+        assert!(err.to_string().contains("checksum"));
+
+        std::fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn test_describe_age() {
+        assert_eq!(describe_age(chrono::Duration::seconds(45)), "45s");
+        assert_eq!(describe_age(chrono::Duration::seconds(600)), "10m");
+        assert_eq!(describe_age(chrono::Duration::minutes(192)), "3h 12m");
+        assert_eq!(describe_age(chrono::Duration::hours(51)), "2d 3h");
+        assert_eq!(describe_age(chrono::Duration::seconds(-5)), "0s");
+    }
+}
@@ -1,11 +1,22 @@
 use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use futures_util::stream::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 use tracing::{error, info, warn};
 
-use crate::config::Config;
+use crate::auth::RequestStamp;
+use crate::config::{Config, RuntimeSettings, SettingsHandle};
+use crate::snapshot::{describe_age, SnapshotStore};
+
+/// Tag of the user-facing inbound in the generated Xray config
+pub const MAIN_INBOUND_TAG: &str = "proxy";
+
+/// First and longest wait between reconnection attempts while running on a cached config
+const RECONNECT_BACKOFF_MIN: Duration = Duration::from_secs(5);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(300);
 
 /// Node configuration received from API service
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +34,22 @@ pub struct NodeConfig {
     pub trojan_config: Option<TrojanConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub hysteria2_config: Option<Hysteria2Config>,
+    /// Hysteria2 port hopping: ports and ranges (`20000-30000`) served besides `port`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ports: Option<String>,
+    /// Runtime agent settings; applied without restart whenever the config is fetched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<RuntimeSettings>,
+    /// New node secret, sent while the API still accepts the one it replaced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_secret: Option<String>,
+    /// API's hash of this config, reported back in heartbeats once applied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_hash: Option<String>,
+    /// Node config version, reported back in heartbeats once applied so admins can
+    /// see which nodes are behind
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_version: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,21 +92,81 @@ pub struct Hysteria2Config {
     pub obfs: Option<String>,
 }
 
+/// Hash and version of the config this agent last applied, shared with the health checker
+///
+/// Heartbeats report the hash and get the API's current one back. A mismatch means a
+/// config update notification was missed (the agent was disconnected from Redis, or
+/// the publish happened while it was reconnecting), so a refetch is requested.
+#[derive(Debug, Clone, Default)]
+pub struct AppliedConfig {
+    state: Arc<std::sync::RwLock<AppliedState>>,
+    resync: Arc<tokio::sync::Notify>,
+}
+
+#[derive(Debug, Default)]
+struct AppliedState {
+    hash: Option<String>,
+    version: Option<i64>,
+}
+
+impl AppliedConfig {
+    /// Hash of the applied config; `None` until a config with a hash has been applied
+    pub fn hash(&self) -> Option<String> {
+        self.state.read().ok().and_then(|state| state.hash.clone())
+    }
+
+    /// Version of the applied config; `None` until a config with a version has been applied
+    pub fn version(&self) -> Option<i64> {
+        self.state.read().ok().and_then(|state| state.version)
+    }
+
+    fn set(&self, hash: Option<String>, version: Option<i64>) {
+        if let Ok(mut state) = self.state.write() {
+            *state = AppliedState { hash, version };
+        }
+    }
+
+    /// Compare with the API's current hash, requesting a refetch when they differ
+    ///
+    /// Returns whether the applied config is outdated. An API that sends no hash
+    /// never makes the config outdated.
+    pub fn check(&self, current: Option<&str>) -> bool {
+        let outdated = current.is_some_and(|current| self.hash().as_deref() != Some(current));
+        if outdated {
+            self.resync.notify_one();
+        }
+        outdated
+    }
+
+    async fn resync_requested(&self) {
+        self.resync.notified().await
+    }
+}
+
 /// Configuration synchronization manager
 pub struct ConfigSync {
     config: Arc<Config>,
+    settings: SettingsHandle,
     http_client: reqwest::Client,
     redis_url: Option<String>,
     current_config: Arc<RwLock<Option<NodeConfig>>>,
+    snapshots: SnapshotStore,
+    /// When the cached config in use was fetched; `None` once the API has been reached
+    cached_since: Arc<RwLock<Option<DateTime<Utc>>>>,
+    applied: AppliedConfig,
 }
 
 impl ConfigSync {
     pub fn new(config: Arc<Config>, redis_url: Option<String>) -> Self {
         Self {
+            settings: SettingsHandle::new(config.initial_settings()),
+            snapshots: SnapshotStore::new(&config.cache_dir),
             config,
             http_client: reqwest::Client::new(),
             redis_url,
             current_config: Arc::new(RwLock::new(None)),
+            cached_since: Arc::new(RwLock::new(None)),
+            applied: AppliedConfig::default(),
         }
     }
 
@@ -87,9 +174,47 @@ impl ConfigSync {
     pub async fn register_and_fetch_config(&self) -> Result<NodeConfig> {
         info!("Registering with API service and fetching initial configuration");
 
+        let node_config = self.fetch_config().await?;
+
+        info!(
+            "Successfully fetched initial configuration: protocol={}, port={}",
+            node_config.protocol, node_config.port
+        );
+
+        if let Some(settings) = &node_config.agent {
+            self.apply_settings(settings.clone());
+        }
+        if let Some(secret) = &node_config.node_secret {
+            if let Err(e) = crate::enroll::adopt_rotated_secret(&self.config, secret) {
+                error!("Failed to save rotated node secret: {}", e);
+            }
+        }
+
+        if let Err(e) = self.snapshots.save_node_config(&node_config) {
+            warn!("Failed to cache node configuration: {}", e);
+        }
+        if let Some(cached_since) = self.cached_since.write().await.take() {
+            info!(
+                "Reconnected to API, replacing cached configuration that was {} old",
+                describe_age(Utc::now() - cached_since)
+            );
+        }
+
+        // Store the configuration
+        let mut current = self.current_config.write().await;
+        *current = Some(node_config.clone());
+
+        Ok(node_config)
+    }
+
+    /// Fetch this node's configuration from the API without applying or caching it
+    pub async fn fetch_config(&self) -> Result<NodeConfig> {
         let url = format!(
-            "{}/api/node/config?node_id={}&secret={}",
-            self.config.api_url, self.config.node_id, self.config.node_secret
+            "{}/api/v1/node/config?node_id={}&secret={}&{}",
+            self.config.api_url,
+            self.config.node_id,
+            self.config.node_secret.get(),
+            RequestStamp::now().query()
         );
 
         let response = self
@@ -109,21 +234,69 @@ impl ConfigSync {
             );
         }
 
-        let node_config: NodeConfig = response
+        response
             .json()
             .await
-            .context("Failed to parse config response")?;
+            .context("Failed to parse config response")
+    }
 
-        info!(
-            "Successfully fetched initial configuration: protocol={}, port={}",
-            node_config.protocol, node_config.port
+    /// Fetch the initial configuration, falling back to the last cached one if the API is down
+    ///
+    /// When the cached config is used, fetching is retried in the background and the fresh
+    /// config is applied as soon as the API answers.
+    pub async fn fetch_or_load_cached_config(&self) -> Result<NodeConfig> {
+        let error = match self.register_and_fetch_config().await {
+            Ok(config) => return Ok(config),
+            Err(e) => e,
+        };
+
+        let snapshot = match self.snapshots.load_node_config() {
+            Ok(Some(snapshot)) => snapshot,
+            Ok(None) => return Err(error.context("No cached configuration to fall back to")),
+            Err(e) => {
+                error!("Failed to load cached configuration: {}", e);
+                return Err(error);
+            }
+        };
+
+        warn!(
+            "API unreachable ({}), starting with cached configuration fetched at {} ({} old)",
+            error,
+            snapshot.saved_at.to_rfc3339(),
+            describe_age(snapshot.age(Utc::now()))
         );
 
-        // Store the configuration
-        let mut current = self.current_config.write().await;
-        *current = Some(node_config.clone());
+        if let Some(settings) = &snapshot.value.agent {
+            self.apply_settings(settings.clone());
+        }
+        *self.cached_since.write().await = Some(snapshot.saved_at);
+        *self.current_config.write().await = Some(snapshot.value.clone());
 
-        Ok(node_config)
+        let config_sync = self.clone_for_updates();
+        tokio::spawn(async move { config_sync.reconcile().await });
+
+        Ok(snapshot.value)
+    }
+
+    /// Retry fetching with backoff until the API answers, then apply the fresh config
+    async fn reconcile(&self) {
+        let mut backoff = RECONNECT_BACKOFF_MIN;
+        loop {
+            tokio::time::sleep(backoff).await;
+
+            match self.register_and_fetch_config().await {
+                Ok(config) => {
+                    if let Err(e) = self.apply_config(&config).await {
+                        error!("Failed to apply configuration after reconnecting: {}", e);
+                    }
+                    return;
+                }
+                Err(e) => {
+                    warn!("API still unreachable, retrying in {:?}: {}", backoff, e);
+                    backoff = (backoff * 2).min(RECONNECT_BACKOFF_MAX);
+                }
+            }
+        }
     }
 
     /// Subscribe to configuration updates via Redis Pub/Sub
@@ -197,6 +370,32 @@ impl ConfigSync {
         Ok(())
     }
 
+    /// Refetch and apply the config whenever a heartbeat finds the applied one outdated
+    ///
+    /// Complements `subscribe_to_updates`: pub/sub delivers changes immediately but drops
+    /// them while the agent is not subscribed, heartbeats catch up on whatever was missed.
+    pub fn start_heartbeat_reconciliation(&self) {
+        let config_sync = self.clone_for_updates();
+
+        tokio::spawn(async move {
+            loop {
+                config_sync.applied.resync_requested().await;
+                warn!("Applied configuration is outdated, fetching the current one");
+
+                match config_sync.register_and_fetch_config().await {
+                    Ok(new_config) => {
+                        if let Err(e) = config_sync.apply_config(&new_config).await {
+                            error!("Failed to apply reconciled configuration: {}", e);
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to fetch reconciled configuration: {}", e);
+                    }
+                }
+            }
+        });
+    }
+
     /// Apply configuration to Xray-core
     pub async fn apply_config(&self, config: &NodeConfig) -> Result<()> {
         info!("Applying configuration to Xray-core");
@@ -211,6 +410,7 @@ impl ConfigSync {
             .context("Failed to write Xray config file")?;
 
         info!("Xray configuration written to {}", config_path);
+        self.applied.set(config.config_hash.clone(), config.config_version);
 
         // Reload Xray-core (send SIGHUP or restart service)
         // This is a placeholder - actual implementation would use systemctl or similar
@@ -220,21 +420,22 @@ impl ConfigSync {
     }
 
     /// Generate Xray-core configuration JSON from NodeConfig
-    fn generate_xray_config(&self, config: &NodeConfig) -> Result<String> {
+    pub fn generate_xray_config(&self, config: &NodeConfig) -> Result<String> {
         let mut xray_config = serde_json::json!({
             "log": {
                 "loglevel": "warning"
             },
             "api": {
                 "tag": "api",
-                "services": ["StatsService"]
+                "services": ["StatsService", "HandlerService"]
             },
             "stats": {},
             "policy": {
                 "levels": {
                     "0": {
                         "statsUserUplink": true,
-                        "statsUserDownlink": true
+                        "statsUserDownlink": true,
+                        "statsUserOnline": true
                     }
                 },
                 "system": {
@@ -275,7 +476,7 @@ impl ConfigSync {
             }));
 
         // Add main inbound based on protocol
-        let main_inbound = match config.protocol.as_str() {
+        let mut main_inbound = match config.protocol.as_str() {
             "vless" => self.generate_vless_inbound(config)?,
             "vmess" => self.generate_vmess_inbound(config)?,
             "trojan" => self.generate_trojan_inbound(config)?,
@@ -283,6 +484,8 @@ impl ConfigSync {
             "hysteria2" => self.generate_hysteria2_inbound(config)?,
             _ => anyhow::bail!("Unsupported protocol: {}", config.protocol),
         };
+        // Tagged so users can be removed at runtime through the HandlerService
+        main_inbound["tag"] = serde_json::json!(MAIN_INBOUND_TAG);
 
         xray_config["inbounds"]
             .as_array_mut()
@@ -408,8 +611,14 @@ impl ConfigSync {
             .as_ref()
             .context("Hysteria2 config is required")?;
 
+        // Xray takes a comma-separated list of ports and ranges for an inbound
+        let port = match &config.ports {
+            Some(ports) => serde_json::json!(format!("{},{}", config.port, ports)),
+            None => serde_json::json!(config.port),
+        };
+
         Ok(serde_json::json!({
-            "port": config.port,
+            "port": port,
             "protocol": "hysteria2",
             "settings": {
                 "password": h2_config.password,
@@ -422,9 +631,31 @@ impl ConfigSync {
     fn clone_for_updates(&self) -> Self {
         Self {
             config: Arc::clone(&self.config),
+            settings: self.settings.clone(),
             http_client: self.http_client.clone(),
             redis_url: self.redis_url.clone(),
             current_config: Arc::clone(&self.current_config),
+            snapshots: self.snapshots.clone(),
+            cached_since: Arc::clone(&self.cached_since),
+            applied: self.applied.clone(),
+        }
+    }
+
+    /// Handle to the applied config hash, shared with the health checker
+    pub fn applied(&self) -> AppliedConfig {
+        self.applied.clone()
+    }
+
+    /// Handle to the runtime settings, shared with the other agent components
+    pub fn settings(&self) -> SettingsHandle {
+        self.settings.clone()
+    }
+
+    /// Publish runtime settings from the API to the running components
+    fn apply_settings(&self, settings: RuntimeSettings) {
+        let changed = self.settings.apply(settings);
+        if !changed.is_empty() {
+            info!("Applied runtime settings from API, changed: {}", changed.join(", "));
         }
     }
 
@@ -460,6 +691,11 @@ mod tests {
             vmess_config: None,
             trojan_config: None,
             hysteria2_config: None,
+            ports: None,
+            agent: None,
+            node_secret: None,
+            config_hash: None,
+            config_version: None,
         };
 
         let json = serde_json::to_string(&config).unwrap();
@@ -469,6 +705,23 @@ mod tests {
         assert_eq!(deserialized.port, 443);
         assert_eq!(deserialized.users.len(), 1);
         assert!(deserialized.reality_config.is_some());
+        assert!(deserialized.agent.is_none());
+    }
+
+    #[test]
+    fn test_node_config_with_agent_settings() {
+        let json = r#"{
+            "protocol": "shadowsocks",
+            "port": 8388,
+            "users": [],
+            "agent": {"heartbeat_interval": 30, "probe_targets": ["1.1.1.1:443"]}
+        }"#;
+        let node_config: NodeConfig = serde_json::from_str(json).unwrap();
+        let agent = node_config.agent.unwrap();
+
+        assert_eq!(agent.heartbeat_interval, 30);
+        assert_eq!(agent.traffic_report_interval, 30);
+        assert_eq!(agent.probe_targets, vec!["1.1.1.1:443".to_string()]);
     }
 
     #[tokio::test]
@@ -476,10 +729,14 @@ mod tests {
         let config = Arc::new(Config {
             api_url: "http://localhost:8080".to_string(),
             node_id: "test-node".to_string(),
-            node_secret: "secret".to_string(),
+            node_secret: crate::config::NodeSecret::new("secret"),
             xray_api_port: 10085,
             traffic_report_interval: 30,
             heartbeat_interval: 60,
+            enrollment_token: None,
+            credentials_file: crate::config::DEFAULT_CREDENTIALS_FILE.to_string(),
+            cache_dir: crate::config::DEFAULT_CACHE_DIR.to_string(),
+            update_check_interval: 0,
         });
 
         let sync = ConfigSync::new(config, None);
@@ -504,6 +761,11 @@ mod tests {
             vmess_config: None,
             trojan_config: None,
             hysteria2_config: None,
+            ports: None,
+            agent: None,
+            node_secret: None,
+            config_hash: None,
+            config_version: None,
         };
 
         let xray_config = sync.generate_xray_config(&node_config).unwrap();
@@ -512,5 +774,60 @@ mod tests {
         assert!(parsed["inbounds"].is_array());
         assert!(parsed["outbounds"].is_array());
         assert!(parsed["api"].is_object());
+        assert_eq!(parsed["inbounds"][1]["tag"], MAIN_INBOUND_TAG);
+        assert_eq!(parsed["policy"]["levels"]["0"]["statsUserOnline"], true);
+    }
+
+    #[test]
+    fn test_applied_config_check() {
+        let applied = AppliedConfig::default();
+        assert!(!applied.check(None));
+        assert!(applied.check(Some("abc")));
+
+        assert_eq!(applied.version(), None);
+
+        applied.set(Some("abc".to_string()), Some(3));
+        assert_eq!(applied.hash().as_deref(), Some("abc"));
+        assert_eq!(applied.version(), Some(3));
+        assert!(!applied.check(Some("abc")));
+        assert!(applied.check(Some("def")));
+        // An older API sends no hash
+        assert!(!applied.check(None));
+    }
+
+    #[tokio::test]
+    async fn test_generate_hysteria2_port_hopping() {
+        let config = Arc::new(Config {
+            api_url: "http://localhost:8080".to_string(),
+            node_id: "test-node".to_string(),
+            node_secret: crate::config::NodeSecret::new("secret"),
+            xray_api_port: 10085,
+            traffic_report_interval: 30,
+            heartbeat_interval: 60,
+            enrollment_token: None,
+            credentials_file: crate::config::DEFAULT_CREDENTIALS_FILE.to_string(),
+            cache_dir: crate::config::DEFAULT_CACHE_DIR.to_string(),
+            update_check_interval: 0,
+        });
+        let sync = ConfigSync::new(config, None);
+
+        let mut node_config: NodeConfig = serde_json::from_str(
+            r#"{
+                "protocol": "hysteria2",
+                "port": 443,
+                "users": [],
+                "hysteria2_config": {"password": "pw", "obfs": null},
+                "ports": "20000-30000"
+            }"#,
+        )
+        .unwrap();
+        let parsed: serde_json::Value =
+            serde_json::from_str(&sync.generate_xray_config(&node_config).unwrap()).unwrap();
+        assert_eq!(parsed["inbounds"][1]["port"], "443,20000-30000");
+
+        node_config.ports = None;
+        let parsed: serde_json::Value =
+            serde_json::from_str(&sync.generate_xray_config(&node_config).unwrap()).unwrap();
+        assert_eq!(parsed["inbounds"][1]["port"], 443);
     }
 }
@@ -4,10 +4,9 @@ use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::time::{interval, Duration};
 use tracing::{error, info, warn};
 
-use crate::config::Config;
+use crate::config::{Config, SettingsHandle};
 
 /// Traffic statistics for a user
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,14 +29,20 @@ pub struct TrafficReport {
 /// Traffic reporter that collects and reports traffic data
 pub struct TrafficReporter {
     config: Arc<Config>,
+    settings: SettingsHandle,
     redis_client: Option<ConnectionManager>,
     xray_api_client: reqwest::Client,
 }
 
 impl TrafficReporter {
-    pub fn new(config: Arc<Config>, redis_client: Option<ConnectionManager>) -> Self {
+    pub fn new(
+        config: Arc<Config>,
+        settings: SettingsHandle,
+        redis_client: Option<ConnectionManager>,
+    ) -> Self {
         Self {
             config,
+            settings,
             redis_client,
             xray_api_client: reqwest::Client::new(),
         }
@@ -55,9 +60,12 @@ impl TrafficReporter {
 
         let config = Arc::clone(&self.config);
         let xray_api_client = self.xray_api_client.clone();
+        let mut ticker = self
+            .settings
+            .interval("traffic_report_interval", |s| s.traffic_report_interval);
+        let period = ticker.period_secs();
 
         tokio::spawn(async move {
-            let mut ticker = interval(Duration::from_secs(config.traffic_report_interval));
 
             loop {
                 ticker.tick().await;
@@ -81,10 +89,7 @@ impl TrafficReporter {
             }
         });
 
-        info!(
-            "Traffic reporter started with interval: {} seconds",
-            self.config.traffic_report_interval
-        );
+        info!("Traffic reporter started with interval: {} seconds", period);
 
         Ok(())
     }
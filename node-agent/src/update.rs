@@ -0,0 +1,347 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+
+use crate::auth::RequestStamp;
+use crate::config::Config;
+
+/// Version of this agent binary, reported in heartbeats and update checks
+pub const AGENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Update offered by `GET /api/v1/node/agent-version`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct UpdateManifest {
+    pub version: String,
+    pub url: String,
+    /// Hex SHA-256 of the binary at `url`
+    pub sha256: String,
+    /// Unix time after which the manifest must not be acted on
+    pub expires_at: i64,
+    /// Hex HMAC-SHA256 of the other fields, keyed with the node secret
+    pub signature: String,
+}
+
+impl UpdateManifest {
+    /// The signed part of the manifest, one field per line
+    fn message(&self) -> String {
+        format!("{}\n{}\n{}\n{}", self.version, self.url, self.sha256, self.expires_at)
+    }
+
+    /// Check the manifest was issued by the API for this node and is still valid
+    pub fn verify(&self, node_secret: &str, now: i64) -> Result<()> {
+        let expected = hmac_sha256_hex(node_secret, self.message().as_bytes());
+        if !constant_time_eq(&expected, &self.signature.trim().to_ascii_lowercase()) {
+            anyhow::bail!("Update manifest for {} has an invalid signature", self.version);
+        }
+        if now > self.expires_at {
+            anyhow::bail!("Update manifest for {} has expired", self.version);
+        }
+        Ok(())
+    }
+
+    /// Check a downloaded binary against the manifest checksum
+    pub fn verify_binary(&self, binary: &[u8]) -> Result<()> {
+        if sha256_hex(binary) != self.sha256.trim().to_ascii_lowercase() {
+            anyhow::bail!("Downloaded agent {} does not match its checksum", self.version);
+        }
+        Ok(())
+    }
+}
+
+/// Response of `GET /api/v1/node/agent-version`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AgentVersionResponse {
+    #[serde(default)]
+    pub latest_version: Option<String>,
+    /// None when this agent is up to date
+    #[serde(default)]
+    pub update: Option<UpdateManifest>,
+}
+
+/// Whether `candidate` is a newer dotted version than `current`
+///
+/// Components are compared numerically; a leading `v` and pre-release suffixes are ignored.
+pub fn is_newer(candidate: &str, current: &str) -> bool {
+    fn parts(version: &str) -> Vec<u64> {
+        let version = version.trim().trim_start_matches('v');
+        let core = version.split(['-', '+']).next().unwrap_or_default();
+        core.split('.').map(|part| part.parse().unwrap_or(0)).collect()
+    }
+
+    let (candidate, current) = (parts(candidate), parts(current));
+    let len = candidate.len().max(current.len());
+    let at = |parts: &[u64], i: usize| parts.get(i).copied().unwrap_or(0);
+    (0..len)
+        .map(|i| at(&candidate, i).cmp(&at(&current, i)))
+        .find(|ordering| ordering.is_ne())
+        .is_some_and(|ordering| ordering.is_gt())
+}
+
+fn sha256_hex(payload: &[u8]) -> String {
+    Sha256::digest(payload)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// HMAC-SHA256 (RFC 2104) as lowercase hex, matching the API's signatures
+fn hmac_sha256_hex(key: &str, message: &[u8]) -> String {
+    const BLOCK_SIZE: usize = 64;
+
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key.as_bytes()));
+    } else {
+        block[..key.len()].copy_from_slice(key.as_bytes());
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+
+    outer
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Checks the API for new agent releases and installs them
+///
+/// A verified binary replaces the running one in place and the agent re-executes
+/// itself with the same arguments, so a supervisor (systemd) sees one long-running
+/// process.
+pub struct Updater {
+    config: Arc<Config>,
+    http_client: reqwest::Client,
+}
+
+impl Updater {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self {
+            config,
+            http_client: reqwest::Client::new(),
+        }
+    }
+
+    /// Start the periodic update check (`AGENT_UPDATE_CHECK_INTERVAL`, 0 disables it)
+    pub async fn start(&self) -> Result<()> {
+        let period = self.config.update_check_interval;
+        if period == 0 {
+            info!("Agent self-update disabled");
+            return Ok(());
+        }
+
+        let config = Arc::clone(&self.config);
+        let http_client = self.http_client.clone();
+        tokio::spawn(async move {
+            let updater = Updater { config, http_client };
+            let mut ticker = interval(Duration::from_secs(period));
+            loop {
+                ticker.tick().await;
+
+                match updater.check().await {
+                    Ok(Some(manifest)) => match updater.install(&manifest).await {
+                        Ok(path) => {
+                            info!("Installed agent {} to {}, restarting", manifest.version, path.display());
+                            let e = restart(&path);
+                            error!("Failed to restart into agent {}: {}", manifest.version, e);
+                        }
+                        Err(e) => error!("Failed to install agent {}: {}", manifest.version, e),
+                    },
+                    Ok(None) => {}
+                    Err(e) => warn!("Agent update check failed: {}", e),
+                }
+            }
+        });
+
+        info!("Agent {} checking for updates every {} seconds", AGENT_VERSION, period);
+
+        Ok(())
+    }
+
+    /// Ask the API for a newer release; returns a verified manifest if there is one
+    pub async fn check(&self) -> Result<Option<UpdateManifest>> {
+        let url = format!(
+            "{}/api/v1/node/agent-version?node_id={}&secret={}&version={}&arch={}&{}",
+            self.config.api_url,
+            self.config.node_id,
+            self.config.node_secret.get(),
+            AGENT_VERSION,
+            std::env::consts::ARCH,
+            RequestStamp::now().query()
+        );
+
+        let response = self
+            .http_client
+            .get(&url)
+            .send()
+            .await
+            .context("Failed to send agent version request")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Agent version request failed: status={}, body={}", status, body);
+        }
+
+        let version: AgentVersionResponse = response
+            .json()
+            .await
+            .context("Failed to parse agent version response")?;
+
+        let Some(manifest) = version.update else {
+            return Ok(None);
+        };
+        if !is_newer(&manifest.version, AGENT_VERSION) {
+            return Ok(None);
+        }
+        manifest.verify(&self.config.node_secret.get(), chrono::Utc::now().timestamp())?;
+
+        Ok(Some(manifest))
+    }
+
+    /// Download and verify the release, then swap it in for the running binary
+    pub async fn install(&self, manifest: &UpdateManifest) -> Result<PathBuf> {
+        info!("Downloading agent {} from {}", manifest.version, manifest.url);
+        let response = self
+            .http_client
+            .get(&manifest.url)
+            .timeout(Duration::from_secs(300))
+            .send()
+            .await
+            .context("Failed to download agent release")?
+            .error_for_status()
+            .context("Failed to download agent release")?;
+        let binary = response.bytes().await.context("Failed to read agent release")?;
+        manifest.verify_binary(&binary)?;
+
+        let current = std::env::current_exe().context("Failed to locate the running agent binary")?;
+        replace_binary(&current, &binary)?;
+
+        Ok(current)
+    }
+}
+
+/// Write `binary` next to `path` and rename it over `path`
+///
+/// The rename is atomic, so a crash leaves either the old or the new binary in place.
+pub fn replace_binary(path: &Path, binary: &[u8]) -> Result<()> {
+    let mut staged = path.as_os_str().to_owned();
+    staged.push(".new");
+    let staged = PathBuf::from(staged);
+
+    std::fs::write(&staged, binary)
+        .with_context(|| format!("Failed to write {}", staged.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("Failed to make {} executable", staged.display()))?;
+    }
+
+    std::fs::rename(&staged, path)
+        .with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+/// Re-execute the (replaced) binary with the current arguments; only returns on failure
+#[cfg(unix)]
+fn restart(path: &Path) -> anyhow::Error {
+    use std::os::unix::process::CommandExt;
+
+    let error = std::process::Command::new(path)
+        .args(std::env::args_os().skip(1))
+        .exec();
+    anyhow::Error::new(error)
+}
+
+/// Exit so the supervisor starts the replaced binary
+#[cfg(not(unix))]
+fn restart(_path: &Path) -> anyhow::Error {
+    std::process::exit(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(secret: &str, expires_at: i64, binary: &[u8]) -> UpdateManifest {
+        let mut manifest = UpdateManifest {
+            version: "1.4.0".to_string(),
+            url: "https://releases.example.com/node-agent-1.4.0".to_string(),
+            sha256: sha256_hex(binary),
+            expires_at,
+            signature: String::new(),
+        };
+        manifest.signature = hmac_sha256_hex(secret, manifest.message().as_bytes());
+        manifest
+    }
+
+    #[test]
+    fn test_hmac_sha256_hex() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hmac_sha256_hex("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("1.10.0", "1.9.3"));
+        assert!(is_newer("v0.2", "0.1.9"));
+        assert!(!is_newer("1.2.0", "1.2"));
+        assert!(!is_newer("1.2.0-rc1", "1.2.0"));
+        assert!(!is_newer("0.9.0", "1.0.0"));
+    }
+
+    #[test]
+    fn test_manifest_verification() {
+        let binary = b"agent binary";
+        let signed = manifest("node-secret", 2_000, binary);
+
+        assert!(signed.verify("node-secret", 1_000).is_ok());
+        assert!(signed.verify("other-secret", 1_000).is_err());
+        assert!(signed.verify("node-secret", 2_001).is_err());
+
+        // Tampering with any signed field breaks the signature
+        let redirected = UpdateManifest {
+            url: "https://evil.example.com/agent".to_string(),
+            ..signed.clone()
+        };
+        assert!(redirected.verify("node-secret", 1_000).is_err());
+
+        assert!(signed.verify_binary(binary).is_ok());
+        assert!(signed.verify_binary(b"tampered binary").is_err());
+    }
+
+    #[test]
+    fn test_replace_binary() {
+        let path = std::env::temp_dir().join(format!("node-agent-update-{}", std::process::id()));
+        std::fs::write(&path, b"old").unwrap();
+
+        replace_binary(&path, b"new").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,12 +1,14 @@
 use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tokio::time::{interval, Duration};
 use tracing::{error, info, warn};
 
-use crate::config::Config;
+use crate::auth::RequestStamp;
+use crate::config::{Config, SettingsHandle};
+use crate::snapshot::{describe_age, SnapshotStore};
 use crate::sync::UserConfig;
 
 /// Active user information
@@ -27,14 +29,18 @@ pub struct ActiveUsersResponse {
 /// User manager that syncs active users and manages Xray-core user configuration
 pub struct UserManager {
     config: Arc<Config>,
+    settings: SettingsHandle,
     http_client: reqwest::Client,
     active_users: Arc<RwLock<HashSet<String>>>,
+    snapshots: SnapshotStore,
 }
 
 impl UserManager {
-    pub fn new(config: Arc<Config>) -> Self {
+    pub fn new(config: Arc<Config>, settings: SettingsHandle) -> Self {
         Self {
+            snapshots: SnapshotStore::new(&config.cache_dir),
             config,
+            settings,
             http_client: reqwest::Client::new(),
             active_users: Arc::new(RwLock::new(HashSet::new())),
         }
@@ -45,20 +51,33 @@ impl UserManager {
         let config = Arc::clone(&self.config);
         let http_client = self.http_client.clone();
         let active_users = Arc::clone(&self.active_users);
+        let snapshots = self.snapshots.clone();
+        let mut ticker = self
+            .settings
+            .interval("user_sync_interval", |s| s.user_sync_interval);
 
         tokio::spawn(async move {
-            // Sync every 60 seconds
-            let mut ticker = interval(Duration::from_secs(60));
+            // When the cached user list in use was fetched
+            let mut cached_since: Option<DateTime<Utc>> = None;
 
             loop {
                 ticker.tick().await;
 
-                match Self::sync_active_users(&config, &http_client, &active_users).await {
+                match Self::sync_active_users(&config, &http_client, &active_users, &snapshots).await {
                     Ok(count) => {
                         info!("Successfully synced {} active users", count);
+                        if let Some(since) = cached_since.take() {
+                            info!(
+                                "Reconnected to API, replacing cached user list that was {} old",
+                                describe_age(Utc::now() - since)
+                            );
+                        }
                     }
                     Err(e) => {
                         error!("Failed to sync active users: {}", e);
+                        if cached_since.is_none() && active_users.read().await.is_empty() {
+                            cached_since = Self::restore_cached_users(&snapshots, &active_users).await;
+                        }
                     }
                 }
             }
@@ -74,11 +93,15 @@ impl UserManager {
         config: &Config,
         http_client: &reqwest::Client,
         active_users: &Arc<RwLock<HashSet<String>>>,
+        snapshots: &SnapshotStore,
     ) -> Result<usize> {
         // Fetch active users from API
         let url = format!(
-            "{}/api/node/users?node_id={}&secret={}",
-            config.api_url, config.node_id, config.node_secret
+            "{}/api/v1/node/users?node_id={}&secret={}&{}",
+            config.api_url,
+            config.node_id,
+            config.node_secret.get(),
+            RequestStamp::now().query()
         );
 
         let response = http_client
@@ -102,11 +125,53 @@ impl UserManager {
             .await
             .context("Failed to parse active users response")?;
 
-        // Update active users set
+        if let Err(e) = snapshots.save_active_users(&users_response.users) {
+            warn!("Failed to cache active users: {}", e);
+        }
+
+        let count = Self::replace_active_users(active_users, &users_response.users).await;
+
+        info!("Active users updated: {} users", count);
+
+        Ok(count)
+    }
+
+    /// Serve the last cached user list until the API can be reached again
+    ///
+    /// Returns when the cached list was fetched, or `None` if there is nothing usable on disk.
+    async fn restore_cached_users(
+        snapshots: &SnapshotStore,
+        active_users: &Arc<RwLock<HashSet<String>>>,
+    ) -> Option<DateTime<Utc>> {
+        let snapshot = match snapshots.load_active_users() {
+            Ok(Some(snapshot)) => snapshot,
+            Ok(None) => return None,
+            Err(e) => {
+                error!("Failed to load cached active users: {}", e);
+                return None;
+            }
+        };
+
+        let count = Self::replace_active_users(active_users, &snapshot.value).await;
+        warn!(
+            "Serving {} cached active users fetched at {} ({} old) until the API is reachable",
+            count,
+            snapshot.saved_at.to_rfc3339(),
+            describe_age(snapshot.age(Utc::now()))
+        );
+
+        Some(snapshot.saved_at)
+    }
+
+    /// Replace the active set, leaving out users over their traffic quota
+    async fn replace_active_users(
+        active_users: &Arc<RwLock<HashSet<String>>>,
+        users: &[ActiveUser],
+    ) -> usize {
         let mut users_set = active_users.write().await;
         users_set.clear();
 
-        for user in &users_response.users {
+        for user in users {
             // Check if user has exceeded traffic quota
             if user.traffic_used >= user.traffic_quota {
                 warn!(
@@ -119,12 +184,7 @@ impl UserManager {
             users_set.insert(user.email.clone());
         }
 
-        let count = users_set.len();
-        drop(users_set);
-
-        info!("Active users updated: {} users", count);
-
-        Ok(count)
+        users_set.len()
     }
 
     /// Get list of active user emails
@@ -226,13 +286,18 @@ mod tests {
         let config = Arc::new(Config {
             api_url: "http://localhost:8080".to_string(),
             node_id: "test-node".to_string(),
-            node_secret: "secret".to_string(),
+            node_secret: crate::config::NodeSecret::new("secret"),
             xray_api_port: 10085,
             traffic_report_interval: 30,
             heartbeat_interval: 60,
+            enrollment_token: None,
+            credentials_file: crate::config::DEFAULT_CREDENTIALS_FILE.to_string(),
+            cache_dir: crate::config::DEFAULT_CACHE_DIR.to_string(),
+            update_check_interval: 0,
         });
 
-        let manager = UserManager::new(config);
+        let settings = SettingsHandle::new(config.initial_settings());
+        let manager = UserManager::new(config, settings);
 
         // Initially no active users
         let users = manager.get_active_users().await;